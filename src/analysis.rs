@@ -0,0 +1,702 @@
+//! The diagnostic pipeline
+//!
+//! Runs every analysis stage over a document's text and returns the resulting
+//! LSP diagnostics. Each stage is timed so slow documents can be profiled.
+
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, Position, Range};
+
+use crate::diagnostics::{
+    validate_concatenation, validate_directives, validate_duplicate_steps, validate_escapes,
+    validate_except_blocks, validate_expression_syntax, validate_expression_types,
+    validate_identifiers, validate_node_properties, validate_quotas, validate_resource_names,
+    validate_scopes, validate_tab_indentation, validate_template_vars,
+    validate_terraform_functions, validate_terraform_variables, validate_type_stability,
+    validate_unclosed_expressions, validate_variable_collisions, validate_workflow, DiagnosticCode,
+    DiagnosticCollector, Suppressions,
+};
+use crate::document::normalize_line_endings;
+use crate::parser::{
+    parse_yaml, preprocess_expressions, scan_anchors, scan_directives, ExpressionMap,
+};
+use crate::rules::{self, RuleContext, RuleRegistry};
+use crate::settings::{RuleLevel, Settings};
+
+/// A stage of the diagnostic pipeline
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Stage {
+    /// Replacing `${}`/`$${}` expressions with placeholders
+    Preprocess,
+    /// YAML syntax parsing
+    Parse,
+    /// Workflow structure validation
+    Structure,
+    /// Expression-level checks
+    Expressions,
+    /// Sending diagnostics to the client (measured by the backend)
+    Publish,
+}
+
+impl Stage {
+    /// The stage's name, as used in `stage:` suppressions
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Stage::Preprocess => "preprocess",
+            Stage::Parse => "parse",
+            Stage::Structure => "structure",
+            Stage::Expressions => "expressions",
+            Stage::Publish => "publish",
+        }
+    }
+}
+
+/// Wall-clock time spent in one pipeline stage
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StageTiming {
+    pub stage: Stage,
+    #[serde(rename = "durationMs", serialize_with = "as_millis")]
+    pub duration: Duration,
+}
+
+fn as_millis<S: serde::Serializer>(duration: &Duration, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_f64(duration.as_secs_f64() * 1000.0)
+}
+
+/// Totals for a batch of validated documents, logged once the batch has
+/// been published
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BatchSummary {
+    pub files: usize,
+    pub errors: usize,
+    pub warnings: usize,
+}
+
+impl BatchSummary {
+    /// Count one document's diagnostics
+    pub fn add(&mut self, diagnostics: &[Diagnostic]) {
+        self.files += 1;
+        for diagnostic in diagnostics {
+            match diagnostic.severity {
+                Some(DiagnosticSeverity::ERROR) => self.errors += 1,
+                Some(DiagnosticSeverity::WARNING) => self.warnings += 1,
+                _ => {}
+            }
+        }
+    }
+
+    /// A one-line summary of a batch that took `duration`
+    pub fn message(&self, duration: Duration) -> String {
+        format!(
+            "Validated {} file(s) in {} ms: {} error(s), {} warning(s)",
+            self.files,
+            duration.as_millis(),
+            self.errors,
+            self.warnings
+        )
+    }
+}
+
+/// What a document is analyzed with
+#[derive(Clone, Default)]
+pub struct AnalysisOptions {
+    /// The settings of the document's workspace and template
+    pub settings: Settings,
+    /// The most severe level to report anything at
+    pub max_severity: Option<RuleLevel>,
+    /// An embedder's rules, run next to the built-in analyzers
+    pub rules: RuleRegistry,
+}
+
+impl From<Settings> for AnalysisOptions {
+    fn from(settings: Settings) -> Self {
+        Self {
+            settings,
+            ..Self::default()
+        }
+    }
+}
+
+/// The outcome of running the pipeline over a document
+#[derive(Debug, Clone, Default)]
+pub struct Analysis {
+    pub diagnostics: Vec<Diagnostic>,
+    /// How long each stage took
+    pub timings: Vec<StageTiming>,
+    /// Whether the text is valid YAML
+    pub parsed: bool,
+}
+
+/// Run the full pipeline over a document
+pub fn analyze(text: &str, options: &AnalysisOptions) -> Analysis {
+    let AnalysisOptions {
+        settings,
+        max_severity,
+        rules,
+    } = options;
+    // Analyzers only deal with `\n`; columns are the same either way
+    let text = &*normalize_line_endings(text);
+    let mut timings = Vec::new();
+    let mut collector = DiagnosticCollector::new();
+    let lines: Vec<&str> = text.lines().collect();
+
+    // Preprocess expressions to replace ${} and $${} with placeholders
+    tracing::trace!("Preprocessing expressions");
+    let start = Instant::now();
+    let (preprocessed, expression_map) = preprocess_expressions(text);
+    timings.push(timing(Stage::Preprocess, start));
+    tracing::trace!(
+        expression_count = expression_map.expressions.len(),
+        "Expressions preprocessed"
+    );
+
+    // Parse YAML and collect errors
+    tracing::trace!("Parsing YAML");
+    let start = Instant::now();
+    let result = run(&mut collector, &lines, Stage::Parse, "yaml", |c| {
+        parse_yaml(&preprocessed, &expression_map, c)
+    });
+    run(
+        &mut collector,
+        &lines,
+        Stage::Parse,
+        "node-properties",
+        |c| validate_node_properties(&scan_anchors(text, &expression_map), c),
+    );
+    run(&mut collector, &lines, Stage::Parse, "directives", |c| {
+        validate_directives(&scan_directives(text), c)
+    });
+    run(
+        &mut collector,
+        &lines,
+        Stage::Parse,
+        "unclosed-expressions",
+        |c| validate_unclosed_expressions(text, &expression_map, c),
+    );
+    run(
+        &mut collector,
+        &lines,
+        Stage::Parse,
+        "tab-indentation",
+        |c| validate_tab_indentation(text, c),
+    );
+    run(&mut collector, &lines, Stage::Parse, "escapes", |c| {
+        validate_escapes(text, &expression_map, c)
+    });
+    let context = |value| RuleContext {
+        text,
+        preprocessed: &preprocessed,
+        value,
+        expressions: &expression_map,
+        settings,
+    };
+    run_rules(
+        &mut collector,
+        &lines,
+        rules,
+        Stage::Parse,
+        &context(result.value.as_ref()),
+    );
+    timings.push(timing(Stage::Parse, start));
+    tracing::trace!("YAML parsing complete");
+
+    // Past the size threshold only YAML syntax is checked
+    let deep = settings.workflow_validation && settings.analyzes_deeply(text.len());
+    if settings.workflow_validation && !deep {
+        tracing::debug!(text_len = text.len(), "Skipping deep analysis");
+        run(&mut collector, &lines, Stage::Parse, "safeguard", |c| {
+            report_skipped_analysis(text, settings.deep_analysis_max_size, c)
+        });
+    }
+
+    // If parsing succeeded, validate workflow structure
+    let start = Instant::now();
+    if let Some(value) = result.value.as_ref().filter(|_| deep) {
+        tracing::trace!("Validating workflow structure");
+        let map = &expression_map;
+        run_preprocessed(
+            &mut collector,
+            &lines,
+            map,
+            Stage::Structure,
+            "workflow",
+            |c| validate_workflow(value, &preprocessed, settings.schema_extensions(), c),
+        );
+        run_preprocessed(
+            &mut collector,
+            &lines,
+            map,
+            Stage::Structure,
+            "scopes",
+            |c| validate_scopes(value, &preprocessed, c),
+        );
+        run_preprocessed(
+            &mut collector,
+            &lines,
+            map,
+            Stage::Structure,
+            "type-stability",
+            |c| validate_type_stability(value, &preprocessed, c),
+        );
+        run(
+            &mut collector,
+            &lines,
+            Stage::Structure,
+            "duplicate-steps",
+            |c| validate_duplicate_steps(text, c),
+        );
+        run(
+            &mut collector,
+            &lines,
+            Stage::Structure,
+            "except-blocks",
+            |c| validate_except_blocks(value, &preprocessed, map, c),
+        );
+        run_preprocessed(
+            &mut collector,
+            &lines,
+            map,
+            Stage::Structure,
+            "resource-names",
+            |c| validate_resource_names(value, &preprocessed, c),
+        );
+        run(
+            &mut collector,
+            &lines,
+            Stage::Structure,
+            "identifiers",
+            |c| validate_identifiers(value, text, &preprocessed, c),
+        );
+        run(&mut collector, &lines, Stage::Structure, "quotas", |c| {
+            validate_quotas(text, &expression_map, &settings.quota_limits(), c)
+        });
+        run_rules(
+            &mut collector,
+            &lines,
+            rules,
+            Stage::Structure,
+            &context(Some(value)),
+        );
+        tracing::trace!("Workflow validation complete");
+    }
+    timings.push(timing(Stage::Structure, start));
+
+    let start = Instant::now();
+    if deep {
+        run(
+            &mut collector,
+            &lines,
+            Stage::Expressions,
+            "expression-syntax",
+            |c| validate_expression_syntax(text, &expression_map, c),
+        );
+        run(
+            &mut collector,
+            &lines,
+            Stage::Expressions,
+            "concatenation",
+            |c| validate_concatenation(&expression_map, settings.max_concatenated_parts, c),
+        );
+        run(
+            &mut collector,
+            &lines,
+            Stage::Expressions,
+            "terraform-functions",
+            |c| validate_terraform_functions(&expression_map, c),
+        );
+        if let Some(module) = &settings.terraform_module {
+            let declared: Vec<&str> = module.variables.iter().map(|v| v.name.as_str()).collect();
+            run(
+                &mut collector,
+                &lines,
+                Stage::Expressions,
+                "terraform-variables",
+                |c| validate_terraform_variables(&expression_map, &declared, c),
+            );
+        }
+        run(
+            &mut collector,
+            &lines,
+            Stage::Expressions,
+            "template-vars",
+            |c| validate_template_vars(text, &expression_map, &settings.template_calls, c),
+        );
+        if let Some(value) = &result.value {
+            run(
+                &mut collector,
+                &lines,
+                Stage::Expressions,
+                "variable-collisions",
+                |c| validate_variable_collisions(value, &preprocessed, &expression_map, c),
+            );
+            run(
+                &mut collector,
+                &lines,
+                Stage::Expressions,
+                "expression-types",
+                |c| validate_expression_types(value, text, &preprocessed, &expression_map, c),
+            );
+        }
+        run_rules(
+            &mut collector,
+            &lines,
+            rules,
+            Stage::Expressions,
+            &context(result.value.as_ref()),
+        );
+    }
+    timings.push(timing(Stage::Expressions, start));
+
+    collector.apply_suppressions(&Suppressions::parse(text));
+    collector.apply_settings(settings);
+    if let Some(max) = *max_severity {
+        collector.apply_severity_cap(max);
+    }
+    Analysis {
+        diagnostics: collector.into_diagnostics(),
+        timings,
+        parsed: result.value.is_some(),
+    }
+}
+
+/// Report on the first line that a document over `max_size` bytes was only
+/// checked for YAML syntax
+fn report_skipped_analysis(text: &str, max_size: usize, collector: &mut DiagnosticCollector) {
+    let first_line = text.lines().next().unwrap_or_default();
+    collector.add_information_with_range(
+        format!(
+            "Document is larger than {} bytes (deepAnalysisMaxSize); only YAML syntax was checked",
+            max_size
+        ),
+        Range::new(
+            Position::new(0, 0),
+            Position::new(0, first_line.chars().count() as u32),
+        ),
+        DiagnosticCode::AnalysisSkipped,
+    );
+}
+
+/// Run one analyzer, recording it as the origin of what it reports
+fn run<T>(
+    collector: &mut DiagnosticCollector,
+    lines: &[&str],
+    stage: Stage,
+    analyzer: &str,
+    check: impl FnOnce(&mut DiagnosticCollector) -> T,
+) -> T {
+    let start = collector.len();
+    let result = check(collector);
+    collector.record_provenance(start, stage, analyzer, lines);
+    result
+}
+
+/// [`run`] an analyzer that reports positions in the preprocessed text,
+/// mapping them back to the document
+fn run_preprocessed(
+    collector: &mut DiagnosticCollector,
+    lines: &[&str],
+    expression_map: &ExpressionMap,
+    stage: Stage,
+    analyzer: &str,
+    check: impl FnOnce(&mut DiagnosticCollector),
+) {
+    run(collector, lines, stage, analyzer, |c| {
+        let start = c.len();
+        check(c);
+        c.adjust_positions(start, expression_map);
+    });
+}
+
+/// Run the registered rules of one stage, each recorded as its own analyzer
+fn run_rules(
+    collector: &mut DiagnosticCollector,
+    lines: &[&str],
+    rules: &RuleRegistry,
+    stage: Stage,
+    context: &RuleContext<'_>,
+) {
+    for rule in rules.in_stage(stage) {
+        run(collector, lines, stage, rule.code(), |c| {
+            c.extend(rules::check(rule, context))
+        });
+    }
+}
+
+fn timing(stage: Stage, start: Instant) -> StageTiming {
+    StageTiming {
+        stage,
+        duration: start.elapsed(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower_lsp::lsp_types::NumberOrString;
+
+    #[test]
+    fn test_analyze_valid_workflow() {
+        let text = "main:\n  steps:\n    - done:\n        return: ${var.x}";
+        assert!(analyze(text, &AnalysisOptions::default())
+            .diagnostics
+            .is_empty());
+    }
+
+    #[test]
+    fn test_analyze_directives() {
+        let text = "%YAML 1.2\n---\nmain:\n  steps:\n    - done:\n        return: 1";
+        assert!(analyze(text, &AnalysisOptions::default())
+            .diagnostics
+            .is_empty());
+
+        let text = "%YAML 1.3\n%FOO bar\n---\nmain:\n  steps:\n    - done:\n        return: 1";
+        let diagnostics = analyze(text, &AnalysisOptions::default()).diagnostics;
+        assert_eq!(diagnostics.len(), 2);
+        assert!(diagnostics
+            .iter()
+            .all(|d| d.code == Some(NumberOrString::String("unsupported-directive".into()))));
+    }
+
+    #[test]
+    fn test_analyze_reports_alias_to_anchor_in_plain_scalar() {
+        let text = "- note: Tom &x Jerry\n- y: *x\n";
+        let codes: Vec<_> = analyze(text, &AnalysisOptions::default())
+            .diagnostics
+            .into_iter()
+            .map(|d| d.code)
+            .collect();
+        assert_eq!(
+            codes,
+            vec![Some(NumberOrString::String("undefined-alias".into()))]
+        );
+    }
+
+    #[test]
+    fn test_analyze_crlf_matches_lf() {
+        let text = "main:\n  steps:\n    - init:\n        assign:\n          - x: ${var.a} $${y\n        next: nowhere\n    - unused: [\n";
+        let lf = analyze(text, &AnalysisOptions::default()).diagnostics;
+        assert!(lf.len() >= 2);
+        assert_eq!(
+            analyze(&text.replace('\n', "\r\n"), &AnalysisOptions::default()).diagnostics,
+            lf
+        );
+    }
+
+    #[test]
+    fn test_analyze_positions_after_multiline_expression() {
+        let text = "main:\n  steps:\n    - init:\n        assign:\n          - cfg: ${jsonencode({\n              a = 1\n            })}\n        next: nowhere\n    - bad: [\n";
+        let lines = |text: &str| -> Vec<(u32, u32)> {
+            analyze(text, &AnalysisOptions::default())
+                .diagnostics
+                .iter()
+                .map(|d| (d.range.start.line, d.range.start.character))
+                .collect()
+        };
+        // The YAML error on the unclosed list
+        assert_eq!(lines(text), vec![(9, 0)]);
+
+        let text = text.replace("    - bad: [\n", "");
+        assert_eq!(lines(&text), vec![(7, 14)]);
+    }
+
+    #[test]
+    fn test_analyze_large_document_checks_syntax_only() {
+        let text = "main:\n  steps:\n    - a:\n        next: nowhere\n    - b: [\n";
+        let settings = Settings::from_value(serde_json::json!({ "deepAnalysisMaxSize": 16 }));
+        let codes: Vec<_> = analyze(text, &settings.clone().into())
+            .diagnostics
+            .into_iter()
+            .map(|d| (d.code, d.severity))
+            .collect();
+        assert_eq!(
+            codes,
+            vec![
+                (
+                    Some(NumberOrString::String("yaml-syntax".into())),
+                    Some(DiagnosticSeverity::ERROR)
+                ),
+                (
+                    Some(NumberOrString::String("analysis-skipped".into())),
+                    Some(DiagnosticSeverity::INFORMATION)
+                ),
+            ]
+        );
+
+        let text = text.replace("    - b: [\n", "");
+        assert_eq!(analyze(&text, &settings.into()).diagnostics.len(), 1);
+        assert_eq!(
+            analyze(&text, &AnalysisOptions::default())
+                .diagnostics
+                .len(),
+            1
+        );
+        let plain = Settings::from_value(
+            serde_json::json!({ "deepAnalysisMaxSize": 16, "workflowValidation": false }),
+        );
+        assert!(analyze("just: yaml\nmore: yaml", &plain.into())
+            .diagnostics
+            .is_empty());
+    }
+
+    #[test]
+    fn test_analyze_checks_terraform_variables_of_loaded_module() {
+        let text = "main:\n  steps:\n    - done:\n        return: ${var.region}";
+        assert!(analyze(text, &AnalysisOptions::default())
+            .diagnostics
+            .is_empty());
+
+        let module = crate::terraform::ModuleDecls::parse(
+            "variable \"project\" {}",
+            std::path::Path::new("variables.tf"),
+        )
+        .unwrap();
+        let settings = Settings {
+            terraform_module: Some(std::sync::Arc::new(module)),
+            ..Settings::default()
+        };
+        let diagnostics = analyze(text, &settings.into()).diagnostics;
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].code,
+            Some(NumberOrString::String("undefined-variable".into()))
+        );
+    }
+
+    #[test]
+    fn test_analyze_applies_suppressions() {
+        let text = "main:\n  steps:\n    - done:\n        return: 1\n# yaml-tftpl-lsp disable-next-line\nextra: 1";
+        assert!(analyze(text, &AnalysisOptions::default())
+            .diagnostics
+            .is_empty());
+    }
+
+    #[test]
+    fn test_analyze_suppresses_stages() {
+        let text = "# yaml-tftpl-lsp disable: stage:structure\nmain:\n  steps:\n    - done:\n        return: 1\nextra: 1";
+        assert!(analyze(text, &AnalysisOptions::default())
+            .diagnostics
+            .is_empty());
+
+        let text = "# yaml-tftpl-lsp disable: stage:parse\nmain:\n  steps:\n    - done:\n        return: 1\nextra: 1";
+        assert_eq!(
+            analyze(text, &AnalysisOptions::default()).diagnostics.len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_analyze_applies_settings() {
+        let text = "main:\n  steps:\n    - done:\n        return: 1\nextra: 1";
+        assert_eq!(
+            analyze(text, &AnalysisOptions::default()).diagnostics.len(),
+            1
+        );
+
+        let off =
+            Settings::from_value(serde_json::json!({ "rules": { "unknown-keyword": "off" } }));
+        assert!(analyze(text, &off.into()).diagnostics.is_empty());
+
+        let plain = Settings::from_value(serde_json::json!({ "workflowValidation": false }));
+        assert!(analyze("just: yaml", &plain.into()).diagnostics.is_empty());
+    }
+
+    struct NoMainParams;
+
+    impl rules::Rule for NoMainParams {
+        fn code(&self) -> &str {
+            "acme/no-main-params"
+        }
+
+        fn check(&self, context: &RuleContext<'_>) -> Vec<Diagnostic> {
+            let has_params = context
+                .value
+                .and_then(|v| v.get("main"))
+                .is_some_and(|main| main.get("params").is_some());
+            has_params
+                .then(|| Diagnostic {
+                    message: "No params on main".to_string(),
+                    ..Default::default()
+                })
+                .into_iter()
+                .collect()
+        }
+    }
+
+    #[test]
+    fn test_analyze_with_rules() {
+        let mut rules = RuleRegistry::new();
+        rules.register(NoMainParams);
+        let text = "main:\n  params: [args]\n  steps:\n    - done:\n        return: args";
+
+        let options = AnalysisOptions {
+            rules,
+            ..AnalysisOptions::default()
+        };
+        let diagnostics = analyze(text, &options).diagnostics;
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].code,
+            Some(NumberOrString::String("acme/no-main-params".into()))
+        );
+        assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::WARNING));
+        let provenance = crate::diagnostics::Provenance::of(&diagnostics[0]).unwrap();
+        assert_eq!(provenance.analyzer, "acme/no-main-params");
+        assert_eq!(provenance.stage, Stage::Structure);
+
+        let off =
+            Settings::from_value(serde_json::json!({ "rules": { "acme/no-main-params": "off" } }));
+        let off = AnalysisOptions {
+            settings: off,
+            ..options
+        };
+        assert!(analyze(text, &off).diagnostics.is_empty());
+        assert!(analyze(text, &AnalysisOptions::default())
+            .diagnostics
+            .is_empty());
+    }
+
+    #[test]
+    fn test_batch_summary() {
+        let mut summary = BatchSummary::default();
+        let options = AnalysisOptions::default();
+        summary.add(&analyze("a: [", &options).diagnostics);
+        summary.add(
+            &analyze(
+                "main:\n  steps:\n    - a:\n        next: nowhere\n    - b:\n        next: gone\nextra: 1",
+                &options,
+            )
+            .diagnostics,
+        );
+        summary.add(&[]);
+        assert_eq!(
+            summary,
+            BatchSummary {
+                files: 3,
+                errors: 1,
+                warnings: 2,
+            }
+        );
+        assert_eq!(
+            summary.message(Duration::from_millis(42)),
+            "Validated 3 file(s) in 42 ms: 1 error(s), 2 warning(s)"
+        );
+    }
+
+    #[test]
+    fn test_analyze_records_stages() {
+        let timings = analyze("a: 1", &AnalysisOptions::default()).timings;
+        let stages: Vec<Stage> = timings.iter().map(|t| t.stage).collect();
+        assert_eq!(
+            stages,
+            vec![
+                Stage::Preprocess,
+                Stage::Parse,
+                Stage::Structure,
+                Stage::Expressions
+            ]
+        );
+    }
+}
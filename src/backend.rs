@@ -1,42 +1,384 @@
 //! LSP Backend implementation
 
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Instant;
 
-use tokio::sync::RwLock;
-use tower_lsp::jsonrpc::Result;
+use ropey::Rope;
+use tokio::sync::{Mutex, RwLock, Semaphore};
+use tokio::task::AbortHandle;
+use tower_lsp::jsonrpc::{Error, ErrorCode, Result};
 use tower_lsp::lsp_types::*;
-use tower_lsp::{Client, LanguageServer};
+use tower_lsp::{Client, ClientSocket, LanguageServer, LspService};
 
-use crate::document::Document;
+use crate::analysis::{analyze, Analysis, AnalysisOptions, BatchSummary, Stage, StageTiming};
+use crate::cache::FileCache;
+use crate::capabilities::{registration_changes, Toggle};
+use crate::config::WorkspaceConfig;
+use crate::diagnostics::{effective_severities, resolve_related_uris, RuleSeverity, Suppressions};
+use crate::document::{
+    check_len, check_size, Document, DocumentStore, InputError, PositionEncoder,
+};
+use crate::features::{CallGraph, ExpressionAt, TerraformSymbol};
+use crate::requests::{
+    self, CallGraphParams, ExtractSnippetArgs, PreviewParams, ProfileParams, ProfileResult,
+    EXTRACT_SNIPPET,
+};
+use crate::rules::RuleRegistry;
+use crate::schema::SchemaOverlay;
+use crate::settings::{RuleLevel, Settings};
+use crate::terraform::{
+    find_module_dir, find_tfvars_files, load_tfvars, template_calls, variables_file, ChainLink,
+    ModuleIndex, TfVars, WorkspaceIndex,
+};
 
 /// The LSP backend that handles all language server requests
+///
+/// Clones share all state, so a clone can be moved into a background task.
+#[derive(Clone)]
 pub struct Backend {
     /// The LSP client for sending notifications
     client: Client,
     /// Map of document URIs to their state
-    documents: Arc<RwLock<HashMap<Url, Document>>>,
+    documents: Arc<RwLock<DocumentStore>>,
+    /// Workspace folders reported by the client at initialization
+    workspace_roots: Arc<RwLock<Vec<PathBuf>>>,
+    /// User settings from initialization options and configuration changes
+    settings: Arc<RwLock<Settings>>,
+    /// Position encoding agreed with the client at initialization
+    position_encoding: Arc<RwLock<PositionEncodingKind>>,
+    /// Capabilities the client lets us register dynamically
+    dynamic_capabilities: Arc<RwLock<Vec<Toggle>>>,
+    /// Dynamic capabilities currently registered with the client
+    registered: Arc<RwLock<Vec<Toggle>>>,
+    /// Permits for concurrent analyses, replaced when the limit changes
+    analysis_slots: Arc<RwLock<Arc<Semaphore>>>,
+    /// The validation running in the background for each document
+    validations: Arc<Mutex<HashMap<Url, AbortHandle>>>,
+    /// Terraform module declarations, shared with other sessions
+    modules: Arc<ModuleIndex>,
+    /// Terraform symbols of the workspace folders, filled in the background
+    /// once the session is initialized
+    index: Arc<WorkspaceIndex>,
+    /// Whether the client lets us register file watchers
+    watches_files: Arc<RwLock<bool>>,
+    /// Workspace configurations by workspace root
+    configs: Arc<FileCache<Arc<WorkspaceConfig>>>,
+    /// Schema overlays by file path
+    overlays: Arc<FileCache<Option<Arc<SchemaOverlay>>>>,
+    /// Merged tfvars values by template path
+    tfvars: Arc<FileCache<Arc<TfVars>>>,
+    /// Rules registered by an embedder, run next to the built-in analyzers
+    rules: RuleRegistry,
+}
+
+/// Build the LSP service for one editor session, with the custom requests
+/// registered and `rules` run on every document
+pub fn service(
+    modules: Arc<ModuleIndex>,
+    rules: RuleRegistry,
+) -> (LspService<Backend>, ClientSocket) {
+    LspService::build(|client| Backend::with_modules(client, modules).with_rules(rules))
+        .custom_method(requests::EFFECTIVE_SEVERITY, Backend::effective_severity)
+        .custom_method(requests::PROFILE, Backend::profile)
+        .custom_method(requests::CALL_GRAPH, Backend::call_graph)
+        .custom_method(requests::EXPRESSION_AT, Backend::expression_at)
+        .custom_method(requests::DEFINITION_CHAIN, Backend::definition_chain)
+        .custom_method(requests::PREVIEW, Backend::preview)
+        .finish()
+}
+
+/// LSP `RequestFailed` error code
+const REQUEST_FAILED: i64 = -32803;
+
+/// File operations the server wants to hear about: templates, and optionally
+/// folders that may contain them
+fn file_operation_filters(folders: bool) -> FileOperationRegistrationOptions {
+    let filter = |glob: &str, matches| FileOperationFilter {
+        scheme: Some("file".to_string()),
+        pattern: FileOperationPattern {
+            glob: glob.to_string(),
+            matches: Some(matches),
+            options: None,
+        },
+    };
+    let mut filters = vec![filter(
+        "**/*.{yaml,yml}.tftpl",
+        FileOperationPatternKind::File,
+    )];
+    if folders {
+        filters.push(filter("**", FileOperationPatternKind::Folder));
+    }
+    FileOperationRegistrationOptions { filters }
+}
+
+/// Turn rejected input into a request error, logging it
+fn input_error(uri: &Url, error: InputError) -> Error {
+    tracing::warn!(uri = %uri, %error, "Rejected request");
+    match error {
+        InputError::TooLarge { .. } => Error {
+            code: ErrorCode::ServerError(REQUEST_FAILED),
+            message: error.to_string().into(),
+            data: None,
+        },
+        _ => Error::invalid_params(error.to_string()),
+    }
 }
 
 impl Backend {
     /// Create a new backend instance
     pub fn new(client: Client) -> Self {
+        Self::with_modules(client, Arc::default())
+    }
+
+    /// Create a backend for one of several sessions sharing a module index
+    ///
+    /// Each session keeps its own documents and settings.
+    pub fn with_modules(client: Client, modules: Arc<ModuleIndex>) -> Self {
         Self {
             client,
-            documents: Arc::new(RwLock::new(HashMap::new())),
+            documents: Arc::new(RwLock::new(DocumentStore::new())),
+            workspace_roots: Arc::new(RwLock::new(Vec::new())),
+            settings: Arc::new(RwLock::new(Settings::default())),
+            position_encoding: Arc::new(RwLock::new(PositionEncodingKind::UTF16)),
+            dynamic_capabilities: Arc::new(RwLock::new(Vec::new())),
+            registered: Arc::new(RwLock::new(Vec::new())),
+            analysis_slots: Arc::new(RwLock::new(Arc::new(Semaphore::new(
+                Settings::default().analysis_concurrency(),
+            )))),
+            validations: Arc::new(Mutex::new(HashMap::new())),
+            modules,
+            index: Arc::default(),
+            watches_files: Arc::new(RwLock::new(false)),
+            configs: Arc::default(),
+            overlays: Arc::default(),
+            tfvars: Arc::default(),
+            rules: RuleRegistry::default(),
         }
     }
 
-    /// Validate a document and publish diagnostics
-    async fn validate_document(&self, uri: &Url, text: &str, version: Option<i32>) {
-        tracing::debug!(
-            uri = %uri,
-            version = ?version,
-            text_len = text.len(),
-            "Validating document"
-        );
+    /// Run an embedder's rules on every document, next to the built-in
+    /// analyzers
+    pub fn with_rules(mut self, rules: RuleRegistry) -> Self {
+        self.rules = rules;
+        self
+    }
+
+    /// Replace the settings, resizing the analysis limit to match
+    ///
+    /// Analyses already holding a permit from the old limit finish normally.
+    async fn apply_settings(&self, settings: Settings) {
+        *self.analysis_slots.write().await =
+            Arc::new(Semaphore::new(settings.analysis_concurrency()));
+        *self.settings.write().await = settings;
+    }
+
+    /// Size in bytes above which documents are not served
+    async fn max_file_size(&self) -> usize {
+        self.settings.read().await.max_file_size
+    }
 
-        let diagnostics = self.compute_diagnostics(text);
+    /// The version of an open document, `None` once it is closed
+    async fn current_version(&self, uri: &Url) -> Option<i32> {
+        self.documents.read().await.get(uri).map(|doc| doc.version)
+    }
+
+    /// Whether the settings enable a capability
+    async fn enabled(&self, toggle: Toggle) -> bool {
+        toggle.enabled(&*self.settings.read().await)
+    }
+
+    /// Register and unregister dynamic capabilities to match the settings
+    async fn sync_registrations(&self) {
+        let changes = {
+            let dynamic = self.dynamic_capabilities.read().await;
+            let registered = self.registered.read().await;
+            registration_changes(&dynamic, &registered, &*self.settings.read().await)
+        };
+
+        if !changes.unregister.is_empty() {
+            let methods: Vec<String> = changes
+                .unregister
+                .iter()
+                .map(|u| u.method.clone())
+                .collect();
+            match self.client.unregister_capability(changes.unregister).await {
+                Ok(()) => {
+                    tracing::info!(?methods, "Unregistered capabilities");
+                    self.registered
+                        .write()
+                        .await
+                        .retain(|t| !methods.iter().any(|m| m == t.method()));
+                }
+                Err(e) => tracing::warn!(error = %e, "Failed to unregister capabilities"),
+            }
+        }
+        if !changes.register.is_empty() {
+            let methods: Vec<String> = changes.register.iter().map(|r| r.method.clone()).collect();
+            match self.client.register_capability(changes.register).await {
+                Ok(()) => {
+                    tracing::info!(?methods, "Registered capabilities");
+                    self.registered.write().await.extend(
+                        Toggle::ALL
+                            .iter()
+                            .filter(|t| methods.iter().any(|m| m == t.method())),
+                    );
+                }
+                Err(e) => tracing::warn!(error = %e, "Failed to register capabilities"),
+            }
+        }
+    }
+
+    /// Look up an open document for a request, rejecting documents too large
+    /// to serve and positions that don't exist in the text
+    async fn checked<'a>(
+        &self,
+        docs: &'a DocumentStore,
+        uri: &Url,
+        positions: &[Position],
+    ) -> Result<Option<&'a Document>> {
+        let Some(doc) = docs.get(uri) else {
+            return Ok(None);
+        };
+        doc.check_size(self.max_file_size().await)
+            .map_err(|e| input_error(uri, e))?;
+        let encoding = self.position_encoding.read().await;
+        for position in positions {
+            doc.lines()
+                .check_position(doc.text(), *position, &encoding)
+                .map_err(|e| input_error(uri, e))?;
+        }
+        Ok(Some(doc))
+    }
+
+    /// An encoder converting positions in `doc`, the document at `uri`,
+    /// between the server and the client
+    async fn encoder<'a>(&self, uri: &'a Url, doc: &'a Document) -> PositionEncoder<'a> {
+        PositionEncoder::for_document(uri, doc, self.position_encoding.read().await.clone())
+    }
+
+    /// The workspace root containing a document, falling back to the first
+    /// root for documents outside every workspace folder
+    async fn workspace_root(&self, uri: &Url) -> Option<PathBuf> {
+        let roots = self.workspace_roots.read().await;
+        let path = uri.to_file_path().ok();
+        path.as_deref()
+            .and_then(|p| roots.iter().find(|root| p.starts_with(root)))
+            .or_else(|| roots.first())
+            .cloned()
+    }
+
+    /// The workspace configuration, logging rather than failing on errors;
+    /// read again only once the file changes
+    fn config(&self, root: &Path) -> Arc<WorkspaceConfig> {
+        let file = WorkspaceConfig::path(root);
+        self.configs.get(root, &[file], || {
+            Arc::new(WorkspaceConfig::load(root).unwrap_or_else(|e| {
+                tracing::warn!(root = %root.display(), error = %e, "Failed to load workspace config");
+                WorkspaceConfig::default()
+            }))
+        })
+    }
+
+    /// The tfvars values that apply to a template, read again only once one
+    /// of the files is added, removed or changed
+    fn tfvars(&self, template: &Path) -> Arc<TfVars> {
+        let files = find_tfvars_files(template);
+        self.tfvars
+            .get(template, &files, || Arc::new(load_tfvars(template)))
+    }
+
+    /// The severity cap the workspace configuration puts on a document
+    async fn max_severity(&self, uri: &Url) -> Option<RuleLevel> {
+        let path = uri.to_file_path().ok()?;
+        let root = self.workspace_root(uri).await?;
+        self.config(&root).max_severity(&root, &path)
+    }
+
+    /// The schema overlay a document is validated with, logging rather than
+    /// failing on errors; read again only once the file changes
+    async fn schema_overlay(
+        &self,
+        uri: &Url,
+        setting: Option<&Path>,
+    ) -> Option<Arc<SchemaOverlay>> {
+        let path = match self.workspace_root(uri).await {
+            Some(root) => self.config(&root).schema_path(&root, setting)?,
+            None => setting?.to_path_buf(),
+        };
+        self.overlays.get(&path, std::slice::from_ref(&path), || {
+            SchemaOverlay::load(&path)
+                .map(Arc::new)
+                .map_err(|e| {
+                    tracing::warn!(path = %path.display(), error = %e, "Failed to load schema overlay");
+                })
+                .ok()
+        })
+    }
+
+    /// Settings for analyzing a document, with its schema overlay, Terraform
+    /// module declarations and `templatefile()` calls loaded
+    async fn analysis_settings(&self, uri: &Url) -> Settings {
+        let mut settings = self.settings.read().await.clone();
+        settings.schema_overlay = self.schema_overlay(uri, settings.schema.as_deref()).await;
+        settings.terraform_module = uri
+            .to_file_path()
+            .ok()
+            .filter(|path| find_module_dir(path).is_some())
+            .map(|path| self.modules.decls(&path));
+        if let Ok(path) = uri.to_file_path() {
+            settings.template_calls = if self.index.is_ready() {
+                self.index.template_calls(&path)
+            } else {
+                let root = self.workspace_root(uri).await;
+                template_calls(&path, root.as_deref())
+            };
+        }
+        settings
+    }
+
+    /// Handle the `yaml-tftpl.extractSnippet` command
+    async fn extract_snippet(&self, args: ExtractSnippetArgs) -> Result<String> {
+        let snippet = {
+            let docs = self.documents.read().await;
+            let doc = self
+                .checked(&docs, &args.uri, &[args.range.start, args.range.end])
+                .await?
+                .ok_or_else(|| Error::invalid_params("Unknown document"))?;
+            let range = self.encoder(&args.uri, doc).await.from_client(args.range);
+            crate::features::extract_snippet(doc.text(), range, args.name)
+                .ok_or_else(|| Error::invalid_params("No step or workflow in range"))?
+        };
+        let snippet = crate::config::Snippet {
+            description: args.description,
+            ..snippet
+        };
+
+        let root = self
+            .workspace_root(&args.uri)
+            .await
+            .ok_or_else(|| Error::invalid_params("No workspace folder to store the snippet in"))?;
+        let mut config = WorkspaceConfig::load(&root).map_err(|e| {
+            Error::invalid_params(format!("Cannot read {}: {}", crate::config::CONFIG_FILE, e))
+        })?;
+        let name = snippet.name.clone();
+        config.add_snippet(snippet);
+        config.save(&root).map_err(|e| {
+            let mut error = Error::internal_error();
+            error.message = format!("Cannot write {}: {}", crate::config::CONFIG_FILE, e).into();
+            error
+        })?;
+
+        tracing::info!(root = %root.display(), name, "Saved snippet");
+        Ok(name)
+    }
+
+    /// Validate a document and publish diagnostics
+    async fn validate_document(&self, uri: &Url, text: Rope, version: Option<i32>) {
+        let Some(Analysis { diagnostics, .. }) = self.diagnose(uri, text, version).await else {
+            return;
+        };
 
         tracing::info!(
             uri = %uri,
@@ -49,45 +391,528 @@ impl Backend {
             .await;
     }
 
-    /// Compute diagnostics for the given text
-    fn compute_diagnostics(&self, text: &str) -> Vec<Diagnostic> {
-        use crate::diagnostics::DiagnosticCollector;
-        use crate::parser::preprocess_expressions;
+    /// Validate a document in the background, cancelling the validation of
+    /// any version it replaces
+    ///
+    /// Edits no longer wait for analysis, and a slow validation of an old
+    /// version can never publish over the diagnostics of a newer one.
+    /// The text is read from the open document when the validation starts,
+    /// as a rope sharing its chunks rather than a copy.
+    async fn spawn_validation(&self, uri: Url, version: i32) {
+        let backend = self.clone();
+        let document = uri.clone();
+        let task = tokio::spawn(async move {
+            let text = {
+                let docs = backend.documents.read().await;
+                let Some(doc) = docs.get(&document) else {
+                    return;
+                };
+                doc.rope().clone()
+            };
+            backend
+                .validate_document(&document, text, Some(version))
+                .await;
+        });
+        if let Some(previous) = self
+            .validations
+            .lock()
+            .await
+            .insert(uri, task.abort_handle())
+        {
+            previous.abort();
+        }
+    }
+
+    /// Cancel a document's background validation, if one is running
+    async fn cancel_validation(&self, uri: &Url) {
+        if let Some(task) = self.validations.lock().await.remove(uri) {
+            task.abort();
+        }
+    }
+
+    /// Analyze a document without publishing its diagnostics, which come
+    /// back in the client's position encoding
+    ///
+    /// The text is built from its rope and analyzed off the async runtime. A
+    /// versioned validation is dropped,
+    /// before and after analyzing, once a newer edit has replaced that
+    /// version, so results for text the user has moved past are never
+    /// published. Whether the analyzed version parsed is recorded on the
+    /// document, so completion can fall back on the last text that did.
+    /// Documents over the size limit get no diagnostics.
+    async fn diagnose(&self, uri: &Url, text: Rope, version: Option<i32>) -> Option<Analysis> {
+        tracing::debug!(
+            uri = %uri,
+            version = ?version,
+            text_len = text.len_bytes(),
+            "Validating document"
+        );
+
+        if let Err(error) = check_len(text.len_bytes(), self.max_file_size().await) {
+            tracing::warn!(uri = %uri, %error, "Skipping validation");
+            return Some(Analysis::default());
+        }
+
+        if version.is_some() && self.current_version(uri).await != version {
+            tracing::debug!(uri = %uri, version = ?version, "Skipping superseded version");
+            return None;
+        }
+
+        let slots = self.analysis_slots.read().await.clone();
+        let _permit = slots.acquire_owned().await.ok()?;
+        let options = AnalysisOptions {
+            settings: self.analysis_settings(uri).await,
+            max_severity: self.max_severity(uri).await,
+            rules: self.rules.clone(),
+        };
+        let encoding = self.position_encoding.read().await.clone();
+        let document = uri.clone();
+        let analysis = match tokio::task::spawn_blocking(move || {
+            let text = text.to_string();
+            let mut analysis = analyze(&text, &options);
+            resolve_related_uris(&mut analysis.diagnostics, &document);
+            analysis.diagnostics =
+                PositionEncoder::new(&document, &text, encoding).to_client(analysis.diagnostics);
+            analysis
+        })
+        .await
+        {
+            Ok(analysis) => analysis,
+            Err(e) => {
+                tracing::error!(uri = %uri, error = %e, "Analysis failed; publishing no diagnostics");
+                Analysis::default()
+            }
+        };
+
+        if let Some(version) = version {
+            let mut docs = self.documents.write().await;
+            let Some(doc) = docs.get_mut(uri).filter(|doc| doc.version == version) else {
+                tracing::debug!(uri = %uri, version, "Discarding stale diagnostics");
+                return None;
+            };
+            doc.record_parse(version, analysis.parsed);
+        }
+        Some(analysis)
+    }
+
+    /// Index the Terraform files of the workspace folders in the background,
+    /// then revalidate the open documents against the complete index
+    fn spawn_indexing(&self) {
+        let backend = self.clone();
+        tokio::spawn(async move {
+            let roots = backend.workspace_roots.read().await.clone();
+            let settings = backend.settings.read().await.clone();
+            let index = backend.index.clone();
+            let start = Instant::now();
+            let indexed = tokio::task::spawn_blocking(move || {
+                let files: usize = roots
+                    .iter()
+                    .map(|root| index.index_root(root, &settings))
+                    .sum();
+                index.mark_ready();
+                files
+            })
+            .await;
+            match indexed {
+                Ok(files) => tracing::info!(
+                    files,
+                    duration_ms = start.elapsed().as_millis() as u64,
+                    "Indexed Terraform files"
+                ),
+                Err(e) => {
+                    tracing::error!(error = %e, "Indexing Terraform files failed");
+                    return;
+                }
+            }
+            backend.revalidate_all().await;
+        });
+    }
+
+    /// Ask the client to report changes to `.tf` and `.tfvars` files, which
+    /// keep the workspace index current
+    async fn watch_terraform_files(&self) {
+        if !*self.watches_files.read().await {
+            return;
+        }
+        let options = DidChangeWatchedFilesRegistrationOptions {
+            watchers: vec![FileSystemWatcher {
+                glob_pattern: GlobPattern::String("**/*.{tf,tfvars}".to_string()),
+                kind: None,
+            }],
+        };
+        let registration = Registration {
+            id: "terraform-files".to_string(),
+            method: "workspace/didChangeWatchedFiles".to_string(),
+            register_options: serde_json::to_value(options).ok(),
+        };
+        if let Err(e) = self.client.register_capability(vec![registration]).await {
+            tracing::warn!(error = %e, "Failed to watch Terraform files");
+        }
+    }
+
+    /// Re-run diagnostics for every open document
+    ///
+    /// Every document is analyzed before any is published, and the batch is
+    /// logged once, to the client too, rather than once per document.
+    async fn revalidate_all(&self) {
+        let start = Instant::now();
+        let documents: Vec<(Url, Rope, i32)> = self
+            .documents
+            .read()
+            .await
+            .iter()
+            .map(|(uri, doc)| (uri.clone(), doc.rope().clone(), doc.version))
+            .collect();
+
+        let mut batch = Vec::with_capacity(documents.len());
+        for (uri, text, version) in documents {
+            if let Some(analysis) = self.diagnose(&uri, text, Some(version)).await {
+                batch.push((uri, analysis.diagnostics, version));
+            }
+        }
 
-        let mut collector = DiagnosticCollector::new();
+        let mut summary = BatchSummary::default();
+        for (uri, diagnostics, version) in batch {
+            // An edit while the batch was analyzed publishes its own results
+            if self.current_version(&uri).await != Some(version) {
+                tracing::debug!(uri = %uri, version, "Discarding stale diagnostics");
+                continue;
+            }
+            summary.add(&diagnostics);
+            self.client
+                .publish_diagnostics(uri, diagnostics, Some(version))
+                .await;
+        }
 
-        // Preprocess expressions to replace ${} and $${} with placeholders
-        tracing::trace!("Preprocessing expressions");
-        let (preprocessed, expression_map) = preprocess_expressions(text);
-        tracing::trace!(
-            expression_count = expression_map.expressions.len(),
-            "Expressions preprocessed"
+        let duration = start.elapsed();
+        tracing::info!(
+            files = summary.files,
+            errors = summary.errors,
+            warnings = summary.warnings,
+            duration_ms = duration.as_millis() as u64,
+            "Published diagnostics batch"
         );
+        self.client
+            .log_message(MessageType::INFO, summary.message(duration))
+            .await;
+    }
+
+    /// Handle `yaml-tftpl/effectiveSeverity`
+    pub async fn effective_severity(
+        &self,
+        params: TextDocumentPositionParams,
+    ) -> Result<Vec<RuleSeverity>> {
+        let uri = &params.text_document.uri;
+        let max_severity = self.max_severity(uri).await;
+        let docs = self.documents.read().await;
+        let doc = self
+            .checked(&docs, uri, &[params.position])
+            .await?
+            .ok_or_else(|| Error::invalid_params("Unknown document"))?;
+
+        let suppressions = Suppressions::parse(doc.text());
+        let settings = self.settings.read().await;
+        Ok(effective_severities(
+            &suppressions,
+            &settings,
+            max_severity,
+            params.position.line,
+        ))
+    }
 
-        // Parse YAML and collect errors
-        tracing::trace!("Parsing YAML");
-        let result = crate::parser::parse_yaml(&preprocessed, &expression_map, &mut collector);
-        tracing::trace!("YAML parsing complete");
+    /// Handle `yaml-tftpl/callGraph`
+    pub async fn call_graph(&self, params: CallGraphParams) -> Result<CallGraph> {
+        let docs = self.documents.read().await;
+        let doc = self
+            .checked(&docs, &params.text_document.uri, &[])
+            .await?
+            .ok_or_else(|| Error::invalid_params("Unknown document"))?;
 
-        // If parsing succeeded, validate workflow structure
-        if let Some(ref value) = result.value {
-            tracing::trace!("Validating workflow structure");
-            crate::diagnostics::validate_workflow(value, &preprocessed, &mut collector);
-            tracing::trace!("Workflow validation complete");
+        Ok(crate::features::call_graph(doc.text()))
+    }
+
+    /// Handle `yaml-tftpl/expressionAt`
+    pub async fn expression_at(
+        &self,
+        params: TextDocumentPositionParams,
+    ) -> Result<Option<ExpressionAt>> {
+        let uri = &params.text_document.uri;
+        let docs = self.documents.read().await;
+        let doc = self
+            .checked(&docs, uri, &[params.position])
+            .await?
+            .ok_or_else(|| Error::invalid_params("Unknown document"))?;
+
+        let encoder = self.encoder(uri, doc).await;
+        Ok(
+            crate::features::expression_at(doc.text(), encoder.decode(params.position)).map(
+                |expression| ExpressionAt {
+                    range: encoder.to_client(expression.range),
+                    ..expression
+                },
+            ),
+        )
+    }
+
+    /// Handle `yaml-tftpl/definitionChain`
+    pub async fn definition_chain(
+        &self,
+        params: TextDocumentPositionParams,
+    ) -> Result<Vec<ChainLink>> {
+        let uri = &params.text_document.uri;
+        let reference = {
+            let docs = self.documents.read().await;
+            let doc = self
+                .checked(&docs, uri, &[params.position])
+                .await?
+                .ok_or_else(|| Error::invalid_params("Unknown document"))?;
+            let position = self.encoder(uri, doc).await.decode(params.position);
+            let offset = doc.position_to_offset(position).unwrap_or(0);
+            crate::features::expression_at(doc.text(), position)
+                .and_then(|e| crate::terraform::reference_at(&e.text, offset - e.byte_range.start))
+        };
+        let (Some(reference), Ok(path)) = (reference, uri.to_file_path()) else {
+            return Ok(Vec::new());
+        };
+        Ok(crate::terraform::definition_chain(&path, &reference))
+    }
+
+    /// Handle `yaml-tftpl/preview`
+    pub async fn preview(&self, params: PreviewParams) -> Result<Vec<crate::features::Preview>> {
+        let uri = params.text_document.uri;
+        let text = {
+            let docs = self.documents.read().await;
+            let doc = self
+                .checked(&docs, &uri, &[])
+                .await?
+                .ok_or_else(|| Error::invalid_params("Unknown document"))?;
+            doc.text().to_string()
+        };
+        let root = self.workspace_root(&uri).await;
+        let config = root
+            .as_deref()
+            .map(|root| self.config(root))
+            .unwrap_or_default();
+        let profiles: Vec<&crate::config::RenderProfile> = match &params.profile {
+            Some(name) => vec![config.render_profile(name).ok_or_else(|| {
+                Error::invalid_params(format!("Unknown render profile '{}'", name))
+            })?],
+            None => config.render_profiles.iter().collect(),
+        };
+
+        let settings = self.analysis_settings(&uri).await;
+        let encoding = self.position_encoding.read().await.clone();
+        // Diagnostics point into each rendered text
+        let encoded = |mut preview: crate::features::Preview| {
+            if let Some(text) = &preview.text {
+                let diagnostics = std::mem::take(&mut preview.diagnostics);
+                preview.diagnostics =
+                    PositionEncoder::new(&uri, text, encoding.clone()).to_client(diagnostics);
+            }
+            preview
+        };
+        if profiles.is_empty() {
+            let vars = match uri.to_file_path() {
+                Ok(path) => crate::terraform::tfvars_values(&self.tfvars(&path)),
+                Err(_) => Default::default(),
+            };
+            return Ok(vec![encoded(crate::features::preview(
+                &text,
+                None,
+                Ok(vars),
+                &settings,
+            ))]);
         }
+        let root = root.unwrap_or_default();
+        Ok(profiles
+            .into_iter()
+            .map(|profile| {
+                encoded(crate::features::preview(
+                    &text,
+                    Some(profile.name.clone()),
+                    crate::terraform::profile_variables(&root, profile),
+                    &settings,
+                ))
+            })
+            .collect())
+    }
+
+    /// Handle `yaml-tftpl/profile`
+    pub async fn profile(&self, params: ProfileParams) -> Result<ProfileResult> {
+        let uri = params.text_document.uri;
+        let (text, version) = {
+            let docs = self.documents.read().await;
+            let doc = self
+                .checked(&docs, &uri, &[])
+                .await?
+                .ok_or_else(|| Error::invalid_params("Unknown document"))?;
+            (doc.rope().clone(), doc.version)
+        };
+
+        // Analyzed like any validation, and dropped the same way once an
+        // edit replaces the version
+        let text_len = text.len_bytes();
+        let Analysis {
+            diagnostics,
+            timings: mut stages,
+            ..
+        } = self
+            .diagnose(&uri, text, Some(version))
+            .await
+            .ok_or_else(Error::content_modified)?;
+        let diagnostics_count = diagnostics.len();
 
-        collector.into_diagnostics()
+        let start = Instant::now();
+        self.client
+            .publish_diagnostics(uri.clone(), diagnostics, Some(version))
+            .await;
+        stages.push(StageTiming {
+            stage: Stage::Publish,
+            duration: start.elapsed(),
+        });
+
+        let total_ms = stages.iter().map(|s| s.duration.as_secs_f64()).sum::<f64>() * 1000.0;
+        tracing::info!(uri = %uri, total_ms, "Profiled document");
+
+        Ok(ProfileResult {
+            version,
+            text_len,
+            diagnostics_count,
+            stages,
+            total_ms,
+        })
     }
 }
 
 #[tower_lsp::async_trait]
 impl LanguageServer for Backend {
-    async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+    async fn initialize(&self, params: InitializeParams) -> Result<InitializeResult> {
+        #[allow(deprecated)]
+        let roots: Vec<PathBuf> = match (params.workspace_folders, params.root_uri) {
+            (Some(folders), _) => folders
+                .iter()
+                .filter_map(|f| f.uri.to_file_path().ok())
+                .collect(),
+            (None, Some(root)) => root.to_file_path().into_iter().collect(),
+            (None, None) => Vec::new(),
+        };
+        *self.workspace_roots.write().await = roots;
+        if let Some(options) = params.initialization_options {
+            self.apply_settings(Settings::from_value(options)).await;
+        }
+
+        // The client's most preferred encoding, UTF-16 when it states none
+        let position_encoding = params
+            .capabilities
+            .general
+            .as_ref()
+            .and_then(|g| g.position_encodings.as_ref())
+            .and_then(|encodings| {
+                encodings.iter().find(|e| {
+                    [
+                        PositionEncodingKind::UTF8,
+                        PositionEncodingKind::UTF16,
+                        PositionEncodingKind::UTF32,
+                    ]
+                    .contains(e)
+                })
+            })
+            .cloned()
+            .unwrap_or(PositionEncodingKind::UTF16);
+        *self.position_encoding.write().await = position_encoding.clone();
+
+        let dynamic: Vec<Toggle> = Toggle::ALL
+            .iter()
+            .copied()
+            .filter(|t| t.is_dynamic(&params.capabilities))
+            .collect();
+        let is_static = |toggle: Toggle| !dynamic.contains(&toggle);
+        let range_formatting = is_static(Toggle::RangeFormatting);
+        let on_type_formatting = is_static(Toggle::OnTypeFormatting);
+        let inlay_hints = is_static(Toggle::InlayHints);
+        let code_lens = is_static(Toggle::CodeLens);
+        *self.dynamic_capabilities.write().await = dynamic;
+        *self.watches_files.write().await = params
+            .capabilities
+            .workspace
+            .as_ref()
+            .and_then(|w| w.did_change_watched_files)
+            .and_then(|w| w.dynamic_registration)
+            .unwrap_or(false);
+        // Saves carry the text the diagnostics are computed from
+        let validate_on_save = self.settings.read().await.validate_on_save;
+
         Ok(InitializeResult {
             capabilities: ServerCapabilities {
-                text_document_sync: Some(TextDocumentSyncCapability::Kind(
-                    TextDocumentSyncKind::FULL,
+                position_encoding: Some(position_encoding),
+                text_document_sync: Some(TextDocumentSyncCapability::Options(
+                    TextDocumentSyncOptions {
+                        open_close: Some(true),
+                        change: Some(TextDocumentSyncKind::INCREMENTAL),
+                        save: Some(TextDocumentSyncSaveOptions::SaveOptions(SaveOptions {
+                            include_text: Some(validate_on_save),
+                        })),
+                        ..Default::default()
+                    },
+                )),
+                hover_provider: Some(HoverProviderCapability::Simple(true)),
+                definition_provider: Some(OneOf::Left(true)),
+                code_action_provider: Some(CodeActionProviderCapability::Options(
+                    CodeActionOptions {
+                        code_action_kinds: Some(vec![
+                            CodeActionKind::QUICKFIX,
+                            CodeActionKind::REFACTOR_REWRITE,
+                            CodeActionKind::SOURCE_FIX_ALL,
+                        ]),
+                        ..Default::default()
+                    },
                 )),
+                document_range_formatting_provider: range_formatting.then_some(OneOf::Left(true)),
+                inlay_hint_provider: inlay_hints.then_some(OneOf::Left(true)),
+                code_lens_provider: code_lens.then_some(CodeLensOptions {
+                    resolve_provider: Some(true),
+                }),
+                document_on_type_formatting_provider: on_type_formatting.then(|| {
+                    DocumentOnTypeFormattingOptions {
+                        first_trigger_character: "\n".to_string(),
+                        more_trigger_character: Some(vec![":".to_string()]),
+                    }
+                }),
+                call_hierarchy_provider: Some(CallHierarchyServerCapability::Simple(true)),
+                linked_editing_range_provider: Some(LinkedEditingRangeServerCapabilities::Simple(
+                    true,
+                )),
+                semantic_tokens_provider: Some(
+                    SemanticTokensServerCapabilities::SemanticTokensOptions(
+                        SemanticTokensOptions {
+                            legend: crate::features::semantic_tokens_legend(),
+                            full: Some(SemanticTokensFullOptions::Bool(true)),
+                            ..Default::default()
+                        },
+                    ),
+                ),
+                completion_provider: Some(CompletionOptions {
+                    trigger_characters: Some(
+                        crate::features::TRIGGER_CHARACTERS
+                            .iter()
+                            .map(|c| c.to_string())
+                            .collect(),
+                    ),
+                    ..Default::default()
+                }),
+                execute_command_provider: Some(ExecuteCommandOptions {
+                    commands: vec![EXTRACT_SNIPPET.to_string()],
+                    ..Default::default()
+                }),
+                workspace: Some(WorkspaceServerCapabilities {
+                    workspace_folders: None,
+                    file_operations: Some(WorkspaceFileOperationsServerCapabilities {
+                        did_create: Some(file_operation_filters(false)),
+                        will_rename: Some(file_operation_filters(true)),
+                        did_delete: Some(file_operation_filters(true)),
+                        ..Default::default()
+                    }),
+                }),
                 ..Default::default()
             },
             server_info: Some(ServerInfo {
@@ -99,6 +924,9 @@ impl LanguageServer for Backend {
 
     async fn initialized(&self, _: InitializedParams) {
         tracing::info!("Server initialized");
+        self.sync_registrations().await;
+        self.watch_terraform_files().await;
+        self.spawn_indexing();
     }
 
     async fn shutdown(&self) -> Result<()> {
@@ -112,40 +940,79 @@ impl LanguageServer for Backend {
         let version = params.text_document.version;
 
         tracing::debug!("Document opened: {}", uri);
+        let max_size = self.max_file_size().await;
+        if let Err(error) = check_size(&text, max_size) {
+            self.client
+                .show_message(
+                    MessageType::WARNING,
+                    format!("{}: {}; diagnostics are disabled for it", uri, error),
+                )
+                .await;
+        }
 
         // Store document
         {
             let mut docs = self.documents.write().await;
-            docs.insert(uri.clone(), Document::new(text.clone(), version));
+            docs.open(uri.clone(), text, version);
         }
 
         // Validate and publish diagnostics
-        self.validate_document(&uri, &text, Some(version)).await;
+        self.spawn_validation(uri, version).await;
     }
 
     async fn did_change(&self, params: DidChangeTextDocumentParams) {
         let uri = params.text_document.uri;
         let version = params.text_document.version;
 
-        // Get the full text from the changes (we use FULL sync)
-        if let Some(change) = params.content_changes.into_iter().next() {
-            let text = change.text;
+        tracing::debug!("Document changed: {}", uri);
 
-            tracing::debug!("Document changed: {}", uri);
-
-            // Update document
-            {
-                let mut docs = self.documents.write().await;
-                docs.insert(uri.clone(), Document::new(text.clone(), version));
+        // Apply the edits to the stored document
+        {
+            let encoding = self.position_encoding.read().await.clone();
+            let mut docs = self.documents.write().await;
+            let changes = params.content_changes;
+            if docs.change(&uri, changes, version, &encoding).is_none() {
+                tracing::warn!(uri = %uri, "Ignoring edits to an unknown document");
+                return;
             }
+        }
 
-            // Validate and publish diagnostics
-            self.validate_document(&uri, &text, Some(version)).await;
+        // Validate and publish diagnostics, unless that waits for a save
+        if !self.settings.read().await.validate_on_save {
+            self.spawn_validation(uri, version).await;
         }
     }
 
+    async fn did_change_configuration(&self, params: DidChangeConfigurationParams) {
+        tracing::debug!("Configuration changed");
+        let settings = self.settings.read().await.merge(params.settings);
+        self.apply_settings(settings).await;
+        self.sync_registrations().await;
+        self.revalidate_all().await;
+    }
+
     async fn did_save(&self, params: DidSaveTextDocumentParams) {
-        tracing::debug!("Document saved: {}", params.text_document.uri);
+        let uri = params.text_document.uri;
+        tracing::debug!("Document saved: {}", uri);
+
+        // The saved text, when sent, is what's on disk and replaces ours
+        let version = {
+            let mut docs = self.documents.write().await;
+            let Some(doc) = docs.get_mut(&uri) else {
+                tracing::warn!(uri = %uri, "Ignoring save of an unknown document");
+                return;
+            };
+            if let Some(text) = params.text.filter(|text| doc.rope() != text) {
+                tracing::debug!(uri = %uri, "Saved text differs from the open document");
+                let version = doc.version;
+                doc.update(text, version);
+            }
+            doc.version
+        };
+
+        if self.settings.read().await.validate_on_save {
+            self.spawn_validation(uri, version).await;
+        }
     }
 
     async fn did_close(&self, params: DidCloseTextDocumentParams) {
@@ -155,10 +1022,512 @@ impl LanguageServer for Backend {
         // Remove document from our state
         {
             let mut docs = self.documents.write().await;
-            docs.remove(&uri);
+            docs.close(&uri);
         }
+        self.cancel_validation(&uri).await;
 
         // Clear diagnostics for this document
         self.client.publish_diagnostics(uri, vec![], None).await;
     }
+
+    async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
+        let uri = params.text_document_position_params.text_document.uri;
+        let position = params.text_document_position_params.position;
+
+        let variables = match uri.to_file_path() {
+            Ok(path) => self.modules.decls(&path).variables.clone(),
+            Err(_) => Vec::new(),
+        };
+
+        let docs = self.documents.read().await;
+        let Some(doc) = self.checked(&docs, &uri, &[position]).await? else {
+            return Ok(None);
+        };
+        let encoder = self.encoder(&uri, doc).await;
+        let hover = crate::features::hover(doc.text(), encoder.decode(position), &variables);
+        Ok(encoder.to_client(hover))
+    }
+
+    async fn range_formatting(
+        &self,
+        params: DocumentRangeFormattingParams,
+    ) -> Result<Option<Vec<TextEdit>>> {
+        if !self.enabled(Toggle::RangeFormatting).await {
+            return Ok(None);
+        }
+        let format_expressions = self.settings.read().await.format_expressions;
+        let uri = params.text_document.uri;
+
+        let docs = self.documents.read().await;
+        let Some(doc) = self
+            .checked(&docs, &uri, &[params.range.start, params.range.end])
+            .await?
+        else {
+            return Ok(None);
+        };
+        let encoder = self.encoder(&uri, doc).await;
+        let range = encoder.from_client(params.range);
+        let edits = crate::features::format_range(doc.text(), range, format_expressions);
+        Ok(Some(encoder.to_client(edits)))
+    }
+
+    async fn code_action(&self, params: CodeActionParams) -> Result<Option<CodeActionResponse>> {
+        let uri = params.text_document.uri;
+        let range = params.range;
+
+        let docs = self.documents.read().await;
+        let Some(doc) = self.checked(&docs, &uri, &[range.start, range.end]).await? else {
+            return Ok(None);
+        };
+        let encoder = self.encoder(&uri, doc).await;
+        let range = encoder.from_client(range);
+        let diagnostics = encoder.from_client(params.context.diagnostics);
+        let only = params.context.only.as_deref();
+        let mut actions = crate::features::quick_fixes(&uri, doc.text(), range, &diagnostics, only);
+        let variables_file = uri
+            .to_file_path()
+            .ok()
+            .and_then(|path| find_module_dir(&path))
+            .and_then(|module| variables_file(&module));
+        if let Some(tf_file) = variables_file {
+            if let Ok(tf_text) = std::fs::read_to_string(&tf_file) {
+                actions.extend(crate::features::declare_variable_fixes(
+                    doc.text(),
+                    &diagnostics,
+                    &tf_file,
+                    &tf_text,
+                    only,
+                ));
+            }
+        }
+        actions.extend(crate::features::expression_code_actions(
+            &uri,
+            doc.text(),
+            range,
+            only,
+        ));
+        Ok(Some(encoder.to_client(actions)))
+    }
+
+    async fn inlay_hint(&self, params: InlayHintParams) -> Result<Option<Vec<InlayHint>>> {
+        if !self.enabled(Toggle::InlayHints).await {
+            return Ok(None);
+        }
+        let uri = params.text_document.uri;
+        let Ok(path) = uri.to_file_path() else {
+            return Ok(None);
+        };
+        let tfvars = self.tfvars(&path);
+
+        let docs = self.documents.read().await;
+        let Some(doc) = self
+            .checked(&docs, &uri, &[params.range.start, params.range.end])
+            .await?
+        else {
+            return Ok(None);
+        };
+        let encoder = self.encoder(&uri, doc).await;
+        let hints =
+            crate::features::inlay_hints(doc.text(), encoder.from_client(params.range), &tfvars);
+        Ok(Some(encoder.to_client(hints)))
+    }
+
+    async fn code_lens(&self, params: CodeLensParams) -> Result<Option<Vec<CodeLens>>> {
+        if !self.enabled(Toggle::CodeLens).await {
+            return Ok(None);
+        }
+        let uri = params.text_document.uri;
+
+        let docs = self.documents.read().await;
+        let Some(doc) = self.checked(&docs, &uri, &[]).await? else {
+            return Ok(None);
+        };
+        let mut lenses = crate::features::code_lenses(doc.text());
+        // Remember the document so the lens can be resolved later
+        for lens in &mut lenses {
+            if let Some(serde_json::Value::Object(data)) = &mut lens.data {
+                data.insert("uri".to_string(), serde_json::json!(uri));
+            }
+        }
+        Ok(Some(self.encoder(&uri, doc).await.to_client(lenses)))
+    }
+
+    async fn code_lens_resolve(&self, lens: CodeLens) -> Result<CodeLens> {
+        let uri = lens
+            .data
+            .as_ref()
+            .and_then(|d| d.get("uri"))
+            .and_then(|u| serde_json::from_value::<Url>(u.clone()).ok());
+        let Some(uri) = uri else {
+            return Ok(lens);
+        };
+
+        let docs = self.documents.read().await;
+        Ok(match self.checked(&docs, &uri, &[]).await? {
+            Some(doc) => crate::features::resolve_code_lens(doc.text(), lens),
+            None => lens,
+        })
+    }
+
+    async fn on_type_formatting(
+        &self,
+        params: DocumentOnTypeFormattingParams,
+    ) -> Result<Option<Vec<TextEdit>>> {
+        if !self.enabled(Toggle::OnTypeFormatting).await {
+            return Ok(None);
+        }
+        let uri = params.text_document_position.text_document.uri;
+        let position = params.text_document_position.position;
+
+        let docs = self.documents.read().await;
+        let Some(doc) = self.checked(&docs, &uri, &[position]).await? else {
+            return Ok(None);
+        };
+        let encoder = self.encoder(&uri, doc).await;
+        let edits =
+            crate::features::format_on_type(doc.text(), encoder.decode(position), &params.ch);
+        Ok(Some(encoder.to_client(edits)))
+    }
+
+    async fn completion(&self, params: CompletionParams) -> Result<Option<CompletionResponse>> {
+        let uri = params.text_document_position.text_document.uri;
+        let position = params.text_document_position.position;
+
+        let snippets = match self.workspace_root(&uri).await {
+            Some(root) => self.config(&root).snippets.clone(),
+            None => Vec::new(),
+        };
+        let decls = match uri.to_file_path() {
+            Ok(path) => self.modules.decls(&path),
+            Err(_) => Default::default(),
+        };
+
+        let (text, last_good, version) = {
+            let docs = self.documents.read().await;
+            let Some(doc) = self.checked(&docs, &uri, &[position]).await? else {
+                return Ok(None);
+            };
+            (doc.text().to_string(), doc.last_good(), doc.version)
+        };
+
+        // Computed off the runtime so a `$/cancelRequest` can drop the request
+        let encoding = self.position_encoding.read().await.clone();
+        let document = uri.clone();
+        let items = tokio::task::spawn_blocking(move || {
+            let encoder = PositionEncoder::new(&document, &text, encoding);
+            let position = encoder.decode(position);
+            let items = crate::features::completions(
+                &text,
+                last_good.as_deref(),
+                position,
+                &snippets,
+                &decls,
+            );
+            encoder.to_client(items)
+        })
+        .await
+        .map_err(|e| {
+            tracing::error!(uri = %uri, error = %e, "Completion failed");
+            Error::internal_error()
+        })?;
+
+        if self.current_version(&uri).await != Some(version) {
+            tracing::debug!(uri = %uri, version, "Discarding completions for a stale version");
+            return Err(Error::content_modified());
+        }
+        Ok(Some(CompletionResponse::Array(items)))
+    }
+
+    async fn execute_command(
+        &self,
+        params: ExecuteCommandParams,
+    ) -> Result<Option<serde_json::Value>> {
+        match params.command.as_str() {
+            EXTRACT_SNIPPET => {
+                let args = params
+                    .arguments
+                    .into_iter()
+                    .next()
+                    .and_then(|a| serde_json::from_value::<ExtractSnippetArgs>(a).ok())
+                    .ok_or_else(|| Error::invalid_params("Expected snippet arguments"))?;
+                let name = self.extract_snippet(args).await?;
+                self.client
+                    .show_message(MessageType::INFO, format!("Saved snippet '{}'", name))
+                    .await;
+                Ok(Some(serde_json::json!({ "name": name })))
+            }
+            other => Err(Error::invalid_params(format!(
+                "Unknown command '{}'",
+                other
+            ))),
+        }
+    }
+
+    async fn did_create_files(&self, params: CreateFilesParams) {
+        for file in params.files {
+            let Ok(uri) = Url::parse(&file.uri) else {
+                continue;
+            };
+            let empty = match uri.to_file_path() {
+                Ok(path) => std::fs::read_to_string(path).is_ok_and(|t| t.trim().is_empty()),
+                Err(_) => false,
+            };
+            if !empty {
+                continue;
+            }
+
+            // The client may wait for this notification to be handled before
+            // it answers the prompt, so the prompt can't be awaited here
+            let client = self.client.clone();
+            tokio::spawn(async move {
+                let insert = MessageActionItem {
+                    title: "Insert workflow skeleton".to_string(),
+                    properties: HashMap::new(),
+                };
+                let choice = client
+                    .show_message_request(
+                        MessageType::INFO,
+                        format!("{} is empty. Start from a workflow skeleton?", uri),
+                        Some(vec![insert.clone()]),
+                    )
+                    .await;
+                if choice
+                    .ok()
+                    .flatten()
+                    .is_some_and(|c| c.title == insert.title)
+                {
+                    let edit = crate::features::scaffold_edit(&uri);
+                    if let Err(e) = client.apply_edit(edit).await {
+                        tracing::warn!(uri = %uri, error = %e, "Failed to insert skeleton");
+                    }
+                }
+            });
+        }
+    }
+
+    async fn did_delete_files(&self, params: DeleteFilesParams) {
+        let deleted: Vec<PathBuf> = params
+            .files
+            .iter()
+            .filter_map(|f| Url::parse(&f.uri).ok()?.to_file_path().ok())
+            .collect();
+
+        // A deleted folder takes every open document below it along
+        let removed: Vec<Url> = {
+            let mut docs = self.documents.write().await;
+            let gone: Vec<Url> = docs
+                .uris()
+                .filter(|uri| {
+                    uri.to_file_path()
+                        .is_ok_and(|p| deleted.iter().any(|d| p.starts_with(d)))
+                })
+                .cloned()
+                .collect();
+            for uri in &gone {
+                docs.close(uri);
+            }
+            gone
+        };
+
+        for uri in removed {
+            tracing::debug!("Document deleted: {}", uri);
+            self.cancel_validation(&uri).await;
+            self.client.publish_diagnostics(uri, vec![], None).await;
+        }
+    }
+
+    async fn did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
+        // Reading and parsing the files blocks, so it runs off the runtime
+        let index = self.index.clone();
+        let indexed = tokio::task::spawn_blocking(move || {
+            let mut changed = 0;
+            for event in params.changes {
+                let Ok(path) = event.uri.to_file_path() else {
+                    continue;
+                };
+                if event.typ == FileChangeType::DELETED {
+                    index.remove(&path);
+                } else {
+                    index.update(&path);
+                }
+                changed += 1;
+            }
+            changed
+        })
+        .await;
+        let changed = match indexed {
+            Ok(changed) => changed,
+            Err(e) => {
+                tracing::error!(error = %e, "Indexing changed Terraform files failed");
+                return;
+            }
+        };
+        if changed > 0 {
+            tracing::debug!(changed, "Terraform files changed; revalidating");
+            self.revalidate_all().await;
+        }
+    }
+
+    async fn will_rename_files(&self, params: RenameFilesParams) -> Result<Option<WorkspaceEdit>> {
+        let renames: Vec<(PathBuf, PathBuf)> = params
+            .files
+            .iter()
+            .filter_map(|rename| {
+                let old = Url::parse(&rename.old_uri).ok()?.to_file_path().ok()?;
+                let new = Url::parse(&rename.new_uri).ok()?.to_file_path().ok()?;
+                Some((old, new))
+            })
+            .collect();
+        if renames.is_empty() {
+            return Ok(None);
+        }
+
+        // Walking the workspace and reading its files blocks, so it runs off
+        // the runtime
+        let roots = self.workspace_roots.read().await.clone();
+        let settings = self.settings.read().await.clone();
+        let count = renames.len();
+        let edit = tokio::task::spawn_blocking(move || {
+            let tf_files: Vec<(PathBuf, String)> = roots
+                .iter()
+                .flat_map(|root| crate::workspace::walk_with(root, &settings))
+                .filter(|path| path.extension().is_some_and(|ext| ext == "tf"))
+                .filter_map(|path| {
+                    let text = std::fs::read_to_string(&path).ok()?;
+                    Some((path, text))
+                })
+                .collect();
+            crate::features::rename_edits(&renames, &tf_files)
+        })
+        .await
+        .map_err(|e| {
+            tracing::error!(error = %e, "Updating templatefile() references failed");
+            Error::internal_error()
+        })?;
+        tracing::info!(
+            renames = count,
+            files = edit
+                .as_ref()
+                .and_then(|e| e.changes.as_ref())
+                .map_or(0, |c| c.len()),
+            "Updating templatefile() references"
+        );
+        Ok(edit)
+    }
+
+    async fn prepare_call_hierarchy(
+        &self,
+        params: CallHierarchyPrepareParams,
+    ) -> Result<Option<Vec<CallHierarchyItem>>> {
+        let uri = params.text_document_position_params.text_document.uri;
+        let position = params.text_document_position_params.position;
+
+        let docs = self.documents.read().await;
+        let Some(doc) = self.checked(&docs, &uri, &[position]).await? else {
+            return Ok(None);
+        };
+        let encoder = self.encoder(&uri, doc).await;
+        let items =
+            crate::features::prepare_call_hierarchy(doc.text(), &uri, encoder.decode(position));
+        Ok(encoder.to_client(items))
+    }
+
+    async fn incoming_calls(
+        &self,
+        params: CallHierarchyIncomingCallsParams,
+    ) -> Result<Option<Vec<CallHierarchyIncomingCall>>> {
+        let uri = &params.item.uri;
+
+        let docs = self.documents.read().await;
+        let Some(doc) = self.checked(&docs, uri, &[]).await? else {
+            return Ok(None);
+        };
+        let encoder = self.encoder(uri, doc).await;
+        let item = encoder.from_client(params.item.clone());
+        let calls = crate::features::incoming_calls(doc.text(), uri, &item);
+        Ok(Some(encoder.to_client(calls)))
+    }
+
+    async fn outgoing_calls(
+        &self,
+        params: CallHierarchyOutgoingCallsParams,
+    ) -> Result<Option<Vec<CallHierarchyOutgoingCall>>> {
+        let uri = &params.item.uri;
+
+        let docs = self.documents.read().await;
+        let Some(doc) = self.checked(&docs, uri, &[]).await? else {
+            return Ok(None);
+        };
+        let encoder = self.encoder(uri, doc).await;
+        let item = encoder.from_client(params.item.clone());
+        let calls = crate::features::outgoing_calls(doc.text(), uri, &item);
+        Ok(Some(encoder.to_client(calls)))
+    }
+
+    async fn goto_definition(
+        &self,
+        params: GotoDefinitionParams,
+    ) -> Result<Option<GotoDefinitionResponse>> {
+        let uri = params.text_document_position_params.text_document.uri;
+        let position = params.text_document_position_params.position;
+
+        let docs = self.documents.read().await;
+        let Some(doc) = self.checked(&docs, &uri, &[position]).await? else {
+            return Ok(None);
+        };
+        let encoder = self.encoder(&uri, doc).await;
+        let position = encoder.decode(position);
+        if let Some(range) = crate::features::definition(doc.text(), position) {
+            return Ok(Some(GotoDefinitionResponse::Scalar(Location::new(
+                uri.clone(),
+                encoder.to_client(range),
+            ))));
+        }
+
+        // `${var.x}`/`${local.y}` jump into the module's `.tf` files
+        let Some(symbol) = crate::features::terraform_symbol_at(doc.text(), position) else {
+            return Ok(None);
+        };
+        let Some(module) = uri.to_file_path().ok().and_then(|p| find_module_dir(&p)) else {
+            return Ok(None);
+        };
+        let location = match symbol {
+            TerraformSymbol::Variable(name) => self.index.variable(&module, &name),
+            TerraformSymbol::Local(name) => self.index.local(&module, &name),
+        };
+        Ok(location.map(GotoDefinitionResponse::Scalar))
+    }
+
+    async fn linked_editing_range(
+        &self,
+        params: LinkedEditingRangeParams,
+    ) -> Result<Option<LinkedEditingRanges>> {
+        let uri = params.text_document_position_params.text_document.uri;
+        let position = params.text_document_position_params.position;
+
+        let docs = self.documents.read().await;
+        let Some(doc) = self.checked(&docs, &uri, &[position]).await? else {
+            return Ok(None);
+        };
+        let encoder = self.encoder(&uri, doc).await;
+        let ranges = crate::features::linked_editing_ranges(doc.text(), encoder.decode(position));
+        Ok(encoder.to_client(ranges))
+    }
+
+    async fn semantic_tokens_full(
+        &self,
+        params: SemanticTokensParams,
+    ) -> Result<Option<SemanticTokensResult>> {
+        let uri = params.text_document.uri;
+
+        let docs = self.documents.read().await;
+        let Some(doc) = self.checked(&docs, &uri, &[]).await? else {
+            return Ok(None);
+        };
+        let tokens = crate::features::semantic_tokens(doc.text());
+        Ok(Some(SemanticTokensResult::Tokens(SemanticTokens {
+            result_id: None,
+            data: self.encoder(&uri, doc).await.semantic_tokens(tokens),
+        })))
+    }
 }
@@ -0,0 +1,92 @@
+//! Values read from files, kept until the files change
+//!
+//! The server rereads the workspace configuration, the schema overlay and
+//! tfvars files for every analysis and many requests. A [`FileCache`] keeps
+//! what was loaded from them and loads again only once one of the files is
+//! added, removed or modified, like [`crate::terraform::ModuleIndex`] does for
+//! module declarations.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// Files with their modification times and sizes, `None` when missing
+type Fingerprint = Vec<(PathBuf, Option<(SystemTime, u64)>)>;
+
+fn fingerprint(files: &[PathBuf]) -> Fingerprint {
+    files
+        .iter()
+        .map(|path| {
+            let stamp = fs::metadata(path)
+                .ok()
+                .and_then(|m| Some((m.modified().ok()?, m.len())));
+            (path.clone(), stamp)
+        })
+        .collect()
+}
+
+/// Values loaded from files, cached by a key such as the file's path
+#[derive(Debug)]
+pub(crate) struct FileCache<T> {
+    entries: Mutex<HashMap<PathBuf, (Fingerprint, T)>>,
+}
+
+impl<T> Default for FileCache<T> {
+    fn default() -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<T: Clone> FileCache<T> {
+    /// The value cached for `key`, or what `load` returns if any of `files`
+    /// changed since it was cached
+    ///
+    /// `load` runs without the lock held, so a slow load doesn't hold up
+    /// lookups of other keys.
+    pub fn get(&self, key: &Path, files: &[PathBuf], load: impl FnOnce() -> T) -> T {
+        let fingerprint = fingerprint(files);
+        if let Some((cached, value)) = self.entries.lock().unwrap().get(key) {
+            if *cached == fingerprint {
+                return value.clone();
+            }
+        }
+        let value = load();
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(key.to_path_buf(), (fingerprint, value.clone()));
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn test_reloads_when_a_file_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.json");
+        let files = [path.clone()];
+        let cache = FileCache::default();
+        let loads = Cell::new(0);
+        let load = || {
+            loads.set(loads.get() + 1);
+            fs::read_to_string(&path).unwrap_or_default()
+        };
+
+        assert_eq!(cache.get(&path, &files, load), "");
+        assert_eq!(cache.get(&path, &files, load), "");
+        assert_eq!(loads.get(), 1);
+
+        fs::write(&path, "{}").unwrap();
+        assert_eq!(cache.get(&path, &files, load), "{}");
+        assert_eq!(cache.get(&path, &files, load), "{}");
+        assert_eq!(loads.get(), 2);
+    }
+}
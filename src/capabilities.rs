@@ -0,0 +1,184 @@
+//! Capabilities that settings can switch on and off
+//!
+//! When the client supports dynamic registration for one of these, it is left
+//! out of the static capabilities and registered after `initialized` instead,
+//! so a configuration change can add or remove it without a restart. Clients
+//! without that support get it statically and the handler checks the setting.
+
+use serde_json::json;
+use tower_lsp::lsp_types::{ClientCapabilities, Registration, Unregistration};
+
+use crate::settings::Settings;
+
+/// A capability tied to a setting
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Toggle {
+    RangeFormatting,
+    OnTypeFormatting,
+    InlayHints,
+    CodeLens,
+}
+
+impl Toggle {
+    pub const ALL: &'static [Toggle] = &[
+        Toggle::RangeFormatting,
+        Toggle::OnTypeFormatting,
+        Toggle::InlayHints,
+        Toggle::CodeLens,
+    ];
+
+    /// The request method the capability serves
+    pub fn method(self) -> &'static str {
+        match self {
+            Toggle::RangeFormatting => "textDocument/rangeFormatting",
+            Toggle::OnTypeFormatting => "textDocument/onTypeFormatting",
+            Toggle::InlayHints => "textDocument/inlayHint",
+            Toggle::CodeLens => "textDocument/codeLens",
+        }
+    }
+
+    /// Whether the settings enable the capability
+    pub fn enabled(self, settings: &Settings) -> bool {
+        match self {
+            Toggle::RangeFormatting | Toggle::OnTypeFormatting => settings.formatting,
+            Toggle::InlayHints => settings.inlay_hints,
+            Toggle::CodeLens => settings.code_lens,
+        }
+    }
+
+    /// Whether the client accepts dynamic registration of the capability
+    pub fn is_dynamic(self, client: &ClientCapabilities) -> bool {
+        let Some(text_document) = &client.text_document else {
+            return false;
+        };
+        let dynamic = match self {
+            Toggle::RangeFormatting => text_document
+                .range_formatting
+                .and_then(|c| c.dynamic_registration),
+            Toggle::OnTypeFormatting => text_document
+                .on_type_formatting
+                .and_then(|c| c.dynamic_registration),
+            Toggle::InlayHints => text_document
+                .inlay_hint
+                .as_ref()
+                .and_then(|c| c.dynamic_registration),
+            Toggle::CodeLens => text_document.code_lens.and_then(|c| c.dynamic_registration),
+        };
+        dynamic.unwrap_or(false)
+    }
+
+    fn registration(self) -> Registration {
+        // A null selector applies the client's own document selector
+        let options = match self {
+            Toggle::OnTypeFormatting => json!({
+                "documentSelector": null,
+                "firstTriggerCharacter": "\n",
+                "moreTriggerCharacter": [":"],
+            }),
+            Toggle::CodeLens => json!({ "documentSelector": null, "resolveProvider": true }),
+            Toggle::RangeFormatting | Toggle::InlayHints => json!({ "documentSelector": null }),
+        };
+        Registration {
+            id: self.method().to_string(),
+            method: self.method().to_string(),
+            register_options: Some(options),
+        }
+    }
+
+    fn unregistration(self) -> Unregistration {
+        Unregistration {
+            id: self.method().to_string(),
+            method: self.method().to_string(),
+        }
+    }
+}
+
+/// Registrations and unregistrations that bring the dynamically registered
+/// capabilities in line with the settings
+#[derive(Debug, Default)]
+pub struct RegistrationChanges {
+    pub register: Vec<Registration>,
+    pub unregister: Vec<Unregistration>,
+}
+
+/// Work out what to register and unregister, given the dynamic capabilities
+/// and the ones currently registered
+pub fn registration_changes(
+    dynamic: &[Toggle],
+    registered: &[Toggle],
+    settings: &Settings,
+) -> RegistrationChanges {
+    let mut changes = RegistrationChanges::default();
+    for &toggle in dynamic {
+        match (toggle.enabled(settings), registered.contains(&toggle)) {
+            (true, false) => changes.register.push(toggle.registration()),
+            (false, true) => changes.unregister.push(toggle.unregistration()),
+            _ => {}
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower_lsp::lsp_types::{
+        DynamicRegistrationClientCapabilities, TextDocumentClientCapabilities,
+    };
+
+    #[test]
+    fn test_is_dynamic() {
+        let client = ClientCapabilities {
+            text_document: Some(TextDocumentClientCapabilities {
+                range_formatting: Some(DynamicRegistrationClientCapabilities {
+                    dynamic_registration: Some(true),
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert!(Toggle::RangeFormatting.is_dynamic(&client));
+        assert!(!Toggle::CodeLens.is_dynamic(&client));
+        assert!(!Toggle::RangeFormatting.is_dynamic(&ClientCapabilities::default()));
+    }
+
+    #[test]
+    fn test_registration_changes() {
+        let dynamic = [
+            Toggle::RangeFormatting,
+            Toggle::OnTypeFormatting,
+            Toggle::CodeLens,
+        ];
+        let settings = Settings::default();
+
+        let changes = registration_changes(&dynamic, &[], &settings);
+        let methods: Vec<&str> = changes.register.iter().map(|r| r.method.as_str()).collect();
+        assert_eq!(
+            methods,
+            vec![
+                "textDocument/rangeFormatting",
+                "textDocument/onTypeFormatting",
+                "textDocument/codeLens"
+            ]
+        );
+
+        let settings = Settings {
+            formatting: false,
+            ..Settings::default()
+        };
+        let changes = registration_changes(&dynamic, &dynamic, &settings);
+        assert!(changes.register.is_empty());
+        let methods: Vec<&str> = changes
+            .unregister
+            .iter()
+            .map(|u| u.method.as_str())
+            .collect();
+        assert_eq!(
+            methods,
+            vec![
+                "textDocument/rangeFormatting",
+                "textDocument/onTypeFormatting"
+            ]
+        );
+    }
+}
@@ -0,0 +1,749 @@
+//! `yaml-tftpl-lsp check`
+//!
+//! Runs the server's analysis over templates without an editor and reports
+//! each diagnostic as `file:line:column`, as SARIF for code scanning tools or
+//! as JSON for other tooling, so templates can be linted in CI.
+
+use std::fmt;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+
+use serde::Serialize;
+use serde_json::{json, Value};
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString};
+
+use crate::analysis::{analyze, AnalysisOptions};
+use crate::config::WorkspaceConfig;
+use crate::diagnostics::DiagnosticCode;
+use crate::rules::RuleRegistry;
+use crate::settings::RuleLevel;
+use crate::workspace;
+
+/// Where the SARIF output says the tool is documented
+const INFORMATION_URI: &str = "https://github.com/mlopezgez/yaml-tftpl-lsp";
+
+/// How the report is printed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Format {
+    /// One `file:line:column: severity[code]: message` line per diagnostic
+    #[default]
+    Human,
+    /// A SARIF 2.1.0 log
+    Sarif,
+    /// A [`JsonReport`]
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(Format::Human),
+            "sarif" => Ok(Format::Sarif),
+            "json" => Ok(Format::Json),
+            other => Err(format!(
+                "unknown format '{}' (expected human, sarif or json)",
+                other
+            )),
+        }
+    }
+}
+
+/// Version of the [`JsonReport`] layout, raised on incompatible changes
+pub const JSON_VERSION: u32 = 1;
+
+/// The report as `--format json` prints it
+///
+/// Unlike the LSP types it is built from, this layout is stable: fields are
+/// only added, and lines and columns count from 1.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct JsonReport {
+    pub version: u32,
+    pub diagnostics: Vec<JsonDiagnostic>,
+    pub unreadable: Vec<JsonUnreadable>,
+}
+
+/// A diagnostic in a [`JsonReport`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct JsonDiagnostic {
+    pub file: String,
+    pub range: JsonRange,
+    /// `error`, `warning`, `info` or `hint`
+    pub severity: &'static str,
+    pub code: Option<String>,
+    pub message: String,
+}
+
+/// Where a [`JsonDiagnostic`] starts and ends
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct JsonRange {
+    pub start: JsonPosition,
+    pub end: JsonPosition,
+}
+
+/// A 1-based line and column
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct JsonPosition {
+    pub line: u32,
+    pub column: u32,
+}
+
+/// A file in a [`JsonReport`] that couldn't be read
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct JsonUnreadable {
+    pub file: String,
+    pub message: String,
+}
+
+/// Command-line options of `check`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Options {
+    pub format: Format,
+    /// The least severe diagnostic that makes the check fail
+    pub fail_on: RuleLevel,
+    /// Templates or directories to check; empty means the current directory
+    pub paths: Vec<PathBuf>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            format: Format::default(),
+            fail_on: RuleLevel::Error,
+            paths: Vec::new(),
+        }
+    }
+}
+
+impl Options {
+    /// Parse the arguments following `check`
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut options = Options::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if !arg.starts_with("--") {
+                options.paths.push(PathBuf::from(arg));
+                continue;
+            }
+            let (name, value) = match arg.split_once('=') {
+                Some((name, value)) => (name, value),
+                None => {
+                    let value = args.next().ok_or(format!("{} needs a value", arg));
+                    (arg.as_str(), value?.as_str())
+                }
+            };
+            match name {
+                "--format" => options.format = value.parse()?,
+                "--fail-on" => options.fail_on = parse_fail_on(value)?,
+                _ => return Err(format!("unknown option '{}'", name)),
+            }
+        }
+        Ok(options)
+    }
+}
+
+fn parse_fail_on(value: &str) -> Result<RuleLevel, String> {
+    match value {
+        "error" => Ok(RuleLevel::Error),
+        "warning" => Ok(RuleLevel::Warning),
+        "info" => Ok(RuleLevel::Information),
+        "hint" => Ok(RuleLevel::Hint),
+        other => Err(format!(
+            "unknown severity '{}' (expected error, warning, info or hint)",
+            other
+        )),
+    }
+}
+
+/// How many diagnostics of each severity a report has
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Counts {
+    pub errors: usize,
+    pub warnings: usize,
+    pub information: usize,
+    pub hints: usize,
+}
+
+impl fmt::Display for Counts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}, {}, {} info, {}",
+            plural(self.errors, "error"),
+            plural(self.warnings, "warning"),
+            self.information,
+            plural(self.hints, "hint")
+        )
+    }
+}
+
+fn plural(count: usize, noun: &str) -> String {
+    format!("{} {}{}", count, noun, if count == 1 { "" } else { "s" })
+}
+
+/// A template and what analyzing it reported
+#[derive(Debug, Clone)]
+pub struct CheckedFile {
+    /// Path relative to the workspace root when below it, else absolute
+    pub path: PathBuf,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// The diagnostics of every template checked
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub files: Vec<CheckedFile>,
+    /// Paths that couldn't be read, with why
+    pub unreadable: Vec<(PathBuf, String)>,
+}
+
+impl Report {
+    /// Whether any template couldn't be read or has a diagnostic at least as
+    /// severe as `fail_on`
+    pub fn fails(&self, fail_on: RuleLevel) -> bool {
+        !self.unreadable.is_empty() || self.diagnostics().any(|(_, d)| level(d) <= fail_on)
+    }
+
+    /// Diagnostics per severity, across every template
+    pub fn counts(&self) -> Counts {
+        let mut counts = Counts::default();
+        for (_, d) in self.diagnostics() {
+            match level(d) {
+                RuleLevel::Error => counts.errors += 1,
+                RuleLevel::Warning => counts.warnings += 1,
+                RuleLevel::Information => counts.information += 1,
+                RuleLevel::Hint | RuleLevel::Off => counts.hints += 1,
+            }
+        }
+        counts
+    }
+
+    /// One line summing up the report, e.g. "Checked 3 templates: 1 error,
+    /// 2 warnings, 0 info, 0 hints"
+    pub fn summary(&self) -> String {
+        let mut summary = format!(
+            "Checked {}: {}",
+            plural(self.files.len(), "template"),
+            self.counts()
+        );
+        if !self.unreadable.is_empty() {
+            summary.push_str(&format!("; {} unreadable", self.unreadable.len()));
+        }
+        summary
+    }
+
+    /// The report printed in `format`
+    pub fn render(&self, format: Format) -> String {
+        match format {
+            Format::Human => self.to_string(),
+            Format::Sarif => serde_json::to_string_pretty(&self.sarif()).unwrap() + "\n",
+            Format::Json => serde_json::to_string_pretty(&self.json()).unwrap() + "\n",
+        }
+    }
+
+    /// The report in its stable JSON layout
+    pub fn json(&self) -> JsonReport {
+        let position = |p: tower_lsp::lsp_types::Position| JsonPosition {
+            line: p.line + 1,
+            column: p.character + 1,
+        };
+        JsonReport {
+            version: JSON_VERSION,
+            diagnostics: self
+                .diagnostics()
+                .map(|(path, d)| JsonDiagnostic {
+                    file: path.display().to_string(),
+                    range: JsonRange {
+                        start: position(d.range.start),
+                        end: position(d.range.end),
+                    },
+                    severity: severity_label(d.severity),
+                    code: match &d.code {
+                        Some(NumberOrString::String(code)) => Some(code.clone()),
+                        Some(NumberOrString::Number(code)) => Some(code.to_string()),
+                        None => None,
+                    },
+                    message: d.message.clone(),
+                })
+                .collect(),
+            unreadable: self
+                .unreadable
+                .iter()
+                .map(|(path, error)| JsonUnreadable {
+                    file: path.display().to_string(),
+                    message: error.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    /// The report as a SARIF 2.1.0 log, with a rule per [`DiagnosticCode`]
+    ///
+    /// Paths are relative to the workspace root, which is left for the
+    /// consumer to resolve as `%SRCROOT%`. Unreadable files are tool
+    /// execution notifications rather than results.
+    pub fn sarif(&self) -> Value {
+        let rules: Vec<Value> = DiagnosticCode::ALL
+            .iter()
+            .map(|code| {
+                json!({
+                    "id": code.as_str(),
+                    "shortDescription": { "text": code.description() },
+                    "defaultConfiguration": {
+                        "level": sarif_level(Some(code.default_severity())),
+                    },
+                })
+            })
+            .collect();
+        let results: Vec<Value> = self
+            .diagnostics()
+            .map(|(path, d)| {
+                let mut result = json!({
+                    "level": sarif_level(d.severity),
+                    "message": { "text": d.message },
+                    "locations": [{
+                        "physicalLocation": {
+                            "artifactLocation": artifact_location(path),
+                            "region": {
+                                "startLine": d.range.start.line + 1,
+                                "startColumn": d.range.start.character + 1,
+                                "endLine": d.range.end.line + 1,
+                                "endColumn": d.range.end.character + 1,
+                            },
+                        },
+                    }],
+                });
+                if let Some(NumberOrString::String(code)) = &d.code {
+                    result["ruleId"] = json!(code);
+                    if let Some(index) = DiagnosticCode::ALL.iter().position(|c| c.as_str() == code)
+                    {
+                        result["ruleIndex"] = json!(index);
+                    }
+                }
+                result
+            })
+            .collect();
+        let notifications: Vec<Value> = self
+            .unreadable
+            .iter()
+            .map(|(path, error)| {
+                json!({
+                    "level": "error",
+                    "message": { "text": error },
+                    "locations": [{
+                        "physicalLocation": { "artifactLocation": artifact_location(path) },
+                    }],
+                })
+            })
+            .collect();
+
+        json!({
+            "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+            "version": "2.1.0",
+            "runs": [{
+                "tool": {
+                    "driver": {
+                        "name": env!("CARGO_PKG_NAME"),
+                        "version": env!("CARGO_PKG_VERSION"),
+                        "informationUri": INFORMATION_URI,
+                        "rules": rules,
+                    },
+                },
+                "invocations": [{
+                    "executionSuccessful": self.unreadable.is_empty(),
+                    "toolExecutionNotifications": notifications,
+                }],
+                "results": results,
+            }],
+        })
+    }
+
+    /// Every diagnostic, with the file it is in, each file's in the order
+    /// of their ranges
+    pub fn diagnostics(&self) -> impl Iterator<Item = (&Path, &Diagnostic)> {
+        self.files.iter().flat_map(|file| {
+            let mut diagnostics: Vec<&Diagnostic> = file.diagnostics.iter().collect();
+            diagnostics.sort_by_key(|d| (d.range.start, d.range.end));
+            diagnostics.into_iter().map(|d| (file.path.as_path(), d))
+        })
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (path, error) in &self.unreadable {
+            writeln!(f, "{}: {}", path.display(), error)?;
+        }
+        for (path, d) in self.diagnostics() {
+            write!(
+                f,
+                "{}:{}:{}: {}",
+                path.display(),
+                d.range.start.line + 1,
+                d.range.start.character + 1,
+                severity_label(d.severity)
+            )?;
+            if let Some(NumberOrString::String(code)) = &d.code {
+                write!(f, "[{}]", code)?;
+            }
+            writeln!(f, ": {}", d.message)?;
+        }
+        writeln!(f, "{}", self.summary())
+    }
+}
+
+/// The level of a diagnostic's severity; LSP treats a missing one as a
+/// warning, and so does `check`
+fn level(diagnostic: &Diagnostic) -> RuleLevel {
+    match diagnostic.severity {
+        Some(DiagnosticSeverity::ERROR) => RuleLevel::Error,
+        Some(DiagnosticSeverity::WARNING) | None => RuleLevel::Warning,
+        Some(DiagnosticSeverity::INFORMATION) => RuleLevel::Information,
+        _ => RuleLevel::Hint,
+    }
+}
+
+/// Lower-case name of a severity, as compilers print it
+pub fn severity_label(severity: Option<DiagnosticSeverity>) -> &'static str {
+    match severity {
+        Some(DiagnosticSeverity::ERROR) => "error",
+        Some(DiagnosticSeverity::WARNING) | None => "warning",
+        Some(DiagnosticSeverity::INFORMATION) => "info",
+        _ => "hint",
+    }
+}
+
+/// SARIF's name for a severity; it has no separate hint level
+fn sarif_level(severity: Option<DiagnosticSeverity>) -> &'static str {
+    match severity {
+        Some(DiagnosticSeverity::ERROR) => "error",
+        Some(DiagnosticSeverity::WARNING) | None => "warning",
+        _ => "note",
+    }
+}
+
+/// A SARIF artifact location: a relative path with forward slashes under
+/// `%SRCROOT%`, or an absolute `file:` URI
+fn artifact_location(path: &Path) -> Value {
+    if path.is_absolute() {
+        let uri = tower_lsp::lsp_types::Url::from_file_path(path)
+            .map_or_else(|_| path.display().to_string(), String::from);
+        return json!({ "uri": uri });
+    }
+    let uri: Vec<String> = path
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    json!({ "uri": uri.join("/"), "uriBaseId": "%SRCROOT%" })
+}
+
+/// Check the templates at `paths`, files or directories to search, with the
+/// configuration and Terraform modules of the workspace at `root`; relative
+/// paths are relative to `root`
+pub fn run(root: &Path, paths: &[PathBuf]) -> Report {
+    run_with_rules(root, paths, &RuleRegistry::default())
+}
+
+/// Like [`run`], analyzing templates with an embedder's rules as well
+pub fn run_with_rules(root: &Path, paths: &[PathBuf], rules: &RuleRegistry) -> Report {
+    let config = WorkspaceConfig::load(root).unwrap_or_default();
+    let settings = config.settings(root);
+    let mut report = Report::default();
+
+    for path in templates(root, paths) {
+        let shown = path.strip_prefix(root).unwrap_or(&path).to_path_buf();
+        match fs::read_to_string(&path) {
+            Ok(text) => {
+                let options = AnalysisOptions {
+                    settings: settings.for_template(&path, root),
+                    max_severity: config.max_severity(root, &path),
+                    rules: rules.clone(),
+                };
+                let diagnostics = analyze(&text, &options).diagnostics;
+                report.files.push(CheckedFile {
+                    path: shown,
+                    diagnostics,
+                });
+            }
+            Err(e) => report.unreadable.push((shown, e.to_string())),
+        }
+    }
+    report
+}
+
+/// The files named by `paths`, resolved against `root`, with directories
+/// replaced by the templates below them; sorted, each listed once
+fn templates(root: &Path, paths: &[PathBuf]) -> Vec<PathBuf> {
+    let mut templates = Vec::new();
+    for path in paths {
+        let path = normalize(&root.join(path));
+        if path.is_dir() {
+            templates.extend(workspace::find_templates(&path));
+        } else {
+            templates.push(path);
+        }
+    }
+    templates.sort();
+    templates.dedup();
+    templates
+}
+
+/// `path` without `.` components and with each `..` applied to the
+/// component before it, without touching the file system
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir
+                if matches!(
+                    normalized.components().next_back(),
+                    Some(Component::Normal(_))
+                ) =>
+            {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower_lsp::lsp_types::{Position, Range};
+
+    /// A valid workflow with one hint, for its unknown `extra` key on line 5
+    const ONE_HINT: &str = "main:\n  steps:\n    - done:\n        return: 1\nextra: 1\n";
+
+    /// A workspace holding `files`, keyed by their path below the root
+    fn workspace(files: &[(&str, &str)]) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for (path, text) in files {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, text).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn test_check_reports_file_line_column() {
+        let bad = format!("{}broken: [\n", ONE_HINT);
+        let dir = workspace(&[
+            (
+                "templates/ok.yaml.tftpl",
+                "main:\n  steps:\n    - done:\n        return: 1\n",
+            ),
+            ("templates/bad.yaml.tftpl", &bad),
+            ("templates/notes.txt", "not a template\n"),
+        ]);
+        let root = dir.path();
+
+        let report = run(
+            root,
+            &[root.join("templates"), root.join("missing.yaml.tftpl")],
+        );
+        assert_eq!(report.files.len(), 2);
+        assert_eq!(report.unreadable[0].0, Path::new("missing.yaml.tftpl"));
+        assert!(report.fails(RuleLevel::Error));
+
+        let output = report.to_string();
+        assert!(output.contains("templates/bad.yaml.tftpl:7:1: error[yaml-syntax]: "));
+        assert!(!output.contains("ok.yaml.tftpl"));
+
+        let report = run(root, &[root.join("templates/ok.yaml.tftpl")]);
+        assert!(!report.fails(RuleLevel::Hint));
+        assert_eq!(
+            report.to_string(),
+            "Checked 1 template: 0 errors, 0 warnings, 0 info, 0 hints\n"
+        );
+    }
+
+    #[test]
+    fn test_relative_paths_resolve_against_root() {
+        let dir = workspace(&[
+            ("templates/b.yaml.tftpl", "main:\n  steps: []\n"),
+            ("templates/a.yaml.tftpl", "main:\n  steps: []\n"),
+        ]);
+        let root = dir.path();
+
+        let report = run(
+            root,
+            &[
+                PathBuf::from("templates/b.yaml.tftpl"),
+                PathBuf::from("./templates"),
+                root.join("templates/../templates/a.yaml.tftpl"),
+            ],
+        );
+        let paths: Vec<_> = report.files.iter().map(|f| f.path.clone()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                PathBuf::from("templates/a.yaml.tftpl"),
+                PathBuf::from("templates/b.yaml.tftpl"),
+            ]
+        );
+        assert!(report.unreadable.is_empty());
+    }
+
+    #[test]
+    fn test_fail_on_and_counts() {
+        let dir = workspace(&[("w.yaml.tftpl", ONE_HINT)]);
+        let root = dir.path();
+
+        let report = run(root, &[root.to_path_buf()]);
+        assert_eq!(
+            report.counts(),
+            Counts {
+                hints: 1,
+                ..Counts::default()
+            }
+        );
+        assert!(!report.fails(RuleLevel::Error));
+        assert!(!report.fails(RuleLevel::Warning));
+        assert!(report.fails(RuleLevel::Hint));
+        assert_eq!(
+            report.summary(),
+            "Checked 1 template: 0 errors, 0 warnings, 0 info, 1 hint"
+        );
+    }
+
+    #[test]
+    fn test_sarif() {
+        let dir = workspace(&[("templates/w.yaml.tftpl", ONE_HINT)]);
+        let root = dir.path();
+
+        let report = run(
+            root,
+            &[root.join("templates"), root.join("gone.yaml.tftpl")],
+        );
+        let sarif = report.sarif();
+        assert_eq!(sarif["version"], "2.1.0");
+        let run = &sarif["runs"][0];
+        let rules = run["tool"]["driver"]["rules"].as_array().unwrap();
+        assert_eq!(rules.len(), DiagnosticCode::ALL.len());
+        assert_eq!(rules[0]["id"], "yaml-syntax");
+        assert_eq!(rules[0]["defaultConfiguration"]["level"], "error");
+
+        let result = &run["results"][0];
+        assert_eq!(result["ruleId"], "unknown-keyword");
+        assert_eq!(
+            rules[result["ruleIndex"].as_u64().unwrap() as usize]["id"],
+            "unknown-keyword"
+        );
+        assert_eq!(result["level"], "note");
+        let location = &result["locations"][0]["physicalLocation"];
+        assert_eq!(
+            location["artifactLocation"]["uri"],
+            "templates/w.yaml.tftpl"
+        );
+        assert_eq!(location["artifactLocation"]["uriBaseId"], "%SRCROOT%");
+        assert_eq!(location["region"]["startLine"], 5);
+        assert_eq!(location["region"]["startColumn"], 1);
+
+        let invocation = &run["invocations"][0];
+        assert_eq!(invocation["executionSuccessful"], false);
+        assert_eq!(
+            invocation["toolExecutionNotifications"][0]["locations"][0]["physicalLocation"]
+                ["artifactLocation"]["uri"],
+            "gone.yaml.tftpl"
+        );
+    }
+
+    #[test]
+    fn test_json() {
+        let dir = workspace(&[("w.yaml.tftpl", ONE_HINT)]);
+        let root = dir.path();
+
+        let report = run(root, &[root.to_path_buf()]);
+        let json = serde_json::to_value(report.json()).unwrap();
+        assert_eq!(
+            json,
+            json!({
+                "version": 1,
+                "diagnostics": [{
+                    "file": "w.yaml.tftpl",
+                    "range": {
+                        "start": { "line": 5, "column": 1 },
+                        "end": { "line": 5, "column": 6 },
+                    },
+                    "severity": "hint",
+                    "code": "unknown-keyword",
+                    "message": report.files[0].diagnostics[0].message,
+                }],
+                "unreadable": [],
+            })
+        );
+    }
+
+    #[test]
+    fn test_diagnostics_in_range_order() {
+        let diagnostic = |line, character| Diagnostic {
+            range: Range::new(
+                Position::new(line, character),
+                Position::new(line, character + 1),
+            ),
+            severity: Some(DiagnosticSeverity::WARNING),
+            message: format!("at {}:{}", line, character),
+            ..Diagnostic::default()
+        };
+        let report = Report {
+            files: vec![CheckedFile {
+                path: PathBuf::from("w.yaml.tftpl"),
+                diagnostics: vec![diagnostic(4, 0), diagnostic(1, 7), diagnostic(1, 2)],
+            }],
+            unreadable: Vec::new(),
+        };
+        let expected = ["at 1:2", "at 1:7", "at 4:0"];
+
+        let human: Vec<String> = report.to_string().lines().map(String::from).collect();
+        for (line, message) in human.iter().zip(expected) {
+            assert!(line.ends_with(message), "{}", line);
+        }
+        let json: Vec<String> = report
+            .json()
+            .diagnostics
+            .into_iter()
+            .map(|d| d.message)
+            .collect();
+        assert_eq!(json, expected);
+        let sarif = report.sarif();
+        let results = sarif["runs"][0]["results"].as_array().unwrap();
+        let sarif: Vec<&str> = results
+            .iter()
+            .map(|r| r["message"]["text"].as_str().unwrap())
+            .collect();
+        assert_eq!(sarif, expected);
+    }
+
+    #[test]
+    fn test_options() {
+        let args = |args: &[&str]| args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+        let options = Options::parse(&args(&["a.yaml.tftpl", "--format", "sarif", "dir"])).unwrap();
+        assert_eq!(options.format, Format::Sarif);
+        assert_eq!(
+            options.paths,
+            vec![PathBuf::from("a.yaml.tftpl"), PathBuf::from("dir")]
+        );
+        assert_eq!(
+            Options::parse(&args(&["--format=human"])).unwrap(),
+            Options::default()
+        );
+        assert!(Options::parse(&args(&["--format", "xml"])).is_err());
+        assert!(Options::parse(&args(&["--format"])).is_err());
+        assert!(Options::parse(&args(&["--verbose"])).is_err());
+
+        let options = Options::parse(&args(&["--fail-on", "warning", "--format=json"])).unwrap();
+        assert_eq!(options.fail_on, RuleLevel::Warning);
+        assert_eq!(options.format, Format::Json);
+        assert_eq!(
+            Options::parse(&args(&["--fail-on=hint"])).unwrap().fail_on,
+            RuleLevel::Hint
+        );
+        assert!(Options::parse(&args(&["--fail-on", "off"])).is_err());
+    }
+}
@@ -0,0 +1,247 @@
+//! Workspace configuration file
+//!
+//! Team-shared settings live in a `.yaml-tftpl-lsp.json` file at the
+//! workspace root, so they can be committed alongside the templates.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use ignore::gitignore::GitignoreBuilder;
+use serde::{Deserialize, Serialize};
+
+use crate::schema::SchemaOverlay;
+use crate::settings::{RuleLevel, Settings};
+
+/// Name of the workspace configuration file
+pub const CONFIG_FILE: &str = ".yaml-tftpl-lsp.json";
+
+/// Contents of the workspace configuration file
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct WorkspaceConfig {
+    /// Reusable step fragments offered through completion
+    pub snippets: Vec<Snippet>,
+    /// Severity caps for templates matching a path pattern
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub severity_overrides: Vec<SeverityOverride>,
+    /// Named sets of variable values to preview and validate templates with
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub render_profiles: Vec<RenderProfile>,
+    /// JSON Schema file, relative to the workspace root, extending workflow
+    /// validation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schema: Option<PathBuf>,
+}
+
+/// A set of variable values a template can be rendered with
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RenderProfile {
+    pub name: String,
+    /// tfvars files relative to the workspace root, later files winning
+    #[serde(default)]
+    pub tfvars: Vec<PathBuf>,
+    /// Values applied over the tfvars files
+    #[serde(default)]
+    pub variables: BTreeMap<String, serde_json::Value>,
+}
+
+/// Caps the severity of every diagnostic in templates under some paths, such
+/// as examples or vendored code
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SeverityOverride {
+    /// Pattern relative to the workspace root, with `.gitignore` syntax
+    /// (e.g. `examples/**`)
+    pub path: String,
+    /// The most severe level reported; `off` hides the diagnostics entirely
+    pub max_severity: RuleLevel,
+}
+
+/// A reusable YAML fragment, such as a step or a whole subworkflow
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Snippet {
+    /// Name shown in completion
+    pub name: String,
+    /// Optional longer description
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// The YAML text, dedented so its first line starts at column 0
+    pub body: String,
+}
+
+impl WorkspaceConfig {
+    /// Path of the configuration file for a workspace root
+    pub fn path(root: &Path) -> PathBuf {
+        root.join(CONFIG_FILE)
+    }
+
+    /// Load the configuration for a workspace root
+    ///
+    /// A missing file yields the default configuration.
+    pub fn load(root: &Path) -> io::Result<Self> {
+        match fs::read_to_string(Self::path(root)) {
+            Ok(text) => serde_json::from_str(&text)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Write the configuration back to the workspace root
+    pub fn save(&self, root: &Path) -> io::Result<()> {
+        let text = serde_json::to_string_pretty(self)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(Self::path(root), text + "\n")
+    }
+
+    /// The severity cap for a file, the strictest of the matching overrides
+    pub fn max_severity(&self, root: &Path, path: &Path) -> Option<RuleLevel> {
+        if !path.starts_with(root) {
+            return None;
+        }
+        self.severity_overrides
+            .iter()
+            .filter(|o| {
+                let mut builder = GitignoreBuilder::new(root);
+                builder.add_line(None, &o.path).is_ok()
+                    && builder.build().is_ok_and(|matcher| {
+                        matcher.matched_path_or_any_parents(path, false).is_ignore()
+                    })
+            })
+            .map(|o| o.max_severity)
+            .max()
+    }
+
+    /// Path of the schema overlay for a workspace root: `setting` when
+    /// given, otherwise this configuration's `schema`
+    pub fn schema_path(&self, root: &Path, setting: Option<&Path>) -> Option<PathBuf> {
+        setting
+            .or(self.schema.as_deref())
+            .map(|path| root.join(path))
+    }
+
+    /// Settings for analyzing the workspace's templates outside the server:
+    /// the defaults, with this configuration's schema overlay if it loads
+    pub fn settings(&self, root: &Path) -> Settings {
+        Settings {
+            schema_overlay: self
+                .schema_path(root, None)
+                .and_then(|path| SchemaOverlay::load(&path).ok())
+                .map(Arc::new),
+            ..Settings::default()
+        }
+    }
+
+    /// Find a render profile by name
+    pub fn render_profile(&self, name: &str) -> Option<&RenderProfile> {
+        self.render_profiles.iter().find(|p| p.name == name)
+    }
+
+    /// Add a snippet, replacing any existing snippet with the same name
+    pub fn add_snippet(&mut self, snippet: Snippet) {
+        self.snippets.retain(|s| s.name != snippet.name);
+        self.snippets.push(snippet);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snippet(name: &str, body: &str) -> Snippet {
+        Snippet {
+            name: name.to_string(),
+            description: None,
+            body: body.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_missing_file_is_default() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(
+            WorkspaceConfig::load(dir.path()).unwrap(),
+            WorkspaceConfig::default()
+        );
+    }
+
+    #[test]
+    fn test_save_and_load_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = WorkspaceConfig::default();
+        config.add_snippet(snippet("log", "- log:\n    call: sys.log"));
+        config.save(dir.path()).unwrap();
+
+        assert_eq!(WorkspaceConfig::load(dir.path()).unwrap(), config);
+    }
+
+    #[test]
+    fn test_add_snippet_replaces_same_name() {
+        let mut config = WorkspaceConfig::default();
+        config.add_snippet(snippet("a", "one"));
+        config.add_snippet(snippet("b", "two"));
+        config.add_snippet(snippet("a", "three"));
+
+        assert_eq!(config.snippets.len(), 2);
+        assert_eq!(config.snippets[1].body, "three");
+    }
+
+    #[test]
+    fn test_max_severity() {
+        let config: WorkspaceConfig = serde_json::from_str(
+            r#"{
+                "severityOverrides": [
+                    { "path": "examples/**", "maxSeverity": "hint" },
+                    { "path": "vendor/", "maxSeverity": "information" },
+                    { "path": "examples/legacy/", "maxSeverity": "off" }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let root = Path::new("/ws");
+        let severity = |p: &str| config.max_severity(root, &root.join(p));
+
+        assert_eq!(severity("examples/a.yaml.tftpl"), Some(RuleLevel::Hint));
+        assert_eq!(
+            severity("vendor/x/b.yaml.tftpl"),
+            Some(RuleLevel::Information)
+        );
+        assert_eq!(
+            severity("examples/legacy/c.yaml.tftpl"),
+            Some(RuleLevel::Off)
+        );
+        assert_eq!(severity("workflows/d.yaml.tftpl"), None);
+        assert_eq!(
+            config.max_severity(root, Path::new("/other/e.yaml.tftpl")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_schema_path() {
+        let config: WorkspaceConfig =
+            serde_json::from_str(r#"{ "schema": "schemas/org.json" }"#).unwrap();
+        let root = Path::new("/ws");
+        assert_eq!(
+            config.schema_path(root, None),
+            Some(PathBuf::from("/ws/schemas/org.json"))
+        );
+        assert_eq!(
+            config.schema_path(root, Some(Path::new("/etc/wf.json"))),
+            Some(PathBuf::from("/etc/wf.json"))
+        );
+        assert_eq!(WorkspaceConfig::default().schema_path(root, None), None);
+    }
+
+    #[test]
+    fn test_invalid_file_is_error() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(WorkspaceConfig::path(dir.path()), "{ not json").unwrap();
+        assert!(WorkspaceConfig::load(dir.path()).is_err());
+    }
+}
@@ -0,0 +1,128 @@
+//! Style check for URLs and resource names built by concatenation
+//!
+//! `$${"https://" + host + "/v1/projects/" + project + "/jobs/" + job}` is
+//! hard to read and easy to get a slash wrong in. Chains of more `+` parts
+//! than the configured limit, with a literal part containing a `/`, are
+//! reported with a suggestion to use a format-style template instead.
+
+use crate::parser::tokens::{tokenize, TokenKind};
+use crate::parser::{ExpressionKind, ExpressionMap};
+
+use super::yaml_errors::{DiagnosticCode, DiagnosticCollector};
+
+/// Report `$${}` expressions that concatenate more than `max_parts` parts
+/// into a URL or resource name
+pub fn validate_concatenation(
+    expressions: &ExpressionMap,
+    max_parts: usize,
+    collector: &mut DiagnosticCollector,
+) {
+    for expr in &expressions.expressions {
+        if expr.kind != ExpressionKind::Workflows {
+            continue;
+        }
+        let Some(body) = expr
+            .original
+            .strip_prefix("$${")
+            .and_then(|b| b.strip_suffix('}'))
+        else {
+            continue;
+        };
+        let Some(parts) = longest_path_chain(body) else {
+            continue;
+        };
+        if parts <= max_parts {
+            continue;
+        }
+        collector.add_workflow_warning_with_range(
+            format!(
+                "URL or resource name built from {} concatenated parts; a format-style template, such as Terraform's format() for values known at deploy time, is easier to read",
+                parts
+            ),
+            expr.start_line,
+            expr.start_column,
+            expr.end_line,
+            expr.end_column,
+            DiagnosticCode::StringConcatenation,
+        );
+    }
+}
+
+/// The number of parts in the longest `+` chain that has a string literal
+/// containing a `/`, if any
+fn longest_path_chain(body: &str) -> Option<usize> {
+    let tokens = tokenize(body)?;
+
+    // One chain per bracket depth: its part count and whether it has a path
+    // literal
+    let mut chains = vec![(1usize, false)];
+    let mut longest = None;
+    let mut finish = |(parts, path): (usize, bool)| {
+        if path && parts > 1 {
+            longest = Some(longest.map_or(parts, |l: usize| l.max(parts)));
+        }
+    };
+
+    for token in tokens {
+        match token.kind {
+            TokenKind::Open => chains.push((1, false)),
+            TokenKind::Close if chains.len() > 1 => finish(chains.pop().unwrap()),
+            TokenKind::Operator if token.text == "+" => {
+                if let Some(chain) = chains.last_mut() {
+                    chain.0 += 1;
+                }
+            }
+            TokenKind::String if token.text.contains('/') => {
+                if let Some(chain) = chains.last_mut() {
+                    chain.1 = true;
+                }
+            }
+            // Anything else joining operands ends the chain at this depth
+            TokenKind::Operator | TokenKind::Comma | TokenKind::Colon | TokenKind::Question => {
+                if let Some(chain) = chains.last_mut() {
+                    finish(std::mem::replace(chain, (1, false)));
+                }
+            }
+            _ => {}
+        }
+    }
+    chains.into_iter().for_each(&mut finish);
+    longest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::preprocess_expressions;
+
+    #[test]
+    fn test_longest_path_chain() {
+        assert_eq!(
+            longest_path_chain(r#""https://" + host + "/v1/" + name"#),
+            Some(4)
+        );
+        assert_eq!(longest_path_chain(r#"a + b + c + d + e"#), None);
+        assert_eq!(
+            longest_path_chain(r#"f("projects/" + p + "/x", a + b + c + d)"#),
+            Some(3)
+        );
+        assert_eq!(
+            longest_path_chain(r#"x == "a/" + b ? "c/" + d + e : f"#),
+            Some(3)
+        );
+    }
+
+    #[test]
+    fn test_reports_long_chains() {
+        let text = "a: $${\"https://\" + host + \"/v1/projects/\" + project + \"/jobs/\" + job}\nb: $${\"p/\" + x}\nc: ${\"h/\" + a + \"/\" + b + \"/\" + c}\n";
+        let (_, expressions) = preprocess_expressions(text);
+        let mut collector = DiagnosticCollector::new();
+        validate_concatenation(&expressions, 4, &mut collector);
+        let diagnostics = collector.into_diagnostics();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].range.start.line, 0);
+        assert!(diagnostics[0]
+            .message
+            .starts_with("URL or resource name built from 6"));
+    }
+}
@@ -0,0 +1,125 @@
+//! Checks on YAML directives
+//!
+//! Directives are blanked before parsing (see `parser::directives`), so
+//! their syntax problems are reported here, along with the directives
+//! Workflows doesn't support: `%TAG`, unknown names and `%YAML` versions
+//! other than 1.1 and 1.2.
+
+use crate::parser::Directives;
+
+use super::yaml_errors::{DiagnosticCode, DiagnosticCollector};
+
+/// `%YAML` versions Workflows reads
+const YAML_VERSIONS: &[&str] = &["1.1", "1.2"];
+
+/// Report malformed directives and directives Workflows doesn't support
+pub fn validate_directives(directives: &Directives, collector: &mut DiagnosticCollector) {
+    let Some(first) = directives.directives.first() else {
+        return;
+    };
+    if directives.document_start.is_none() {
+        collector.add_yaml_error_with_range(
+            "Directives must be followed by a '---' document start marker".to_string(),
+            first.line,
+            0,
+            first.line,
+            first.end_column,
+            DiagnosticCode::YamlSyntax,
+        );
+    }
+
+    let mut seen_yaml = false;
+    for directive in &directives.directives {
+        let message = match directive.name.as_str() {
+            "YAML" if seen_yaml => {
+                collector.add_yaml_error_with_range(
+                    "Duplicate '%YAML' directive".to_string(),
+                    directive.line,
+                    0,
+                    directive.line,
+                    directive.end_column,
+                    DiagnosticCode::YamlSyntax,
+                );
+                continue;
+            }
+            "YAML" => {
+                seen_yaml = true;
+                let version = directive.parameters.first().map_or("", String::as_str);
+                if YAML_VERSIONS.contains(&version) {
+                    continue;
+                }
+                format!(
+                    "YAML version '{}' is not supported by Workflows; use '%YAML 1.2' or remove the directive",
+                    version
+                )
+            }
+            "TAG" => "Workflows does not support '%TAG' directives".to_string(),
+            name => format!(
+                "Unknown directive '%{}' is not supported by Workflows",
+                name
+            ),
+        };
+        collector.add_workflow_warning_with_range(
+            message,
+            directive.line,
+            0,
+            directive.line,
+            directive.end_column,
+            DiagnosticCode::UnsupportedDirective,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::scan_directives;
+    use tower_lsp::lsp_types::{Diagnostic, NumberOrString};
+
+    fn check(text: &str) -> Vec<Diagnostic> {
+        let mut collector = DiagnosticCollector::new();
+        validate_directives(&scan_directives(text), &mut collector);
+        collector.into_diagnostics()
+    }
+
+    fn codes(diagnostics: &[Diagnostic]) -> Vec<(u32, &str)> {
+        diagnostics
+            .iter()
+            .map(|d| match &d.code {
+                Some(NumberOrString::String(c)) => (d.range.start.line, c.as_str()),
+                _ => (d.range.start.line, ""),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_supported_directives() {
+        assert!(check("%YAML 1.2\n---\nmain: 1\n").is_empty());
+        assert!(check("---\nmain: 1\n").is_empty());
+        assert!(check("main: 1\n").is_empty());
+    }
+
+    #[test]
+    fn test_unsupported_directives() {
+        let diagnostics = check("%YAML 1.3\n%TAG !e! tag:e.com:\n%FOO bar\n%YAML 1.2\n---\n");
+        assert_eq!(
+            codes(&diagnostics),
+            vec![
+                (0, "unsupported-directive"),
+                (1, "unsupported-directive"),
+                (2, "unsupported-directive"),
+                (3, "yaml-syntax"),
+            ]
+        );
+        assert!(diagnostics[0].message.contains("'1.3'"));
+        assert!(diagnostics[2].message.contains("'%FOO'"));
+        assert_eq!(diagnostics[1].range.end.character, 19);
+    }
+
+    #[test]
+    fn test_missing_document_start() {
+        let diagnostics = check("%YAML 1.2\nmain: 1\n");
+        assert_eq!(codes(&diagnostics), vec![(0, "yaml-syntax")]);
+        assert!(diagnostics[0].message.contains("'---'"));
+    }
+}
@@ -0,0 +1,95 @@
+//! Duplicate step names
+//!
+//! Two steps with the same name in one `steps` list shadow each other in GCP
+//! Workflows: jumps and error messages can only reach one of them. Steps are
+//! found by scanning lines, so the original text (not the preprocessed one)
+//! gives exact positions.
+
+use std::collections::HashMap;
+
+use tower_lsp::lsp_types::{Position, Range};
+
+use super::yaml_errors::{DiagnosticCode, DiagnosticCollector};
+use crate::parser::lines::parent_line;
+use crate::parser::outline::StepDef;
+use crate::parser::Outline;
+
+/// Warn on every step whose name is already used earlier in the same list
+pub fn validate_duplicate_steps(text: &str, collector: &mut DiagnosticCollector) {
+    let lines: Vec<&str> = text
+        .split('\n')
+        .map(|l| l.strip_suffix('\r').unwrap_or(l))
+        .collect();
+
+    // First step of each name, keyed by the `steps:` line owning the list
+    let mut first: HashMap<(usize, &str), &StepDef> = HashMap::new();
+    let outline = Outline::parse(text);
+    for step in &outline.steps {
+        let Some(parent) = parent_line(&lines, step.line as usize) else {
+            continue;
+        };
+        match first.get(&(parent, step.name.as_str())) {
+            Some(original) => collector.add_warning_with_related(
+                format!("Duplicate step name '{}' in this steps list", step.name),
+                name_range(step),
+                DiagnosticCode::DuplicateStep,
+                vec![(
+                    name_range(original),
+                    format!("'{}' first defined here", step.name),
+                )],
+            ),
+            None => {
+                first.insert((parent, step.name.as_str()), step);
+            }
+        }
+    }
+}
+
+fn name_range(step: &StepDef) -> Range {
+    Range::new(
+        Position::new(step.line, step.column),
+        Position::new(step.line, step.column + step.name.chars().count() as u32),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_duplicate_steps() {
+        let text = r#"main:
+  steps:
+    - init:
+        assign:
+          - x: 1
+    - loop:
+        for:
+          value: v
+          in: [1, 2]
+          steps:
+            - init:
+                assign:
+                  - y: 2
+    - init:
+        return: x
+helper:
+  steps:
+    - init:
+        return: 1
+"#;
+        let mut collector = DiagnosticCollector::new();
+        validate_duplicate_steps(text, &mut collector);
+        let diagnostics = collector.into_diagnostics();
+
+        assert_eq!(diagnostics.len(), 1);
+        let d = &diagnostics[0];
+        assert_eq!(d.message, "Duplicate step name 'init' in this steps list");
+        assert_eq!(
+            d.range,
+            Range::new(Position::new(13, 6), Position::new(13, 10))
+        );
+        let related = &d.related_information.as_ref().unwrap()[0];
+        assert_eq!(related.location.range.start, Position::new(2, 6));
+    }
+}
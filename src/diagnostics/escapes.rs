@@ -0,0 +1,126 @@
+//! Template escapes that don't escape anything
+//!
+//! Terraform renders `$${` as a literal `${` and `%%{` as `%{`, which is how
+//! Workflows expressions survive `templatefile()`. A doubled `$` or `%`
+//! anywhere else is not an escape and renders exactly as written, so
+//! `cost: $$5` stays `$$5`. Runs inside Terraform interpolations and
+//! directives, and in comments, are left alone.
+
+use crate::document::LineOffsets;
+use crate::parser::{ExpressionKind, ExpressionMap};
+
+use super::expression_syntax::in_comment;
+use super::yaml_errors::{DiagnosticCode, DiagnosticCollector};
+
+/// Warn about `$$` and `%%` that aren't followed by `{`; `text` is the
+/// document as written
+pub fn validate_escapes(
+    text: &str,
+    expressions: &ExpressionMap,
+    collector: &mut DiagnosticCollector,
+) {
+    let bytes = text.as_bytes();
+    let lines = LineOffsets::new(text);
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i];
+        if c != b'$' && c != b'%' {
+            i += 1;
+            continue;
+        }
+        let run_end = bytes[i..]
+            .iter()
+            .position(|&b| b != c)
+            .map_or(bytes.len(), |n| i + n);
+        if run_end - i >= 2
+            && bytes.get(run_end) != Some(&b'{')
+            && !in_terraform(expressions, i)
+            && !in_comment(text, i)
+        {
+            let run = &text[i..run_end];
+            let start = lines.offset_to_position(text, i);
+            let end = lines.offset_to_position(text, run_end);
+            collector.add_workflow_warning_with_range(
+                format!(
+                    "'{}' renders as written: Terraform only reads '{}{}' as an escape before '{{'",
+                    run, c as char, c as char
+                ),
+                start.line,
+                start.character,
+                end.line,
+                end.character,
+                DiagnosticCode::IneffectiveEscape,
+            );
+        }
+        i = run_end;
+    }
+}
+
+/// Whether `offset` is inside a `${}` interpolation or `%{}` directive
+fn in_terraform(expressions: &ExpressionMap, offset: usize) -> bool {
+    expressions
+        .expressions
+        .iter()
+        .any(|e| e.kind != ExpressionKind::Workflows && offset >= e.start && offset < e.end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::preprocess_expressions;
+
+    fn diagnostics(text: &str) -> Vec<(u32, u32, u32, String)> {
+        let (_, expressions) = preprocess_expressions(text);
+        let mut collector = DiagnosticCollector::new();
+        validate_escapes(text, &expressions, &mut collector);
+        collector
+            .into_diagnostics()
+            .into_iter()
+            .map(|d| {
+                (
+                    d.range.start.line,
+                    d.range.start.character,
+                    d.range.end.character,
+                    d.message,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_escapes_before_brace() {
+        let text = "a: $${x}\nb: \"100%%{ not a directive }\"\nc: $$${x}\nd: $5\n";
+        assert!(diagnostics(text).is_empty());
+    }
+
+    #[test]
+    fn test_lone_doubled_characters() {
+        let text = "a: cost $$5\nb: 100%%\nc: ${\"$$\"} # or $$\nd: $$${x} $$$\n";
+        assert_eq!(
+            diagnostics(text),
+            vec![
+                (
+                    0,
+                    8,
+                    10,
+                    "'$$' renders as written: Terraform only reads '$$' as an escape before '{'"
+                        .to_string()
+                ),
+                (
+                    1,
+                    6,
+                    8,
+                    "'%%' renders as written: Terraform only reads '%%' as an escape before '{'"
+                        .to_string()
+                ),
+                (
+                    3,
+                    10,
+                    13,
+                    "'$$$' renders as written: Terraform only reads '$$' as an escape before '{'"
+                        .to_string()
+                ),
+            ]
+        );
+    }
+}
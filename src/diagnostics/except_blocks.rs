@@ -0,0 +1,457 @@
+//! `except` blocks and the error variables they bind
+//!
+//! An `except` needs an `as` name to bind the caught error to and a `steps`
+//! list to handle it. The error variable only exists inside that block, so a
+//! `$${e.message}` outside any `except` with `as: e` fails at run time unless
+//! `e` is an ordinary variable of the workflow. Inside the block, the error
+//! only has the fields of [`ERROR_FIELDS`].
+
+use std::collections::{HashMap, HashSet};
+
+use serde_yaml::Value;
+use tower_lsp::lsp_types::{Position, Range};
+
+use super::scopes::{walk_assignments, BindingKind};
+use super::workflow_validator::describe_value;
+use super::yaml_errors::{DiagnosticCode, DiagnosticCollector};
+use crate::parser::tokens::{tokenize, TokenKind};
+use crate::parser::{without_unparsed_directives, ExpressionKind, ExpressionMap, SpannedValue};
+use crate::schema::{error_field, ERROR_FIELDS};
+
+/// The error variable name most handlers use, checked even in workflows
+/// that never declare it
+const CONVENTIONAL_ERROR_VARIABLE: &str = "e";
+
+/// Check the shape of every `except` block and warn about error fields read
+/// where no `except` binds the error variable
+///
+/// `text` is the document `value` was parsed from.
+pub fn validate_except_blocks(
+    value: &Value,
+    text: &str,
+    expressions: &ExpressionMap,
+    collector: &mut DiagnosticCollector,
+) {
+    let Some(mapping) = value.as_mapping() else {
+        return;
+    };
+
+    // Variables each workflow binds other than through `as`
+    let mut bound: HashMap<&str, HashSet<String>> = HashMap::new();
+    walk_assignments(value, text, &mut |binding| {
+        if binding.kind != BindingKind::ErrorVariable {
+            bound
+                .entry(binding.workflow)
+                .or_default()
+                .insert(binding.name);
+        }
+    });
+
+    let spanned = SpannedValue::parse(&without_unparsed_directives(text)).unwrap_or_default();
+    let mut checker = Checker {
+        expressions,
+        candidates: HashSet::new(),
+        active: Vec::new(),
+        collector,
+    };
+    for (key, body) in mapping {
+        let (Some(name), Some(steps)) = (key.as_str(), body.get("steps")) else {
+            continue;
+        };
+        let mut error_variables = HashSet::from([CONVENTIONAL_ERROR_VARIABLE.to_string()]);
+        collect_error_variables(steps, &mut error_variables);
+        let own = bound.get(name);
+        error_variables.retain(|v| !own.is_some_and(|own| own.contains(v)));
+
+        checker.candidates = error_variables;
+        checker.active.clear();
+        checker.walk(steps, spanned.at_key(name).at_key("steps"));
+    }
+}
+
+/// Every `as` name of the `except` blocks under `value`
+fn collect_error_variables(value: &Value, names: &mut HashSet<String>) {
+    match value {
+        Value::Mapping(mapping) => {
+            for (key, child) in mapping {
+                if key.as_str() == Some("except") {
+                    if let Some(name) = child.get("as").and_then(Value::as_str) {
+                        names.insert(name.to_string());
+                    }
+                }
+                collect_error_variables(child, names);
+            }
+        }
+        Value::Sequence(items) => items
+            .iter()
+            .for_each(|item| collect_error_variables(item, names)),
+        _ => {}
+    }
+}
+
+/// Walks one workflow's steps in document order
+struct Checker<'a, 'c> {
+    expressions: &'a ExpressionMap,
+    /// Error variable names that aren't otherwise bound in the workflow
+    candidates: HashSet<String>,
+    /// Error variables bound by the `except` blocks around the current value
+    active: Vec<&'a str>,
+    collector: &'c mut DiagnosticCollector,
+}
+
+impl<'a> Checker<'a, '_> {
+    /// Walk `value`, with `node` its place in the spanned tree
+    fn walk(&mut self, value: &'a Value, node: &SpannedValue) {
+        match value {
+            Value::Mapping(mapping) => {
+                for (key, child) in mapping {
+                    let key = key.as_str().unwrap_or_default();
+                    if key == "except" {
+                        self.except(child, node.key_range(key), node.at_key(key));
+                    } else {
+                        self.walk(child, node.at_key(key));
+                    }
+                }
+            }
+            Value::Sequence(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    self.walk(item, node.at_index(i));
+                }
+            }
+            Value::String(s) if s.contains("__EXPR_") => self.references(s),
+            _ => {}
+        }
+    }
+
+    /// Check an `except` block's shape, then walk it with its error variable
+    /// in scope
+    ///
+    /// `range` is the `except` key's and `node` the block's in the spanned
+    /// tree.
+    fn except(&mut self, value: &'a Value, range: Range, node: &SpannedValue) {
+        let Some(except) = value.as_mapping() else {
+            self.warn(
+                format!(
+                    "'except' expects a mapping with 'as' and 'steps', found {}",
+                    describe_value(value)
+                ),
+                range,
+                DiagnosticCode::TypeMismatch,
+            );
+            return;
+        };
+
+        let error = match except.get("as") {
+            None => {
+                self.warn(
+                    "'except' is missing 'as'; its steps can't read the caught error".to_string(),
+                    range,
+                    DiagnosticCode::WorkflowStructure,
+                );
+                None
+            }
+            Some(Value::String(name)) => Some(name.as_str()),
+            Some(other) => {
+                self.warn(
+                    format!(
+                        "'as' expects a variable name, found {}",
+                        describe_value(other)
+                    ),
+                    node.key_range("as"),
+                    DiagnosticCode::TypeMismatch,
+                );
+                None
+            }
+        };
+        match except.get("steps") {
+            None => self.warn(
+                "'except' is missing 'steps'; the error would be caught and ignored".to_string(),
+                range,
+                DiagnosticCode::WorkflowStructure,
+            ),
+            Some(steps) if !steps.is_sequence() => self.warn(
+                format!("'steps' expects list, found {}", describe_value(steps)),
+                node.key_range("steps"),
+                DiagnosticCode::TypeMismatch,
+            ),
+            Some(_) => {}
+        }
+
+        let depth = self.active.len();
+        self.active.extend(error);
+        for (key, child) in except {
+            let key = key.as_str().unwrap_or_default();
+            if key != "as" {
+                self.walk(child, node.at_key(key));
+            }
+        }
+        self.active.truncate(depth);
+    }
+
+    /// Warn at a range of the preprocessed text, mapped back to the document
+    fn warn(&mut self, message: String, range: Range, code: DiagnosticCode) {
+        let range = self.expressions.adjust_range(range);
+        self.collector.add_workflow_warning_in(message, range, code);
+    }
+
+    /// Warn about error fields read in the `$${}` expressions of a value
+    /// while their error variable is out of scope
+    fn references(&mut self, value: &str) {
+        let expressions = self.expressions;
+        for expr in expressions
+            .expressions
+            .iter()
+            .filter(|e| e.kind == ExpressionKind::Workflows && value.contains(&e.placeholder))
+        {
+            let Some(tokens) = expr
+                .original
+                .strip_prefix("$${")
+                .and_then(|b| b.strip_suffix('}'))
+                .and_then(tokenize)
+            else {
+                continue;
+            };
+            // `e.code` or `e["code"]`, but not `x.e.code`
+            let unbound = tokens.windows(2).enumerate().find_map(|(i, pair)| {
+                let (name, next) = (pair[0], pair[1]);
+                let is_root = i == 0 || tokens[i - 1].kind != TokenKind::Dot;
+                let reads_field = next.kind == TokenKind::Dot || next.text == "[";
+                (name.kind == TokenKind::Name
+                    && is_root
+                    && reads_field
+                    && self.candidates.contains(name.text)
+                    && !self.active.contains(&name.text))
+                .then_some(name.text)
+            });
+            if let Some(name) = unbound {
+                self.collector.add_workflow_warning_with_range(
+                    format!(
+                        "'{}' is not defined here; the fields of an error are only available inside an 'except' block with 'as: {}'",
+                        name, name
+                    ),
+                    expr.start_line,
+                    expr.start_column,
+                    expr.end_line,
+                    expr.end_column,
+                    DiagnosticCode::ErrorVariableOutOfScope,
+                );
+            }
+
+            // `e.status` or `e["status"]` on an error caught around here
+            for (i, window) in tokens.windows(3).enumerate() {
+                let [name, access, field] = window else {
+                    continue;
+                };
+                let is_root = i == 0 || tokens[i - 1].kind != TokenKind::Dot;
+                let field = match (access.kind, field.kind) {
+                    (TokenKind::Dot, TokenKind::Name) => field.text,
+                    (TokenKind::Open, TokenKind::String) if access.text == "[" => {
+                        &field.text[1..field.text.len() - 1]
+                    }
+                    _ => continue,
+                };
+                if name.kind != TokenKind::Name
+                    || !is_root
+                    || !self.candidates.contains(name.text)
+                    || !self.active.contains(&name.text)
+                    || error_field(field).is_some()
+                {
+                    continue;
+                }
+                let known: Vec<&str> = ERROR_FIELDS.iter().map(|f| f.name).collect();
+                self.collector.add_hint_with_range(
+                    format!(
+                        "Error '{}' has no field '{}' (expected one of: {})",
+                        name.text,
+                        field,
+                        known.join(", ")
+                    ),
+                    Range::new(
+                        Position::new(expr.start_line, expr.start_column),
+                        Position::new(expr.end_line, expr.end_column),
+                    ),
+                    DiagnosticCode::UnknownField,
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::preprocess_expressions;
+
+    fn diagnostics(text: &str) -> Vec<(u32, String)> {
+        let (preprocessed, expressions) = preprocess_expressions(text);
+        let value: Value = serde_yaml::from_str(&preprocessed).unwrap();
+        let mut collector = DiagnosticCollector::new();
+        validate_except_blocks(&value, &preprocessed, &expressions, &mut collector);
+        collector
+            .into_diagnostics()
+            .into_iter()
+            .map(|d| (d.range.start.line, d.message))
+            .collect()
+    }
+
+    #[test]
+    fn test_valid_except() {
+        let text = r#"main:
+  steps:
+    - call_api:
+        try:
+          call: http.get
+          args:
+            url: https://example.com
+        except:
+          as: e
+          steps:
+            - log:
+                call: sys.log
+                args:
+                  text: $${e.message}
+            - check:
+                switch:
+                  - condition: $${e.code == 404}
+                    return: $${e["tags"]}
+"#;
+        assert!(diagnostics(text).is_empty());
+    }
+
+    #[test]
+    fn test_except_shape() {
+        let text = r#"main:
+  steps:
+    - a:
+        try:
+          call: http.get
+        except:
+          steps:
+            - log:
+                return: 1
+    - b:
+        try:
+          call: http.get
+        except:
+          as: err
+    - c:
+        try:
+          call: http.get
+        except: oops
+"#;
+        assert_eq!(
+            diagnostics(text),
+            vec![
+                (
+                    5,
+                    "'except' is missing 'as'; its steps can't read the caught error".to_string()
+                ),
+                (
+                    12,
+                    "'except' is missing 'steps'; the error would be caught and ignored"
+                        .to_string()
+                ),
+                (
+                    17,
+                    "'except' expects a mapping with 'as' and 'steps', found string".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unknown_error_fields() {
+        let text = r#"main:
+  steps:
+    - a:
+        try:
+          call: http.get
+        except:
+          as: e
+          steps:
+            - log:
+                return: $${e.status + e["body"] + e.operation.status}
+            - other:
+                return: $${e["reason"]}
+"#;
+        let expected = "(expected one of: code, message, tags, operation, headers, body)";
+        assert_eq!(
+            diagnostics(text),
+            vec![
+                (9, format!("Error 'e' has no field 'status' {}", expected)),
+                (11, format!("Error 'e' has no field 'reason' {}", expected)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_error_fields_out_of_scope() {
+        let text = r#"main:
+  steps:
+    - a:
+        try:
+          call: http.get
+        except:
+          as: err
+          steps:
+            - log:
+                return: $${err.message}
+    - b:
+        return: $${err.message + " " + e.code + other.e.code}
+helper:
+  params: [e]
+  steps:
+    - done:
+        return: $${e.code}
+"#;
+        assert_eq!(
+            diagnostics(text),
+            vec![(
+                11,
+                "'err' is not defined here; the fields of an error are only available inside an 'except' block with 'as: err'".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_reports_at_the_except_key() {
+        let text = r#"main:
+  steps:
+    - note:
+        return: |
+          except: not a key
+    - a:
+        try:
+          call: http.get
+        except:
+          steps: []
+"#;
+        let (preprocessed, expressions) = preprocess_expressions(text);
+        let value: Value = serde_yaml::from_str(&preprocessed).unwrap();
+        let mut collector = DiagnosticCollector::new();
+        validate_except_blocks(&value, &preprocessed, &expressions, &mut collector);
+        let diagnostics = collector.into_diagnostics();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].range,
+            Range::new(Position::new(8, 8), Position::new(8, 14))
+        );
+    }
+
+    #[test]
+    fn test_out_of_scope_error_code() {
+        let text = "main:\n  steps:\n    - done:\n        return: $${e.code}\n";
+        let (preprocessed, expressions) = preprocess_expressions(text);
+        let value: Value = serde_yaml::from_str(&preprocessed).unwrap();
+        let mut collector = DiagnosticCollector::new();
+        validate_except_blocks(&value, &preprocessed, &expressions, &mut collector);
+        let diagnostics = collector.into_diagnostics();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].code,
+            Some(tower_lsp::lsp_types::NumberOrString::String(
+                "error-variable-out-of-scope".to_string()
+            ))
+        );
+    }
+}
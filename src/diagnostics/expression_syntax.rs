@@ -0,0 +1,185 @@
+//! Syntax errors inside expressions
+//!
+//! Parses the body of every `$${...}` expression with the Workflows grammar
+//! and of every `${...}` interpolation with the Terraform (HCL) expression
+//! grammar, and reports the first syntax error in each on the exact
+//! characters of the document it concerns. Workflows bodies with a Terraform
+//! `${...}` or `%{...}` inside are only complete once rendered and are
+//! skipped, as are expressions in comments.
+
+use crate::document::LineOffsets;
+use crate::parser::expressions::Expression;
+use crate::parser::{wf_expr, ExpressionKind, ExpressionMap};
+
+use super::yaml_errors::{DiagnosticCode, DiagnosticCollector};
+
+/// Report syntax errors in `$${}` and `${}` expressions; `text` is the
+/// document as written
+pub fn validate_expression_syntax(
+    text: &str,
+    expressions: &ExpressionMap,
+    collector: &mut DiagnosticCollector,
+) {
+    let lines = LineOffsets::new(text);
+    for expr in &expressions.expressions {
+        if in_comment(text, expr.start) {
+            continue;
+        }
+        let error = match expr.kind {
+            ExpressionKind::Workflows => workflows_error(expr),
+            ExpressionKind::Terraform => terraform_error(expr),
+            ExpressionKind::Directive => None,
+        };
+        let Some((message, start, end)) = error else {
+            continue;
+        };
+
+        // Errors at a point, such as the end of the body, cover the
+        // character there, which is at worst the closing brace
+        let end = if end > start {
+            end
+        } else {
+            start
+                + text[start..expr.end]
+                    .chars()
+                    .next()
+                    .map_or(0, char::len_utf8)
+        };
+        let start = lines.offset_to_position(text, start);
+        let end = lines.offset_to_position(text, end);
+        collector.add_yaml_error_with_range(
+            message,
+            start.line,
+            start.character,
+            end.line,
+            end.character,
+            DiagnosticCode::ExpressionSyntax,
+        );
+    }
+}
+
+/// The first syntax error in a `$${}` expression, with its document offsets
+fn workflows_error(expr: &Expression) -> Option<(String, usize, usize)> {
+    let body = expr.original.strip_prefix("$${")?.strip_suffix('}')?;
+    if body.contains("${") || body.contains("%{") {
+        return None;
+    }
+    let error = wf_expr::parse(body).err()?;
+    let body_start = expr.start + "$${".len();
+    Some((
+        format!("Invalid Workflows expression: {}", error.message),
+        body_start + error.span.start,
+        body_start + error.span.end,
+    ))
+}
+
+/// The first syntax error in a `${}` interpolation, with its document offset
+fn terraform_error(expr: &Expression) -> Option<(String, usize, usize)> {
+    let body = expr.original.strip_prefix("${")?.strip_suffix('}')?;
+    // Strip markers trim whitespace around the interpolation and aren't part
+    // of the expression; the parser doesn't accept surrounding whitespace
+    let inner = body.strip_prefix('~').unwrap_or(body);
+    // Measured before the closing marker goes, which would shorten the rest
+    let leading = body.len() - inner.trim_start().len();
+    let inner = inner.strip_suffix('~').unwrap_or(inner).trim();
+    let error = hcl::edit::parser::parse_expr(inner).err()?;
+    let inner_start = expr.start + "${".len() + leading;
+    let offset = inner_start + error.location().offset().min(inner.len());
+    Some((
+        format!("Invalid Terraform expression: {}", error.message()),
+        offset,
+        offset,
+    ))
+}
+
+/// Whether the byte at `offset` is inside a `# comment`, which Workflows
+/// never evaluates
+pub(super) fn in_comment(text: &str, offset: usize) -> bool {
+    let line_start = text[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let before = &text[line_start..offset];
+    before.trim_start().starts_with('#') || before.contains(" #")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::preprocess_expressions;
+    use tower_lsp::lsp_types::{DiagnosticSeverity, Position, Range};
+
+    fn diagnostics(text: &str) -> Vec<(Range, String)> {
+        let (_, expressions) = preprocess_expressions(text);
+        let mut collector = DiagnosticCollector::new();
+        validate_expression_syntax(text, &expressions, &mut collector);
+        let diagnostics = collector.into_diagnostics();
+        assert!(diagnostics
+            .iter()
+            .all(|d| d.severity == Some(DiagnosticSeverity::ERROR)));
+        diagnostics
+            .into_iter()
+            .map(|d| (d.range, d.message))
+            .collect()
+    }
+
+    #[test]
+    fn test_valid_expressions() {
+        let text = r#"a: $${len(items) + 1}
+b: $${"p/${var.x}" + (}
+c: ${var.y == "" ? local.z[*].id : [for k, v in var.m : upper(k) if v]}
+d: ${~ var.name ~}
+# Use $${} for runtime values and ${} for deploy-time ones
+e: 1 # or $${x +}
+"#;
+        assert_eq!(diagnostics(text), vec![]);
+    }
+
+    #[test]
+    fn test_reports_errors_at_their_position() {
+        let text =
+            "a: $${x + }\nb: \"$${f(a, b c)}\"\nc: |\n  $${\n    map.get(m,\n      \"k\" ]\n  }\n";
+        assert_eq!(
+            diagnostics(text),
+            vec![
+                (
+                    Range::new(Position::new(0, 10), Position::new(0, 11)),
+                    "Invalid Workflows expression: Expected an expression, found the end of the expression"
+                        .to_string()
+                ),
+                (
+                    Range::new(Position::new(1, 14), Position::new(1, 15)),
+                    "Invalid Workflows expression: Expected ')' to close '(', found 'c'".to_string()
+                ),
+                (
+                    Range::new(Position::new(5, 10), Position::new(5, 11)),
+                    "Invalid Workflows expression: Expected ')' to close '(', found ']'"
+                        .to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reports_terraform_errors() {
+        let text = "a: ${var.x +}\nb: ${upper(var.y}\nc: ${~ var.z ? 1 ~}\nd: ${ var.z ? 1 ~}\n";
+        assert_eq!(
+            diagnostics(text),
+            vec![
+                (
+                    Range::new(Position::new(0, 10), Position::new(0, 11)),
+                    "Invalid Terraform expression: unexpected token".to_string()
+                ),
+                (
+                    Range::new(Position::new(1, 15), Position::new(1, 16)),
+                    "Invalid Terraform expression: expected `)` or expression".to_string()
+                ),
+                (
+                    Range::new(Position::new(2, 15), Position::new(2, 16)),
+                    "Invalid Terraform expression: expected `:`".to_string()
+                ),
+                (
+                    Range::new(Position::new(3, 14), Position::new(3, 15)),
+                    "Invalid Terraform expression: expected `:`".to_string()
+                ),
+            ]
+        );
+    }
+}
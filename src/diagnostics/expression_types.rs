@@ -0,0 +1,494 @@
+//! Type checks inside Workflows expressions
+//!
+//! Infers the types of `$${...}` expressions from literals, standard library
+//! return types and variables that every `assign` in their workflow gives
+//! the same literal type, then warns about operations that can only fail at
+//! run time, such as indexing an int or adding a map to a string. Anything
+//! of unknown type, including Terraform `${...}` values, is left alone.
+
+use std::collections::HashMap;
+
+use serde_yaml::Value;
+
+use crate::parser::tokens::{tokenize, Token, TokenKind};
+use crate::parser::{ExpressionKind, ExpressionMap};
+use crate::schema::{return_type, ExprType};
+
+use super::scopes::{walk_assignments, BindingKind};
+use super::yaml_errors::{DiagnosticCode, DiagnosticCollector};
+
+/// Known variable types of one workflow; `None` when bindings disagree or
+/// one can't be typed
+type Variables = HashMap<String, Option<ExprType>>;
+
+/// Report invalid operations on values of known type in `$${}` expressions
+///
+/// `text` is the document as written, `preprocessed` the one `value` was
+/// parsed from.
+pub fn validate_expression_types(
+    value: &Value,
+    text: &str,
+    preprocessed: &str,
+    expressions: &ExpressionMap,
+    collector: &mut DiagnosticCollector,
+) {
+    let variables = variable_types(value, preprocessed, expressions);
+    let workflows = workflow_lines(text);
+    let none = Variables::new();
+
+    for expr in &expressions.expressions {
+        let Some(body) = workflows_body(&expr.original, expr.kind) else {
+            continue;
+        };
+        let workflow = workflows
+            .iter()
+            .rfind(|(line, _)| *line <= expr.start_line)
+            .map_or("", |(_, name)| *name);
+        let env = variables.get(workflow).unwrap_or(&none);
+        let Some((_, problems)) = check(body, env) else {
+            continue;
+        };
+        for problem in problems {
+            collector.add_workflow_warning_with_range(
+                problem,
+                expr.start_line,
+                expr.start_column,
+                expr.end_line,
+                expr.end_column,
+                DiagnosticCode::InvalidOperation,
+            );
+        }
+    }
+}
+
+/// The body of a `$${...}` expression
+fn workflows_body(original: &str, kind: ExpressionKind) -> Option<&str> {
+    if kind != ExpressionKind::Workflows {
+        return None;
+    }
+    original.strip_prefix("$${")?.strip_suffix('}')
+}
+
+/// Variable types per workflow, from the bindings in execution order
+fn variable_types<'a>(
+    value: &'a Value,
+    text: &str,
+    expressions: &ExpressionMap,
+) -> HashMap<&'a str, Variables> {
+    let mut types: HashMap<&str, Variables> = HashMap::new();
+    walk_assignments(value, text, &mut |binding| {
+        if binding.kind == BindingKind::Assign && !binding.whole {
+            return;
+        }
+        let variables = types.entry(binding.workflow).or_default();
+        let ty = match binding.value {
+            Some(value) if binding.kind == BindingKind::Assign => {
+                value_type(value, expressions, variables)
+            }
+            _ => None,
+        };
+        variables
+            .entry(binding.name)
+            .and_modify(|known| {
+                if *known != ty {
+                    *known = None;
+                }
+            })
+            .or_insert(ty);
+    });
+    types
+}
+
+/// Type of an assigned value
+fn value_type(value: &Value, expressions: &ExpressionMap, env: &Variables) -> Option<ExprType> {
+    match value {
+        Value::String(s) if s.contains("__EXPR_") => {
+            let expr = expressions.find_by_placeholder(s)?;
+            check(workflows_body(&expr.original, expr.kind)?, env)?.0
+        }
+        Value::String(_) => Some(ExprType::String),
+        Value::Number(n) if n.is_f64() => Some(ExprType::Double),
+        Value::Number(_) => Some(ExprType::Int),
+        Value::Bool(_) => Some(ExprType::Boolean),
+        Value::Sequence(_) => Some(ExprType::List),
+        Value::Mapping(_) => Some(ExprType::Map),
+        Value::Null => Some(ExprType::Null),
+        Value::Tagged(_) => None,
+    }
+}
+
+/// Lines of the top-level keys, which name the workflows
+fn workflow_lines(text: &str) -> Vec<(u32, &str)> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| line.starts_with(|c: char| c.is_alphabetic() || c == '_'))
+        .filter_map(|(i, line)| Some((i as u32, line.split_once(':')?.0.trim())))
+        .collect()
+}
+
+/// Infer the type of an expression body and collect the problems found in
+/// it, or `None` if the body isn't understood
+fn check(body: &str, env: &Variables) -> Option<(Option<ExprType>, Vec<String>)> {
+    let mut checker = Checker {
+        tokens: tokenize(body)?,
+        pos: 0,
+        env,
+        problems: Vec::new(),
+    };
+    let ty = checker.or()?;
+    (checker.pos == checker.tokens.len()).then_some((ty, checker.problems))
+}
+
+/// Recursive descent over the tokens of one expression
+///
+/// Each method returns the type of what it parsed, `Some(None)` when that is
+/// unknown, and `None` when the tokens don't form an expression.
+struct Checker<'t, 'e> {
+    tokens: Vec<Token<'t>>,
+    pos: usize,
+    env: &'e Variables,
+    problems: Vec<String>,
+}
+
+impl<'t> Checker<'t, '_> {
+    fn peek(&self) -> Option<Token<'t>> {
+        self.tokens.get(self.pos).copied()
+    }
+
+    fn peek_is(&self, kind: TokenKind, text: &str) -> bool {
+        self.peek()
+            .is_some_and(|t| t.kind == kind && t.text == text)
+    }
+
+    fn eat(&mut self, kind: TokenKind, text: &str) -> bool {
+        let found = self.peek_is(kind, text);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn or(&mut self) -> Option<Option<ExprType>> {
+        let mut ty = self.and()?;
+        while self.eat(TokenKind::Name, "or") {
+            self.and()?;
+            ty = Some(ExprType::Boolean);
+        }
+        Some(ty)
+    }
+
+    fn and(&mut self) -> Option<Option<ExprType>> {
+        let mut ty = self.not()?;
+        while self.eat(TokenKind::Name, "and") {
+            self.not()?;
+            ty = Some(ExprType::Boolean);
+        }
+        Some(ty)
+    }
+
+    fn not(&mut self) -> Option<Option<ExprType>> {
+        if self.eat(TokenKind::Name, "not") || self.eat(TokenKind::Bang, "!") {
+            self.not()?;
+            return Some(Some(ExprType::Boolean));
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Option<Option<ExprType>> {
+        let mut ty = self.additive()?;
+        while self.peek().is_some_and(|t| {
+            matches!(t.text, "==" | "!=" | "<" | ">" | "<=" | ">=")
+                || (t.kind == TokenKind::Name && t.text == "in")
+        }) {
+            self.pos += 1;
+            self.additive()?;
+            ty = Some(ExprType::Boolean);
+        }
+        Some(ty)
+    }
+
+    fn additive(&mut self) -> Option<Option<ExprType>> {
+        let mut left = self.multiplicative()?;
+        while let Some(op) = self
+            .peek()
+            .filter(|t| t.kind == TokenKind::Operator && matches!(t.text, "+" | "-"))
+        {
+            self.pos += 1;
+            let right = self.multiplicative()?;
+            left = self.binary(op.text, left, right);
+        }
+        Some(left)
+    }
+
+    fn multiplicative(&mut self) -> Option<Option<ExprType>> {
+        let mut left = self.unary()?;
+        while let Some(op) = self
+            .peek()
+            .filter(|t| t.kind == TokenKind::Operator && matches!(t.text, "*" | "/" | "%"))
+        {
+            self.pos += 1;
+            // `//` is integer division
+            let op = if op.text == "/" && self.eat(TokenKind::Operator, "/") {
+                "//"
+            } else {
+                op.text
+            };
+            let right = self.unary()?;
+            left = self.binary(op, left, right);
+        }
+        Some(left)
+    }
+
+    fn unary(&mut self) -> Option<Option<ExprType>> {
+        if self.eat(TokenKind::Operator, "-") {
+            let ty = self.unary()?;
+            return Some(match ty {
+                Some(ty) if !ty.is_numeric() => {
+                    self.problems
+                        .push(format!("Can't apply '-' to {}", ty.name()));
+                    None
+                }
+                ty => ty,
+            });
+        }
+        self.postfix()
+    }
+
+    fn postfix(&mut self) -> Option<Option<ExprType>> {
+        let mut ty = self.primary()?;
+        loop {
+            if self.eat(TokenKind::Open, "[") {
+                self.or()?;
+                if !self.eat(TokenKind::Close, "]") {
+                    return None;
+                }
+                if let Some(t) =
+                    ty.filter(|t| !matches!(t, ExprType::List | ExprType::Map | ExprType::String))
+                {
+                    self.problems
+                        .push(format!("Can't index a value of type {}", t.name()));
+                }
+                ty = None;
+            } else if self.eat(TokenKind::Dot, ".") {
+                let field = self.peek().filter(|t| t.kind != TokenKind::Open)?;
+                self.pos += 1;
+                self.field(ty, field.text);
+                ty = None;
+            } else {
+                return Some(ty);
+            }
+        }
+    }
+
+    fn primary(&mut self) -> Option<Option<ExprType>> {
+        let token = self.peek()?;
+        self.pos += 1;
+        match token.kind {
+            TokenKind::Number if token.text.contains(['.', 'e', 'E']) => {
+                Some(Some(ExprType::Double))
+            }
+            TokenKind::Number => Some(Some(ExprType::Int)),
+            TokenKind::String => Some(Some(ExprType::String)),
+            TokenKind::Name => self.name(token.text),
+            TokenKind::Open if token.text == "(" => {
+                let ty = self.or()?;
+                self.eat(TokenKind::Close, ")").then_some(ty)
+            }
+            TokenKind::Open if token.text == "[" => {
+                self.items("]", false)?;
+                Some(Some(ExprType::List))
+            }
+            TokenKind::Open => {
+                self.items("}", true)?;
+                Some(Some(ExprType::Map))
+            }
+            _ => None,
+        }
+    }
+
+    /// A literal, a variable with its fields, or a function call
+    fn name(&mut self, first: &str) -> Option<Option<ExprType>> {
+        match first {
+            "true" | "false" => return Some(Some(ExprType::Boolean)),
+            "null" => return Some(Some(ExprType::Null)),
+            _ => {}
+        }
+
+        // `text.split(...)` calls a function; `a.b` without a call reads a
+        // field of variable `a`
+        let mut path = vec![first];
+        let mut end = self.pos;
+        while self
+            .tokens
+            .get(end)
+            .is_some_and(|t| t.kind == TokenKind::Dot)
+            && self
+                .tokens
+                .get(end + 1)
+                .is_some_and(|t| t.kind == TokenKind::Name)
+        {
+            path.push(self.tokens[end + 1].text);
+            end += 2;
+        }
+        if self.tokens.get(end).is_some_and(|t| t.text == "(") {
+            self.pos = end + 1;
+            self.items(")", false)?;
+            return Some(return_type(&path.join(".")));
+        }
+        Some(self.env.get(first).copied().flatten())
+    }
+
+    /// Comma-separated items up to `close`, as `key: value` pairs for maps
+    fn items(&mut self, close: &str, pairs: bool) -> Option<()> {
+        if self.eat(TokenKind::Close, close) {
+            return Some(());
+        }
+        loop {
+            self.or()?;
+            if pairs && !self.eat(TokenKind::Colon, ":") {
+                return None;
+            }
+            if pairs {
+                self.or()?;
+            }
+            if self.eat(TokenKind::Close, close) {
+                return Some(());
+            }
+            if !self.eat(TokenKind::Comma, ",") {
+                return None;
+            }
+        }
+    }
+
+    /// Check reading `field` from a value of type `ty`
+    fn field(&mut self, ty: Option<ExprType>, field: &str) {
+        if let Some(ty) = ty.filter(|t| *t != ExprType::Map) {
+            self.problems.push(format!(
+                "Can't read field '{}' of a value of type {}",
+                field,
+                ty.name()
+            ));
+        }
+    }
+
+    /// Check a binary arithmetic operation and return its result type
+    fn binary(
+        &mut self,
+        op: &str,
+        left: Option<ExprType>,
+        right: Option<ExprType>,
+    ) -> Option<ExprType> {
+        let (left, right) = (left?, right?);
+        let result = match (left, right) {
+            (ExprType::Int, ExprType::Int) if op == "/" => Some(ExprType::Double),
+            (ExprType::Int, ExprType::Int) => Some(ExprType::Int),
+            (l, r) if l.is_numeric() && r.is_numeric() => Some(ExprType::Double),
+            (ExprType::String, ExprType::String) if op == "+" => Some(ExprType::String),
+            (ExprType::List, ExprType::List) if op == "+" => Some(ExprType::List),
+            _ => None,
+        };
+        if result.is_none() {
+            self.problems.push(format!(
+                "Can't apply '{}' to {} and {}",
+                op,
+                left.name(),
+                right.name()
+            ));
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::preprocess_expressions;
+
+    fn problems(body: &str) -> Vec<String> {
+        check(body, &Variables::new()).map_or_else(Vec::new, |(_, p)| p)
+    }
+
+    fn diagnostics(text: &str) -> Vec<(u32, String)> {
+        let (preprocessed, expressions) = preprocess_expressions(text);
+        let value: Value = serde_yaml::from_str(&preprocessed).unwrap();
+        let mut collector = DiagnosticCollector::new();
+        validate_expression_types(&value, text, &preprocessed, &expressions, &mut collector);
+        collector
+            .into_diagnostics()
+            .into_iter()
+            .map(|d| (d.range.start.line, d.message))
+            .collect()
+    }
+
+    #[test]
+    fn test_infers_types() {
+        let ty = |body| check(body, &Variables::new()).and_then(|(ty, _)| ty);
+        assert_eq!(ty("len(x) + 1"), Some(ExprType::Int));
+        assert_eq!(ty("1 / 2"), Some(ExprType::Double));
+        assert_eq!(ty("\"a\" + text.to_upper(\"b\")"), Some(ExprType::String));
+        assert_eq!(ty("x == 1 and y"), Some(ExprType::Boolean));
+        assert_eq!(ty("{\"a\": 1}"), Some(ExprType::Map));
+        assert_eq!(ty("map.get(m, \"k\")"), None);
+        assert_eq!(ty("x[0]"), None);
+    }
+
+    #[test]
+    fn test_invalid_operations() {
+        assert_eq!(problems("5[0]"), vec!["Can't index a value of type int"]);
+        assert_eq!(
+            problems("\"id: \" + {\"a\": 1}"),
+            vec!["Can't apply '+' to string and map"]
+        );
+        assert_eq!(
+            problems("\"n=\" + len(items)"),
+            vec!["Can't apply '+' to string and int"]
+        );
+        assert_eq!(problems("-\"a\""), vec!["Can't apply '-' to string"]);
+        assert_eq!(
+            problems("sys.now().seconds"),
+            vec!["Can't read field 'seconds' of a value of type double"]
+        );
+        assert!(problems("a + b * (c - 1) // 2").is_empty());
+        assert!(problems("[1] + [2]").is_empty());
+        assert!(problems("\"a\" + \"${nested}\"").is_empty());
+        assert_eq!(check("a +", &Variables::new()), None);
+    }
+
+    #[test]
+    fn test_variable_types() {
+        let text = r#"main:
+  steps:
+    - init:
+        assign:
+          - count: 0
+          - name: "wf"
+          - label: $${"run-" + name}
+          - config:
+              region: eu
+          - mixed: 1
+          - mixed: "one"
+    - use:
+        assign:
+          - a: $${count[0]}
+          - b: $${label + config}
+          - c: $${config.region + mixed}
+          - d: $${name.first}
+helper:
+  params: [count]
+  steps:
+    - done:
+        return: $${count[0]}
+"#;
+        assert_eq!(
+            diagnostics(text),
+            vec![
+                (13, "Can't index a value of type int".to_string()),
+                (14, "Can't apply '+' to string and map".to_string()),
+                (
+                    16,
+                    "Can't read field 'first' of a value of type string".to_string()
+                ),
+            ]
+        );
+    }
+}
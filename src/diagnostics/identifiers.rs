@@ -0,0 +1,188 @@
+//! Step, subworkflow and variable identifiers
+//!
+//! Workflows names steps, subworkflows and variables with identifiers made
+//! of ASCII letters, digits and underscores that don't start with a digit.
+//! Names that break the grammar are reported on the name itself. Names built
+//! from Terraform `${...}` interpolation are only known once rendered and are
+//! left alone.
+
+use serde_yaml::Value;
+
+use super::scopes::{walk_assignments, BindingKind};
+use super::yaml_errors::{DiagnosticCode, DiagnosticCollector};
+use crate::parser::Outline;
+
+/// Warn about step, subworkflow and variable names that aren't identifiers
+///
+/// `text` is the document as written, `preprocessed` the one `value` was
+/// parsed from.
+pub fn validate_identifiers(
+    value: &Value,
+    text: &str,
+    preprocessed: &str,
+    collector: &mut DiagnosticCollector,
+) {
+    let lines: Vec<&str> = text.lines().collect();
+    let outline = Outline::parse(text);
+
+    for workflow in &outline.workflows {
+        report(&workflow.name, "Subworkflow", workflow.line, 0, collector);
+    }
+    for step in &outline.steps {
+        report(&step.name, "Step", step.line, step.column, collector);
+    }
+
+    // Params are checked with the rest of the `params` entry
+    walk_assignments(value, preprocessed, &mut |binding| {
+        if binding.kind == BindingKind::Param || binding.name.contains("__EXPR_") {
+            return;
+        }
+        // A quoted name starts after its quote
+        let start = binding.range.start;
+        let quoted = lines
+            .get(start.line as usize)
+            .and_then(|line| line.chars().nth(start.character as usize))
+            .is_some_and(|c| c == '"' || c == '\'');
+        let column = start.character + quoted as u32;
+        report(&binding.name, "Variable", start.line, column, collector);
+    });
+}
+
+/// Warn if `name`, found at `line`/`column`, is not an identifier
+fn report(name: &str, what: &str, line: u32, column: u32, collector: &mut DiagnosticCollector) {
+    if name.contains('$') {
+        return;
+    }
+    let Some(problem) = identifier_problem(name) else {
+        return;
+    };
+    collector.add_workflow_warning_with_range(
+        format!(
+            "{} name '{}' is not a valid identifier: it {}",
+            what, name, problem
+        ),
+        line,
+        column,
+        line,
+        column + name.chars().count() as u32,
+        DiagnosticCode::InvalidIdentifier,
+    );
+}
+
+/// What keeps `name` from being an identifier, if anything
+fn identifier_problem(name: &str) -> Option<String> {
+    let first = name.chars().next()?;
+    if first.is_ascii_digit() {
+        return Some("starts with a digit".to_string());
+    }
+    name.chars()
+        .find(|c| !c.is_ascii_alphanumeric() && *c != '_')
+        .map(|c| format!("contains '{}'", c))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::preprocess_expressions;
+
+    fn diagnostics(text: &str) -> Vec<(u32, u32, u32, String)> {
+        let (preprocessed, _) = preprocess_expressions(text);
+        let value: Value = serde_yaml::from_str(&preprocessed).unwrap();
+        let mut collector = DiagnosticCollector::new();
+        validate_identifiers(&value, text, &preprocessed, &mut collector);
+        collector
+            .into_diagnostics()
+            .into_iter()
+            .map(|d| {
+                (
+                    d.range.start.line,
+                    d.range.start.character,
+                    d.range.end.character,
+                    d.message,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_identifier_problem() {
+        assert_eq!(identifier_problem("init_1"), None);
+        assert_eq!(identifier_problem("_private"), None);
+        assert_eq!(
+            identifier_problem("1st"),
+            Some("starts with a digit".to_string())
+        );
+        assert_eq!(
+            identifier_problem("get-user"),
+            Some("contains '-'".to_string())
+        );
+        assert_eq!(identifier_problem("café"), Some("contains 'é'".to_string()));
+    }
+
+    #[test]
+    fn test_reports_invalid_names() {
+        let text = r#"main:
+  steps:
+    - get-user:
+        call: http.get
+        args:
+          url: https://example.com
+        result: user-data
+    - loop:
+        for:
+          value: 2nd
+          in: [1, 2]
+          steps:
+            - ok:
+                assign:
+                  - total.sum: 1
+                  - my-list[0]: 2
+    - step_${var.env}:
+        return: user-data
+sub-flow:
+  params: [x]
+  steps:
+    - done:
+        return: x
+"#;
+        assert_eq!(
+            diagnostics(text),
+            vec![
+                (
+                    18,
+                    0,
+                    8,
+                    "Subworkflow name 'sub-flow' is not a valid identifier: it contains '-'"
+                        .to_string()
+                ),
+                (
+                    2,
+                    6,
+                    14,
+                    "Step name 'get-user' is not a valid identifier: it contains '-'".to_string()
+                ),
+                (
+                    6,
+                    16,
+                    25,
+                    "Variable name 'user-data' is not a valid identifier: it contains '-'"
+                        .to_string()
+                ),
+                (
+                    9,
+                    17,
+                    20,
+                    "Variable name '2nd' is not a valid identifier: it starts with a digit"
+                        .to_string()
+                ),
+                (
+                    15,
+                    20,
+                    27,
+                    "Variable name 'my-list' is not a valid identifier: it contains '-'"
+                        .to_string()
+                ),
+            ]
+        );
+    }
+}
@@ -3,9 +3,57 @@
 //! This module provides:
 //! - `DiagnosticCollector`: Collects and converts errors to LSP diagnostics
 //! - `DiagnosticCode`: Categorizes different types of diagnostics
+//! - `Suppressions`: Inline `# yaml-tftpl-lsp disable...` comments
+//! - `effective_severities`: Per-rule severity resolution for a position
+//! - `Provenance`: The rule, stage and analyzer behind each diagnostic
 
+mod concatenation;
+mod directives;
+mod duplicate_steps;
+mod escapes;
+mod except_blocks;
+mod expression_syntax;
+mod expression_types;
+mod identifiers;
+mod node_properties;
+mod provenance;
+mod quotas;
+mod resource_names;
+mod scopes;
+mod severity;
+mod suppressions;
+mod tab_indentation;
+mod template_vars;
+mod terraform_functions;
+mod terraform_variables;
+mod type_stability;
+mod unclosed_expressions;
 mod workflow_validator;
 mod yaml_errors;
 
-pub use workflow_validator::validate_workflow;
-pub use yaml_errors::{DiagnosticCode, DiagnosticCollector};
+pub use concatenation::validate_concatenation;
+pub use directives::validate_directives;
+pub use duplicate_steps::validate_duplicate_steps;
+pub use escapes::validate_escapes;
+pub use except_blocks::validate_except_blocks;
+pub use expression_syntax::validate_expression_syntax;
+pub use expression_types::validate_expression_types;
+pub use identifiers::validate_identifiers;
+pub use node_properties::validate_node_properties;
+pub use provenance::Provenance;
+pub use quotas::{
+    validate_quotas, QuotaLimits, DEFAULT_MAX_EXPRESSION_LENGTH, DEFAULT_MAX_NESTING_DEPTH,
+    DEFAULT_MAX_STEPS,
+};
+pub use resource_names::validate_resource_names;
+pub use scopes::validate_scopes;
+pub use severity::{effective_severities, RuleSeverity, SeveritySource};
+pub use suppressions::{RuleSet, SuppressionScope, Suppressions};
+pub use tab_indentation::validate_tab_indentation;
+pub use template_vars::{validate_template_vars, validate_variable_collisions};
+pub use terraform_functions::validate_terraform_functions;
+pub use terraform_variables::validate_terraform_variables;
+pub use type_stability::validate_type_stability;
+pub use unclosed_expressions::validate_unclosed_expressions;
+pub use workflow_validator::{validate_workflow, SchemaExtensions};
+pub use yaml_errors::{resolve_related_uris, DiagnosticCode, DiagnosticCollector, THIS_DOCUMENT};
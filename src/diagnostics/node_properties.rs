@@ -0,0 +1,137 @@
+//! Strict checks on YAML node properties (anchors, aliases and tags)
+//!
+//! serde_yaml happily accepts re-defined anchors and arbitrary tags, but the
+//! YAML dialect accepted by GCP Workflows does not, so these are reported
+//! before deployment rejects the rendered file. Aliases to anchors that are
+//! never declared are reported here too, by name and at the alias itself.
+
+use crate::parser::AnchorIndex;
+
+use super::yaml_errors::{DiagnosticCode, DiagnosticCollector};
+
+/// Tags from the YAML core schema and the standard `!!timestamp` and
+/// `!!binary` types, which Workflows accepts
+const CORE_TAGS: &[&str] = &[
+    "!!str",
+    "!!int",
+    "!!float",
+    "!!bool",
+    "!!null",
+    "!!map",
+    "!!seq",
+    "!!timestamp",
+    "!!binary",
+];
+
+/// Report undefined aliases, re-defined anchors and tags outside the YAML
+/// core schema
+pub fn validate_node_properties(index: &AnchorIndex, collector: &mut DiagnosticCollector) {
+    for alias in &index.aliases {
+        if index.resolve(alias).is_some() {
+            continue;
+        }
+        let message = if index.anchors.iter().any(|a| a.name == alias.name) {
+            format!(
+                "Alias '*{}' is used before its anchor is declared",
+                alias.name
+            )
+        } else {
+            format!("Alias '*{}' refers to an undefined anchor", alias.name)
+        };
+        collector.add_yaml_error_with_range(
+            message,
+            alias.line,
+            alias.column,
+            alias.line,
+            alias.end_column,
+            DiagnosticCode::UndefinedAlias,
+        );
+    }
+
+    for (anchor, first) in index.redefinitions() {
+        collector.add_workflow_warning_with_range(
+            format!(
+                "Anchor '&{}' is already defined on line {}",
+                anchor.name,
+                first.line + 1
+            ),
+            anchor.line,
+            anchor.column,
+            anchor.line,
+            anchor.end_column,
+            DiagnosticCode::DuplicateAnchor,
+        );
+    }
+
+    for tag in &index.tags {
+        if CORE_TAGS.contains(&tag.tag.as_str()) {
+            continue;
+        }
+        collector.add_yaml_error_with_range(
+            format!("Unsupported YAML tag '{}'", tag.tag),
+            tag.line,
+            tag.column,
+            tag.line,
+            tag.end_column,
+            DiagnosticCode::UnknownTag,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{preprocess_expressions, scan_anchors};
+    use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString};
+
+    fn check(text: &str) -> Vec<Diagnostic> {
+        let (_, map) = preprocess_expressions(text);
+        let mut collector = DiagnosticCollector::new();
+        validate_node_properties(&scan_anchors(text, &map), &mut collector);
+        collector.into_diagnostics()
+    }
+
+    fn code(d: &Diagnostic) -> &str {
+        match &d.code {
+            Some(NumberOrString::String(c)) => c,
+            _ => "",
+        }
+    }
+
+    #[test]
+    fn test_duplicate_anchor() {
+        let diagnostics = check("a: &x 1\nb: &x 2");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(code(&diagnostics[0]), "duplicate-anchor");
+        assert_eq!(diagnostics[0].range.start.line, 1);
+        assert!(diagnostics[0].message.contains("line 1"));
+    }
+
+    #[test]
+    fn test_unknown_tags() {
+        let diagnostics = check("a: !!python/object:os.system x\nb: !Ref Bucket\nc: !!str 1");
+        assert_eq!(diagnostics.len(), 2);
+        assert!(diagnostics.iter().all(|d| code(d) == "unknown-tag"));
+        assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::ERROR));
+        assert_eq!(diagnostics[1].range.start.character, 3);
+        assert_eq!(diagnostics[1].range.end.character, 7);
+    }
+
+    #[test]
+    fn test_undefined_aliases() {
+        let diagnostics = check("a: *missing\nb: *later\nc: &later 1");
+        assert_eq!(diagnostics.len(), 2);
+        assert!(diagnostics.iter().all(|d| code(d) == "undefined-alias"));
+        assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::ERROR));
+        assert_eq!(diagnostics[0].range.start.character, 3);
+        assert_eq!(diagnostics[0].range.end.character, 11);
+        assert!(diagnostics[0].message.contains("undefined anchor"));
+        assert!(diagnostics[1].message.contains("before its anchor"));
+    }
+
+    #[test]
+    fn test_clean_document() {
+        assert!(check("a: &x 1\nb: *x").is_empty());
+        assert!(check("a: !!timestamp 2024-01-01\nb: !!binary aGk=\nc: Hi !there").is_empty());
+    }
+}
@@ -0,0 +1,81 @@
+//! Diagnostic provenance
+//!
+//! Every diagnostic carries, in its `data` field, the rule that produced it,
+//! the pipeline stage and analyzer that ran the rule, and the key path of the
+//! offending line. Bug reports can then name the subsystem behind a false
+//! positive, and suppression comments can target a whole stage.
+
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::{Diagnostic, NumberOrString};
+
+use crate::analysis::Stage;
+use crate::parser::lines::key_path;
+
+/// Where a diagnostic came from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Provenance {
+    /// The diagnostic code (e.g. "unknown-keyword")
+    pub rule: String,
+    /// The pipeline stage that reported it
+    pub stage: Stage,
+    /// The analyzer within the stage (e.g. "workflow", "scopes")
+    pub analyzer: String,
+    /// The mapping keys leading to the diagnostic's line, joined with `.`
+    /// (e.g. "main.steps.init.assign")
+    pub schema_path: String,
+}
+
+impl Provenance {
+    /// Describe a diagnostic reported by `analyzer` in `stage`
+    pub fn new(diagnostic: &Diagnostic, stage: Stage, analyzer: &str, lines: &[&str]) -> Self {
+        let rule = match &diagnostic.code {
+            Some(NumberOrString::String(code)) => code.clone(),
+            Some(NumberOrString::Number(code)) => code.to_string(),
+            None => String::new(),
+        };
+        let line = diagnostic.range.start.line as usize;
+        Self {
+            rule,
+            stage,
+            analyzer: analyzer.to_string(),
+            schema_path: if line < lines.len() {
+                key_path(lines, line)
+            } else {
+                String::new()
+            },
+        }
+    }
+
+    /// Read the provenance attached to a diagnostic
+    pub fn of(diagnostic: &Diagnostic) -> Option<Self> {
+        serde_json::from_value(diagnostic.data.clone()?).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::{analyze, AnalysisOptions};
+
+    #[test]
+    fn test_diagnostics_carry_provenance() {
+        let text = "main:\n  steps:\n    - init:\n        assign:\n          - 5\n    - done:\n        return: [1\n";
+        let diagnostics = analyze(text, &AnalysisOptions::default()).diagnostics;
+        let provenance = Provenance::of(&diagnostics[0]).unwrap();
+        assert_eq!(provenance.stage, Stage::Parse);
+        assert_eq!(provenance.analyzer, "yaml");
+
+        let text = "main:\n  steps:\n    - init:\n        assign:\n          - 5\n";
+        let diagnostics = analyze(text, &AnalysisOptions::default()).diagnostics;
+        assert_eq!(
+            Provenance::of(&diagnostics[0]),
+            Some(Provenance {
+                rule: "workflow-structure".to_string(),
+                stage: Stage::Structure,
+                analyzer: "workflow".to_string(),
+                schema_path: "main.steps.init.assign".to_string(),
+            })
+        );
+    }
+}
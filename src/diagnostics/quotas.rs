@@ -0,0 +1,214 @@
+//! GCP Workflows quota limits
+//!
+//! Workflows rejects a deployment whose definition has too many steps, an
+//! expression longer than it accepts or steps nested too deeply. The limits
+//! are configurable, so a template can be held to tighter ones than the
+//! service enforces; a limit of 0 turns its check off.
+
+use super::yaml_errors::{DiagnosticCode, DiagnosticCollector};
+use crate::parser::{ExpressionKind, ExpressionMap, Outline};
+
+/// Steps a workflow definition may have, subworkflows included
+pub const DEFAULT_MAX_STEPS: usize = 100_000;
+
+/// Characters a `$${}` expression body may have
+pub const DEFAULT_MAX_EXPRESSION_LENGTH: usize = 400;
+
+/// Levels of steps nested inside other steps, top-level steps counting as 1
+pub const DEFAULT_MAX_NESTING_DEPTH: usize = 20;
+
+/// Thresholds checked by [`validate_quotas`]; 0 turns a check off
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuotaLimits {
+    pub steps: usize,
+    pub expression_length: usize,
+    pub nesting_depth: usize,
+}
+
+impl Default for QuotaLimits {
+    fn default() -> Self {
+        Self {
+            steps: DEFAULT_MAX_STEPS,
+            expression_length: DEFAULT_MAX_EXPRESSION_LENGTH,
+            nesting_depth: DEFAULT_MAX_NESTING_DEPTH,
+        }
+    }
+}
+
+/// Warn where a document goes past one of the quota limits
+///
+/// Steps are found by scanning lines, so `text` must be the document as
+/// written.
+pub fn validate_quotas(
+    text: &str,
+    expressions: &ExpressionMap,
+    limits: &QuotaLimits,
+    collector: &mut DiagnosticCollector,
+) {
+    let outline = Outline::parse(text);
+
+    if limits.steps > 0 {
+        if let Some(step) = outline.steps.get(limits.steps) {
+            collector.add_workflow_warning_with_range(
+                format!(
+                    "Workflow has {} steps, more than the limit of {}",
+                    outline.steps.len(),
+                    limits.steps
+                ),
+                step.line,
+                step.column,
+                step.line,
+                step.column + step.name.chars().count() as u32,
+                DiagnosticCode::QuotaExceeded,
+            );
+        }
+    }
+
+    if limits.nesting_depth > 0 {
+        // End lines of the steps enclosing the current one
+        let mut enclosing: Vec<u32> = Vec::new();
+        for step in &outline.steps {
+            while enclosing.last().is_some_and(|&end| end < step.line) {
+                enclosing.pop();
+            }
+            enclosing.push(step.end_line);
+            // Only the first step past the limit on each path is reported
+            if enclosing.len() == limits.nesting_depth + 1 {
+                collector.add_workflow_warning_with_range(
+                    format!(
+                        "Step '{}' is nested {} levels deep, more than the limit of {}",
+                        step.name,
+                        enclosing.len(),
+                        limits.nesting_depth
+                    ),
+                    step.line,
+                    step.column,
+                    step.line,
+                    step.column + step.name.chars().count() as u32,
+                    DiagnosticCode::QuotaExceeded,
+                );
+            }
+        }
+    }
+
+    if limits.expression_length > 0 {
+        for expr in &expressions.expressions {
+            if expr.kind != ExpressionKind::Workflows {
+                continue;
+            }
+            let Some(body) = expr
+                .original
+                .strip_prefix("$${")
+                .and_then(|b| b.strip_suffix('}'))
+            else {
+                continue;
+            };
+            let length = body.chars().count();
+            if length <= limits.expression_length {
+                continue;
+            }
+            collector.add_workflow_warning_with_range(
+                format!(
+                    "Expression is {} characters long, more than the limit of {}",
+                    length, limits.expression_length
+                ),
+                expr.start_line,
+                expr.start_column,
+                expr.end_line,
+                expr.end_column,
+                DiagnosticCode::QuotaExceeded,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::preprocess_expressions;
+
+    fn diagnostics(text: &str, limits: QuotaLimits) -> Vec<(u32, String)> {
+        let (_, expressions) = preprocess_expressions(text);
+        let mut collector = DiagnosticCollector::new();
+        validate_quotas(text, &expressions, &limits, &mut collector);
+        collector
+            .into_diagnostics()
+            .into_iter()
+            .map(|d| (d.range.start.line, d.message))
+            .collect()
+    }
+
+    const TEXT: &str = r#"main:
+  steps:
+    - outer:
+        try:
+          steps:
+            - inner:
+                for:
+                  value: v
+                  in: [1, 2]
+                  steps:
+                    - deepest:
+                        assign:
+                          - x: $${"abcdef" + v}
+    - done:
+        return: x
+"#;
+
+    #[test]
+    fn test_within_limits() {
+        assert!(diagnostics(TEXT, QuotaLimits::default()).is_empty());
+    }
+
+    #[test]
+    fn test_step_count() {
+        let limits = QuotaLimits {
+            steps: 3,
+            ..QuotaLimits::default()
+        };
+        assert_eq!(
+            diagnostics(TEXT, limits),
+            vec![(
+                13,
+                "Workflow has 4 steps, more than the limit of 3".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_nesting_depth() {
+        let limits = QuotaLimits {
+            nesting_depth: 2,
+            ..QuotaLimits::default()
+        };
+        assert_eq!(
+            diagnostics(TEXT, limits),
+            vec![(
+                10,
+                "Step 'deepest' is nested 3 levels deep, more than the limit of 2".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_expression_length() {
+        let limits = QuotaLimits {
+            expression_length: 10,
+            ..QuotaLimits::default()
+        };
+        assert_eq!(
+            diagnostics(TEXT, limits),
+            vec![(
+                12,
+                "Expression is 12 characters long, more than the limit of 10".to_string()
+            )]
+        );
+
+        let off = QuotaLimits {
+            steps: 0,
+            expression_length: 0,
+            nesting_depth: 0,
+        };
+        assert!(diagnostics(TEXT, off).is_empty());
+    }
+}
@@ -0,0 +1,273 @@
+//! Resource name lint for connector arguments
+//!
+//! Checks literal project IDs, locations and resource names passed to
+//! `googleapis.*` connectors against the basic Google Cloud formats, so a
+//! malformed `projects/{id}/locations/{loc}` string or a Windows-style path
+//! is caught before deployment. Values containing expressions are skipped.
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde_yaml::Value;
+
+use super::yaml_errors::{DiagnosticCode, DiagnosticCollector};
+
+lazy_static! {
+    /// Project ID: 6-30 lowercase letters, digits and hyphens, starting with
+    /// a letter and not ending with a hyphen; or a numeric project number
+    static ref PROJECT_ID_RE: Regex =
+        Regex::new(r"^(?:[a-z][a-z0-9-]{4,28}[a-z0-9]|\d+)$").unwrap();
+    /// Location: `global`, a multi-region like `us`, `nam5` or
+    /// `nam-eur-asia1`, or a region/zone like `europe-west1` or
+    /// `us-central1-a`
+    static ref LOCATION_RE: Regex =
+        Regex::new(r"^(?:[a-z]+\d*|[a-z]+(?:-[a-z]+)+\d+(?:-[a-z])?)$").unwrap();
+}
+
+/// Argument keys holding a project ID
+const PROJECT_KEYS: &[&str] = &["project", "projectId", "project_id"];
+
+/// Argument keys holding a location
+const LOCATION_KEYS: &[&str] = &["location", "region"];
+
+/// Argument keys holding a resource name or object path
+const PATH_KEYS: &[&str] = &["name", "parent", "resource", "object", "bucket", "path"];
+
+/// Warn about malformed literal resource names in `googleapis.*` call args
+pub fn validate_resource_names(value: &Value, text: &str, collector: &mut DiagnosticCollector) {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut cursor = 0;
+    walk(value, &lines, &mut cursor, collector);
+}
+
+/// Find connector calls anywhere below `value`, in document order
+fn walk(value: &Value, lines: &[&str], cursor: &mut usize, collector: &mut DiagnosticCollector) {
+    match value {
+        Value::Mapping(mapping) => {
+            let is_connector = mapping
+                .get("call")
+                .and_then(Value::as_str)
+                .is_some_and(|call| call.starts_with("googleapis."));
+            for (key, val) in mapping {
+                if is_connector && key.as_str() == Some("args") {
+                    check_args(val, lines, cursor, collector);
+                } else {
+                    walk(val, lines, cursor, collector);
+                }
+            }
+        }
+        Value::Sequence(items) => {
+            for item in items {
+                walk(item, lines, cursor, collector);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Check the string arguments of a connector call; what's nested in a
+/// request body follows the API's own schema, so it is left alone
+fn check_args(
+    args: &Value,
+    lines: &[&str],
+    cursor: &mut usize,
+    collector: &mut DiagnosticCollector,
+) {
+    let Some(mapping) = args.as_mapping() else {
+        return;
+    };
+    for (key, val) in mapping {
+        let Some(key) = key.as_str() else {
+            continue;
+        };
+        match val {
+            Value::String(s) if !s.contains("__EXPR_") => {
+                if let Some(problem) = problem(key, s) {
+                    let (line, column) = locate(lines, cursor, key, s);
+                    collector.add_workflow_warning_with_range(
+                        problem,
+                        line,
+                        column,
+                        line,
+                        column + s.chars().count() as u32,
+                        DiagnosticCode::InvalidResourceName,
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// What is wrong with a literal argument, if anything
+fn problem(key: &str, value: &str) -> Option<String> {
+    if PROJECT_KEYS.contains(&key) && !PROJECT_ID_RE.is_match(value) {
+        return Some(format!(
+            "'{}' is not a valid project ID: expected 6-30 lowercase letters, digits or hyphens, starting with a letter",
+            value
+        ));
+    }
+    if LOCATION_KEYS.contains(&key) && !LOCATION_RE.is_match(value) {
+        return Some(format!(
+            "'{}' is not a valid location, e.g. 'us-central1' or 'global'",
+            value
+        ));
+    }
+    if PATH_KEYS.contains(&key) || value.starts_with("projects/") {
+        if value.contains('\\') {
+            return Some(format!(
+                "'{}' uses backslashes; resource names and object paths use '/'",
+                value
+            ));
+        }
+        if value.chars().any(char::is_whitespace) {
+            return Some(format!(
+                "'{}' contains whitespace, which is not valid in a resource name",
+                value
+            ));
+        }
+    }
+    if value.starts_with("projects/") {
+        return resource_name_problem(value);
+    }
+    None
+}
+
+/// Check a `projects/{id}/locations/{loc}/...` name: collection and ID
+/// segments alternate, none empty, with a valid project and location
+fn resource_name_problem(name: &str) -> Option<String> {
+    let segments: Vec<&str> = name.split('/').collect();
+    let malformed = || {
+        Some(format!(
+            "Malformed resource name '{}': expected 'projects/{{project}}/locations/{{location}}/...'",
+            name
+        ))
+    };
+    if segments.iter().any(|s| s.is_empty()) {
+        return malformed();
+    }
+    for pair in segments.chunks(2) {
+        match pair {
+            ["projects", id] if !PROJECT_ID_RE.is_match(id) => {
+                return Some(format!("'{}' is not a valid project ID in '{}'", id, name))
+            }
+            ["locations", location] if !LOCATION_RE.is_match(location) => {
+                return Some(format!(
+                    "'{}' is not a valid location in '{}'",
+                    location, name
+                ))
+            }
+            [_] => return malformed(),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Line and column of `key: value` at or after the cursor, advancing it
+fn locate(lines: &[&str], cursor: &mut usize, key: &str, value: &str) -> (u32, u32) {
+    let pattern = format!("{}:", key);
+    let found = lines
+        .iter()
+        .enumerate()
+        .skip(*cursor)
+        .find_map(|(i, line)| {
+            let trimmed = line.trim_start();
+            let trimmed = trimmed.strip_prefix("- ").unwrap_or(trimmed);
+            if !trimmed.starts_with(&pattern) {
+                return None;
+            }
+            let byte = line.find(value)?;
+            Some((i, line[..byte].chars().count()))
+        });
+    match found {
+        Some((line, column)) => {
+            *cursor = line + 1;
+            (line as u32, column as u32)
+        }
+        None => (*cursor as u32, 0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(yaml: &str) -> Vec<(String, u32, u32)> {
+        let value: Value = serde_yaml::from_str(yaml).unwrap();
+        let mut collector = DiagnosticCollector::new();
+        validate_resource_names(&value, yaml, &mut collector);
+        collector
+            .into_diagnostics()
+            .into_iter()
+            .map(|d| (d.message, d.range.start.line, d.range.start.character))
+            .collect()
+    }
+
+    #[test]
+    fn test_valid_names() {
+        let yaml = r#"
+main:
+  steps:
+    - create:
+        call: googleapis.run.v2.projects.locations.jobs.run
+        args:
+          name: projects/my-project-123/locations/europe-west1/jobs/nightly
+          location: us-central1
+    - database:
+        call: googleapis.firestore.v1.projects.databases.create
+        args:
+          parent: projects/my-project-123
+          body:
+            locationId: nam5
+            name: Not A Resource Name
+    - spanner:
+        call: googleapis.spanner.v1.projects.instances.create
+        args:
+          location: eur3
+          region: nam-eur-asia1
+    - read:
+        call: googleapis.storage.v1.objects.get
+        args:
+          bucket: my-bucket
+          object: reports/2024/summary.json
+          project: __EXPR_000__
+"#;
+        assert!(messages(yaml).is_empty());
+    }
+
+    #[test]
+    fn test_malformed_names() {
+        let yaml = r#"
+main:
+  steps:
+    - create:
+        call: googleapis.run.v2.projects.locations.jobs.run
+        args:
+          name: projects/My_Project/locations/europe-west1/jobs/nightly
+          parent: projects/my-project-123/locations//jobs
+    - read:
+        call: googleapis.storage.v1.objects.get
+        args:
+          object: reports\2024\summary.json
+          region: Europe West
+    - other:
+        call: http.get
+        args:
+          name: projects//whatever
+"#;
+        let found = messages(yaml);
+        assert_eq!(found.len(), 4);
+        assert_eq!(
+            found[0],
+            (
+                "'My_Project' is not a valid project ID in 'projects/My_Project/locations/europe-west1/jobs/nightly'".to_string(),
+                6,
+                16
+            )
+        );
+        assert!(found[1].0.starts_with("Malformed resource name"));
+        assert_eq!(found[1].1, 7);
+        assert!(found[2].0.contains("uses backslashes"));
+        assert!(found[3].0.contains("not a valid location"));
+    }
+}
@@ -0,0 +1,526 @@
+//! Variable scope analysis
+//!
+//! Walks each workflow's steps in execution order and reports every variable
+//! binding — params, `assign` entries, call `result`s, loop variables and
+//! `except` error variables — together with the scope it happens in. Lints
+//! that reason about variables are built on top of [`walk_assignments`].
+
+use serde_yaml::Value;
+use tower_lsp::lsp_types::Range;
+
+use super::yaml_errors::{DiagnosticCode, DiagnosticCollector};
+use crate::parser::{without_unparsed_directives, SpannedValue};
+
+/// How a variable was bound
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum BindingKind {
+    Param,
+    Assign,
+    Result,
+    LoopVariable,
+    ErrorVariable,
+}
+
+/// A single variable binding
+#[derive(Debug, Clone)]
+pub(super) struct Binding<'a> {
+    /// The variable name (the root of `name.field` or `name[0]` targets)
+    pub name: String,
+    /// Whether the whole variable is replaced, rather than a field or element
+    pub whole: bool,
+    /// Where the name is written: the `assign` key, or the value of `result`,
+    /// `value`, `index` or `as`
+    pub range: Range,
+    /// The assigned value, for `assign` entries
+    pub value: Option<&'a Value>,
+    pub kind: BindingKind,
+    /// Workflow the binding belongs to
+    pub workflow: &'a str,
+    /// Whether the name was already bound in an enclosing block
+    pub bound_outside: bool,
+    /// Whether the binding happens in a parallel branch or iteration, the name
+    /// comes from outside the `parallel` step, and it is not listed in `shared`
+    pub unshared_in_parallel: bool,
+}
+
+/// One block of the scope stack
+#[derive(Debug, Default)]
+struct Frame {
+    variables: Vec<String>,
+    /// Variables declared `shared` when this frame is a parallel branch
+    shared: Option<Vec<String>>,
+}
+
+/// Walks a workflow document and reports bindings to a callback
+///
+/// The parsed `Value` says what is bound; the [`SpannedValue`] of the same
+/// text, walked alongside it, says where.
+struct Walker<'a, 'f> {
+    frames: Vec<Frame>,
+    workflow: &'a str,
+    visit: &'f mut dyn FnMut(Binding<'a>),
+}
+
+/// Visit every variable binding in a parsed workflow document, in order
+pub(super) fn walk_assignments<'a>(
+    value: &'a Value,
+    text: &str,
+    visit: &mut dyn FnMut(Binding<'a>),
+) {
+    let Some(mapping) = value.as_mapping() else {
+        return;
+    };
+    let root = SpannedValue::parse(&without_unparsed_directives(text)).unwrap_or_default();
+    let mut walker = Walker {
+        frames: Vec::new(),
+        workflow: "",
+        visit,
+    };
+
+    for (key, body) in mapping {
+        let (Some(name), Some(body)) = (key.as_str(), body.as_mapping()) else {
+            continue;
+        };
+        let Some(steps) = body.get("steps") else {
+            continue;
+        };
+        let node = root.at_key(name);
+        walker.workflow = name;
+        walker.frames = vec![Frame::default()];
+
+        if let Some(params) = body.get("params").and_then(Value::as_sequence) {
+            let params_node = node.at_key("params");
+            for (i, param) in params.iter().enumerate() {
+                let param_node = params_node.at_index(i);
+                let (param_name, range) = match param {
+                    Value::String(s) => (Some(s.as_str()), param_node.range),
+                    Value::Mapping(m) => (
+                        m.keys().next().and_then(Value::as_str),
+                        param_node
+                            .entries()
+                            .first()
+                            .map_or_else(|| param_node.head(), |entry| entry.key.range),
+                    ),
+                    _ => (None, param_node.range),
+                };
+                if let Some(param_name) = param_name {
+                    walker.bind(param_name, range, None, BindingKind::Param);
+                }
+            }
+        }
+        walker.steps(steps, node.at_key("steps"));
+    }
+}
+
+/// Range of the value at `key`, or the node's head when it isn't written
+fn value_range(node: &SpannedValue, key: &str) -> Range {
+    node.entry(key)
+        .map_or_else(|| node.head(), |entry| entry.value.range)
+}
+
+impl<'a> Walker<'a, '_> {
+    /// Record a binding and add the name to the innermost frame
+    fn bind(&mut self, target: &str, range: Range, value: Option<&'a Value>, kind: BindingKind) {
+        let name = root_name(target).to_string();
+        let (current, enclosing) = self.frames.split_last().expect("walker has a frame");
+        let bound_outside = enclosing.iter().any(|f| f.variables.contains(&name));
+
+        let parallel = self.frames.iter().rposition(|f| f.shared.is_some());
+        let unshared_in_parallel = parallel.is_some_and(|p| {
+            let shared = self.frames[p].shared.as_deref().unwrap_or_default();
+            self.frames[..p].iter().any(|f| f.variables.contains(&name)) && !shared.contains(&name)
+        });
+
+        let is_new = !current.variables.contains(&name);
+        (self.visit)(Binding {
+            name: name.clone(),
+            whole: name == target.trim(),
+            range,
+            value,
+            kind,
+            workflow: self.workflow,
+            bound_outside,
+            unshared_in_parallel,
+        });
+        if is_new && !bound_outside {
+            self.frames.last_mut().unwrap().variables.push(name);
+        }
+    }
+
+    /// Run `f` inside a new frame, discarding the names bound in it
+    fn nested(&mut self, frame: Frame, f: impl FnOnce(&mut Self)) {
+        self.frames.push(frame);
+        f(self);
+        self.frames.pop();
+    }
+
+    /// Walk a `steps` list written at `node`
+    fn steps(&mut self, steps: &'a Value, node: &SpannedValue) {
+        let Some(steps) = steps.as_sequence() else {
+            return;
+        };
+        for (i, step) in steps.iter().enumerate() {
+            let Some((name, body)) = step.as_mapping().and_then(|m| m.iter().next()) else {
+                continue;
+            };
+            let step_node = node.at_index(i);
+            let body_node = name.as_str().map_or(step_node, |n| step_node.at_key(n));
+            self.step_body(body, body_node);
+        }
+    }
+
+    /// Walk the body of a single step, written at `node`
+    fn step_body(&mut self, body: &'a Value, node: &SpannedValue) {
+        let Some(body) = body.as_mapping() else {
+            return;
+        };
+
+        if let Some(assign) = body.get("assign").and_then(Value::as_sequence) {
+            let assign_node = node.at_key("assign");
+            for (i, entry) in assign.iter().enumerate() {
+                let Some((target, value)) = entry.as_mapping().and_then(|m| m.iter().next()) else {
+                    continue;
+                };
+                let Some(target) = target.as_str() else {
+                    continue;
+                };
+                let range = assign_node.at_index(i).key_range(target);
+                self.bind(target, range, Some(value), BindingKind::Assign);
+            }
+        }
+
+        if let Some(result) = body.get("result").and_then(Value::as_str) {
+            self.bind(
+                result,
+                value_range(node, "result"),
+                None,
+                BindingKind::Result,
+            );
+        }
+
+        if let Some(for_body) = body.get("for") {
+            self.for_loop(for_body, node.at_key("for"), None);
+        }
+
+        if let Some(parallel) = body.get("parallel").and_then(Value::as_mapping) {
+            let parallel_node = node.at_key("parallel");
+            let shared: Vec<String> = parallel
+                .get("shared")
+                .and_then(Value::as_sequence)
+                .map(|names| {
+                    names
+                        .iter()
+                        .filter_map(Value::as_str)
+                        .map(String::from)
+                        .collect()
+                })
+                .unwrap_or_default();
+
+            if let Some(branches) = parallel.get("branches").and_then(Value::as_sequence) {
+                let branches_node = parallel_node.at_key("branches");
+                for (i, branch) in branches.iter().enumerate() {
+                    let Some((name, branch_body)) =
+                        branch.as_mapping().and_then(|m| m.iter().next())
+                    else {
+                        continue;
+                    };
+                    let branch_node = branches_node.at_index(i);
+                    let branch_node = name.as_str().map_or(branch_node, |n| branch_node.at_key(n));
+                    let frame = Frame {
+                        variables: Vec::new(),
+                        shared: Some(shared.clone()),
+                    };
+                    self.nested(frame, |w| {
+                        if let Some(steps) = branch_body.get("steps") {
+                            w.steps(steps, branch_node.at_key("steps"));
+                        }
+                    });
+                }
+            }
+            if let Some(for_body) = parallel.get("for") {
+                self.for_loop(for_body, parallel_node.at_key("for"), Some(shared));
+            }
+        }
+
+        if let Some(try_body) = body.get("try") {
+            let try_node = node.at_key("try");
+            match try_body.get("steps") {
+                Some(steps) => self.steps(steps, try_node.at_key("steps")),
+                None => self.step_body(try_body, try_node),
+            }
+        }
+
+        if let Some(except) = body.get("except").and_then(Value::as_mapping) {
+            let except_node = node.at_key("except");
+            self.nested(Frame::default(), |w| {
+                if let Some(error) = except.get("as").and_then(Value::as_str) {
+                    let range = value_range(except_node, "as");
+                    w.bind(error, range, None, BindingKind::ErrorVariable);
+                }
+                if let Some(steps) = except.get("steps") {
+                    w.steps(steps, except_node.at_key("steps"));
+                }
+            });
+        }
+
+        if let Some(conditions) = body.get("switch").and_then(Value::as_sequence) {
+            let switch_node = node.at_key("switch");
+            for (i, condition) in conditions.iter().enumerate() {
+                if let Some(steps) = condition.get("steps") {
+                    self.steps(steps, switch_node.at_index(i).at_key("steps"));
+                }
+            }
+        }
+
+        if let Some(steps) = body.get("steps") {
+            self.steps(steps, node.at_key("steps"));
+        }
+    }
+
+    /// Walk a `for` loop body written at `node`; `shared` is set for parallel
+    /// iterations
+    fn for_loop(&mut self, for_body: &'a Value, node: &SpannedValue, shared: Option<Vec<String>>) {
+        let Some(for_body) = for_body.as_mapping() else {
+            return;
+        };
+        self.nested(Frame::default(), |w| {
+            for key in ["value", "index"] {
+                if let Some(name) = for_body.get(key).and_then(Value::as_str) {
+                    w.bind(
+                        name,
+                        value_range(node, key),
+                        None,
+                        BindingKind::LoopVariable,
+                    );
+                }
+            }
+            // Loop variables are private to each iteration; a parallel loop's
+            // body additionally may only write `shared` outer variables
+            let frame = Frame {
+                variables: Vec::new(),
+                shared,
+            };
+            w.nested(frame, |w| {
+                if let Some(steps) = for_body.get("steps") {
+                    w.steps(steps, node.at_key("steps"));
+                }
+            });
+        });
+    }
+}
+
+/// The variable a target like `a.b[0]` or `a["k"]` writes to
+fn root_name(target: &str) -> &str {
+    let end = target.find(['.', '[']).unwrap_or(target.len());
+    target[..end].trim()
+}
+
+/// Warn about assignments in parallel branches or loops that shadow an
+/// outer variable instead of updating it
+///
+/// - Inside `parallel`, writing an outer variable requires listing it in
+///   `shared`; otherwise the write is lost when the branch ends.
+/// - A loop's `value`/`index` variable hides an outer variable of the same
+///   name for the duration of the loop.
+/// - An `except` block's `as` variable likewise hides an outer variable for
+///   the duration of the block.
+pub fn validate_scopes(value: &Value, text: &str, collector: &mut DiagnosticCollector) {
+    walk_assignments(value, text, &mut |binding| match binding.kind {
+        BindingKind::Assign | BindingKind::Result if binding.unshared_in_parallel => {
+            collector.add_workflow_warning_in(
+                format!(
+                    "Variable '{}' is assigned in a parallel branch but not declared in 'shared'; the outer value will not be updated",
+                    binding.name
+                ),
+                binding.range,
+                DiagnosticCode::VariableShadowing,
+            );
+        }
+        BindingKind::LoopVariable if binding.bound_outside => {
+            collector.add_workflow_warning_in(
+                format!(
+                    "Loop variable '{}' shadows an outer variable of the same name",
+                    binding.name
+                ),
+                binding.range,
+                DiagnosticCode::VariableShadowing,
+            );
+        }
+        BindingKind::ErrorVariable if binding.bound_outside => {
+            collector.add_workflow_warning_in(
+                format!(
+                    "Error variable '{}' shadows an outer variable of the same name",
+                    binding.name
+                ),
+                binding.range,
+                DiagnosticCode::VariableShadowing,
+            );
+        }
+        _ => {}
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower_lsp::lsp_types::Position;
+
+    fn scope_diagnostics(yaml: &str) -> Vec<(u32, String)> {
+        let value: Value = serde_yaml::from_str(yaml).unwrap();
+        let mut collector = DiagnosticCollector::new();
+        validate_scopes(&value, yaml, &mut collector);
+        collector
+            .into_diagnostics()
+            .into_iter()
+            .map(|d| (d.range.start.line, d.message))
+            .collect()
+    }
+
+    #[test]
+    fn test_unshared_parallel_assignment() {
+        let yaml = r#"main:
+  steps:
+    - init:
+        assign:
+          - total: 0
+          - results: {}
+    - fanout:
+        parallel:
+          shared: [results]
+          branches:
+            - a:
+                steps:
+                  - set:
+                      assign:
+                        - total: 1
+                        - results.a: 1
+                        - local: 2
+"#;
+        let diagnostics = scope_diagnostics(yaml);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].0, 14);
+        assert!(diagnostics[0].1.starts_with("Variable 'total'"));
+    }
+
+    #[test]
+    fn test_reports_at_the_assignment_key() {
+        let yaml = r#"main:
+  steps:
+    - init:
+        assign:
+          - total: 0
+    - fanout:
+        parallel:
+          branches:
+            - a:
+                steps:
+                  - set:
+                      assign:
+                        - cfg:
+                            total: 5
+                        - total: 1
+"#;
+        let value: Value = serde_yaml::from_str(yaml).unwrap();
+        let mut collector = DiagnosticCollector::new();
+        validate_scopes(&value, yaml, &mut collector);
+        let ranges: Vec<Range> = collector
+            .into_diagnostics()
+            .into_iter()
+            .map(|d| d.range)
+            .collect();
+        assert_eq!(
+            ranges,
+            vec![Range::new(Position::new(14, 26), Position::new(14, 31))]
+        );
+    }
+
+    #[test]
+    fn test_parallel_for_and_result() {
+        let yaml = r#"main:
+  steps:
+    - init:
+        assign:
+          - response: null
+    - each:
+        parallel:
+          for:
+            value: item
+            in: [1, 2]
+            steps:
+              - get:
+                  call: http.get
+                  result: response
+"#;
+        let diagnostics = scope_diagnostics(yaml);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].0, 13);
+    }
+
+    #[test]
+    fn test_loop_variable_shadowing() {
+        let yaml = r#"main:
+  params: [item]
+  steps:
+    - loop:
+        for:
+          value: item
+          in: [1, 2]
+          steps:
+            - use:
+                assign:
+                  - item: 3
+"#;
+        let diagnostics = scope_diagnostics(yaml);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].0, 5);
+        assert!(diagnostics[0].1.starts_with("Loop variable 'item'"));
+    }
+
+    #[test]
+    fn test_error_variable_shadowing() {
+        let yaml = r#"main:
+  steps:
+    - init:
+        assign:
+          - err: null
+    - call_api:
+        try:
+          call: http.get
+        except:
+          as: err
+          steps:
+            - fail:
+                raise: err
+"#;
+        let diagnostics = scope_diagnostics(yaml);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].0, 9);
+        assert!(diagnostics[0].1.starts_with("Error variable 'err'"));
+    }
+
+    #[test]
+    fn test_sequential_loop_may_update_outer() {
+        let yaml = r#"main:
+  steps:
+    - init:
+        assign:
+          - sum: 0
+    - loop:
+        for:
+          value: v
+          in: [1, 2]
+          steps:
+            - add:
+                assign:
+                  - sum: 1
+"#;
+        assert!(scope_diagnostics(yaml).is_empty());
+    }
+
+    #[test]
+    fn test_root_name() {
+        assert_eq!(root_name("a"), "a");
+        assert_eq!(root_name("a.b.c"), "a");
+        assert_eq!(root_name("a[\"k\"]"), "a");
+    }
+}
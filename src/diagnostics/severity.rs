@@ -0,0 +1,147 @@
+//! Effective rule severity resolution
+//!
+//! Explains, for a given line, which severity each rule would be reported
+//! with and why, so editor extensions can show the reasoning behind a squiggle.
+//! Inline suppressions win over user settings, which win over the defaults;
+//! a `severityOverrides` cap for the file then lowers whatever is stricter.
+
+use serde::Serialize;
+use tower_lsp::lsp_types::DiagnosticSeverity;
+
+use super::suppressions::{SuppressionScope, Suppressions};
+use super::yaml_errors::DiagnosticCode;
+use crate::settings::{RuleLevel, Settings};
+
+/// Why a rule has its effective severity
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SeveritySource {
+    /// The rule's built-in default
+    Default,
+    /// A severity override in the user's settings
+    Settings,
+    /// Suppressed by a file-level `disable` comment
+    FileSuppression,
+    /// Suppressed by a `disable-line` or `disable-next-line` comment
+    LineSuppression,
+    /// Lowered or turned off by a `severityOverrides` path in the workspace
+    /// configuration
+    PathOverride,
+}
+
+/// The effective severity of one rule at a position
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RuleSeverity {
+    /// The diagnostic code (e.g. "unknown-keyword")
+    pub code: String,
+    /// Severity the rule reports with when nothing overrides it
+    pub default_severity: DiagnosticSeverity,
+    /// Severity actually reported at this position, `None` if suppressed
+    pub effective_severity: Option<DiagnosticSeverity>,
+    /// What determined the effective severity
+    pub source: SeveritySource,
+}
+
+/// Resolve the effective severity of every rule on the given line of a file
+/// whose diagnostics are capped at `max_severity`
+pub fn effective_severities(
+    suppressions: &Suppressions,
+    settings: &Settings,
+    max_severity: Option<RuleLevel>,
+    line: u32,
+) -> Vec<RuleSeverity> {
+    DiagnosticCode::ALL
+        .iter()
+        .map(|code| {
+            let default_severity = code.default_severity();
+            let (effective_severity, source) = match suppressions.scope(code.as_str(), line) {
+                Some(SuppressionScope::Line) => (None, SeveritySource::LineSuppression),
+                Some(SuppressionScope::File) => (None, SeveritySource::FileSuppression),
+                None => match settings.rule_level(code.as_str()) {
+                    Some(level) => (level.severity(), SeveritySource::Settings),
+                    None => (Some(default_severity), SeveritySource::Default),
+                },
+            };
+            // LSP severities grow less severe as their value grows
+            let (effective_severity, source) = match (effective_severity, max_severity) {
+                (Some(severity), Some(max)) if max.severity().is_none_or(|cap| severity < cap) => {
+                    (max.severity(), SeveritySource::PathOverride)
+                }
+                _ => (effective_severity, source),
+            };
+            RuleSeverity {
+                code: code.as_str().to_string(),
+                default_severity,
+                effective_severity,
+                source,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find<'a>(rules: &'a [RuleSeverity], code: &str) -> &'a RuleSeverity {
+        rules.iter().find(|r| r.code == code).unwrap()
+    }
+
+    #[test]
+    fn test_defaults_without_suppressions() {
+        let rules =
+            effective_severities(&Suppressions::parse("a: 1"), &Settings::default(), None, 0);
+        assert_eq!(rules.len(), DiagnosticCode::ALL.len());
+        let rule = find(&rules, "unknown-keyword");
+        assert_eq!(rule.effective_severity, Some(DiagnosticSeverity::HINT));
+        assert_eq!(rule.source, SeveritySource::Default);
+    }
+
+    #[test]
+    fn test_line_suppression_wins() {
+        let text = "# yaml-tftpl-lsp disable: workflow-structure\n# yaml-tftpl-lsp disable-next-line: unknown-keyword\nfoo: 1";
+        let settings = Settings::from_value(serde_json::json!({
+            "rules": { "unknown-keyword": "error", "yaml-syntax": "warning" }
+        }));
+        let rules = effective_severities(&Suppressions::parse(text), &settings, None, 2);
+
+        let rule = find(&rules, "unknown-keyword");
+        assert_eq!(rule.effective_severity, None);
+        assert_eq!(rule.source, SeveritySource::LineSuppression);
+
+        let rule = find(&rules, "workflow-structure");
+        assert_eq!(rule.source, SeveritySource::FileSuppression);
+
+        let rule = find(&rules, "yaml-syntax");
+        assert_eq!(rule.effective_severity, Some(DiagnosticSeverity::WARNING));
+        assert_eq!(rule.source, SeveritySource::Settings);
+    }
+
+    #[test]
+    fn test_path_override_caps_severity() {
+        let settings = Settings::from_value(serde_json::json!({
+            "rules": { "unknown-keyword": "error" }
+        }));
+        let suppressions = Suppressions::parse("a: 1");
+        let rules = effective_severities(&suppressions, &settings, Some(RuleLevel::Warning), 0);
+
+        let rule = find(&rules, "unknown-keyword");
+        assert_eq!(rule.effective_severity, Some(DiagnosticSeverity::WARNING));
+        assert_eq!(rule.source, SeveritySource::PathOverride);
+
+        let rule = find(&rules, "yaml-syntax");
+        assert_eq!(rule.effective_severity, Some(DiagnosticSeverity::WARNING));
+        assert_eq!(rule.source, SeveritySource::PathOverride);
+
+        // Already below the cap
+        let rule = find(&rules, "unused-variable");
+        assert_eq!(rule.effective_severity, Some(DiagnosticSeverity::HINT));
+        assert_eq!(rule.source, SeveritySource::Default);
+
+        let rules = effective_severities(&suppressions, &settings, Some(RuleLevel::Off), 0);
+        let rule = find(&rules, "unknown-keyword");
+        assert_eq!(rule.effective_severity, None);
+        assert_eq!(rule.source, SeveritySource::PathOverride);
+    }
+}
@@ -0,0 +1,174 @@
+//! Inline diagnostic suppression comments
+//!
+//! Templates can silence rules with comments:
+//!
+//! ```yaml
+//! # yaml-tftpl-lsp disable: unknown-keyword           (whole file)
+//! # yaml-tftpl-lsp disable-next-line: workflow-structure
+//! key: value  # yaml-tftpl-lsp disable-line
+//! ```
+//!
+//! Omitting the code list suppresses every rule in scope. A `stage:<name>`
+//! entry, such as `stage:structure`, suppresses every rule of that pipeline
+//! stage.
+
+use std::collections::HashMap;
+
+use tower_lsp::lsp_types::{Diagnostic, NumberOrString};
+
+use super::provenance::Provenance;
+
+/// Marker that introduces a suppression directive inside a comment
+const DIRECTIVE: &str = "yaml-tftpl-lsp";
+
+/// Which rules a directive applies to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuleSet {
+    /// Every rule
+    All,
+    /// Only the listed diagnostic codes
+    Codes(Vec<String>),
+}
+
+impl RuleSet {
+    fn contains(&self, code: &str) -> bool {
+        match self {
+            RuleSet::All => true,
+            RuleSet::Codes(codes) => codes.iter().any(|c| c == code),
+        }
+    }
+}
+
+/// Where a suppression for a given rule and line comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SuppressionScope {
+    /// A `disable` directive anywhere in the file
+    File,
+    /// A `disable-line` or `disable-next-line` directive for this line
+    Line,
+}
+
+/// Suppression directives parsed from a document
+#[derive(Debug, Default)]
+pub struct Suppressions {
+    file: Vec<RuleSet>,
+    lines: HashMap<u32, Vec<RuleSet>>,
+}
+
+impl Suppressions {
+    /// Parse every suppression directive in the document
+    pub fn parse(text: &str) -> Self {
+        let mut suppressions = Self::default();
+
+        for (line_no, line) in text.lines().enumerate() {
+            let Some(comment_start) = line.find('#') else {
+                continue;
+            };
+            let comment = line[comment_start + 1..].trim();
+            let Some(rest) = comment.strip_prefix(DIRECTIVE) else {
+                continue;
+            };
+            let rest = rest.trim_start();
+            let (kind, codes) = match rest.split_once(':') {
+                Some((kind, codes)) => (kind.trim(), codes),
+                None => (rest.trim(), ""),
+            };
+
+            let codes: Vec<String> = codes
+                .split(',')
+                .map(|c| c.trim().to_string())
+                .filter(|c| !c.is_empty())
+                .collect();
+            let rules = if codes.is_empty() {
+                RuleSet::All
+            } else {
+                RuleSet::Codes(codes)
+            };
+
+            let line_no = line_no as u32;
+            match kind {
+                "disable" => suppressions.file.push(rules),
+                "disable-line" => suppressions.lines.entry(line_no).or_default().push(rules),
+                "disable-next-line" => suppressions
+                    .lines
+                    .entry(line_no + 1)
+                    .or_default()
+                    .push(rules),
+                _ => {}
+            }
+        }
+
+        suppressions
+    }
+
+    /// Check whether the given rule is suppressed on a line, and by what
+    pub fn scope(&self, code: &str, line: u32) -> Option<SuppressionScope> {
+        if self
+            .lines
+            .get(&line)
+            .is_some_and(|rules| rules.iter().any(|r| r.contains(code)))
+        {
+            Some(SuppressionScope::Line)
+        } else if self.file.iter().any(|r| r.contains(code)) {
+            Some(SuppressionScope::File)
+        } else {
+            None
+        }
+    }
+
+    /// Check whether a diagnostic is suppressed at its start line, by its
+    /// code or by the stage that reported it
+    pub fn is_suppressed(&self, diagnostic: &Diagnostic) -> bool {
+        let code = match &diagnostic.code {
+            Some(NumberOrString::String(code)) => code.as_str(),
+            _ => return false,
+        };
+        let line = diagnostic.range.start.line;
+        if self.scope(code, line).is_some() {
+            return true;
+        }
+        Provenance::of(diagnostic).is_some_and(|p| {
+            self.scope(&format!("stage:{}", p.stage.as_str()), line)
+                .is_some()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_next_line() {
+        let text = "# yaml-tftpl-lsp disable-next-line: unknown-keyword\nfoo: bar";
+        let s = Suppressions::parse(text);
+        assert_eq!(s.scope("unknown-keyword", 1), Some(SuppressionScope::Line));
+        assert_eq!(s.scope("unknown-keyword", 0), None);
+        assert_eq!(s.scope("yaml-syntax", 1), None);
+    }
+
+    #[test]
+    fn test_parse_same_line_all_rules() {
+        let s = Suppressions::parse("foo: bar # yaml-tftpl-lsp disable-line");
+        assert_eq!(s.scope("anything", 0), Some(SuppressionScope::Line));
+    }
+
+    #[test]
+    fn test_parse_file_level_multiple_codes() {
+        let s = Suppressions::parse(
+            "a: 1\n# yaml-tftpl-lsp disable: unknown-keyword, workflow-structure",
+        );
+        assert_eq!(s.scope("unknown-keyword", 0), Some(SuppressionScope::File));
+        assert_eq!(
+            s.scope("workflow-structure", 9),
+            Some(SuppressionScope::File)
+        );
+        assert_eq!(s.scope("yaml-syntax", 0), None);
+    }
+
+    #[test]
+    fn test_unrelated_comments_ignored() {
+        let s = Suppressions::parse("# just a comment\n# yaml-tftpl-lsp unknown-directive");
+        assert_eq!(s.scope("yaml-syntax", 1), None);
+    }
+}
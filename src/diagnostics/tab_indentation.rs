@@ -0,0 +1,42 @@
+//! Tabs in indentation
+//!
+//! YAML only allows spaces for indentation. libyaml rejects a leading tab
+//! with "found character that cannot start any token", which says nothing
+//! about tabs and is easy to miss in editors that render tabs as spaces, so
+//! tab-indented lines are reported here instead (see `parser::parse_yaml`).
+
+use crate::parser::lines::tab_indented_lines;
+
+use super::yaml_errors::{DiagnosticCode, DiagnosticCollector};
+
+/// Report every line indented with tabs
+pub fn validate_tab_indentation(text: &str, collector: &mut DiagnosticCollector) {
+    for (line_no, indent) in tab_indented_lines(text) {
+        collector.add_yaml_error_with_range(
+            "Tab used for indentation; YAML only allows spaces".to_string(),
+            line_no,
+            0,
+            line_no,
+            indent.chars().count() as u32,
+            DiagnosticCode::TabIndentation,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tab_indentation() {
+        let text = "a:\n\tb: 1\n  \tc: 2\nd: |\n  text\n  \tcontent\n\t\ne:\tf\n";
+        let mut collector = DiagnosticCollector::new();
+        validate_tab_indentation(text, &mut collector);
+        let found: Vec<(u32, u32)> = collector
+            .into_diagnostics()
+            .iter()
+            .map(|d| (d.range.start.line, d.range.end.character))
+            .collect();
+        assert_eq!(found, vec![(1, 1), (2, 3)]);
+    }
+}
@@ -0,0 +1,377 @@
+//! Template variables against the `templatefile()` calls that render them
+//!
+//! `templatefile()` fails when the template reads a variable its vars object
+//! doesn't pass, which today only shows at `terraform plan`. Variables passed
+//! but never read are harmless, but usually left over from a rename.
+//!
+//! A template variable sharing its name with a runtime variable the workflow
+//! assigns is also reported: `${name}` and `$${name}` then read different
+//! values, and which one was meant is easy to get backwards.
+
+use std::collections::{HashMap, HashSet};
+
+use serde_yaml::Value;
+use tower_lsp::lsp_types::{Position, Range};
+
+use super::scopes::walk_assignments;
+use super::yaml_errors::{DiagnosticCode, DiagnosticCollector};
+use crate::parser::expressions::Expression;
+use crate::parser::tokens::{tokenize, Token, TokenKind};
+use crate::parser::{ExpressionKind, ExpressionMap};
+use crate::terraform::TemplateCall;
+
+/// Names that are part of the expression language rather than variables
+const KEYWORDS: &[&str] = &[
+    "for", "in", "if", "else", "endif", "endfor", "true", "false", "null",
+];
+
+/// Terraform's own namespaces, which aren't template variables
+const NAMESPACES: &[&str] = &[
+    "var",
+    "local",
+    "module",
+    "path",
+    "data",
+    "terraform",
+    "each",
+    "count",
+    "self",
+];
+
+/// Warn about variables the template reads that a `templatefile()` call
+/// doesn't pass, and hint at those a call passes that the template never
+/// reads
+///
+/// Calls whose vars aren't an object literal are skipped.
+pub fn validate_template_vars(
+    text: &str,
+    expressions: &ExpressionMap,
+    calls: &[TemplateCall],
+    collector: &mut DiagnosticCollector,
+) {
+    let calls: Vec<(&TemplateCall, &[String])> = calls
+        .iter()
+        .filter_map(|call| Some((call, call.vars.as_deref()?)))
+        .collect();
+    if calls.is_empty() {
+        return;
+    }
+
+    let references = template_variables(expressions);
+    for (expr, name) in references.iter().filter(|(_, name)| !name.qualified) {
+        for (call, vars) in &calls {
+            if vars.iter().any(|var| var == name.name) {
+                continue;
+            }
+            collector.add_workflow_warning_with_range(
+                format!(
+                    "Template variable '{}' is not passed by the templatefile() call in {}",
+                    name.name,
+                    call.describe()
+                ),
+                expr.start_line,
+                expr.start_column,
+                expr.end_line,
+                expr.end_column,
+                DiagnosticCode::UndefinedVariable,
+            );
+        }
+    }
+
+    let used: HashSet<&str> = references.iter().map(|(_, name)| name.name).collect();
+    let first_line = text.lines().next().unwrap_or_default();
+    let range = Range::new(
+        Position::new(0, 0),
+        Position::new(0, first_line.chars().count() as u32),
+    );
+    for (call, vars) in &calls {
+        for var in vars.iter().filter(|var| !used.contains(var.as_str())) {
+            collector.add_hint_with_range(
+                format!(
+                    "Variable '{}' passed by the templatefile() call in {} is never used",
+                    var,
+                    call.describe()
+                ),
+                range,
+                DiagnosticCode::UnusedVariable,
+            );
+        }
+    }
+}
+
+/// Warn about template variables named like a runtime variable the workflow
+/// binds, at each `${...}` reading one
+///
+/// Copying a template variable into its namesake, as in `- project: ${project}`,
+/// is deliberate and left alone.
+pub fn validate_variable_collisions(
+    value: &Value,
+    preprocessed: &str,
+    expressions: &ExpressionMap,
+    collector: &mut DiagnosticCollector,
+) {
+    let mut runtime: HashMap<String, Vec<u32>> = HashMap::new();
+    walk_assignments(value, preprocessed, &mut |binding| {
+        runtime
+            .entry(binding.name)
+            .or_default()
+            .push(binding.range.start.line);
+    });
+    if runtime.is_empty() {
+        return;
+    }
+
+    let references = template_variables(expressions);
+    for (expr, name) in references.iter().filter(|(_, name)| !name.qualified) {
+        let Some(lines) = runtime.get(name.name) else {
+            continue;
+        };
+        if lines.contains(&expr.start_line) {
+            continue;
+        }
+        collector.add_workflow_warning_with_range(
+            format!(
+                "Template variable '{}' is rendered at deploy time, but the workflow also assigns a runtime variable '{}' on line {}; use $${{{}}} to read the runtime value",
+                name.name,
+                name.name,
+                lines[0] + 1,
+                name.name
+            ),
+            expr.start_line,
+            expr.start_column,
+            expr.end_line,
+            expr.end_column,
+            DiagnosticCode::VariableShadowing,
+        );
+    }
+}
+
+/// A variable read by a template
+struct VariableName<'a> {
+    name: &'a str,
+    /// Read as `var.name` rather than bare
+    qualified: bool,
+}
+
+/// The variables read by `${...}` expressions and `%{...}` directives, with
+/// the expression reading each
+fn template_variables(expressions: &ExpressionMap) -> Vec<(&Expression, VariableName<'_>)> {
+    let tokenized: Vec<(&Expression, Vec<Token>)> = expressions
+        .expressions
+        .iter()
+        .filter(|e| e.kind != ExpressionKind::Workflows)
+        .filter_map(|e| {
+            let body = e.original.get(2..e.original.len() - 1)?;
+            let body = body.trim().trim_start_matches('~').trim_end_matches('~');
+            Some((e, tokenize(body)?))
+        })
+        .collect();
+
+    // Names bound by `for` loops, in directives or `[for ...]` expressions
+    let mut bound: HashSet<&str> = HashSet::new();
+    for (_, tokens) in &tokenized {
+        let mut binding = false;
+        for token in tokens {
+            match (token.kind, token.text) {
+                (TokenKind::Name, "for") => binding = true,
+                (TokenKind::Name, "in") => binding = false,
+                (TokenKind::Name, name) if binding => {
+                    bound.insert(name);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let mut references = Vec::new();
+    for (expr, tokens) in &tokenized {
+        let keys = object_keys(tokens);
+        for (i, token) in tokens.iter().enumerate() {
+            let next = |n: usize| tokens.get(i + n);
+            let after_dot = i > 0 && tokens[i - 1].kind == TokenKind::Dot;
+            let is_call = next(1).is_some_and(|t| t.text == "(");
+            let is_key = next(1).is_some_and(|t| t.text == "=") || keys[i];
+            if token.kind != TokenKind::Name
+                || after_dot
+                || is_call
+                || is_key
+                || KEYWORDS.contains(&token.text)
+                || bound.contains(token.text)
+            {
+                continue;
+            }
+            if token.text == "var" {
+                if let (Some(dot), Some(name)) = (next(1), next(2)) {
+                    if dot.kind == TokenKind::Dot && name.kind == TokenKind::Name {
+                        let name = VariableName {
+                            name: name.text,
+                            qualified: true,
+                        };
+                        references.push((*expr, name));
+                    }
+                }
+            } else if !NAMESPACES.contains(&token.text) {
+                let name = VariableName {
+                    name: token.text,
+                    qualified: false,
+                };
+                references.push((*expr, name));
+            }
+        }
+    }
+    references
+}
+
+/// Whether each token is an object key written `key: value`: a name before
+/// a `:` directly inside `{...}`, where the `:` isn't the one of a `? :`
+/// conditional or of a `{for ...}` expression
+fn object_keys(tokens: &[Token]) -> Vec<bool> {
+    // For each open bracket, whether it's an object and how many `?` in it
+    // are still waiting for their `:`
+    let mut open: Vec<(bool, usize)> = Vec::new();
+    let mut keys = vec![false; tokens.len()];
+    for (i, token) in tokens.iter().enumerate() {
+        match token.kind {
+            TokenKind::Open => {
+                let is_for = tokens.get(i + 1).is_some_and(|t| t.text == "for");
+                open.push((token.text == "{" && !is_for, 0));
+            }
+            TokenKind::Close => {
+                open.pop();
+            }
+            TokenKind::Question => {
+                if let Some((_, pending)) = open.last_mut() {
+                    *pending += 1;
+                }
+            }
+            TokenKind::Colon => match open.last_mut() {
+                Some((_, pending)) if *pending > 0 => *pending -= 1,
+                Some((true, _)) if i > 0 && tokens[i - 1].kind == TokenKind::Name => {
+                    keys[i - 1] = true;
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+    keys
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::preprocess_expressions;
+    use std::path::PathBuf;
+
+    fn diagnostics(text: &str, vars: Option<&[&str]>) -> Vec<(u32, String)> {
+        let (_, expressions) = preprocess_expressions(text);
+        let call = TemplateCall {
+            file: PathBuf::from("/repo/main.tf"),
+            line: 4,
+            vars: vars.map(|vars| vars.iter().map(|v| v.to_string()).collect()),
+        };
+        let mut collector = DiagnosticCollector::new();
+        validate_template_vars(text, &expressions, &[call], &mut collector);
+        collector
+            .into_diagnostics()
+            .into_iter()
+            .map(|d| (d.range.start.line, d.message))
+            .collect()
+    }
+
+    const TEMPLATE: &str = r#"main:
+  steps:
+    - init:
+        assign:
+          - project: ${project}
+          - region: ${var.region}
+          - url: ${format("https://%s/v1", host)}
+          - names: ${jsonencode([for s in steps : s.name])}
+          - cfg: ${jsonencode({ zone = local.zone })}
+          - loc: ${jsonencode({ zone: region })}
+          - runtime: $${sys.get_env("X") + missing}
+%{ for label in labels ~}
+          - ${label}: true
+%{ endfor ~}
+"#;
+
+    #[test]
+    fn test_variable_collisions() {
+        let text = r#"main:
+  params: [input]
+  steps:
+    - init:
+        assign:
+          - region: "us-central1"
+          - url: ${format("https://%s", region)}
+          - project: ${project}
+    - read:
+        return: $${region + input}
+"#;
+        let (preprocessed, expressions) = preprocess_expressions(text);
+        let value: Value = serde_yaml::from_str(&preprocessed).unwrap();
+        let mut collector = DiagnosticCollector::new();
+        validate_variable_collisions(&value, &preprocessed, &expressions, &mut collector);
+        let diagnostics: Vec<(u32, String)> = collector
+            .into_diagnostics()
+            .into_iter()
+            .map(|d| (d.range.start.line, d.message))
+            .collect();
+        assert_eq!(
+            diagnostics,
+            vec![(
+                6,
+                "Template variable 'region' is rendered at deploy time, but the workflow also assigns a runtime variable 'region' on line 6; use $${region} to read the runtime value".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_object_keys_do_not_collide() {
+        let text = r#"main:
+  steps:
+    - init:
+        assign:
+          - region: "us-central1"
+          - labels: ${jsonencode({ region: var.x })}
+          - tags: ${jsonencode({ region = var.x })}
+    - read:
+        return: $${region}
+"#;
+        let (preprocessed, expressions) = preprocess_expressions(text);
+        let value: Value = serde_yaml::from_str(&preprocessed).unwrap();
+        let mut collector = DiagnosticCollector::new();
+        validate_variable_collisions(&value, &preprocessed, &expressions, &mut collector);
+        assert!(collector.into_diagnostics().is_empty());
+    }
+
+    #[test]
+    fn test_template_vars_match() {
+        let all = ["project", "region", "host", "steps", "labels"];
+        assert!(diagnostics(TEMPLATE, Some(&all)).is_empty());
+        // Vars that aren't a literal object can't be checked
+        assert!(diagnostics(TEMPLATE, None).is_empty());
+    }
+
+    #[test]
+    fn test_template_vars_missing_and_unused() {
+        assert_eq!(
+            diagnostics(
+                TEMPLATE,
+                Some(&["project", "steps", "labels", "region", "zone"])
+            ),
+            vec![
+                (
+                    6,
+                    "Template variable 'host' is not passed by the templatefile() call in main.tf:5"
+                        .to_string()
+                ),
+                (
+                    0,
+                    "Variable 'zone' passed by the templatefile() call in main.tf:5 is never used"
+                        .to_string()
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,262 @@
+//! Calls to functions Terraform doesn't have
+//!
+//! Terraform only reports an unknown function such as `jsonEncode` when it
+//! renders the template, so a misspelled call inside `${...}` is flagged with
+//! the built-in it most likely meant.
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use super::terraform_variables::position_in;
+use super::yaml_errors::{DiagnosticCode, DiagnosticCollector};
+use crate::parser::{ExpressionKind, ExpressionMap};
+use crate::terraform::{similar_function, terraform_function};
+
+lazy_static! {
+    static ref CALL_RE: Regex = Regex::new(r"([A-Za-z_][A-Za-z0-9_-]*)\s*\(").unwrap();
+}
+
+/// Directive keywords that can be followed by a parenthesized condition
+const KEYWORDS: &[&str] = &["if", "for", "in"];
+
+/// Warn about calls in `${...}` expressions and `%{...}` directives to
+/// functions that aren't Terraform built-ins
+///
+/// Provider-defined functions (`provider::aws::arn_parse`) are left alone.
+pub fn validate_terraform_functions(
+    expressions: &ExpressionMap,
+    collector: &mut DiagnosticCollector,
+) {
+    for expr in &expressions.expressions {
+        if expr.kind == ExpressionKind::Workflows {
+            continue;
+        }
+        for found in CALL_RE.captures_iter(&expr.original) {
+            let name = found.get(1).unwrap();
+            let before = &expr.original[..name.start()];
+            let in_string = before.matches('"').count() - before.matches("\\\"").count();
+            let qualified = before
+                .ends_with(|c: char| c == '.' || c == ':' || c.is_ascii_alphanumeric() || c == '_');
+            if in_string % 2 == 1
+                || qualified
+                || KEYWORDS.contains(&name.as_str())
+                || terraform_function(name.as_str()).is_some()
+            {
+                continue;
+            }
+            let message = match similar_function(name.as_str()) {
+                Some(function) => format!(
+                    "Unknown Terraform function '{}'; did you mean '{}'?",
+                    name.as_str(),
+                    function.name
+                ),
+                None => format!("Unknown Terraform function '{}'", name.as_str()),
+            };
+            let (line, column) = position_in(expr.start_line, expr.start_column, before);
+            collector.add_workflow_warning_with_range(
+                message,
+                line,
+                column,
+                line,
+                column + name.as_str().chars().count() as u32,
+                DiagnosticCode::UnknownFunction,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::preprocess_expressions;
+
+    fn diagnostics(text: &str) -> Vec<(u32, u32, u32, String)> {
+        let (_, expressions) = preprocess_expressions(text);
+        let mut collector = DiagnosticCollector::new();
+        validate_terraform_functions(&expressions, &mut collector);
+        collector
+            .into_diagnostics()
+            .into_iter()
+            .map(|d| {
+                (
+                    d.range.start.line,
+                    d.range.start.character,
+                    d.range.end.character,
+                    d.message,
+                )
+            })
+            .collect()
+    }
+
+    /// Every built-in in Terraform's function reference
+    const DOCUMENTED: &[&str] = &[
+        "abs",
+        "ceil",
+        "floor",
+        "log",
+        "max",
+        "min",
+        "parseint",
+        "pow",
+        "signum",
+        "chomp",
+        "endswith",
+        "format",
+        "formatlist",
+        "indent",
+        "join",
+        "lower",
+        "regex",
+        "regexall",
+        "replace",
+        "split",
+        "startswith",
+        "strcontains",
+        "strrev",
+        "substr",
+        "templatestring",
+        "title",
+        "trim",
+        "trimprefix",
+        "trimsuffix",
+        "trimspace",
+        "upper",
+        "alltrue",
+        "anytrue",
+        "chunklist",
+        "coalesce",
+        "coalescelist",
+        "compact",
+        "concat",
+        "contains",
+        "distinct",
+        "element",
+        "flatten",
+        "index",
+        "keys",
+        "length",
+        "lookup",
+        "matchkeys",
+        "merge",
+        "one",
+        "range",
+        "reverse",
+        "setintersection",
+        "setproduct",
+        "setsubtract",
+        "setunion",
+        "slice",
+        "sort",
+        "sum",
+        "transpose",
+        "values",
+        "zipmap",
+        "base64decode",
+        "base64encode",
+        "base64gzip",
+        "csvdecode",
+        "jsondecode",
+        "jsonencode",
+        "textdecodebase64",
+        "textencodebase64",
+        "urlencode",
+        "yamldecode",
+        "yamlencode",
+        "abspath",
+        "dirname",
+        "pathexpand",
+        "basename",
+        "file",
+        "fileexists",
+        "fileset",
+        "filebase64",
+        "templatefile",
+        "formatdate",
+        "plantimestamp",
+        "timeadd",
+        "timecmp",
+        "timestamp",
+        "base64sha256",
+        "base64sha512",
+        "bcrypt",
+        "filebase64sha256",
+        "filebase64sha512",
+        "filemd5",
+        "filesha1",
+        "filesha256",
+        "filesha512",
+        "md5",
+        "rsadecrypt",
+        "sha1",
+        "sha256",
+        "sha512",
+        "uuid",
+        "uuidv5",
+        "cidrhost",
+        "cidrnetmask",
+        "cidrsubnet",
+        "cidrsubnets",
+        "can",
+        "ephemeralasnull",
+        "issensitive",
+        "nonsensitive",
+        "sensitive",
+        "tobool",
+        "tolist",
+        "tomap",
+        "tonumber",
+        "toset",
+        "tostring",
+        "try",
+        "type",
+    ];
+
+    #[test]
+    fn test_documented_functions_are_known() {
+        let text: String = DOCUMENTED
+            .iter()
+            .map(|name| format!("- x: ${{{}(var.x)}}\n", name))
+            .collect();
+        assert!(diagnostics(&text).is_empty());
+    }
+
+    #[test]
+    fn test_unknown_functions() {
+        let text = r#"main:
+  steps:
+    - init:
+        assign:
+          - cfg: ${jsonEncode({ a = 1 })}
+          - name: ${format("x(%s)", lenght(var.names))}
+          - ok: ${jsonencode(provider::aws::arn_parse(var.arn))}
+          - runtime: $${sys.get_env("X") + frobnicate(1)}
+%{ if (frobnicate(var.x)) ~}
+          - x: 1
+%{ endif ~}
+"#;
+        assert_eq!(
+            diagnostics(text),
+            vec![
+                (
+                    4,
+                    19,
+                    29,
+                    "Unknown Terraform function 'jsonEncode'; did you mean 'jsonencode'?"
+                        .to_string()
+                ),
+                (
+                    5,
+                    36,
+                    42,
+                    "Unknown Terraform function 'lenght'; did you mean 'length'?".to_string()
+                ),
+                (
+                    8,
+                    7,
+                    17,
+                    "Unknown Terraform function 'frobnicate'".to_string()
+                ),
+            ]
+        );
+    }
+}
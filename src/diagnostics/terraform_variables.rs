@@ -0,0 +1,118 @@
+//! `var.` references to variables the Terraform module doesn't declare
+//!
+//! Terraform fails to plan a module whose `${var.name}` has no
+//! `variable "name"` block, so a template referencing one is reported as soon
+//! as it is written rather than at the next `terraform plan`.
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use super::yaml_errors::{DiagnosticCode, DiagnosticCollector};
+use crate::parser::{ExpressionKind, ExpressionMap};
+
+lazy_static! {
+    static ref VAR_RE: Regex = Regex::new(r"\bvar\.([A-Za-z_][A-Za-z0-9_-]*)").unwrap();
+}
+
+/// Warn about `var.name` references in `${...}` expressions and `%{...}`
+/// directives whose name isn't one of `declared`
+pub fn validate_terraform_variables(
+    expressions: &ExpressionMap,
+    declared: &[&str],
+    collector: &mut DiagnosticCollector,
+) {
+    for expr in &expressions.expressions {
+        if expr.kind == ExpressionKind::Workflows {
+            continue;
+        }
+        for found in VAR_RE.captures_iter(&expr.original) {
+            let (whole, name) = (found.get(0).unwrap(), &found[1]);
+            let before = &expr.original[..whole.start()];
+            let in_string = before.matches('"').count() - before.matches("\\\"").count();
+            if in_string % 2 == 1 || declared.contains(&name) {
+                continue;
+            }
+            let (line, column) = position_in(expr.start_line, expr.start_column, before);
+            collector.add_workflow_warning_with_range(
+                format!(
+                    "Variable '{}' is not declared in the Terraform module; add a variable \"{}\" block",
+                    name, name
+                ),
+                line,
+                column,
+                line,
+                column + whole.as_str().chars().count() as u32,
+                DiagnosticCode::UndefinedVariable,
+            );
+        }
+    }
+}
+
+/// Where the text after `before` starts, given where `before` starts
+pub(super) fn position_in(line: u32, column: u32, before: &str) -> (u32, u32) {
+    match before.rfind('\n') {
+        Some(i) => (
+            line + before.matches('\n').count() as u32,
+            before[i + 1..].chars().count() as u32,
+        ),
+        None => (line, column + before.chars().count() as u32),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::preprocess_expressions;
+
+    fn diagnostics(text: &str, declared: &[&str]) -> Vec<(u32, u32, u32, String)> {
+        let (_, expressions) = preprocess_expressions(text);
+        let mut collector = DiagnosticCollector::new();
+        validate_terraform_variables(&expressions, declared, &mut collector);
+        collector
+            .into_diagnostics()
+            .into_iter()
+            .map(|d| {
+                (
+                    d.range.start.line,
+                    d.range.start.character,
+                    d.range.end.character,
+                    d.message,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_undeclared_variables() {
+        let text = r#"main:
+  params: [input]
+  steps:
+    - init:
+        assign:
+          - region: ${var.region}
+          - name: ${format("%s-var.x", var.prefix, local.suffix)}
+          - cfg: ${jsonencode({
+              zone = var.zone
+            })}
+          - runtime: $${var.region}
+%{ if var.debug }
+          - debug: true
+%{ endif }
+"#;
+        let message = |name: &str| {
+            format!(
+                "Variable '{}' is not declared in the Terraform module; add a variable \"{}\" block",
+                name, name
+            )
+        };
+        assert_eq!(
+            diagnostics(text, &["region"]),
+            vec![
+                (6, 39, 49, message("prefix")),
+                (8, 21, 29, message("zone")),
+                (11, 6, 15, message("debug")),
+            ]
+        );
+        assert!(diagnostics(text, &["region", "prefix", "zone", "debug"]).is_empty());
+    }
+}
@@ -0,0 +1,143 @@
+//! Assignment type-stability lint
+//!
+//! Tracks the type each `assign` gives a variable and hints when a later
+//! assignment in the same workflow gives it a different one, e.g. a string
+//! that later becomes a map.
+
+use std::collections::HashMap;
+
+use serde_yaml::Value;
+
+use super::scopes::{walk_assignments, BindingKind};
+use super::yaml_errors::{DiagnosticCode, DiagnosticCollector};
+
+/// Type of a statically known value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InferredType {
+    String,
+    Number,
+    Boolean,
+    List,
+    Map,
+}
+
+impl InferredType {
+    /// Infer a value's type; `None` for null and expressions, whose type is
+    /// unknown or deliberately open
+    fn of(value: &Value) -> Option<Self> {
+        match value {
+            Value::String(s) if s.contains("__EXPR_") => None,
+            Value::String(_) => Some(InferredType::String),
+            Value::Number(_) => Some(InferredType::Number),
+            Value::Bool(_) => Some(InferredType::Boolean),
+            Value::Sequence(_) => Some(InferredType::List),
+            Value::Mapping(_) => Some(InferredType::Map),
+            Value::Null | Value::Tagged(_) => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            InferredType::String => "string",
+            InferredType::Number => "number",
+            InferredType::Boolean => "boolean",
+            InferredType::List => "list",
+            InferredType::Map => "map",
+        }
+    }
+}
+
+/// Hint when a variable is reassigned a value of a different type
+pub fn validate_type_stability(value: &Value, text: &str, collector: &mut DiagnosticCollector) {
+    // (workflow, variable) -> (type, line it was first given that type)
+    let mut types: HashMap<(&str, String), (InferredType, u32)> = HashMap::new();
+
+    walk_assignments(value, text, &mut |binding| {
+        if binding.kind != BindingKind::Assign || !binding.whole {
+            return;
+        }
+        let Some(ty) = binding.value.and_then(InferredType::of) else {
+            return;
+        };
+        let key = (binding.workflow, binding.name.clone());
+        match types.get(&key) {
+            Some(&(previous, first_line)) if previous != ty => {
+                collector.add_hint_with_range(
+                    format!(
+                        "Variable '{}' changes type from {} (line {}) to {}",
+                        binding.name,
+                        previous.name(),
+                        first_line + 1,
+                        ty.name()
+                    ),
+                    binding.range,
+                    DiagnosticCode::TypeInstability,
+                );
+            }
+            Some(_) => {}
+            None => {
+                types.insert(key, (ty, binding.range.start.line));
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower_lsp::lsp_types::{DiagnosticSeverity, Position, Range};
+
+    fn diagnostics(yaml: &str) -> Vec<tower_lsp::lsp_types::Diagnostic> {
+        let value: Value = serde_yaml::from_str(yaml).unwrap();
+        let mut collector = DiagnosticCollector::new();
+        validate_type_stability(&value, yaml, &mut collector);
+        collector.into_diagnostics()
+    }
+
+    #[test]
+    fn test_type_change_is_hinted() {
+        let yaml = r#"main:
+  steps:
+    - init:
+        assign:
+          - config: "default"
+          - count: 0
+    - later:
+        assign:
+          - config:
+              region: eu
+          - count: 2
+"#;
+        let diagnostics = diagnostics(yaml);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].message,
+            "Variable 'config' changes type from string (line 5) to map"
+        );
+        assert_eq!(
+            diagnostics[0].range,
+            Range::new(Position::new(8, 12), Position::new(8, 18))
+        );
+        assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::HINT));
+    }
+
+    #[test]
+    fn test_unknown_types_and_fields_are_ignored() {
+        let yaml = r#"main:
+  steps:
+    - init:
+        assign:
+          - result: null
+          - data: {}
+          - data.items: [1, 2]
+          - result: "x"
+          - result: __EXPR_000__
+helper:
+  steps:
+    - other:
+        assign:
+          - data: "separate workflow"
+"#;
+        assert!(diagnostics(yaml).is_empty());
+    }
+}
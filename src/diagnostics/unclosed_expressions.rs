@@ -0,0 +1,94 @@
+//! Expressions and directives that are never closed
+//!
+//! An opener without a matching `}` is left in the text as written, where it
+//! fails `templatefile()` or, for `$${`, Workflows at deploy time. It is
+//! reported from the opener to the end of its line; the YAML error it may
+//! cause on that line is left out (see `parser::parse_yaml`).
+
+use crate::parser::{ExpressionKind, ExpressionMap};
+
+use super::yaml_errors::{DiagnosticCode, DiagnosticCollector};
+
+/// Report every `${`, `$${` and `%{` without a closing `}`; `text` is the
+/// document as written
+pub fn validate_unclosed_expressions(
+    text: &str,
+    expressions: &ExpressionMap,
+    collector: &mut DiagnosticCollector,
+) {
+    for unclosed in &expressions.unclosed {
+        let line = text.lines().nth(unclosed.line as usize).unwrap_or("");
+        let (what, opener) = match unclosed.kind {
+            ExpressionKind::Terraform => ("Terraform expression", "${"),
+            ExpressionKind::Workflows => ("Workflows expression", "$${"),
+            ExpressionKind::Directive => ("template directive", "%{"),
+        };
+        collector.add_yaml_error_with_range(
+            format!("Unclosed {}: '{}' has no matching '}}'", what, opener),
+            unclosed.line,
+            unclosed.column,
+            unclosed.line,
+            line.chars().count() as u32,
+            DiagnosticCode::UnclosedExpression,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::preprocess_expressions;
+
+    #[test]
+    fn test_unclosed_expressions() {
+        let text = "a: ${var.x\nb: \"$${sys.now()\"\nc: ${ok}\n%{ if x\n";
+        let (_, expressions) = preprocess_expressions(text);
+        let mut collector = DiagnosticCollector::new();
+        validate_unclosed_expressions(text, &expressions, &mut collector);
+        let diagnostics: Vec<_> = collector
+            .into_diagnostics()
+            .into_iter()
+            .map(|d| {
+                (
+                    d.range.start.line,
+                    d.range.start.character,
+                    d.range.end.character,
+                    d.message,
+                )
+            })
+            .collect();
+        assert_eq!(
+            diagnostics,
+            vec![
+                (
+                    0,
+                    3,
+                    10,
+                    "Unclosed Terraform expression: '${' has no matching '}'".to_string()
+                ),
+                (
+                    1,
+                    4,
+                    17,
+                    "Unclosed Workflows expression: '$${' has no matching '}'".to_string()
+                ),
+                (
+                    3,
+                    0,
+                    7,
+                    "Unclosed template directive: '%{' has no matching '}'".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_escaped_quotes_in_double_quoted_scalar() {
+        let text = "a: \"$${\\\"a\\\" + string(x)}\"\nb: \"${\\\"a\\\"}\"\n";
+        let (_, expressions) = preprocess_expressions(text);
+        let mut collector = DiagnosticCollector::new();
+        validate_unclosed_expressions(text, &expressions, &mut collector);
+        assert!(collector.into_diagnostics().is_empty());
+        assert_eq!(expressions.expressions.len(), 2);
+    }
+}
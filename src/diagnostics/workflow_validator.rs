@@ -3,9 +3,14 @@
 //! Validates the structure of Google Cloud Workflows YAML documents,
 //! checking for required fields, valid step structures, and unknown keys.
 
+use std::collections::HashMap;
+
 use serde_yaml::Value;
+use tower_lsp::lsp_types::Range;
 
 use super::yaml_errors::{DiagnosticCode, DiagnosticCollector};
+use crate::parser::{without_unparsed_directives, SpannedValue};
+use crate::schema::{Block, PathSegment, SchemaOverlay, StepKind};
 
 /// Validate a parsed YAML value as a GCP Workflow document.
 ///
@@ -15,8 +20,43 @@ use super::yaml_errors::{DiagnosticCode, DiagnosticCollector};
 /// - `steps` must be a list
 /// - Each step should have exactly one named key
 /// - Subworkflows should have `params` or `steps`
-/// - Unknown top-level keys produce hints
-pub fn validate_workflow(value: &Value, text: &str, collector: &mut DiagnosticCollector) {
+/// - Keys of steps, `for` loops, `parallel` settings, `switch` entries and
+///   `except` blocks belong to that construct; a key of another one gets a
+///   hint naming where it belongs
+/// - Unknown top-level keys produce hints, except that workflow block keys
+///   stranded after an empty block, or a misspelled `steps`, are reported as
+///   one warning on the parent with the children as related information
+/// - Fields with a fixed shape (`call`, `args`, `concurrency_limit`, ...) have
+///   values of the expected type
+/// - `next:` targets name a step in the enclosing `steps` block or one around it
+/// - `args:` of standard library calls like `http.get` and `sys.sleep` name
+///   only known arguments, include the required ones and have the right types
+/// - `http.*` requests use a known `method`, an `auth` with a supported
+///   `type` and a positive `timeout` of at most 1800 seconds
+/// - `args:` of calls to subworkflows in the document name only declared
+///   params and include every param without a default
+/// - `parallel` has either `branches` or a `for` loop, `shared` names
+///   variables and a literal `concurrency_limit` is positive
+/// - `switch` entries are mappings with a `condition` and exactly one of
+///   `next`, `steps`, `return`, `raise` or `assign`
+/// - `retry` policies use only `predicate`, `max_retries` and a `backoff`
+///   mapping of numeric delays
+/// - `assign` entries each set exactly one variable
+/// - `params` entries are identifiers or single `name: default` mappings
+///   whose default is not itself a mapping
+/// - Subworkflows other than `main` are called from somewhere else in the
+///   document; unused ones get a hint tagged `Unnecessary`
+/// - A step with `return` has no `next` or other action, since `return` ends
+///   the workflow before they could run
+/// - Top-level keys the user's schema overlay declares match their schema
+///   instead, and the keys it requires are present; keys in the configured
+///   allowlists get no unknown-key hints
+pub fn validate_workflow(
+    value: &Value,
+    text: &str,
+    extensions: SchemaExtensions,
+    collector: &mut DiagnosticCollector,
+) {
     let mapping = match value.as_mapping() {
         Some(m) => m,
         None => {
@@ -30,8 +70,16 @@ pub fn validate_workflow(value: &Value, text: &str, collector: &mut DiagnosticCo
         }
     };
 
-    let line_index = LineIndex::new(text);
+    let root = SpannedValue::parse(&without_unparsed_directives(text)).unwrap_or_default();
+    let context = Context {
+        subworkflows: subworkflow_params(mapping),
+        step_keys: extensions.step_keys,
+    };
     let mut has_main = false;
+    let stranded = stranded_block_keys(mapping);
+    if let Some((parent, children)) = &stranded {
+        report_stranded_keys(parent, children, &root, collector);
+    }
 
     for (key, val) in mapping {
         let key_str = match key.as_str() {
@@ -39,19 +87,55 @@ pub fn validate_workflow(value: &Value, text: &str, collector: &mut DiagnosticCo
             None => continue,
         };
 
-        let key_line = find_key_line(&line_index, key_str);
+        if let Some((parent, children)) = &stranded {
+            if key_str == *parent || children.contains(&key_str) {
+                has_main |= key_str == "main";
+                continue;
+            }
+        }
 
-        if key_str == "main" {
+        let block = Located::at_key(&root, key_str);
+        let overlay = extensions.overlay;
+        if let Some((overlay, schema)) = overlay.and_then(|o| Some((o, o.property(key_str)?))) {
+            has_main |= key_str == "main";
+            for violation in overlay.validate(schema, val) {
+                warn(
+                    collector,
+                    format!(
+                        "{} at '{}'",
+                        violation.message,
+                        violation_path(key_str, &violation.path)
+                    ),
+                    violation_range(block, &violation.path),
+                    DiagnosticCode::SchemaViolation,
+                );
+            }
+        } else if key_str == "main" {
             has_main = true;
-            validate_workflow_block(val, key_str, &line_index, collector);
+            validate_workflow_block(val, block, key_str, &context, collector);
         } else if is_likely_subworkflow(val) {
-            validate_workflow_block(val, key_str, &line_index, collector);
-        } else {
+            validate_workflow_block(val, block, key_str, &context, collector);
+        } else if !extensions.top_level_keys.iter().any(|k| k == key_str) {
             // Unknown top-level key - emit hint
-            collector.add_hint(
+            collector.add_hint_with_range(
                 format!("Unknown workflow element: '{}'", key_str),
-                key_line,
-                0,
+                block.key,
+                DiagnosticCode::UnknownKeyword,
+            );
+        }
+    }
+
+    for key in extensions
+        .overlay
+        .into_iter()
+        .flat_map(SchemaOverlay::required)
+    {
+        if !mapping.contains_key(key) {
+            warn(
+                collector,
+                format!("Missing top-level key '{}' required by the schema", key),
+                Range::default(),
+                DiagnosticCode::SchemaViolation,
             );
         }
     }
@@ -59,6 +143,263 @@ pub fn validate_workflow(value: &Value, text: &str, collector: &mut DiagnosticCo
     if !has_main && !mapping.is_empty() {
         collector.add_workflow_warning("Workflow must have a 'main' block".to_string(), 0, 0);
     }
+
+    report_unused_subworkflows(mapping, &context.subworkflows, &root, collector);
+}
+
+/// Additions the user has made to the Workflows schema
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SchemaExtensions<'a> {
+    /// JSON Schema overlay for organization-specific top-level keys
+    pub overlay: Option<&'a SchemaOverlay>,
+    /// Top-level keys accepted without an unknown-key hint
+    pub top_level_keys: &'a [String],
+    /// Step keys accepted without an unknown-key hint
+    pub step_keys: &'a [String],
+}
+
+/// What validating a block needs to know about the rest of the document
+struct Context<'a> {
+    subworkflows: SubworkflowParams<'a>,
+    step_keys: &'a [String],
+}
+
+/// Hint at subworkflows that no `call:` outside their own block refers to
+fn report_unused_subworkflows(
+    mapping: &serde_yaml::Mapping,
+    subworkflows: &SubworkflowParams,
+    root: &SpannedValue,
+    collector: &mut DiagnosticCollector,
+) {
+    let mut called_from: Vec<(&str, &str)> = Vec::new();
+    for (key, block) in mapping {
+        let Some(caller) = key.as_str() else {
+            continue;
+        };
+        let mut targets = Vec::new();
+        call_targets(block, &mut targets);
+        called_from.extend(targets.into_iter().map(|target| (caller, target)));
+    }
+
+    for (key, _) in mapping {
+        let Some(name) = key.as_str() else {
+            continue;
+        };
+        if name == "main" || !subworkflows.contains_key(name) {
+            continue;
+        }
+        if called_from
+            .iter()
+            .any(|&(caller, target)| target == name && caller != name)
+        {
+            continue;
+        }
+        collector.add_unnecessary_hint(
+            format!("Subworkflow '{}' is never called", name),
+            root.key_range(name),
+            DiagnosticCode::UnusedSubworkflow,
+        );
+    }
+}
+
+/// A field of a mapping: the range of its key and the written value
+#[derive(Clone, Copy)]
+struct Located<'a> {
+    key: Range,
+    node: &'a SpannedValue,
+}
+
+impl<'a> Located<'a> {
+    fn at_key(parent: &'a SpannedValue, key: &str) -> Self {
+        Located {
+            key: parent.key_range(key),
+            node: parent.at_key(key),
+        }
+    }
+
+    /// Where to report on item `index` of the field's list: the item's first
+    /// character, or the key when the item isn't written out
+    fn item_head(&self, index: usize) -> Range {
+        self.node
+            .items()
+            .get(index)
+            .map_or(self.key, SpannedValue::head)
+    }
+}
+
+/// Where to report a schema violation found at `path` under a top-level key
+fn violation_range(block: Located, path: &[PathSegment]) -> Range {
+    let mut range = block.key;
+    let mut node = block.node;
+    for segment in path {
+        match segment {
+            PathSegment::Key(key) => {
+                range = node.key_range(key);
+                node = node.at_key(key);
+            }
+            PathSegment::Index(index) => {
+                range = node.items().get(*index).map_or(range, SpannedValue::head);
+                node = node.at_index(*index);
+            }
+        }
+    }
+    range
+}
+
+/// `path` under a top-level key written out, e.g. `metadata.tags[0]`
+fn violation_path(key: &str, path: &[PathSegment]) -> String {
+    path.iter()
+        .fold(key.to_string(), |written, segment| match segment {
+            PathSegment::Key(key) => format!("{}.{}", written, key),
+            PathSegment::Index(index) => format!("{}[{}]", written, index),
+        })
+}
+
+/// Report a warning on `range`
+fn warn(collector: &mut DiagnosticCollector, message: String, range: Range, code: DiagnosticCode) {
+    collector.add_workflow_warning_with_range(
+        message,
+        range.start.line,
+        range.start.character,
+        range.end.line,
+        range.end.character,
+        code,
+    );
+}
+
+/// Every string `call:` target in a value, at any depth
+fn call_targets<'v>(value: &'v Value, out: &mut Vec<&'v str>) {
+    match value {
+        Value::Mapping(mapping) => {
+            for (key, val) in mapping {
+                match (key.as_str(), val.as_str()) {
+                    (Some("call"), Some(target)) => out.push(target),
+                    _ => call_targets(val, out),
+                }
+            }
+        }
+        Value::Sequence(items) => items.iter().for_each(|item| call_targets(item, out)),
+        _ => {}
+    }
+}
+
+/// The params each workflow block declares, with whether they are required
+///
+/// `None` marks a block whose params can't be read, such as a `params` given
+/// as an expression.
+type SubworkflowParams<'a> = HashMap<&'a str, Option<Vec<(&'a str, bool)>>>;
+
+fn subworkflow_params(mapping: &serde_yaml::Mapping) -> SubworkflowParams<'_> {
+    mapping
+        .iter()
+        .filter_map(|(key, value)| {
+            let name = key.as_str()?;
+            if name != "main" && !is_likely_subworkflow(value) {
+                return None;
+            }
+            let params = match value.get("params") {
+                None => Some(Vec::new()),
+                Some(params) => params.as_sequence().and_then(|params| {
+                    params
+                        .iter()
+                        .map(|param| match param {
+                            // `name: default` makes the param optional
+                            Value::Mapping(m) if m.len() == 1 => {
+                                Some((m.keys().next()?.as_str()?, false))
+                            }
+                            Value::String(name) if !is_expression_placeholder(param) => {
+                                Some((name.as_str(), true))
+                            }
+                            _ => None,
+                        })
+                        .collect()
+                }),
+            };
+            Some((name, params))
+        })
+        .collect()
+}
+
+/// Find workflow block keys (`params`, `steps`, `labels`) that sit at the top
+/// level right after an empty block, as happens when the block's contents
+/// lose their indentation
+///
+/// Returns the empty block's name and the stranded keys.
+fn stranded_block_keys(mapping: &serde_yaml::Mapping) -> Option<(&str, Vec<&str>)> {
+    let mut parent = None;
+    let mut children = Vec::new();
+    for (key, val) in mapping {
+        let Some(key) = key.as_str() else {
+            continue;
+        };
+        if val.is_null() && Block::Workflow.field(key).is_none() {
+            if !children.is_empty() {
+                break;
+            }
+            parent = Some(key);
+        } else if parent.is_some()
+            && Block::Workflow.field(key).is_some()
+            && !is_likely_subworkflow(val)
+        {
+            children.push(key);
+        } else if !children.is_empty() {
+            break;
+        } else {
+            parent = None;
+        }
+    }
+    parent
+        .filter(|_| !children.is_empty())
+        .map(|parent| (parent, children))
+}
+
+/// Report stranded block keys as one warning on the empty block
+fn report_stranded_keys(
+    parent: &str,
+    children: &[&str],
+    root: &SpannedValue,
+    collector: &mut DiagnosticCollector,
+) {
+    let related = children
+        .iter()
+        .map(|child| {
+            (
+                root.key_range(child),
+                format!("'{}' is outside '{}'", child, parent),
+            )
+        })
+        .collect();
+    let names: Vec<String> = children.iter().map(|c| format!("'{}'", c)).collect();
+    collector.add_warning_with_related(
+        format!(
+            "'{}' is empty, but {} after it look like its contents; indent them under '{}'",
+            parent,
+            names.join(", "),
+            parent
+        ),
+        root.key_range(parent),
+        DiagnosticCode::WorkflowStructure,
+        related,
+    );
+}
+
+/// Whether `key` is one or two edits away from `keyword` (but not equal)
+fn is_misspelling_of(key: &str, keyword: &str) -> bool {
+    let a: Vec<char> = key.chars().collect();
+    let b: Vec<char> = keyword.chars().collect();
+    if a == b || a.len().abs_diff(b.len()) > 2 {
+        return false;
+    }
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()] <= 2
 }
 
 /// Check if a value looks like a subworkflow definition (has params or steps)
@@ -74,44 +415,95 @@ fn is_likely_subworkflow(value: &Value) -> bool {
 /// Validate a workflow or subworkflow block (must have `steps`)
 fn validate_workflow_block(
     value: &Value,
+    block: Located,
     name: &str,
-    line_index: &LineIndex,
+    context: &Context,
     collector: &mut DiagnosticCollector,
 ) {
     let mapping = match value.as_mapping() {
         Some(m) => m,
         None => {
-            let line = find_key_line(line_index, name);
-            collector.add_workflow_warning(format!("'{}' block must be a mapping", name), line, 0);
+            warn(
+                collector,
+                format!("'{}' block must be a mapping", name),
+                block.key,
+                DiagnosticCode::WorkflowStructure,
+            );
             return;
         }
     };
 
     let has_steps = mapping.keys().any(|k| k.as_str() == Some("steps"));
 
+    // A misspelled `steps` would otherwise be reported both as a missing
+    // `steps` and as an unknown key, with its steps never validated
+    let misspelled = mapping.iter().find(|(k, v)| {
+        v.is_sequence() && k.as_str().is_some_and(|k| is_misspelling_of(k, "steps"))
+    });
+    if let (false, Some((key, steps))) = (has_steps, misspelled) {
+        let key = key.as_str().unwrap_or_default();
+        let steps_node = block.node.at_key(key);
+        let related = steps
+            .as_sequence()
+            .into_iter()
+            .flatten()
+            .enumerate()
+            .filter_map(|(i, step)| Some((steps_node.at_index(i), step.as_mapping()?)))
+            .flat_map(|(node, step)| step.keys().map(move |k| (node, k)))
+            .filter_map(|(node, step)| {
+                let step = step.as_str()?;
+                Some((
+                    node.key_range(step),
+                    format!("step '{}' is not validated", step),
+                ))
+            })
+            .collect();
+        collector.add_warning_with_related(
+            format!(
+                "'{}' block has '{}' instead of 'steps'; its steps are ignored",
+                name, key
+            ),
+            block.node.key_range(key),
+            DiagnosticCode::WorkflowStructure,
+            related,
+        );
+        return;
+    }
+
     if !has_steps {
-        let line = find_key_line(line_index, name);
-        collector.add_workflow_warning(format!("'{}' block must contain 'steps'", name), line, 0);
+        warn(
+            collector,
+            format!("'{}' block must contain 'steps'", name),
+            block.key,
+            DiagnosticCode::WorkflowStructure,
+        );
         return;
     }
 
     // Validate steps
     for (k, v) in mapping {
         if k.as_str() == Some("steps") {
-            validate_steps(v, line_index, collector);
+            let steps = Located::at_key(block.node, "steps");
+            validate_steps(v, steps, context, collector);
+            check_jumps_in_steps(v, steps.node, &mut Vec::new(), collector);
         }
     }
 
     // Check for unknown keys in workflow block
-    let valid_keys = ["params", "steps"];
-    for key in mapping.keys() {
+    for (key, val) in mapping {
         if let Some(s) = key.as_str() {
-            if !valid_keys.contains(&s) {
-                let line = find_key_line(line_index, s);
-                collector.add_hint(
+            let field = Located::at_key(block.node, s);
+            if s == "labels" || s == "params" {
+                check_value_type(Block::Workflow, s, val, field.key, collector);
+                if s == "params" {
+                    check_params_entries(val, field, collector);
+                }
+            }
+            if Block::Workflow.field(s).is_none() {
+                collector.add_hint_with_range(
                     format!("Unknown key '{}' in workflow block '{}'", s, name),
-                    line,
-                    0,
+                    field.key,
+                    DiagnosticCode::UnknownKeyword,
                 );
             }
         }
@@ -119,221 +511,1143 @@ fn validate_workflow_block(
 }
 
 /// Validate a `steps` list
-fn validate_steps(value: &Value, line_index: &LineIndex, collector: &mut DiagnosticCollector) {
+fn validate_steps(
+    value: &Value,
+    steps_field: Located,
+    context: &Context,
+    collector: &mut DiagnosticCollector,
+) {
     let steps = match value.as_sequence() {
         Some(s) => s,
         None => {
-            let line = find_key_line(line_index, "steps");
-            collector.add_workflow_warning("'steps' must be a list".to_string(), line, 0);
+            warn(
+                collector,
+                "'steps' must be a list".to_string(),
+                steps_field.key,
+                DiagnosticCode::WorkflowStructure,
+            );
             return;
         }
     };
 
-    for step in steps {
+    for (i, step) in steps.iter().enumerate() {
         let mapping = match step.as_mapping() {
             Some(m) => m,
             None => continue,
         };
+        let step_node = steps_field.node.at_index(i);
 
         if mapping.len() != 1 {
-            // Try to find approximate line
-            if let Some((first_key, _)) = mapping.iter().next() {
-                if let Some(s) = first_key.as_str() {
-                    let line = find_key_line(line_index, s);
-                    collector.add_workflow_warning_with_code(
-                        "Step should have exactly one named key".to_string(),
-                        line,
-                        0,
-                        DiagnosticCode::WorkflowStructure,
-                    );
-                }
+            if let Some(first) = mapping.keys().next().and_then(Value::as_str) {
+                warn(
+                    collector,
+                    "Step should have exactly one named key".to_string(),
+                    step_node.key_range(first),
+                    DiagnosticCode::WorkflowStructure,
+                );
             }
         }
 
         // Validate step content
-        for (_step_name, step_value) in mapping {
-            validate_step_body(step_value, line_index, collector);
+        for (step_name, step_value) in mapping {
+            let body = step_name
+                .as_str()
+                .map_or(step_node, |name| step_node.at_key(name));
+            validate_step_body(step_value, body, context, collector);
         }
     }
 }
 
 /// Validate the body of a single step
-fn validate_step_body(value: &Value, line_index: &LineIndex, collector: &mut DiagnosticCollector) {
+fn validate_step_body(
+    value: &Value,
+    node: &SpannedValue,
+    context: &Context,
+    collector: &mut DiagnosticCollector,
+) {
     let mapping = match value.as_mapping() {
         Some(m) => m,
         None => return, // scalar or sequence step body - not necessarily invalid
     };
 
-    use crate::schema;
-
-    for key in mapping.keys() {
+    let step = describe_step(mapping);
+    for (key, val) in mapping {
         if let Some(s) = key.as_str() {
-            if !schema::is_step_action(s) && !is_step_modifier(s) {
-                let line = find_key_line(line_index, s);
-                collector.add_hint(format!("Unknown step action: '{}'", s), line, 0);
+            let field = Located::at_key(node, s);
+            if !context.step_keys.iter().any(|k| k == s) {
+                check_block_key(Block::Step, &step, s, field.key, collector);
+            }
+            check_value_type(Block::Step, s, val, field.key, collector);
+            if s != "return" && StepKind::from_key(s).is_some() && mapping.contains_key("return") {
+                warn(
+                    collector,
+                    format!(
+                        "'{}' can never run: 'return' in the same step ends the workflow or subworkflow",
+                        s
+                    ),
+                    field.key,
+                    DiagnosticCode::UnreachableCode,
+                );
+            }
+            if s == "call_log_level" {
+                check_call_log_level(val, field.key, collector);
+            }
+            if s == "assign" {
+                check_assign_entries(val, field, collector);
+            }
+            if s == "call" {
+                let args = mapping.get("args");
+                let args_node = node.at_key("args");
+                match val.as_str().and_then(|name| context.subworkflows.get(name)) {
+                    Some(Some(params)) => {
+                        check_subworkflow_args(val, params, args, args_node, field.key, collector)
+                    }
+                    Some(None) => {}
+                    None => check_call_args(val, args, args_node, field.key, collector),
+                }
+            }
+            if s == "retry" {
+                check_retry_policy(val, field, collector);
+            }
+            if s == "switch" {
+                check_switch_entries(val, field.node, collector);
+            }
+            if s == "parallel" {
+                check_parallel(val, field, collector);
             }
+
+            // `parallel` and `for` bodies carry typed fields of their own
+            let inner_block = match s {
+                "parallel" => Some(Block::Parallel),
+                "for" => Some(Block::For),
+                _ => None,
+            };
+            if let (Some(block), Some(body)) = (inner_block, val.as_mapping()) {
+                for (inner_key, inner_val) in body {
+                    if let Some(inner) = inner_key.as_str() {
+                        let inner_range = field.node.key_range(inner);
+                        check_block_key(block, block.describe(), inner, inner_range, collector);
+                        check_value_type(block, inner, inner_val, inner_range, collector);
+                    }
+                }
+            }
+            if s == "except" {
+                for inner in val
+                    .as_mapping()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|(k, _)| k.as_str())
+                {
+                    let inner_range = field.node.key_range(inner);
+                    let except = Block::Except;
+                    check_block_key(except, except.describe(), inner, inner_range, collector);
+                }
+            }
+        }
+    }
+}
+
+/// Check the `next:` jumps under a `steps` list, with `visible` holding the
+/// step names of the enclosing blocks
+fn check_jumps_in_steps<'v>(
+    steps: &'v Value,
+    node: &SpannedValue,
+    visible: &mut Vec<&'v str>,
+    collector: &mut DiagnosticCollector,
+) {
+    let Some(steps) = steps.as_sequence() else {
+        return;
+    };
+    let depth = visible.len();
+    visible.extend(
+        steps
+            .iter()
+            .filter_map(Value::as_mapping)
+            .flat_map(|step| step.keys())
+            .filter_map(Value::as_str),
+    );
+    for (i, step) in steps.iter().enumerate() {
+        let step_node = node.at_index(i);
+        for (name, body) in step.as_mapping().into_iter().flatten() {
+            let body_node = name.as_str().map_or(step_node, |n| step_node.at_key(n));
+            check_jumps_in_body(body, body_node, visible, collector);
         }
     }
+    visible.truncate(depth);
 }
 
-/// Check if a key is a valid step modifier (not an action but valid in step context)
-fn is_step_modifier(key: &str) -> bool {
-    matches!(
-        key,
-        "args"
-            | "result"
-            | "condition"
-            | "value"
-            | "index"
-            | "range"
-            | "in"
-            | "branches"
-            | "shared"
-            | "concurrency_limit"
-            | "exception_policy"
-            | "except"
-            | "retry"
-            | "as"
-            | "steps"
-            | "predicate"
-            | "max_retries"
-            | "backoff"
-            | "initial_delay"
-            | "max_delay"
-            | "multiplier"
-            | "params"
-            | "next"
-    )
-}
-
-/// Simple line index for finding key positions in text
-struct LineIndex {
-    lines: Vec<String>,
-}
-
-impl LineIndex {
-    fn new(text: &str) -> Self {
-        Self {
-            lines: text.lines().map(|l| l.to_string()).collect(),
-        }
-    }
-
-    /// Find the first line containing the given key pattern "key:"
-    fn find_key(&self, key: &str) -> Option<u32> {
-        let pattern = format!("{}:", key);
-        // Also match "- key:" for list items
-        let list_pattern = format!("- {}:", key);
-        // And bare key as a list item name "- key" or "  key:"
-        for (i, line) in self.lines.iter().enumerate() {
-            let trimmed = line.trim();
-            if trimmed.starts_with(&pattern)
-                || trimmed.starts_with(&list_pattern)
-                || trimmed == key
-                || trimmed == format!("{}:", key)
-            {
-                return Some(i as u32);
+/// Check the `next:` jumps in a step body and the blocks nested in it
+fn check_jumps_in_body<'v>(
+    body: &'v Value,
+    node: &SpannedValue,
+    visible: &mut Vec<&'v str>,
+    collector: &mut DiagnosticCollector,
+) {
+    let Some(mapping) = body.as_mapping() else {
+        return;
+    };
+    for (key, val) in mapping {
+        let Some(key) = key.as_str() else {
+            continue;
+        };
+        let field = node.at_key(key);
+        match key {
+            "next" => check_next_target(val, field, visible, collector),
+            "steps" => check_jumps_in_steps(val, field, visible, collector),
+            "try" | "except" | "for" | "parallel" => {
+                check_jumps_in_body(val, field, visible, collector)
+            }
+            "switch" => {
+                let conditions = val.as_sequence().into_iter().flatten();
+                for (i, condition) in conditions.enumerate() {
+                    check_jumps_in_body(condition, field.at_index(i), visible, collector);
+                }
+            }
+            "branches" => {
+                let branches = val.as_sequence().into_iter().flatten();
+                for (i, branch) in branches.enumerate() {
+                    let branch_node = field.at_index(i);
+                    for (name, branch_body) in branch.as_mapping().into_iter().flatten() {
+                        let body_node =
+                            name.as_str().map_or(branch_node, |n| branch_node.at_key(n));
+                        check_jumps_in_body(branch_body, body_node, visible, collector);
+                    }
+                }
             }
+            _ => {}
         }
-        None
     }
 }
 
-/// Find the line where a key appears in the document
-fn find_key_line(line_index: &LineIndex, key: &str) -> u32 {
-    line_index.find_key(key).unwrap_or(0)
+/// Warn when a `next:` value is not a special target or a visible step
+fn check_next_target(
+    value: &Value,
+    node: &SpannedValue,
+    visible: &[&str],
+    collector: &mut DiagnosticCollector,
+) {
+    let Some(target) = value.as_str() else {
+        return;
+    };
+    if is_expression_placeholder(value)
+        || matches!(target, "end" | "break" | "continue")
+        || visible.contains(&target)
+    {
+        return;
+    }
+    warn(
+        collector,
+        format!("'next' target '{}' does not match any step", target),
+        node.range,
+        DiagnosticCode::UnknownStep,
+    );
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::diagnostics::DiagnosticCollector;
+/// Warn when a field of `block` with a fixed shape has a value of the
+/// wrong type
+///
+/// Expression placeholders are skipped since their type is only known at
+/// render or run time.
+fn check_value_type(
+    block: Block,
+    key: &str,
+    value: &Value,
+    at: Range,
+    collector: &mut DiagnosticCollector,
+) {
+    let Some(expected) = block.field(key).and_then(|field| field.value_type) else {
+        return;
+    };
+    if is_expression_placeholder(value) || expected.matches(value) {
+        return;
+    }
+    warn(
+        collector,
+        format!(
+            "'{}' expects {}, found {}",
+            key,
+            expected.name(),
+            describe_value(value)
+        ),
+        at,
+        DiagnosticCode::TypeMismatch,
+    );
+}
 
-    fn parse_and_validate(yaml: &str) -> Vec<tower_lsp::lsp_types::Diagnostic> {
-        let value: Value = serde_yaml::from_str(yaml).expect("test YAML should parse");
-        let mut collector = DiagnosticCollector::new();
-        validate_workflow(&value, yaml, &mut collector);
-        collector.into_diagnostics()
+/// Warn when `call_log_level` is not one of the supported levels
+fn check_call_log_level(value: &Value, at: Range, collector: &mut DiagnosticCollector) {
+    let Some(level) = value.as_str() else {
+        return;
+    };
+    if is_expression_placeholder(value) || crate::schema::CALL_LOG_LEVELS.contains(&level) {
+        return;
     }
+    warn(
+        collector,
+        format!(
+            "'call_log_level' expects one of {}, found '{}'",
+            crate::schema::CALL_LOG_LEVELS.join(", "),
+            level
+        ),
+        at,
+        DiagnosticCode::TypeMismatch,
+    );
+}
 
-    #[test]
-    fn test_valid_workflow() {
-        let yaml = r#"
-main:
-  steps:
-    - init:
-        assign:
-          - result: "hello"
-    - done:
-        return: result
-"#;
-        let diagnostics = parse_and_validate(yaml);
-        assert!(
-            diagnostics.is_empty(),
-            "Expected no diagnostics, got: {:?}",
-            diagnostics
-        );
+/// Check the `args:` of a standard library call against its schema
+///
+/// Calls without a schema (subworkflows, connectors) and `args:` given as an
+/// expression are skipped, as are argument values that are expressions.
+fn check_call_args(
+    call: &Value,
+    args: Option<&Value>,
+    args_node: &SpannedValue,
+    call_at: Range,
+    collector: &mut DiagnosticCollector,
+) {
+    let Some(name) = call.as_str() else {
+        return;
+    };
+    let Some(schema) = crate::schema::call_args(name) else {
+        return;
+    };
+    if args.is_some_and(is_expression_placeholder) {
+        return;
     }
+    let given = args.and_then(Value::as_mapping);
 
-    #[test]
-    fn test_missing_main() {
-        let yaml = r#"
-other:
-  steps:
-    - init:
-        assign:
-          - x: 1
-"#;
-        let diagnostics = parse_and_validate(yaml);
-        // Should not warn about missing main since "other" looks like a subworkflow
-        // Actually it has steps so it IS a subworkflow, but no main
-        assert!(diagnostics.iter().any(|d| d.message.contains("'main'")));
+    for arg in schema.iter().filter(|arg| arg.required) {
+        if !given.is_some_and(|given| given.contains_key(arg.name)) {
+            warn(
+                collector,
+                format!("'{}' requires argument '{}'", name, arg.name),
+                call_at,
+                DiagnosticCode::MissingArgument,
+            );
+        }
     }
 
-    #[test]
-    fn test_main_missing_steps() {
-        let yaml = r#"
-main:
-  params:
-    - name
-"#;
-        let diagnostics = parse_and_validate(yaml);
-        assert!(diagnostics.iter().any(|d| d.message.contains("'steps'")));
+    for (key, value) in given.into_iter().flatten() {
+        let Some(key) = key.as_str() else {
+            continue;
+        };
+        let at = args_node.key_range(key);
+        let Some(arg) = schema.iter().find(|arg| arg.name == key) else {
+            let known: Vec<&str> = schema.iter().map(|arg| arg.name).collect();
+            warn(
+                collector,
+                format!(
+                    "'{}' does not take argument '{}' (expected one of: {})",
+                    name,
+                    key,
+                    known.join(", ")
+                ),
+                at,
+                DiagnosticCode::UnknownArgument,
+            );
+            continue;
+        };
+        let Some(expected) = arg.value_type else {
+            continue;
+        };
+        if is_expression_placeholder(value) || expected.matches(value) {
+            continue;
+        }
+        warn(
+            collector,
+            format!(
+                "Argument '{}' of '{}' expects {}, found {}",
+                key,
+                name,
+                expected.name(),
+                describe_value(value)
+            ),
+            at,
+            DiagnosticCode::TypeMismatch,
+        );
     }
 
-    #[test]
-    fn test_steps_not_a_list() {
-        let yaml = r#"
-main:
-  steps:
-    init:
-      assign:
-        - x: 1
-"#;
-        let diagnostics = parse_and_validate(yaml);
-        assert!(diagnostics
-            .iter()
-            .any(|d| d.message.contains("'steps' must be a list")));
+    if let Some(given) = given.filter(|_| crate::schema::is_http_request(name)) {
+        check_http_args(name, given, args_node, collector);
     }
+}
 
-    #[test]
-    fn test_unknown_top_level_key() {
-        let yaml = r#"
-main:
-  steps:
-    - init:
-        assign:
-          - x: 1
-something_else: true
-"#;
-        let diagnostics = parse_and_validate(yaml);
-        assert!(diagnostics
-            .iter()
-            .any(|d| d.message.contains("Unknown workflow element")));
+/// Check the `method`, `timeout` and `auth` an `http.*` request passes
+/// beyond their types
+fn check_http_args(
+    name: &str,
+    args: &serde_yaml::Mapping,
+    node: &SpannedValue,
+    collector: &mut DiagnosticCollector,
+) {
+    use crate::schema::{HTTP_AUTH_ARGS, HTTP_AUTH_TYPES, HTTP_METHODS, MAX_HTTP_TIMEOUT};
+
+    let literal = |key: &str| args.get(key).filter(|v| !is_expression_placeholder(v));
+    if let Some(method) = literal("method").and_then(Value::as_str) {
+        if !HTTP_METHODS.iter().any(|m| m.eq_ignore_ascii_case(method)) {
+            warn(
+                collector,
+                format!(
+                    "Argument 'method' of '{}' expects one of {}, found '{}'",
+                    name,
+                    HTTP_METHODS.join(", "),
+                    method
+                ),
+                node.key_range("method"),
+                DiagnosticCode::TypeMismatch,
+            );
+        }
     }
 
-    #[test]
-    fn test_valid_subworkflow() {
-        let yaml = r#"
+    if let Some(timeout) = literal("timeout").and_then(Value::as_f64) {
+        let at = node.key_range("timeout");
+        if timeout <= 0.0 {
+            warn(
+                collector,
+                format!(
+                    "Argument 'timeout' of '{}' must be positive, found {}",
+                    name, timeout
+                ),
+                at,
+                DiagnosticCode::TypeMismatch,
+            );
+        } else if timeout > MAX_HTTP_TIMEOUT {
+            warn(
+                collector,
+                format!(
+                    "Argument 'timeout' of '{}' is {} seconds, past the limit of {}",
+                    name, timeout, MAX_HTTP_TIMEOUT
+                ),
+                at,
+                DiagnosticCode::QuotaExceeded,
+            );
+        }
+    }
+
+    let Some(auth) = literal("auth").and_then(Value::as_mapping) else {
+        return;
+    };
+    let auth_node = node.at_key("auth");
+    if !auth.contains_key("type") {
+        warn(
+            collector,
+            format!("'auth' of '{}' requires 'type'", name),
+            node.key_range("auth"),
+            DiagnosticCode::MissingArgument,
+        );
+    }
+    for (key, value) in auth {
+        let Some(key) = key.as_str() else {
+            continue;
+        };
+        let at = auth_node.key_range(key);
+        let Some(arg) = HTTP_AUTH_ARGS.iter().find(|arg| arg.name == key) else {
+            let known: Vec<&str> = HTTP_AUTH_ARGS.iter().map(|arg| arg.name).collect();
+            warn(
+                collector,
+                format!(
+                    "'auth' of '{}' does not take '{}' (expected one of: {})",
+                    name,
+                    key,
+                    known.join(", ")
+                ),
+                at,
+                DiagnosticCode::UnknownArgument,
+            );
+            continue;
+        };
+        if is_expression_placeholder(value) {
+            continue;
+        }
+        if let Some(expected) = arg.value_type.filter(|expected| !expected.matches(value)) {
+            warn(
+                collector,
+                format!(
+                    "'auth.{}' of '{}' expects {}, found {}",
+                    key,
+                    name,
+                    expected.name(),
+                    describe_value(value)
+                ),
+                at,
+                DiagnosticCode::TypeMismatch,
+            );
+        } else if let Some(kind) = value.as_str().filter(|_| key == "type") {
+            if !HTTP_AUTH_TYPES.contains(&kind) {
+                warn(
+                    collector,
+                    format!(
+                        "'auth.type' of '{}' expects one of {}, found '{}'",
+                        name,
+                        HTTP_AUTH_TYPES.join(", "),
+                        kind
+                    ),
+                    at,
+                    DiagnosticCode::TypeMismatch,
+                );
+            }
+        }
+    }
+}
+
+/// Check the `args:` of a call to a subworkflow against its `params`
+fn check_subworkflow_args(
+    call: &Value,
+    params: &[(&str, bool)],
+    args: Option<&Value>,
+    args_node: &SpannedValue,
+    call_at: Range,
+    collector: &mut DiagnosticCollector,
+) {
+    let name = call.as_str().unwrap_or_default();
+    if args.is_some_and(is_expression_placeholder) {
+        return;
+    }
+    let given = args.and_then(Value::as_mapping);
+
+    for (param, _) in params.iter().filter(|(_, required)| *required) {
+        if !given.is_some_and(|given| given.contains_key(*param)) {
+            warn(
+                collector,
+                format!("Subworkflow '{}' requires argument '{}'", name, param),
+                call_at,
+                DiagnosticCode::MissingArgument,
+            );
+        }
+    }
+
+    for key in given.into_iter().flatten().filter_map(|(k, _)| k.as_str()) {
+        if params.iter().any(|(param, _)| *param == key) {
+            continue;
+        }
+        let expected = if params.is_empty() {
+            "it declares no params".to_string()
+        } else {
+            let names: Vec<&str> = params.iter().map(|(param, _)| *param).collect();
+            format!("expected one of: {}", names.join(", "))
+        };
+        warn(
+            collector,
+            format!(
+                "Subworkflow '{}' has no param '{}' ({})",
+                name, key, expected
+            ),
+            args_node.key_range(key),
+            DiagnosticCode::UnknownArgument,
+        );
+    }
+}
+
+/// Check the structure of a `parallel` body beyond the field types
+fn check_parallel(value: &Value, parallel: Located, collector: &mut DiagnosticCollector) {
+    let Some(body) = value.as_mapping() else {
+        return;
+    };
+    match (body.contains_key("branches"), body.contains_key("for")) {
+        (true, true) => warn(
+            collector,
+            "'parallel' has both 'branches' and 'for'; use one or the other".to_string(),
+            parallel.key,
+            DiagnosticCode::WorkflowStructure,
+        ),
+        (false, false) => warn(
+            collector,
+            "'parallel' needs 'branches' or a 'for' loop".to_string(),
+            parallel.key,
+            DiagnosticCode::WorkflowStructure,
+        ),
+        _ => {}
+    }
+
+    if let Some(shared) = body.get("shared").and_then(Value::as_sequence) {
+        let shared_node = parallel.node.at_key("shared");
+        for (i, entry) in shared.iter().enumerate() {
+            let Some(name) = entry.as_str() else {
+                continue;
+            };
+            if !is_variable_name(name) {
+                warn(
+                    collector,
+                    format!("'shared' entry '{}' is not a variable name", name),
+                    shared_node.at_index(i).range,
+                    DiagnosticCode::TypeMismatch,
+                );
+            }
+        }
+    }
+
+    if let Some(limit) = body.get("concurrency_limit").and_then(Value::as_i64) {
+        if limit <= 0 {
+            warn(
+                collector,
+                format!("'concurrency_limit' must be positive, found {}", limit),
+                parallel.node.key_range("concurrency_limit"),
+                DiagnosticCode::TypeMismatch,
+            );
+        }
+    }
+}
+
+/// Whether `name` is a plain variable name (letters, digits, underscores)
+fn is_variable_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Check that every `switch` entry has a `condition` and one outcome
+///
+/// A `switch` that is not a list is left to the type check.
+fn check_switch_entries(value: &Value, node: &SpannedValue, collector: &mut DiagnosticCollector) {
+    let Some(entries) = value.as_sequence() else {
+        return;
+    };
+    for (i, entry) in entries.iter().enumerate() {
+        let entry_node = node.at_index(i);
+        let Some(entry) = entry.as_mapping() else {
+            warn(
+                collector,
+                format!(
+                    "'switch' entry must be a mapping with a 'condition', found {}",
+                    describe_value(entry)
+                ),
+                entry_node.head(),
+                DiagnosticCode::WorkflowStructure,
+            );
+            continue;
+        };
+        let keys: Vec<&str> = entry.keys().filter_map(Value::as_str).collect();
+        let at = keys
+            .first()
+            .map_or_else(|| entry_node.head(), |first| entry_node.key_range(first));
+
+        if let Some(assign) = entry.get("assign") {
+            check_assign_entries(assign, Located::at_key(entry_node, "assign"), collector);
+        }
+        let block = Block::SwitchCondition;
+        for key in &keys {
+            check_block_key(
+                block,
+                block.describe(),
+                key,
+                entry_node.key_range(key),
+                collector,
+            );
+        }
+        if !keys.contains(&"condition") {
+            warn(
+                collector,
+                "'switch' entry is missing 'condition'".to_string(),
+                at,
+                DiagnosticCode::WorkflowStructure,
+            );
+        }
+
+        let outcomes: Vec<String> = keys
+            .iter()
+            .filter(|k| switch_outcomes().any(|outcome| outcome == **k))
+            .map(|k| format!("'{}'", k))
+            .collect();
+        let message = match outcomes.len() {
+            0 => format!(
+                "'switch' entry needs one of {}",
+                switch_outcomes()
+                    .map(|k| format!("'{}'", k))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            1 => continue,
+            _ => format!(
+                "'switch' entry has {}, but takes only one of them",
+                outcomes.join(" and ")
+            ),
+        };
+        warn(collector, message, at, DiagnosticCode::WorkflowStructure);
+    }
+}
+
+/// Check that every `assign` entry is a mapping of one variable to its value
+///
+/// An `assign` that is not a list is left to the type check, and entries
+/// given as an expression are skipped.
+fn check_assign_entries(value: &Value, assign: Located, collector: &mut DiagnosticCollector) {
+    let Some(entries) = value.as_sequence() else {
+        return;
+    };
+    for (i, entry) in entries.iter().enumerate() {
+        let message = match entry.as_mapping() {
+            _ if is_expression_placeholder(entry) => continue,
+            None => format!(
+                "'assign' entry must be a 'name: value' mapping, found {}",
+                describe_value(entry)
+            ),
+            Some(vars) if vars.is_empty() => {
+                "'assign' entry is empty; expected 'name: value'".to_string()
+            }
+            Some(vars) if vars.len() > 1 => format!(
+                "'assign' entry sets {}; each variable needs its own list item",
+                vars.keys()
+                    .map(|k| format!("'{}'", k.as_str().unwrap_or("?")))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Some(_) => continue,
+        };
+        warn(
+            collector,
+            message,
+            assign.item_head(i),
+            DiagnosticCode::WorkflowStructure,
+        );
+    }
+}
+
+/// Check that each `params` entry is a param name or a single
+/// `name: default` mapping, and that the name is an identifier
+fn check_params_entries(value: &Value, params: Located, collector: &mut DiagnosticCollector) {
+    let Some(entries) = value.as_sequence() else {
+        return;
+    };
+    for (i, entry) in entries.iter().enumerate() {
+        let (message, code) = match entry {
+            _ if is_expression_placeholder(entry) => continue,
+            Value::String(name) => (invalid_param_name(name), DiagnosticCode::TypeMismatch),
+            Value::Mapping(param) if param.is_empty() => (
+                Some("'params' entry is empty; expected a name or 'name: default'".to_string()),
+                DiagnosticCode::WorkflowStructure,
+            ),
+            Value::Mapping(param) if param.len() > 1 => (
+                Some(format!(
+                    "'params' entry declares {}; each param needs its own list item",
+                    param
+                        .keys()
+                        .map(|k| format!("'{}'", k.as_str().unwrap_or("?")))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )),
+                DiagnosticCode::WorkflowStructure,
+            ),
+            Value::Mapping(param) => match param.iter().next() {
+                Some((Value::String(name), default)) if default.is_mapping() => (
+                    Some(format!(
+                        "Default of param '{}' is a mapping; a default must be a single value, and nested params are not supported",
+                        name
+                    )),
+                    DiagnosticCode::TypeMismatch,
+                ),
+                Some((Value::String(name), _)) => {
+                    (invalid_param_name(name), DiagnosticCode::TypeMismatch)
+                }
+                _ => (
+                    Some("'params' entry name must be a string".to_string()),
+                    DiagnosticCode::TypeMismatch,
+                ),
+            },
+            other => (
+                Some(format!(
+                    "'params' entry must be a name or a 'name: default' mapping, found {}",
+                    describe_value(other)
+                )),
+                DiagnosticCode::WorkflowStructure,
+            ),
+        };
+        let Some(message) = message else {
+            continue;
+        };
+        warn(collector, message, params.item_head(i), code);
+    }
+}
+
+/// The warning for a param name that isn't an identifier, if it isn't one
+fn invalid_param_name(name: &str) -> Option<String> {
+    (!is_variable_name(name)).then(|| format!("Param name '{}' is not a valid identifier", name))
+}
+
+/// Check the keys and value types of a `retry` policy
+///
+/// A policy given as an expression, such as `${http.default_retry}`, is
+/// skipped.
+fn check_retry_policy(value: &Value, retry: Located, collector: &mut DiagnosticCollector) {
+    if is_expression_placeholder(value) {
+        return;
+    }
+    let Some(policy) = value.as_mapping() else {
+        warn(
+            collector,
+            format!(
+                "'retry' expects mapping or expression, found {}",
+                describe_value(value)
+            ),
+            retry.key,
+            DiagnosticCode::TypeMismatch,
+        );
+        return;
+    };
+
+    for (key, val) in policy {
+        let Some(key) = key.as_str() else {
+            continue;
+        };
+        let at = retry.node.key_range(key);
+        if Block::Backoff.field(key).is_some() {
+            warn(
+                collector,
+                format!("'{}' belongs under 'backoff'", key),
+                at,
+                DiagnosticCode::WorkflowStructure,
+            );
+            continue;
+        }
+        if Block::Retry.field(key).is_none() {
+            warn(
+                collector,
+                format!(
+                    "Unknown retry key '{}' (expected one of: {})",
+                    key,
+                    Block::Retry.names().collect::<Vec<_>>().join(", ")
+                ),
+                at,
+                DiagnosticCode::WorkflowStructure,
+            );
+            continue;
+        }
+        check_value_type(Block::Retry, key, val, at, collector);
+
+        if key == "backoff" {
+            let backoff = retry.node.at_key("backoff");
+            for (inner_key, inner_val) in val.as_mapping().into_iter().flatten() {
+                let Some(inner) = inner_key.as_str() else {
+                    continue;
+                };
+                let inner_at = backoff.key_range(inner);
+                if Block::Backoff.field(inner).is_some() {
+                    check_value_type(Block::Backoff, inner, inner_val, inner_at, collector);
+                } else {
+                    warn(
+                        collector,
+                        format!(
+                            "Unknown backoff key '{}' (expected one of: {})",
+                            inner,
+                            Block::Backoff.names().collect::<Vec<_>>().join(", ")
+                        ),
+                        inner_at,
+                        DiagnosticCode::WorkflowStructure,
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Whether a value is a whole `__EXPR_NNN__` placeholder
+fn is_expression_placeholder(value: &Value) -> bool {
+    value
+        .as_str()
+        .is_some_and(|s| s.starts_with("__EXPR_") && s.ends_with("__"))
+}
+
+/// Short description of a value's type for diagnostics
+pub(super) fn describe_value(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_f64() => "number",
+        Value::Number(_) => "integer",
+        Value::String(_) => "string",
+        Value::Sequence(_) => "list",
+        Value::Mapping(_) => "mapping",
+        Value::Tagged(_) => "tagged value",
+    }
+}
+
+/// Hint at a key that `block`, described as `here`, doesn't take, naming
+/// the mapping it belongs in when another one does
+fn check_block_key(
+    block: Block,
+    here: &str,
+    key: &str,
+    at: Range,
+    collector: &mut DiagnosticCollector,
+) {
+    if block.field(key).is_some() {
+        return;
+    }
+    let message = match Block::of(key) {
+        Some(home) => format!(
+            "'{}' is not valid inside {}; it belongs in {}",
+            key,
+            here,
+            home.describe()
+        ),
+        None if block == Block::Step => format!("Unknown step action: '{}'", key),
+        None => format!("Unknown key '{}' in {}", key, here),
+    };
+    collector.add_hint_with_range(message, at, DiagnosticCode::UnknownKeyword);
+}
+
+/// A step as messages name it, by its action: "a 'call' step"
+fn describe_step(mapping: &serde_yaml::Mapping) -> String {
+    let kinds: Vec<StepKind> = mapping
+        .keys()
+        .filter_map(Value::as_str)
+        .filter_map(StepKind::from_key)
+        .collect();
+    // `next` is an action on its own, but usually follows another one
+    kinds
+        .iter()
+        .find(|kind| **kind != StepKind::Next)
+        .or(kinds.first())
+        .map_or_else(
+            || Block::Step.describe().to_string(),
+            |kind| format!("a '{}' step", kind.action().name),
+        )
+}
+
+/// The keys that say what a `switch` entry does when it matches; each entry
+/// takes exactly one
+fn switch_outcomes() -> impl Iterator<Item = &'static str> {
+    Block::SwitchCondition
+        .fields()
+        .iter()
+        .filter(|field| !field.required)
+        .map(|field| field.name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostics::DiagnosticCollector;
+    use tower_lsp::lsp_types::{NumberOrString, Position};
+
+    fn parse_and_validate(yaml: &str) -> Vec<tower_lsp::lsp_types::Diagnostic> {
+        let value: Value = serde_yaml::from_str(yaml).expect("test YAML should parse");
+        let mut collector = DiagnosticCollector::new();
+        validate_workflow(&value, yaml, SchemaExtensions::default(), &mut collector);
+        collector.into_diagnostics()
+    }
+
+    #[test]
+    fn test_valid_workflow() {
+        let yaml = r#"
+main:
+  steps:
+    - init:
+        assign:
+          - result: "hello"
+    - done:
+        return: result
+"#;
+        let diagnostics = parse_and_validate(yaml);
+        assert!(
+            diagnostics.is_empty(),
+            "Expected no diagnostics, got: {:?}",
+            diagnostics
+        );
+    }
+
+    #[test]
+    fn test_missing_main() {
+        let yaml = r#"
+other:
+  steps:
+    - init:
+        assign:
+          - x: 1
+"#;
+        let diagnostics = parse_and_validate(yaml);
+        // Should not warn about missing main since "other" looks like a subworkflow
+        // Actually it has steps so it IS a subworkflow, but no main
+        assert!(diagnostics.iter().any(|d| d.message.contains("'main'")));
+    }
+
+    #[test]
+    fn test_main_missing_steps() {
+        let yaml = r#"
+main:
+  params:
+    - name
+"#;
+        let diagnostics = parse_and_validate(yaml);
+        assert!(diagnostics.iter().any(|d| d.message.contains("'steps'")));
+    }
+
+    #[test]
+    fn test_steps_not_a_list() {
+        let yaml = r#"
+main:
+  steps:
+    init:
+      assign:
+        - x: 1
+"#;
+        let diagnostics = parse_and_validate(yaml);
+        assert!(diagnostics
+            .iter()
+            .any(|d| d.message.contains("'steps' must be a list")));
+    }
+
+    #[test]
+    fn test_unknown_top_level_key() {
+        let yaml = r#"
+main:
+  steps:
+    - init:
+        assign:
+          - x: 1
+something_else: true
+"#;
+        let diagnostics = parse_and_validate(yaml);
+        assert!(diagnostics
+            .iter()
+            .any(|d| d.message.contains("Unknown workflow element")));
+    }
+
+    #[test]
+    fn test_http_args() {
+        let yaml = r#"
+main:
+  steps:
+    - get:
+        call: http.request
+        args:
+          method: FETCH
+          url: https://example.com
+          timeout: 3600
+          auth:
+            type: Basic
+            user: me
+    - post:
+        call: http.post
+        args:
+          url: https://example.com
+          timeout: 0
+          auth:
+            audience: __EXPR_000__
+    - ok:
+        call: http.get
+        args:
+          url: https://example.com
+          timeout: 1800
+          auth:
+            type: OIDC
+"#;
+        let messages: Vec<(String, u32)> = parse_and_validate(yaml)
+            .into_iter()
+            .map(|d| (d.message, d.range.start.line))
+            .collect();
+        let expected = [
+            (
+                "Argument 'method' of 'http.request' expects one of GET, HEAD, POST, PUT, DELETE, PATCH, OPTIONS, found 'FETCH'",
+                6,
+            ),
+            (
+                "Argument 'timeout' of 'http.request' is 3600 seconds, past the limit of 1800",
+                8,
+            ),
+            (
+                "'auth.type' of 'http.request' expects one of OIDC, OAuth2, found 'Basic'",
+                10,
+            ),
+            (
+                "'auth' of 'http.request' does not take 'user' (expected one of: type, audience, scopes, scope)",
+                11,
+            ),
+            ("Argument 'timeout' of 'http.post' must be positive, found 0", 16),
+            ("'auth' of 'http.post' requires 'type'", 17),
+        ];
+        assert_eq!(
+            messages,
+            expected
+                .iter()
+                .map(|(m, l)| (m.to_string(), *l))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_allowlisted_keys() {
+        let yaml = "metadata: x\nmain:\n  steps:\n    - get:\n        call: sys.now\n        owner: data\n        cost: 1\nother: 1\n";
+        let value: Value = serde_yaml::from_str(yaml).unwrap();
+        let top_level_keys = ["metadata".to_string()];
+        let step_keys = ["owner".to_string()];
+        let extensions = SchemaExtensions {
+            top_level_keys: &top_level_keys,
+            step_keys: &step_keys,
+            ..SchemaExtensions::default()
+        };
+        let mut collector = DiagnosticCollector::new();
+        validate_workflow(&value, yaml, extensions, &mut collector);
+        let hints: Vec<String> = collector
+            .into_diagnostics()
+            .into_iter()
+            .filter(|d| d.code == Some(NumberOrString::String("unknown-keyword".into())))
+            .map(|d| d.message)
+            .collect();
+        assert_eq!(
+            hints,
+            vec![
+                "Unknown step action: 'cost'",
+                "Unknown workflow element: 'other'"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_keys_in_the_wrong_block() {
+        let yaml = r#"
+main:
+  steps:
+    - get:
+        call: http.get
+        args:
+          url: https://example.com
+        condition: true
+        custom: 1
+    - loop:
+        for:
+          value: v
+          in: [1]
+          branches: []
+          steps:
+            - a:
+                next: end
+    - pick:
+        switch:
+          - condition: true
+            max_retries: 3
+            next: end
+"#;
+        let diagnostics = parse_and_validate(yaml);
+        let hints: Vec<(&str, u32)> = diagnostics
+            .iter()
+            .filter(|d| d.code == Some(NumberOrString::String("unknown-keyword".into())))
+            .map(|d| (d.message.as_str(), d.range.start.line))
+            .collect();
+        assert_eq!(
+            hints,
+            vec![
+                (
+                    "'condition' is not valid inside a 'call' step; it belongs in a 'switch' entry",
+                    7
+                ),
+                ("Unknown step action: 'custom'", 8),
+                (
+                    "'branches' is not valid inside a 'for' loop; it belongs in a 'parallel' step",
+                    13
+                ),
+                (
+                    "'max_retries' is not valid inside a 'switch' entry; it belongs in a 'retry' policy",
+                    20
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_valid_subworkflow() {
+        let yaml = r#"
 main:
   steps:
     - callSub:
@@ -376,12 +1690,762 @@ main:
         );
     }
 
+    #[test]
+    fn test_value_type_mismatches() {
+        let yaml = r#"
+main:
+  steps:
+    - fanout:
+        parallel:
+          concurrency_limit: "two"
+          shared: [a, 1]
+          branches:
+            - b1:
+                steps:
+                  - x:
+                      return: 1
+    - callIt:
+        call: [not, a, string]
+        args: value
+"#;
+        let diagnostics = parse_and_validate(yaml);
+        let messages: Vec<&str> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+        assert!(messages.contains(&"'concurrency_limit' expects integer, found string"));
+        assert!(messages.contains(&"'shared' expects list of strings, found list"));
+        assert!(messages.contains(&"'call' expects string, found list"));
+        assert!(messages.contains(&"'args' expects mapping, found string"));
+
+        let call = diagnostics
+            .iter()
+            .find(|d| d.message.starts_with("'call'"))
+            .unwrap();
+        assert_eq!(call.range.start.line, 13);
+    }
+
+    #[test]
+    fn test_labels_and_call_log_level() {
+        let yaml = r#"
+main:
+  labels:
+    team: data
+  steps:
+    - quiet:
+        call: helper
+        call_log_level: LOG_NONE
+    - loud:
+        call: helper
+        call_log_level: VERBOSE
+helper:
+  labels: [not, a, mapping]
+  steps:
+    - done:
+        return: 1
+"#;
+        let diagnostics = parse_and_validate(yaml);
+        let messages: Vec<&str> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "'call_log_level' expects one of LOG_ALL_CALLS, LOG_ERRORS_ONLY, LOG_NONE, found 'VERBOSE'",
+                "'labels' expects mapping, found list",
+            ]
+        );
+        assert_eq!(diagnostics[0].range.start.line, 10);
+        assert_eq!(diagnostics[1].range.start.line, 12);
+    }
+
+    #[test]
+    fn test_value_type_skips_expressions() {
+        let yaml = r#"
+main:
+  steps:
+    - fanout:
+        parallel:
+          concurrency_limit: __EXPR_000__
+          branches:
+            - b1:
+                steps:
+                  - x:
+                      return: 1
+"#;
+        let diagnostics = parse_and_validate(yaml);
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+    }
+
+    #[test]
+    fn test_call_args() {
+        let yaml = r#"
+main:
+  steps:
+    - fetch:
+        call: http.get
+        args:
+          url: https://example.com
+          timeout: 30.5
+          headers: __EXPR_000__
+    - post:
+        call: http.post
+        args:
+          uri: https://example.com
+          timeout: soon
+    - nap:
+        call: sys.sleep
+    - dynamic:
+        call: sys.sleep
+        args: __EXPR_001__
+    - sub:
+        call: helper
+        args:
+          anything: 1
+"#;
+        let diagnostics = parse_and_validate(yaml);
+        let found: Vec<(&str, u32)> = diagnostics
+            .iter()
+            .map(|d| (d.message.as_str(), d.range.start.line))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("'http.post' requires argument 'url'", 10),
+                ("'http.post' does not take argument 'uri' (expected one of: url, body, headers, query, auth, timeout, private_service_name)", 12),
+                ("Argument 'timeout' of 'http.post' expects number, found string", 13),
+                ("'sys.sleep' requires argument 'seconds'", 15),
+            ]
+        );
+        assert_eq!(
+            diagnostics[0].code,
+            Some(NumberOrString::String("missing-argument".to_string()))
+        );
+        assert_eq!(
+            diagnostics[1].code,
+            Some(NumberOrString::String("unknown-argument".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_subworkflow_args() {
+        let yaml = r#"
+main:
+  steps:
+    - ok:
+        call: greet
+        args:
+          name: world
+    - bad:
+        call: greet
+        args:
+          greeting: hi
+          loud: true
+    - none:
+        call: noop
+        args:
+          x: 1
+    - dynamic:
+        call: dynamic
+        args:
+          anything: 1
+greet:
+  params: [name, greeting: "Hello"]
+  steps:
+    - done:
+        return: __EXPR_000__
+noop:
+  steps:
+    - done:
+        return: 1
+dynamic:
+  params: __EXPR_001__
+  steps:
+    - done:
+        return: 1
+"#;
+        let diagnostics = parse_and_validate(yaml);
+        let found: Vec<(&str, u32)> = diagnostics
+            .iter()
+            .map(|d| (d.message.as_str(), d.range.start.line))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("Subworkflow 'greet' requires argument 'name'", 8),
+                (
+                    "Subworkflow 'greet' has no param 'loud' (expected one of: name, greeting)",
+                    11
+                ),
+                (
+                    "Subworkflow 'noop' has no param 'x' (it declares no params)",
+                    15
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unused_subworkflows() {
+        let yaml = r#"
+main:
+  steps:
+    - run:
+        try:
+          call: used
+helper:
+  steps:
+    - again:
+        call: helper
+used:
+  params: [x]
+  steps:
+    - done:
+        return: 1
+"#;
+        let diagnostics = parse_and_validate(yaml);
+        let unused: Vec<_> = diagnostics
+            .iter()
+            .filter(|d| d.code == Some(NumberOrString::String("unused-subworkflow".to_string())))
+            .collect();
+        assert_eq!(unused.len(), 1);
+        assert_eq!(unused[0].message, "Subworkflow 'helper' is never called");
+        assert_eq!(
+            unused[0].range,
+            Range::new(Position::new(6, 0), Position::new(6, 6))
+        );
+        assert_eq!(
+            unused[0].tags,
+            Some(vec![tower_lsp::lsp_types::DiagnosticTag::UNNECESSARY])
+        );
+    }
+
+    #[test]
+    fn test_retry_policy() {
+        let yaml = r#"
+main:
+  steps:
+    - good:
+        try:
+          call: helper
+        retry:
+          predicate: __EXPR_000__
+          max_retries: 5
+          backoff:
+            initial_delay: 1
+            max_delay: 60.5
+            multiplier: __EXPR_001__
+    - default:
+        try:
+          call: helper
+        retry: __EXPR_002__
+    - bad:
+        try:
+          call: helper
+        retry:
+          max_retries: "five"
+          initial_delay: 1
+          attempts: 3
+          backoff:
+            max_delay: soon
+            jitter: true
+    - flat:
+        try:
+          call: helper
+        retry: [1, 2]
+    - scalar_backoff:
+        try:
+          call: helper
+        retry:
+          backoff: 10
+"#;
+        let diagnostics = parse_and_validate(yaml);
+        let found: Vec<(&str, u32)> = diagnostics
+            .iter()
+            .map(|d| (d.message.as_str(), d.range.start.line))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("'max_retries' expects integer, found string", 21),
+                ("'initial_delay' belongs under 'backoff'", 22),
+                ("Unknown retry key 'attempts' (expected one of: predicate, max_retries, backoff)", 23),
+                ("'max_delay' expects number, found string", 25),
+                ("Unknown backoff key 'jitter' (expected one of: initial_delay, max_delay, multiplier)", 26),
+                ("'retry' expects mapping or expression, found list", 30),
+                ("'backoff' expects mapping, found integer", 35),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_misspelled_steps_is_one_warning() {
+        let yaml = r#"
+main:
+  params: [input]
+  step:
+    - init:
+        assign:
+          - x: 1
+    - done:
+        return: x
+"#;
+        let diagnostics = parse_and_validate(yaml);
+        assert_eq!(diagnostics.len(), 1, "{:?}", diagnostics);
+        let d = &diagnostics[0];
+        assert_eq!(
+            d.message,
+            "'main' block has 'step' instead of 'steps'; its steps are ignored"
+        );
+        assert_eq!(
+            d.range,
+            Range::new(Position::new(3, 2), Position::new(3, 6))
+        );
+        let related: Vec<(u32, &str)> = d
+            .related_information
+            .iter()
+            .flatten()
+            .map(|r| (r.location.range.start.line, r.message.as_str()))
+            .collect();
+        assert_eq!(
+            related,
+            vec![
+                (4, "step 'init' is not validated"),
+                (7, "step 'done' is not validated")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_stranded_block_keys_are_one_warning() {
+        let yaml = r#"
+main:
+params: [input]
+steps:
+  - done:
+      return: input
+"#;
+        let diagnostics = parse_and_validate(yaml);
+        assert_eq!(diagnostics.len(), 1, "{:?}", diagnostics);
+        let d = &diagnostics[0];
+        assert_eq!(
+            d.message,
+            "'main' is empty, but 'params', 'steps' after it look like its contents; indent them under 'main'"
+        );
+        assert_eq!(d.range.start.line, 1);
+        let related: Vec<u32> = d
+            .related_information
+            .iter()
+            .flatten()
+            .map(|r| r.location.range.start.line)
+            .collect();
+        assert_eq!(related, vec![2, 3]);
+    }
+
+    #[test]
+    fn test_is_misspelling_of() {
+        assert!(is_misspelling_of("step", "steps"));
+        assert!(is_misspelling_of("stpes", "steps"));
+        assert!(!is_misspelling_of("steps", "steps"));
+        assert!(!is_misspelling_of("params", "steps"));
+    }
+
+    #[test]
+    fn test_parallel_structure() {
+        let yaml = r#"
+main:
+  steps:
+    - both:
+        parallel:
+          shared: [total, "user.name"]
+          concurrency_limit: 0
+          branches:
+            - b1:
+                steps:
+                  - x:
+                      return: 1
+          for:
+            value: item
+            in: [1, 2]
+            steps:
+              - y:
+                  return: 2
+    - neither:
+        parallel:
+          concurrency_limit: __EXPR_000__
+    - fine:
+        parallel:
+          shared: [total]
+          concurrency_limit: 2
+          for:
+            value: item
+            in: [1, 2]
+            steps:
+              - z:
+                  return: 3
+"#;
+        let diagnostics = parse_and_validate(yaml);
+        let found: Vec<(&str, u32)> = diagnostics
+            .iter()
+            .map(|d| (d.message.as_str(), d.range.start.line))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (
+                    "'parallel' has both 'branches' and 'for'; use one or the other",
+                    4
+                ),
+                ("'shared' entry 'user.name' is not a variable name", 5),
+                ("'concurrency_limit' must be positive, found 0", 6),
+                ("'parallel' needs 'branches' or a 'for' loop", 19),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_switch_entries() {
+        let yaml = r#"
+main:
+  steps:
+    - route:
+        switch:
+          - condition: __EXPR_000__
+            next: done
+          - condition: __EXPR_001__
+            assign:
+              - x: 1
+          - next: done
+          - condition: true
+          - condition: false
+            next: done
+            return: 1
+          - just a string
+    - done:
+        return: 1
+"#;
+        let diagnostics = parse_and_validate(yaml);
+        let found: Vec<(&str, u32)> = diagnostics
+            .iter()
+            .map(|d| (d.message.as_str(), d.range.start.line))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("'switch' entry is missing 'condition'", 10),
+                (
+                    "'switch' entry needs one of 'next', 'steps', 'return', 'raise', 'assign'",
+                    11
+                ),
+                (
+                    "'switch' entry has 'next' and 'return', but takes only one of them",
+                    12
+                ),
+                (
+                    "'switch' entry must be a mapping with a 'condition', found string",
+                    15
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_assign_entries() {
+        let yaml = r#"
+main:
+  steps:
+    - init:
+        assign:
+          - ok: 1
+          - list:
+              - a: 1
+                b: 2
+          - just_a_name
+          - {}
+          - x: 1
+            y: 2
+          - __EXPR_000__
+    - route:
+        switch:
+          - condition: true
+            assign:
+              - z: 1
+              - 5
+"#;
+        let diagnostics = parse_and_validate(yaml);
+        let found: Vec<(&str, u32, u32)> = diagnostics
+            .iter()
+            .map(|d| {
+                (
+                    d.message.as_str(),
+                    d.range.start.line,
+                    d.range.start.character,
+                )
+            })
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (
+                    "'assign' entry must be a 'name: value' mapping, found string",
+                    9,
+                    12
+                ),
+                ("'assign' entry is empty; expected 'name: value'", 10, 12),
+                (
+                    "'assign' entry sets 'x', 'y'; each variable needs its own list item",
+                    11,
+                    12
+                ),
+                (
+                    "'assign' entry must be a 'name: value' mapping, found integer",
+                    19,
+                    16
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_params_entries() {
+        let yaml = r#"
+main:
+  params:
+    - ok
+    - greeting: "Hello"
+    - bad-name
+    - config:
+        depth: 1
+    - {}
+    - a: 1
+      b: 2
+    - 7
+    - __EXPR_000__
+  steps:
+    - helper:
+        call: helper
+        args:
+          ok2: 1
+          name: x
+helper:
+  params: [ok2, 2name: "x", name]
+  steps:
+    - done:
+        return: 1
+"#;
+        let diagnostics = parse_and_validate(yaml);
+        let found: Vec<(&str, u32, u32)> = diagnostics
+            .iter()
+            .filter(|d| d.message.to_lowercase().contains("param"))
+            .map(|d| {
+                (
+                    d.message.as_str(),
+                    d.range.start.line,
+                    d.range.start.character,
+                )
+            })
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("Param name 'bad-name' is not a valid identifier", 5, 6),
+                (
+                    "Default of param 'config' is a mapping; a default must be a single value, and nested params are not supported",
+                    6,
+                    6
+                ),
+                (
+                    "'params' entry is empty; expected a name or 'name: default'",
+                    8,
+                    6
+                ),
+                (
+                    "'params' entry declares 'a', 'b'; each param needs its own list item",
+                    9,
+                    6
+                ),
+                (
+                    "'params' entry must be a name or a 'name: default' mapping, found integer",
+                    11,
+                    6
+                ),
+                ("Param name '2name' is not a valid identifier", 20, 16),
+            ]
+        );
+
+        let diagnostics =
+            parse_and_validate("main:\n  params: args\n  steps:\n    - done:\n        return: 1\n");
+        assert_eq!(
+            diagnostics[0].message,
+            "'params' expects list, found string"
+        );
+    }
+
+    #[test]
+    fn test_repeated_key_names_reported_where_written() {
+        let yaml = r#"
+main:
+  steps:
+    - init:
+        foo: 1
+        call: sub
+sub:
+  steps:
+    - init:
+        foo: 2
+        return: 1
+"#;
+        let diagnostics = parse_and_validate(yaml);
+        let ranges: Vec<Range> = diagnostics
+            .iter()
+            .filter(|d| d.message.contains("'foo'"))
+            .map(|d| d.range)
+            .collect();
+        assert_eq!(
+            ranges,
+            vec![
+                Range::new(Position::new(4, 8), Position::new(4, 11)),
+                Range::new(Position::new(9, 8), Position::new(9, 11)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unreachable_after_return() {
+        let yaml = r#"
+main:
+  steps:
+    - done:
+        return: 1
+        next: other
+    - other:
+        assign:
+          - x: 1
+        return: x
+    - fine:
+        return: 2
+"#;
+        let diagnostics = parse_and_validate(yaml);
+        let found: Vec<(&str, u32)> = diagnostics
+            .iter()
+            .filter(|d| d.code == Some(NumberOrString::String("unreachable-code".to_string())))
+            .map(|d| (d.message.as_str(), d.range.start.line))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (
+                    "'next' can never run: 'return' in the same step ends the workflow or subworkflow",
+                    5
+                ),
+                (
+                    "'assign' can never run: 'return' in the same step ends the workflow or subworkflow",
+                    7
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_next_targets() {
+        let yaml = r#"
+main:
+  steps:
+    - check:
+        switch:
+          - condition: __EXPR_000__
+            next: done
+          - condition: true
+            next: retry_later
+    - loop:
+        for:
+          value: x
+          in: [1, 2]
+          steps:
+            - inner:
+                next: continue
+            - back_out:
+                next: check
+    - jump:
+        next: __EXPR_001__
+    - done:
+        next: end
+helper:
+  steps:
+    - go:
+        next: inner
+"#;
+        let diagnostics = parse_and_validate(yaml);
+        let found: Vec<(&str, u32)> = diagnostics
+            .iter()
+            .filter(|d| d.code != Some(NumberOrString::String("unused-subworkflow".to_string())))
+            .map(|d| (d.message.as_str(), d.range.start.line))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("'next' target 'retry_later' does not match any step", 8),
+                ("'next' target 'inner' does not match any step", 25),
+            ]
+        );
+        assert_eq!(
+            diagnostics[0].code,
+            Some(NumberOrString::String("unknown-step".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_schema_overlay() {
+        let overlay = SchemaOverlay::new(serde_json::json!({
+            "properties": {
+                "metadata": {
+                    "type": "object",
+                    "properties": { "owner": { "type": "string" } },
+                    "additionalProperties": false
+                }
+            },
+            "required": ["metadata", "labels"]
+        }))
+        .unwrap();
+        let yaml = "metadata:\n  owner: [a]\n  team: data\nmain:\n  steps:\n    - done:\n        return: 1\n";
+        let value: Value = serde_yaml::from_str(yaml).unwrap();
+        let mut collector = DiagnosticCollector::new();
+        let extensions = SchemaExtensions {
+            overlay: Some(&overlay),
+            ..SchemaExtensions::default()
+        };
+        validate_workflow(&value, yaml, extensions, &mut collector);
+        let diagnostics: Vec<_> = collector
+            .into_diagnostics()
+            .into_iter()
+            .filter(|d| d.code == Some(NumberOrString::String("schema-violation".into())))
+            .map(|d| (d.message, d.range.start))
+            .collect();
+        assert_eq!(
+            diagnostics,
+            vec![
+                (
+                    "Expected string, found array at 'metadata.owner'".to_string(),
+                    Position::new(1, 2)
+                ),
+                (
+                    "Property 'team' is not allowed at 'metadata.team'".to_string(),
+                    Position::new(2, 2)
+                ),
+                (
+                    "Missing top-level key 'labels' required by the schema".to_string(),
+                    Position::new(0, 0)
+                ),
+            ]
+        );
+
+        // Without the overlay the key is unknown
+        assert!(parse_and_validate(yaml)
+            .iter()
+            .any(|d| d.message == "Unknown workflow element: 'metadata'"));
+    }
+
     #[test]
     fn test_non_mapping_document() {
         let yaml = "- item1\n- item2";
         let value: Value = serde_yaml::from_str(yaml).unwrap();
         let mut collector = DiagnosticCollector::new();
-        validate_workflow(&value, yaml, &mut collector);
+        validate_workflow(&value, yaml, SchemaExtensions::default(), &mut collector);
         let diagnostics = collector.into_diagnostics();
         assert!(diagnostics
             .iter()
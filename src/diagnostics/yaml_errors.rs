@@ -3,7 +3,16 @@
 //! This module provides diagnostic collection and conversion to LSP format,
 //! with support for different severity levels and diagnostic codes.
 
-use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString, Position, Range};
+use tower_lsp::lsp_types::{
+    Diagnostic, DiagnosticRelatedInformation, DiagnosticSeverity, DiagnosticTag, Location,
+    NumberOrString, Position, Range, Url,
+};
+
+use super::provenance::Provenance;
+use super::suppressions::Suppressions;
+use crate::analysis::Stage;
+use crate::parser::ExpressionMap;
+use crate::settings::{RuleLevel, Settings};
 
 /// Diagnostic codes for categorizing errors
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -18,9 +27,105 @@ pub enum DiagnosticCode {
     WorkflowStructure,
     /// Unknown workflow keyword
     UnknownKeyword,
+    /// Anchor name declared more than once
+    DuplicateAnchor,
+    /// Custom or language-specific YAML tag
+    UnknownTag,
+    /// `*alias` with no anchor of that name declared before it
+    UndefinedAlias,
+    /// Field value has the wrong type
+    TypeMismatch,
+    /// Assignment hides or fails to update an outer variable
+    VariableShadowing,
+    /// Variable reassigned a value of a different type
+    TypeInstability,
+    /// `next:` target that names no reachable step
+    UnknownStep,
+    /// Step name used twice in one `steps` list
+    DuplicateStep,
+    /// Malformed project ID, location or resource name in connector args
+    InvalidResourceName,
+    /// Argument not accepted by a standard library call
+    UnknownArgument,
+    /// Required argument of a standard library call left out
+    MissingArgument,
+    /// Step field that can never take effect
+    UnreachableCode,
+    /// Subworkflow that no `call:` refers to
+    UnusedSubworkflow,
+    /// URL or resource name assembled from a long `+` chain
+    StringConcatenation,
+    /// YAML directive that Workflows doesn't support
+    UnsupportedDirective,
+    /// Operation on a Workflows value of a type it can't apply to
+    InvalidOperation,
+    /// Definition past a GCP Workflows quota limit
+    QuotaExceeded,
+    /// Step, subworkflow or variable name outside the identifier grammar
+    InvalidIdentifier,
+    /// Variable read where nothing binds it
+    UndefinedVariable,
+    /// Malformed `$${}` expression
+    ExpressionSyntax,
+    /// `$$` or `%%` that Terraform renders as written
+    IneffectiveEscape,
+    /// `${`, `$${` or `%{` without a closing brace
+    UnclosedExpression,
+    /// Line indented with tab characters
+    TabIndentation,
+    /// Document too large for anything but YAML syntax checking
+    AnalysisSkipped,
+    /// Top-level key that fails the user's schema overlay
+    SchemaViolation,
+    /// Field a caught error doesn't have
+    UnknownField,
+    /// Variable passed to a template that the template never reads
+    UnusedVariable,
+    /// Call to a function Terraform doesn't have
+    UnknownFunction,
+    /// Error field read outside every `except` that binds the error
+    ErrorVariableOutOfScope,
 }
 
 impl DiagnosticCode {
+    /// Every diagnostic code, in declaration order
+    pub const ALL: &'static [DiagnosticCode] = &[
+        DiagnosticCode::YamlSyntax,
+        DiagnosticCode::InvalidIndentation,
+        DiagnosticCode::UnclosedString,
+        DiagnosticCode::WorkflowStructure,
+        DiagnosticCode::UnknownKeyword,
+        DiagnosticCode::DuplicateAnchor,
+        DiagnosticCode::UnknownTag,
+        DiagnosticCode::UndefinedAlias,
+        DiagnosticCode::TypeMismatch,
+        DiagnosticCode::VariableShadowing,
+        DiagnosticCode::TypeInstability,
+        DiagnosticCode::UnknownStep,
+        DiagnosticCode::DuplicateStep,
+        DiagnosticCode::InvalidResourceName,
+        DiagnosticCode::UnknownArgument,
+        DiagnosticCode::MissingArgument,
+        DiagnosticCode::UnreachableCode,
+        DiagnosticCode::UnusedSubworkflow,
+        DiagnosticCode::StringConcatenation,
+        DiagnosticCode::UnsupportedDirective,
+        DiagnosticCode::InvalidOperation,
+        DiagnosticCode::QuotaExceeded,
+        DiagnosticCode::InvalidIdentifier,
+        DiagnosticCode::UndefinedVariable,
+        DiagnosticCode::ExpressionSyntax,
+        DiagnosticCode::IneffectiveEscape,
+        DiagnosticCode::UnclosedExpression,
+        DiagnosticCode::TabIndentation,
+        DiagnosticCode::AnalysisSkipped,
+        DiagnosticCode::SchemaViolation,
+        DiagnosticCode::UnknownField,
+        DiagnosticCode::UnusedVariable,
+        DiagnosticCode::UnknownFunction,
+        DiagnosticCode::ErrorVariableOutOfScope,
+    ];
+
     /// Get the string code for this diagnostic
     pub fn as_str(&self) -> &'static str {
         match self {
@@ -29,6 +134,135 @@ impl DiagnosticCode {
             DiagnosticCode::UnclosedString => "unclosed-string",
             DiagnosticCode::WorkflowStructure => "workflow-structure",
             DiagnosticCode::UnknownKeyword => "unknown-keyword",
+            DiagnosticCode::DuplicateAnchor => "duplicate-anchor",
+            DiagnosticCode::UnknownTag => "unknown-tag",
+            DiagnosticCode::UndefinedAlias => "undefined-alias",
+            DiagnosticCode::TypeMismatch => "type-mismatch",
+            DiagnosticCode::VariableShadowing => "variable-shadowing",
+            DiagnosticCode::TypeInstability => "type-instability",
+            DiagnosticCode::UnknownStep => "unknown-step",
+            DiagnosticCode::DuplicateStep => "duplicate-step",
+            DiagnosticCode::InvalidResourceName => "invalid-resource-name",
+            DiagnosticCode::UnknownArgument => "unknown-argument",
+            DiagnosticCode::MissingArgument => "missing-argument",
+            DiagnosticCode::UnreachableCode => "unreachable-code",
+            DiagnosticCode::UnusedSubworkflow => "unused-subworkflow",
+            DiagnosticCode::StringConcatenation => "string-concatenation",
+            DiagnosticCode::UnsupportedDirective => "unsupported-directive",
+            DiagnosticCode::InvalidOperation => "invalid-operation",
+            DiagnosticCode::QuotaExceeded => "quota-exceeded",
+            DiagnosticCode::InvalidIdentifier => "invalid-identifier",
+            DiagnosticCode::UndefinedVariable => "undefined-variable",
+            DiagnosticCode::ExpressionSyntax => "expression-syntax",
+            DiagnosticCode::IneffectiveEscape => "ineffective-escape",
+            DiagnosticCode::UnclosedExpression => "unclosed-expression",
+            DiagnosticCode::TabIndentation => "tab-indentation",
+            DiagnosticCode::AnalysisSkipped => "analysis-skipped",
+            DiagnosticCode::SchemaViolation => "schema-violation",
+            DiagnosticCode::UnknownField => "unknown-field",
+            DiagnosticCode::UnusedVariable => "unused-variable",
+            DiagnosticCode::UnknownFunction => "unknown-function",
+            DiagnosticCode::ErrorVariableOutOfScope => "error-variable-out-of-scope",
+        }
+    }
+
+    /// One-line description of what this code reports
+    pub fn description(&self) -> &'static str {
+        match self {
+            DiagnosticCode::YamlSyntax => "YAML syntax error (parsing failed)",
+            DiagnosticCode::InvalidIndentation => "Invalid indentation",
+            DiagnosticCode::UnclosedString => "Unclosed string literal",
+            DiagnosticCode::WorkflowStructure => "Workflow structure error",
+            DiagnosticCode::UnknownKeyword => "Unknown workflow keyword",
+            DiagnosticCode::DuplicateAnchor => "Anchor name declared more than once",
+            DiagnosticCode::UnknownTag => "Custom or language-specific YAML tag",
+            DiagnosticCode::UndefinedAlias => {
+                "`*alias` with no anchor of that name declared before it"
+            }
+            DiagnosticCode::TypeMismatch => "Field value has the wrong type",
+            DiagnosticCode::VariableShadowing => {
+                "Assignment hides or fails to update an outer variable"
+            }
+            DiagnosticCode::TypeInstability => "Variable reassigned a value of a different type",
+            DiagnosticCode::UnknownStep => "`next:` target that names no reachable step",
+            DiagnosticCode::DuplicateStep => "Step name used twice in one `steps` list",
+            DiagnosticCode::InvalidResourceName => {
+                "Malformed project ID, location or resource name in connector args"
+            }
+            DiagnosticCode::UnknownArgument => "Argument not accepted by a standard library call",
+            DiagnosticCode::MissingArgument => {
+                "Required argument of a standard library call left out"
+            }
+            DiagnosticCode::UnreachableCode => "Step field that can never take effect",
+            DiagnosticCode::UnusedSubworkflow => "Subworkflow that no `call:` refers to",
+            DiagnosticCode::StringConcatenation => {
+                "URL or resource name assembled from a long `+` chain"
+            }
+            DiagnosticCode::UnsupportedDirective => "YAML directive that Workflows doesn't support",
+            DiagnosticCode::InvalidOperation => {
+                "Operation on a Workflows value of a type it can't apply to"
+            }
+            DiagnosticCode::QuotaExceeded => "Definition past a GCP Workflows quota limit",
+            DiagnosticCode::InvalidIdentifier => {
+                "Step, subworkflow or variable name outside the identifier grammar"
+            }
+            DiagnosticCode::UndefinedVariable => "Variable read where nothing binds it",
+            DiagnosticCode::ExpressionSyntax => "Malformed `$${}` expression",
+            DiagnosticCode::IneffectiveEscape => "`$$` or `%%` that Terraform renders as written",
+            DiagnosticCode::UnclosedExpression => "`${`, `$${` or `%{` without a closing brace",
+            DiagnosticCode::TabIndentation => "Line indented with tab characters",
+            DiagnosticCode::AnalysisSkipped => {
+                "Document too large for anything but YAML syntax checking"
+            }
+            DiagnosticCode::SchemaViolation => "Top-level key that fails the user's schema overlay",
+            DiagnosticCode::UnknownField => "Field a caught error doesn't have",
+            DiagnosticCode::UnusedVariable => {
+                "Variable passed to a template that the template never reads"
+            }
+            DiagnosticCode::UnknownFunction => "Call to a function Terraform doesn't have",
+            DiagnosticCode::ErrorVariableOutOfScope => {
+                "Error field read outside every `except` that binds the error"
+            }
+        }
+    }
+
+    /// The severity this diagnostic is reported with when not overridden
+    pub fn default_severity(&self) -> DiagnosticSeverity {
+        match self {
+            DiagnosticCode::YamlSyntax
+            | DiagnosticCode::InvalidIndentation
+            | DiagnosticCode::UnclosedString
+            | DiagnosticCode::UnknownTag
+            | DiagnosticCode::UndefinedAlias
+            | DiagnosticCode::ExpressionSyntax
+            | DiagnosticCode::UnclosedExpression
+            | DiagnosticCode::TabIndentation => DiagnosticSeverity::ERROR,
+            DiagnosticCode::WorkflowStructure
+            | DiagnosticCode::DuplicateAnchor
+            | DiagnosticCode::TypeMismatch
+            | DiagnosticCode::VariableShadowing
+            | DiagnosticCode::UnknownStep
+            | DiagnosticCode::DuplicateStep
+            | DiagnosticCode::InvalidResourceName
+            | DiagnosticCode::UnknownArgument
+            | DiagnosticCode::MissingArgument
+            | DiagnosticCode::UnreachableCode
+            | DiagnosticCode::StringConcatenation
+            | DiagnosticCode::UnsupportedDirective
+            | DiagnosticCode::InvalidOperation
+            | DiagnosticCode::QuotaExceeded
+            | DiagnosticCode::InvalidIdentifier
+            | DiagnosticCode::UndefinedVariable
+            | DiagnosticCode::IneffectiveEscape
+            | DiagnosticCode::SchemaViolation
+            | DiagnosticCode::UnknownFunction
+            | DiagnosticCode::ErrorVariableOutOfScope => DiagnosticSeverity::WARNING,
+            DiagnosticCode::UnknownKeyword
+            | DiagnosticCode::TypeInstability
+            | DiagnosticCode::UnusedSubworkflow
+            | DiagnosticCode::UnknownField
+            | DiagnosticCode::UnusedVariable => DiagnosticSeverity::HINT,
+            DiagnosticCode::AnalysisSkipped => DiagnosticSeverity::INFORMATION,
         }
     }
 
@@ -48,6 +282,24 @@ impl DiagnosticCode {
     }
 }
 
+/// URI standing in for the analyzed document in related information, since
+/// analysis only sees text; swapped for the real URI by
+/// [`resolve_related_uris`] before publishing
+pub const THIS_DOCUMENT: &str = "yaml-tftpl:this-document";
+
+/// Point related information at `uri` instead of [`THIS_DOCUMENT`]
+pub fn resolve_related_uris(diagnostics: &mut [Diagnostic], uri: &Url) {
+    let related = diagnostics
+        .iter_mut()
+        .filter_map(|d| d.related_information.as_mut())
+        .flatten();
+    for info in related {
+        if info.location.uri.as_str() == THIS_DOCUMENT {
+            info.location.uri = uri.clone();
+        }
+    }
+}
+
 /// Collects diagnostics during parsing and validation
 #[derive(Debug, Default)]
 pub struct DiagnosticCollector {
@@ -151,9 +403,97 @@ impl DiagnosticCollector {
         });
     }
 
+    /// Add a warning diagnostic with explicit range
+    pub fn add_workflow_warning_with_range(
+        &mut self,
+        message: String,
+        start_line: u32,
+        start_column: u32,
+        end_line: u32,
+        end_column: u32,
+        code: DiagnosticCode,
+    ) {
+        self.diagnostics.push(Diagnostic {
+            range: Range {
+                start: Position {
+                    line: start_line,
+                    character: start_column,
+                },
+                end: Position {
+                    line: end_line,
+                    character: end_column,
+                },
+            },
+            severity: Some(DiagnosticSeverity::WARNING),
+            code: Some(NumberOrString::String(code.as_str().to_string())),
+            code_description: None,
+            source: Some("yaml-tftpl-lsp".to_string()),
+            message,
+            related_information: None,
+            tags: None,
+            data: None,
+        });
+    }
+
+    /// Add a warning diagnostic covering `range`
+    pub fn add_workflow_warning_in(&mut self, message: String, range: Range, code: DiagnosticCode) {
+        self.add_workflow_warning_with_range(
+            message,
+            range.start.line,
+            range.start.character,
+            range.end.line,
+            range.end.character,
+            code,
+        );
+    }
+
+    /// Add a warning that points at related ranges of the same document
+    pub fn add_warning_with_related(
+        &mut self,
+        message: String,
+        range: Range,
+        code: DiagnosticCode,
+        related: Vec<(Range, String)>,
+    ) {
+        let uri = Url::parse(THIS_DOCUMENT).unwrap();
+        self.diagnostics.push(Diagnostic {
+            range,
+            severity: Some(DiagnosticSeverity::WARNING),
+            code: Some(NumberOrString::String(code.as_str().to_string())),
+            code_description: None,
+            source: Some("yaml-tftpl-lsp".to_string()),
+            message,
+            related_information: Some(
+                related
+                    .into_iter()
+                    .map(|(range, message)| DiagnosticRelatedInformation {
+                        location: Location {
+                            uri: uri.clone(),
+                            range,
+                        },
+                        message,
+                    })
+                    .collect(),
+            ),
+            tags: None,
+            data: None,
+        });
+    }
+
     /// Add a hint diagnostic
     #[allow(dead_code)]
     pub fn add_hint(&mut self, message: String, line: u32, column: u32) {
+        self.add_hint_with_code(message, line, column, DiagnosticCode::UnknownKeyword);
+    }
+
+    /// Add a hint diagnostic with explicit code
+    pub fn add_hint_with_code(
+        &mut self,
+        message: String,
+        line: u32,
+        column: u32,
+        code: DiagnosticCode,
+    ) {
         self.diagnostics.push(Diagnostic {
             range: Range {
                 start: Position {
@@ -166,9 +506,7 @@ impl DiagnosticCollector {
                 },
             },
             severity: Some(DiagnosticSeverity::HINT),
-            code: Some(NumberOrString::String(
-                DiagnosticCode::UnknownKeyword.as_str().to_string(),
-            )),
+            code: Some(NumberOrString::String(code.as_str().to_string())),
             code_description: None,
             source: Some("yaml-tftpl-lsp".to_string()),
             message,
@@ -178,6 +516,61 @@ impl DiagnosticCollector {
         });
     }
 
+    /// Add a hint diagnostic with explicit range
+    pub fn add_hint_with_range(&mut self, message: String, range: Range, code: DiagnosticCode) {
+        self.diagnostics.push(Diagnostic {
+            range,
+            severity: Some(DiagnosticSeverity::HINT),
+            code: Some(NumberOrString::String(code.as_str().to_string())),
+            code_description: None,
+            source: Some("yaml-tftpl-lsp".to_string()),
+            message,
+            related_information: None,
+            tags: None,
+            data: None,
+        });
+    }
+
+    /// Add a hint for code that has no effect, which editors may grey out
+    pub fn add_unnecessary_hint(&mut self, message: String, range: Range, code: DiagnosticCode) {
+        self.diagnostics.push(Diagnostic {
+            range,
+            severity: Some(DiagnosticSeverity::HINT),
+            code: Some(NumberOrString::String(code.as_str().to_string())),
+            code_description: None,
+            source: Some("yaml-tftpl-lsp".to_string()),
+            message,
+            related_information: None,
+            tags: Some(vec![DiagnosticTag::UNNECESSARY]),
+            data: None,
+        });
+    }
+
+    /// Add an informational diagnostic with explicit range
+    pub fn add_information_with_range(
+        &mut self,
+        message: String,
+        range: Range,
+        code: DiagnosticCode,
+    ) {
+        self.diagnostics.push(Diagnostic {
+            range,
+            severity: Some(DiagnosticSeverity::INFORMATION),
+            code: Some(NumberOrString::String(code.as_str().to_string())),
+            code_description: None,
+            source: Some("yaml-tftpl-lsp".to_string()),
+            message,
+            related_information: None,
+            tags: None,
+            data: None,
+        });
+    }
+
+    /// Add diagnostics built elsewhere, such as by an embedder's rule
+    pub fn extend(&mut self, diagnostics: impl IntoIterator<Item = Diagnostic>) {
+        self.diagnostics.extend(diagnostics);
+    }
+
     /// Get the number of diagnostics collected
     #[allow(dead_code)]
     pub fn len(&self) -> usize {
@@ -190,6 +583,70 @@ impl DiagnosticCollector {
         self.diagnostics.is_empty()
     }
 
+    /// Apply user severity overrides, dropping rules that are turned off
+    pub fn apply_settings(&mut self, settings: &Settings) {
+        self.diagnostics.retain_mut(|d| {
+            let code = match &d.code {
+                Some(NumberOrString::String(code)) => code.as_str(),
+                _ => return true,
+            };
+            match settings.rule_level(code).map(|level| level.severity()) {
+                Some(Some(severity)) => {
+                    d.severity = Some(severity);
+                    true
+                }
+                Some(None) => false,
+                None => true,
+            }
+        });
+    }
+
+    /// Lower every diagnostic to at most `max`, dropping them all when `max`
+    /// is `off`
+    pub fn apply_severity_cap(&mut self, max: RuleLevel) {
+        let Some(cap) = max.severity() else {
+            self.diagnostics.clear();
+            return;
+        };
+        for d in &mut self.diagnostics {
+            // LSP severities grow less severe as their value grows
+            if d.severity.is_none_or(|s| s < cap) {
+                d.severity = Some(cap);
+            }
+        }
+    }
+
+    /// Record `stage` and `analyzer` as the origin of the diagnostics added
+    /// since the collector held `start` of them
+    pub fn record_provenance(
+        &mut self,
+        start: usize,
+        stage: Stage,
+        analyzer: &str,
+        lines: &[&str],
+    ) {
+        for d in self.diagnostics.iter_mut().skip(start) {
+            let provenance = Provenance::new(d, stage, analyzer, lines);
+            d.data = serde_json::to_value(provenance).ok();
+        }
+    }
+
+    /// Map the diagnostics added since the collector held `start` of them
+    /// from preprocessed text positions back to the original document
+    pub fn adjust_positions(&mut self, start: usize, expression_map: &ExpressionMap) {
+        for d in self.diagnostics.iter_mut().skip(start) {
+            d.range = expression_map.adjust_range(d.range);
+            for related in d.related_information.iter_mut().flatten() {
+                related.location.range = expression_map.adjust_range(related.location.range);
+            }
+        }
+    }
+
+    /// Drop diagnostics silenced by inline suppression comments
+    pub fn apply_suppressions(&mut self, suppressions: &Suppressions) {
+        self.diagnostics.retain(|d| !suppressions.is_suppressed(d));
+    }
+
     /// Convert into the final list of diagnostics
     pub fn into_diagnostics(self) -> Vec<Diagnostic> {
         self.diagnostics
@@ -287,6 +744,55 @@ mod tests {
         assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::HINT));
     }
 
+    #[test]
+    fn test_default_severity() {
+        assert_eq!(
+            DiagnosticCode::YamlSyntax.default_severity(),
+            DiagnosticSeverity::ERROR
+        );
+        assert_eq!(
+            DiagnosticCode::UnknownKeyword.default_severity(),
+            DiagnosticSeverity::HINT
+        );
+    }
+
+    #[test]
+    fn test_apply_severity_cap() {
+        let mut collector = DiagnosticCollector::new();
+        collector.add_yaml_error("bad".to_string(), 0, 0);
+        collector.add_hint("hint".to_string(), 1, 0);
+        collector.apply_severity_cap(RuleLevel::Information);
+        let severities: Vec<_> = collector
+            .diagnostics
+            .iter()
+            .map(|d| d.severity.unwrap())
+            .collect();
+        assert_eq!(
+            severities,
+            vec![DiagnosticSeverity::INFORMATION, DiagnosticSeverity::HINT]
+        );
+
+        collector.apply_severity_cap(RuleLevel::Off);
+        assert!(collector.is_empty());
+    }
+
+    #[test]
+    fn test_apply_suppressions() {
+        let mut collector = DiagnosticCollector::new();
+        collector.add_hint("unknown keyword".to_string(), 1, 0);
+        collector.add_yaml_error("error".to_string(), 1, 0);
+
+        let suppressions = Suppressions::parse(
+            "# yaml-tftpl-lsp disable-next-line: unknown-keyword
+foo: bar",
+        );
+        collector.apply_suppressions(&suppressions);
+
+        let diagnostics = collector.into_diagnostics();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::ERROR));
+    }
+
     #[test]
     fn test_len_and_is_empty() {
         let mut collector = DiagnosticCollector::new();
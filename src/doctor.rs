@@ -0,0 +1,405 @@
+//! `yaml-tftpl-lsp doctor`
+//!
+//! Checks a workspace the way the server would see it and prints a report,
+//! so setup problems can be found without an editor in the loop.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use tower_lsp::lsp_types::Diagnostic;
+
+use crate::analysis::{analyze, AnalysisOptions};
+use crate::config::{WorkspaceConfig, CONFIG_FILE};
+use crate::diagnostics::Provenance;
+use crate::rules::RuleRegistry;
+use crate::schema::SchemaOverlay;
+use crate::terraform::TfVars;
+use crate::workspace::{self, TEMPLATE_EXTENSIONS};
+
+/// Outcome of a single check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Ok,
+    Warn,
+    Fail,
+}
+
+impl Status {
+    fn label(self) -> &'static str {
+        match self {
+            Status::Ok => "ok",
+            Status::Warn => "warn",
+            Status::Fail => "FAIL",
+        }
+    }
+}
+
+/// A named check with its outcome and supporting details
+#[derive(Debug, Clone)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: Status,
+    pub summary: String,
+    pub details: Vec<String>,
+}
+
+/// The full doctor report for a workspace root
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub root: PathBuf,
+    pub checks: Vec<Check>,
+}
+
+impl Report {
+    /// Whether any check failed
+    pub fn has_failures(&self) -> bool {
+        self.checks.iter().any(|c| c.status == Status::Fail)
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "yaml-tftpl-lsp {}", env!("CARGO_PKG_VERSION"))?;
+        writeln!(f, "Workspace root: {}", self.root.display())?;
+        for check in &self.checks {
+            writeln!(
+                f,
+                "\n[{}] {}: {}",
+                check.status.label(),
+                check.name,
+                check.summary
+            )?;
+            for detail in &check.details {
+                writeln!(f, "    {}", detail)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Run every check against a workspace root
+pub fn run(root: &Path) -> Report {
+    run_with_rules(root, &RuleRegistry::default())
+}
+
+/// Run every check against a workspace root, analyzing templates with an
+/// embedder's rules as well
+pub fn run_with_rules(root: &Path, rules: &RuleRegistry) -> Report {
+    let files = workspace::walk(root);
+    let templates: Vec<&PathBuf> = files.iter().filter(|p| workspace::is_template(p)).collect();
+
+    Report {
+        root: root.to_path_buf(),
+        checks: vec![
+            check_workspace(root, &templates),
+            check_associations(root, &files),
+            check_config(root),
+            check_terraform(root, &files),
+            check_templates(root, &templates, rules),
+        ],
+    }
+}
+
+fn relative(root: &Path, path: &Path) -> String {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .display()
+        .to_string()
+}
+
+fn check_workspace(root: &Path, templates: &[&PathBuf]) -> Check {
+    if !root.is_dir() {
+        return Check {
+            name: "Workspace",
+            status: Status::Fail,
+            summary: "root is not a directory".to_string(),
+            details: Vec::new(),
+        };
+    }
+    let count = templates.len();
+    Check {
+        name: "Workspace",
+        status: if count == 0 { Status::Warn } else { Status::Ok },
+        summary: format!(
+            "{} template{} found",
+            count,
+            if count == 1 { "" } else { "s" }
+        ),
+        details: if count == 0 {
+            vec![format!(
+                "No files ending in {} below the root",
+                TEMPLATE_EXTENSIONS.join(" or ")
+            )]
+        } else {
+            Vec::new()
+        },
+    }
+}
+
+/// Templates with other extensions are silently ignored by editors
+/// configured for `.yaml.tftpl`, which is the usual "nothing happens" cause
+fn check_associations(root: &Path, files: &[PathBuf]) -> Check {
+    let unmatched: Vec<String> = files
+        .iter()
+        .filter(|p| !workspace::is_template(p))
+        .filter(|p| {
+            p.file_name().and_then(|n| n.to_str()).is_some_and(|n| {
+                n.ends_with(".tftpl") && (n.contains(".yaml") || n.contains(".yml"))
+            })
+        })
+        .map(|p| relative(root, p))
+        .collect();
+
+    Check {
+        name: "File associations",
+        status: if unmatched.is_empty() {
+            Status::Ok
+        } else {
+            Status::Warn
+        },
+        summary: if unmatched.is_empty() {
+            format!(
+                "editors should associate {}",
+                TEMPLATE_EXTENSIONS.join(", ")
+            )
+        } else {
+            format!(
+                "{} YAML-like template{} will not be recognized",
+                unmatched.len(),
+                if unmatched.len() == 1 { "" } else { "s" }
+            )
+        },
+        details: unmatched,
+    }
+}
+
+fn check_config(root: &Path) -> Check {
+    let path = WorkspaceConfig::path(root);
+    if !path.exists() {
+        return Check {
+            name: "Configuration",
+            status: Status::Ok,
+            summary: format!("no {} (using defaults)", CONFIG_FILE),
+            details: Vec::new(),
+        };
+    }
+    match WorkspaceConfig::load(root) {
+        Ok(config) => {
+            let schema = config.schema_path(root, None).map(|path| {
+                let loaded = SchemaOverlay::load(&path);
+                (relative(root, &path), loaded)
+            });
+            if let Some((path, Err(e))) = &schema {
+                return Check {
+                    name: "Configuration",
+                    status: Status::Fail,
+                    summary: format!("schema {} is invalid", path),
+                    details: vec![e.to_string()],
+                };
+            }
+            let mut details = vec![
+                format!("{} snippet(s)", config.snippets.len()),
+                format!("{} render profile(s)", config.render_profiles.len()),
+            ];
+            if let Some((path, _)) = schema {
+                details.push(format!("schema overlay {}", path));
+            }
+            Check {
+                name: "Configuration",
+                status: Status::Ok,
+                summary: format!("{} is valid", CONFIG_FILE),
+                details,
+            }
+        }
+        Err(e) => Check {
+            name: "Configuration",
+            status: Status::Fail,
+            summary: format!("{} is invalid", CONFIG_FILE),
+            details: vec![e.to_string()],
+        },
+    }
+}
+
+fn check_terraform(root: &Path, files: &[PathBuf]) -> Check {
+    let mut parsed = 0;
+    let mut details = Vec::new();
+
+    for path in files {
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+        let is_tf = name.ends_with(".tf");
+        let is_tfvars = name.ends_with(".tfvars");
+        if !is_tf && !is_tfvars {
+            continue;
+        }
+        let ok = match fs::read_to_string(path) {
+            Ok(text) if is_tf => match hcl::parse(&text) {
+                Ok(_) => true,
+                Err(e) => {
+                    details.push(format!("{}: {}", relative(root, path), e));
+                    false
+                }
+            },
+            Ok(text) => {
+                let ok = TfVars::parse(&text).is_some();
+                if !ok {
+                    details.push(format!("{}: not valid HCL", relative(root, path)));
+                }
+                ok
+            }
+            Err(e) => {
+                details.push(format!("{}: {}", relative(root, path), e));
+                false
+            }
+        };
+        if ok {
+            parsed += 1;
+        }
+    }
+
+    let status = if !details.is_empty() {
+        Status::Fail
+    } else if parsed == 0 {
+        Status::Warn
+    } else {
+        Status::Ok
+    };
+    let summary = if parsed == 0 && details.is_empty() {
+        "no .tf or .tfvars files found; Terraform-aware features are disabled".to_string()
+    } else {
+        format!("{} file(s) parsed, {} failed", parsed, details.len())
+    };
+
+    Check {
+        name: "Terraform",
+        status,
+        summary,
+        details,
+    }
+}
+
+fn check_templates(root: &Path, templates: &[&PathBuf], rules: &RuleRegistry) -> Check {
+    let mut details = Vec::new();
+    let mut unreadable = false;
+
+    let config = WorkspaceConfig::load(root).unwrap_or_default();
+    let settings = config.settings(root);
+    for path in templates {
+        match fs::read_to_string(path) {
+            Ok(text) => {
+                let options = AnalysisOptions {
+                    settings: settings.for_template(path, root),
+                    max_severity: config.max_severity(root, path),
+                    rules: rules.clone(),
+                };
+                let diagnostics = analyze(&text, &options).diagnostics;
+                if !diagnostics.is_empty() {
+                    details.push(format!(
+                        "{}: {} diagnostic(s) ({})",
+                        relative(root, path),
+                        diagnostics.len(),
+                        by_analyzer(&diagnostics)
+                    ));
+                }
+            }
+            Err(e) => {
+                unreadable = true;
+                details.push(format!("{}: {}", relative(root, path), e));
+            }
+        }
+    }
+
+    Check {
+        name: "Templates",
+        status: if unreadable { Status::Fail } else { Status::Ok },
+        summary: format!(
+            "analyzed {}, {} with diagnostics",
+            templates.len(),
+            details.len()
+        ),
+        details,
+    }
+}
+
+/// Diagnostic counts per analyzer, e.g. "workflow: 2, scopes: 1", so a
+/// false positive can be traced to the subsystem that reported it
+fn by_analyzer(diagnostics: &[Diagnostic]) -> String {
+    let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+    for d in diagnostics {
+        let analyzer = Provenance::of(d).map_or_else(|| "unknown".to_string(), |p| p.analyzer);
+        *counts.entry(analyzer).or_default() += 1;
+    }
+    counts
+        .iter()
+        .map(|(analyzer, count)| format!("{}: {}", analyzer, count))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(report: &Report, name: &str) -> Status {
+        report
+            .checks
+            .iter()
+            .find(|c| c.name == name)
+            .unwrap()
+            .status
+    }
+
+    #[test]
+    fn test_healthy_workspace() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("w.yaml.tftpl"),
+            "main:\n  steps:\n    - done:\n        return: 1\n",
+        )
+        .unwrap();
+        fs::write(dir.path().join("main.tf"), "variable \"region\" {}\n").unwrap();
+
+        let report = run(dir.path());
+        assert!(!report.has_failures());
+        assert!(report.checks.iter().all(|c| c.status == Status::Ok));
+        assert!(report
+            .to_string()
+            .contains("[ok] Workspace: 1 template found"));
+    }
+
+    #[test]
+    fn test_problems_are_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("w.yaml.tpl.tftpl"), "").unwrap();
+        fs::write(dir.path().join("broken.tf"), "variable {").unwrap();
+        fs::write(dir.path().join(CONFIG_FILE), "{ not json").unwrap();
+
+        let report = run(dir.path());
+        assert!(report.has_failures());
+        assert_eq!(status(&report, "Workspace"), Status::Warn);
+        assert_eq!(status(&report, "File associations"), Status::Warn);
+        assert_eq!(status(&report, "Configuration"), Status::Fail);
+        assert_eq!(status(&report, "Terraform"), Status::Fail);
+    }
+    #[test]
+    fn test_template_diagnostics_name_their_analyzer() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("w.yaml.tftpl"),
+            "main:\n  steps:\n    - done:\n        return: 1\nextra: 1\n",
+        )
+        .unwrap();
+
+        let report = run(dir.path());
+        let templates = report
+            .checks
+            .iter()
+            .find(|c| c.name == "Templates")
+            .unwrap();
+        assert_eq!(
+            templates.details,
+            vec!["w.yaml.tftpl: 1 diagnostic(s) (workflow: 1)"]
+        );
+    }
+}
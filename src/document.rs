@@ -1,18 +1,1079 @@
 //! Document state management
 
+use std::borrow::Cow;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::OnceLock;
+
+use ropey::Rope;
+
+use tower_lsp::lsp_types::{
+    CallHierarchyIncomingCall, CallHierarchyItem, CallHierarchyOutgoingCall, CodeAction,
+    CodeActionOrCommand, CodeLens, CompletionItem, CompletionTextEdit, Diagnostic, Hover,
+    InlayHint, InlayHintLabel, LinkedEditingRanges, Location, Position, PositionEncodingKind,
+    Range, SemanticToken, TextDocumentContentChangeEvent, TextEdit, Url, WorkspaceEdit,
+};
+
 /// Represents the state of a text document
+///
+/// The text lives in a rope so incremental changes are applied in place
+/// rather than by rebuilding the whole string.
 #[derive(Debug, Clone)]
-#[allow(dead_code)]
 pub struct Document {
     /// The document text content
-    pub text: String,
+    rope: Rope,
+    /// The text as one string, built on first use after each change
+    text: OnceLock<String>,
+    /// Line starts of the text, built on first use after each change
+    lines: OnceLock<LineOffsets>,
     /// The document version
     pub version: i32,
+    /// Whether analysis found the text valid YAML, unknown until it has run
+    parsed: Option<bool>,
+    /// The most recent text analysis found valid YAML
+    last_good: Option<Rope>,
 }
 
 impl Document {
     /// Create a new document with the given text and version
     pub fn new(text: String, version: i32) -> Self {
-        Self { text, version }
+        Self {
+            rope: Rope::from_str(&text),
+            text: OnceLock::from(text),
+            lines: OnceLock::new(),
+            version,
+            parsed: None,
+            last_good: None,
+        }
+    }
+
+    /// The document text
+    pub fn text(&self) -> &str {
+        self.text.get_or_init(|| self.rope.to_string())
+    }
+
+    /// The line starts of the document text
+    pub fn lines(&self) -> &LineOffsets {
+        self.lines.get_or_init(|| LineOffsets::new(self.text()))
+    }
+
+    /// Convert a position to a byte offset in the text, see
+    /// [`LineOffsets::position_to_offset`]
+    pub fn position_to_offset(&self, position: Position) -> Option<usize> {
+        self.lines().position_to_offset(self.text(), position)
+    }
+
+    /// Convert a byte offset in the text to a position
+    pub fn offset_to_position(&self, offset: usize) -> Position {
+        self.lines().offset_to_position(self.text(), offset)
+    }
+
+    /// The document text as a rope
+    pub fn rope(&self) -> &Rope {
+        &self.rope
+    }
+
+    /// Replace the text
+    pub fn update(&mut self, text: String, version: i32) {
+        self.apply_changes(
+            vec![TextDocumentContentChangeEvent {
+                range: None,
+                range_length: None,
+                text,
+            }],
+            version,
+            &PositionEncodingKind::UTF32,
+        );
+    }
+
+    /// Apply a client's changes in order, with ranges in `encoding`
+    ///
+    /// A change without a range replaces the whole text. Positions past the
+    /// end of a line or of the document clamp to it, as the protocol says.
+    /// Only the rope is edited; the text is built and parsed when next used.
+    pub fn apply_changes(
+        &mut self,
+        changes: Vec<TextDocumentContentChangeEvent>,
+        version: i32,
+        encoding: &PositionEncodingKind,
+    ) {
+        for change in changes {
+            match change.range {
+                Some(range) => {
+                    let start = self.char_index(range.start, encoding);
+                    let end = self.char_index(range.end, encoding).max(start);
+                    self.rope.remove(start..end);
+                    self.rope.insert(start, &change.text);
+                }
+                None => self.rope = Rope::from_str(&change.text),
+            }
+        }
+        self.text = OnceLock::new();
+        self.lines = OnceLock::new();
+        self.parsed = None;
+        self.version = version;
+    }
+
+    /// Record whether analysis found the text of `version` valid YAML,
+    /// unless a newer version has replaced it since
+    pub fn record_parse(&mut self, version: i32, parsed: bool) {
+        if version != self.version {
+            return;
+        }
+        self.parsed = Some(parsed);
+        if parsed {
+            // Cloning a rope shares its chunks, so this copies nothing
+            self.last_good = Some(self.rope.clone());
+        }
+    }
+
+    /// The char index of a client position in `encoding`
+    fn char_index(&self, position: Position, encoding: &PositionEncodingKind) -> usize {
+        let line_index = position.line as usize;
+        if line_index >= self.rope.len_lines() {
+            return self.rope.len_chars();
+        }
+        let line = self.rope.line(line_index);
+        // Up to the line break, which a position can't point inside
+        let mut len = line.len_chars();
+        for break_char in ['\n', '\r'] {
+            if len > 0 && line.char(len - 1) == break_char {
+                len -= 1;
+            }
+        }
+        let line = line.slice(..len);
+        let units = position.character as usize;
+        let column = match encoding.as_str() {
+            "utf-8" => line.byte_to_char(units.min(line.len_bytes())),
+            "utf-16" => line.utf16_cu_to_char(units.min(line.len_utf16_cu())),
+            _ => units.min(len),
+        };
+        self.rope.line_to_char(line_index) + column
+    }
+
+    /// The last text analysis found valid YAML, unless that is the current
+    /// text
+    pub fn last_good(&self) -> Option<String> {
+        if self.parsed == Some(true) {
+            return None;
+        }
+        self.last_good.as_ref().map(Rope::to_string)
+    }
+
+    /// Whether the document is small enough to analyze
+    pub fn check_size(&self, max_size: usize) -> Result<(), InputError> {
+        check_len(self.rope.len_bytes(), max_size)
+    }
+}
+
+/// A document's text and version at one point in time
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DocumentSnapshot {
+    pub uri: Url,
+    pub text: String,
+    pub version: i32,
+}
+
+/// The open documents of a session, keyed by URI
+///
+/// Follows the document lifecycle of the protocol, so embedders driving the
+/// analyzer without an LSP transport can keep documents the way the server
+/// does.
+#[derive(Debug, Clone, Default)]
+pub struct DocumentStore {
+    documents: HashMap<Url, Document>,
+}
+
+impl DocumentStore {
+    /// Create an empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Open a document, replacing any open at the same URI
+    pub fn open(&mut self, uri: Url, text: String, version: i32) -> &mut Document {
+        match self.documents.entry(uri) {
+            Entry::Occupied(mut entry) => {
+                entry.insert(Document::new(text, version));
+                entry.into_mut()
+            }
+            Entry::Vacant(entry) => entry.insert(Document::new(text, version)),
+        }
+    }
+
+    /// Apply a client's changes to a document, see [`Document::apply_changes`]
+    ///
+    /// Edits can't be applied to a document never opened, but they can
+    /// after a change replacing the whole text, which opens it. Returns
+    /// `None` when the changes were ignored.
+    pub fn change(
+        &mut self,
+        uri: &Url,
+        mut changes: Vec<TextDocumentContentChangeEvent>,
+        version: i32,
+        encoding: &PositionEncodingKind,
+    ) -> Option<&Document> {
+        if self.documents.contains_key(uri) {
+            let doc = self.documents.get_mut(uri)?;
+            doc.apply_changes(changes, version, encoding);
+            return Some(doc);
+        }
+        let full = changes.iter().rposition(|c| c.range.is_none())?;
+        let rest = changes.split_off(full + 1);
+        let text = changes.swap_remove(full).text;
+        let doc = self.open(uri.clone(), text, version);
+        if !rest.is_empty() {
+            doc.apply_changes(rest, version, encoding);
+        }
+        Some(doc)
+    }
+
+    /// Close a document, returning it if it was open
+    pub fn close(&mut self, uri: &Url) -> Option<Document> {
+        self.documents.remove(uri)
+    }
+
+    /// An open document
+    pub fn get(&self, uri: &Url) -> Option<&Document> {
+        self.documents.get(uri)
+    }
+
+    /// An open document, to update in place
+    pub fn get_mut(&mut self, uri: &Url) -> Option<&mut Document> {
+        self.documents.get_mut(uri)
+    }
+
+    /// Every open document, in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (&Url, &Document)> {
+        self.documents.iter()
+    }
+
+    /// The URIs of every open document
+    pub fn uris(&self) -> impl Iterator<Item = &Url> {
+        self.documents.keys()
+    }
+
+    /// The number of open documents
+    pub fn len(&self) -> usize {
+        self.documents.len()
+    }
+
+    /// Whether no document is open
+    pub fn is_empty(&self) -> bool {
+        self.documents.is_empty()
+    }
+
+    /// The text and version of an open document, to analyze without holding
+    /// on to the store
+    pub fn snapshot(&self, uri: &Url) -> Option<DocumentSnapshot> {
+        self.documents.get(uri).map(|doc| DocumentSnapshot {
+            uri: uri.clone(),
+            text: doc.text().to_string(),
+            version: doc.version,
+        })
+    }
+
+    /// Snapshots of every open document
+    pub fn snapshots(&self) -> Vec<DocumentSnapshot> {
+        self.documents
+            .keys()
+            .filter_map(|uri| self.snapshot(uri))
+            .collect()
+    }
+}
+
+/// Input a client should not have sent, or that is too large to serve
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputError {
+    /// The document exceeds the configured size limit
+    TooLarge { len: usize, limit: usize },
+    /// The position's line is past the end of the document
+    LineOutOfRange { line: u32, line_count: u32 },
+    /// A UTF-16 position points between the two halves of a surrogate pair
+    SplitSurrogatePair(Position),
+    /// A UTF-8 position points inside the bytes of a character
+    SplitCharacter(Position),
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InputError::TooLarge { len, limit } => write!(
+                f,
+                "Document is {} bytes, over the {} byte limit",
+                len, limit
+            ),
+            InputError::LineOutOfRange { line, line_count } => write!(
+                f,
+                "Line {} is outside the document ({} lines)",
+                line, line_count
+            ),
+            InputError::SplitSurrogatePair(position) => write!(
+                f,
+                "Position {}:{} splits a surrogate pair",
+                position.line, position.character
+            ),
+            InputError::SplitCharacter(position) => write!(
+                f,
+                "Position {}:{} is inside a multi-byte character",
+                position.line, position.character
+            ),
+        }
+    }
+}
+
+/// Check that a document is no larger than `max_size` bytes
+pub fn check_size(text: &str, max_size: usize) -> Result<(), InputError> {
+    check_len(text.len(), max_size)
+}
+
+/// Check that a document of `len` bytes is no larger than `max_size` bytes
+pub fn check_len(len: usize, max_size: usize) -> Result<(), InputError> {
+    if len > max_size {
+        Err(InputError::TooLarge {
+            len,
+            limit: max_size,
+        })
+    } else {
+        Ok(())
+    }
+}
+
+/// Check that a client position refers to a real place in `text`
+///
+/// Characters past the end of a line are allowed, since the protocol says
+/// they clamp to the line end.
+pub fn check_position(
+    text: &str,
+    position: Position,
+    encoding: &PositionEncodingKind,
+) -> Result<(), InputError> {
+    LineOffsets::new(text).check_position(text, position, encoding)
+}
+
+/// The byte offset of every line start in a text, so offsets and positions
+/// convert without rescanning it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LineOffsets {
+    starts: Vec<usize>,
+    /// Length of the text
+    len: usize,
+}
+
+impl LineOffsets {
+    pub fn new(text: &str) -> Self {
+        Self {
+            starts: std::iter::once(0)
+                .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+                .collect(),
+            len: text.len(),
+        }
+    }
+
+    /// [`check_position`] using the index
+    pub fn check_position(
+        &self,
+        text: &str,
+        position: Position,
+        encoding: &PositionEncodingKind,
+    ) -> Result<(), InputError> {
+        let Some(line) = self.line(text, position.line) else {
+            return Err(InputError::LineOutOfRange {
+                line: position.line,
+                line_count: self.line_count(),
+            });
+        };
+
+        let len: fn(char) -> usize = match encoding.as_str() {
+            "utf-16" => char::len_utf16,
+            "utf-8" => char::len_utf8,
+            _ => return Ok(()),
+        };
+        let mut units = 0;
+        for c in line.chars() {
+            if units >= position.character {
+                break;
+            }
+            units += len(c) as u32;
+            if units > position.character {
+                return Err(if *encoding == PositionEncodingKind::UTF16 {
+                    InputError::SplitSurrogatePair(position)
+                } else {
+                    InputError::SplitCharacter(position)
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Number of lines, counting the empty one after a final line break
+    pub fn line_count(&self) -> u32 {
+        self.starts.len() as u32
+    }
+
+    /// The text of `line` in `text` without its line break, CRLF included
+    pub fn line<'t>(&self, text: &'t str, line: u32) -> Option<&'t str> {
+        let start = *self.starts.get(line as usize)?;
+        let end = self
+            .starts
+            .get(line as usize + 1)
+            .map_or(self.len, |next| next - 1);
+        let line = &text[start..end];
+        Some(line.strip_suffix('\r').unwrap_or(line))
+    }
+
+    /// Convert an LSP position (line, character column) to a byte offset in
+    /// `text`
+    ///
+    /// Returns `None` if the line does not exist. Columns past the end of
+    /// the line are clamped to the end of that line, before the `\r` of a
+    /// CRLF.
+    pub fn position_to_offset(&self, text: &str, position: Position) -> Option<usize> {
+        let line = self.line(text, position.line)?;
+        let offset = line
+            .char_indices()
+            .nth(position.character as usize)
+            .map_or(line.len(), |(i, _)| i);
+        Some(self.starts[position.line as usize] + offset)
+    }
+
+    /// Convert a byte offset in `text` to an LSP position (line, character
+    /// column)
+    pub fn offset_to_position(&self, text: &str, offset: usize) -> Position {
+        let offset = offset.min(self.len);
+        let line = self.starts.partition_point(|&start| start <= offset) - 1;
+        let line_text = &text[self.starts[line]..offset];
+        let mut character = line_text.chars().count() as u32;
+        // The `\r` of a CRLF is part of the line break, not a column
+        if line_text.ends_with('\r') && text[offset..].starts_with('\n') {
+            character -= 1;
+        }
+        Position::new(line as u32, character)
+    }
+}
+
+/// `text` with CRLF line breaks turned into LF
+///
+/// The `\r` only ever ends a line, so every position before it is the same
+/// in both texts and results computed on the normalized text apply to the
+/// original unchanged.
+pub fn normalize_line_endings(text: &str) -> Cow<'_, str> {
+    if text.contains("\r\n") {
+        Cow::Owned(text.replace("\r\n", "\n"))
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
+/// Converts positions between the server and the client
+///
+/// Analyzers and features count columns in characters (UTF-32 code units).
+/// Clients count them in the encoding agreed at initialization, UTF-16
+/// unless both sides support another, so every position crossing the
+/// protocol goes through an encoder for the document it refers to.
+#[derive(Debug, Clone)]
+pub struct PositionEncoder<'a> {
+    uri: &'a Url,
+    text: &'a str,
+    encoding: PositionEncodingKind,
+    /// Line starts of `text`, `None` when columns need no conversion
+    lines: Option<Cow<'a, LineOffsets>>,
+}
+
+impl<'a> PositionEncoder<'a> {
+    /// An encoder for positions in `text`, the document at `uri`
+    pub fn new(uri: &'a Url, text: &'a str, encoding: PositionEncodingKind) -> Self {
+        let lines =
+            (encoding != PositionEncodingKind::UTF32).then(|| Cow::Owned(LineOffsets::new(text)));
+        Self {
+            uri,
+            text,
+            encoding,
+            lines,
+        }
+    }
+
+    /// An encoder for positions in an open document, using its line index
+    pub fn for_document(uri: &'a Url, doc: &'a Document, encoding: PositionEncodingKind) -> Self {
+        let lines = (encoding != PositionEncodingKind::UTF32).then(|| Cow::Borrowed(doc.lines()));
+        Self {
+            uri,
+            text: doc.text(),
+            encoding,
+            lines,
+        }
+    }
+
+    /// The text of `line`, or `None` when no conversion is needed
+    fn line(&self, line: u32) -> Option<&'a str> {
+        self.lines.as_ref()?.line(self.text, line)
+    }
+
+    /// Length of `c` in the client's code units
+    fn units(&self, c: char) -> u32 {
+        if self.encoding == PositionEncodingKind::UTF8 {
+            c.len_utf8() as u32
+        } else {
+            c.len_utf16() as u32
+        }
+    }
+
+    /// Convert a server position to the client's encoding
+    ///
+    /// Columns past the end of the line stay that far past it.
+    pub fn encode(&self, position: Position) -> Position {
+        let Some(line) = self.line(position.line) else {
+            return position;
+        };
+        let mut chars = 0;
+        let mut units = 0;
+        for c in line.chars().take(position.character as usize) {
+            chars += 1;
+            units += self.units(c);
+        }
+        Position::new(position.line, units + (position.character - chars))
+    }
+
+    /// Convert a client position to character columns
+    ///
+    /// A column inside a character counts as the start of the next one.
+    pub fn decode(&self, position: Position) -> Position {
+        let Some(line) = self.line(position.line) else {
+            return position;
+        };
+        let mut chars = 0;
+        let mut units = 0;
+        for c in line.chars() {
+            if units >= position.character {
+                break;
+            }
+            chars += 1;
+            units += self.units(c);
+        }
+        Position::new(
+            position.line,
+            chars + position.character.saturating_sub(units),
+        )
+    }
+
+    /// Convert the positions in a server result to the client's encoding
+    pub fn to_client<T: Positions>(&self, mut value: T) -> T {
+        if self.lines.is_some() {
+            value.map_positions(self.uri, &mut |p| self.encode(p));
+        }
+        value
+    }
+
+    /// Convert the positions in client input to character columns
+    pub fn from_client<T: Positions>(&self, mut value: T) -> T {
+        if self.lines.is_some() {
+            value.map_positions(self.uri, &mut |p| self.decode(p));
+        }
+        value
+    }
+
+    /// Convert delta-encoded semantic tokens, whose starts and lengths are
+    /// counted in characters, to the client's encoding
+    pub fn semantic_tokens(&self, tokens: Vec<SemanticToken>) -> Vec<SemanticToken> {
+        if self.lines.is_none() {
+            return tokens;
+        }
+        let (mut line, mut start) = (0, 0);
+        let (mut last_line, mut last_start) = (0, 0);
+        tokens
+            .into_iter()
+            .map(|token| {
+                line += token.delta_line;
+                start = if token.delta_line > 0 {
+                    token.delta_start
+                } else {
+                    start + token.delta_start
+                };
+                let begin = self.encode(Position::new(line, start));
+                let end = self.encode(Position::new(line, start + token.length));
+                let delta_start = if line == last_line {
+                    begin.character - last_start
+                } else {
+                    begin.character
+                };
+                let encoded = SemanticToken {
+                    delta_line: line - last_line,
+                    delta_start,
+                    length: end.character - begin.character,
+                    ..token
+                };
+                (last_line, last_start) = (line, begin.character);
+                encoded
+            })
+            .collect()
+    }
+}
+
+/// Values carrying positions in a document, which a [`PositionEncoder`]
+/// converts
+///
+/// Locations and edits in other documents are left alone.
+pub trait Positions {
+    /// Replace every position in `document` with `f` of it
+    fn map_positions(&mut self, document: &Url, f: &mut dyn FnMut(Position) -> Position);
+}
+
+impl Positions for Position {
+    fn map_positions(&mut self, _: &Url, f: &mut dyn FnMut(Position) -> Position) {
+        *self = f(*self);
+    }
+}
+
+impl Positions for Range {
+    fn map_positions(&mut self, document: &Url, f: &mut dyn FnMut(Position) -> Position) {
+        self.start.map_positions(document, f);
+        self.end.map_positions(document, f);
+    }
+}
+
+impl<T: Positions> Positions for Vec<T> {
+    fn map_positions(&mut self, document: &Url, f: &mut dyn FnMut(Position) -> Position) {
+        for item in self {
+            item.map_positions(document, f);
+        }
+    }
+}
+
+impl<T: Positions> Positions for Option<T> {
+    fn map_positions(&mut self, document: &Url, f: &mut dyn FnMut(Position) -> Position) {
+        if let Some(value) = self {
+            value.map_positions(document, f);
+        }
+    }
+}
+
+impl Positions for Location {
+    fn map_positions(&mut self, document: &Url, f: &mut dyn FnMut(Position) -> Position) {
+        if self.uri == *document {
+            self.range.map_positions(document, f);
+        }
+    }
+}
+
+impl Positions for TextEdit {
+    fn map_positions(&mut self, document: &Url, f: &mut dyn FnMut(Position) -> Position) {
+        self.range.map_positions(document, f);
+    }
+}
+
+impl Positions for WorkspaceEdit {
+    fn map_positions(&mut self, document: &Url, f: &mut dyn FnMut(Position) -> Position) {
+        if let Some(edits) = self.changes.as_mut().and_then(|c| c.get_mut(document)) {
+            edits.map_positions(document, f);
+        }
+    }
+}
+
+impl Positions for Diagnostic {
+    fn map_positions(&mut self, document: &Url, f: &mut dyn FnMut(Position) -> Position) {
+        self.range.map_positions(document, f);
+        for related in self.related_information.iter_mut().flatten() {
+            related.location.map_positions(document, f);
+        }
+    }
+}
+
+impl Positions for Hover {
+    fn map_positions(&mut self, document: &Url, f: &mut dyn FnMut(Position) -> Position) {
+        self.range.map_positions(document, f);
+    }
+}
+
+impl Positions for CompletionItem {
+    fn map_positions(&mut self, document: &Url, f: &mut dyn FnMut(Position) -> Position) {
+        match &mut self.text_edit {
+            Some(CompletionTextEdit::Edit(edit)) => edit.map_positions(document, f),
+            Some(CompletionTextEdit::InsertAndReplace(edit)) => {
+                edit.insert.map_positions(document, f);
+                edit.replace.map_positions(document, f);
+            }
+            None => {}
+        }
+        self.additional_text_edits.map_positions(document, f);
+    }
+}
+
+impl Positions for CodeActionOrCommand {
+    fn map_positions(&mut self, document: &Url, f: &mut dyn FnMut(Position) -> Position) {
+        if let CodeActionOrCommand::CodeAction(action) = self {
+            action.map_positions(document, f);
+        }
+    }
+}
+
+impl Positions for CodeAction {
+    fn map_positions(&mut self, document: &Url, f: &mut dyn FnMut(Position) -> Position) {
+        self.diagnostics.map_positions(document, f);
+        self.edit.map_positions(document, f);
+    }
+}
+
+impl Positions for InlayHint {
+    fn map_positions(&mut self, document: &Url, f: &mut dyn FnMut(Position) -> Position) {
+        self.position.map_positions(document, f);
+        self.text_edits.map_positions(document, f);
+        if let InlayHintLabel::LabelParts(parts) = &mut self.label {
+            for part in parts {
+                part.location.map_positions(document, f);
+            }
+        }
+    }
+}
+
+impl Positions for CodeLens {
+    fn map_positions(&mut self, document: &Url, f: &mut dyn FnMut(Position) -> Position) {
+        self.range.map_positions(document, f);
+    }
+}
+
+impl Positions for CallHierarchyItem {
+    fn map_positions(&mut self, document: &Url, f: &mut dyn FnMut(Position) -> Position) {
+        if self.uri == *document {
+            self.range.map_positions(document, f);
+            self.selection_range.map_positions(document, f);
+        }
+    }
+}
+
+impl Positions for CallHierarchyIncomingCall {
+    fn map_positions(&mut self, document: &Url, f: &mut dyn FnMut(Position) -> Position) {
+        self.from.map_positions(document, f);
+        if self.from.uri == *document {
+            self.from_ranges.map_positions(document, f);
+        }
+    }
+}
+
+impl Positions for CallHierarchyOutgoingCall {
+    fn map_positions(&mut self, document: &Url, f: &mut dyn FnMut(Position) -> Position) {
+        self.to.map_positions(document, f);
+        // The ranges are the call sites, in the document the calls are from
+        self.from_ranges.map_positions(document, f);
+    }
+}
+
+impl Positions for LinkedEditingRanges {
+    fn map_positions(&mut self, document: &Url, f: &mut dyn FnMut(Position) -> Position) {
+        self.ranges.map_positions(document, f);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_position_to_offset() {
+        let text = "ab\ncde\nf";
+        let lines = LineOffsets::new(text);
+        let offset =
+            |line, character| lines.position_to_offset(text, Position::new(line, character));
+        assert_eq!(offset(0, 0), Some(0));
+        assert_eq!(offset(1, 1), Some(4));
+        assert_eq!(offset(2, 0), Some(7));
+        // Past end of line clamps
+        assert_eq!(offset(0, 10), Some(2));
+        // Missing line
+        assert_eq!(offset(5, 0), None);
+    }
+
+    #[test]
+    fn test_offset_to_position() {
+        let text = "ab\ncde\nf";
+        let lines = LineOffsets::new(text);
+        assert_eq!(lines.offset_to_position(text, 0), Position::new(0, 0));
+        assert_eq!(lines.offset_to_position(text, 4), Position::new(1, 1));
+        assert_eq!(lines.offset_to_position(text, 7), Position::new(2, 0));
+        assert_eq!(lines.offset_to_position(text, 100), Position::new(2, 1));
+    }
+
+    #[test]
+    fn test_line_offsets() {
+        let text = "ab\r\ncdé\n\nf";
+        let lines = LineOffsets::new(text);
+        assert_eq!(lines.line_count(), 4);
+        assert_eq!(lines.line(text, 0), Some("ab"));
+        assert_eq!(lines.line(text, 1), Some("cdé"));
+        assert_eq!(lines.line(text, 4), None);
+        let positions = [
+            (0, (0, 0)),
+            (2, (0, 2)),
+            (3, (0, 2)),
+            (4, (1, 0)),
+            (6, (1, 2)),
+            (8, (1, 3)),
+            (9, (2, 0)),
+            (10, (3, 0)),
+            (11, (3, 1)),
+            (20, (3, 1)),
+        ];
+        for (offset, (line, character)) in positions {
+            assert_eq!(
+                lines.offset_to_position(text, offset),
+                Position::new(line, character)
+            );
+        }
+        let offsets = [
+            ((0, 0), Some(0)),
+            ((0, 5), Some(2)),
+            ((1, 2), Some(6)),
+            ((1, 3), Some(8)),
+            ((2, 0), Some(9)),
+            ((3, 1), Some(11)),
+            ((4, 0), None),
+        ];
+        for ((line, character), offset) in offsets {
+            assert_eq!(
+                lines.position_to_offset(text, Position::new(line, character)),
+                offset
+            );
+        }
+    }
+
+    #[test]
+    fn test_crlf_positions() {
+        let text = "ab\r\ncd\r\n";
+        let lines = LineOffsets::new(text);
+        let offset =
+            |line, character| lines.position_to_offset(text, Position::new(line, character));
+        assert_eq!(offset(0, 2), Some(2));
+        assert_eq!(offset(0, 9), Some(2));
+        assert_eq!(offset(1, 1), Some(5));
+        assert_eq!(lines.offset_to_position(text, 2), Position::new(0, 2));
+        assert_eq!(lines.offset_to_position(text, 3), Position::new(0, 2));
+        assert_eq!(lines.offset_to_position(text, 4), Position::new(1, 0));
+        // A lone `\r` is an ordinary character
+        let text = "a\rb";
+        let lines = LineOffsets::new(text);
+        assert_eq!(lines.offset_to_position(text, 2), Position::new(0, 2));
+    }
+
+    #[test]
+    fn test_normalize_line_endings() {
+        assert_eq!(normalize_line_endings("a: 1\r\nb: 2\r\n"), "a: 1\nb: 2\n");
+        assert!(matches!(normalize_line_endings("a: 1\n"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_update_keeps_last_good_text() {
+        let mut doc = Document::new("a: 1".to_string(), 1);
+        doc.record_parse(1, true);
+        assert_eq!(doc.last_good(), None);
+
+        doc.update("a: [".to_string(), 2);
+        doc.record_parse(2, false);
+        assert_eq!(doc.last_good().as_deref(), Some("a: 1"));
+        doc.update("a: [1".to_string(), 3);
+        // Analysis of a replaced version is ignored
+        doc.record_parse(2, true);
+        assert_eq!(doc.last_good().as_deref(), Some("a: 1"));
+
+        doc.update("a: [1]".to_string(), 4);
+        doc.record_parse(4, true);
+        assert_eq!(doc.last_good(), None);
+    }
+
+    fn change(range: Option<(u32, u32, u32, u32)>, text: &str) -> TextDocumentContentChangeEvent {
+        TextDocumentContentChangeEvent {
+            range: range
+                .map(|(sl, sc, el, ec)| Range::new(Position::new(sl, sc), Position::new(el, ec))),
+            range_length: None,
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_apply_changes() {
+        let utf16 = PositionEncodingKind::UTF16;
+        let mut doc = Document::new("a: 1\nb: \u{1F600}x\n".to_string(), 1);
+        doc.apply_changes(
+            vec![
+                // After the emoji, which takes two UTF-16 units
+                change(Some((1, 5, 1, 6)), "y"),
+                change(Some((0, 3, 0, 4)), "22"),
+                // Inserting a line, then editing past the end of it
+                change(Some((2, 0, 2, 0)), "c: 3\n"),
+                change(Some((2, 3, 2, 99)), "4"),
+            ],
+            2,
+            &utf16,
+        );
+        assert_eq!(doc.text(), "a: 22\nb: \u{1F600}y\nc: 4\n");
+        assert_eq!(doc.version, 2);
+
+        // Line ends clamp before a CRLF; a change without a range replaces
+        // everything
+        let mut doc = Document::new("a: 1\r\nb: 2\r\n".to_string(), 1);
+        let utf8 = PositionEncodingKind::UTF8;
+        doc.apply_changes(vec![change(Some((0, 3, 0, 10)), "x")], 2, &utf8);
+        assert_eq!(doc.text(), "a: x\r\nb: 2\r\n");
+        doc.apply_changes(vec![change(None, "z: 0")], 3, &utf8);
+        assert_eq!(doc.text(), "z: 0");
+        assert_eq!(doc.rope().len_lines(), 1);
+    }
+
+    #[test]
+    fn test_document_store() {
+        let utf16 = PositionEncodingKind::UTF16;
+        let a = Url::parse("file:///a.yaml.tftpl").unwrap();
+        let b = Url::parse("file:///b.yaml.tftpl").unwrap();
+        let mut store = DocumentStore::new();
+        store.open(a.clone(), "a: 1".to_string(), 1);
+
+        let doc = store.change(&a, vec![change(Some((0, 3, 0, 4)), "2")], 2, &utf16);
+        assert_eq!(doc.map(Document::text), Some("a: 2"));
+        // Unknown documents only take edits after a full replacement
+        let edit = change(Some((0, 0, 0, 0)), "#");
+        assert!(store.change(&b, vec![edit.clone()], 1, &utf16).is_none());
+        let doc = store.change(&b, vec![change(None, "b: 1"), edit], 1, &utf16);
+        assert_eq!(doc.map(Document::text), Some("#b: 1"));
+
+        let mut snapshots = store.snapshots();
+        snapshots.sort_by(|x, y| x.uri.as_str().cmp(y.uri.as_str()));
+        assert_eq!(
+            snapshots,
+            vec![
+                DocumentSnapshot {
+                    uri: a.clone(),
+                    text: "a: 2".to_string(),
+                    version: 2,
+                },
+                DocumentSnapshot {
+                    uri: b.clone(),
+                    text: "#b: 1".to_string(),
+                    version: 1,
+                },
+            ]
+        );
+        assert!(store.close(&a).is_some());
+        assert!(store.get(&a).is_none() && store.snapshot(&a).is_none());
+        assert_eq!(store.len(), 1);
+    }
+
+    #[test]
+    fn test_apply_changes_keeps_last_good_text() {
+        let utf16 = PositionEncodingKind::UTF16;
+        let mut doc = Document::new("a: [1]".to_string(), 1);
+        doc.record_parse(1, true);
+        doc.apply_changes(vec![change(Some((0, 5, 0, 6)), "")], 2, &utf16);
+        // Until analysis has run, the last good text stands in
+        assert_eq!(doc.last_good().as_deref(), Some("a: [1]"));
+        doc.record_parse(2, false);
+        assert_eq!(doc.last_good().as_deref(), Some("a: [1]"));
+        doc.apply_changes(vec![change(Some((0, 5, 0, 5)), ", 2]")], 3, &utf16);
+        doc.record_parse(3, true);
+        assert_eq!(doc.last_good(), None);
+        assert_eq!(doc.text(), "a: [1, 2]");
+    }
+
+    #[test]
+    fn test_check_position() {
+        let utf16 = PositionEncodingKind::UTF16;
+        let text = "a: 1\nb: \u{1F600}x\n";
+        assert!(check_position(text, Position::new(0, 99), &utf16).is_ok());
+        assert!(check_position(text, Position::new(2, 0), &utf16).is_ok());
+        assert_eq!(
+            check_position(text, Position::new(3, 0), &utf16),
+            Err(InputError::LineOutOfRange {
+                line: 3,
+                line_count: 3
+            })
+        );
+
+        // The emoji takes UTF-16 units 3 and 4
+        assert!(check_position(text, Position::new(1, 3), &utf16).is_ok());
+        assert_eq!(
+            check_position(text, Position::new(1, 4), &utf16),
+            Err(InputError::SplitSurrogatePair(Position::new(1, 4)))
+        );
+        assert!(check_position(text, Position::new(1, 5), &utf16).is_ok());
+        assert!(check_position(text, Position::new(1, 4), &PositionEncodingKind::UTF32).is_ok());
+    }
+
+    #[test]
+    fn test_check_position_utf8() {
+        let utf8 = PositionEncodingKind::UTF8;
+        // `é` takes bytes 3 and 4
+        let text = "a: é\n";
+        assert!(check_position(text, Position::new(0, 3), &utf8).is_ok());
+        assert_eq!(
+            check_position(text, Position::new(0, 4), &utf8),
+            Err(InputError::SplitCharacter(Position::new(0, 4)))
+        );
+        assert!(check_position(text, Position::new(0, 5), &utf8).is_ok());
+    }
+
+    #[test]
+    fn test_encode_and_decode() {
+        let uri = Url::parse("file:///w.yaml.tftpl").unwrap();
+        let text = "a: 1\nb: \u{1F600}é x\n";
+        let utf16 = PositionEncoder::new(&uri, text, PositionEncodingKind::UTF16);
+        let utf8 = PositionEncoder::new(&uri, text, PositionEncodingKind::UTF8);
+        let utf32 = PositionEncoder::new(&uri, text, PositionEncodingKind::UTF32);
+
+        // Character 6 is the `x` after the emoji, `é` and a space
+        let x = Position::new(1, 6);
+        assert_eq!(utf16.encode(x), Position::new(1, 7));
+        assert_eq!(utf8.encode(x), Position::new(1, 10));
+        assert_eq!(utf32.encode(x), x);
+        assert_eq!(utf16.decode(Position::new(1, 7)), x);
+        assert_eq!(utf8.decode(Position::new(1, 10)), x);
+
+        // ASCII lines and columns past the line end keep their distance
+        assert_eq!(utf8.encode(Position::new(0, 3)), Position::new(0, 3));
+        assert_eq!(utf16.encode(Position::new(1, 9)), Position::new(1, 10));
+        assert_eq!(utf16.decode(Position::new(1, 10)), Position::new(1, 9));
+    }
+
+    #[test]
+    fn test_positions_in_other_documents_are_kept() {
+        let uri = Url::parse("file:///w.yaml.tftpl").unwrap();
+        let other = Url::parse("file:///main.tf").unwrap();
+        let text = "é: 1\n";
+        let encoder = PositionEncoder::new(&uri, text, PositionEncodingKind::UTF16);
+        let range = Range::new(Position::new(0, 1), Position::new(0, 2));
+        let utf8 = PositionEncoder::new(&uri, text, PositionEncodingKind::UTF8);
+
+        let location = |uri: &Url| Location::new(uri.clone(), range);
+        assert_eq!(encoder.to_client(location(&uri)), location(&uri));
+        assert_eq!(
+            utf8.to_client(location(&uri)).range,
+            Range::new(Position::new(0, 2), Position::new(0, 3))
+        );
+        assert_eq!(utf8.to_client(location(&other)), location(&other));
+    }
+
+    #[test]
+    fn test_semantic_tokens() {
+        let uri = Url::parse("file:///w.yaml.tftpl").unwrap();
+        let text = "a: \u{1F600} $${x}\nb: $${y}\n";
+        let token = |delta_line, delta_start, length| SemanticToken {
+            delta_line,
+            delta_start,
+            length,
+            token_type: 0,
+            token_modifiers_bitset: 0,
+        };
+        let encoder = PositionEncoder::new(&uri, text, PositionEncodingKind::UTF16);
+        let tokens = vec![token(0, 5, 3), token(0, 3, 1), token(1, 3, 3)];
+        assert_eq!(
+            encoder.semantic_tokens(tokens),
+            vec![token(0, 6, 3), token(0, 3, 1), token(1, 3, 3)]
+        );
+    }
+
+    #[test]
+    fn test_check_size() {
+        assert!(check_size("a: 1", 4).is_ok());
+        assert_eq!(
+            check_size("a: 10", 4),
+            Err(InputError::TooLarge { len: 5, limit: 4 })
+        );
     }
 }
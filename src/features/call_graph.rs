@@ -0,0 +1,114 @@
+//! Subworkflow call graph
+//!
+//! A serializable view of which workflows call which, for documentation
+//! generators and architecture tools.
+
+use serde::Serialize;
+
+use crate::parser::Outline;
+
+/// The call graph of a document
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallGraph {
+    /// Workflow definitions, in document order
+    pub nodes: Vec<CallGraphNode>,
+    /// Calls between workflows, one edge per caller/callee pair
+    pub edges: Vec<CallGraphEdge>,
+}
+
+/// A workflow definition
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallGraphNode {
+    pub name: String,
+    /// Line of the definition (0-indexed)
+    pub line: u32,
+    /// Number of direct steps
+    pub step_count: usize,
+}
+
+/// Calls from one workflow to another
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallGraphEdge {
+    pub from: String,
+    pub to: String,
+    /// Lines of the `call:` sites (0-indexed)
+    pub lines: Vec<u32>,
+}
+
+/// Build the call graph of a document
+///
+/// Only calls to workflows defined in the document become edges; standard
+/// library and connector calls such as `http.get` are left out.
+pub fn call_graph(text: &str) -> CallGraph {
+    let outline = Outline::parse(text);
+    let mut graph = CallGraph {
+        nodes: outline
+            .workflows
+            .iter()
+            .map(|w| CallGraphNode {
+                name: w.name.clone(),
+                line: w.line,
+                step_count: w.steps.len(),
+            })
+            .collect(),
+        edges: Vec::new(),
+    };
+
+    for call in &outline.calls {
+        let Some(caller) = &call.caller else {
+            continue;
+        };
+        if outline.workflow(&call.target).is_none() {
+            continue;
+        }
+        match graph
+            .edges
+            .iter_mut()
+            .find(|e| &e.from == caller && e.to == call.target)
+        {
+            Some(edge) => edge.lines.push(call.line),
+            None => graph.edges.push(CallGraphEdge {
+                from: caller.clone(),
+                to: call.target.clone(),
+                lines: vec![call.line],
+            }),
+        }
+    }
+
+    graph
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOC: &str = "main:\n  steps:\n    - a:\n        call: helper\n    - b:\n        call: helper\n    - c:\n        call: http.get\nhelper:\n  params: [x]\n  steps:\n    - d:\n        call: leaf\nleaf:\n  steps:\n    - e:\n        return: 1\n";
+
+    #[test]
+    fn test_call_graph() {
+        let graph = call_graph(DOC);
+        let nodes: Vec<(&str, usize)> = graph
+            .nodes
+            .iter()
+            .map(|n| (n.name.as_str(), n.step_count))
+            .collect();
+        assert_eq!(nodes, vec![("main", 3), ("helper", 1), ("leaf", 1)]);
+
+        assert_eq!(graph.edges.len(), 2);
+        assert_eq!(graph.edges[0].from, "main");
+        assert_eq!(graph.edges[0].to, "helper");
+        assert_eq!(graph.edges[0].lines, vec![3, 5]);
+        assert_eq!(graph.edges[1].from, "helper");
+        assert_eq!(graph.edges[1].to, "leaf");
+    }
+
+    #[test]
+    fn test_serialized_shape() {
+        let json = serde_json::to_value(call_graph(DOC)).unwrap();
+        assert_eq!(json["nodes"][0]["stepCount"], 3);
+        assert_eq!(json["edges"][1]["lines"][0], 12);
+    }
+}
@@ -0,0 +1,173 @@
+//! Call hierarchy for subworkflows
+//!
+//! Items are workflow definitions; calls are the `call:` sites between them.
+//! Calls to standard library functions and connectors are not items.
+
+use tower_lsp::lsp_types::{
+    CallHierarchyIncomingCall, CallHierarchyItem, CallHierarchyOutgoingCall, Position, Range,
+    SymbolKind, Url,
+};
+
+use crate::parser::outline::{CallSite, WorkflowDef};
+use crate::parser::Outline;
+
+/// Find the workflow at a position: either its definition or a call to it
+pub fn prepare_call_hierarchy(
+    text: &str,
+    uri: &Url,
+    position: Position,
+) -> Option<Vec<CallHierarchyItem>> {
+    let outline = Outline::parse(text);
+    let workflow = outline
+        .workflows
+        .iter()
+        .find(|w| w.line == position.line)
+        .or_else(|| {
+            outline
+                .calls
+                .iter()
+                .find(|c| {
+                    c.line == position.line
+                        && position.character >= c.column
+                        && position.character <= c.end_column
+                })
+                .and_then(|c| outline.workflow(&c.target))
+        })?;
+    Some(vec![item(uri, workflow)])
+}
+
+/// Workflows that call the given item, with the ranges of their calls
+pub fn incoming_calls(
+    text: &str,
+    uri: &Url,
+    item: &CallHierarchyItem,
+) -> Vec<CallHierarchyIncomingCall> {
+    let outline = Outline::parse(text);
+    let mut calls: Vec<CallHierarchyIncomingCall> = Vec::new();
+
+    for site in outline.calls_to(&item.name) {
+        let Some(caller) = site.caller.as_deref().and_then(|c| outline.workflow(c)) else {
+            continue;
+        };
+        match calls.iter_mut().find(|c| c.from.name == caller.name) {
+            Some(call) => call.from_ranges.push(site_range(site)),
+            None => calls.push(CallHierarchyIncomingCall {
+                from: self::item(uri, caller),
+                from_ranges: vec![site_range(site)],
+            }),
+        }
+    }
+
+    calls
+}
+
+/// Workflows called by the given item, with the ranges of the calls
+pub fn outgoing_calls(
+    text: &str,
+    uri: &Url,
+    item: &CallHierarchyItem,
+) -> Vec<CallHierarchyOutgoingCall> {
+    let outline = Outline::parse(text);
+    let mut calls: Vec<CallHierarchyOutgoingCall> = Vec::new();
+
+    let sites = outline
+        .calls
+        .iter()
+        .filter(|c| c.caller.as_deref() == Some(item.name.as_str()));
+    for site in sites {
+        let Some(callee) = outline.workflow(&site.target) else {
+            continue;
+        };
+        match calls.iter_mut().find(|c| c.to.name == callee.name) {
+            Some(call) => call.from_ranges.push(site_range(site)),
+            None => calls.push(CallHierarchyOutgoingCall {
+                to: self::item(uri, callee),
+                from_ranges: vec![site_range(site)],
+            }),
+        }
+    }
+
+    calls
+}
+
+fn item(uri: &Url, workflow: &WorkflowDef) -> CallHierarchyItem {
+    let name_end = workflow.name.chars().count() as u32;
+    CallHierarchyItem {
+        name: workflow.name.clone(),
+        kind: SymbolKind::FUNCTION,
+        tags: None,
+        detail: Some(format!(
+            "{} step{}",
+            workflow.steps.len(),
+            if workflow.steps.len() == 1 { "" } else { "s" }
+        )),
+        uri: uri.clone(),
+        range: Range::new(
+            Position::new(workflow.line, 0),
+            Position::new(workflow.end_line + 1, 0),
+        ),
+        selection_range: Range::new(
+            Position::new(workflow.line, 0),
+            Position::new(workflow.line, name_end),
+        ),
+        data: None,
+    }
+}
+
+fn site_range(site: &CallSite) -> Range {
+    Range::new(
+        Position::new(site.line, site.column),
+        Position::new(site.line, site.end_column),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOC: &str = "main:\n  steps:\n    - a:\n        call: helper\n    - b:\n        call: helper\n    - c:\n        call: leaf\nhelper:\n  params: [x]\n  steps:\n    - d:\n        call: leaf\nleaf:\n  steps:\n    - e:\n        call: http.get\n";
+
+    fn uri() -> Url {
+        Url::parse("file:///w.yaml.tftpl").unwrap()
+    }
+
+    fn prepare(line: u32, character: u32) -> Option<CallHierarchyItem> {
+        prepare_call_hierarchy(DOC, &uri(), Position::new(line, character))
+            .map(|items| items.into_iter().next().unwrap())
+    }
+
+    #[test]
+    fn test_prepare_on_definition_and_call() {
+        assert_eq!(prepare(8, 2).unwrap().name, "helper");
+        assert_eq!(prepare(3, 16).unwrap().name, "helper");
+        assert_eq!(prepare(8, 2).unwrap().selection_range.end.character, 6);
+        // Calls to non-workflows have no item
+        assert!(prepare(16, 16).is_none());
+        assert!(prepare(1, 2).is_none());
+    }
+
+    #[test]
+    fn test_incoming_calls() {
+        let leaf = prepare(13, 0).unwrap();
+        let calls = incoming_calls(DOC, &uri(), &leaf);
+        let callers: Vec<&str> = calls.iter().map(|c| c.from.name.as_str()).collect();
+        assert_eq!(callers, vec!["main", "helper"]);
+
+        let helper = prepare(8, 0).unwrap();
+        let calls = incoming_calls(DOC, &uri(), &helper);
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].from_ranges.len(), 2);
+    }
+
+    #[test]
+    fn test_outgoing_calls() {
+        let main = prepare(0, 0).unwrap();
+        let calls = outgoing_calls(DOC, &uri(), &main);
+        let callees: Vec<&str> = calls.iter().map(|c| c.to.name.as_str()).collect();
+        assert_eq!(callees, vec!["helper", "leaf"]);
+        assert_eq!(calls[0].from_ranges[1].start, Position::new(5, 14));
+
+        let leaf = prepare(13, 0).unwrap();
+        assert!(outgoing_calls(DOC, &uri(), &leaf).is_empty());
+    }
+}
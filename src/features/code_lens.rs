@@ -0,0 +1,111 @@
+//! Code lenses
+//!
+//! Shows "N steps" above every workflow definition and, for subworkflows, a
+//! lazily resolved "called from M places" lens.
+
+use serde_json::json;
+use tower_lsp::lsp_types::{CodeLens, Command, Position, Range};
+
+use crate::parser::Outline;
+
+/// Compute the code lenses for a document
+///
+/// Reference-count lenses are returned unresolved, carrying the workflow name
+/// in `data`; [`resolve_code_lens`] fills in their command.
+pub fn code_lenses(text: &str) -> Vec<CodeLens> {
+    let outline = Outline::parse(text);
+    let mut lenses = Vec::new();
+
+    for workflow in &outline.workflows {
+        let range = Range::new(
+            Position::new(workflow.line, 0),
+            Position::new(workflow.line, workflow.name.chars().count() as u32),
+        );
+        let count = workflow.steps.len();
+        lenses.push(CodeLens {
+            range,
+            command: Some(label(format!(
+                "{} step{}",
+                count,
+                if count == 1 { "" } else { "s" }
+            ))),
+            data: None,
+        });
+
+        if workflow.name != "main" {
+            lenses.push(CodeLens {
+                range,
+                command: None,
+                data: Some(json!({ "workflow": workflow.name })),
+            });
+        }
+    }
+
+    lenses
+}
+
+/// Resolve a reference-count lens by counting `call:` sites in the document
+pub fn resolve_code_lens(text: &str, mut lens: CodeLens) -> CodeLens {
+    let Some(name) = lens
+        .data
+        .as_ref()
+        .and_then(|d| d.get("workflow"))
+        .and_then(|w| w.as_str())
+    else {
+        return lens;
+    };
+
+    let count = Outline::parse(text).calls_to(name).count();
+    let title = match count {
+        0 => "not called".to_string(),
+        1 => "called from 1 place".to_string(),
+        n => format!("called from {} places", n),
+    };
+    lens.command = Some(label(title));
+    lens
+}
+
+/// A display-only command
+fn label(title: String) -> Command {
+    Command {
+        title,
+        command: String::new(),
+        arguments: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOC: &str = "main:\n  steps:\n    - a:\n        call: helper\n    - b:\n        call: helper\nhelper:\n  steps:\n    - only:\n        return: 1\n";
+
+    fn title(lens: &CodeLens) -> &str {
+        &lens.command.as_ref().unwrap().title
+    }
+
+    #[test]
+    fn test_step_count_lenses() {
+        let lenses = code_lenses(DOC);
+        assert_eq!(lenses.len(), 3);
+        assert_eq!(title(&lenses[0]), "2 steps");
+        assert_eq!(lenses[0].range.start.line, 0);
+        assert_eq!(title(&lenses[1]), "1 step");
+        assert_eq!(lenses[1].range.start.line, 6);
+        assert!(lenses[2].command.is_none());
+    }
+
+    #[test]
+    fn test_resolve_reference_count() {
+        let lens = code_lenses(DOC).pop().unwrap();
+        let resolved = resolve_code_lens(DOC, lens);
+        assert_eq!(title(&resolved), "called from 2 places");
+    }
+
+    #[test]
+    fn test_resolve_uncalled() {
+        let text = "main:\n  steps:\n    - a:\n        return: 1\nhelper:\n  steps:\n    - b:\n        return: 2\n";
+        let lens = code_lenses(text).pop().unwrap();
+        assert_eq!(title(&resolve_code_lens(text, lens)), "not called");
+    }
+}
@@ -0,0 +1,887 @@
+//! Completion
+//!
+//! Inside a Terraform `${...}` expression, offers the variables, locals and
+//! modules declared in the surrounding module and Terraform's built-in
+//! functions. Inside a Workflows `$${...}` expression, offers the standard
+//! library modules and their functions. After `call:`, offers standard
+//! library functions and connectors, and under `args:` the arguments the
+//! call takes. In a step or workflow body, offers the keys valid there,
+//! reading the structure from the last text that parsed when the current
+//! edit has broken it. Elsewhere, offers skeletons for common step shapes
+//! and the workspace's stored snippets, re-indented to the cursor.
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use tower_lsp::lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionTextEdit, Documentation, InsertTextFormat,
+    MarkupContent, MarkupKind, Position, Range, TextEdit,
+};
+
+use crate::config::Snippet;
+use crate::parser::lines::{indentation, step_spans, strip_comment};
+use crate::schema::{
+    call_args, module_functions, Block, CallArg, Field, StdlibFunction, CONNECTORS,
+    STDLIB_FUNCTIONS, STDLIB_MODULES,
+};
+use crate::terraform::{ModuleDecls, TERRAFORM_FUNCTIONS};
+
+/// Built-in step skeletons: label, description and LSP snippet body
+///
+/// Bodies use `\$\${...}` for literal Workflows expressions since `$` starts
+/// a tab stop in snippet syntax.
+const STEP_SKELETONS: &[(&str, &str, &str)] = &[
+    (
+        "http-call",
+        "HTTP call with try/except",
+        r#"- ${1:call_api}:
+    try:
+      call: http.${2|get,post,put,patch,delete|}
+      args:
+        url: ${3:https://example.com}
+      result: ${4:response}
+    except:
+      as: e
+      steps:
+        - ${5:handle_error}:
+            raise: \$\${e}"#,
+    ),
+    (
+        "switch",
+        "Switch with two conditions",
+        r#"- ${1:decide}:
+    switch:
+      - condition: \$\${${2:x > 0}}
+        next: ${3:positive}
+      - condition: \$\${${4:true}}
+        next: ${5:otherwise}"#,
+    ),
+    (
+        "for-in",
+        "For loop over a list",
+        r#"- ${1:loop}:
+    for:
+      value: ${2:item}
+      in: \$\${${3:items}}
+      steps:
+        - ${4:process}:
+            assign:
+              - ${5:last}: \$\${${2:item}}"#,
+    ),
+    (
+        "parallel-branches",
+        "Parallel step with two branches",
+        r#"- ${1:fanout}:
+    parallel:
+      shared: [${2:results}]
+      branches:
+        - ${3:first}:
+            steps:
+              - ${4:first_step}:
+                  assign:
+                    - ${2:results}: ${5:null}
+        - ${6:second}:
+            steps:
+              - ${7:second_step}:
+                  assign:
+                    - ${2:results}: ${8:null}"#,
+    ),
+];
+
+lazy_static! {
+    /// Tab stops and placeholders (`$1`, `${1:default}`, `${1|a,b|}`)
+    static ref TAB_STOP_RE: Regex =
+        Regex::new(r"\$\{\d+:([^}]*)\}|\$\{\d+\|([^,|]*)[^}]*\|\}|\$\d+").unwrap();
+}
+
+/// Characters that should re-trigger completion
+pub const TRIGGER_CHARACTERS: &[&str] = &[".", "{"];
+
+/// Compute completion items at a position
+pub fn completions(
+    text: &str,
+    last_good: Option<&str>,
+    position: Position,
+    snippets: &[Snippet],
+    decls: &ModuleDecls,
+) -> Vec<CompletionItem> {
+    let line = text.split('\n').nth(position.line as usize).unwrap_or("");
+    let line = line.strip_suffix('\r').unwrap_or(line);
+    let prefix: String = line.chars().take(position.character as usize).collect();
+
+    if let Some(reference) = terraform_reference(&prefix) {
+        return reference_items(reference, position, decls);
+    }
+    if let Some(reference) = workflows_reference(&prefix) {
+        return stdlib_items(reference, position);
+    }
+
+    if let Some(partial) = call_reference(&prefix) {
+        return call_items(partial, position);
+    }
+
+    let partial = prefix.trim_start();
+    if partial
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_')
+    {
+        let line = position.line as usize;
+        let indent = indentation(&prefix);
+        if let Some(args) = args_context(text, line, indent) {
+            return arg_items(args, partial, position);
+        }
+        let keys = key_context(text, line, indent).or_else(|| {
+            let (spliced, line) = splice_into(last_good?, text, line);
+            key_context(&spliced, line, indent)
+        });
+        if let Some(keys) = keys {
+            return key_items(keys, partial, position);
+        }
+    }
+
+    // Snippets are whole blocks, so only offer them on an otherwise empty line
+    if !prefix.trim().is_empty() && prefix.trim() != "-" {
+        return Vec::new();
+    }
+    let indent = indentation(&prefix);
+
+    let skeletons = STEP_SKELETONS.iter().map(|(label, detail, body)| {
+        block_item(
+            label,
+            detail,
+            &preview(body),
+            body,
+            InsertTextFormat::SNIPPET,
+            position,
+            indent,
+        )
+    });
+    let stored = snippets.iter().map(|snippet| {
+        block_item(
+            &snippet.name,
+            snippet
+                .description
+                .as_deref()
+                .unwrap_or("Workspace snippet"),
+            &snippet.body,
+            &snippet.body,
+            InsertTextFormat::PLAIN_TEXT,
+            position,
+            indent,
+        )
+    });
+    skeletons.chain(stored).collect()
+}
+
+/// The text a snippet body inserts with every placeholder at its default
+fn preview(body: &str) -> String {
+    TAB_STOP_RE
+        .replace_all(body, |caps: &regex::Captures| {
+            caps.get(1)
+                .or(caps.get(2))
+                .map_or("", |m| m.as_str())
+                .to_string()
+        })
+        .replace("\\$", "$")
+}
+
+/// The keys valid on a line indented by `indent`, with the keys its
+/// siblings already use removed
+///
+/// Returns `None` unless the line belongs directly to a step body or to a
+/// top-level workflow definition.
+fn key_context(text: &str, line: usize, indent: usize) -> Option<Vec<&'static Field>> {
+    let lines = split_lines(text);
+    let owner = owner_line(&lines, line, indent)?;
+    let block = if step_spans(&lines).iter().any(|&(start, _)| start == owner) {
+        Block::Step
+    } else if indentation(lines[owner]) == 0 {
+        Block::Workflow
+    } else {
+        return None;
+    };
+
+    let used = sibling_keys(&lines, owner, line, indent);
+    Some(
+        block
+            .fields()
+            .iter()
+            .filter(|field| !used.contains(&field.name))
+            .collect(),
+    )
+}
+
+/// The arguments a line indented by `indent` may name, with those its
+/// siblings already use removed
+///
+/// Returns `None` unless the line belongs directly to the `args:` of a call
+/// with a known schema.
+fn args_context(text: &str, line: usize, indent: usize) -> Option<Vec<&'static CallArg>> {
+    let lines = split_lines(text);
+    let owner = owner_line(&lines, line, indent)?;
+    if strip_comment(lines[owner].trim()).trim_start_matches("- ") != "args:" {
+        return None;
+    }
+
+    // The `call:` next to `args:` in the same step body
+    let owner_indent = indentation(lines[owner]);
+    let in_body = |l: &&&str| !is_content(l) || indentation(l) >= owner_indent;
+    let before = lines[..owner].iter().rev().take_while(in_body);
+    let after = lines[owner + 1..].iter().take_while(in_body);
+    let call = before
+        .chain(after)
+        .filter(|l| is_content(l) && indentation(l) == owner_indent)
+        .find_map(|l| strip_comment(l.trim()).strip_prefix("call:"))?;
+    let params = call_args(call.trim())?;
+
+    let used = sibling_keys(&lines, owner, line, indent);
+    Some(params.iter().filter(|p| !used.contains(&p.name)).collect())
+}
+
+fn split_lines(text: &str) -> Vec<&str> {
+    text.split('\n')
+        .map(|l| l.strip_suffix('\r').unwrap_or(l))
+        .collect()
+}
+
+fn is_content(line: &&str) -> bool {
+    let t = line.trim();
+    !t.is_empty() && !t.starts_with('#')
+}
+
+/// The closest line above `line` indented less than `indent`
+fn owner_line(lines: &[&str], line: usize, indent: usize) -> Option<usize> {
+    (0..line.min(lines.len()))
+        .rev()
+        .find(|&i| is_content(&lines[i]) && indentation(lines[i]) < indent)
+}
+
+/// Keys of the lines below `owner` at `indent`, other than `line`
+fn sibling_keys<'t>(lines: &[&'t str], owner: usize, line: usize, indent: usize) -> Vec<&'t str> {
+    let owner_indent = indentation(lines[owner]);
+    lines
+        .iter()
+        .enumerate()
+        .skip(owner + 1)
+        .filter(|&(i, _)| i != line)
+        .map(|(_, l)| *l)
+        .filter(is_content)
+        .take_while(|l| indentation(l) > owner_indent)
+        .filter(|l| indentation(l) == indent)
+        .filter_map(|l| strip_comment(l.trim()).split(':').next())
+        .collect()
+}
+
+/// `good` with line `line` of `text` spliced in where it belongs, and the
+/// line's index in the result
+///
+/// Lines outside the edited region replace their unchanged counterpart. A
+/// line inside it takes the place of the matching line of the old region, or
+/// follows that region when the edit added lines.
+fn splice_into(good: &str, text: &str, line: usize) -> (String, usize) {
+    let good: Vec<&str> = good.split('\n').collect();
+    let current: Vec<&str> = text.split('\n').collect();
+    let common = good
+        .iter()
+        .zip(&current)
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = good
+        .iter()
+        .rev()
+        .zip(current.iter().rev())
+        .take(good.len().min(current.len()) - common)
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let (at, replace) = if line < common {
+        (line, true)
+    } else if line >= current.len() - suffix {
+        (line + good.len() - current.len(), true)
+    } else {
+        let offset = line - common;
+        let region = good.len() - suffix - common;
+        (common + offset.min(region), offset < region)
+    };
+
+    let mut spliced = good[..at].to_vec();
+    spliced.push(current.get(line).copied().unwrap_or(""));
+    spliced.extend(&good[at + usize::from(replace)..]);
+    (spliced.join("\n"), at)
+}
+
+/// Items inserting `key: ` for each valid key
+fn key_items(fields: Vec<&Field>, partial: &str, position: Position) -> Vec<CompletionItem> {
+    let range = Range::new(
+        Position::new(position.line, position.character - partial.len() as u32),
+        position,
+    );
+    fields
+        .into_iter()
+        .map(|field| CompletionItem {
+            label: field.name.to_string(),
+            kind: Some(CompletionItemKind::PROPERTY),
+            documentation: Some(Documentation::MarkupContent(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: field.doc.to_string(),
+            })),
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                range,
+                new_text: format!("{}: ", field.name),
+            })),
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// Items inserting `name: ` for each argument a call takes
+fn arg_items(args: Vec<&CallArg>, partial: &str, position: Position) -> Vec<CompletionItem> {
+    let range = Range::new(
+        Position::new(position.line, position.character - partial.len() as u32),
+        position,
+    );
+    args.into_iter()
+        .map(|arg| CompletionItem {
+            label: arg.name.to_string(),
+            kind: Some(CompletionItemKind::PROPERTY),
+            detail: Some(format!(
+                "{}{}",
+                arg.value_type.map_or("any", |t| t.name()),
+                if arg.required { ", required" } else { "" }
+            )),
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                range,
+                new_text: format!("{}: ", arg.name),
+            })),
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// The partially typed function name after a step's `call:`
+fn call_reference(prefix: &str) -> Option<&str> {
+    let entry = prefix.trim_start();
+    let entry = entry.strip_prefix("- ").unwrap_or(entry);
+    let name = entry.strip_prefix("call:")?.trim_start();
+    name.chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+        .then_some(name)
+}
+
+/// Items for the standard library functions and connectors starting with
+/// `partial`, each replacing the whole name
+fn call_items(partial: &str, position: Position) -> Vec<CompletionItem> {
+    let range = Range::new(
+        Position::new(position.line, position.character - partial.len() as u32),
+        position,
+    );
+    let item = |name: &str, signature: String, description: &str| CompletionItem {
+        label: name.to_string(),
+        kind: Some(CompletionItemKind::FUNCTION),
+        detail: Some(signature),
+        documentation: Some(Documentation::MarkupContent(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: description.to_string(),
+        })),
+        text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+            range,
+            new_text: name.to_string(),
+        })),
+        ..Default::default()
+    };
+    let functions = STDLIB_FUNCTIONS
+        .iter()
+        .filter(|f| f.module().is_some() && f.name.starts_with(partial))
+        .map(|f| item(f.name, f.signature(), f.description));
+    let connectors = CONNECTORS
+        .iter()
+        .filter(|c| c.name.starts_with(partial))
+        .map(|c| item(c.name, c.signature(), c.description));
+    functions.chain(connectors).collect()
+}
+
+/// The partially typed reference when the cursor is inside an open Terraform
+/// `${` expression (but not an escaped Workflows `$${`)
+fn terraform_reference(prefix: &str) -> Option<&str> {
+    let open = prefix.rfind("${")?;
+    if prefix[..open].ends_with('$') || prefix[open..].contains('}') {
+        return None;
+    }
+    let body = &prefix[open + 2..];
+    let start = body
+        .rfind(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.'))
+        .map_or(0, |i| i + 1);
+    Some(&body[start..])
+}
+
+/// Items for a partially typed `var.`/`local.`/`module.` reference
+fn reference_items(
+    reference: &str,
+    position: Position,
+    decls: &ModuleDecls,
+) -> Vec<CompletionItem> {
+    let (namespace, partial) = match reference.split_once('.') {
+        Some((namespace, rest)) if !rest.contains('.') => (Some(namespace), rest),
+        Some(_) => return Vec::new(),
+        None => (None, reference),
+    };
+    let range = Range::new(
+        Position::new(
+            position.line,
+            position.character - partial.chars().count() as u32,
+        ),
+        position,
+    );
+    let item = |label: String, kind, detail: Option<String>, documentation: Option<String>| {
+        CompletionItem {
+            label: label.clone(),
+            kind: Some(kind),
+            detail,
+            documentation: documentation.map(|value| {
+                Documentation::MarkupContent(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value,
+                })
+            }),
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                range,
+                new_text: label,
+            })),
+            ..Default::default()
+        }
+    };
+
+    match namespace {
+        Some("var") => decls
+            .variables
+            .iter()
+            .map(|v| {
+                item(
+                    v.name.clone(),
+                    CompletionItemKind::VARIABLE,
+                    v.type_expr.clone(),
+                    v.description.clone(),
+                )
+            })
+            .collect(),
+        Some("local") => decls
+            .locals
+            .iter()
+            .map(|name| item(name.clone(), CompletionItemKind::VARIABLE, None, None))
+            .collect(),
+        Some("module") => decls
+            .modules
+            .iter()
+            .map(|name| item(name.clone(), CompletionItemKind::MODULE, None, None))
+            .collect(),
+        Some(_) => Vec::new(),
+        None => [
+            ("var.", !decls.variables.is_empty()),
+            ("local.", !decls.locals.is_empty()),
+            ("module.", !decls.modules.is_empty()),
+        ]
+        .into_iter()
+        .filter(|&(_, discovered)| discovered)
+        .map(|(label, _)| item(label.to_string(), CompletionItemKind::KEYWORD, None, None))
+        .chain(TERRAFORM_FUNCTIONS.iter().map(|function| CompletionItem {
+            insert_text_format: Some(InsertTextFormat::SNIPPET),
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                range,
+                new_text: function.snippet(),
+            })),
+            ..item(
+                function.name.to_string(),
+                CompletionItemKind::FUNCTION,
+                Some(function.signature()),
+                Some(function.description.to_string()),
+            )
+        }))
+        .collect(),
+    }
+}
+
+/// The partially typed name when the cursor is inside an open Workflows
+/// `$${` expression
+fn workflows_reference(prefix: &str) -> Option<&str> {
+    let open = prefix.rfind("$${")?;
+    if prefix[open..].contains('}') {
+        return None;
+    }
+    let body = &prefix[open + 3..];
+    let start = body
+        .rfind(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.'))
+        .map_or(0, |i| i + 1);
+    // A field of a value, such as `x.` in `a + x.`, is not a module
+    if body[..start].ends_with(['"', '\'']) {
+        return None;
+    }
+    Some(&body[start..])
+}
+
+/// Items for a partially typed standard library module or function
+fn stdlib_items(reference: &str, position: Position) -> Vec<CompletionItem> {
+    let (module, partial) = match reference.split_once('.') {
+        Some((module, rest)) if !rest.contains('.') && STDLIB_MODULES.contains(&module) => {
+            (Some(module), rest)
+        }
+        Some(_) => return Vec::new(),
+        None => (None, reference),
+    };
+    let range = Range::new(
+        Position::new(position.line, position.character - partial.len() as u32),
+        position,
+    );
+
+    // Blocking calls such as `http.get` only run as a step's `call:`
+    let functions = module_functions(module)
+        .filter(|function| function.in_expressions())
+        .map(|function| function_item(function, range));
+    if module.is_some() {
+        return functions.collect();
+    }
+    let modules = STDLIB_MODULES
+        .iter()
+        .filter(|module| module_functions(Some(module)).any(|f| f.in_expressions()))
+        .map(|module| CompletionItem {
+            label: format!("{}.", module),
+            kind: Some(CompletionItemKind::MODULE),
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                range,
+                new_text: format!("{}.", module),
+            })),
+            ..Default::default()
+        });
+    modules.chain(functions).collect()
+}
+
+/// Item inserting a call to `function`, with a tab stop for its arguments
+fn function_item(function: &StdlibFunction, range: Range) -> CompletionItem {
+    let arguments = if function.params.is_empty() { "" } else { "$1" };
+    CompletionItem {
+        label: function.short_name().to_string(),
+        kind: Some(CompletionItemKind::FUNCTION),
+        detail: Some(function.signature()),
+        documentation: Some(Documentation::MarkupContent(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: function.description.to_string(),
+        })),
+        insert_text_format: Some(InsertTextFormat::SNIPPET),
+        text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+            range,
+            new_text: format!("{}({})", function.short_name(), arguments),
+        })),
+        ..Default::default()
+    }
+}
+
+/// Build the completion item for a block of YAML (a snippet or skeleton)
+///
+/// The block replaces everything from the first non-blank character of the
+/// line to the cursor, and continuation lines are indented to match.
+fn block_item(
+    label: &str,
+    detail: &str,
+    preview: &str,
+    body: &str,
+    format: InsertTextFormat,
+    position: Position,
+    indent: usize,
+) -> CompletionItem {
+    let padding = " ".repeat(indent);
+    let new_text = body
+        .lines()
+        .enumerate()
+        .map(|(i, l)| {
+            if i == 0 || l.is_empty() {
+                l.to_string()
+            } else {
+                format!("{}{}", padding, l)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    CompletionItem {
+        label: label.to_string(),
+        kind: Some(CompletionItemKind::SNIPPET),
+        detail: Some(detail.to_string()),
+        documentation: Some(Documentation::MarkupContent(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: format!("```yaml\n{}\n```", preview),
+        })),
+        insert_text_format: Some(format),
+        text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+            range: Range::new(Position::new(position.line, indent as u32), position),
+            new_text,
+        })),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snippets() -> Vec<Snippet> {
+        vec![Snippet {
+            name: "log".to_string(),
+            description: None,
+            body: "- log:\n    call: sys.log".to_string(),
+        }]
+    }
+
+    fn decls() -> ModuleDecls {
+        let variable = |name: &str| crate::terraform::VariableDecl {
+            name: name.to_string(),
+            type_expr: Some("string".to_string()),
+            default: None,
+            description: None,
+            file: "variables.tf".into(),
+        };
+        ModuleDecls {
+            variables: vec![variable("region"), variable("replicas")],
+            locals: vec!["prefix".to_string()],
+            modules: vec!["network".to_string()],
+        }
+    }
+
+    fn edit(item: &CompletionItem) -> &TextEdit {
+        match item.text_edit.as_ref().unwrap() {
+            CompletionTextEdit::Edit(edit) => edit,
+            _ => panic!("expected plain edit"),
+        }
+    }
+
+    #[test]
+    fn test_snippet_reindented_to_cursor() {
+        let text = "main:\n  steps:\n    ";
+        let items = completions(text, None, Position::new(2, 4), &snippets(), &decls());
+        let log = items.iter().find(|i| i.label == "log").unwrap();
+        assert_eq!(log.insert_text_format, Some(InsertTextFormat::PLAIN_TEXT));
+        assert_eq!(edit(log).new_text, "- log:\n        call: sys.log");
+        assert_eq!(edit(log).range.start.character, 4);
+    }
+
+    #[test]
+    fn test_snippet_replaces_typed_dash() {
+        let text = "  steps:\n    - ";
+        let items = completions(text, None, Position::new(1, 6), &snippets(), &decls());
+        assert_eq!(edit(&items[0]).range.start.character, 4);
+    }
+
+    #[test]
+    fn test_step_skeletons() {
+        let text = "main:\n  steps:\n    ";
+        let items = completions(text, None, Position::new(2, 4), &[], &decls());
+        let labels: Vec<&str> = items.iter().map(|i| i.label.as_str()).collect();
+        assert_eq!(
+            labels,
+            vec!["http-call", "switch", "for-in", "parallel-branches"]
+        );
+
+        let switch = &items[1];
+        assert_eq!(switch.insert_text_format, Some(InsertTextFormat::SNIPPET));
+        assert!(edit(switch).new_text.starts_with(
+            "- ${1:decide}:\n        switch:\n          - condition: \\$\\${${2:x > 0}}"
+        ));
+
+        let Some(Documentation::MarkupContent(doc)) = &switch.documentation else {
+            panic!("expected markup");
+        };
+        assert!(doc
+            .value
+            .contains("- decide:\n    switch:\n      - condition: $${x > 0}"));
+    }
+
+    #[test]
+    fn test_preview_choices_and_tab_stops() {
+        assert_eq!(preview("call: http.${2|get,post|}$0"), "call: http.get");
+    }
+
+    #[test]
+    fn test_no_snippets_mid_line() {
+        let text = "key: va";
+        assert!(completions(text, None, Position::new(0, 7), &snippets(), &decls()).is_empty());
+    }
+
+    fn labels(text: &str, character: u32) -> Vec<String> {
+        completions(
+            text,
+            None,
+            Position::new(0, character),
+            &snippets(),
+            &decls(),
+        )
+        .into_iter()
+        .map(|i| i.label)
+        .collect()
+    }
+
+    #[test]
+    fn test_step_body_keys() {
+        let text = "main:\n  steps:\n    - get:\n        call: http.get\n        ca";
+        let items = completions(text, None, Position::new(4, 10), &snippets(), &decls());
+        let labels: Vec<&str> = items.iter().map(|i| i.label.as_str()).collect();
+        assert!(labels.contains(&"call_log_level"));
+        assert!(!labels.contains(&"call"));
+        assert_eq!(edit(&items[0]).new_text, "args: ");
+        assert_eq!(edit(&items[0]).range.start.character, 8);
+    }
+
+    #[test]
+    fn test_keys_from_last_good_text() {
+        let good = "main:\n  steps:\n    - get:\n        call: http.get\n";
+        // Renaming the step has left its line without a colon
+        let text = "main:\n  steps:\n    - get_user\n        call: http.get\n        ar";
+        assert!(completions(text, None, Position::new(4, 10), &[], &decls()).is_empty());
+
+        let items = completions(text, Some(good), Position::new(4, 10), &[], &decls());
+        let labels: Vec<&str> = items.iter().map(|i| i.label.as_str()).collect();
+        assert!(labels.contains(&"args"));
+        assert!(!labels.contains(&"call"));
+    }
+
+    #[test]
+    fn test_splice_into() {
+        let good = "a:\n  b: 1\n  c: 2\nd: 3";
+        // Unchanged line keeps its place
+        assert_eq!(
+            splice_into(good, "a:\n  b: 1\n  c: [\nd: 3", 1),
+            (good.to_string(), 1)
+        );
+        // Edited line replaces its old version
+        assert_eq!(
+            splice_into(good, "a:\n  b: 1\n  c: [\nd: 3", 2),
+            ("a:\n  b: 1\n  c: [\nd: 3".to_string(), 2)
+        );
+        // Inserted line follows the old region
+        assert_eq!(
+            splice_into(good, "a:\n  b: [\n  x\n  c: 2\nd: 3", 2),
+            ("a:\n  b: 1\n  x\n  c: 2\nd: 3".to_string(), 2)
+        );
+    }
+
+    #[test]
+    fn test_workflow_keys() {
+        let text = "main:\n  steps:\n    - a:\n        return: 1\n  ";
+        let items = completions(text, None, Position::new(4, 2), &snippets(), &decls());
+        let labels: Vec<&str> = items.iter().map(|i| i.label.as_str()).collect();
+        assert_eq!(labels, vec!["params", "labels"]);
+    }
+
+    #[test]
+    fn test_terraform_variable_completion() {
+        let text = "region: ${var.re";
+        assert_eq!(labels(text, 16), vec!["region", "replicas"]);
+
+        let items = completions(text, None, Position::new(0, 16), &snippets(), &decls());
+        assert_eq!(items[0].detail.as_deref(), Some("string"));
+        assert_eq!(edit(&items[0]).range.start.character, 14);
+    }
+
+    #[test]
+    fn test_terraform_namespaces() {
+        assert_eq!(labels("a: ${", 5)[..3], ["var.", "local.", "module."]);
+        assert_eq!(labels("a: ${upper(local.", 17), vec!["prefix"]);
+        assert_eq!(labels("a: ${module.", 12), vec!["network"]);
+    }
+
+    #[test]
+    fn test_terraform_function_completion() {
+        let text = "a: ${jo";
+        let items = completions(text, None, Position::new(0, 7), &snippets(), &decls());
+        let join = items.iter().find(|i| i.label == "join").unwrap();
+        assert_eq!(join.kind, Some(CompletionItemKind::FUNCTION));
+        assert_eq!(join.detail.as_deref(), Some("join(separator, list)"));
+        assert_eq!(join.insert_text_format, Some(InsertTextFormat::SNIPPET));
+        assert_eq!(edit(join).new_text, "join(${1:separator}, ${2:list})");
+        assert_eq!(edit(join).range.start.character, 5);
+
+        // Functions aren't members of a namespace
+        assert!(!labels("a: ${var.", 9).contains(&"join".to_string()));
+        assert!(labels("a: ${upper(", 11).contains(&"jsonencode".to_string()));
+    }
+
+    #[test]
+    fn test_stdlib_completion() {
+        let text = "a: $${text.sp";
+        let items = completions(text, None, Position::new(0, 13), &snippets(), &decls());
+        let labels: Vec<&str> = items.iter().map(|i| i.label.as_str()).collect();
+        assert!(labels.contains(&"split"));
+        assert!(labels.contains(&"to_upper"));
+        assert!(!labels.contains(&"now"));
+
+        let split = items.iter().find(|i| i.label == "split").unwrap();
+        assert_eq!(
+            split.detail.as_deref(),
+            Some("text.split(source, separator)")
+        );
+        assert_eq!(edit(split).new_text, "split($1)");
+        assert_eq!(edit(split).range.start.character, 11);
+
+        let items = completions("a: $${sys.", None, Position::new(0, 10), &[], &decls());
+        let now = items.iter().find(|i| i.label == "now").unwrap();
+        assert_eq!(edit(now).new_text, "now()");
+        // Blocking calls aren't offered inside expressions
+        assert!(!items.iter().any(|i| i.label == "sleep"));
+        let http = super::tests::labels("a: $${http.", 11);
+        assert!(http.contains(&"default_retry_predicate".to_string()));
+        assert!(!http.contains(&"get".to_string()));
+    }
+
+    #[test]
+    fn test_call_completion() {
+        let text = "main:\n  steps:\n    - q:\n        call: googleapis.bigquery.v2.jobs.q";
+        let items = completions(text, None, Position::new(3, 43), &[], &decls());
+        let labels: Vec<&str> = items.iter().map(|i| i.label.as_str()).collect();
+        assert_eq!(labels, vec!["googleapis.bigquery.v2.jobs.query"]);
+        assert_eq!(edit(&items[0]).range.start.character, 14);
+
+        let items = completions(
+            "- s:\n    call: sys.sl",
+            None,
+            Position::new(1, 16),
+            &[],
+            &decls(),
+        );
+        let labels: Vec<&str> = items.iter().map(|i| i.label.as_str()).collect();
+        assert_eq!(labels, vec!["sys.sleep", "sys.sleep_until"]);
+    }
+
+    #[test]
+    fn test_args_completion() {
+        let text = "main:\n  steps:\n    - q:\n        call: googleapis.pubsub.v1.projects.topics.publish\n        args:\n          topic: t\n          \n";
+        let items = completions(text, None, Position::new(6, 10), &[], &decls());
+        let labels: Vec<&str> = items.iter().map(|i| i.label.as_str()).collect();
+        assert_eq!(labels, vec!["body", "connector_params"]);
+        assert_eq!(items[0].detail.as_deref(), Some("mapping, required"));
+        assert_eq!(edit(&items[0]).new_text, "body: ");
+
+        // Args of calls without a schema fall back to snippets
+        let text = text.replace("googleapis.pubsub.v1.projects.topics.publish", "helper");
+        let items = completions(&text, None, Position::new(6, 10), &[], &decls());
+        assert!(!items.iter().any(|i| i.label == "body"));
+    }
+
+    #[test]
+    fn test_stdlib_modules_and_builtins() {
+        let items = labels("a: $${len(x) + ", 15);
+        assert!(items.contains(&"math.".to_string()));
+        assert!(items.contains(&"uuid.".to_string()));
+        assert!(items.contains(&"retry.".to_string()));
+        assert!(!items.contains(&"events.".to_string()));
+        assert!(items.contains(&"len".to_string()));
+        assert!(!items.contains(&"split".to_string()));
+
+        // Fields of variables, closed expressions and strings
+        assert!(labels("a: $${user.", 11).is_empty());
+        assert!(labels("a: $${x} text.", 14).is_empty());
+        assert!(labels("a: $${\"text.", 12).is_empty());
+    }
+
+    #[test]
+    fn test_no_terraform_completion_outside_expressions() {
+        // Workflows expressions and closed Terraform expressions
+        assert!(labels("a: $${var.", 10).is_empty());
+        assert!(labels("a: ${var.x} var.", 16).is_empty());
+    }
+}
@@ -0,0 +1,108 @@
+//! Go to definition from a YAML `*alias` to the `&anchor` it refers to, and
+//! from `${var.x}`/`${local.y}` to the Terraform symbol it names
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use tower_lsp::lsp_types::{Position, Range};
+
+use crate::document::LineOffsets;
+use crate::parser::{preprocess_expressions, scan_anchors, ExpressionKind};
+
+lazy_static! {
+    static ref REFERENCE_RE: Regex =
+        Regex::new(r"\b(var|local)\.([A-Za-z_][A-Za-z0-9_-]*)").unwrap();
+}
+
+/// A Terraform symbol referenced from a `${...}` expression or `%{...}`
+/// directive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TerraformSymbol {
+    /// `var.name`, declared by a `variable "name"` block
+    Variable(String),
+    /// `local.name`, declared in a `locals` block
+    Local(String),
+}
+
+/// Range of the anchor declaration the alias under the cursor resolves to
+pub fn definition(text: &str, position: Position) -> Option<Range> {
+    let (_, expression_map) = preprocess_expressions(text);
+    let anchors = scan_anchors(text, &expression_map);
+    let alias = anchors.alias_at(position.line, position.character)?;
+    let anchor = anchors.resolve(alias)?;
+    Some(Range::new(
+        Position::new(anchor.line, anchor.column),
+        Position::new(anchor.line, anchor.end_column),
+    ))
+}
+
+/// The `var.` or `local.` reference under the cursor, if any
+pub fn terraform_symbol_at(text: &str, position: Position) -> Option<TerraformSymbol> {
+    let offset = LineOffsets::new(text).position_to_offset(text, position)?;
+    let (_, expression_map) = preprocess_expressions(text);
+    let expr = expression_map
+        .expressions
+        .iter()
+        .find(|e| e.kind != ExpressionKind::Workflows && offset >= e.start && offset < e.end)?;
+
+    let found = REFERENCE_RE.captures_iter(&expr.original).find(|found| {
+        let whole = found.get(0).unwrap();
+        let before = &expr.original[..whole.start()];
+        let in_string = before.matches('"').count() - before.matches("\\\"").count();
+        in_string % 2 == 0
+            && offset >= expr.start + whole.start()
+            && offset <= expr.start + whole.end()
+    })?;
+    let name = found[2].to_string();
+    Some(match &found[1] {
+        "var" => TerraformSymbol::Variable(name),
+        _ => TerraformSymbol::Local(name),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "a: &x 1\nb: *x\nc: &x 2\nd: [*x, *y]\n";
+
+    #[test]
+    fn test_alias_jumps_to_closest_preceding_anchor() {
+        assert_eq!(
+            definition(TEXT, Position::new(1, 4)),
+            Some(Range::new(Position::new(0, 3), Position::new(0, 5)))
+        );
+        assert_eq!(
+            definition(TEXT, Position::new(3, 5)),
+            Some(Range::new(Position::new(2, 3), Position::new(2, 5)))
+        );
+    }
+
+    #[test]
+    fn test_no_definition() {
+        // Undefined alias, anchor itself, plain text
+        assert_eq!(definition(TEXT, Position::new(3, 9)), None);
+        assert_eq!(definition(TEXT, Position::new(0, 4)), None);
+        assert_eq!(definition(TEXT, Position::new(1, 0)), None);
+    }
+
+    #[test]
+    fn test_terraform_symbol_at() {
+        let text = "a: ${var.project}\nb: ${format(\"var.x-%s\", local.suffix)}\nc: $${var.y}\n%{ if var.debug }\n";
+        assert_eq!(
+            terraform_symbol_at(text, Position::new(0, 10)),
+            Some(TerraformSymbol::Variable("project".to_string()))
+        );
+        assert_eq!(
+            terraform_symbol_at(text, Position::new(1, 30)),
+            Some(TerraformSymbol::Local("suffix".to_string()))
+        );
+        assert_eq!(
+            terraform_symbol_at(text, Position::new(3, 8)),
+            Some(TerraformSymbol::Variable("debug".to_string()))
+        );
+        // Inside a string, a Workflows expression, or outside any reference
+        assert_eq!(terraform_symbol_at(text, Position::new(1, 16)), None);
+        assert_eq!(terraform_symbol_at(text, Position::new(2, 9)), None);
+        assert_eq!(terraform_symbol_at(text, Position::new(1, 6)), None);
+    }
+}
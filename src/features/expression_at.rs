@@ -0,0 +1,82 @@
+//! Expression under the cursor
+//!
+//! Reports the `${...}`/`$${...}` expression at a position exactly as the
+//! scanner sees it, so editor extensions can copy or evaluate it without
+//! re-implementing brace matching.
+
+use std::ops::Range as ByteRange;
+
+use serde::Serialize;
+use tower_lsp::lsp_types::{Position, Range};
+
+use crate::document::LineOffsets;
+use crate::parser::{preprocess_expressions, ExpressionKind};
+
+/// An expression and where it sits in the document
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExpressionAt {
+    /// Original expression text, including the `${`/`$${` and `}`
+    pub text: String,
+    pub kind: ExpressionKind,
+    pub range: Range,
+    /// Byte offsets of the expression in the document text
+    pub byte_range: ByteRange<usize>,
+}
+
+/// The expression containing a position, if any
+pub fn expression_at(text: &str, position: Position) -> Option<ExpressionAt> {
+    let lines = LineOffsets::new(text);
+    let offset = lines.position_to_offset(text, position)?;
+    let (_, map) = preprocess_expressions(text);
+    let expression = map
+        .expressions
+        .into_iter()
+        .find(|e| e.start <= offset && offset < e.end)?;
+
+    Some(ExpressionAt {
+        range: Range::new(
+            lines.offset_to_position(text, expression.start),
+            lines.offset_to_position(text, expression.end),
+        ),
+        byte_range: expression.start..expression.end,
+        text: expression.original,
+        kind: expression.kind,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_terraform_expression() {
+        let text = "a: 1\nurl: ${var.base}/x";
+        let found = expression_at(text, Position::new(1, 8)).unwrap();
+        assert_eq!(found.text, "${var.base}");
+        assert_eq!(found.kind, ExpressionKind::Terraform);
+        assert_eq!(
+            found.range,
+            Range::new(Position::new(1, 5), Position::new(1, 16))
+        );
+        assert_eq!(found.byte_range, 10..21);
+    }
+
+    #[test]
+    fn test_multiline_workflows_expression() {
+        let text = "x: é $${map.get(\n  a, \"b\")}\n";
+        let found = expression_at(text, Position::new(1, 3)).unwrap();
+        assert_eq!(found.kind, ExpressionKind::Workflows);
+        assert_eq!(found.text, "$${map.get(\n  a, \"b\")}");
+        assert_eq!(found.range.start, Position::new(0, 5));
+        assert_eq!(found.range.end, Position::new(1, 10));
+        assert_eq!(found.byte_range.start, 6);
+    }
+
+    #[test]
+    fn test_outside_expressions() {
+        let text = "url: ${var.base}/x";
+        assert_eq!(expression_at(text, Position::new(0, 2)), None);
+        assert_eq!(expression_at(text, Position::new(0, 16)), None);
+    }
+}
@@ -0,0 +1,280 @@
+//! Expression whitespace normalization
+//!
+//! Rewrites the spacing inside `${...}` and `$${...}` expressions to one
+//! style, e.g. `$${ x+1}` becomes `$${x + 1}`: single spaces around binary
+//! operators and after commas and colons, none inside brackets or around
+//! `.`. Only single-line expressions the tokenizer fully understands are
+//! changed, and the YAML around them is never touched.
+
+use std::collections::HashMap;
+
+use tower_lsp::lsp_types::{
+    CodeAction, CodeActionKind, CodeActionOrCommand, Position, Range, TextEdit, Url, WorkspaceEdit,
+};
+
+use crate::document::LineOffsets;
+use crate::parser::tokens::{tokenize, Token, TokenKind};
+use crate::parser::{preprocess_expressions, ExpressionKind};
+
+/// Names that act as operators, and so are spaced like them
+const WORD_OPERATORS: &[&str] = &["and", "or", "not", "in", "for", "if"];
+
+/// Word operators that are read as a name, e.g. a map key or Workflows
+/// function, when a `(` follows
+const CALLABLE_WORDS: &[&str] = &["in", "for", "if"];
+
+/// Edits normalizing every expression that intersects `range`
+pub fn expression_whitespace_edits(text: &str, range: Range) -> Vec<TextEdit> {
+    let (_, map) = preprocess_expressions(text);
+    let lines = LineOffsets::new(text);
+    map.expressions
+        .iter()
+        .filter(|e| e.start_line == e.end_line)
+        .filter(|e| e.start_line >= range.start.line && e.start_line <= range.end.line)
+        .filter_map(|e| {
+            let prefix = match e.kind {
+                ExpressionKind::Terraform => "${",
+                ExpressionKind::Workflows => "$${",
+                ExpressionKind::Directive => return None,
+            };
+            let body = e.original.strip_prefix(prefix)?.strip_suffix('}')?;
+            let normalized = format!("{}{}}}", prefix, normalize(body)?);
+            (normalized != e.original).then(|| TextEdit {
+                range: Range::new(
+                    lines.offset_to_position(text, e.start),
+                    lines.offset_to_position(text, e.end),
+                ),
+                new_text: normalized,
+            })
+        })
+        .collect()
+}
+
+/// Code actions normalizing the expressions in `range`, and a fix-all for the
+/// whole document, limited to the kinds in `only` when given
+pub fn expression_code_actions(
+    uri: &Url,
+    text: &str,
+    range: Range,
+    only: Option<&[CodeActionKind]>,
+) -> Vec<CodeActionOrCommand> {
+    let wanted = |kind: &CodeActionKind| {
+        only.is_none_or(|only| {
+            only.iter().any(|o| {
+                kind.as_str() == o.as_str()
+                    || kind.as_str().starts_with(&format!("{}.", o.as_str()))
+            })
+        })
+    };
+    let action = |title: &str, kind: CodeActionKind, edits: Vec<TextEdit>| {
+        CodeActionOrCommand::CodeAction(CodeAction {
+            title: title.to_string(),
+            kind: Some(kind),
+            edit: Some(WorkspaceEdit {
+                changes: Some(HashMap::from([(uri.clone(), edits)])),
+                ..Default::default()
+            }),
+            ..Default::default()
+        })
+    };
+
+    let mut actions = Vec::new();
+    if wanted(&CodeActionKind::REFACTOR_REWRITE) {
+        let edits = expression_whitespace_edits(text, range);
+        if !edits.is_empty() {
+            actions.push(action(
+                "Normalize expression whitespace",
+                CodeActionKind::REFACTOR_REWRITE,
+                edits,
+            ));
+        }
+    }
+    if wanted(&CodeActionKind::SOURCE_FIX_ALL) {
+        let whole = Range::new(Position::new(0, 0), Position::new(u32::MAX, 0));
+        let edits = expression_whitespace_edits(text, whole);
+        if !edits.is_empty() {
+            actions.push(action(
+                "Normalize whitespace in all expressions",
+                CodeActionKind::SOURCE_FIX_ALL,
+                edits,
+            ));
+        }
+    }
+    actions
+}
+
+/// Normalize the spacing of an expression body
+fn normalize(body: &str) -> Option<String> {
+    let tokens = tokenize(body)?;
+    let mut out = String::with_capacity(body.len());
+    // Bracket depths of the open `?`s and `for`s, so the `:` closing one can
+    // be told apart from a map key separator
+    let mut pending: Vec<usize> = Vec::new();
+    let mut depth = 0usize;
+    let mut prev: Option<(Token, bool)> = None;
+
+    for (i, &token) in tokens.iter().enumerate() {
+        let called = tokens.get(i + 1).is_some_and(|t| t.text == "(");
+        let unary = is_prefix(&token, prev.as_ref().map(|(t, _)| t));
+        let ternary_colon = token.kind == TokenKind::Colon && pending.last() == Some(&depth);
+        if let Some((prev, prev_unary)) = &prev {
+            if space_between(prev, *prev_unary, &token, ternary_colon) {
+                out.push(' ');
+            }
+        }
+        match token.kind {
+            TokenKind::Open => depth += 1,
+            TokenKind::Close => depth = depth.saturating_sub(1),
+            TokenKind::Question => pending.push(depth),
+            TokenKind::Name if token.text == "for" && !called => pending.push(depth),
+            TokenKind::Colon if ternary_colon => {
+                pending.pop();
+            }
+            _ => {}
+        }
+        out.push_str(token.text);
+        // A ternary `:` is spaced on both sides; remember it as an operator
+        let token = if ternary_colon {
+            Token {
+                kind: TokenKind::Operator,
+                ..token
+            }
+        } else {
+            token
+        };
+        prev = Some((token, unary));
+    }
+
+    Some(out)
+}
+
+/// Whether `token` is a prefix operator given the token before it
+fn is_prefix(token: &Token, prev: Option<&Token>) -> bool {
+    match token.kind {
+        TokenKind::Bang => true,
+        TokenKind::Operator if token.text == "-" => match prev {
+            None => true,
+            Some(prev) => {
+                matches!(
+                    prev.kind,
+                    TokenKind::Operator
+                        | TokenKind::Bang
+                        | TokenKind::Open
+                        | TokenKind::Comma
+                        | TokenKind::Colon
+                        | TokenKind::Question
+                ) || is_word_operator(prev)
+            }
+        },
+        _ => false,
+    }
+}
+
+fn is_word_operator(token: &Token) -> bool {
+    token.kind == TokenKind::Name && WORD_OPERATORS.contains(&token.text)
+}
+
+/// Whether a space separates `prev` from `next`
+fn space_between(prev: &Token, prev_unary: bool, next: &Token, ternary_colon: bool) -> bool {
+    use TokenKind::*;
+
+    if prev_unary || prev.kind == Open || prev.kind == Dot {
+        return false;
+    }
+    match next.kind {
+        Close | Comma | Dot | Ellipsis => false,
+        Colon => ternary_colon,
+        // Calls and indexes hug what they apply to
+        Open if next.text != "{" => {
+            let call = next.text == "(" && CALLABLE_WORDS.contains(&prev.text);
+            !matches!(prev.kind, Name | Close | String) || (is_word_operator(prev) && !call)
+        }
+        // `[*]` and `.*` splats
+        Operator if next.text == "*" && matches!(prev.kind, Open | Dot) => false,
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        let cases = [
+            (" x+1", "x + 1"),
+            ("map.get( args , \"key\" )", "map.get(args, \"key\")"),
+            ("a==b&&!c", "a == b && !c"),
+            ("-x*  -2", "-x * -2"),
+            ("not  ok", "not ok"),
+            ("x in [ 1,2 ]", "x in [1, 2]"),
+            ("{\"a\" :1}", "{\"a\": 1}"),
+            ("cond?1:2", "cond ? 1 : 2"),
+            ("c?{\"a\":1}:2", "c ? {\"a\": 1} : 2"),
+            (
+                "[for s in var.list: upper(s)]",
+                "[for s in var.list : upper(s)]",
+            ),
+            ("var.list[*].id", "var.list[*].id"),
+            ("jsonencode( var.x )", "jsonencode(var.x)"),
+            ("f(args...)", "f(args...)"),
+            ("if(x, 1, 2)", "if(x, 1, 2)"),
+            ("m.get(in( \"k\" ))", "m.get(in(\"k\"))"),
+            ("for(x)?1:2", "for(x) ? 1 : 2"),
+            ("x in [ 1 ]", "x in [1]"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(normalize(input).as_deref(), Some(expected), "{}", input);
+        }
+        assert_eq!(normalize("\"${nested}\""), None);
+    }
+
+    #[test]
+    fn test_edits_leave_yaml_alone() {
+        let text = "a:  $${ x+1}   # note\nb: ${var.ok}\nc: $${map.get(\n  m, \"k\")}\n";
+        let all = Range::new(Position::new(0, 0), Position::new(3, 0));
+        let edits = expression_whitespace_edits(text, all);
+        assert_eq!(
+            edits,
+            vec![TextEdit {
+                range: Range::new(Position::new(0, 4), Position::new(0, 12)),
+                new_text: "$${x + 1}".to_string(),
+            }]
+        );
+
+        let elsewhere = Range::new(Position::new(1, 0), Position::new(1, 0));
+        assert!(expression_whitespace_edits(text, elsewhere).is_empty());
+    }
+
+    #[test]
+    fn test_code_actions() {
+        let uri = Url::parse("file:///w.yaml.tftpl").unwrap();
+        let text = "a: $${ x+1}\nb: ${var.ok}\n";
+        let title = |action: &CodeActionOrCommand| match action {
+            CodeActionOrCommand::CodeAction(a) => a.title.clone(),
+            CodeActionOrCommand::Command(c) => c.title.clone(),
+        };
+
+        let on_a = Range::new(Position::new(0, 5), Position::new(0, 5));
+        let titles: Vec<String> = expression_code_actions(&uri, text, on_a, None)
+            .iter()
+            .map(title)
+            .collect();
+        assert_eq!(
+            titles,
+            vec![
+                "Normalize expression whitespace",
+                "Normalize whitespace in all expressions"
+            ]
+        );
+
+        let on_b = Range::new(Position::new(1, 5), Position::new(1, 5));
+        let only = [CodeActionKind::SOURCE];
+        let actions = expression_code_actions(&uri, text, on_b, Some(&only));
+        assert_eq!(actions.len(), 1);
+        assert_eq!(
+            title(&actions[0]),
+            "Normalize whitespace in all expressions"
+        );
+    }
+}
@@ -0,0 +1,130 @@
+//! Template renames
+//!
+//! Keeps `templatefile()` calls in the workspace's `.tf` files pointing at
+//! templates that are renamed or moved, including every template inside a
+//! renamed directory.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use tower_lsp::lsp_types::{Range, TextEdit, Url, WorkspaceEdit};
+
+use crate::document::LineOffsets;
+use crate::terraform::template_references;
+
+/// Where `path` ends up after the renames, if any of them moves it
+fn renamed(path: &Path, renames: &[(PathBuf, PathBuf)]) -> Option<PathBuf> {
+    renames.iter().find_map(|(old, new)| {
+        let rest = path.strip_prefix(old).ok()?;
+        Some(if rest.as_os_str().is_empty() {
+            new.clone()
+        } else {
+            new.join(rest)
+        })
+    })
+}
+
+/// Edits to the given `.tf` files (path and text) that keep their
+/// `templatefile()` calls valid once the renames (old path, new path) happen
+pub fn rename_edits(
+    renames: &[(PathBuf, PathBuf)],
+    tf_files: &[(PathBuf, String)],
+) -> Option<WorkspaceEdit> {
+    let mut changes: HashMap<Url, Vec<TextEdit>> = HashMap::new();
+
+    for (tf_path, text) in tf_files {
+        // The `.tf` file may move too, when its directory is renamed
+        let tf_dir = renamed(tf_path, renames)
+            .unwrap_or_else(|| tf_path.clone())
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+
+        let lines = LineOffsets::new(text);
+        let edits: Vec<TextEdit> = template_references(text, tf_path)
+            .into_iter()
+            .filter_map(|reference| {
+                let target = renamed(&reference.target, renames)?;
+                let literal = reference.literal_for(&tf_dir, &target);
+                if literal == text[reference.span.clone()] {
+                    return None;
+                }
+                Some(TextEdit {
+                    range: Range::new(
+                        lines.offset_to_position(text, reference.span.start),
+                        lines.offset_to_position(text, reference.span.end),
+                    ),
+                    new_text: literal,
+                })
+            })
+            .collect();
+
+        if !edits.is_empty() {
+            if let Ok(uri) = Url::from_file_path(tf_path) {
+                changes.insert(uri, edits);
+            }
+        }
+    }
+
+    (!changes.is_empty()).then(|| WorkspaceEdit {
+        changes: Some(changes),
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tf(text: &str) -> Vec<(PathBuf, String)> {
+        vec![(PathBuf::from("/repo/infra/main.tf"), text.to_string())]
+    }
+
+    fn edits(edit: WorkspaceEdit) -> Vec<TextEdit> {
+        edit.changes.unwrap().into_values().next().unwrap()
+    }
+
+    #[test]
+    fn test_renamed_template() {
+        let text = "a = templatefile(\"${path.module}/wf/a.yaml.tftpl\", {})\n";
+        let renames = [(
+            PathBuf::from("/repo/infra/wf/a.yaml.tftpl"),
+            PathBuf::from("/repo/infra/wf/b.yaml.tftpl"),
+        )];
+        let edits = edits(rename_edits(&renames, &tf(text)).unwrap());
+        assert_eq!(edits.len(), 1);
+        assert_eq!(edits[0].new_text, "${path.module}/wf/b.yaml.tftpl");
+        assert_eq!(
+            edits[0].range.start,
+            tower_lsp::lsp_types::Position::new(0, 18)
+        );
+    }
+
+    #[test]
+    fn test_renamed_directory() {
+        let text = "a = templatefile(\"wf/a.yaml.tftpl\", {})\n";
+        let renames = [(
+            PathBuf::from("/repo/infra/wf"),
+            PathBuf::from("/repo/workflows"),
+        )];
+        let edits = edits(rename_edits(&renames, &tf(text)).unwrap());
+        assert_eq!(edits[0].new_text, "../workflows/a.yaml.tftpl");
+    }
+
+    #[test]
+    fn test_moving_module_with_templates_needs_no_edit() {
+        let text = "a = templatefile(\"${path.module}/a.yaml.tftpl\", {})\n";
+        let renames = [(PathBuf::from("/repo/infra"), PathBuf::from("/repo/prod"))];
+        assert!(rename_edits(&renames, &tf(text)).is_none());
+    }
+
+    #[test]
+    fn test_unrelated_rename() {
+        let text = "a = templatefile(\"${path.module}/a.yaml.tftpl\", {})\n";
+        let renames = [(
+            PathBuf::from("/repo/infra/other.yaml.tftpl"),
+            PathBuf::from("/repo/infra/x.yaml.tftpl"),
+        )];
+        assert!(rename_edits(&renames, &tf(text)).is_none());
+    }
+}
@@ -0,0 +1,439 @@
+//! Document formatting
+//!
+//! Formatting is deliberately conservative: it only normalizes whitespace that
+//! carries no meaning in YAML (trailing spaces, padding after `-` and `key:`,
+//! spaces before `:`). Block scalar bodies are never touched, and expression
+//! contents only get their spacing normalized when asked to. On-type
+//! formatting places the cursor line at the indentation implied by its
+//! structural parent.
+
+use tower_lsp::lsp_types::{Position, Range, TextEdit};
+
+use super::expression_format::expression_whitespace_edits;
+use crate::parser::lines::{
+    block_scalar_lines, indentation, parent_key, step_spans, strip_comment,
+};
+use crate::parser::preprocess_expressions;
+
+/// Format only the steps that intersect `range`
+///
+/// Every line outside the selected steps is left byte-identical. If the range
+/// does not touch any step, no edits are produced. With `expressions`, the
+/// spacing inside single-line expressions is normalized too.
+pub fn format_range(text: &str, range: Range, expressions: bool) -> Vec<TextEdit> {
+    let lines: Vec<&str> = text.split('\n').collect();
+    let spans = step_spans(&lines);
+
+    let intersecting: Vec<(usize, usize)> = spans
+        .into_iter()
+        .filter(|&(start, end)| start as u32 <= range.end.line && end as u32 >= range.start.line)
+        .collect();
+    // Keep only the outermost steps so nested ones aren't formatted twice
+    let selected = intersecting.iter().copied().filter(|&(s, e)| {
+        !intersecting
+            .iter()
+            .any(|&(os, oe)| (os, oe) != (s, e) && os <= s && e <= oe)
+    });
+
+    let skip = protected_lines(text, &lines);
+    let expression_edits = if expressions {
+        expression_whitespace_edits(text, range)
+    } else {
+        Vec::new()
+    };
+    let mut edits = Vec::new();
+
+    for (start, end) in selected {
+        for (line_no, line) in lines.iter().enumerate().take(end + 1).skip(start) {
+            if skip[line_no] {
+                continue;
+            }
+            let content = line.strip_suffix('\r').unwrap_or(line);
+            let on_line = expression_edits
+                .iter()
+                .filter(|e| e.range.start.line == line_no as u32);
+            let collapse_markers = !has_continuation(&lines, line_no);
+            let formatted = format_line(&apply_line_edits(content, on_line), collapse_markers);
+            if formatted != content {
+                edits.push(TextEdit {
+                    range: Range::new(
+                        Position::new(line_no as u32, 0),
+                        Position::new(line_no as u32, content.chars().count() as u32),
+                    ),
+                    new_text: formatted,
+                });
+            }
+        }
+    }
+
+    edits
+}
+
+/// Apply edits confined to one line, given in document order
+fn apply_line_edits<'a>(
+    line: &str,
+    edits: impl DoubleEndedIterator<Item = &'a TextEdit>,
+) -> String {
+    let mut out = line.to_string();
+    let byte = |s: &str, character: u32| {
+        s.char_indices()
+            .nth(character as usize)
+            .map_or(s.len(), |(i, _)| i)
+    };
+    // Back to front, so earlier columns stay valid
+    for edit in edits.rev() {
+        let start = byte(&out, edit.range.start.character);
+        let end = byte(&out, edit.range.end.character);
+        out.replace_range(start..end, &edit.new_text);
+    }
+    out
+}
+
+/// Lines that must not be reformatted: block scalar bodies and lines that
+/// continue a multi-line expression
+fn protected_lines(text: &str, lines: &[&str]) -> Vec<bool> {
+    let mut skip = block_scalar_lines(lines);
+    let (_, expression_map) = preprocess_expressions(text);
+    for expr in &expression_map.expressions {
+        for line in expr.start_line + 1..=expr.end_line {
+            if let Some(flag) = skip.get_mut(line as usize) {
+                *flag = true;
+            }
+        }
+    }
+    skip
+}
+
+/// Compute edits after the user types `ch` at `position`
+///
+/// - On `Enter`, indents the new line to match its structural parent, adding a
+///   `- ` marker for new entries under `steps:` and `assign:`.
+/// - On `:`, completing a bare `steps:` or `assign:` key at the end of a line
+///   opens the first entry on the next line.
+pub fn format_on_type(text: &str, position: Position, ch: &str) -> Vec<TextEdit> {
+    let lines: Vec<&str> = text.split('\n').map(|l| l.trim_end_matches('\r')).collect();
+    let line_no = position.line as usize;
+    let Some(line) = lines.get(line_no) else {
+        return Vec::new();
+    };
+    let unit = detect_indent_width(&lines);
+
+    match ch {
+        "\n" => {
+            let Some((prev_no, prev)) = lines[..line_no]
+                .iter()
+                .enumerate()
+                .rev()
+                .find(|(_, l)| !l.trim().is_empty())
+            else {
+                return Vec::new();
+            };
+            let Some((indent, dash)) = new_line_indent(&lines, prev_no, prev, unit) else {
+                return Vec::new();
+            };
+
+            let current_indent = indentation(line);
+            let has_dash = line.trim_start().starts_with('-');
+            let mut new_text = " ".repeat(indent);
+            if dash && !has_dash {
+                new_text.push_str("- ");
+            }
+            if new_text == line[..current_indent] {
+                return Vec::new();
+            }
+            vec![TextEdit {
+                range: Range::new(
+                    Position::new(position.line, 0),
+                    Position::new(position.line, current_indent as u32),
+                ),
+                new_text,
+            }]
+        }
+        ":" => {
+            let trimmed = strip_comment(line.trim()).trim_end();
+            let at_end = position.character as usize >= line.chars().count();
+            let opens_list = matches!(trimmed, "steps:" | "assign:");
+            let has_children = lines.get(line_no + 1).is_some_and(|next| {
+                !next.trim().is_empty() && indentation(next) > indentation(line)
+            });
+            if !at_end || !opens_list || has_children {
+                return Vec::new();
+            }
+            vec![TextEdit {
+                range: Range::new(position, position),
+                new_text: format!("\n{}- ", " ".repeat(indentation(line) + unit)),
+            }]
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Indentation (and whether to start a `- ` entry) for a line following `prev`
+fn new_line_indent(
+    lines: &[&str],
+    prev_no: usize,
+    prev: &str,
+    unit: usize,
+) -> Option<(usize, bool)> {
+    let indent = indentation(prev);
+    let trimmed = strip_comment(prev.trim()).trim_end();
+
+    // Column where the content of `prev` starts, past any `- ` markers
+    let mut content = trimmed;
+    let mut content_col = indent;
+    while let Some(rest) = content.strip_prefix("- ") {
+        content = rest.trim_start();
+        content_col = indent + (trimmed.len() - content.len());
+    }
+
+    if matches!(content, "steps:" | "assign:") {
+        return Some((content_col + unit, true));
+    }
+    if content.ends_with(':') {
+        return Some((content_col + unit, false));
+    }
+    if trimmed.starts_with("- ") && parent_key(lines, prev_no) == Some("assign") {
+        return Some((indent, true));
+    }
+    None
+}
+
+/// Detect the indentation width used by the document, defaulting to 2
+pub(crate) fn detect_indent_width(lines: &[&str]) -> usize {
+    let mut previous = 0;
+    let mut width: Option<usize> = None;
+    for line in lines {
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            continue;
+        }
+        let indent = indentation(line);
+        if indent > previous {
+            let step = indent - previous;
+            width = Some(width.map_or(step, |w| w.min(step)));
+        }
+        previous = indent;
+    }
+    width.unwrap_or(2)
+}
+
+/// Whether the node starting on `line_no` continues on the lines after it,
+/// which are indented deeper
+fn has_continuation(lines: &[&str], line_no: usize) -> bool {
+    let indent = indentation(lines[line_no]);
+    lines[line_no + 1..]
+        .iter()
+        .map(|l| l.trim_end_matches('\r'))
+        .find(|l| !l.trim().is_empty() && !l.trim_start().starts_with('#'))
+        .is_some_and(|next| indentation(next) > indent)
+}
+
+/// Normalize insignificant whitespace on a single line
+///
+/// Padding after `-` markers is only collapsed with `collapse_markers`: the
+/// keys continuing a sequence item on later lines are aligned with its first
+/// key, so moving that key alone would re-parent them.
+fn format_line(line: &str, collapse_markers: bool) -> String {
+    let line = line.trim_end();
+    let indent = &line[..indentation(line)];
+    let mut rest = line.trim_start();
+    let mut out = String::from(indent);
+
+    // Collapse padding after sequence markers: `-   item` -> `- item`
+    while let Some(after) = rest.strip_prefix('-').filter(|_| collapse_markers) {
+        let trimmed = after.trim_start();
+        if after.len() == trimmed.len() || trimmed.is_empty() {
+            break;
+        }
+        out.push_str("- ");
+        rest = trimmed;
+    }
+
+    if let Some((key, value)) = split_plain_key(rest) {
+        out.push_str(key.trim_end());
+        out.push(':');
+        let value = value.trim_start();
+        if !value.is_empty() {
+            out.push(' ');
+            out.push_str(value);
+        }
+    } else {
+        out.push_str(rest);
+    }
+
+    out
+}
+
+/// Split `key: value` when the key is a plain (unquoted) scalar
+///
+/// Returns `None` when the line is not a mapping entry or the separator is
+/// ambiguous (quotes, flow collections, expressions in the key).
+fn split_plain_key(rest: &str) -> Option<(&str, &str)> {
+    let first = rest.chars().next()?;
+    if matches!(
+        first,
+        '"' | '\'' | '{' | '[' | '#' | '&' | '*' | '!' | '|' | '>'
+    ) {
+        return None;
+    }
+
+    let bytes = rest.as_bytes();
+    for (i, &b) in bytes.iter().enumerate() {
+        match b {
+            b'$' | b'"' | b'\'' | b'#' => return None,
+            b':' if i + 1 == bytes.len() || bytes[i + 1] == b' ' => {
+                return Some((&rest[..i], &rest[i + 1..]));
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Apply single-line text edits to a document
+#[cfg(test)]
+fn apply_edits(text: &str, edits: &[TextEdit]) -> String {
+    let mut lines: Vec<String> = text.split('\n').map(String::from).collect();
+    for edit in edits {
+        let line = &mut lines[edit.range.start.line as usize];
+        let end = line
+            .char_indices()
+            .nth(edit.range.end.character as usize)
+            .map_or(line.len(), |(i, _)| i);
+        line.replace_range(..end, &edit.new_text);
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOC: &str = "main:\n  steps:\n    - first:\n        assign:\n          -   x :   1   \n    - second:\n        call:   http.get  \n        args:\n            url:  https://example.com\n    - third:\n        return:   x\n";
+
+    fn range(start: u32, end: u32) -> Range {
+        Range::new(Position::new(start, 0), Position::new(end, 0))
+    }
+
+    #[test]
+    fn test_format_line() {
+        assert_eq!(format_line("  key:   value  ", true), "  key: value");
+        assert_eq!(format_line("  key :value", true), "  key :value");
+        assert_eq!(format_line("  key : value", true), "  key: value");
+        assert_eq!(format_line("  -   item", true), "  - item");
+        assert_eq!(format_line("  -   x:   1", true), "  - x: 1");
+        assert_eq!(format_line("url: https://a.b", true), "url: https://a.b");
+        assert_eq!(format_line("msg: \"a:   b\"", true), "msg: \"a:   b\"");
+        assert_eq!(format_line("key:", true), "key:");
+        assert_eq!(format_line("  -   x:   1", false), "  -   x: 1");
+    }
+
+    #[test]
+    fn test_format_range_keeps_multi_key_item_padding() {
+        let text = "main:\n  steps:\n    - init:\n        assign:\n          -   a:   1\n              b: 2\n          -   c:   3\n";
+        let formatted = apply_edits(text, &format_range(text, range(2, 6), false));
+        assert!(formatted.contains("          -   a: 1\n              b: 2\n"));
+        assert!(formatted.contains("          - c: 3\n"));
+    }
+
+    #[test]
+    fn test_expression_keys_untouched() {
+        assert_eq!(format_line("${var.x}:   y", true), "${var.x}:   y");
+        assert_eq!(format_line("a:   $${x  +  1}", true), "a: $${x  +  1}");
+    }
+
+    #[test]
+    fn test_format_range_only_touches_selected_step() {
+        let edits = format_range(DOC, range(6, 6), true);
+        let formatted = apply_edits(DOC, &edits);
+
+        assert!(formatted.contains("        call: http.get\n"));
+        assert!(formatted.contains("            url: https://example.com\n"));
+        // First and third steps untouched
+        assert!(formatted.contains("          -   x :   1   \n"));
+        assert!(formatted.contains("        return:   x\n"));
+    }
+
+    #[test]
+    fn test_format_range_spanning_steps() {
+        let edits = format_range(DOC, range(3, 10), true);
+        let formatted = apply_edits(DOC, &edits);
+        assert!(formatted.contains("          - x: 1\n"));
+        assert!(formatted.contains("        return: x\n"));
+    }
+
+    #[test]
+    fn test_format_range_outside_steps() {
+        assert!(format_range(DOC, range(0, 0), true).is_empty());
+    }
+
+    fn on_type(text: &str, line: u32, character: u32, ch: &str) -> Vec<TextEdit> {
+        format_on_type(text, Position::new(line, character), ch)
+    }
+
+    #[test]
+    fn test_detect_indent_width() {
+        assert_eq!(detect_indent_width(&["a:", "    b:", "        c: 1"]), 4);
+        assert_eq!(detect_indent_width(&["a:", "  b: 1"]), 2);
+        assert_eq!(detect_indent_width(&["a: 1"]), 2);
+    }
+
+    #[test]
+    fn test_enter_after_steps_starts_step() {
+        let text = "main:\n    steps:\n";
+        let edits = on_type(text, 2, 0, "\n");
+        assert_eq!(edits.len(), 1);
+        assert_eq!(edits[0].new_text, "        - ");
+    }
+
+    #[test]
+    fn test_enter_after_step_name_indents_body() {
+        let text = "main:\n  steps:\n    - init:\n    ";
+        let edits = on_type(text, 3, 4, "\n");
+        assert_eq!(edits[0].new_text, "        ");
+        assert_eq!(edits[0].range.end.character, 4);
+    }
+
+    #[test]
+    fn test_enter_after_assign_item_continues_list() {
+        let text = "- init:\n    assign:\n      - x: 1\n";
+        let edits = on_type(text, 3, 0, "\n");
+        assert_eq!(edits[0].new_text, "      - ");
+    }
+
+    #[test]
+    fn test_enter_after_plain_value_is_noop() {
+        let text = "a: 1\n";
+        assert!(on_type(text, 1, 0, "\n").is_empty());
+    }
+
+    #[test]
+    fn test_colon_opens_assign_list() {
+        let text = "steps:\n  - init:\n      assign:";
+        let edits = on_type(text, 2, 13, ":");
+        assert_eq!(edits[0].new_text, "\n        - ");
+    }
+
+    #[test]
+    fn test_colon_with_existing_children_is_noop() {
+        let text = "steps:\n  - a:";
+        assert!(on_type(text, 0, 6, ":").is_empty());
+    }
+
+    #[test]
+    fn test_block_scalars_untouched() {
+        let text = "main:\n  steps:\n    - log:\n        args:\n          text: |\n            a:   b   \n";
+        let edits = format_range(text, range(2, 5), true);
+        assert!(edits.is_empty());
+    }
+
+    #[test]
+    fn test_expression_spacing() {
+        let text = "main:\n  steps:\n    - a:\n        return:   $${ x+1} \n";
+        assert!(format_range(text, range(2, 3), true)
+            .iter()
+            .any(|e| e.new_text == "        return: $${x + 1}"));
+        assert!(format_range(text, range(2, 3), false)
+            .iter()
+            .any(|e| e.new_text == "        return: $${ x+1}"));
+    }
+}
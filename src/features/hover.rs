@@ -0,0 +1,578 @@
+//! Hover information
+//!
+//! Covers YAML anchors and aliases — hovering an `*alias` shows the anchored
+//! content, hovering an `&anchor` shows how many aliases use it — Terraform
+//! variables, which show their `variable` declaration, expressions, which show
+//! when they're evaluated and what Terraform renders them to, standard
+//! library and Terraform built-in functions, fields of caught errors, and
+//! Workflows keywords.
+
+use tower_lsp::lsp_types::{Hover, HoverContents, MarkupContent, MarkupKind, Position, Range};
+
+use super::inlay_hints::variable_references;
+use crate::document::LineOffsets;
+use crate::parser::expressions::{Expression, ExpressionKind, ExpressionMap};
+use crate::parser::{preprocess_expressions, scan_anchors};
+use crate::schema::{CallArg, Connector, StdlibFunction};
+use crate::terraform::VariableDecl;
+
+/// Where Terraform documents each built-in function, by name
+const TERRAFORM_FUNCTION_DOCS: &str =
+    "https://developer.hashicorp.com/terraform/language/functions/";
+
+/// Compute hover information for the given position in the document
+///
+/// `variables` are the declarations of the template's Terraform module.
+pub fn hover(text: &str, position: Position, variables: &[VariableDecl]) -> Option<Hover> {
+    let (_, expression_map) = preprocess_expressions(text);
+    let lines = LineOffsets::new(text);
+    if let Some(hover) = variable_hover(text, &lines, position, &expression_map, variables) {
+        return Some(hover);
+    }
+
+    let anchors = scan_anchors(text, &expression_map);
+
+    if let Some(alias) = anchors.alias_at(position.line, position.character) {
+        let range = token_range(alias.line, alias.column, alias.end_column);
+        let value = match anchors.resolve(alias) {
+            Some(anchor) => format!(
+                "**Alias** `*{}` → anchor on line {}\n\n```yaml\n{}\n```",
+                alias.name,
+                anchor.line + 1,
+                anchor.content
+            ),
+            None => format!("**Alias** `*{}`\n\nNo matching anchor found", alias.name),
+        };
+        return Some(markdown_hover(value, range));
+    }
+
+    if let Some(anchor) = anchors.anchor_at(position.line, position.character) {
+        let range = token_range(anchor.line, anchor.column, anchor.end_column);
+        let count = anchors.usages(anchor).count();
+        let value = format!(
+            "**Anchor** `&{}`\n\nUsed by {} alias{}",
+            anchor.name,
+            count,
+            if count == 1 { "" } else { "es" }
+        );
+        return Some(markdown_hover(value, range));
+    }
+
+    if let Some(hover) = terraform_function_hover(text, &lines, position, &expression_map) {
+        return Some(hover);
+    }
+
+    if let Some(hover) = function_hover(text, &lines, position, &expression_map) {
+        return Some(hover);
+    }
+
+    if let Some(hover) = error_field_hover(text, &lines, position, &expression_map) {
+        return Some(hover);
+    }
+
+    if let Some(hover) = expression_hover(text, &lines, position, &expression_map) {
+        return Some(hover);
+    }
+
+    keyword_hover(text, position)
+}
+
+/// Hover for a standard library function or connector named inside a `$${...}`
+/// expression or by a step's `call:`
+fn function_hover(
+    text: &str,
+    lines: &LineOffsets,
+    position: Position,
+    expression_map: &ExpressionMap,
+) -> Option<Hover> {
+    let line = text.split('\n').nth(position.line as usize)?;
+    let (start, end) = dotted_name_at(line, position.character);
+    let name = &line[start..end];
+    let in_expression = in_workflows_expression(text, lines, position, expression_map);
+    let entry = line.trim_start();
+    let entry = entry.strip_prefix("- ").unwrap_or(entry);
+    let is_call = entry
+        .strip_prefix("call:")
+        .is_some_and(|value| value.trim() == name);
+    if !in_expression && !is_call {
+        return None;
+    }
+    let value = match crate::schema::stdlib_function(name) {
+        Some(function) => function_markdown(function),
+        None if is_call => connector_markdown(crate::schema::connector(name)?),
+        None => return None,
+    };
+
+    let start_column = line[..start].chars().count() as u32;
+    let end_column = start_column + name.chars().count() as u32;
+    Some(markdown_hover(
+        value,
+        token_range(position.line, start_column, end_column),
+    ))
+}
+
+/// Hover for a Terraform built-in function called inside a `${...}`
+/// expression or `%{...}` directive
+fn terraform_function_hover(
+    text: &str,
+    lines: &LineOffsets,
+    position: Position,
+    expression_map: &ExpressionMap,
+) -> Option<Hover> {
+    let offset = lines.position_to_offset(text, position)?;
+    if !expression_map
+        .expressions
+        .iter()
+        .any(|e| e.kind != ExpressionKind::Workflows && offset >= e.start && offset < e.end)
+    {
+        return None;
+    }
+    let line = text.split('\n').nth(position.line as usize)?;
+    let (start, end) = dotted_name_at(line, position.character);
+    if !line[end..].trim_start().starts_with('(') {
+        return None;
+    }
+    let function = crate::terraform::terraform_function(&line[start..end])?;
+
+    let value = format!(
+        "**Terraform function** `{}`\n\n{}\n\n[Documentation]({}{})",
+        function.signature(),
+        function.description,
+        TERRAFORM_FUNCTION_DOCS,
+        function.name
+    );
+    let start_column = line[..start].chars().count() as u32;
+    let end_column = start_column + function.name.len() as u32;
+    Some(markdown_hover(
+        value,
+        token_range(position.line, start_column, end_column),
+    ))
+}
+
+/// Hover for a field of a caught error, such as `e.code`, inside a `$${...}`
+/// expression
+fn error_field_hover(
+    text: &str,
+    lines: &LineOffsets,
+    position: Position,
+    expression_map: &ExpressionMap,
+) -> Option<Hover> {
+    if !in_workflows_expression(text, lines, position, expression_map) {
+        return None;
+    }
+    let line = text.split('\n').nth(position.line as usize)?;
+    let (start, end) = dotted_name_at(line, position.character);
+    let mut segments = line[start..end].split('.');
+    let (root, name) = (segments.next()?, segments.next()?);
+    let field = crate::schema::error_field(name)?;
+    let binds_root = text.lines().any(|l| {
+        let entry = l.trim_start();
+        let entry = entry.strip_prefix("- ").unwrap_or(entry);
+        entry
+            .strip_prefix("as:")
+            .is_some_and(|value| value.trim() == root)
+    });
+    if !binds_root {
+        return None;
+    }
+
+    let mut value = format!("**Error field** `{}.{}`", root, field.name);
+    if let Some(value_type) = field.value_type {
+        value.push_str(&format!(": `{}`", value_type.name()));
+    }
+    value.push_str(&format!("\n\n{}", field.doc));
+    let start_column = line[..start].chars().count() as u32;
+    let end_column = start_column + (root.len() + 1 + name.len()) as u32;
+    Some(markdown_hover(
+        value,
+        token_range(position.line, start_column, end_column),
+    ))
+}
+
+/// Byte range of the dotted name (`http.get`, `e.code`) around a column
+fn dotted_name_at(line: &str, character: u32) -> (usize, usize) {
+    let at = line
+        .char_indices()
+        .nth(character as usize)
+        .map_or(line.len(), |(i, _)| i);
+    let is_name = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '.';
+    let start = line[..at].rfind(|c| !is_name(c)).map_or(0, |i| i + 1);
+    let end = line[at..]
+        .find(|c| !is_name(c))
+        .map_or(line.len(), |i| at + i);
+    (start, end)
+}
+
+fn in_workflows_expression(
+    text: &str,
+    lines: &LineOffsets,
+    position: Position,
+    expression_map: &ExpressionMap,
+) -> bool {
+    lines
+        .position_to_offset(text, position)
+        .is_some_and(|offset| {
+            expression_map
+                .expressions
+                .iter()
+                .any(|e| e.kind == ExpressionKind::Workflows && offset >= e.start && offset < e.end)
+        })
+}
+
+fn function_markdown(function: &StdlibFunction) -> String {
+    let mut value = format!("**Function** `{}`", function.signature());
+    if let Some(returns) = function.returns {
+        value.push_str(&format!(" → `{}`", returns.name()));
+    }
+    value.push_str(&format!("\n\n{}", function.description));
+    push_params(&mut value, function.params);
+    if function.blocking {
+        value.push_str("\n\n*Blocking: only callable from a step's `call:`*");
+    }
+    value
+}
+
+fn connector_markdown(connector: &Connector) -> String {
+    let mut value = format!(
+        "**Connector** `{}`\n\n{}",
+        connector.signature(),
+        connector.description
+    );
+    push_params(&mut value, connector.params);
+    value
+}
+
+fn push_params(value: &mut String, params: &[CallArg]) {
+    for param in params {
+        value.push_str(&format!("\n- `{}`", param.name));
+        if let Some(value_type) = param.value_type {
+            value.push_str(&format!(": {}", value_type.name()));
+        }
+        if !param.required {
+            value.push_str(" (optional)");
+        }
+    }
+}
+
+/// Hover for a whole `${...}` or `$${...}` expression or `%{...}` directive
+fn expression_hover(
+    text: &str,
+    lines: &LineOffsets,
+    position: Position,
+    expression_map: &ExpressionMap,
+) -> Option<Hover> {
+    let offset = lines.position_to_offset(text, position)?;
+    let expr = expression_map
+        .expressions
+        .iter()
+        .find(|e| offset >= e.start && offset < e.end)?;
+    let range = Range::new(
+        lines.offset_to_position(text, expr.start),
+        lines.offset_to_position(text, expr.end),
+    );
+    Some(markdown_hover(expression_markdown(expr), range))
+}
+
+fn expression_markdown(expr: &Expression) -> String {
+    match expr.kind {
+        ExpressionKind::Terraform => format!(
+            "**Terraform expression** (deploy time)\n\n```hcl\n{}\n```\n\nEvaluated by `templatefile()` when Terraform renders the template",
+            expr.original
+        ),
+        ExpressionKind::Directive => format!(
+            "**Terraform directive** (deploy time)\n\n```hcl\n{}\n```\n\nApplied by `templatefile()` when Terraform renders the template",
+            expr.original
+        ),
+        ExpressionKind::Workflows => {
+            let mut value = format!(
+                "**Workflows expression** (runtime)\n\n```\n{}\n```\n\nRendered by Terraform as:\n\n```\n{}\n```\n\nEvaluated by Workflows when the step runs",
+                expr.original,
+                rendered_workflows_expression(&expr.original)
+            );
+            let body = &expr.original[3..];
+            if body.contains("${") || body.contains("%{") {
+                value.push_str("; the Terraform `${...}` inside is filled in first");
+            }
+            value
+        }
+    }
+}
+
+/// What Terraform renders a `$${...}` expression to: its escapes unescaped,
+/// with any `${...}` inside left for Terraform to fill in
+fn rendered_workflows_expression(original: &str) -> String {
+    original.replace("$${", "${").replace("%%{", "%{")
+}
+
+/// Hover for a Workflows keyword used as a mapping key
+fn keyword_hover(text: &str, position: Position) -> Option<Hover> {
+    let line = text.split('\n').nth(position.line as usize)?;
+    let trimmed = line.trim_start();
+    let entry = trimmed.strip_prefix("- ").unwrap_or(trimmed);
+    let start = line.len() - entry.len();
+    let key = &entry[..entry.find(':')?];
+    let doc = crate::schema::keyword_doc(key)?;
+
+    let start_column = line[..start].chars().count() as u32;
+    let end_column = start_column + key.chars().count() as u32;
+    if position.character < start_column || position.character > end_column {
+        return None;
+    }
+    Some(markdown_hover(
+        format!("**`{}`**\n\n{}", key, doc),
+        token_range(position.line, start_column, end_column),
+    ))
+}
+
+/// Hover for a variable reference inside a Terraform `${...}` expression
+fn variable_hover(
+    text: &str,
+    lines: &LineOffsets,
+    position: Position,
+    expression_map: &ExpressionMap,
+    variables: &[VariableDecl],
+) -> Option<Hover> {
+    let offset = lines.position_to_offset(text, position)?;
+    let expr = expression_map
+        .expressions
+        .iter()
+        .find(|e| e.kind == ExpressionKind::Terraform && offset >= e.start && offset < e.end)?;
+
+    let body_start = expr.start + 2;
+    let body = &text[body_start..expr.end - 1];
+    // Only `var.` reaches the module's variables; a bare name is whatever
+    // the `templatefile()` call passes
+    let (end, name) = variable_references(body).into_iter().find(|&(end, name)| {
+        let start = end - name.len();
+        body[..start].ends_with("var.")
+            && offset >= body_start + start
+            && offset <= body_start + end
+    })?;
+    let variable = variables.iter().find(|v| v.name == name)?;
+
+    let range = Range::new(
+        lines.offset_to_position(text, body_start + end - name.len()),
+        lines.offset_to_position(text, body_start + end),
+    );
+    Some(markdown_hover(variable_markdown(variable), range))
+}
+
+fn variable_markdown(variable: &VariableDecl) -> String {
+    let mut value = format!("**Variable** `{}`", variable.name);
+    if let Some(type_expr) = &variable.type_expr {
+        value.push_str(&format!(": `{}`", type_expr));
+    }
+    if let Some(description) = &variable.description {
+        value.push_str(&format!("\n\n{}", description));
+    }
+    match &variable.default {
+        Some(default) => value.push_str(&format!("\n\nDefault:\n```hcl\n{}\n```", default)),
+        None => value.push_str("\n\nRequired (no default)"),
+    }
+    if let Some(file) = variable.file.file_name() {
+        value.push_str(&format!("\n\n*Declared in `{}`*", file.to_string_lossy()));
+    }
+    value
+}
+
+/// Range covering a single-line token
+fn token_range(line: u32, start: u32, end: u32) -> Range {
+    Range::new(Position::new(line, start), Position::new(line, end))
+}
+
+fn markdown_hover(value: String, range: Range) -> Hover {
+    Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value,
+        }),
+        range: Some(range),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hover_text(text: &str, line: u32, character: u32) -> Option<String> {
+        hover(text, Position::new(line, character), &[]).map(|h| match h.contents {
+            HoverContents::Markup(m) => m.value,
+            _ => panic!("expected markup"),
+        })
+    }
+
+    #[test]
+    fn test_hover_alias_shows_content() {
+        let text = "defaults: &defaults\n  timeout: 30\nother: *defaults";
+        let value = hover_text(text, 2, 9).unwrap();
+        assert!(value.contains("timeout: 30"));
+        assert!(value.contains("line 1"));
+    }
+
+    #[test]
+    fn test_hover_anchor_shows_usage_count() {
+        let text = "a: &x 1\nb: *x\nc: *x";
+        let value = hover_text(text, 0, 4).unwrap();
+        assert!(value.contains("Used by 2 aliases"));
+    }
+
+    #[test]
+    fn test_hover_unresolved_alias() {
+        let value = hover_text("b: *missing", 0, 5).unwrap();
+        assert!(value.contains("No matching anchor"));
+    }
+
+    #[test]
+    fn test_hover_terraform_variable() {
+        let variables = vec![VariableDecl {
+            name: "region".to_string(),
+            type_expr: Some("string".to_string()),
+            default: Some("\"europe-west1\"".to_string()),
+            description: Some("Region to deploy to".to_string()),
+            file: "variables.tf".into(),
+        }];
+        let text = "location: ${var.region}\nother: ${region}";
+
+        let hover = hover(text, Position::new(0, 18), &variables).unwrap();
+        let HoverContents::Markup(markup) = hover.contents else {
+            panic!("expected markup");
+        };
+        assert!(markup.value.contains("`region`: `string`"));
+        assert!(markup.value.contains("Region to deploy to"));
+        assert!(markup.value.contains("\"europe-west1\""));
+        assert!(markup.value.contains("variables.tf"));
+        assert_eq!(
+            hover.range,
+            Some(Range::new(Position::new(0, 16), Position::new(0, 22)))
+        );
+
+        // Bare names are templatefile() variables, not the module's
+        let bare = super::hover(text, Position::new(1, 10), &variables).unwrap();
+        let HoverContents::Markup(markup) = bare.contents else {
+            panic!("expected markup");
+        };
+        assert!(markup.value.starts_with("**Terraform expression**"));
+        // `var.` prefix itself is not the variable, just part of the expression
+        let value = hover_text(text, 0, 12).unwrap();
+        assert!(value.starts_with("**Terraform expression**"));
+    }
+
+    #[test]
+    fn test_hover_expression() {
+        let text = "a: ${var.b}\nc: $${\"p/${var.d}/\" + x}\nd: $${sys.now()}\n%{ if e }\n";
+
+        let value = hover_text(text, 0, 4).unwrap();
+        assert!(value.starts_with("**Terraform expression** (deploy time)"));
+        assert!(value.contains("```hcl\n${var.b}\n```"));
+
+        let value = hover_text(text, 2, 5).unwrap();
+        assert!(value.starts_with("**Workflows expression** (runtime)"));
+        assert!(value.contains("```\n$${sys.now()}\n```"));
+        assert!(value.contains("Rendered by Terraform as:\n\n```\n${sys.now()}\n```"));
+        assert!(!value.contains("filled in first"));
+
+        let value = hover_text(text, 1, 5).unwrap();
+        assert!(value.contains("```\n${\"p/${var.d}/\" + x}\n```"));
+        assert!(value.contains("filled in first"));
+
+        let value = hover_text(text, 3, 2).unwrap();
+        assert!(value.starts_with("**Terraform directive**"));
+
+        let hover = hover(text, Position::new(2, 5), &[]).unwrap();
+        assert_eq!(
+            hover.range,
+            Some(Range::new(Position::new(2, 3), Position::new(2, 16)))
+        );
+    }
+
+    #[test]
+    fn test_hover_stdlib_function() {
+        let text = "main:\n  steps:\n    - get:\n        call: http.get\n        args:\n          url: $${text.split(x, \"/\")[0]}\n";
+        let value = hover_text(text, 3, 16).unwrap();
+        assert!(value.starts_with("**Function** `http.get(url, headers"));
+        assert!(value.contains("- `url`: string\n- `headers`: mapping (optional)"));
+        assert!(value.contains("*Blocking"));
+
+        let value = hover_text(text, 5, 20).unwrap();
+        assert!(value.starts_with("**Function** `text.split(source, separator)` → `list`"));
+        let hover = hover(text, Position::new(5, 20), &[]).unwrap();
+        assert_eq!(
+            hover.range,
+            Some(Range::new(Position::new(5, 18), Position::new(5, 28)))
+        );
+
+        // The rest of the expression still shows the expression
+        let value = hover_text(text, 5, 30).unwrap();
+        assert!(value.starts_with("**Workflows expression**"));
+    }
+
+    #[test]
+    fn test_hover_terraform_function() {
+        let text = "a: ${join(\",\", var.names)}\nb: $${join}\n%{ if length(var.names) > 0 ~}\nc: ${format}\n";
+        let value = hover_text(text, 0, 6).unwrap();
+        assert!(value.starts_with("**Terraform function** `join(separator, list)`\n\n"));
+        assert!(value.ends_with(
+            "[Documentation](https://developer.hashicorp.com/terraform/language/functions/join)"
+        ));
+        let hover = hover(text, Position::new(0, 6), &[]).unwrap();
+        assert_eq!(
+            hover.range,
+            Some(Range::new(Position::new(0, 5), Position::new(0, 9)))
+        );
+
+        let value = hover_text(text, 2, 8).unwrap();
+        assert!(value.starts_with("**Terraform function** `length("));
+
+        // Not a call, or inside a Workflows expression
+        assert!(!hover_text(text, 3, 6)
+            .unwrap()
+            .contains("Terraform function"));
+        assert!(!hover_text(text, 1, 7)
+            .unwrap()
+            .contains("Terraform function"));
+    }
+
+    #[test]
+    fn test_hover_connector() {
+        let text = "- get:\n    call: googleapis.storage.v1.objects.get\n";
+        let value = hover_text(text, 1, 20).unwrap();
+        assert!(
+            value.starts_with("**Connector** `googleapis.storage.v1.objects.get(bucket, object")
+        );
+        assert!(value.contains("- `connector_params`: mapping (optional)"));
+    }
+
+    #[test]
+    fn test_hover_error_field() {
+        let text = "- a:\n    except:\n      as: e\n      steps:\n        - log:\n            return: $${e.code + e.status + x.code}\n";
+        let value = hover_text(text, 5, 24).unwrap();
+        assert!(value.starts_with("**Error field** `e.code`: `int`\n\nHTTP status code"));
+        let hover = hover(text, Position::new(5, 23), &[]).unwrap();
+        assert_eq!(
+            hover.range,
+            Some(Range::new(Position::new(5, 23), Position::new(5, 29)))
+        );
+
+        // Unknown fields and variables no `except` binds are just expressions
+        let value = hover_text(text, 5, 34).unwrap();
+        assert!(value.starts_with("**Workflows expression**"));
+        let value = hover_text(text, 5, 43).unwrap();
+        assert!(value.starts_with("**Workflows expression**"));
+    }
+
+    #[test]
+    fn test_hover_keyword() {
+        let text = "main:\n  steps:\n    - get:\n        call_log_level: LOG_NONE";
+        let value = hover_text(text, 3, 12).unwrap();
+        assert!(value.starts_with("**`call_log_level`**"));
+        assert!(value.contains("LOG_ERRORS_ONLY"));
+        // Values and step names are not keywords
+        assert!(hover_text(text, 3, 28).is_none());
+        assert!(hover_text(text, 2, 7).is_none());
+    }
+
+    #[test]
+    fn test_hover_elsewhere_is_none() {
+        assert!(hover_text("a: &x 1\nb: *x", 0, 0).is_none());
+    }
+}
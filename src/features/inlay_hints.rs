@@ -0,0 +1,156 @@
+//! Inlay hints
+//!
+//! Shows the value a Terraform variable will render to, read from the module's
+//! tfvars files, right after each `var.name` reference inside `${...}`.
+
+use tower_lsp::lsp_types::{InlayHint, InlayHintKind, InlayHintLabel, Range};
+
+use crate::document::LineOffsets;
+use crate::parser::expressions::ExpressionKind;
+use crate::parser::preprocess_expressions;
+use crate::terraform::TfVars;
+
+/// Compute variable value hints for Terraform expressions within `range`
+pub fn inlay_hints(text: &str, range: Range, tfvars: &TfVars) -> Vec<InlayHint> {
+    if tfvars.is_empty() {
+        return Vec::new();
+    }
+
+    let (_, expression_map) = preprocess_expressions(text);
+    let lines = LineOffsets::new(text);
+    let mut hints = Vec::new();
+
+    for expr in &expression_map.expressions {
+        if expr.kind != ExpressionKind::Terraform
+            || expr.end_line < range.start.line
+            || expr.start_line > range.end.line
+        {
+            continue;
+        }
+
+        // Body between `${` and `}`
+        let body_start = expr.start + 2;
+        let body = &text[body_start..expr.end - 1];
+
+        for (offset, name) in variable_references(body) {
+            let Some(value) = tfvars.display(name) else {
+                continue;
+            };
+            hints.push(InlayHint {
+                position: lines.offset_to_position(text, body_start + offset),
+                label: InlayHintLabel::String(format!("= {}", value)),
+                kind: Some(InlayHintKind::PARAMETER),
+                text_edits: None,
+                tooltip: None,
+                padding_left: Some(true),
+                padding_right: None,
+                data: None,
+            });
+        }
+    }
+
+    hints
+}
+
+/// Find variable references in a Terraform expression body
+///
+/// Returns the byte offset just past each reference and the variable name.
+/// Matches `var.name` anywhere outside string literals, and a body consisting
+/// of a bare identifier (the `templatefile()` variable form).
+pub(crate) fn variable_references(body: &str) -> Vec<(usize, &str)> {
+    let trimmed = body.trim();
+    if !trimmed.is_empty() && trimmed.chars().all(is_ident_char) {
+        let end = body.find(trimmed).unwrap_or(0) + trimmed.len();
+        return vec![(end, trimmed)];
+    }
+
+    let bytes = body.as_bytes();
+    let mut refs = Vec::new();
+    let mut in_string = false;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' => in_string = !in_string,
+            b'\\' if in_string => i += 1,
+            b'v' if !in_string
+                && body[i..].starts_with("var.")
+                && (i == 0 || !is_ident_char(bytes[i - 1] as char)) =>
+            {
+                let name_start = i + 4;
+                let name_len = body[name_start..]
+                    .find(|c: char| !is_ident_char(c))
+                    .unwrap_or(body.len() - name_start);
+                if name_len > 0 {
+                    let end = name_start + name_len;
+                    refs.push((end, &body[name_start..end]));
+                    i = end;
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+
+    refs
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '-'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower_lsp::lsp_types::Position;
+
+    fn all() -> Range {
+        Range::new(Position::new(0, 0), Position::new(u32::MAX, 0))
+    }
+
+    fn label(hint: &InlayHint) -> &str {
+        match &hint.label {
+            InlayHintLabel::String(s) => s,
+            _ => panic!("expected string label"),
+        }
+    }
+
+    #[test]
+    fn test_variable_references() {
+        assert_eq!(variable_references("var.project"), vec![(11, "project")]);
+        assert_eq!(variable_references(" name "), vec![(5, "name")]);
+        assert_eq!(
+            variable_references("format(\"%s-var.x\", var.a, var.b)"),
+            vec![(24, "a"), (31, "b")]
+        );
+        assert!(variable_references("local.x").is_empty());
+    }
+
+    #[test]
+    fn test_hint_after_reference() {
+        let vars = TfVars::parse("project_id = \"my-project\"").unwrap();
+        let text = "name: ${var.project_id}\nother: ${var.unknown}";
+        let hints = inlay_hints(text, all(), &vars);
+
+        assert_eq!(hints.len(), 1);
+        assert_eq!(label(&hints[0]), "= \"my-project\"");
+        assert_eq!(hints[0].position, Position::new(0, 22));
+    }
+
+    #[test]
+    fn test_workflows_expressions_ignored() {
+        let vars = TfVars::parse("x = 1").unwrap();
+        assert!(inlay_hints("a: $${x}", all(), &vars).is_empty());
+    }
+
+    #[test]
+    fn test_range_filter() {
+        let vars = TfVars::parse("x = 1").unwrap();
+        let text = "a: ${x}\nb: ${x}";
+        let range = Range::new(Position::new(1, 0), Position::new(1, 10));
+        let hints = inlay_hints(text, range, &vars);
+        assert_eq!(hints.len(), 1);
+        assert_eq!(hints[0].position.line, 1);
+    }
+}
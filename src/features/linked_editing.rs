@@ -0,0 +1,102 @@
+//! Linked editing of step names and the `next:` fields that jump to them
+
+use tower_lsp::lsp_types::{LinkedEditingRanges, Position, Range};
+
+use crate::parser::Outline;
+
+/// Characters a step name may be edited into
+const WORD_PATTERN: &str = "[A-Za-z_][\\w-]*";
+
+/// Ranges to edit together when the cursor is on a step name or `next:` target
+///
+/// Jumps are only linked to steps of the same workflow, since each workflow
+/// has its own step namespace. Nothing is returned when no `next:` refers to
+/// the step, so plain renames of unreferenced steps are left alone.
+pub fn linked_editing_ranges(text: &str, position: Position) -> Option<LinkedEditingRanges> {
+    let outline = Outline::parse(text);
+    let contains = |line: u32, start: u32, end: u32| {
+        line == position.line && position.character >= start && position.character <= end
+    };
+
+    let name = outline
+        .steps
+        .iter()
+        .find(|s| contains(s.line, s.column, s.column + s.name.chars().count() as u32))
+        .map(|s| s.name.as_str())
+        .or_else(|| {
+            outline
+                .jumps
+                .iter()
+                .find(|j| contains(j.line, j.column, j.end_column))
+                .map(|j| j.target.as_str())
+        })?;
+    let workflow = outline.workflow_at(position.line).map(|w| w.name.as_str());
+    let in_scope = |line: u32| outline.workflow_at(line).map(|w| w.name.as_str()) == workflow;
+
+    let step = outline
+        .steps
+        .iter()
+        .find(|s| s.name == name && in_scope(s.line))?;
+    let mut ranges = vec![Range::new(
+        Position::new(step.line, step.column),
+        Position::new(step.line, step.column + name.chars().count() as u32),
+    )];
+    ranges.extend(
+        outline
+            .jumps
+            .iter()
+            .filter(|j| j.target == name && in_scope(j.line))
+            .map(|j| {
+                Range::new(
+                    Position::new(j.line, j.column),
+                    Position::new(j.line, j.end_column),
+                )
+            }),
+    );
+
+    (ranges.len() > 1).then(|| LinkedEditingRanges {
+        ranges,
+        word_pattern: Some(WORD_PATTERN.to_string()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOC: &str = "main:\n  steps:\n    - check:\n        switch:\n          - condition: $${x}\n            next: done\n        next: check\n    - done:\n        return: 1\nhelper:\n  steps:\n    - done:\n        return: 2\n";
+
+    fn ranges(line: u32, character: u32) -> Option<Vec<Range>> {
+        linked_editing_ranges(DOC, Position::new(line, character)).map(|r| r.ranges)
+    }
+
+    #[test]
+    fn test_from_step_name() {
+        let ranges = ranges(7, 7).unwrap();
+        assert_eq!(
+            ranges,
+            vec![
+                Range::new(Position::new(7, 6), Position::new(7, 10)),
+                Range::new(Position::new(5, 18), Position::new(5, 22)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_from_next_target() {
+        let ranges = ranges(6, 16).unwrap();
+        assert_eq!(ranges.len(), 2);
+        assert_eq!(ranges[0].start, Position::new(2, 6));
+    }
+
+    #[test]
+    fn test_other_workflow_not_linked() {
+        // `done` in helper has no jumps referring to it
+        assert!(ranges(11, 7).is_none());
+    }
+
+    #[test]
+    fn test_outside_names() {
+        assert!(ranges(8, 10).is_none());
+    }
+}
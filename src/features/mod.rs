@@ -0,0 +1,45 @@
+//! LSP feature providers
+//!
+//! Each submodule computes the result of one LSP request from document text,
+//! independent of the tower-lsp plumbing in the backend, so features can be
+//! unit tested directly.
+
+mod call_graph;
+mod call_hierarchy;
+mod code_lens;
+mod completion;
+mod definition;
+mod expression_at;
+mod expression_format;
+mod file_rename;
+mod formatting;
+mod hover;
+mod inlay_hints;
+mod linked_editing;
+mod preview;
+mod quick_fix;
+mod scaffold;
+mod semantic_tokens;
+mod snippets;
+mod workflow_diff;
+
+pub use call_graph::{call_graph, CallGraph, CallGraphEdge, CallGraphNode};
+pub use call_hierarchy::{incoming_calls, outgoing_calls, prepare_call_hierarchy};
+pub use code_lens::{code_lenses, resolve_code_lens};
+pub use completion::{completions, TRIGGER_CHARACTERS};
+pub use definition::{definition, terraform_symbol_at, TerraformSymbol};
+pub use expression_at::{expression_at, ExpressionAt};
+pub use expression_format::{expression_code_actions, expression_whitespace_edits};
+pub use file_rename::rename_edits;
+pub use formatting::{format_on_type, format_range};
+pub use hover::hover;
+pub use inlay_hints::inlay_hints;
+pub use linked_editing::linked_editing_ranges;
+pub use preview::{preview, Preview};
+pub use quick_fix::{declare_variable_fixes, quick_fixes};
+pub use scaffold::{scaffold_edit, WORKFLOW_SKELETON};
+pub use semantic_tokens::{semantic_tokens, semantic_tokens_legend};
+pub use snippets::extract_snippet;
+pub use workflow_diff::{
+    workflow_diff, DiffError, StepRename, ValueChange, WorkflowChanges, WorkflowDiff,
+};
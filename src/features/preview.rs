@@ -0,0 +1,80 @@
+//! Render previews
+//!
+//! Renders a template with a set of variable values and validates the result,
+//! catching problems that only appear for some variable combinations.
+
+use serde::Serialize;
+use tower_lsp::lsp_types::Diagnostic;
+
+use crate::analysis::analyze;
+use crate::settings::Settings;
+use crate::terraform::{render, Variables};
+
+/// A template rendered with one set of variables
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Preview {
+    /// The render profile used, `None` for the module's own tfvars
+    pub profile: Option<String>,
+    /// The rendered text, `None` when rendering failed
+    pub text: Option<String>,
+    /// Why the variables could not be loaded or the template not rendered
+    pub error: Option<String>,
+    /// Diagnostics for the rendered text, with positions in that text
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Render a template and validate the output
+pub fn preview(
+    text: &str,
+    profile: Option<String>,
+    vars: Result<Variables, String>,
+    settings: &Settings,
+) -> Preview {
+    match vars.and_then(|vars| render(text, &vars)) {
+        Ok(rendered) => Preview {
+            profile,
+            diagnostics: analyze(&rendered, &settings.clone().into()).diagnostics,
+            text: Some(rendered),
+            error: None,
+        },
+        Err(error) => Preview {
+            profile,
+            text: None,
+            error: Some(error),
+            diagnostics: Vec::new(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_preview_validates_rendered_text() {
+        let template = "main:\n  steps:\n    - done:\n        ${step_body}\n";
+        let vars = |body: &str| -> Result<Variables, String> {
+            Ok([("step_body".to_string(), hcl::Value::from(body))].into())
+        };
+
+        let good = preview(template, None, vars("return: 1"), &Settings::default());
+        assert_eq!(
+            good.text.as_deref(),
+            Some("main:\n  steps:\n    - done:\n        return: 1\n")
+        );
+        assert!(good.diagnostics.is_empty());
+
+        let bad = preview(
+            template,
+            Some("broken".to_string()),
+            vars("return: [1"),
+            &Settings::default(),
+        );
+        assert_eq!(bad.profile.as_deref(), Some("broken"));
+        assert!(!bad.diagnostics.is_empty());
+
+        let failed = preview(template, None, Ok(Variables::new()), &Settings::default());
+        assert!(failed.text.is_none() && failed.error.is_some());
+    }
+}
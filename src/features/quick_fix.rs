@@ -0,0 +1,266 @@
+//! Quick fixes for diagnostics
+//!
+//! Converts tab indentation to spaces, one indentation level of the document
+//! per tab, and declares Terraform variables a template references but its
+//! module lacks.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use tower_lsp::lsp_types::{
+    CodeAction, CodeActionKind, CodeActionOrCommand, Diagnostic, NumberOrString, Position, Range,
+    TextEdit, Url, WorkspaceEdit,
+};
+
+use super::formatting::detect_indent_width;
+use crate::diagnostics::DiagnosticCode;
+use crate::document::LineOffsets;
+use crate::parser::lines::{tab_indentation, tab_indented_lines};
+
+/// Quick fixes for the lines in `range`, given the client's `diagnostics`
+/// there, unless `only` asks for other kinds of actions
+pub fn quick_fixes(
+    uri: &Url,
+    text: &str,
+    range: Range,
+    diagnostics: &[Diagnostic],
+    only: Option<&[CodeActionKind]>,
+) -> Vec<CodeActionOrCommand> {
+    if !wants_quick_fixes(only) {
+        return Vec::new();
+    }
+
+    // Measure the document's indentation step on the lines indented with spaces
+    let spaced: Vec<&str> = text
+        .lines()
+        .filter(|line| tab_indentation(line).is_none())
+        .collect();
+    let unit = detect_indent_width(&spaced);
+    let edits: Vec<TextEdit> = tab_indented_lines(text)
+        .into_iter()
+        .filter(|(line, _)| (range.start.line..=range.end.line).contains(line))
+        .map(|(line, indent)| TextEdit {
+            range: Range::new(
+                Position::new(line, 0),
+                Position::new(line, indent.chars().count() as u32),
+            ),
+            new_text: expand_tabs(indent, unit),
+        })
+        .collect();
+    if edits.is_empty() {
+        return Vec::new();
+    }
+
+    let code = NumberOrString::String(DiagnosticCode::TabIndentation.as_str().to_string());
+    let fixed: Vec<Diagnostic> = diagnostics
+        .iter()
+        .filter(|d| d.code.as_ref() == Some(&code))
+        .filter(|d| {
+            edits
+                .iter()
+                .any(|e| e.range.start.line == d.range.start.line)
+        })
+        .cloned()
+        .collect();
+    vec![CodeActionOrCommand::CodeAction(CodeAction {
+        title: "Convert tab indentation to spaces".to_string(),
+        kind: Some(CodeActionKind::QUICKFIX),
+        diagnostics: (!fixed.is_empty()).then_some(fixed),
+        edit: Some(WorkspaceEdit {
+            changes: Some(HashMap::from([(uri.clone(), edits)])),
+            ..Default::default()
+        }),
+        is_preferred: Some(true),
+        ..Default::default()
+    })]
+}
+
+/// Quick fixes appending a `variable` block to `tf_file`, whose contents are
+/// `tf_text`, for each Terraform variable `diagnostics` report as undeclared
+/// in `text`
+pub fn declare_variable_fixes(
+    text: &str,
+    diagnostics: &[Diagnostic],
+    tf_file: &Path,
+    tf_text: &str,
+    only: Option<&[CodeActionKind]>,
+) -> Vec<CodeActionOrCommand> {
+    let Ok(tf_uri) = Url::from_file_path(tf_file) else {
+        return Vec::new();
+    };
+    if !wants_quick_fixes(only) {
+        return Vec::new();
+    }
+
+    let code = NumberOrString::String(DiagnosticCode::UndefinedVariable.as_str().to_string());
+    let last_line = tf_text.split('\n').count() as u32 - 1;
+    let end = Position::new(
+        last_line,
+        tf_text
+            .rsplit('\n')
+            .next()
+            .unwrap_or_default()
+            .chars()
+            .count() as u32,
+    );
+    let separator = match tf_text {
+        "" => "",
+        t if t.ends_with("\n\n") => "",
+        t if t.ends_with('\n') => "\n",
+        _ => "\n\n",
+    };
+    let file_name = tf_file.file_name().unwrap_or_default().to_string_lossy();
+
+    let lines = LineOffsets::new(text);
+    let mut declared: Vec<&str> = Vec::new();
+    let mut actions = Vec::new();
+    for diagnostic in diagnostics
+        .iter()
+        .filter(|d| d.code.as_ref() == Some(&code))
+    {
+        let (Some(start), Some(stop)) = (
+            lines.position_to_offset(text, diagnostic.range.start),
+            lines.position_to_offset(text, diagnostic.range.end),
+        ) else {
+            continue;
+        };
+        let Some(name) = text.get(start..stop).and_then(|r| r.strip_prefix("var.")) else {
+            continue;
+        };
+        if declared.contains(&name) {
+            continue;
+        }
+        declared.push(name);
+        let edit = TextEdit {
+            range: Range::new(end, end),
+            new_text: format!(
+                "{}variable \"{}\" {{\n  type = string\n}}\n",
+                separator, name
+            ),
+        };
+        actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+            title: format!("Declare variable '{}' in {}", name, file_name),
+            kind: Some(CodeActionKind::QUICKFIX),
+            diagnostics: Some(vec![diagnostic.clone()]),
+            edit: Some(WorkspaceEdit {
+                changes: Some(HashMap::from([(tf_uri.clone(), vec![edit])])),
+                ..Default::default()
+            }),
+            is_preferred: Some(true),
+            ..Default::default()
+        }));
+    }
+    actions
+}
+
+/// Whether a code action request limited to `only` kinds takes quick fixes
+fn wants_quick_fixes(only: Option<&[CodeActionKind]>) -> bool {
+    only.is_none_or(|only| {
+        only.iter()
+            .any(|kind| CodeActionKind::QUICKFIX.as_str().starts_with(kind.as_str()))
+    })
+}
+
+/// Leading whitespace with each tab replaced by spaces up to the next
+/// multiple of `unit`
+fn expand_tabs(indent: &str, unit: usize) -> String {
+    let mut out = String::new();
+    for c in indent.chars() {
+        if c == '\t' {
+            out.push_str(&" ".repeat(unit - out.len() % unit));
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uri() -> Url {
+        Url::parse("file:///w.yaml.tftpl").unwrap()
+    }
+
+    fn edits(actions: &[CodeActionOrCommand]) -> Vec<(u32, String)> {
+        let CodeActionOrCommand::CodeAction(action) = &actions[0] else {
+            panic!("expected a code action");
+        };
+        let changes = action.edit.as_ref().unwrap().changes.as_ref().unwrap();
+        changes[&uri()]
+            .iter()
+            .map(|e| (e.range.start.line, e.new_text.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn test_converts_tabs_in_range() {
+        let text = "main:\n    steps:\n\t\t- a:\n\t\t\t\treturn: 1\n";
+        let range = Range::new(Position::new(0, 0), Position::new(2, 0));
+        let actions = quick_fixes(&uri(), text, range, &[], None);
+        assert_eq!(edits(&actions), vec![(2, "        ".to_string())]);
+    }
+
+    #[test]
+    fn test_mixed_indentation_and_diagnostics() {
+        let text = "a:\n  b:\n  \tc: 1\n";
+        let range = Range::new(Position::new(2, 0), Position::new(2, 0));
+        let diagnostic = Diagnostic {
+            range: Range::new(Position::new(2, 0), Position::new(2, 3)),
+            code: Some(NumberOrString::String("tab-indentation".to_string())),
+            ..Default::default()
+        };
+        let actions = quick_fixes(&uri(), text, range, std::slice::from_ref(&diagnostic), None);
+        assert_eq!(edits(&actions), vec![(2, "    ".to_string())]);
+        let CodeActionOrCommand::CodeAction(action) = &actions[0] else {
+            unreachable!();
+        };
+        assert_eq!(action.diagnostics, Some(vec![diagnostic]));
+    }
+
+    #[test]
+    fn test_declare_variable() {
+        let text = "main:\n  steps:\n    - a:\n        return: ${var.region}\n";
+        let diagnostic = |start, end| Diagnostic {
+            range: Range::new(Position::new(3, start), Position::new(3, end)),
+            code: Some(NumberOrString::String("undefined-variable".to_string())),
+            ..Default::default()
+        };
+        let tf_file = Path::new("/module/variables.tf");
+        let tf_text = "variable \"project\" {\n  type = string\n}";
+        let actions = declare_variable_fixes(
+            text,
+            &[diagnostic(18, 28), diagnostic(18, 28), diagnostic(16, 28)],
+            tf_file,
+            tf_text,
+            None,
+        );
+        assert_eq!(actions.len(), 1);
+        let CodeActionOrCommand::CodeAction(action) = &actions[0] else {
+            panic!("expected a code action");
+        };
+        assert_eq!(action.title, "Declare variable 'region' in variables.tf");
+        let changes = action.edit.as_ref().unwrap().changes.as_ref().unwrap();
+        let edit = &changes[&Url::from_file_path(tf_file).unwrap()][0];
+        assert_eq!(edit.range.start, Position::new(2, 1));
+        assert_eq!(
+            edit.new_text,
+            "\n\nvariable \"region\" {\n  type = string\n}\n"
+        );
+
+        let only = [CodeActionKind::REFACTOR];
+        assert!(
+            declare_variable_fixes(text, &[diagnostic(18, 28)], tf_file, "", Some(&only))
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_nothing_to_fix() {
+        let range = Range::new(Position::new(0, 0), Position::new(9, 0));
+        assert!(quick_fixes(&uri(), "a:\n  b: 1\n", range, &[], None).is_empty());
+        let only = [CodeActionKind::REFACTOR];
+        assert!(quick_fixes(&uri(), "a:\n\tb: 1\n", range, &[], Some(&only)).is_empty());
+    }
+}
@@ -0,0 +1,42 @@
+//! Scaffolding for new templates
+
+use std::collections::HashMap;
+
+use tower_lsp::lsp_types::{Position, Range, TextEdit, Url, WorkspaceEdit};
+
+/// Starting point for an empty template
+pub const WORKFLOW_SKELETON: &str = "\
+main:
+  params: [args]
+  steps:
+    - init:
+        assign:
+          - result: null
+    - done:
+        return: $${result}
+";
+
+/// Edit inserting [`WORKFLOW_SKELETON`] at the start of an empty template
+pub fn scaffold_edit(uri: &Url) -> WorkspaceEdit {
+    let edit = TextEdit {
+        range: Range::new(Position::new(0, 0), Position::new(0, 0)),
+        new_text: WORKFLOW_SKELETON.to_string(),
+    };
+    WorkspaceEdit {
+        changes: Some(HashMap::from([(uri.clone(), vec![edit])])),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::{analyze, AnalysisOptions};
+
+    #[test]
+    fn test_skeleton_is_a_valid_workflow() {
+        assert!(analyze(WORKFLOW_SKELETON, &AnalysisOptions::default())
+            .diagnostics
+            .is_empty());
+    }
+}
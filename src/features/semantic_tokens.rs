@@ -0,0 +1,349 @@
+//! Semantic tokens
+//!
+//! Colors the inside of `${...}`/`$${...}` expressions and `%{...}`
+//! directives, which YAML grammars see as plain scalars: the delimiters,
+//! function names, namespaces such as `var` and `sys`, variables, fields,
+//! string and number literals, operators and keywords. Text outside
+//! expressions is left to the editor's YAML grammar.
+
+use tower_lsp::lsp_types::{SemanticToken, SemanticTokenType, SemanticTokensLegend};
+
+use crate::document::LineOffsets;
+use crate::parser::{preprocess_expressions, ExpressionKind};
+use crate::schema::STDLIB_MODULES;
+
+/// Token types, in legend order
+const TOKEN_TYPES: &[SemanticTokenType] = &[
+    SemanticTokenType::MACRO,
+    SemanticTokenType::NAMESPACE,
+    SemanticTokenType::FUNCTION,
+    SemanticTokenType::VARIABLE,
+    SemanticTokenType::PROPERTY,
+    SemanticTokenType::STRING,
+    SemanticTokenType::NUMBER,
+    SemanticTokenType::OPERATOR,
+    SemanticTokenType::KEYWORD,
+];
+
+/// Roots of Terraform references that name a namespace, not a value
+const TERRAFORM_NAMESPACES: &[&str] = &[
+    "var",
+    "local",
+    "module",
+    "data",
+    "path",
+    "terraform",
+    "each",
+    "count",
+    "self",
+];
+
+/// Names that are keywords, literals or word operators in either language
+const KEYWORDS: &[&str] = &[
+    "true", "false", "null", "and", "or", "not", "in", "for", "if", "else", "endif", "endfor",
+];
+
+/// The legend advertised to the client
+pub fn semantic_tokens_legend() -> SemanticTokensLegend {
+    SemanticTokensLegend {
+        token_types: TOKEN_TYPES.to_vec(),
+        token_modifiers: Vec::new(),
+    }
+}
+
+/// Token classes, indexing [`TOKEN_TYPES`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Class {
+    Delimiter,
+    Namespace,
+    Function,
+    Variable,
+    Property,
+    String,
+    Number,
+    Operator,
+    Keyword,
+}
+
+/// Semantic tokens for every expression and directive in the document,
+/// delta-encoded as the protocol expects
+pub fn semantic_tokens(text: &str) -> Vec<SemanticToken> {
+    let (_, map) = preprocess_expressions(text);
+    let mut spans = Vec::new();
+    for expr in &map.expressions {
+        let opener = match expr.kind {
+            ExpressionKind::Workflows => 3,
+            ExpressionKind::Terraform | ExpressionKind::Directive => 2,
+        };
+        let body = &text[expr.start + opener..expr.end - 1];
+        let leading = usize::from(body.starts_with('~'));
+        let trailing = usize::from(body.len() > leading && body.ends_with('~'));
+
+        spans.push((expr.start, expr.start + opener + leading, Class::Delimiter));
+        classify(
+            text,
+            expr.start + opener + leading,
+            expr.end - 1 - trailing,
+            expr.kind,
+            &mut spans,
+        );
+        spans.push((expr.end - 1 - trailing, expr.end, Class::Delimiter));
+    }
+    encode(text, &spans)
+}
+
+/// Classify the tokens of an expression body at `start..end` of `text`
+fn classify(
+    text: &str,
+    start: usize,
+    end: usize,
+    kind: ExpressionKind,
+    spans: &mut Vec<(usize, usize, Class)>,
+) {
+    let bytes = text.as_bytes();
+    // Terraform identifiers may contain dashes; in Workflows `-` subtracts
+    let dashes = kind != ExpressionKind::Workflows;
+    let is_ident = |b: u8| b.is_ascii_alphanumeric() || b == b'_' || (dashes && b == b'-');
+    let next_significant = |from: usize| {
+        bytes[from..end]
+            .iter()
+            .copied()
+            .find(|b| !b.is_ascii_whitespace())
+    };
+
+    let mut previous = None;
+    let mut i = start;
+    while i < end {
+        let b = bytes[i];
+        let token_start = i;
+        let class = if b.is_ascii_whitespace() {
+            i += 1;
+            continue;
+        } else if b == b'"' || b == b'\'' {
+            i += 1;
+            while i < end && bytes[i] != b {
+                i += if bytes[i] == b'\\' { 2 } else { 1 };
+            }
+            i = (i + 1).min(end);
+            Some(Class::String)
+        } else if b.is_ascii_digit() {
+            while i < end
+                && (bytes[i].is_ascii_alphanumeric()
+                    || bytes[i] == b'.' && bytes.get(i + 1).is_some_and(u8::is_ascii_digit))
+            {
+                i += 1;
+            }
+            Some(Class::Number)
+        } else if b.is_ascii_alphabetic() || b == b'_' {
+            while i < end && is_ident(bytes[i]) {
+                i += 1;
+            }
+            let name = &text[token_start..i];
+            let after = next_significant(i);
+            let member = previous == Some(b'.');
+            Some(if after == Some(b'(') {
+                Class::Function
+            } else if member {
+                Class::Property
+            } else if KEYWORDS.contains(&name) {
+                Class::Keyword
+            } else if after == Some(b'.') && is_namespace(name, kind, &text[i..end]) {
+                Class::Namespace
+            } else {
+                Class::Variable
+            })
+        } else if b"+-*/%<>=!&|?:".contains(&b) {
+            while i < end && b"+-*/%<>=!&|?:".contains(&bytes[i]) {
+                i += 1;
+            }
+            // `:` also separates map keys and values, which isn't an operator
+            (&text[token_start..i] != ":").then_some(Class::Operator)
+        } else {
+            i += text[i..].chars().next().map_or(1, char::len_utf8);
+            None
+        };
+        if let Some(class) = class {
+            spans.push((token_start, i, class));
+        }
+        previous = Some(bytes[i - 1]);
+    }
+}
+
+/// Whether `name`, followed by `.` and `rest`, is a namespace root
+fn is_namespace(name: &str, kind: ExpressionKind, rest: &str) -> bool {
+    if kind == ExpressionKind::Workflows {
+        // `sys.now()`, or `http.get` and other connector-like paths
+        if STDLIB_MODULES
+            .iter()
+            .any(|module| module.split('.').next() == Some(name))
+        {
+            return true;
+        }
+        let member = rest.trim_start().trim_start_matches('.').trim_start();
+        let member_end = member
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(member.len());
+        return member[member_end..].trim_start().starts_with('(');
+    }
+    TERRAFORM_NAMESPACES.contains(&name)
+}
+
+/// Delta-encode spans, splitting any that cross lines
+fn encode(text: &str, spans: &[(usize, usize, Class)]) -> Vec<SemanticToken> {
+    let mut tokens = Vec::new();
+    let lines = LineOffsets::new(text);
+    let (mut last_line, mut last_start) = (0, 0);
+    for &(start, end, class) in spans {
+        let mut piece_start = start;
+        for piece in text[start..end].split_inclusive('\n') {
+            let piece_end = piece_start + piece.trim_end_matches(['\r', '\n']).len();
+            if piece_end > piece_start {
+                let position = lines.offset_to_position(text, piece_start);
+                let length = text[piece_start..piece_end].chars().count() as u32;
+                let delta_line = position.line - last_line;
+                let delta_start = if delta_line == 0 {
+                    position.character - last_start
+                } else {
+                    position.character
+                };
+                tokens.push(SemanticToken {
+                    delta_line,
+                    delta_start,
+                    length,
+                    token_type: class as u32,
+                    token_modifiers_bitset: 0,
+                });
+                (last_line, last_start) = (position.line, position.character);
+            }
+            piece_start += piece.len();
+        }
+    }
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Decode tokens into (line, column, text, type) for readable assertions
+    fn decoded(text: &str) -> Vec<(u32, u32, String, SemanticTokenType)> {
+        let lines: Vec<&str> = text.lines().collect();
+        let (mut line, mut column) = (0, 0);
+        semantic_tokens(text)
+            .into_iter()
+            .map(|t| {
+                if t.delta_line > 0 {
+                    line += t.delta_line;
+                    column = t.delta_start;
+                } else {
+                    column += t.delta_start;
+                }
+                let token: String = lines[line as usize]
+                    .chars()
+                    .skip(column as usize)
+                    .take(t.length as usize)
+                    .collect();
+                (
+                    line,
+                    column,
+                    token,
+                    TOKEN_TYPES[t.token_type as usize].clone(),
+                )
+            })
+            .collect()
+    }
+
+    fn types(text: &str) -> Vec<(String, &'static str)> {
+        decoded(text)
+            .into_iter()
+            .map(|(_, _, token, ty)| {
+                let name = TOKEN_TYPES.iter().find(|t| **t == ty).unwrap().as_str();
+                (token, name)
+            })
+            .collect()
+    }
+
+    fn pairs(expected: &[(&str, &'static str)]) -> Vec<(String, &'static str)> {
+        expected
+            .iter()
+            .map(|(t, ty)| (t.to_string(), *ty))
+            .collect()
+    }
+
+    #[test]
+    fn test_workflows_expression() {
+        assert_eq!(
+            types(r#"a: $${text.split(name, "/")[0] + sys.now() * 1.5 and not x.y}"#),
+            pairs(&[
+                ("$${", "macro"),
+                ("text", "namespace"),
+                ("split", "function"),
+                ("name", "variable"),
+                ("\"/\"", "string"),
+                ("0", "number"),
+                ("+", "operator"),
+                ("sys", "namespace"),
+                ("now", "function"),
+                ("*", "operator"),
+                ("1.5", "number"),
+                ("and", "keyword"),
+                ("not", "keyword"),
+                ("x", "variable"),
+                ("y", "property"),
+                ("}", "macro"),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_terraform_expression_and_directive() {
+        assert_eq!(
+            types("a: ${~ upper(var.my-name) == \"\" ? local.x : 3 ~}\n%{ if each.key }"),
+            pairs(&[
+                ("${~", "macro"),
+                ("upper", "function"),
+                ("var", "namespace"),
+                ("my-name", "property"),
+                ("==", "operator"),
+                ("\"\"", "string"),
+                ("?", "operator"),
+                ("local", "namespace"),
+                ("x", "property"),
+                ("3", "number"),
+                ("~}", "macro"),
+                ("%{", "macro"),
+                ("if", "keyword"),
+                ("each", "namespace"),
+                ("key", "property"),
+                ("}", "macro"),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_positions_across_lines() {
+        let text = "a: 1\nb: |\n  $${\n    len(x)\n  }\nc: é $${y}";
+        let decoded = decoded(text);
+        let positions: Vec<(u32, u32, &str)> = decoded
+            .iter()
+            .map(|(line, column, token, _)| (*line, *column, token.as_str()))
+            .collect();
+        assert_eq!(
+            positions,
+            vec![
+                (2, 2, "$${"),
+                (3, 4, "len"),
+                (3, 8, "x"),
+                (4, 2, "}"),
+                (5, 5, "$${"),
+                (5, 8, "y"),
+                (5, 9, "}"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_no_expressions() {
+        assert!(semantic_tokens("a: 1\nb: [x, y]\n").is_empty());
+    }
+}
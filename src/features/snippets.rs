@@ -0,0 +1,119 @@
+//! Extracting reusable snippets from a document
+
+use tower_lsp::lsp_types::Range;
+
+use crate::config::Snippet;
+use crate::parser::lines::{indentation, step_spans};
+use crate::parser::Outline;
+
+/// Extract the steps intersecting `range` as a snippet
+///
+/// When the range touches no step but falls inside a workflow definition, the
+/// whole definition is extracted instead. The snippet is named after the
+/// first extracted step or workflow unless `name` is given.
+pub fn extract_snippet(text: &str, range: Range, name: Option<String>) -> Option<Snippet> {
+    let lines: Vec<&str> = text
+        .split('\n')
+        .map(|l| l.strip_suffix('\r').unwrap_or(l))
+        .collect();
+
+    let intersecting: Vec<(usize, usize)> = step_spans(&lines)
+        .into_iter()
+        .filter(|&(start, end)| start as u32 <= range.end.line && end as u32 >= range.start.line)
+        .collect();
+    let outer: Vec<(usize, usize)> = intersecting
+        .iter()
+        .copied()
+        .filter(|&(s, e)| {
+            !intersecting
+                .iter()
+                .any(|&(os, oe)| (os, oe) != (s, e) && os <= s && e <= oe)
+        })
+        .collect();
+
+    let (start, end, default_name) = match (outer.first(), outer.last()) {
+        (Some(&(start, _)), Some(&(_, end))) => {
+            let first = lines[start]
+                .trim_start()
+                .trim_start_matches('-')
+                .trim_start();
+            let step_name = first.split(':').next().unwrap_or_default().to_string();
+            (start, end, step_name)
+        }
+        _ => {
+            let outline = Outline::parse(text);
+            let workflow = outline
+                .workflows
+                .iter()
+                .find(|w| w.line <= range.start.line && range.start.line <= w.end_line)?;
+            (
+                workflow.line as usize,
+                workflow.end_line as usize,
+                workflow.name.clone(),
+            )
+        }
+    };
+
+    Some(Snippet {
+        name: name.unwrap_or(default_name),
+        description: None,
+        body: dedent(&lines[start..=end]),
+    })
+}
+
+/// Remove the common leading indentation from a block of lines
+fn dedent(lines: &[&str]) -> String {
+    let min = lines
+        .iter()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| indentation(l))
+        .min()
+        .unwrap_or(0);
+    lines
+        .iter()
+        .map(|l| l.get(min..).unwrap_or("").trim_end())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower_lsp::lsp_types::Position;
+
+    const DOC: &str = "main:\n  steps:\n    - first:\n        call: sys.log\n        args:\n          text: hi\n    - second:\n        return: 1\nhelper:\n  params: [x]\n  steps:\n    - done:\n        return: x\n";
+
+    fn range(start: u32, end: u32) -> Range {
+        Range::new(Position::new(start, 0), Position::new(end, 0))
+    }
+
+    #[test]
+    fn test_extract_single_step() {
+        let snippet = extract_snippet(DOC, range(3, 3), None).unwrap();
+        assert_eq!(snippet.name, "first");
+        assert_eq!(
+            snippet.body,
+            "- first:\n    call: sys.log\n    args:\n      text: hi"
+        );
+    }
+
+    #[test]
+    fn test_extract_multiple_steps_with_name() {
+        let snippet = extract_snippet(DOC, range(2, 6), Some("pair".to_string())).unwrap();
+        assert_eq!(snippet.name, "pair");
+        assert!(snippet.body.starts_with("- first:"));
+        assert!(snippet.body.ends_with("- second:\n    return: 1"));
+    }
+
+    #[test]
+    fn test_extract_subworkflow() {
+        let snippet = extract_snippet(DOC, range(9, 9), None).unwrap();
+        assert_eq!(snippet.name, "helper");
+        assert!(snippet.body.starts_with("helper:\n  params: [x]"));
+    }
+
+    #[test]
+    fn test_extract_nothing() {
+        assert!(extract_snippet("a: 1\n", range(0, 0), None).is_none());
+    }
+}
@@ -0,0 +1,408 @@
+//! Semantic diff between two versions of a workflow template
+//!
+//! Compares workflows and their steps rather than lines, so reformatting,
+//! reordering keys or renumbered expressions don't show up, while added,
+//! removed and renamed steps, changed call targets and changed retry policies
+//! do. Useful for reviewing generated or refactored workflows.
+
+use std::fmt;
+
+use serde::Serialize;
+use serde_yaml::Value;
+
+use crate::parser::{preprocess_expressions, without_unparsed_directives, ExpressionMap};
+
+/// What changed between two versions of a template
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkflowDiff {
+    /// Workflows only in the new version
+    pub workflows_added: Vec<String>,
+    /// Workflows only in the old version
+    pub workflows_removed: Vec<String>,
+    /// Changes inside workflows present in both, in new-version order
+    pub workflows_changed: Vec<WorkflowChanges>,
+}
+
+/// Step-level changes within one workflow
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkflowChanges {
+    pub name: String,
+    pub steps_added: Vec<String>,
+    pub steps_removed: Vec<String>,
+    pub steps_renamed: Vec<StepRename>,
+    pub calls_changed: Vec<ValueChange>,
+    pub retries_changed: Vec<ValueChange>,
+}
+
+/// A step whose name changed but whose body did not
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StepRename {
+    pub from: String,
+    pub to: String,
+}
+
+/// A field of a step that differs between versions; `None` when absent
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ValueChange {
+    pub step: String,
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+/// A template that could not be parsed
+#[derive(Debug)]
+pub enum DiffError {
+    Old(serde_yaml::Error),
+    New(serde_yaml::Error),
+}
+
+impl fmt::Display for DiffError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiffError::Old(e) => write!(f, "old template is not valid YAML: {}", e),
+            DiffError::New(e) => write!(f, "new template is not valid YAML: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for DiffError {}
+
+impl WorkflowDiff {
+    /// Whether the two versions are semantically the same
+    pub fn is_empty(&self) -> bool {
+        self.workflows_added.is_empty()
+            && self.workflows_removed.is_empty()
+            && self.workflows_changed.is_empty()
+    }
+}
+
+impl WorkflowChanges {
+    fn is_empty(&self) -> bool {
+        self.steps_added.is_empty()
+            && self.steps_removed.is_empty()
+            && self.steps_renamed.is_empty()
+            && self.calls_changed.is_empty()
+            && self.retries_changed.is_empty()
+    }
+}
+
+impl fmt::Display for WorkflowDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "No workflow changes");
+        }
+        for name in &self.workflows_added {
+            writeln!(f, "+ workflow '{}'", name)?;
+        }
+        for name in &self.workflows_removed {
+            writeln!(f, "- workflow '{}'", name)?;
+        }
+        for workflow in &self.workflows_changed {
+            writeln!(f, "~ workflow '{}'", workflow.name)?;
+            for step in &workflow.steps_added {
+                writeln!(f, "    + step '{}'", step)?;
+            }
+            for step in &workflow.steps_removed {
+                writeln!(f, "    - step '{}'", step)?;
+            }
+            for rename in &workflow.steps_renamed {
+                writeln!(f, "    ~ step '{}' renamed to '{}'", rename.from, rename.to)?;
+            }
+            for change in &workflow.calls_changed {
+                writeln!(f, "    ~ step '{}' call: {}", change.step, change)?;
+            }
+            for change in &workflow.retries_changed {
+                writeln!(f, "    ~ step '{}' retry: {}", change.step, change)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ValueChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show = |v: &Option<String>| v.clone().unwrap_or_else(|| "(none)".to_string());
+        write!(f, "{} -> {}", show(&self.from), show(&self.to))
+    }
+}
+
+/// Compare two versions of a template
+pub fn workflow_diff(old: &str, new: &str) -> Result<WorkflowDiff, DiffError> {
+    let old = workflows(old).map_err(DiffError::Old)?;
+    let new = workflows(new).map_err(DiffError::New)?;
+    let mut diff = WorkflowDiff::default();
+
+    for (name, _) in &old {
+        if !new.iter().any(|(n, _)| n == name) {
+            diff.workflows_removed.push(name.clone());
+        }
+    }
+    for (name, new_steps) in &new {
+        match old.iter().find(|(n, _)| n == name) {
+            Some((_, old_steps)) => {
+                let changes = step_changes(name, old_steps, new_steps);
+                if !changes.is_empty() {
+                    diff.workflows_changed.push(changes);
+                }
+            }
+            None => diff.workflows_added.push(name.clone()),
+        }
+    }
+    Ok(diff)
+}
+
+/// Compare the steps of one workflow
+fn step_changes(name: &str, old: &[(String, Value)], new: &[(String, Value)]) -> WorkflowChanges {
+    let mut changes = WorkflowChanges {
+        name: name.to_string(),
+        ..Default::default()
+    };
+    let find = |steps: &[(String, Value)], name: &str| -> Option<Value> {
+        steps
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, b)| b.clone())
+    };
+
+    let mut removed: Vec<&(String, Value)> =
+        old.iter().filter(|(n, _)| find(new, n).is_none()).collect();
+    for (step, body) in new {
+        let Some(old_body) = find(old, step) else {
+            // An identical body under a new name is a rename
+            match removed.iter().position(|(_, b)| b == body) {
+                Some(i) => changes.steps_renamed.push(StepRename {
+                    from: removed.remove(i).0.clone(),
+                    to: step.clone(),
+                }),
+                None => changes.steps_added.push(step.clone()),
+            }
+            continue;
+        };
+        for (field, list) in [
+            ("call", &mut changes.calls_changed),
+            ("retry", &mut changes.retries_changed),
+        ] {
+            let from = field_text(&old_body, field);
+            let to = field_text(body, field);
+            if from != to {
+                list.push(ValueChange {
+                    step: step.clone(),
+                    from,
+                    to,
+                });
+            }
+        }
+    }
+    changes.steps_removed = removed.into_iter().map(|(n, _)| n.clone()).collect();
+    changes
+}
+
+/// A step field rendered compactly, e.g. `http.get` or a retry policy as JSON
+///
+/// A field of a `try` block's inline step counts as the step's own.
+fn field_text(body: &Value, field: &str) -> Option<String> {
+    let value = body
+        .get(field)
+        .or_else(|| body.get("try").and_then(|t| t.get(field)))?;
+    match value {
+        Value::String(s) => Some(s.clone()),
+        other => serde_json::to_string(other).ok(),
+    }
+}
+
+/// Steps of a workflow by name, with their bodies
+type Steps = Vec<(String, Value)>;
+
+/// Every workflow in a template with all its steps, nested ones included,
+/// in document order
+fn workflows(text: &str) -> Result<Vec<(String, Steps)>, serde_yaml::Error> {
+    let (preprocessed, expression_map) = preprocess_expressions(text);
+    let mut value: Value = serde_yaml::from_str(&without_unparsed_directives(&preprocessed))?;
+    restore_expressions(&mut value, &expression_map);
+
+    let mut workflows = Vec::new();
+    for (name, block) in value.as_mapping().into_iter().flatten() {
+        let (Some(name), Some(steps)) = (name.as_str(), block.get("steps")) else {
+            continue;
+        };
+        let mut collected = Vec::new();
+        collect_steps(steps, &mut collected);
+        workflows.push((name.to_string(), collected));
+    }
+    Ok(workflows)
+}
+
+/// Collect the steps of a `steps` list and of the blocks nested in them
+///
+/// A name used twice keeps its first step.
+fn collect_steps(steps: &Value, out: &mut Steps) {
+    for step in steps.as_sequence().into_iter().flatten() {
+        for (name, body) in step.as_mapping().into_iter().flatten() {
+            if let Some(name) = name.as_str() {
+                if !out.iter().any(|(n, _)| n == name) {
+                    out.push((name.to_string(), body.clone()));
+                }
+            }
+            collect_nested(body, out);
+        }
+    }
+}
+
+/// Find `steps` lists anywhere below a step body: in `try`, `except`, `for`,
+/// `parallel` branches and `switch` conditions
+fn collect_nested(value: &Value, out: &mut Steps) {
+    match value {
+        Value::Mapping(mapping) => {
+            for (key, val) in mapping {
+                if key.as_str() == Some("steps") {
+                    collect_steps(val, out);
+                } else {
+                    collect_nested(val, out);
+                }
+            }
+        }
+        Value::Sequence(items) => {
+            for item in items {
+                collect_nested(item, out);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Put the original expression text back in place of placeholders, since
+/// placeholder numbers differ between versions
+fn restore_expressions(value: &mut Value, expression_map: &ExpressionMap) {
+    match value {
+        Value::String(s) if s.contains("__EXPR_") => {
+            for expr in &expression_map.expressions {
+                if s.contains(&expr.placeholder) {
+                    *s = s.replace(&expr.placeholder, &expr.original);
+                }
+            }
+        }
+        Value::Mapping(mapping) => {
+            for (_, val) in mapping.iter_mut() {
+                restore_expressions(val, expression_map);
+            }
+        }
+        Value::Sequence(items) => {
+            for item in items {
+                restore_expressions(item, expression_map);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OLD: &str = r#"main:
+  steps:
+    - fetch:
+        try:
+          call: http.get
+          args:
+            url: ${base_url}/users
+          result: users
+        retry:
+          predicate: $${http.default_retry_predicate}
+          max_retries: 3
+    - legacy_log:
+        call: sys.log
+        args:
+          text: done
+    - finish:
+        return: $${users}
+cleanup:
+  steps:
+    - noop:
+        return: 1
+"#;
+
+    const NEW: &str = r#"# Reformatted, with an expression renumbered
+main:
+  steps:
+    - init:
+        assign:
+          - started: $${sys.now()}
+    - fetch:
+        try:
+          call: http.post
+          args:
+            url: ${base_url}/users
+          result: users
+        retry: {predicate: $${http.default_retry_predicate}, max_retries: 5}
+    - done:
+        return: $${users}
+report:
+  params: [users]
+  steps:
+    - log:
+        call: sys.log
+"#;
+
+    #[test]
+    fn test_workflow_diff() {
+        let diff = workflow_diff(OLD, NEW).unwrap();
+        assert_eq!(diff.workflows_added, vec!["report"]);
+        assert_eq!(diff.workflows_removed, vec!["cleanup"]);
+        assert_eq!(diff.workflows_changed.len(), 1);
+
+        let main = &diff.workflows_changed[0];
+        assert_eq!(main.name, "main");
+        assert_eq!(main.steps_added, vec!["init"]);
+        assert_eq!(main.steps_removed, vec!["legacy_log"]);
+        assert_eq!(
+            main.steps_renamed,
+            vec![StepRename {
+                from: "finish".to_string(),
+                to: "done".to_string()
+            }]
+        );
+        assert_eq!(
+            main.calls_changed,
+            vec![ValueChange {
+                step: "fetch".to_string(),
+                from: Some("http.get".to_string()),
+                to: Some("http.post".to_string()),
+            }]
+        );
+        assert_eq!(
+            main.retries_changed[0].to.as_deref(),
+            Some(r#"{"predicate":"$${http.default_retry_predicate}","max_retries":5}"#)
+        );
+    }
+
+    #[test]
+    fn test_nested_call_change() {
+        let old = "main:\n  steps:\n    - t:\n        try:\n          steps:\n            - get:\n                call: http.get\n";
+        let new = old.replace("http.get", "http.post");
+        let diff = workflow_diff(old, &new).unwrap();
+        let main = &diff.workflows_changed[0];
+        assert_eq!(
+            main.calls_changed,
+            vec![ValueChange {
+                step: "get".to_string(),
+                from: Some("http.get".to_string()),
+                to: Some("http.post".to_string()),
+            }]
+        );
+        assert_eq!(
+            diff.to_string(),
+            "~ workflow 'main'\n    ~ step 'get' call: http.get -> http.post\n"
+        );
+    }
+
+    #[test]
+    fn test_same_workflow_has_no_changes() {
+        let diff = workflow_diff(OLD, OLD).unwrap();
+        assert!(diff.is_empty());
+        assert_eq!(diff.to_string(), "No workflow changes\n");
+        assert!(matches!(workflow_diff("a: [", OLD), Err(DiffError::Old(_))));
+    }
+}
@@ -0,0 +1,261 @@
+//! Golden-test fixtures
+//!
+//! A fixture is a template plus an optional `*.expected.json` sidecar listing
+//! the diagnostics the server should report for it; a missing sidecar means
+//! no diagnostics are expected. The sidecar for `foo.yaml.tftpl` is
+//! `foo.expected.json`, next to it.
+//!
+//! Regression cases for new rules are contributed by dropping a template in a
+//! fixture directory and running the tests with `YAML_TFTPL_BLESS=1`, which
+//! writes the sidecars from the current output for review.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString};
+
+use crate::analysis::analyze;
+use crate::settings::Settings;
+use crate::workspace::{find_templates, TEMPLATE_EXTENSIONS};
+
+/// Environment variable that makes [`assert_corpus`] rewrite sidecars
+pub const BLESS_ENV: &str = "YAML_TFTPL_BLESS";
+
+/// A diagnostic as recorded in a sidecar file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExpectedDiagnostic {
+    /// 1-based line of the diagnostic start
+    pub line: u32,
+    pub code: String,
+    pub severity: String,
+    pub message: String,
+}
+
+impl ExpectedDiagnostic {
+    fn from_diagnostic(diagnostic: &Diagnostic) -> Self {
+        let code = match &diagnostic.code {
+            Some(NumberOrString::String(code)) => code.clone(),
+            Some(NumberOrString::Number(code)) => code.to_string(),
+            None => String::new(),
+        };
+        let severity = match diagnostic.severity {
+            Some(DiagnosticSeverity::ERROR) => "error",
+            Some(DiagnosticSeverity::WARNING) => "warning",
+            Some(DiagnosticSeverity::INFORMATION) => "information",
+            _ => "hint",
+        };
+        Self {
+            line: diagnostic.range.start.line + 1,
+            code,
+            severity: severity.to_string(),
+            message: diagnostic.message.clone(),
+        }
+    }
+}
+
+impl fmt::Display for ExpectedDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} [{}] {}",
+            self.line, self.severity, self.code, self.message
+        )
+    }
+}
+
+/// A template and the diagnostics expected for it
+#[derive(Debug, Clone)]
+pub struct Fixture {
+    pub path: PathBuf,
+    pub text: String,
+    pub expected: Vec<ExpectedDiagnostic>,
+}
+
+/// Differences between expected and actual diagnostics for one fixture
+#[derive(Debug, Clone)]
+pub struct Mismatch {
+    pub path: PathBuf,
+    /// Expected but not reported
+    pub missing: Vec<ExpectedDiagnostic>,
+    /// Reported but not expected
+    pub unexpected: Vec<ExpectedDiagnostic>,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}:", self.path.display())?;
+        for d in &self.missing {
+            writeln!(f, "  - {}", d)?;
+        }
+        for d in &self.unexpected {
+            writeln!(f, "  + {}", d)?;
+        }
+        Ok(())
+    }
+}
+
+/// Path of the sidecar file for a template
+pub fn sidecar_path(template: &Path) -> PathBuf {
+    let name = template
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or_default();
+    let stem = TEMPLATE_EXTENSIONS
+        .iter()
+        .find_map(|ext| name.strip_suffix(ext))
+        .unwrap_or(name);
+    template.with_file_name(format!("{}.expected.json", stem))
+}
+
+impl Fixture {
+    /// Load a template and its sidecar
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let expected = match fs::read_to_string(sidecar_path(path)) {
+            Ok(json) => serde_json::from_str(&json)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            text,
+            expected,
+        })
+    }
+
+    /// The diagnostics the server currently reports for this fixture
+    pub fn actual(&self, settings: &Settings) -> Vec<ExpectedDiagnostic> {
+        analyze(&self.text, &settings.clone().into())
+            .diagnostics
+            .iter()
+            .map(ExpectedDiagnostic::from_diagnostic)
+            .collect()
+    }
+
+    /// Compare the expected diagnostics against the current output
+    pub fn check(&self, settings: &Settings) -> Result<(), Mismatch> {
+        let actual = self.actual(settings);
+        let missing: Vec<_> = self
+            .expected
+            .iter()
+            .filter(|d| !actual.contains(d))
+            .cloned()
+            .collect();
+        let unexpected: Vec<_> = actual
+            .into_iter()
+            .filter(|d| !self.expected.contains(d))
+            .collect();
+
+        if missing.is_empty() && unexpected.is_empty() {
+            Ok(())
+        } else {
+            Err(Mismatch {
+                path: self.path.clone(),
+                missing,
+                unexpected,
+            })
+        }
+    }
+
+    /// Rewrite the sidecar from the current output, removing it when no
+    /// diagnostics are reported
+    pub fn bless(&self, settings: &Settings) -> io::Result<()> {
+        let actual = self.actual(settings);
+        let sidecar = sidecar_path(&self.path);
+        if actual.is_empty() {
+            return match fs::remove_file(&sidecar) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+                _ => Ok(()),
+            };
+        }
+        let json = serde_json::to_string_pretty(&actual)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(sidecar, json + "\n")
+    }
+}
+
+/// Load every fixture below a directory
+pub fn load_corpus(dir: &Path) -> io::Result<Vec<Fixture>> {
+    find_templates(dir)
+        .iter()
+        .map(|path| Fixture::load(path))
+        .collect()
+}
+
+/// Check every fixture below a directory with default settings, panicking
+/// with a report of all mismatches
+///
+/// With `YAML_TFTPL_BLESS=1` set, sidecars are rewritten instead.
+pub fn assert_corpus(dir: &Path) {
+    let settings = Settings::default();
+    let corpus = load_corpus(dir)
+        .unwrap_or_else(|e| panic!("failed to load fixtures from {}: {}", dir.display(), e));
+    assert!(!corpus.is_empty(), "no fixtures in {}", dir.display());
+
+    if std::env::var_os(BLESS_ENV).is_some() {
+        for fixture in &corpus {
+            fixture
+                .bless(&settings)
+                .unwrap_or_else(|e| panic!("failed to bless {}: {}", fixture.path.display(), e));
+        }
+        return;
+    }
+
+    let mismatches: Vec<String> = corpus
+        .iter()
+        .filter_map(|fixture| fixture.check(&settings).err())
+        .map(|m| m.to_string())
+        .collect();
+    assert!(
+        mismatches.is_empty(),
+        "{} fixture(s) differ from their expected diagnostics (rerun with {}=1 to update):\n{}",
+        mismatches.len(),
+        BLESS_ENV,
+        mismatches.join("\n")
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sidecar_path() {
+        assert_eq!(
+            sidecar_path(Path::new("dir/foo.yaml.tftpl")),
+            PathBuf::from("dir/foo.expected.json")
+        );
+        assert_eq!(
+            sidecar_path(Path::new("bar.yml.tftpl")),
+            PathBuf::from("bar.expected.json")
+        );
+    }
+
+    #[test]
+    fn test_check_and_bless() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("w.yaml.tftpl");
+        fs::write(
+            &path,
+            "main:\n  steps:\n    - a:\n        return: 1\nextra: 1\n",
+        )
+        .unwrap();
+        let settings = Settings::default();
+
+        let fixture = Fixture::load(&path).unwrap();
+        let mismatch = fixture.check(&settings).unwrap_err();
+        assert!(mismatch.missing.is_empty());
+        assert_eq!(mismatch.unexpected.len(), 1);
+        assert_eq!(mismatch.unexpected[0].line, 5);
+        assert_eq!(mismatch.unexpected[0].code, "unknown-keyword");
+
+        fixture.bless(&settings).unwrap();
+        let fixture = Fixture::load(&path).unwrap();
+        assert!(fixture.check(&settings).is_ok());
+        assert!(dir.path().join("w.expected.json").exists());
+    }
+}
@@ -4,6 +4,8 @@
 //! - Expression preprocessing for Terraform ${...} and Workflows $${...} syntax
 //! - YAML parsing with error recovery
 //! - Diagnostic collection and reporting
+//! - Editor features such as hover
+//! - Terraform module awareness (tfvars values)
 //!
 //! # Example
 //!
@@ -18,11 +20,24 @@
 //! let diagnostics = collector.into_diagnostics();
 //! ```
 
+pub mod analysis;
+pub mod capabilities;
+pub mod check;
+pub mod config;
 pub mod diagnostics;
+pub mod doctor;
 pub mod document;
+pub mod features;
+pub mod fixtures;
 pub mod parser;
+pub mod requests;
+pub mod rules;
 pub mod schema;
+pub mod settings;
+pub mod terraform;
+pub mod workspace;
 
 mod backend;
+mod cache;
 
-pub use backend::Backend;
+pub use backend::{service, Backend};
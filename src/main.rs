@@ -1,12 +1,73 @@
 //! yaml-tftpl-lsp: LSP server for YAML Terraform template files with GCP Workflows syntax
 
-use tower_lsp::{LspService, Server};
+use std::io::{self, Write};
+use std::process::ExitCode;
+use std::sync::Arc;
+
+use tokio::net::TcpListener;
+use tower_lsp::Server;
 use tracing_subscriber::EnvFilter;
 
-use yaml_tftpl_lsp::Backend;
+use yaml_tftpl_lsp::rules::RuleRegistry;
+use yaml_tftpl_lsp::terraform::ModuleIndex;
+use yaml_tftpl_lsp::{check, doctor, features, service};
 
 #[tokio::main]
-async fn main() {
+async fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().is_some_and(|a| a == "call-graph") {
+        let Some(path) = args.get(1) else {
+            eprintln!("usage: yaml-tftpl-lsp call-graph <file>");
+            return ExitCode::FAILURE;
+        };
+        return print_call_graph(path);
+    }
+    if args.first().is_some_and(|a| a == "diff") {
+        let (Some(old), Some(new)) = (args.get(1), args.get(2)) else {
+            eprintln!("usage: yaml-tftpl-lsp diff <old> <new>");
+            return ExitCode::FAILURE;
+        };
+        return print_diff(old, new);
+    }
+    if args.first().is_some_and(|a| a == "check") {
+        let mut options = match check::Options::parse(&args[1..]) {
+            Ok(options) => options,
+            Err(e) => {
+                eprintln!("{}", e);
+                eprintln!("usage: yaml-tftpl-lsp check [--format human|sarif|json] [--fail-on error|warning|info|hint] [path...]");
+                return ExitCode::FAILURE;
+            }
+        };
+        let root = std::env::current_dir().unwrap_or_default();
+        if options.paths.is_empty() {
+            options.paths.push(root.clone());
+        }
+        let report = check::run(&root, &options.paths);
+        print!("{}", report.render(options.format));
+        if options.format != check::Format::Human {
+            eprintln!("{}", report.summary());
+        }
+        return if report.fails(options.fail_on) {
+            ExitCode::FAILURE
+        } else {
+            ExitCode::SUCCESS
+        };
+    }
+    if args.first().is_some_and(|a| a == "doctor") {
+        let root = args
+            .get(1)
+            .map(std::path::PathBuf::from)
+            .or_else(|| std::env::current_dir().ok())
+            .unwrap_or_default();
+        let report = doctor::run(&root);
+        print!("{}", report);
+        return if report.has_failures() {
+            ExitCode::FAILURE
+        } else {
+            ExitCode::SUCCESS
+        };
+    }
+
     // Initialize logging
     tracing_subscriber::fmt()
         .with_env_filter(
@@ -15,11 +76,106 @@ async fn main() {
         .with_writer(std::io::stderr)
         .init();
 
+    if let Some(flag) = args.first().filter(|a| *a == "--listen") {
+        let Some(addr) = args.get(1) else {
+            eprintln!("usage: yaml-tftpl-lsp {} <address:port>", flag);
+            return ExitCode::FAILURE;
+        };
+        return serve_tcp(addr).await;
+    }
+
     tracing::info!("Starting yaml-tftpl-lsp server");
 
     let stdin = tokio::io::stdin();
     let stdout = tokio::io::stdout();
 
-    let (service, socket) = LspService::new(Backend::new);
+    let (service, socket) = service(Arc::default(), RuleRegistry::default());
     Server::new(stdin, stdout, socket).serve(service).await;
+    ExitCode::SUCCESS
+}
+
+/// Serve every TCP connection as its own editor session until interrupted
+///
+/// Sessions have separate documents and settings but share the module index,
+/// so many short-lived connections don't each re-read the workspace.
+async fn serve_tcp(addr: &str) -> ExitCode {
+    let listener = match TcpListener::bind(addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("{}: {}", addr, e);
+            return ExitCode::FAILURE;
+        }
+    };
+    tracing::info!(addr, "Listening for editor sessions");
+
+    let modules: Arc<ModuleIndex> = Arc::default();
+    loop {
+        let (stream, peer) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(connection) => connection,
+                Err(e) => {
+                    tracing::warn!(error = %e, "Failed to accept connection");
+                    continue;
+                }
+            },
+            _ = tokio::signal::ctrl_c() => {
+                tracing::info!("Interrupted; no longer accepting sessions");
+                return ExitCode::SUCCESS;
+            }
+        };
+
+        let modules = modules.clone();
+        tokio::spawn(async move {
+            tracing::info!(%peer, "Session started");
+            let (read, write) = tokio::io::split(stream);
+            let (service, socket) = service(modules, RuleRegistry::default());
+            Server::new(read, write, socket).serve(service).await;
+            tracing::info!(%peer, "Session ended");
+        });
+    }
+}
+
+/// Print the semantic differences between two templates and exit
+fn print_diff(old: &str, new: &str) -> ExitCode {
+    let read = |path: &str| {
+        std::fs::read_to_string(path).map_err(|e| {
+            eprintln!("{}: {}", path, e);
+        })
+    };
+    let (Ok(old_text), Ok(new_text)) = (read(old), read(new)) else {
+        return ExitCode::FAILURE;
+    };
+    match features::workflow_diff(&old_text, &new_text) {
+        Ok(diff) => {
+            print!("{}", diff);
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Print the call graph of a template as JSON and exit
+fn print_call_graph(path: &str) -> ExitCode {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let graph = features::call_graph(&text);
+    let mut stdout = io::stdout().lock();
+    let written = serde_json::to_writer_pretty(&mut stdout, &graph)
+        .map_err(io::Error::from)
+        .and_then(|()| writeln!(stdout));
+    match written {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Cannot write the call graph: {}", e);
+            ExitCode::FAILURE
+        }
+    }
 }
@@ -0,0 +1,388 @@
+//! YAML anchor (`&name`), alias (`*name`) and tag (`!tag`) scanning
+//!
+//! serde_yaml resolves aliases transparently, so the parsed value carries no
+//! trace of where anchors were declared or used. This module scans the
+//! original text for anchor, alias and tag tokens, skipping quoted strings,
+//! comments, block scalars and template expressions, and records their
+//! positions so editor features can link them together. Only `&`, `*` and `!`
+//! where a node can start count, so `Tom &x Jerry` stays a plain scalar.
+
+use super::directives::is_directive;
+use super::expressions::ExpressionMap;
+use super::lines::{indentation, starts_block_scalar};
+
+/// An anchor declaration such as `&defaults`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Anchor {
+    /// Anchor name without the leading `&`
+    pub name: String,
+    /// Line of the `&` token (0-indexed)
+    pub line: u32,
+    /// Column of the `&` token (0-indexed)
+    pub column: u32,
+    /// Column just past the anchor name
+    pub end_column: u32,
+    /// The anchored YAML text, dedented
+    pub content: String,
+}
+
+/// An alias reference such as `*defaults`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Alias {
+    /// Alias name without the leading `*`
+    pub name: String,
+    /// Line of the `*` token (0-indexed)
+    pub line: u32,
+    /// Column of the `*` token (0-indexed)
+    pub column: u32,
+    /// Column just past the alias name
+    pub end_column: u32,
+}
+
+/// A node tag such as `!!str` or `!Ref`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tag {
+    /// The full tag including its leading `!` characters
+    pub tag: String,
+    /// Line of the tag (0-indexed)
+    pub line: u32,
+    /// Column of the first `!`
+    pub column: u32,
+    /// Column just past the tag
+    pub end_column: u32,
+}
+
+/// All anchors, aliases and tags found in a document, in document order
+#[derive(Debug, Default)]
+pub struct AnchorIndex {
+    pub anchors: Vec<Anchor>,
+    pub aliases: Vec<Alias>,
+    pub tags: Vec<Tag>,
+}
+
+impl AnchorIndex {
+    /// Find the anchor an alias refers to: the closest declaration of the
+    /// same name that precedes the alias
+    pub fn resolve(&self, alias: &Alias) -> Option<&Anchor> {
+        self.anchors
+            .iter()
+            .rfind(|a| a.name == alias.name && (a.line, a.column) < (alias.line, alias.column))
+    }
+
+    /// Aliases that resolve to the given anchor
+    pub fn usages<'a>(&'a self, anchor: &'a Anchor) -> impl Iterator<Item = &'a Alias> + 'a {
+        self.aliases
+            .iter()
+            .filter(move |alias| self.resolve(alias) == Some(anchor))
+    }
+
+    /// Anchors whose name was already declared earlier in the document,
+    /// paired with that earlier declaration
+    pub fn redefinitions(&self) -> impl Iterator<Item = (&Anchor, &Anchor)> {
+        self.anchors.iter().enumerate().filter_map(|(i, anchor)| {
+            self.anchors[..i]
+                .iter()
+                .find(|a| a.name == anchor.name)
+                .map(|first| (anchor, first))
+        })
+    }
+
+    /// Find the anchor whose token covers the given position
+    pub fn anchor_at(&self, line: u32, column: u32) -> Option<&Anchor> {
+        self.anchors
+            .iter()
+            .find(|a| a.line == line && column >= a.column && column <= a.end_column)
+    }
+
+    /// Find the alias whose token covers the given position
+    pub fn alias_at(&self, line: u32, column: u32) -> Option<&Alias> {
+        self.aliases
+            .iter()
+            .find(|a| a.line == line && column >= a.column && column <= a.end_column)
+    }
+}
+
+/// Scan the original document text for anchors, aliases and tags
+///
+/// Expression spans from `expression_map` are skipped so that operators like
+/// `$${a *b}` are not mistaken for aliases.
+pub fn scan_anchors(text: &str, expression_map: &ExpressionMap) -> AnchorIndex {
+    let mut index = AnchorIndex::default();
+    let lines: Vec<&str> = text.split('\n').collect();
+    let mut line_start = 0usize;
+    let mut block_scalar_indent: Option<usize> = None;
+
+    for (line_no, raw_line) in lines.iter().enumerate() {
+        let line = raw_line.strip_suffix('\r').unwrap_or(raw_line);
+        let indent = indentation(line);
+        let offset = line_start;
+        line_start += raw_line.len() + 1;
+
+        // Skip the body of block scalars (`key: |` / `key: >`)
+        if let Some(parent) = block_scalar_indent {
+            if line.trim().is_empty() || indent > parent {
+                continue;
+            }
+            block_scalar_indent = None;
+        }
+
+        // `%TAG !e! ...` declares a tag handle rather than using a tag
+        if is_directive(line) {
+            continue;
+        }
+
+        let mut in_single = false;
+        let mut in_double = false;
+        let mut escaped = false;
+        // Whether a node (and so a node property) may start here
+        let mut node_start = true;
+        let mut flow_depth = 0usize;
+        let mut prev: Option<char> = None;
+        let mut chars = line.char_indices().peekable();
+        let mut column = 0u32;
+
+        while let Some((i, ch)) = chars.next() {
+            let in_expression = expression_map
+                .expressions
+                .iter()
+                .any(|e| offset + i >= e.start && offset + i < e.end);
+            let before_space = chars.peek().is_none_or(|(_, c)| c.is_whitespace());
+
+            if in_expression {
+                node_start = false;
+            } else if in_single {
+                in_single = ch != '\'';
+            } else if in_double {
+                match ch {
+                    _ if escaped => escaped = false,
+                    '\\' => escaped = true,
+                    '"' => in_double = false,
+                    _ => {}
+                }
+            } else {
+                match ch {
+                    '\'' if node_start => in_single = true,
+                    '"' if node_start => in_double = true,
+                    '#' if prev.is_none_or(char::is_whitespace) => break,
+                    '&' | '*' | '!' if node_start => {
+                        let name: String = line[i + 1..]
+                            .chars()
+                            .take_while(|c| is_anchor_char(*c))
+                            .collect();
+                        if !name.is_empty() {
+                            let name_len = name.chars().count() as u32;
+                            let end_column = column + 1 + name_len;
+                            let last = name.chars().next_back();
+                            if ch == '&' {
+                                let rest = &line[i + 1 + name.len()..];
+                                let content = anchored_content(rest, &lines, line_no, indent);
+                                index.anchors.push(Anchor {
+                                    name,
+                                    line: line_no as u32,
+                                    column,
+                                    end_column,
+                                    content,
+                                });
+                            } else if ch == '!' {
+                                index.tags.push(Tag {
+                                    tag: format!("!{}", name),
+                                    line: line_no as u32,
+                                    column,
+                                    end_column,
+                                });
+                            } else {
+                                index.aliases.push(Alias {
+                                    name,
+                                    line: line_no as u32,
+                                    column,
+                                    end_column,
+                                });
+                                // An alias is the whole node
+                                node_start = false;
+                            }
+                            for _ in 0..name_len {
+                                chars.next();
+                            }
+                            column = end_column;
+                            prev = last;
+                            continue;
+                        }
+                        node_start = false;
+                    }
+                    '[' | '{' if node_start => flow_depth += 1,
+                    ']' | '}' if flow_depth > 0 => {
+                        flow_depth -= 1;
+                        node_start = false;
+                    }
+                    ',' if flow_depth > 0 => node_start = true,
+                    '-' | '?' if node_start && before_space => {}
+                    ':' if before_space => node_start = true,
+                    c if c.is_whitespace() => {}
+                    _ => node_start = false,
+                }
+            }
+
+            prev = Some(ch);
+            column += 1;
+        }
+
+        if starts_block_scalar(line) {
+            block_scalar_indent = Some(indent);
+        }
+    }
+
+    index
+}
+
+/// Characters allowed in an anchor name (anything but whitespace and flow indicators)
+fn is_anchor_char(c: char) -> bool {
+    !c.is_whitespace() && !matches!(c, ',' | '[' | ']' | '{' | '}')
+}
+
+/// Collect the YAML text anchored at a declaration: the remainder of the
+/// anchor's line plus any following lines indented deeper than it
+fn anchored_content(rest: &str, lines: &[&str], line_no: usize, indent: usize) -> String {
+    let mut content: Vec<String> = Vec::new();
+    let inline = rest.trim();
+    if !inline.is_empty() {
+        content.push(inline.to_string());
+    }
+
+    let mut block: Vec<&str> = Vec::new();
+    for next in &lines[line_no + 1..] {
+        let next = next.strip_suffix('\r').unwrap_or(next);
+        if next.trim().is_empty() {
+            block.push("");
+            continue;
+        }
+        if indentation(next) <= indent {
+            break;
+        }
+        block.push(next);
+    }
+    while block.last() == Some(&"") {
+        block.pop();
+    }
+
+    let min_indent = block
+        .iter()
+        .filter(|l| !l.is_empty())
+        .map(|l| indentation(l))
+        .min()
+        .unwrap_or(0);
+    content.extend(
+        block
+            .iter()
+            .map(|l| l.get(min_indent..).unwrap_or("").to_string()),
+    );
+
+    content.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::preprocess_expressions;
+
+    fn scan(text: &str) -> AnchorIndex {
+        let (_, map) = preprocess_expressions(text);
+        scan_anchors(text, &map)
+    }
+
+    #[test]
+    fn test_scan_block_anchor_and_alias() {
+        let text = "defaults: &defaults\n  timeout: 30\n  retries: 2\nother: *defaults";
+        let index = scan(text);
+
+        assert_eq!(index.anchors.len(), 1);
+        assert_eq!(index.anchors[0].name, "defaults");
+        assert_eq!(index.anchors[0].line, 0);
+        assert_eq!(index.anchors[0].column, 10);
+        assert_eq!(index.anchors[0].content, "timeout: 30\nretries: 2");
+
+        assert_eq!(index.aliases.len(), 1);
+        assert_eq!(index.aliases[0].name, "defaults");
+        assert_eq!(index.aliases[0].line, 3);
+        assert_eq!(index.aliases[0].column, 7);
+        assert_eq!(index.aliases[0].end_column, 16);
+    }
+
+    #[test]
+    fn test_scan_inline_anchor() {
+        let index = scan("a: &val 42\nb: *val");
+        assert_eq!(index.anchors[0].content, "42");
+        assert_eq!(index.resolve(&index.aliases[0]).unwrap().name, "val");
+    }
+
+    #[test]
+    fn test_ignores_quotes_comments_and_expressions() {
+        let text = "a: \"&quoted\"\nb: x # *comment\nc: $${a *b}\nd: '*single'";
+        let index = scan(text);
+        assert!(index.anchors.is_empty());
+        assert!(index.aliases.is_empty());
+    }
+
+    #[test]
+    fn test_ignores_block_scalars() {
+        let text = "text: |\n  *not_alias\n  &not_anchor\nnext: *real";
+        let index = scan(text);
+        assert!(index.anchors.is_empty());
+        assert_eq!(index.aliases.len(), 1);
+        assert_eq!(index.aliases[0].name, "real");
+    }
+
+    #[test]
+    fn test_usages_respect_redefinition() {
+        let text = "a: &x 1\nb: *x\nc: *x\nd: &x 2\ne: *x";
+        let index = scan(text);
+        assert_eq!(index.anchors.len(), 2);
+        assert_eq!(index.usages(&index.anchors[0]).count(), 2);
+        assert_eq!(index.usages(&index.anchors[1]).count(), 1);
+    }
+
+    #[test]
+    fn test_redefinitions() {
+        let index = scan("a: &x 1\nb: &y 2\nc: &x 3");
+        let redefined: Vec<(u32, u32)> = index
+            .redefinitions()
+            .map(|(dup, first)| (dup.line, first.line))
+            .collect();
+        assert_eq!(redefined, vec![(2, 0)]);
+    }
+
+    #[test]
+    fn test_scan_tags() {
+        let text = "a: !!str 1\nb: !Ref Bucket\nc: wow!bang\nd: \"!quoted\"";
+        let index = scan(text);
+        let tags: Vec<&str> = index.tags.iter().map(|t| t.tag.as_str()).collect();
+        assert_eq!(tags, vec!["!!str", "!Ref"]);
+        assert_eq!(index.tags[1].line, 1);
+        assert_eq!(index.tags[1].column, 3);
+        assert_eq!(index.tags[1].end_column, 7);
+
+        let index = scan("%TAG !e! tag:example.com,2000:\n---\na: !e!x 1");
+        let tags: Vec<&str> = index.tags.iter().map(|t| t.tag.as_str()).collect();
+        assert_eq!(tags, vec!["!e!x"]);
+        assert_eq!(index.tags[0].line, 2);
+    }
+
+    #[test]
+    fn test_properties_only_where_a_node_starts() {
+        let text = "- note: Tom &x Jerry\n- y: Hi !there\n- z: it's *not\n- w: \"a \\\" &q\"\n- [a, &f 1, {k: *f}]\n- &s !!str x\n";
+        let index = scan(text);
+        let anchors: Vec<&str> = index.anchors.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(anchors, vec!["f", "s"]);
+        let aliases: Vec<&str> = index.aliases.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(aliases, vec!["f"]);
+        let tags: Vec<&str> = index.tags.iter().map(|t| t.tag.as_str()).collect();
+        assert_eq!(tags, vec!["!!str"]);
+    }
+
+    #[test]
+    fn test_anchor_in_sequence_item() {
+        let text = "steps:\n  - &step\n    name: x\n  - *step";
+        let index = scan(text);
+        assert_eq!(index.anchors[0].content, "name: x");
+        assert_eq!(index.aliases[0].line, 3);
+    }
+}
@@ -0,0 +1,147 @@
+//! YAML directives (`%YAML`, `%TAG`) and the document start marker
+//!
+//! libyaml, underneath serde_yaml, rejects directives the YAML spec says a
+//! parser should only warn about: unknown directive names and `%YAML`
+//! versions newer than 1.2. Those lines are blanked before parsing so they
+//! can be reported as directives rather than as syntax errors.
+
+use std::borrow::Cow;
+
+/// A directive line such as `%YAML 1.2`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Directive {
+    /// Directive name without the leading `%`
+    pub name: String,
+    /// Whitespace-separated parameters, comment excluded
+    pub parameters: Vec<String>,
+    /// Line of the directive (0-indexed)
+    pub line: u32,
+    /// Column just past the last parameter
+    pub end_column: u32,
+}
+
+/// The directives at the top of a document and the `---` that ends them
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Directives {
+    pub directives: Vec<Directive>,
+    /// Line of the first `---` marker, if it comes before any content
+    pub document_start: Option<u32>,
+}
+
+/// Scan the directives before the first document of `text`
+pub fn scan_directives(text: &str) -> Directives {
+    let mut result = Directives::default();
+
+    for (line_no, line) in text.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        if is_document_start(line) {
+            result.document_start = Some(line_no as u32);
+            break;
+        }
+        if !is_directive(line) {
+            break;
+        }
+        let content = line.split(" #").next().unwrap_or(line).trim_end();
+        let mut words = content[1..].split_whitespace();
+        result.directives.push(Directive {
+            name: words.next().unwrap_or_default().to_string(),
+            parameters: words.map(str::to_string).collect(),
+            line: line_no as u32,
+            end_column: content.chars().count() as u32,
+        });
+    }
+
+    result
+}
+
+/// Whether a line is a directive: a `%` in the first column followed by a
+/// name, which template directives such as `%{ if }` never are
+pub(crate) fn is_directive(line: &str) -> bool {
+    line.strip_prefix('%')
+        .and_then(|rest| rest.chars().next())
+        .is_some_and(|c| c.is_ascii_alphabetic())
+}
+
+/// Whether a line is a `---` document start marker
+fn is_document_start(line: &str) -> bool {
+    line.strip_prefix("---")
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with([' ', '\t']))
+}
+
+/// `text` with the directive lines libyaml would reject blanked out
+///
+/// `%YAML` and unknown directives are always blanked, and `%TAG` too when no
+/// `---` follows the directives, since libyaml then fails on the missing
+/// marker instead. Line numbers are unchanged.
+pub fn without_unparsed_directives(text: &str) -> Cow<'_, str> {
+    let scanned = scan_directives(text);
+    let blanked: Vec<u32> = scanned
+        .directives
+        .iter()
+        .filter(|d| d.name != "TAG" || scanned.document_start.is_none())
+        .map(|d| d.line)
+        .collect();
+    if blanked.is_empty() {
+        return Cow::Borrowed(text);
+    }
+
+    let mut out = String::with_capacity(text.len());
+    for (line_no, line) in text.split_inclusive('\n').enumerate() {
+        if blanked.contains(&(line_no as u32)) {
+            out.push_str(&line[line.trim_end_matches(['\r', '\n']).len()..]);
+        } else {
+            out.push_str(line);
+        }
+    }
+    Cow::Owned(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scan_directives() {
+        let text = "# header\n%YAML 1.2\n%TAG !e! tag:example.com,2000:  # custom\n--- # start\nmain: 1\n%FOO x\n";
+        let scanned = scan_directives(text);
+        assert_eq!(scanned.document_start, Some(3));
+        assert_eq!(
+            scanned.directives,
+            vec![
+                Directive {
+                    name: "YAML".to_string(),
+                    parameters: vec!["1.2".to_string()],
+                    line: 1,
+                    end_column: 9,
+                },
+                Directive {
+                    name: "TAG".to_string(),
+                    parameters: vec!["!e!".to_string(), "tag:example.com,2000:".to_string()],
+                    line: 2,
+                    end_column: 30,
+                },
+            ]
+        );
+
+        let scanned = scan_directives("%{ if x }\na: 1\n");
+        assert!(scanned.directives.is_empty());
+        assert_eq!(scanned.document_start, None);
+    }
+
+    #[test]
+    fn test_without_unparsed_directives() {
+        let text = "%YAML 1.3\r\n%TAG !e! tag:e.com:\n%FOO\n---\na: 1\n";
+        assert_eq!(
+            without_unparsed_directives(text),
+            "\r\n%TAG !e! tag:e.com:\n\n---\na: 1\n"
+        );
+        assert_eq!(without_unparsed_directives("%TAG ! x:\na: 1"), "\na: 1");
+        assert!(matches!(
+            without_unparsed_directives("---\na: 1\n"),
+            Cow::Borrowed(_)
+        ));
+    }
+}
@@ -1,5 +1,8 @@
 //! Terraform ${} and Workflows $${} expression handling
 
+use serde::Serialize;
+use tower_lsp::lsp_types::{Position, Range};
+
 /// Represents a single expression found in the document
 #[derive(Debug, Clone)]
 #[allow(dead_code)]
@@ -20,7 +23,8 @@ pub struct Expression {
     pub end_line: u32,
     /// End column (0-indexed)
     pub end_column: u32,
-    /// Whether this is a Terraform (${}) or Workflows ($${}) expression
+    /// Whether this is a Terraform (${}) or Workflows ($${}) expression, or a
+    /// template directive (%{})
     pub kind: ExpressionKind,
 }
 
@@ -43,19 +47,40 @@ impl Expression {
 }
 
 /// The kind of expression
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum ExpressionKind {
     /// Terraform interpolation: ${...}
     Terraform,
     /// GCP Workflows runtime expression: $${...}
     Workflows,
+    /// Terraform template directive: %{if ...}, %{for ...}, %{endif}, ...
+    Directive,
+}
+
+/// An expression or directive opener (`${`, `$${` or `%{`) with no matching
+/// `}`, left in the text as written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnclosedExpression {
+    /// Byte offset of the opener in the original document
+    pub start: usize,
+    /// Line of the opener (0-indexed)
+    pub line: u32,
+    /// Column of the opener (0-indexed)
+    pub column: u32,
+    /// The kind of expression the opener starts
+    pub kind: ExpressionKind,
 }
 
-/// Represents a position offset caused by placeholder substitution
+/// Where a placeholder sits in the preprocessed text and where the
+/// expression it replaced sits in the original
+///
+/// A multi-line expression collapses into a one-line placeholder, so the
+/// text after it continues on the placeholder's line and every later line
+/// moves up by the lines the expression spanned.
 #[derive(Debug, Clone)]
-#[allow(dead_code)]
 struct PositionDelta {
-    /// The line where this expression starts (in preprocessed text)
+    /// The line of the placeholder (in preprocessed text)
     preprocessed_line: u32,
     /// The column where the placeholder starts (in preprocessed text)
     preprocessed_column: u32,
@@ -69,8 +94,13 @@ struct PositionDelta {
     original_end_line: u32,
     /// The original expression's end column
     original_end_column: u32,
-    /// Whether this is a multi-line expression
-    is_multiline: bool,
+}
+
+impl PositionDelta {
+    /// Lines the expression spans beyond its first
+    fn collapsed_lines(&self) -> u32 {
+        self.original_end_line - self.original_line
+    }
 }
 
 /// A map of all expressions found in a document
@@ -78,6 +108,8 @@ struct PositionDelta {
 pub struct ExpressionMap {
     /// All expressions, in document order
     pub expressions: Vec<Expression>,
+    /// Openers without a closing brace, in document order
+    pub unclosed: Vec<UnclosedExpression>,
     /// Cached position deltas for efficient position adjustment
     position_deltas: Vec<PositionDelta>,
 }
@@ -115,36 +147,33 @@ impl ExpressionMap {
     fn build_position_deltas(&mut self) {
         self.position_deltas.clear();
 
-        // Track cumulative column offset for each line
-        // For expressions on the same line, we need to account for previous substitutions
-        let mut current_line = 0u32;
-        let mut cumulative_column_offset: isize = 0;
+        // Lines collapsed by the expressions so far, and the last place on
+        // the current preprocessed line known in both texts: the line start,
+        // or the end of the previous placeholder
+        let mut collapsed = 0u32;
+        let mut anchor: Option<(u32, u32, u32)> = None; // (preprocessed line, column, original column)
 
         for expr in &self.expressions {
-            // Reset cumulative offset when moving to a new line
-            if expr.start_line != current_line {
-                current_line = expr.start_line;
-                cumulative_column_offset = 0;
-            }
-
-            // Calculate the preprocessed column (after previous substitutions on same line)
-            let preprocessed_column =
-                (expr.start_column as isize - cumulative_column_offset) as u32;
+            let preprocessed_line = expr.start_line - collapsed;
+            let (anchor_column, anchor_original) = match anchor {
+                Some((line, column, original)) if line == preprocessed_line => (column, original),
+                _ => (0, 0),
+            };
+            let preprocessed_column = anchor_column + (expr.start_column - anchor_original);
             let preprocessed_end_column = preprocessed_column + expr.placeholder_len() as u32;
 
             self.position_deltas.push(PositionDelta {
-                preprocessed_line: expr.start_line,
+                preprocessed_line,
                 preprocessed_column,
                 preprocessed_end_column,
                 original_line: expr.start_line,
                 original_column: expr.start_column,
                 original_end_line: expr.end_line,
                 original_end_column: expr.end_column,
-                is_multiline: expr.start_line != expr.end_line,
             });
 
-            // Update cumulative offset for next expression on same line
-            cumulative_column_offset += expr.len_delta();
+            collapsed += expr.end_line - expr.start_line;
+            anchor = Some((preprocessed_line, preprocessed_end_column, expr.end_column));
         }
     }
 
@@ -152,36 +181,61 @@ impl ExpressionMap {
     ///
     /// This handles the case where YAML parsing reports an error at a position
     /// that falls within or after a placeholder, mapping it back to the correct
-    /// position in the original document.
+    /// position in the original document, including the lines multi-line
+    /// expressions collapsed.
     pub fn adjust_position(&self, line: u32, column: u32) -> (u32, u32) {
-        let mut adjusted_column = column as i64;
+        self.adjust(line, column, false)
+    }
+
+    /// Adjust a range from preprocessed coordinates back to original
+    /// coordinates, widening ends within a placeholder to the end of its
+    /// expression
+    pub fn adjust_range(&self, range: Range) -> Range {
+        let (line, character) = self.adjust(range.start.line, range.start.character, false);
+        let (end_line, end_character) = self.adjust(range.end.line, range.end.character, true);
+        Range::new(
+            Position::new(line, character),
+            Position::new(end_line, end_character),
+        )
+    }
 
-        // Find all deltas that affect this position
+    /// [`Self::adjust_position`], mapping positions within a placeholder to
+    /// the end of its expression when `end` is set
+    fn adjust(&self, line: u32, column: u32, end: bool) -> (u32, u32) {
+        // The last placeholder starting at or before the position on its line
+        let mut collapsed = 0;
+        let mut last = None;
         for delta in &self.position_deltas {
-            // Only consider deltas on the same line (for single-line expressions)
-            // or that might affect this line (for multi-line expressions)
+            let past = if end {
+                delta.preprocessed_column >= column
+            } else {
+                delta.preprocessed_column > column
+            };
+            if delta.preprocessed_line > line || (delta.preprocessed_line == line && past) {
+                break;
+            }
             if delta.preprocessed_line == line {
-                if column >= delta.preprocessed_column && column < delta.preprocessed_end_column {
-                    // Position is within a placeholder - map to start of original expression
-                    return (delta.original_line, delta.original_column);
-                } else if column >= delta.preprocessed_end_column {
-                    // Position is after this placeholder - adjust by the length difference
-                    // Use signed arithmetic since placeholder can be longer than original
-                    let placeholder_len =
-                        (delta.preprocessed_end_column - delta.preprocessed_column) as i64;
-                    let original_len = if delta.is_multiline {
-                        // For multi-line expressions, only count first line portion
-                        // This is a simplification; full implementation would track line breaks
-                        (delta.original_end_column - delta.original_column) as i64
-                    } else {
-                        (delta.original_end_column - delta.original_column) as i64
-                    };
-                    adjusted_column += original_len - placeholder_len;
-                }
+                last = Some(delta);
+            } else {
+                collapsed += delta.collapsed_lines();
             }
         }
 
-        (line, adjusted_column.max(0) as u32)
+        match last {
+            // Within a placeholder: the start of the original expression
+            Some(delta) if column < delta.preprocessed_end_column && !end => {
+                (delta.original_line, delta.original_column)
+            }
+            Some(delta) if column < delta.preprocessed_end_column => {
+                (delta.original_end_line, delta.original_end_column)
+            }
+            // After a placeholder: that far past the end of the expression
+            Some(delta) => (
+                delta.original_end_line,
+                delta.original_end_column + (column - delta.preprocessed_end_column),
+            ),
+            None => (line + collapsed, column),
+        }
     }
 
     /// Check if a position falls within any expression
@@ -291,4 +345,29 @@ mod tests {
         assert!(map.is_within_expression(0, 15)); // Middle
         assert!(!map.is_within_expression(0, 19)); // After (placeholder ends at 19)
     }
+
+    #[test]
+    fn test_adjust_position_across_multiline_expressions() {
+        let text = "a: ${jsonencode({\n  x = 1\n})} b: [\nc: $${f(\n1)} ${y}  ]\nd: e: f\n";
+        let (preprocessed, map) = super::super::preprocess_expressions(text);
+        assert_eq!(
+            preprocessed,
+            "a: __EXPR_002__ b: [\nc: __EXPR_001__ __EXPR_000__  ]\nd: e: f\n"
+        );
+
+        // After the first placeholder, on the line the expression ends on
+        assert_eq!(map.adjust_position(0, 19), (2, 7));
+        // Before and within the placeholders of the next line
+        assert_eq!(map.adjust_position(1, 1), (3, 1));
+        assert_eq!(map.adjust_position(1, 5), (3, 3));
+        assert_eq!(map.adjust_position(1, 17), (4, 4));
+        assert_eq!(map.adjust_position(1, 30), (4, 10));
+        // Lines after both collapse
+        assert_eq!(map.adjust_position(2, 4), (5, 4));
+
+        // A range over a placeholder covers the whole expression
+        let range = |sl, sc, el, ec| Range::new(Position::new(sl, sc), Position::new(el, ec));
+        assert_eq!(map.adjust_range(range(0, 3, 0, 15)), range(0, 3, 2, 3));
+        assert_eq!(map.adjust_range(range(0, 0, 0, 3)), range(0, 0, 0, 3));
+    }
 }
@@ -0,0 +1,223 @@
+//! Line-level helpers shared by the text scanners
+
+/// Number of leading spaces on a line
+pub(crate) fn indentation(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+/// The leading whitespace of `line`, if it contains a tab that YAML reads
+/// as indentation
+pub(crate) fn tab_indentation(line: &str) -> Option<&str> {
+    let rest = line.trim_start_matches([' ', '\t']);
+    let indent = &line[..line.len() - rest.len()];
+    // Whitespace-only lines carry no indentation
+    (indent.contains('\t') && !rest.trim().is_empty()).then_some(indent)
+}
+
+/// Lines (0-indexed) indented with tabs, with their leading whitespace
+///
+/// Inside a block scalar, tabs after the spaces that indent the scalar are
+/// part of its content and left alone.
+pub(crate) fn tab_indented_lines(text: &str) -> Vec<(u32, &str)> {
+    let lines: Vec<&str> = text.lines().collect();
+    let in_block_scalar = block_scalar_lines(&lines);
+    lines
+        .iter()
+        .enumerate()
+        .filter_map(|(line_no, line)| {
+            let indent = tab_indentation(line)?;
+            (!in_block_scalar[line_no] || indent.starts_with('\t'))
+                .then_some((line_no as u32, indent))
+        })
+        .collect()
+}
+
+/// Whether a line's value opens a block scalar (`|`, `>`, with optional modifiers)
+pub(crate) fn starts_block_scalar(line: &str) -> bool {
+    let value = line.split_once(": ").map_or(line.trim(), |(_, v)| v.trim());
+    let value = value.strip_prefix("- ").unwrap_or(value);
+    let mut chars = value.chars();
+    matches!(chars.next(), Some('|') | Some('>'))
+        && chars.all(|c| c.is_ascii_digit() || c == '+' || c == '-')
+}
+
+/// Line numbers (0-indexed) that belong to the body of a block scalar
+pub(crate) fn block_scalar_lines(lines: &[&str]) -> Vec<bool> {
+    let mut inside = vec![false; lines.len()];
+    let mut parent: Option<usize> = None;
+
+    for (i, line) in lines.iter().enumerate() {
+        let indent = indentation(line);
+        if let Some(p) = parent {
+            if line.trim().is_empty() || indent > p {
+                inside[i] = true;
+                continue;
+            }
+            parent = None;
+        }
+        if starts_block_scalar(line) {
+            parent = Some(indent);
+        }
+    }
+
+    inside
+}
+
+/// Find the line span (inclusive) of every step: a `- name:` sequence entry
+/// whose parent key is `steps:`
+pub(crate) fn step_spans(lines: &[&str]) -> Vec<(usize, usize)> {
+    let mut spans = Vec::new();
+
+    for (i, line) in lines.iter().enumerate() {
+        let trimmed = line.trim_start();
+        if !trimmed.starts_with("- ") || !is_named_entry(&trimmed[2..]) {
+            continue;
+        }
+        let dash = indentation(line);
+        if parent_key(lines, i) != Some("steps") {
+            continue;
+        }
+
+        let mut end = i;
+        for (j, next) in lines.iter().enumerate().skip(i + 1) {
+            let t = next.trim();
+            if t.is_empty() || t.starts_with('#') {
+                continue;
+            }
+            if indentation(next) <= dash {
+                break;
+            }
+            end = j;
+        }
+        spans.push((i, end));
+    }
+
+    spans
+}
+
+/// The line owning the sequence entry on line `i`: the nearest preceding line
+/// that is less indented, or equally indented but not itself an entry
+pub(crate) fn parent_line(lines: &[&str], i: usize) -> Option<usize> {
+    let dash = indentation(lines[i]);
+    lines[..i].iter().rposition(|l| {
+        let t = l.trim_start();
+        if t.is_empty() || t.starts_with('#') {
+            return false;
+        }
+        let indent = indentation(l);
+        indent < dash || (indent == dash && !t.starts_with('-'))
+    })
+}
+
+/// The key owning the sequence entry on line `i`
+pub(crate) fn parent_key<'a>(lines: &[&'a str], i: usize) -> Option<&'a str> {
+    let parent = lines[parent_line(lines, i)?];
+    strip_comment(parent.trim()).trim_end().strip_suffix(':')
+}
+
+/// The mapping keys leading to line `i`, outermost first, joined with `.`
+///
+/// Sequence entries don't add a segment, so the key of the `assign` in
+/// `main: {steps: [{init: {assign: ...}}]}` is `main.steps.init.assign`.
+pub(crate) fn key_path(lines: &[&str], i: usize) -> String {
+    let mut keys = Vec::new();
+    let mut limit = usize::MAX;
+    let mut below_entry = false;
+
+    for line in lines[..=i].iter().rev() {
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let indent = indentation(line);
+        let (entry, rest) = match trimmed.strip_prefix("- ") {
+            Some(rest) => (true, rest),
+            None => (false, trimmed),
+        };
+        // A key owns the lines indented past it; an unindented sequence may
+        // sit at the same indentation as its key
+        let key_indent = if entry { indent + 2 } else { indent };
+        let owns = key_indent < limit || (below_entry && !entry && indent == limit);
+        if !owns {
+            continue;
+        }
+        if let Some(key) = mapping_key(rest) {
+            keys.push(key);
+        }
+        limit = indent;
+        below_entry = entry;
+    }
+
+    keys.reverse();
+    keys.join(".")
+}
+
+/// The key of a `key: value` or `key:` line
+fn mapping_key(text: &str) -> Option<&str> {
+    let text = strip_comment(text).trim_end();
+    let end = text
+        .find(": ")
+        .or_else(|| text.strip_suffix(':').map(str::len))?;
+    let key = text[..end].trim().trim_matches(|c| c == '"' || c == '\'');
+    (!key.is_empty() && !key.starts_with(['{', '[', '-'])).then_some(key)
+}
+
+/// Whether the text after `- ` is a `name:` mapping key with no inline value
+fn is_named_entry(rest: &str) -> bool {
+    let rest = strip_comment(rest).trim_end();
+    match rest.strip_suffix(':') {
+        Some(name) => !name.is_empty() && !name.contains(' '),
+        None => false,
+    }
+}
+
+/// Strip a trailing `# comment` that is preceded by whitespace
+pub(crate) fn strip_comment(s: &str) -> &str {
+    match s.find(" #") {
+        Some(i) => &s[..i],
+        None => s,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_starts_block_scalar() {
+        assert!(starts_block_scalar("text: |"));
+        assert!(starts_block_scalar("text: >-"));
+        assert!(starts_block_scalar("- |2"));
+        assert!(!starts_block_scalar("text: a | b"));
+        assert!(!starts_block_scalar("text: value"));
+    }
+
+    #[test]
+    fn test_block_scalar_lines() {
+        let lines = ["a: |", "  one", "", "  two", "b: x"];
+        assert_eq!(
+            block_scalar_lines(&lines),
+            vec![false, true, true, true, false]
+        );
+    }
+
+    const DOC: &str = "main:\n  steps:\n    - first:\n        assign:\n          - x: 1\n    - second:\n        call: http.get\n        args:\n            url: https://example.com\n    - third:\n        return: x\n";
+
+    #[test]
+    fn test_key_path() {
+        let lines: Vec<&str> = DOC.split('\n').collect();
+        assert_eq!(key_path(&lines, 0), "main");
+        assert_eq!(key_path(&lines, 4), "main.steps.first.assign.x");
+        assert_eq!(key_path(&lines, 8), "main.steps.second.args.url");
+        assert_eq!(key_path(&lines, 10), "main.steps.third.return");
+
+        let flush = ["main:", "  steps:", "  - only:", "      return: 1"];
+        assert_eq!(key_path(&flush, 3), "main.steps.only.return");
+    }
+
+    #[test]
+    fn test_step_spans() {
+        let lines: Vec<&str> = DOC.split('\n').collect();
+        assert_eq!(step_spans(&lines), vec![(2, 4), (5, 8), (9, 10)]);
+    }
+}
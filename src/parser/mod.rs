@@ -1,8 +1,20 @@
 //! Parser module for YAML and expression handling
 
+pub mod anchors;
+pub mod directives;
 pub(crate) mod expressions;
+pub(crate) mod lines;
+pub mod outline;
 mod preprocessor;
+pub mod spanned;
+pub(crate) mod tokens;
+pub mod wf_expr;
 mod yaml;
 
+pub use anchors::{scan_anchors, AnchorIndex};
+pub use directives::{scan_directives, without_unparsed_directives, Directives};
+pub use expressions::{ExpressionKind, ExpressionMap, UnclosedExpression};
+pub use outline::Outline;
 pub use preprocessor::preprocess_expressions;
+pub use spanned::{SpannedEntry, SpannedNode, SpannedValue};
 pub use yaml::parse_yaml;
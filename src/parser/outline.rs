@@ -0,0 +1,313 @@
+//! Workflow outline
+//!
+//! A lightweight, text-based view of a document's workflow definitions, their
+//! steps, and the `call:` sites between them. Because it only scans lines it
+//! keeps working while the YAML is temporarily broken during editing.
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use super::lines::{indentation, step_spans, strip_comment};
+
+/// A top-level workflow definition (`main` or a subworkflow)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkflowDef {
+    /// Workflow name (the top-level key)
+    pub name: String,
+    /// Line of the top-level key (0-indexed)
+    pub line: u32,
+    /// Last line belonging to the definition
+    pub end_line: u32,
+    /// Direct steps of the workflow's `steps:` list, in order
+    pub steps: Vec<StepDef>,
+}
+
+/// A named step
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StepDef {
+    /// Step name
+    pub name: String,
+    /// Line of the `- name:` entry (0-indexed)
+    pub line: u32,
+    /// Column where the name starts
+    pub column: u32,
+    /// Last line belonging to the step
+    pub end_line: u32,
+}
+
+/// A `call: target` site
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallSite {
+    /// The called workflow or function (e.g. `helper`, `http.get`)
+    pub target: String,
+    /// Line of the `call:` key (0-indexed)
+    pub line: u32,
+    /// Column where the target starts
+    pub column: u32,
+    /// Column just past the target
+    pub end_column: u32,
+    /// Name of the workflow containing the call
+    pub caller: Option<String>,
+}
+
+/// A `next: target` jump
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Jump {
+    /// The target step name (or `end`, `break`, `continue`)
+    pub target: String,
+    /// Line of the `next:` key (0-indexed)
+    pub line: u32,
+    /// Column where the target starts
+    pub column: u32,
+    /// Column just past the target
+    pub end_column: u32,
+}
+
+/// Workflow definitions, steps, call sites and jumps found in a document
+#[derive(Debug, Default, Clone)]
+pub struct Outline {
+    pub workflows: Vec<WorkflowDef>,
+    /// Every step in the document, including nested ones, in document order
+    pub steps: Vec<StepDef>,
+    pub calls: Vec<CallSite>,
+    pub jumps: Vec<Jump>,
+}
+
+impl Outline {
+    /// Scan a document for workflow definitions and call sites
+    pub fn parse(text: &str) -> Self {
+        let lines: Vec<&str> = text
+            .split('\n')
+            .map(|l| l.strip_suffix('\r').unwrap_or(l))
+            .collect();
+        let spans = step_spans(&lines);
+        let mut outline = Outline::default();
+
+        for (i, line) in lines.iter().enumerate() {
+            let Some(name) = top_level_key(line) else {
+                continue;
+            };
+            let end = block_end(&lines, i);
+            let body = &lines[i + 1..=end];
+            let child_indent = body
+                .iter()
+                .filter(|l| !l.trim().is_empty() && !l.trim_start().starts_with('#'))
+                .map(|l| indentation(l))
+                .next();
+            let has_workflow_keys = child_indent.is_some_and(|indent| {
+                body.iter().any(|l| {
+                    indentation(l) == indent
+                        && matches!(
+                            strip_comment(l.trim()).split(':').next(),
+                            Some("steps") | Some("params")
+                        )
+                })
+            });
+            if name != "main" && !has_workflow_keys {
+                continue;
+            }
+
+            let nested: Vec<(usize, usize)> = spans
+                .iter()
+                .copied()
+                .filter(|&(s, _)| s > i && s <= end)
+                .collect();
+            let direct_indent = nested.iter().map(|&(s, _)| indentation(lines[s])).min();
+            let steps = nested
+                .iter()
+                .filter(|&&(s, _)| Some(indentation(lines[s])) == direct_indent)
+                .map(|&(s, e)| step_def(lines[s], s, e))
+                .collect();
+
+            outline.workflows.push(WorkflowDef {
+                name: name.to_string(),
+                line: i as u32,
+                end_line: end as u32,
+                steps,
+            });
+        }
+
+        outline.steps = spans
+            .iter()
+            .map(|&(s, e)| step_def(lines[s], s, e))
+            .collect();
+
+        for (i, line) in lines.iter().enumerate() {
+            if let Some(caps) = NEXT_RE.captures(line) {
+                let target = caps.get(1).unwrap();
+                outline.jumps.push(Jump {
+                    target: target.as_str().to_string(),
+                    line: i as u32,
+                    column: line[..target.start()].chars().count() as u32,
+                    end_column: line[..target.end()].chars().count() as u32,
+                });
+            }
+            if let Some(caps) = CALL_RE.captures(line) {
+                let target = caps.get(1).unwrap();
+                let line_no = i as u32;
+                outline.calls.push(CallSite {
+                    target: target.as_str().to_string(),
+                    line: line_no,
+                    column: line[..target.start()].chars().count() as u32,
+                    end_column: line[..target.end()].chars().count() as u32,
+                    caller: outline
+                        .workflows
+                        .iter()
+                        .find(|w| w.line < line_no && line_no <= w.end_line)
+                        .map(|w| w.name.clone()),
+                });
+            }
+        }
+
+        outline
+    }
+
+    /// Find a workflow definition by name
+    pub fn workflow(&self, name: &str) -> Option<&WorkflowDef> {
+        self.workflows.iter().find(|w| w.name == name)
+    }
+
+    /// The workflow definition containing a line
+    pub fn workflow_at(&self, line: u32) -> Option<&WorkflowDef> {
+        self.workflows
+            .iter()
+            .find(|w| w.line <= line && line <= w.end_line)
+    }
+
+    /// All call sites targeting the given workflow
+    pub fn calls_to<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a CallSite> + 'a {
+        self.calls.iter().filter(move |c| c.target == name)
+    }
+}
+
+lazy_static! {
+    static ref CALL_RE: Regex =
+        Regex::new(r"^\s*(?:-\s+)?call:\s*([A-Za-z_][\w.]*)\s*(?:#.*)?$").unwrap();
+    static ref NEXT_RE: Regex =
+        Regex::new(r"^\s*(?:-\s+)?next:\s*([A-Za-z_][\w-]*)\s*(?:#.*)?$").unwrap();
+}
+
+/// The key of a top-level `name:` line with no inline value
+fn top_level_key(line: &str) -> Option<&str> {
+    if line.starts_with([' ', '\t', '#', '-']) {
+        return None;
+    }
+    let key = strip_comment(line).trim_end().strip_suffix(':')?;
+    (!key.is_empty() && !key.contains([' ', ':'])).then_some(key)
+}
+
+/// Last line of the block that starts at `start`: every following line that
+/// is blank or more indented
+fn block_end(lines: &[&str], start: usize) -> usize {
+    let indent = indentation(lines[start]);
+    let mut end = start;
+    for (j, line) in lines.iter().enumerate().skip(start + 1) {
+        let t = line.trim();
+        if t.is_empty() || t.starts_with('#') {
+            continue;
+        }
+        if indentation(line) <= indent {
+            break;
+        }
+        end = j;
+    }
+    end
+}
+
+fn step_def(line: &str, start: usize, end: usize) -> StepDef {
+    let dash = indentation(line);
+    let after_dash = &line[dash + 1..];
+    let name_start = dash + 1 + (after_dash.len() - after_dash.trim_start().len());
+    let name = strip_comment(&line[name_start..])
+        .trim_end()
+        .trim_end_matches(':')
+        .to_string();
+    StepDef {
+        name,
+        line: start as u32,
+        column: line[..name_start].chars().count() as u32,
+        end_line: end as u32,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOC: &str = r#"main:
+  steps:
+    - first:
+        call: helper
+        args:
+          x: 1
+    - loop:
+        for:
+          value: v
+          in: [1, 2]
+          steps:
+            - inner:
+                call: helper
+    - third:
+        call: http.get
+helper:
+  params: [x]
+  steps:
+    - done:
+        return: x
+unused:
+  steps:
+    - a:
+        return: 1
+metadata:
+  owner: team
+"#;
+
+    #[test]
+    fn test_workflow_definitions() {
+        let outline = Outline::parse(DOC);
+        let names: Vec<&str> = outline.workflows.iter().map(|w| w.name.as_str()).collect();
+        assert_eq!(names, vec!["main", "helper", "unused"]);
+
+        let main = outline.workflow("main").unwrap();
+        assert_eq!(main.line, 0);
+        assert_eq!(main.end_line, 14);
+        let steps: Vec<&str> = main.steps.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(steps, vec!["first", "loop", "third"]);
+        assert_eq!(main.steps[0].column, 6);
+    }
+
+    #[test]
+    fn test_call_sites() {
+        let outline = Outline::parse(DOC);
+        assert_eq!(outline.calls.len(), 3);
+        assert_eq!(outline.calls_to("helper").count(), 2);
+        assert_eq!(outline.calls_to("unused").count(), 0);
+
+        let call = &outline.calls[0];
+        assert_eq!(call.line, 3);
+        assert_eq!(call.column, 14);
+        assert_eq!(call.end_column, 20);
+        assert_eq!(call.caller.as_deref(), Some("main"));
+    }
+
+    #[test]
+    fn test_all_steps_and_jumps() {
+        let outline = Outline::parse(DOC);
+        let steps: Vec<&str> = outline.steps.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(steps, vec!["first", "loop", "inner", "third", "done", "a"]);
+
+        let outline = Outline::parse("main:\n  steps:\n    - a:\n        next: b_2 # jump\n");
+        assert_eq!(outline.jumps.len(), 1);
+        assert_eq!(outline.jumps[0].target, "b_2");
+        assert_eq!(outline.jumps[0].column, 14);
+        assert_eq!(outline.workflow_at(3).unwrap().name, "main");
+    }
+
+    #[test]
+    fn test_broken_yaml_still_outlines() {
+        let text = "main:\n  steps:\n    - a:\n        call: helper\n        args: {\n";
+        let outline = Outline::parse(text);
+        assert_eq!(outline.workflows.len(), 1);
+        assert_eq!(outline.calls.len(), 1);
+    }
+}
@@ -1,12 +1,16 @@
 //! Expression placeholder substitution
 //!
 //! This module handles replacing Terraform ${...} and Workflows $${...}
-//! expressions with valid YAML placeholders before parsing.
+//! expressions with valid YAML placeholders before parsing. Template
+//! directives such as %{ if ... } are replaced too; a line holding nothing
+//! else becomes a comment, since the directive renders to no YAML of its own.
+//! Strip markers (`${~ ... ~}`, `%{~ ... ~}`) are part of the delimiters.
 //!
 //! Uses a custom brace-matching algorithm to properly handle deeply nested
 //! expressions like ${jsonencode({a: {b: {c: "value"}}})}
 
-use super::expressions::{Expression, ExpressionKind, ExpressionMap};
+use super::expressions::{Expression, ExpressionKind, ExpressionMap, UnclosedExpression};
+use crate::document::LineOffsets;
 
 /// Represents a match found by the expression scanner
 #[derive(Debug, Clone)]
@@ -17,55 +21,61 @@ struct ExpressionMatch {
     kind: ExpressionKind,
 }
 
-/// Scan text for Terraform ${...} and Workflows $${...} expressions
-/// using proper brace matching to handle arbitrary nesting depth.
-fn scan_expressions(text: &str) -> Vec<ExpressionMatch> {
+/// Scan text for Terraform ${...} and Workflows $${...} expressions and
+/// %{...} directives using proper brace matching to handle arbitrary nesting
+/// depth. `%%{`, like `$${`, is an escape and not a directive; a `$$` or
+/// `%%` not followed by `{` is plain text.
+///
+/// Also returns the openers that have no matching closing brace.
+fn scan_expressions(text: &str) -> (Vec<ExpressionMatch>, Vec<(usize, ExpressionKind)>) {
     let mut matches = Vec::new();
+    let mut unclosed = Vec::new();
     let bytes = text.as_bytes();
     let len = bytes.len();
     let mut i = 0;
 
     while i < len {
-        // Check for $${...} (Workflows) first - more specific pattern
-        if i + 2 < len && bytes[i] == b'$' && bytes[i + 1] == b'$' && bytes[i + 2] == b'{' {
-            if let Some(end) = find_matching_brace(text, i + 2) {
-                matches.push(ExpressionMatch {
-                    start: i,
-                    end,
-                    text: text[i..end].to_string(),
-                    kind: ExpressionKind::Workflows,
-                });
-                i = end;
-                continue;
-            }
-        }
-        // Check for ${...} (Terraform) - but not if preceded by another $
-        else if i + 1 < len && bytes[i] == b'$' && bytes[i + 1] == b'{' {
-            // Make sure this isn't part of a $${
-            if i > 0 && bytes[i - 1] == b'$' {
-                i += 1;
-                continue;
-            }
-            if let Some(end) = find_matching_brace(text, i + 1) {
-                matches.push(ExpressionMatch {
-                    start: i,
-                    end,
-                    text: text[i..end].to_string(),
-                    kind: ExpressionKind::Terraform,
-                });
-                i = end;
-                continue;
+        let rest = &bytes[i..];
+        let escaped = |c| i > 0 && bytes[i - 1] == c;
+        // $${...} (Workflows) first - more specific pattern; then ${...}
+        // (Terraform) and %{...} (directive), but not the second half of a
+        // $${ or a %%{ escape
+        let opener = if rest.starts_with(b"$${") {
+            Some((ExpressionKind::Workflows, i + 2))
+        } else if rest.starts_with(b"${") && !escaped(b'$') {
+            Some((ExpressionKind::Terraform, i + 1))
+        } else if rest.starts_with(b"%{") && !escaped(b'%') {
+            Some((ExpressionKind::Directive, i + 1))
+        } else {
+            None
+        };
+
+        if let Some((kind, brace)) = opener {
+            match find_matching_brace(text, brace) {
+                Some(end) => {
+                    matches.push(ExpressionMatch {
+                        start: i,
+                        end,
+                        text: text[i..end].to_string(),
+                        kind,
+                    });
+                    i = end;
+                    continue;
+                }
+                None => unclosed.push((i, kind)),
             }
         }
         i += 1;
     }
 
-    matches
+    (matches, unclosed)
 }
 
 /// Find the matching closing brace for an opening brace at position `open_pos`.
 /// Returns the end position (exclusive).
 /// Handles nested braces, string literals (with escaped quotes), and multi-line content.
+/// Inside a double-quoted YAML scalar the literal's own quotes are written `\"`,
+/// so a `\"` outside a literal opens one that the next `\"` closes.
 fn find_matching_brace(text: &str, open_pos: usize) -> Option<usize> {
     let bytes = text.as_bytes();
     if bytes.get(open_pos) != Some(&b'{') {
@@ -77,9 +87,7 @@ fn find_matching_brace(text: &str, open_pos: usize) -> Option<usize> {
     let len = bytes.len();
 
     while i < len {
-        let ch = bytes[i];
-
-        match ch {
+        match bytes[i] {
             b'{' => depth += 1,
             b'}' => {
                 depth -= 1;
@@ -87,34 +95,9 @@ fn find_matching_brace(text: &str, open_pos: usize) -> Option<usize> {
                     return Some(i + 1);
                 }
             }
-            // Handle double-quoted strings - skip their contents
-            b'"' => {
-                i += 1;
-                while i < len {
-                    match bytes[i] {
-                        b'\\' => i += 2, // Skip escaped character
-                        b'"' => break,
-                        _ => i += 1,
-                    }
-                    if i >= len {
-                        break;
-                    }
-                }
-            }
-            // Handle single-quoted strings - skip their contents
-            b'\'' => {
-                i += 1;
-                while i < len {
-                    match bytes[i] {
-                        b'\\' => i += 2, // Skip escaped character
-                        b'\'' => break,
-                        _ => i += 1,
-                    }
-                    if i >= len {
-                        break;
-                    }
-                }
-            }
+            // Handle quoted strings - skip their contents
+            b'"' | b'\'' => i = literal_end(bytes, i + 1, Some(bytes[i])),
+            b'\\' if bytes.get(i + 1) == Some(&b'"') => i = literal_end(bytes, i + 2, None),
             _ => {}
         }
         i += 1;
@@ -124,14 +107,49 @@ fn find_matching_brace(text: &str, open_pos: usize) -> Option<usize> {
     None
 }
 
+/// Position of the closing quote of a string literal whose contents start at
+/// `i`: `quote`, or a YAML-escaped `\"` (its last byte) when `quote` is None.
+/// Returns at least `bytes.len()` when the literal is never closed.
+fn literal_end(bytes: &[u8], mut i: usize, quote: Option<u8>) -> usize {
+    while i < bytes.len() {
+        match (bytes[i], quote) {
+            (b'\\', None) if bytes.get(i + 1) == Some(&b'"') => return i + 1,
+            // `\\` is a YAML-escaped backslash, which escapes what follows
+            // in the literal, itself possibly YAML-escaped
+            (b'\\', None) if bytes.get(i + 1) == Some(&b'\\') => {
+                i += 2;
+                i += if bytes.get(i) == Some(&b'\\') { 2 } else { 1 };
+            }
+            (b'\\', _) => i += 2, // Skip escaped character
+            (c, Some(q)) if c == q => return i,
+            _ => i += 1,
+        }
+    }
+    i
+}
+
 /// Preprocess a document by replacing expressions with placeholders
 ///
 /// Returns the preprocessed text and a map of expressions for position adjustment.
 pub fn preprocess_expressions(text: &str) -> (String, ExpressionMap) {
     let mut expression_map = ExpressionMap::new();
+    let lines = LineOffsets::new(text);
+    let line_col = |offset| {
+        let position = lines.offset_to_position(text, offset);
+        (position.line, position.character)
+    };
 
     // Scan for all expressions using our brace-matching algorithm
-    let matches = scan_expressions(text);
+    let (matches, unclosed) = scan_expressions(text);
+    for (start, kind) in unclosed {
+        let (line, column) = line_col(start);
+        expression_map.unclosed.push(UnclosedExpression {
+            start,
+            line,
+            column,
+            kind,
+        });
+    }
 
     if matches.is_empty() {
         return (text.to_string(), expression_map);
@@ -143,10 +161,15 @@ pub fn preprocess_expressions(text: &str) -> (String, ExpressionMap) {
 
     // Process matches in reverse order to preserve positions
     for (counter, mat) in matches.iter().rev().enumerate() {
-        let placeholder = format!("__EXPR_{:03}__", counter);
+        let placeholder =
+            if mat.kind == ExpressionKind::Directive && stands_alone(text, &matches, mat) {
+                format!("#__EXPR_{:03}__", counter)
+            } else {
+                format!("__EXPR_{:03}__", counter)
+            };
 
-        let (start_line, start_column) = offset_to_line_col(text, mat.start);
-        let (end_line, end_column) = offset_to_line_col(text, mat.end);
+        let (start_line, start_column) = line_col(mat.start);
+        let (end_line, end_column) = line_col(mat.end);
 
         expression_map.add(Expression {
             original: mat.text.clone(),
@@ -169,24 +192,26 @@ pub fn preprocess_expressions(text: &str) -> (String, ExpressionMap) {
     (result, expression_map)
 }
 
-/// Convert a byte offset to (line, column) coordinates
-fn offset_to_line_col(text: &str, offset: usize) -> (u32, u32) {
-    let mut line = 0u32;
-    let mut col = 0u32;
-
-    for (i, ch) in text.char_indices() {
-        if i >= offset {
-            break;
-        }
-        if ch == '\n' {
-            line += 1;
-            col = 0;
-        } else {
-            col += 1;
+/// Whether a directive shares its line(s) with nothing but whitespace and
+/// other directives
+fn stands_alone(text: &str, matches: &[ExpressionMatch], mat: &ExpressionMatch) -> bool {
+    let line_start = text[..mat.start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = text[mat.end..]
+        .find('\n')
+        .map_or(text.len(), |i| mat.end + i);
+    let mut pos = line_start;
+    for other in matches
+        .iter()
+        .filter(|m| m.end > line_start && m.start < line_end)
+    {
+        if other.kind != ExpressionKind::Directive
+            || !text[pos..other.start.max(pos)].trim().is_empty()
+        {
+            return false;
         }
+        pos = other.end;
     }
-
-    (line, col)
+    text[pos.min(line_end)..line_end].trim().is_empty()
 }
 
 #[cfg(test)]
@@ -235,12 +260,14 @@ mod tests {
     }
 
     #[test]
-    fn test_offset_to_line_col() {
-        let text = "line1\nline2\nline3";
-        assert_eq!(offset_to_line_col(text, 0), (0, 0));
-        assert_eq!(offset_to_line_col(text, 5), (0, 5));
-        assert_eq!(offset_to_line_col(text, 6), (1, 0));
-        assert_eq!(offset_to_line_col(text, 10), (1, 4));
+    fn test_crlf_expression_positions() {
+        let (_, map) = preprocess_expressions("a: 1\r\nb: ${x}\r\nc: ${\r\ny}\n");
+        let positions: Vec<_> = map
+            .expressions
+            .iter()
+            .map(|e| (e.start_line, e.start_column, e.end_line, e.end_column))
+            .collect();
+        assert_eq!(positions, vec![(1, 3, 1, 7), (2, 3, 3, 2)]);
     }
 
     // === Edge case tests for Phase 2 ===
@@ -361,6 +388,79 @@ value: $${data.get("key")}"#;
 
         // Should not find any complete expressions
         assert_eq!(map.expressions.len(), 0);
+        assert_eq!(map.unclosed.len(), 1);
+        assert_eq!(map.unclosed[0].column, 7);
+        assert_eq!(map.unclosed[0].kind, ExpressionKind::Terraform);
+    }
+
+    #[test]
+    fn test_unclosed_openers() {
+        let input = "a: $${x\nb: ${y}\n%{ if z\nc: ${\"}\"";
+        let (_, map) = preprocess_expressions(input);
+        assert_eq!(map.expressions.len(), 1);
+        let unclosed: Vec<_> = map
+            .unclosed
+            .iter()
+            .map(|u| (u.line, u.column, u.kind))
+            .collect();
+        assert_eq!(
+            unclosed,
+            vec![
+                (0, 3, ExpressionKind::Workflows),
+                (2, 0, ExpressionKind::Directive),
+                (3, 3, ExpressionKind::Terraform),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_strip_markers() {
+        let input = "a: ${~ var.name ~}\nb: $${~x}";
+        let (result, map) = preprocess_expressions(input);
+        assert_eq!(result, "a: __EXPR_001__\nb: __EXPR_000__");
+        assert_eq!(map.expressions[0].original, "${~ var.name ~}");
+        assert_eq!(map.expressions[1].kind, ExpressionKind::Workflows);
+    }
+
+    #[test]
+    fn test_directives() {
+        let input = r#"steps:
+%{~ for name in names ~}
+  - ${name}:
+      return: %{ if debug }1%{ else }0%{ endif }
+%{ endfor }%{if x}
+notes: 100%%{ literal }
+"#;
+        let (result, map) = preprocess_expressions(input);
+        assert_eq!(
+            result,
+            r#"steps:
+#__EXPR_006__
+  - __EXPR_005__:
+      return: __EXPR_004__1__EXPR_003__0__EXPR_002__
+#__EXPR_001__#__EXPR_000__
+notes: 100%%{ literal }
+"#
+        );
+        let directives: Vec<_> = map
+            .expressions
+            .iter()
+            .filter(|e| e.kind == ExpressionKind::Directive)
+            .map(|e| e.original.as_str())
+            .collect();
+        assert_eq!(
+            directives,
+            vec![
+                "%{~ for name in names ~}",
+                "%{ if debug }",
+                "%{ else }",
+                "%{ endif }",
+                "%{ endfor }",
+                "%{if x}"
+            ]
+        );
+        let value: serde_yaml::Value = serde_yaml::from_str(&result).unwrap();
+        assert!(value["steps"].is_sequence());
     }
 
     #[test]
@@ -439,5 +539,8 @@ value: $${data.get("key")}"#;
         // Braces inside strings should be ignored
         assert_eq!(find_matching_brace(r#"{"a": "}"}"#, 0), Some(10));
         assert_eq!(find_matching_brace(r#"{"\""}"#, 0), Some(6));
+        // Quotes escaped for a double-quoted YAML scalar
+        assert_eq!(find_matching_brace(r#"{\"a\"}"#, 0), Some(7));
+        assert_eq!(find_matching_brace(r#"{\"}\\\"\"}"#, 0), Some(11));
     }
 }
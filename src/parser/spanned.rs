@@ -0,0 +1,295 @@
+//! YAML values that remember where they were written
+//!
+//! serde_yaml's `Value` carries no positions, so validators working on it
+//! have to search the text for a key to report on, which goes wrong as soon
+//! as a key name appears on more than one line. `SpannedValue` is built from
+//! libyaml's event stream (the same parser serde_yaml runs) and records the
+//! range of every key and value, so a node of the parsed value can be paired
+//! with the exact place it came from.
+//!
+//! Aliases are kept as written rather than expanded, and `<<` merge keys are
+//! not applied: positions only exist for what is in the text.
+
+use std::mem::MaybeUninit;
+
+use tower_lsp::lsp_types::{Position, Range};
+use unsafe_libyaml::{
+    yaml_event_delete, yaml_event_t, yaml_mark_t, yaml_parser_delete, yaml_parser_initialize,
+    yaml_parser_parse, yaml_parser_set_encoding, yaml_parser_set_input_string, yaml_parser_t,
+    YAML_ALIAS_EVENT, YAML_DOCUMENT_START_EVENT, YAML_MAPPING_END_EVENT, YAML_MAPPING_START_EVENT,
+    YAML_SCALAR_EVENT, YAML_SEQUENCE_END_EVENT, YAML_SEQUENCE_START_EVENT, YAML_STREAM_END_EVENT,
+    YAML_STREAM_START_EVENT, YAML_UTF8_ENCODING,
+};
+
+/// A YAML node with its range in the parsed text
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SpannedValue {
+    /// From the node's first character to just past its last
+    pub range: Range,
+    pub node: SpannedNode,
+}
+
+/// The shape of a [`SpannedValue`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SpannedNode {
+    /// Any scalar, with its text as parsed (quotes and escapes resolved)
+    Scalar(String),
+    /// A `*name` alias
+    Alias(String),
+    Sequence(Vec<SpannedValue>),
+    Mapping(Vec<SpannedEntry>),
+}
+
+impl Default for SpannedNode {
+    fn default() -> Self {
+        SpannedNode::Scalar(String::new())
+    }
+}
+
+/// A `key: value` pair of a mapping
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpannedEntry {
+    pub key: SpannedValue,
+    pub value: SpannedValue,
+}
+
+impl SpannedValue {
+    /// Parse the first document of `text`, or `None` when it isn't valid YAML
+    pub fn parse(text: &str) -> Option<SpannedValue> {
+        let mut events = parse_events(text)?.into_iter();
+        // Skip the stream and document starts
+        let (first, start) = events.find(|(event, _)| !matches!(event, Event::Start))?;
+        build(first, start, &mut events)
+    }
+
+    /// The scalar's text, if this is a scalar
+    pub fn as_str(&self) -> Option<&str> {
+        match &self.node {
+            SpannedNode::Scalar(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// The entries of a mapping, empty for any other node
+    pub fn entries(&self) -> &[SpannedEntry] {
+        match &self.node {
+            SpannedNode::Mapping(entries) => entries,
+            _ => &[],
+        }
+    }
+
+    /// The items of a sequence, empty for any other node
+    pub fn items(&self) -> &[SpannedValue] {
+        match &self.node {
+            SpannedNode::Sequence(items) => items,
+            _ => &[],
+        }
+    }
+
+    /// The entry of a mapping whose key is the scalar `key`
+    pub fn entry(&self, key: &str) -> Option<&SpannedEntry> {
+        self.entries()
+            .iter()
+            .find(|entry| entry.key.as_str() == Some(key))
+    }
+
+    /// The value at `key`, or this node itself when the key isn't written
+    /// here (it came from a `<<` merge, or this node is an alias), so callers
+    /// can keep descending and report at the closest written position
+    pub fn at_key(&self, key: &str) -> &SpannedValue {
+        self.entry(key).map_or(self, |entry| &entry.value)
+    }
+
+    /// The item at `index`, or this node itself, like [`Self::at_key`]
+    pub fn at_index(&self, index: usize) -> &SpannedValue {
+        self.items().get(index).unwrap_or(self)
+    }
+
+    /// Range of the `key` token, or [`Self::head`] when it isn't written here
+    pub fn key_range(&self, key: &str) -> Range {
+        self.entry(key)
+            .map_or_else(|| self.head(), |entry| entry.key.range)
+    }
+
+    /// The node's first character, a short range to report a whole node on
+    pub fn head(&self) -> Range {
+        let start = self.range.start;
+        Range::new(start, Position::new(start.line, start.character + 1))
+    }
+}
+
+/// The events a [`SpannedValue`] is built from
+enum Event {
+    /// Stream or document start
+    Start,
+    /// Stream or document end
+    End,
+    Scalar(String),
+    Alias(String),
+    SequenceStart,
+    MappingStart,
+    /// End of a sequence or mapping
+    CollectionEnd,
+}
+
+/// Build the node that starts with `first`, consuming its events
+fn build(
+    first: Event,
+    range: Range,
+    events: &mut impl Iterator<Item = (Event, Range)>,
+) -> Option<SpannedValue> {
+    let spanned = |node, end: Range| SpannedValue {
+        range: Range::new(range.start, end.end),
+        node,
+    };
+    Some(match first {
+        Event::Scalar(s) => spanned(SpannedNode::Scalar(s), range),
+        Event::Alias(name) => spanned(SpannedNode::Alias(name), range),
+        Event::SequenceStart => {
+            let mut items = Vec::new();
+            loop {
+                match events.next()? {
+                    (Event::CollectionEnd, end) => {
+                        break spanned(SpannedNode::Sequence(items), end)
+                    }
+                    (event, at) => items.push(build(event, at, events)?),
+                }
+            }
+        }
+        Event::MappingStart => {
+            let mut entries = Vec::new();
+            loop {
+                match events.next()? {
+                    (Event::CollectionEnd, end) => {
+                        break spanned(SpannedNode::Mapping(entries), end)
+                    }
+                    (event, at) => {
+                        let key = build(event, at, events)?;
+                        let (event, at) = events.next()?;
+                        let value = build(event, at, events)?;
+                        entries.push(SpannedEntry { key, value });
+                    }
+                }
+            }
+        }
+        Event::Start | Event::End | Event::CollectionEnd => return None,
+    })
+}
+
+/// Run libyaml over `text`, collecting its events up to the end of the
+/// stream with their ranges, or `None` on a syntax error
+fn parse_events(text: &str) -> Option<Vec<(Event, Range)>> {
+    // SAFETY: the parser is heap-allocated so it doesn't move while libyaml
+    // holds pointers into it, `text` outlives it, each event is converted
+    // before it's deleted, and the parser is deleted before returning
+    unsafe {
+        let mut parser = Box::new(MaybeUninit::<yaml_parser_t>::uninit());
+        let parser = parser.as_mut_ptr();
+        if yaml_parser_initialize(parser).fail {
+            return None;
+        }
+        yaml_parser_set_encoding(parser, YAML_UTF8_ENCODING);
+        yaml_parser_set_input_string(parser, text.as_ptr(), text.len() as u64);
+
+        let mut events = Vec::new();
+        let complete = loop {
+            let mut event = MaybeUninit::<yaml_event_t>::uninit();
+            if yaml_parser_parse(parser, event.as_mut_ptr()).fail {
+                break false;
+            }
+            let event = event.as_mut_ptr();
+            let range = Range::new(mark(&(*event).start_mark), mark(&(*event).end_mark));
+            let converted = convert(&*event);
+            let stream_end = (*event).type_ == YAML_STREAM_END_EVENT;
+            yaml_event_delete(event);
+            events.push((converted, range));
+            if stream_end {
+                break true;
+            }
+        };
+        yaml_parser_delete(parser);
+        complete.then_some(events)
+    }
+}
+
+fn mark(mark: &yaml_mark_t) -> Position {
+    Position::new(mark.line as u32, mark.column as u32)
+}
+
+/// Convert a libyaml event, copying out the text it points to
+///
+/// # Safety
+/// `event` must be a live event returned by `yaml_parser_parse`.
+unsafe fn convert(event: &yaml_event_t) -> Event {
+    let text = |ptr: *const u8, len: usize| {
+        String::from_utf8_lossy(std::slice::from_raw_parts(ptr, len)).into_owned()
+    };
+    match event.type_ {
+        YAML_SCALAR_EVENT => {
+            let scalar = event.data.scalar;
+            Event::Scalar(text(scalar.value, scalar.length as usize))
+        }
+        YAML_ALIAS_EVENT => {
+            let anchor = event.data.alias.anchor;
+            let len = (0..).take_while(|&i| *anchor.add(i) != 0).count();
+            Event::Alias(text(anchor, len))
+        }
+        YAML_SEQUENCE_START_EVENT => Event::SequenceStart,
+        YAML_MAPPING_START_EVENT => Event::MappingStart,
+        YAML_SEQUENCE_END_EVENT | YAML_MAPPING_END_EVENT => Event::CollectionEnd,
+        YAML_STREAM_START_EVENT | YAML_DOCUMENT_START_EVENT => Event::Start,
+        _ => Event::End,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(start: (u32, u32), end: (u32, u32)) -> Range {
+        Range::new(Position::new(start.0, start.1), Position::new(end.0, end.1))
+    }
+
+    #[test]
+    fn test_key_and_value_ranges() {
+        let root = SpannedValue::parse("a:\n  b: 'x y'\n  c: [1, é, 3]\n").unwrap();
+        let a = root.at_key("a");
+        assert_eq!(root.key_range("a"), range((0, 0), (0, 1)));
+        assert_eq!(a.key_range("b"), range((1, 2), (1, 3)));
+        assert_eq!(a.at_key("b").as_str(), Some("x y"));
+        assert_eq!(a.at_key("b").range, range((1, 5), (1, 10)));
+        let c = a.at_key("c");
+        assert_eq!(c.range, range((2, 5), (2, 14)));
+        assert_eq!(c.at_index(2).range, range((2, 12), (2, 13)));
+    }
+
+    #[test]
+    fn test_repeated_key_names_keep_their_own_lines() {
+        let text = "main:\n  steps:\n    - init:\n        next: done\nsub:\n  steps:\n    - init:\n        next: end\n";
+        let root = SpannedValue::parse(text).unwrap();
+        let step = |workflow: &str| {
+            root.at_key(workflow)
+                .at_key("steps")
+                .at_index(0)
+                .at_key("init")
+                .key_range("next")
+        };
+        assert_eq!(step("main").start.line, 3);
+        assert_eq!(step("sub").start.line, 7);
+    }
+
+    #[test]
+    fn test_unwritten_keys_fall_back_to_the_node() {
+        let root = SpannedValue::parse("base: &b\n  x: 1\nuse:\n  <<: *b\n  y: 2\n").unwrap();
+        let used = root.at_key("use");
+        assert_eq!(used.at_key("x"), used);
+        assert_eq!(used.key_range("x"), range((3, 2), (3, 3)));
+        assert_eq!(used.at_key("<<").node, SpannedNode::Alias("b".to_string()));
+    }
+
+    #[test]
+    fn test_invalid_yaml() {
+        assert_eq!(SpannedValue::parse("a: [1\nb: 2"), None);
+        assert_eq!(SpannedValue::parse(""), None);
+    }
+}
@@ -0,0 +1,171 @@
+//! Tokenizer for the contents of `${...}` and `$${...}` expressions
+//!
+//! Covers the shared subset of Terraform and Workflows expression syntax:
+//! names, numbers, quoted strings, operators and punctuation. Whitespace is
+//! dropped. Input the tokenizer doesn't understand, such as heredocs or
+//! strings with nested interpolation, is rejected rather than guessed at.
+
+/// What a token is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TokenKind {
+    /// A name or keyword (`var`, `map`, `in`, `not`, ...)
+    Name,
+    Number,
+    /// A quoted string, quotes included
+    String,
+    /// An operator that can join two operands (`+`, `==`, `&&`, `=`, `=>`, ...)
+    Operator,
+    /// `!`, only ever a prefix
+    Bang,
+    /// `(`, `[` or `{`
+    Open,
+    /// `)`, `]` or `}`
+    Close,
+    Comma,
+    Dot,
+    Colon,
+    Question,
+    /// `...`, expanding a function argument
+    Ellipsis,
+}
+
+/// One token and its text
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Token<'a> {
+    pub kind: TokenKind,
+    pub text: &'a str,
+}
+
+/// Operators, longest first so `==` isn't read as `=` twice
+const OPERATORS: &[&str] = &[
+    "==", "!=", "<=", ">=", "&&", "||", "=>", "+", "-", "*", "/", "%", "<", ">", "=",
+];
+
+/// Split an expression body into tokens, or `None` if it contains something
+/// the tokenizer can't represent faithfully
+pub(crate) fn tokenize(source: &str) -> Option<Vec<Token<'_>>> {
+    let mut tokens = Vec::new();
+    let mut rest = source;
+
+    while let Some(c) = rest.chars().next() {
+        if c.is_whitespace() {
+            rest = &rest[c.len_utf8()..];
+            continue;
+        }
+
+        let (kind, len) = match c {
+            '"' | '\'' => (TokenKind::String, string_len(rest, c)?),
+            '(' | '[' | '{' => (TokenKind::Open, 1),
+            ')' | ']' | '}' => (TokenKind::Close, 1),
+            ',' => (TokenKind::Comma, 1),
+            ':' => (TokenKind::Colon, 1),
+            '?' => (TokenKind::Question, 1),
+            '.' if rest.starts_with("...") => (TokenKind::Ellipsis, 3),
+            '.' => (TokenKind::Dot, 1),
+            '!' if !rest.starts_with("!=") => (TokenKind::Bang, 1),
+            c if c.is_ascii_digit() => (TokenKind::Number, number_len(rest)),
+            c if c.is_alphabetic() || c == '_' => (
+                TokenKind::Name,
+                rest.find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-'))
+                    .unwrap_or(rest.len()),
+            ),
+            _ if rest.starts_with("<<") => return None,
+            _ => (
+                TokenKind::Operator,
+                OPERATORS.iter().find(|op| rest.starts_with(*op))?.len(),
+            ),
+        };
+
+        tokens.push(Token {
+            kind,
+            text: &rest[..len],
+        });
+        rest = &rest[len..];
+    }
+
+    Some(tokens)
+}
+
+/// Length of the string starting at the beginning of `text`, quotes included
+///
+/// Strings containing an interpolation are rejected, since their quotes may
+/// nest.
+fn string_len(text: &str, quote: char) -> Option<usize> {
+    let mut escaped = false;
+    for (i, c) in text.char_indices().skip(1) {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '$' | '%' if text[i + 1..].starts_with('{') => return None,
+            c if c == quote => return Some(i + 1),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Length of the number at the beginning of `text`
+fn number_len(text: &str) -> usize {
+    let bytes = text.as_bytes();
+    let mut len = 0;
+    while len < bytes.len() {
+        let b = bytes[len];
+        let fraction = b == b'.' && bytes.get(len + 1).is_some_and(u8::is_ascii_digit);
+        let exponent_sign =
+            matches!(b, b'+' | b'-') && len > 0 && matches!(bytes[len - 1], b'e' | b'E');
+        if b.is_ascii_digit() || fraction || exponent_sign || matches!(b, b'e' | b'E') {
+            len += 1;
+        } else {
+            break;
+        }
+    }
+    len
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(source: &str) -> Vec<(TokenKind, &str)> {
+        tokenize(source)
+            .unwrap()
+            .into_iter()
+            .map(|t| (t.kind, t.text))
+            .collect()
+    }
+
+    #[test]
+    fn test_tokenize() {
+        use TokenKind::*;
+        assert_eq!(
+            kinds(r#"map.get( x ,"a\"b")>=1.5e-3&&!ok"#),
+            vec![
+                (Name, "map"),
+                (Dot, "."),
+                (Name, "get"),
+                (Open, "("),
+                (Name, "x"),
+                (Comma, ","),
+                (String, r#""a\"b""#),
+                (Close, ")"),
+                (Operator, ">="),
+                (Number, "1.5e-3"),
+                (Operator, "&&"),
+                (Bang, "!"),
+                (Name, "ok"),
+            ]
+        );
+        assert_eq!(
+            kinds("list.0"),
+            vec![(Name, "list"), (Dot, "."), (Number, "0")]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_rejects_what_it_cannot_represent() {
+        assert_eq!(tokenize(r#""${nested}""#), None);
+        assert_eq!(tokenize("<<EOT"), None);
+        assert_eq!(tokenize("\"unterminated"), None);
+        assert_eq!(tokenize("a # b"), None);
+    }
+}
@@ -0,0 +1,754 @@
+//! Parser for the contents of Workflows `$${...}` expressions
+//!
+//! Builds a small syntax tree of literals, names, member access, indexing,
+//! function calls, unary and binary operators, lists and maps. Every node and
+//! error carries a byte span into the expression body, so callers can map it
+//! back to the document. Parsing stops at the first syntax error.
+
+/// A byte range into the parsed expression body
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    fn to(self, other: Span) -> Span {
+        Span::new(self.start, other.end)
+    }
+}
+
+/// A literal value
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    Int(i64),
+    Double(f64),
+    /// The string's value, with escapes resolved
+    String(String),
+    Bool(bool),
+    Null,
+}
+
+/// A unary operator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    Negate,
+    Not,
+}
+
+/// A binary operator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Or,
+    And,
+    Eq,
+    NotEq,
+    Less,
+    LessEq,
+    Greater,
+    GreaterEq,
+    In,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    IntDiv,
+    Rem,
+}
+
+impl BinaryOp {
+    /// The operator as written
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BinaryOp::Or => "or",
+            BinaryOp::And => "and",
+            BinaryOp::Eq => "==",
+            BinaryOp::NotEq => "!=",
+            BinaryOp::Less => "<",
+            BinaryOp::LessEq => "<=",
+            BinaryOp::Greater => ">",
+            BinaryOp::GreaterEq => ">=",
+            BinaryOp::In => "in",
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::IntDiv => "//",
+            BinaryOp::Rem => "%",
+        }
+    }
+}
+
+/// A node of the expression tree
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Literal(Literal, Span),
+    /// A variable or function name
+    Name(String, Span),
+    /// `object.field`
+    Member {
+        object: Box<Expr>,
+        field: String,
+        span: Span,
+    },
+    /// `object[index]`
+    Index {
+        object: Box<Expr>,
+        index: Box<Expr>,
+        span: Span,
+    },
+    /// `callee(args...)`, where the callee is a name or a member path such
+    /// as `text.split`
+    Call {
+        callee: Box<Expr>,
+        args: Vec<Expr>,
+        span: Span,
+    },
+    Unary {
+        op: UnaryOp,
+        operand: Box<Expr>,
+        span: Span,
+    },
+    Binary {
+        op: BinaryOp,
+        left: Box<Expr>,
+        right: Box<Expr>,
+        span: Span,
+    },
+    List(Vec<Expr>, Span),
+    Map(Vec<(Expr, Expr)>, Span),
+}
+
+impl Expr {
+    /// The part of the body this node covers
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::Literal(_, span)
+            | Expr::Name(_, span)
+            | Expr::List(_, span)
+            | Expr::Map(_, span) => *span,
+            Expr::Member { span, .. }
+            | Expr::Index { span, .. }
+            | Expr::Call { span, .. }
+            | Expr::Unary { span, .. }
+            | Expr::Binary { span, .. } => *span,
+        }
+    }
+
+    /// The dotted path of a name or member chain, such as `text.split`
+    pub fn path(&self) -> Option<String> {
+        match self {
+            Expr::Name(name, _) => Some(name.clone()),
+            Expr::Member { object, field, .. } => Some(format!("{}.{}", object.path()?, field)),
+            _ => None,
+        }
+    }
+}
+
+/// A syntax error and the part of the body it concerns
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxError {
+    pub message: String,
+    pub span: Span,
+}
+
+/// Parse an expression body, the text between `$${` and `}`
+pub fn parse(source: &str) -> Result<Expr, SyntaxError> {
+    let tokens = lex(source)?;
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        end: source.len(),
+    };
+    if parser.tokens.is_empty() {
+        return Err(SyntaxError {
+            message: "Empty expression".to_string(),
+            span: Span::new(0, source.len()),
+        });
+    }
+    let expr = parser.or()?;
+    match parser.peek() {
+        None => Ok(expr),
+        Some(token) => Err(parser.unexpected(token)),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    Name(String),
+    Int(i64),
+    Double(f64),
+    String(String),
+    /// Operators and punctuation
+    Symbol(&'static str),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Token {
+    kind: TokenKind,
+    span: Span,
+}
+
+/// Symbols, longest first so `//` isn't read as `/` twice
+const SYMBOLS: &[&str] = &[
+    "==", "!=", "<=", ">=", "//", "+", "-", "*", "/", "%", "<", ">", "(", ")", "[", "]", "{", "}",
+    ",", ":", ".",
+];
+
+fn lex(source: &str) -> Result<Vec<Token>, SyntaxError> {
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while let Some(c) = source[i..].chars().next() {
+        if c.is_whitespace() {
+            i += c.len_utf8();
+            continue;
+        }
+        let start = i;
+        let kind = if c == '"' || c == '\'' {
+            let (value, len) = lex_string(&source[i..], c).ok_or_else(|| SyntaxError {
+                message: "Unterminated string".to_string(),
+                span: Span::new(start, source.len()),
+            })?;
+            i += len;
+            TokenKind::String(value)
+        } else if c.is_ascii_digit() {
+            let len = number_len(&source[i..]);
+            let text = &source[i..i + len];
+            i += len;
+            match text.parse::<i64>() {
+                Ok(n) => TokenKind::Int(n),
+                Err(_) => TokenKind::Double(text.parse().map_err(|_| SyntaxError {
+                    message: format!("Invalid number '{}'", text),
+                    span: Span::new(start, i),
+                })?),
+            }
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let len = source[i..]
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(source.len() - i);
+            i += len;
+            TokenKind::Name(source[start..i].to_string())
+        } else if let Some(symbol) = SYMBOLS.iter().find(|s| source[i..].starts_with(**s)) {
+            i += symbol.len();
+            TokenKind::Symbol(symbol)
+        } else {
+            return Err(SyntaxError {
+                message: format!("Unexpected character '{}'", c),
+                span: Span::new(start, start + c.len_utf8()),
+            });
+        };
+        tokens.push(Token {
+            kind,
+            span: Span::new(start, i),
+        });
+    }
+    Ok(tokens)
+}
+
+/// The value and length of the string at the start of `text`
+fn lex_string(text: &str, quote: char) -> Option<(String, usize)> {
+    let mut value = String::new();
+    let mut chars = text.char_indices().skip(1);
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => {
+                let (_, escaped) = chars.next()?;
+                value.push(match escaped {
+                    'n' => '\n',
+                    't' => '\t',
+                    'r' => '\r',
+                    other => other,
+                });
+            }
+            c if c == quote => return Some((value, i + 1)),
+            c => value.push(c),
+        }
+    }
+    None
+}
+
+/// Length of the number at the start of `text`
+fn number_len(text: &str) -> usize {
+    let bytes = text.as_bytes();
+    let mut len = 0;
+    while len < bytes.len() {
+        let b = bytes[len];
+        let fraction = b == b'.' && bytes.get(len + 1).is_some_and(u8::is_ascii_digit);
+        let exponent = matches!(b, b'e' | b'E')
+            && bytes
+                .get(len + 1)
+                .is_some_and(|n| n.is_ascii_digit() || matches!(n, b'+' | b'-'));
+        let exponent_sign =
+            matches!(b, b'+' | b'-') && len > 0 && matches!(bytes[len - 1], b'e' | b'E');
+        if b.is_ascii_digit() || fraction || exponent || exponent_sign {
+            len += 1;
+        } else {
+            break;
+        }
+    }
+    len
+}
+
+/// Recursive descent over the tokens, lowest precedence first
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    /// Length of the body, where errors about a missing token point
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn is_symbol(&self, symbol: &str) -> bool {
+        self.peek()
+            .is_some_and(|t| matches!(t.kind, TokenKind::Symbol(s) if s == symbol))
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        self.peek()
+            .is_some_and(|t| matches!(&t.kind, TokenKind::Name(n) if n == keyword))
+    }
+
+    fn bump(&mut self) -> Token {
+        let token = self.tokens[self.pos].clone();
+        self.pos += 1;
+        token
+    }
+
+    fn unexpected(&self, token: &Token) -> SyntaxError {
+        SyntaxError {
+            message: format!("Unexpected {}", describe(&token.kind)),
+            span: token.span,
+        }
+    }
+
+    /// Error for an operand missing where the next token (or the end) is
+    fn expected_expression(&self) -> SyntaxError {
+        match self.peek() {
+            Some(token) => SyntaxError {
+                message: format!("Expected an expression, found {}", describe(&token.kind)),
+                span: token.span,
+            },
+            None => SyntaxError {
+                message: "Expected an expression, found the end of the expression".to_string(),
+                span: Span::new(self.end, self.end),
+            },
+        }
+    }
+
+    /// Consume the symbol closing the bracket `open`
+    fn close(&mut self, close: &str, open: &Token) -> Result<Span, SyntaxError> {
+        if self.is_symbol(close) {
+            return Ok(self.bump().span);
+        }
+        let open_text = symbol_text(open);
+        Err(match self.peek() {
+            Some(token) => SyntaxError {
+                message: format!(
+                    "Expected '{}' to close '{}', found {}",
+                    close,
+                    open_text,
+                    describe(&token.kind)
+                ),
+                span: token.span,
+            },
+            None => SyntaxError {
+                message: format!("Unclosed '{}'", open_text),
+                span: open.span,
+            },
+        })
+    }
+
+    fn binary_level(
+        &mut self,
+        operators: &[(&str, BinaryOp)],
+        next: fn(&mut Self) -> Result<Expr, SyntaxError>,
+    ) -> Result<Expr, SyntaxError> {
+        let mut left = next(self)?;
+        while let Some(&(_, op)) = operators.iter().find(|(text, _)| {
+            if text.starts_with(|c: char| c.is_ascii_alphabetic()) {
+                self.is_keyword(text)
+            } else {
+                self.is_symbol(text)
+            }
+        }) {
+            self.bump();
+            let right = next(self)?;
+            let span = left.span().to(right.span());
+            left = Expr::Binary {
+                op,
+                left: Box::new(left),
+                right: Box::new(right),
+                span,
+            };
+        }
+        Ok(left)
+    }
+
+    fn or(&mut self) -> Result<Expr, SyntaxError> {
+        self.binary_level(&[("or", BinaryOp::Or)], Self::and)
+    }
+
+    fn and(&mut self) -> Result<Expr, SyntaxError> {
+        self.binary_level(&[("and", BinaryOp::And)], Self::not)
+    }
+
+    fn not(&mut self) -> Result<Expr, SyntaxError> {
+        if self.is_keyword("not") {
+            let start = self.bump().span;
+            let operand = self.not()?;
+            let span = start.to(operand.span());
+            return Ok(Expr::Unary {
+                op: UnaryOp::Not,
+                operand: Box::new(operand),
+                span,
+            });
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expr, SyntaxError> {
+        self.binary_level(
+            &[
+                ("==", BinaryOp::Eq),
+                ("!=", BinaryOp::NotEq),
+                ("<=", BinaryOp::LessEq),
+                (">=", BinaryOp::GreaterEq),
+                ("<", BinaryOp::Less),
+                (">", BinaryOp::Greater),
+                ("in", BinaryOp::In),
+            ],
+            Self::additive,
+        )
+    }
+
+    fn additive(&mut self) -> Result<Expr, SyntaxError> {
+        self.binary_level(
+            &[("+", BinaryOp::Add), ("-", BinaryOp::Sub)],
+            Self::multiplicative,
+        )
+    }
+
+    fn multiplicative(&mut self) -> Result<Expr, SyntaxError> {
+        self.binary_level(
+            &[
+                ("*", BinaryOp::Mul),
+                ("//", BinaryOp::IntDiv),
+                ("/", BinaryOp::Div),
+                ("%", BinaryOp::Rem),
+            ],
+            Self::unary,
+        )
+    }
+
+    fn unary(&mut self) -> Result<Expr, SyntaxError> {
+        if self.is_symbol("-") {
+            let start = self.bump().span;
+            let operand = self.unary()?;
+            let span = start.to(operand.span());
+            return Ok(Expr::Unary {
+                op: UnaryOp::Negate,
+                operand: Box::new(operand),
+                span,
+            });
+        }
+        self.postfix()
+    }
+
+    fn postfix(&mut self) -> Result<Expr, SyntaxError> {
+        let mut expr = self.primary()?;
+        loop {
+            if self.is_symbol(".") {
+                let dot = self.bump();
+                let field = match self.peek().map(|t| t.kind.clone()) {
+                    Some(TokenKind::Name(name)) => name,
+                    Some(_) => {
+                        return Err(SyntaxError {
+                            message: "Expected a field name after '.'".to_string(),
+                            span: self.peek().map_or(dot.span, |t| t.span),
+                        })
+                    }
+                    None => {
+                        return Err(SyntaxError {
+                            message: "Expected a field name after '.'".to_string(),
+                            span: dot.span,
+                        })
+                    }
+                };
+                let end = self.bump().span;
+                let span = expr.span().to(end);
+                expr = Expr::Member {
+                    object: Box::new(expr),
+                    field,
+                    span,
+                };
+            } else if self.is_symbol("[") {
+                let open = self.bump();
+                let index = self.or()?;
+                let end = self.close("]", &open)?;
+                let span = expr.span().to(end);
+                expr = Expr::Index {
+                    object: Box::new(expr),
+                    index: Box::new(index),
+                    span,
+                };
+            } else if self.is_symbol("(") {
+                if expr.path().is_none() {
+                    return Err(SyntaxError {
+                        message: "Only named functions can be called".to_string(),
+                        span: self.peek().map_or(expr.span(), |t| t.span),
+                    });
+                }
+                let open = self.bump();
+                let (args, end) = self.items(")", &open, Self::or)?;
+                let span = expr.span().to(end);
+                expr = Expr::Call {
+                    callee: Box::new(expr),
+                    args,
+                    span,
+                };
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, SyntaxError> {
+        let Some(token) = self.peek().cloned() else {
+            return Err(self.expected_expression());
+        };
+        let literal = |literal| Ok(Expr::Literal(literal, token.span));
+        match &token.kind {
+            TokenKind::Int(n) => {
+                self.pos += 1;
+                literal(Literal::Int(*n))
+            }
+            TokenKind::Double(n) => {
+                self.pos += 1;
+                literal(Literal::Double(*n))
+            }
+            TokenKind::String(s) => {
+                self.pos += 1;
+                literal(Literal::String(s.clone()))
+            }
+            TokenKind::Name(name) => match name.as_str() {
+                "and" | "or" | "not" | "in" => Err(self.expected_expression()),
+                "true" | "True" | "TRUE" => {
+                    self.pos += 1;
+                    literal(Literal::Bool(true))
+                }
+                "false" | "False" | "FALSE" => {
+                    self.pos += 1;
+                    literal(Literal::Bool(false))
+                }
+                "null" => {
+                    self.pos += 1;
+                    literal(Literal::Null)
+                }
+                _ => {
+                    self.pos += 1;
+                    Ok(Expr::Name(name.clone(), token.span))
+                }
+            },
+            TokenKind::Symbol("(") => {
+                let open = self.bump();
+                let inner = self.or()?;
+                self.close(")", &open)?;
+                Ok(inner)
+            }
+            TokenKind::Symbol("[") => {
+                let open = self.bump();
+                let (items, end) = self.items("]", &open, Self::or)?;
+                Ok(Expr::List(items, open.span.to(end)))
+            }
+            TokenKind::Symbol("{") => {
+                let open = self.bump();
+                let (entries, end) = self.items("}", &open, Self::entry)?;
+                Ok(Expr::Map(entries, open.span.to(end)))
+            }
+            _ => Err(self.expected_expression()),
+        }
+    }
+
+    /// A `key: value` map entry
+    fn entry(&mut self) -> Result<(Expr, Expr), SyntaxError> {
+        let key = self.or()?;
+        if !self.is_symbol(":") {
+            return Err(match self.peek() {
+                Some(token) => SyntaxError {
+                    message: format!(
+                        "Expected ':' after a map key, found {}",
+                        describe(&token.kind)
+                    ),
+                    span: token.span,
+                },
+                None => SyntaxError {
+                    message: "Expected ':' after a map key".to_string(),
+                    span: key.span(),
+                },
+            });
+        }
+        self.bump();
+        Ok((key, self.or()?))
+    }
+
+    /// Comma-separated items up to the bracket closing `open`, allowing a
+    /// trailing comma
+    fn items<T>(
+        &mut self,
+        close: &str,
+        open: &Token,
+        item: fn(&mut Self) -> Result<T, SyntaxError>,
+    ) -> Result<(Vec<T>, Span), SyntaxError> {
+        let mut items = Vec::new();
+        loop {
+            if self.is_symbol(close) {
+                return Ok((items, self.bump().span));
+            }
+            if self.peek().is_none() {
+                return Err(SyntaxError {
+                    message: format!("Unclosed '{}'", symbol_text(open)),
+                    span: open.span,
+                });
+            }
+            items.push(item(self)?);
+            if self.is_symbol(",") {
+                self.bump();
+            } else {
+                let end = self.close(close, open)?;
+                return Ok((items, end));
+            }
+        }
+    }
+}
+
+fn symbol_text(token: &Token) -> &'static str {
+    match &token.kind {
+        TokenKind::Symbol(s) => s,
+        _ => "",
+    }
+}
+
+/// How a token is named in error messages
+fn describe(kind: &TokenKind) -> String {
+    match kind {
+        TokenKind::Name(name) => format!("'{}'", name),
+        TokenKind::Int(_) | TokenKind::Double(_) => "a number".to_string(),
+        TokenKind::String(_) => "a string".to_string(),
+        TokenKind::Symbol(s) => format!("'{}'", s),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(source: &str) -> (String, usize, usize) {
+        let e = parse(source).unwrap_err();
+        (e.message, e.span.start, e.span.end)
+    }
+
+    #[test]
+    fn test_parses_expressions() {
+        let expr = parse(r#"text.split(name, "-")[0] + "x""#).unwrap();
+        let Expr::Binary {
+            op: BinaryOp::Add,
+            left,
+            ..
+        } = &expr
+        else {
+            panic!("expected an addition, got {:?}", expr);
+        };
+        let Expr::Index { object, .. } = left.as_ref() else {
+            panic!("expected an index, got {:?}", left);
+        };
+        let Expr::Call { callee, args, span } = object.as_ref() else {
+            panic!("expected a call, got {:?}", object);
+        };
+        assert_eq!(callee.path().as_deref(), Some("text.split"));
+        assert_eq!(args.len(), 2);
+        assert_eq!(*span, Span::new(0, 21));
+        assert_eq!(expr.span(), Span::new(0, 30));
+
+        for source in [
+            "not (a and b) or c in [1, 2.5, -3e2]",
+            r#"{"a": {"b": null}, "c": [true, 'x\'y'],}"#,
+            "len(items) // 2 % 3 >= 1",
+            "map.get(m, \"k\").field",
+            "sys.now()",
+        ] {
+            assert!(parse(source).is_ok(), "failed to parse {}", source);
+        }
+    }
+
+    #[test]
+    fn test_precedence() {
+        let expr = parse("a + b * c == d").unwrap();
+        let Expr::Binary {
+            op: BinaryOp::Eq,
+            left,
+            ..
+        } = expr
+        else {
+            panic!("expected a comparison");
+        };
+        assert!(matches!(
+            *left,
+            Expr::Binary {
+                op: BinaryOp::Add,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        assert_eq!(error(""), ("Empty expression".to_string(), 0, 0));
+        assert_eq!(
+            error("a +"),
+            (
+                "Expected an expression, found the end of the expression".to_string(),
+                3,
+                3
+            )
+        );
+        assert_eq!(error("f(a, b"), ("Unclosed '('".to_string(), 1, 2));
+        assert_eq!(
+            error("[1, 2 3]"),
+            (
+                "Expected ']' to close '[', found a number".to_string(),
+                6,
+                7
+            )
+        );
+        assert_eq!(error("a b"), ("Unexpected 'b'".to_string(), 2, 3));
+        assert_eq!(error("\"abc"), ("Unterminated string".to_string(), 0, 4));
+        assert_eq!(
+            error("a.1"),
+            ("Expected a field name after '.'".to_string(), 2, 3)
+        );
+        assert_eq!(
+            error("{\"a\" 1}"),
+            (
+                "Expected ':' after a map key, found a number".to_string(),
+                5,
+                6
+            )
+        );
+        assert_eq!(
+            error("a & b"),
+            ("Unexpected character '&'".to_string(), 2, 3)
+        );
+        assert_eq!(
+            error("[f][0](b)"),
+            ("Only named functions can be called".to_string(), 6, 7)
+        );
+    }
+}
@@ -1,12 +1,18 @@
 //! YAML parsing with error recovery
 //!
 //! This module parses preprocessed YAML text (with expression placeholders) and
-//! collects syntax errors, adjusting error positions back to the original document
+//! collects syntax errors, recovering after each one to report the next,
+//! adjusting error positions back to the original document
 //! coordinates when errors fall within or after expression placeholders.
+//! Aliases are expanded by serde_yaml and `<<` merge keys are applied here,
+//! so workflow validation sees the structure Workflows will deploy.
 
 use crate::diagnostics::DiagnosticCollector;
 
+use super::anchors::scan_anchors;
+use super::directives::without_unparsed_directives;
 use super::expressions::ExpressionMap;
+use super::lines::{indentation, tab_indentation};
 
 /// Result of parsing YAML, containing any parsed value
 #[derive(Debug)]
@@ -36,37 +42,142 @@ pub fn parse_yaml(
     expression_map: &ExpressionMap,
     collector: &mut DiagnosticCollector,
 ) -> ParseResult {
-    // Attempt to parse the YAML
-    match serde_yaml::from_str::<serde_yaml::Value>(text) {
-        Ok(value) => {
-            // Successfully parsed - no YAML syntax errors
-            ParseResult {
+    // Attempt to parse the YAML, leaving directives libyaml rejects to the
+    // directive checks
+    let source = without_unparsed_directives(text);
+    let err = match serde_yaml::from_str::<serde_yaml::Value>(&source) {
+        Ok(mut value) => {
+            // Successfully parsed - no YAML syntax errors. A merge that fails
+            // leaves the value unmerged, as written
+            let mut merged = value.clone();
+            if merged.apply_merge().is_ok() {
+                value = merged;
+            }
+            return ParseResult {
                 value: Some(value),
                 success: true,
-            }
+            };
+        }
+        Err(err) => err,
+    };
+
+    // libyaml stops at the first error, so blank out the node it failed in
+    // and parse again to find the errors after it. Only errors further down
+    // the document are kept: anything earlier is a consequence of the
+    // blanking, not a problem in the document.
+    let mut err = err;
+    let mut line = report_error(&err, text, expression_map, collector);
+    let mut recovered = source.into_owned();
+    for _ in 1..MAX_YAML_ERRORS {
+        if swallows_rest(&err.to_string()) {
+            break;
         }
-        Err(err) => {
-            // Extract error information
-            let message = err.to_string();
+        recovered = blank_node(&recovered, line as usize);
+        err = match serde_yaml::from_str::<serde_yaml::Value>(&recovered) {
+            Ok(_) => break,
+            Err(err) => err,
+        };
+        if extract_error_position(&err.to_string()).0 <= line {
+            break;
+        }
+        line = report_error(&err, text, expression_map, collector);
+    }
 
-            // serde_yaml error messages often contain location info like "at line X column Y"
-            // We try to extract this for better diagnostics
-            let (line, column) = extract_error_position(&message);
+    ParseResult {
+        value: None,
+        success: false,
+    }
+}
 
-            // Adjust position if it falls within or after an expression placeholder
-            let (adjusted_line, adjusted_column) = expression_map.adjust_position(line, column);
+/// Most syntax errors reported for one document
+const MAX_YAML_ERRORS: usize = 20;
 
-            // Clean up the error message to remove position info (we provide it via range)
-            let clean_message = clean_error_message(&message);
+/// Report a serde_yaml error, returning the (preprocessed) line it was
+/// raised on
+fn report_error(
+    err: &serde_yaml::Error,
+    text: &str,
+    expression_map: &ExpressionMap,
+    collector: &mut DiagnosticCollector,
+) -> u32 {
+    // Extract error information
+    let message = err.to_string();
+
+    // serde_yaml error messages often contain location info like "at line X column Y"
+    // We try to extract this for better diagnostics
+    let (line, column) = extract_error_position(&message);
+
+    // Adjust position if it falls within or after an expression placeholder
+    let (adjusted_line, adjusted_column) = expression_map.adjust_position(line, column);
+
+    // Clean up the error message to remove position info (we provide it via range)
+    let clean_message = clean_error_message(&message);
+
+    // An unclosed expression or tab indentation on the line is reported on
+    // its own and is the likelier cause; undefined aliases are reported by
+    // name with the other node property checks, when they found one there
+    let reported_elsewhere = (message.starts_with("unknown anchor")
+        && undefined_alias_on(text, line))
+        || expression_map
+            .unclosed
+            .iter()
+            .any(|u| u.line == adjusted_line)
+        || text
+            .lines()
+            .nth(line as usize)
+            .and_then(tab_indentation)
+            .is_some();
+    if !reported_elsewhere {
+        collector.add_yaml_error(clean_message, adjusted_line, adjusted_column);
+    }
 
-            collector.add_yaml_error(clean_message, adjusted_line, adjusted_column);
+    line
+}
+
+/// Whether the node property checks report an undefined alias on `line`
+///
+/// Placeholders contain no `&`/`*`, so the preprocessed text scans the same
+/// as the original.
+fn undefined_alias_on(text: &str, line: u32) -> bool {
+    let index = scan_anchors(text, &ExpressionMap::new());
+    index
+        .aliases
+        .iter()
+        .any(|alias| alias.line == line && index.resolve(alias).is_none())
+}
+
+/// Whether the error comes from a construct left open (a quoted string or
+/// a flow collection), which takes the rest of the document with it so
+/// later errors can't be trusted
+fn swallows_rest(message: &str) -> bool {
+    message.contains("end of stream")
+        || message.contains("while parsing a flow")
+        || message.contains("while scanning a quoted scalar")
+}
 
-            ParseResult {
-                value: None,
-                success: false,
+/// `text` with line `line` and the lines nested under it (indented deeper)
+/// emptied, keeping every line number unchanged
+fn blank_node(text: &str, line: usize) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut node_indent = None;
+    for (line_no, content) in text.split_inclusive('\n').enumerate() {
+        let body = content.trim_end_matches(['\r', '\n']);
+        let blank = match node_indent {
+            _ if line_no == line => {
+                node_indent = Some(indentation(body));
+                true
             }
+            Some(indent) => body.trim().is_empty() || indentation(body) > indent,
+            None => false,
+        };
+        if blank {
+            out.push_str(&content[body.len()..]);
+        } else {
+            node_indent = None;
+            out.push_str(content);
         }
     }
+    out
 }
 
 /// Extract line and column from a serde_yaml error message
@@ -173,6 +284,89 @@ mod tests {
         assert!(!diagnostics.is_empty());
     }
 
+    #[test]
+    fn test_error_on_unclosed_expression_line_left_out() {
+        let input = "a: 1\nb: ${jsonencode({c: 1})\n";
+        let (preprocessed, expression_map) = preprocess_expressions(input);
+        let mut collector = DiagnosticCollector::new();
+
+        let result = parse_yaml(&preprocessed, &expression_map, &mut collector);
+
+        assert!(!result.success);
+        assert!(collector.into_diagnostics().is_empty());
+    }
+
+    #[test]
+    fn test_reports_every_syntax_error() {
+        let yaml = "main:\n  steps:\n    - a:\n        assign:\n          - x: 1\n           - y: 2\n    - b\n        call: foo\n    - c:\n        return: \"x\" y\n";
+        let mut collector = DiagnosticCollector::new();
+
+        let result = parse_yaml(yaml, &ExpressionMap::new(), &mut collector);
+
+        assert!(!result.success);
+        let lines: Vec<u32> = collector
+            .into_diagnostics()
+            .iter()
+            .map(|d| d.range.start.line)
+            .collect();
+        assert_eq!(lines, vec![5, 7, 9]);
+    }
+
+    #[test]
+    fn test_no_recovery_past_unterminated_flow_collection() {
+        let yaml = "a: [1, 2\nb: 1\n  c: 2\nd: 3\n";
+        let mut collector = DiagnosticCollector::new();
+
+        parse_yaml(yaml, &ExpressionMap::new(), &mut collector);
+
+        assert_eq!(collector.into_diagnostics().len(), 1);
+    }
+
+    #[test]
+    fn test_blank_node() {
+        let yaml = "a:\n  b: 1\n\n  c:\n    d: 2\ne: 3\r\n";
+        assert_eq!(blank_node(yaml, 0), "\n\n\n\n\ne: 3\r\n");
+        assert_eq!(blank_node(yaml, 3), "a:\n  b: 1\n\n\n\ne: 3\r\n");
+        assert_eq!(blank_node(yaml, 5), "a:\n  b: 1\n\n  c:\n    d: 2\n\r\n");
+    }
+
+    #[test]
+    fn test_merge_keys_applied() {
+        let yaml = "defaults: &defaults\n  a: 1\n  b: 2\nstep:\n  <<: *defaults\n  b: 3";
+        let mut collector = DiagnosticCollector::new();
+
+        let result = parse_yaml(yaml, &ExpressionMap::new(), &mut collector);
+
+        let step = &result.value.unwrap()["step"];
+        assert_eq!(step["a"], serde_yaml::Value::from(1));
+        assert_eq!(step["b"], serde_yaml::Value::from(3));
+        assert!(step.get("<<").is_none());
+    }
+
+    #[test]
+    fn test_unknown_anchor_left_to_node_properties() {
+        let yaml = "a: 1\nb: *missing";
+        let mut collector = DiagnosticCollector::new();
+
+        let result = parse_yaml(yaml, &ExpressionMap::new(), &mut collector);
+
+        assert!(!result.success);
+        assert!(collector.into_diagnostics().is_empty());
+    }
+
+    #[test]
+    fn test_unknown_anchor_without_alias_is_kept() {
+        // The scanner reads `&x` at the start of a quoted scalar's second
+        // line as an anchor, so `*x` resolves for node_properties and the
+        // parser's error is all there is
+        let yaml = "a: \"first\n  &x second\"\nb: *x\n";
+        let mut collector = DiagnosticCollector::new();
+        parse_yaml(yaml, &ExpressionMap::new(), &mut collector);
+        let diagnostics = collector.into_diagnostics();
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].message.starts_with("unknown anchor"));
+    }
+
     #[test]
     fn test_parse_invalid_yaml_bad_list_format() {
         let yaml = "list:\n  - item1\n   - item2"; // Misaligned list items
@@ -327,6 +521,18 @@ config:
         assert!(result.success);
     }
 
+    #[test]
+    fn test_parse_yaml_with_directives() {
+        let yaml = "%YAML 1.3\n%FOO bar\n%TAG !e! tag:example.com,2000:\n---\na: !e!x 1";
+        let expression_map = ExpressionMap::new();
+        let mut collector = DiagnosticCollector::new();
+
+        let result = parse_yaml(yaml, &expression_map, &mut collector);
+
+        assert!(result.success);
+        assert!(collector.into_diagnostics().is_empty());
+    }
+
     #[test]
     fn test_parse_yaml_comment_only() {
         let yaml = "# This is a comment\n# Another comment";
@@ -337,4 +543,16 @@ config:
 
         assert!(result.success);
     }
+
+    #[test]
+    fn test_tab_indentation_left_to_its_own_diagnostic() {
+        let yaml = "main:\n\tsteps: []\n";
+        let expression_map = ExpressionMap::new();
+        let mut collector = DiagnosticCollector::new();
+
+        let result = parse_yaml(yaml, &expression_map, &mut collector);
+
+        assert!(!result.success);
+        assert!(collector.into_diagnostics().is_empty());
+    }
 }
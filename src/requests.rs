@@ -0,0 +1,106 @@
+//! Custom `yaml-tftpl/*` LSP requests
+//!
+//! These extend the protocol for the companion editor extensions. Each request
+//! has a method name constant and, where needed, its own params type; handlers
+//! live on [`crate::Backend`].
+
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::{Range, TextDocumentIdentifier, Url};
+
+use crate::analysis::StageTiming;
+
+/// `yaml-tftpl/effectiveSeverity`: effective severity of every rule at a
+/// position, considering inline suppressions.
+///
+/// Params: `TextDocumentPositionParams`. Result: `RuleSeverity[]`.
+pub const EFFECTIVE_SEVERITY: &str = "yaml-tftpl/effectiveSeverity";
+
+/// `yaml-tftpl/profile`: re-run the diagnostic pipeline for a document and
+/// report how long each stage took, including publishing the results.
+pub const PROFILE: &str = "yaml-tftpl/profile";
+
+/// Params for [`PROFILE`]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileParams {
+    pub text_document: TextDocumentIdentifier,
+}
+
+/// Result of [`PROFILE`]
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileResult {
+    /// Document version that was profiled
+    pub version: i32,
+    /// Document size in bytes
+    pub text_len: usize,
+    /// Number of diagnostics produced
+    pub diagnostics_count: usize,
+    /// Per-stage timings, in pipeline order
+    pub stages: Vec<StageTiming>,
+    /// Sum of all stage durations in milliseconds
+    pub total_ms: f64,
+}
+
+/// `yaml-tftpl/callGraph`: the subworkflow call graph of a document.
+///
+/// Params: `{ textDocument }`. Result: [`crate::features::CallGraph`].
+pub const CALL_GRAPH: &str = "yaml-tftpl/callGraph";
+
+/// Params for [`CALL_GRAPH`]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallGraphParams {
+    pub text_document: TextDocumentIdentifier,
+}
+
+/// `yaml-tftpl/expressionAt`: the `${...}`/`$${...}` expression containing a
+/// position.
+///
+/// Params: `TextDocumentPositionParams`. Result:
+/// [`crate::features::ExpressionAt`] or `null`.
+pub const EXPRESSION_AT: &str = "yaml-tftpl/expressionAt";
+
+/// `yaml-tftpl/definitionChain`: the definitions behind the `local.*` or
+/// `var.*` reference at a position, following locals to the variables and
+/// tfvars assignments they are built from.
+///
+/// Params: `TextDocumentPositionParams`. Result:
+/// [`crate::terraform::ChainLink`]`[]`, starting with the reference itself.
+pub const DEFINITION_CHAIN: &str = "yaml-tftpl/definitionChain";
+
+/// `yaml-tftpl/preview`: render a template with variable values and validate
+/// the result.
+///
+/// Renders with the named render profile from the workspace configuration, or
+/// with every profile when none is named. Without configured profiles the
+/// module's own tfvars are used. Result: [`crate::features::Preview`]`[]`.
+pub const PREVIEW: &str = "yaml-tftpl/preview";
+
+/// Params for [`PREVIEW`]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PreviewParams {
+    pub text_document: TextDocumentIdentifier,
+    #[serde(default)]
+    pub profile: Option<String>,
+}
+
+/// `yaml-tftpl.extractSnippet` (`workspace/executeCommand`): store the steps or
+/// subworkflow under a range as a snippet in the workspace configuration.
+///
+/// Arguments: a single [`ExtractSnippetArgs`] object.
+pub const EXTRACT_SNIPPET: &str = "yaml-tftpl.extractSnippet";
+
+/// Arguments for [`EXTRACT_SNIPPET`]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExtractSnippetArgs {
+    pub uri: Url,
+    pub range: Range,
+    /// Snippet name; defaults to the first extracted step or workflow name
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+}
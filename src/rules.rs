@@ -0,0 +1,139 @@
+//! Rules supplied by embedders
+//!
+//! Organizations can ship private lints that run in the same process as the
+//! built-in checks: implement [`Rule`], register it in a [`RuleRegistry`] and
+//! hand the registry to [`crate::service`], [`crate::Backend::with_rules`] or
+//! [`crate::doctor::run_with_rules`].
+//!
+//! ```
+//! use tower_lsp::lsp_types::{Diagnostic, Position, Range};
+//! use yaml_tftpl_lsp::rules::{Rule, RuleContext, RuleRegistry};
+//!
+//! struct NoHttp;
+//!
+//! impl Rule for NoHttp {
+//!     fn code(&self) -> &str {
+//!         "acme/no-http"
+//!     }
+//!
+//!     fn check(&self, context: &RuleContext<'_>) -> Vec<Diagnostic> {
+//!         context
+//!             .text
+//!             .lines()
+//!             .enumerate()
+//!             .filter(|(_, line)| line.contains("http://"))
+//!             .map(|(i, _)| Diagnostic {
+//!                 range: Range::new(Position::new(i as u32, 0), Position::new(i as u32, 0)),
+//!                 message: "Use https".to_string(),
+//!                 ..Default::default()
+//!             })
+//!             .collect()
+//!     }
+//! }
+//!
+//! let mut rules = RuleRegistry::new();
+//! rules.register(NoHttp);
+//! ```
+
+use std::fmt;
+use std::sync::Arc;
+
+use serde_yaml::Value;
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString};
+
+use crate::analysis::Stage;
+use crate::parser::ExpressionMap;
+use crate::settings::Settings;
+
+/// What a rule gets to look at
+pub struct RuleContext<'a> {
+    /// The document as written, with CRLF line breaks turned into LF
+    pub text: &'a str,
+    /// The document with expressions replaced by `__EXPR_NNN__` placeholders
+    pub preprocessed: &'a str,
+    /// The parsed document; `None` in the parse stage when parsing failed
+    pub value: Option<&'a Value>,
+    /// The document's `${}` and `$${}` expressions
+    pub expressions: &'a ExpressionMap,
+    pub settings: &'a Settings,
+}
+
+/// A lint run alongside the built-in analyzers
+pub trait Rule: Send + Sync {
+    /// The code the rule's diagnostics carry, such as `acme/no-http`, used in
+    /// the `rules` setting and in suppression comments
+    fn code(&self) -> &str;
+
+    /// The stage the rule runs in, after that stage's built-in analyzers
+    ///
+    /// Structure rules only run on documents that parsed, with workflow
+    /// validation on; expression rules only with workflow validation on.
+    /// Rules in any other stage than parse, structure or expressions never
+    /// run.
+    fn stage(&self) -> Stage {
+        Stage::Structure
+    }
+
+    /// Severity for diagnostics that don't set one
+    fn default_severity(&self) -> DiagnosticSeverity {
+        DiagnosticSeverity::WARNING
+    }
+
+    /// Check a document
+    ///
+    /// Diagnostics without a code, source or severity get the rule's code,
+    /// this server as their source and the rule's default severity.
+    fn check(&self, context: &RuleContext<'_>) -> Vec<Diagnostic>;
+}
+
+/// The rules registered by an embedder, cheap to clone into each session
+#[derive(Clone, Default)]
+pub struct RuleRegistry {
+    rules: Vec<Arc<dyn Rule>>,
+}
+
+impl RuleRegistry {
+    /// An empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a rule
+    pub fn register(&mut self, rule: impl Rule + 'static) -> &mut Self {
+        self.rules.push(Arc::new(rule));
+        self
+    }
+
+    /// Whether no rules are registered
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// The registered rules that run in `stage`, in registration order
+    pub fn in_stage(&self, stage: Stage) -> impl Iterator<Item = &dyn Rule> {
+        self.rules
+            .iter()
+            .map(|rule| rule.as_ref())
+            .filter(move |rule| rule.stage() == stage)
+    }
+}
+
+impl fmt::Debug for RuleRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.rules.iter().map(|rule| rule.code()))
+            .finish()
+    }
+}
+
+/// Run a rule, filling in what its diagnostics leave out
+pub(crate) fn check(rule: &dyn Rule, context: &RuleContext<'_>) -> Vec<Diagnostic> {
+    let mut diagnostics = rule.check(context);
+    for d in &mut diagnostics {
+        d.code
+            .get_or_insert_with(|| NumberOrString::String(rule.code().to_string()));
+        d.source.get_or_insert_with(|| "yaml-tftpl-lsp".to_string());
+        d.severity.get_or_insert_with(|| rule.default_severity());
+    }
+    diagnostics
+}
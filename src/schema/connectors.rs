@@ -0,0 +1,385 @@
+//! Google Cloud connectors
+//!
+//! The `googleapis.*` methods workflows call most, with the arguments each
+//! takes. Connectors not listed here are still accepted, just not checked.
+//! Every connector also takes `connector_params`, which tunes the call
+//! itself (timeout, polling, scopes) rather than the API request.
+
+use super::workflows::{optional, required, CallArg, INTEGER, MAPPING, STRING};
+
+/// A `googleapis.*` connector method
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Connector {
+    /// Qualified name, such as `googleapis.bigquery.v2.jobs.insert`
+    pub name: &'static str,
+    pub params: &'static [CallArg],
+    pub description: &'static str,
+}
+
+impl Connector {
+    /// The call signature, e.g. `googleapis.run.v2.projects.locations.jobs.get(name)`
+    pub fn signature(&self) -> String {
+        let params: Vec<&str> = self
+            .params
+            .iter()
+            .map(|param| param.name)
+            .filter(|name| *name != "connector_params")
+            .collect();
+        format!("{}({})", self.name, params.join(", "))
+    }
+}
+
+const fn connector_method(
+    name: &'static str,
+    params: &'static [CallArg],
+    description: &'static str,
+) -> Connector {
+    Connector {
+        name,
+        params,
+        description,
+    }
+}
+
+const CONNECTOR_PARAMS: CallArg = optional("connector_params", MAPPING);
+
+/// Connector methods whose `args:` are checked and completed
+pub const CONNECTORS: &[Connector] = &[
+    connector_method(
+        "googleapis.bigquery.v2.jobs.insert",
+        &[
+            required("projectId", STRING),
+            required("body", MAPPING),
+            CONNECTOR_PARAMS,
+        ],
+        "Start a BigQuery job and wait for it to finish.",
+    ),
+    connector_method(
+        "googleapis.bigquery.v2.jobs.query",
+        &[
+            required("projectId", STRING),
+            required("body", MAPPING),
+            CONNECTOR_PARAMS,
+        ],
+        "Run a BigQuery query and return its first page of results.",
+    ),
+    connector_method(
+        "googleapis.bigquery.v2.jobs.get",
+        &[
+            required("projectId", STRING),
+            required("jobId", STRING),
+            optional("location", STRING),
+            CONNECTOR_PARAMS,
+        ],
+        "Get a BigQuery job.",
+    ),
+    connector_method(
+        "googleapis.bigquery.v2.jobs.getQueryResults",
+        &[
+            required("projectId", STRING),
+            required("jobId", STRING),
+            optional("location", STRING),
+            optional("maxResults", INTEGER),
+            optional("pageToken", STRING),
+            optional("startIndex", STRING),
+            optional("timeoutMs", INTEGER),
+            CONNECTOR_PARAMS,
+        ],
+        "Get the results of a BigQuery query job.",
+    ),
+    connector_method(
+        "googleapis.bigquery.v2.tabledata.list",
+        &[
+            required("projectId", STRING),
+            required("datasetId", STRING),
+            required("tableId", STRING),
+            optional("maxResults", INTEGER),
+            optional("pageToken", STRING),
+            optional("selectedFields", STRING),
+            optional("startIndex", STRING),
+            CONNECTOR_PARAMS,
+        ],
+        "List the rows of a BigQuery table.",
+    ),
+    connector_method(
+        "googleapis.bigquery.v2.tables.get",
+        &[
+            required("projectId", STRING),
+            required("datasetId", STRING),
+            required("tableId", STRING),
+            optional("selectedFields", STRING),
+            CONNECTOR_PARAMS,
+        ],
+        "Get a BigQuery table.",
+    ),
+    connector_method(
+        "googleapis.pubsub.v1.projects.topics.publish",
+        &[
+            required("topic", STRING),
+            required("body", MAPPING),
+            CONNECTOR_PARAMS,
+        ],
+        "Publish messages to a Pub/Sub topic.",
+    ),
+    connector_method(
+        "googleapis.pubsub.v1.projects.subscriptions.pull",
+        &[
+            required("subscription", STRING),
+            required("body", MAPPING),
+            CONNECTOR_PARAMS,
+        ],
+        "Pull messages from a Pub/Sub subscription.",
+    ),
+    connector_method(
+        "googleapis.pubsub.v1.projects.subscriptions.acknowledge",
+        &[
+            required("subscription", STRING),
+            required("body", MAPPING),
+            CONNECTOR_PARAMS,
+        ],
+        "Acknowledge pulled Pub/Sub messages.",
+    ),
+    connector_method(
+        "googleapis.run.v1.namespaces.jobs.run",
+        &[
+            required("name", STRING),
+            required("location", STRING),
+            optional("body", MAPPING),
+            CONNECTOR_PARAMS,
+        ],
+        "Run a Cloud Run job and wait for the execution to finish.",
+    ),
+    connector_method(
+        "googleapis.run.v2.projects.locations.jobs.run",
+        &[
+            required("name", STRING),
+            optional("body", MAPPING),
+            CONNECTOR_PARAMS,
+        ],
+        "Run a Cloud Run job and wait for the execution to finish.",
+    ),
+    connector_method(
+        "googleapis.run.v2.projects.locations.jobs.get",
+        &[required("name", STRING), CONNECTOR_PARAMS],
+        "Get a Cloud Run job.",
+    ),
+    connector_method(
+        "googleapis.run.v2.projects.locations.services.get",
+        &[required("name", STRING), CONNECTOR_PARAMS],
+        "Get a Cloud Run service.",
+    ),
+    connector_method(
+        "googleapis.storage.v1.objects.get",
+        &[
+            required("bucket", STRING),
+            required("object", STRING),
+            optional("alt", STRING),
+            optional("generation", STRING),
+            optional("userProject", STRING),
+            CONNECTOR_PARAMS,
+        ],
+        "Get a Cloud Storage object's metadata, or its content with `alt: media`.",
+    ),
+    connector_method(
+        "googleapis.storage.v1.objects.insert",
+        &[
+            required("bucket", STRING),
+            optional("name", STRING),
+            optional("uploadType", STRING),
+            optional("predefinedAcl", STRING),
+            optional("body", MAPPING),
+            CONNECTOR_PARAMS,
+        ],
+        "Upload an object to Cloud Storage.",
+    ),
+    connector_method(
+        "googleapis.storage.v1.objects.list",
+        &[
+            required("bucket", STRING),
+            optional("prefix", STRING),
+            optional("delimiter", STRING),
+            optional("maxResults", INTEGER),
+            optional("pageToken", STRING),
+            CONNECTOR_PARAMS,
+        ],
+        "List the objects of a Cloud Storage bucket.",
+    ),
+    connector_method(
+        "googleapis.storage.v1.objects.delete",
+        &[
+            required("bucket", STRING),
+            required("object", STRING),
+            optional("generation", STRING),
+            CONNECTOR_PARAMS,
+        ],
+        "Delete a Cloud Storage object.",
+    ),
+    connector_method(
+        "googleapis.storage.v1.objects.copy",
+        &[
+            required("sourceBucket", STRING),
+            required("sourceObject", STRING),
+            required("destinationBucket", STRING),
+            required("destinationObject", STRING),
+            optional("body", MAPPING),
+            CONNECTOR_PARAMS,
+        ],
+        "Copy a Cloud Storage object.",
+    ),
+    connector_method(
+        "googleapis.firestore.v1.projects.databases.documents.get",
+        &[
+            required("name", STRING),
+            optional("transaction", STRING),
+            optional("readTime", STRING),
+            CONNECTOR_PARAMS,
+        ],
+        "Get a Firestore document.",
+    ),
+    connector_method(
+        "googleapis.firestore.v1.projects.databases.documents.patch",
+        &[
+            required("name", STRING),
+            required("body", MAPPING),
+            CONNECTOR_PARAMS,
+        ],
+        "Create or update a Firestore document.",
+    ),
+    connector_method(
+        "googleapis.firestore.v1.projects.databases.documents.createDocument",
+        &[
+            required("parent", STRING),
+            required("collectionId", STRING),
+            optional("documentId", STRING),
+            required("body", MAPPING),
+            CONNECTOR_PARAMS,
+        ],
+        "Create a Firestore document in a collection.",
+    ),
+    connector_method(
+        "googleapis.firestore.v1.projects.databases.documents.delete",
+        &[required("name", STRING), CONNECTOR_PARAMS],
+        "Delete a Firestore document.",
+    ),
+    connector_method(
+        "googleapis.secretmanager.v1.projects.secrets.versions.access",
+        &[required("name", STRING), CONNECTOR_PARAMS],
+        "Access a secret version; the payload is base64-encoded.",
+    ),
+    connector_method(
+        "googleapis.secretmanager.v1.projects.secrets.versions.accessString",
+        &[
+            required("secret_id", STRING),
+            optional("version", STRING),
+            optional("project_id", STRING),
+            CONNECTOR_PARAMS,
+        ],
+        "Access a secret version, decoded to a string.",
+    ),
+    connector_method(
+        "googleapis.workflowexecutions.v1.projects.locations.workflows.executions.run",
+        &[
+            required("workflow_id", STRING),
+            optional("location", STRING),
+            optional("project_id", STRING),
+            optional("argument", None),
+            CONNECTOR_PARAMS,
+        ],
+        "Run a workflow and wait for its result.",
+    ),
+    connector_method(
+        "googleapis.workflowexecutions.v1.projects.locations.workflows.executions.create",
+        &[
+            required("parent", STRING),
+            required("body", MAPPING),
+            CONNECTOR_PARAMS,
+        ],
+        "Start a workflow execution without waiting for it.",
+    ),
+    connector_method(
+        "googleapis.workflowexecutions.v1.projects.locations.workflows.executions.get",
+        &[required("name", STRING), CONNECTOR_PARAMS],
+        "Get a workflow execution.",
+    ),
+    connector_method(
+        "googleapis.cloudtasks.v2.projects.locations.queues.tasks.create",
+        &[
+            required("parent", STRING),
+            required("body", MAPPING),
+            CONNECTOR_PARAMS,
+        ],
+        "Add a task to a Cloud Tasks queue.",
+    ),
+    connector_method(
+        "googleapis.cloudfunctions.v2.projects.locations.functions.get",
+        &[required("name", STRING), CONNECTOR_PARAMS],
+        "Get a Cloud Function.",
+    ),
+    connector_method(
+        "googleapis.compute.v1.instances.get",
+        &[
+            required("project", STRING),
+            required("zone", STRING),
+            required("instance", STRING),
+            CONNECTOR_PARAMS,
+        ],
+        "Get a Compute Engine instance.",
+    ),
+    connector_method(
+        "googleapis.compute.v1.instances.start",
+        &[
+            required("project", STRING),
+            required("zone", STRING),
+            required("instance", STRING),
+            CONNECTOR_PARAMS,
+        ],
+        "Start a Compute Engine instance and wait until it runs.",
+    ),
+    connector_method(
+        "googleapis.compute.v1.instances.stop",
+        &[
+            required("project", STRING),
+            required("zone", STRING),
+            required("instance", STRING),
+            CONNECTOR_PARAMS,
+        ],
+        "Stop a Compute Engine instance and wait until it stops.",
+    ),
+    connector_method(
+        "googleapis.logging.v2.entries.write",
+        &[required("body", MAPPING), CONNECTOR_PARAMS],
+        "Write entries to Cloud Logging.",
+    ),
+    connector_method(
+        "googleapis.cloudbuild.v1.projects.builds.create",
+        &[
+            required("projectId", STRING),
+            required("body", MAPPING),
+            optional("parent", STRING),
+            CONNECTOR_PARAMS,
+        ],
+        "Start a Cloud Build and wait for it to finish.",
+    ),
+];
+
+/// Look up a connector method by its qualified name
+pub fn connector(name: &str) -> Option<&'static Connector> {
+    CONNECTORS.iter().find(|c| c.name == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connector() {
+        let publish = connector("googleapis.pubsub.v1.projects.topics.publish").unwrap();
+        assert_eq!(
+            publish.signature(),
+            "googleapis.pubsub.v1.projects.topics.publish(topic, body)"
+        );
+        assert!(CONNECTORS.iter().all(|c| c.name.starts_with("googleapis.")
+            && c.params.iter().any(|p| p.name == "connector_params")));
+        assert!(connector("googleapis.pubsub.v1.projects.topics.nope").is_none());
+    }
+}
@@ -0,0 +1,75 @@
+//! HTTP request arguments
+//!
+//! The `http.*` calls take a richer `args:` mapping than other standard
+//! library functions: an `auth` block with its own keys, a `method` from a
+//! fixed set and a `timeout` capped by the platform. The validator checks
+//! those shapes on top of the argument list every call gets.
+
+use super::workflows::{optional, required, CallArg, ANY, MAPPING, NUMBER, STRING};
+
+/// Arguments shared by every `http.*` request after `url` and `body`
+const HTTP_OPTIONS: [CallArg; 5] = [
+    optional("headers", MAPPING),
+    optional("query", MAPPING),
+    optional("auth", MAPPING),
+    optional("timeout", NUMBER),
+    optional("private_service_name", STRING),
+];
+
+pub(super) const HTTP_GET_ARGS: &[CallArg] = &[
+    required("url", STRING),
+    HTTP_OPTIONS[0],
+    HTTP_OPTIONS[1],
+    HTTP_OPTIONS[2],
+    HTTP_OPTIONS[3],
+    HTTP_OPTIONS[4],
+];
+
+pub(super) const HTTP_BODY_ARGS: &[CallArg] = &[
+    required("url", STRING),
+    optional("body", ANY),
+    HTTP_OPTIONS[0],
+    HTTP_OPTIONS[1],
+    HTTP_OPTIONS[2],
+    HTTP_OPTIONS[3],
+    HTTP_OPTIONS[4],
+];
+
+pub(super) const HTTP_REQUEST_ARGS: &[CallArg] = &[
+    required("method", STRING),
+    required("url", STRING),
+    optional("body", ANY),
+    HTTP_OPTIONS[0],
+    HTTP_OPTIONS[1],
+    HTTP_OPTIONS[2],
+    HTTP_OPTIONS[3],
+    HTTP_OPTIONS[4],
+];
+
+/// The response of an `http.*` call: `body`, `code` and `headers`
+pub(super) const HTTP_RESPONSE: &str = "The response's `body`, `code` and `headers` as a map.";
+
+/// Methods `http.request` accepts, matched without regard to case
+pub const HTTP_METHODS: &[&str] = &["GET", "HEAD", "POST", "PUT", "DELETE", "PATCH", "OPTIONS"];
+
+/// Values of an `http.*` request's `auth.type`
+pub const HTTP_AUTH_TYPES: &[&str] = &["OIDC", "OAuth2"];
+
+/// Keys of an `http.*` request's `auth` mapping
+pub const HTTP_AUTH_ARGS: &[CallArg] = &[
+    required("type", STRING),
+    optional("audience", STRING),
+    optional("scopes", ANY),
+    optional("scope", STRING),
+];
+
+/// Longest `timeout` an `http.*` request may set, in seconds
+pub const MAX_HTTP_TIMEOUT: f64 = 1800.0;
+
+/// Whether a call is an HTTP request whose `args:` get the checks above
+pub fn is_http_request(call: &str) -> bool {
+    matches!(
+        call,
+        "http.get" | "http.post" | "http.put" | "http.delete" | "http.patch" | "http.request"
+    )
+}
@@ -1,10 +1,19 @@
 //! Schema module for GCP Workflows definitions
 
+mod connectors;
+mod http;
+mod overlay;
+mod stdlib;
 mod workflows;
 
+pub use connectors::{connector, Connector, CONNECTORS};
+pub use http::{is_http_request, HTTP_AUTH_ARGS, HTTP_AUTH_TYPES, HTTP_METHODS, MAX_HTTP_TIMEOUT};
+pub use overlay::{PathSegment, SchemaOverlay, Violation};
+pub use stdlib::{
+    module_functions, stdlib_function, StdlibFunction, STDLIB_FUNCTIONS, STDLIB_MODULES,
+};
 pub use workflows::{
-    is_step_action, is_workflow_keyword, step_action_set, workflow_keyword_set, CALL_STEP_KEYWORDS,
-    FOR_STEP_KEYWORDS, PARALLEL_STEP_KEYWORDS, RETRY_KEYWORDS, STEP_ACTION_KEYWORDS,
-    SUBWORKFLOW_KEYWORDS, SWITCH_CONDITION_KEYWORDS, SWITCH_STEP_KEYWORDS, TRY_STEP_KEYWORDS,
-    WORKFLOW_KEYWORDS,
+    call_args, error_field, field, is_step_action, is_workflow_keyword, keyword_doc, return_type,
+    Block, CallArg, ErrorField, ExprType, Field, StepKind, ValueType, CALL_LOG_LEVELS,
+    ERROR_FIELDS,
 };
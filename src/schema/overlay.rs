@@ -0,0 +1,406 @@
+//! User-supplied JSON Schema overlay
+//!
+//! Organizations often put their own top-level keys in workflow templates,
+//! such as ownership metadata a deployment pipeline strips before deploying.
+//! A JSON Schema named by the `schema` setting or the workspace
+//! configuration declares them: each top-level property it lists is checked
+//! against its schema rather than reported as an unknown workflow element,
+//! and its top-level `required` keys must be present. Listing a workflow
+//! such as `main` replaces the built-in checks of that block.
+//!
+//! The common keywords are understood: `type`, `enum`, `const`,
+//! `properties`, `patternProperties`, `additionalProperties`, `required`,
+//! `items`, `minItems`, `maxItems`, `minimum`, `maximum`, `minLength`,
+//! `maxLength`, `pattern`, `allOf`, `anyOf` and `$ref`s within the file.
+//! Others are ignored. Values written as expressions match any schema, since
+//! they are only known once rendered.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use regex::Regex;
+use serde_json::Value as Json;
+use serde_yaml::Value;
+
+/// A JSON Schema extending the built-in workflow validation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaOverlay {
+    schema: Json,
+}
+
+/// Where in a value a [`Violation`] is
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+/// A way a value fails its schema
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// Path from the checked value to the offending node
+    pub path: Vec<PathSegment>,
+    pub message: String,
+}
+
+impl SchemaOverlay {
+    /// Use `schema`, which must be a JSON object
+    pub fn new(schema: Json) -> Result<Self, String> {
+        if !schema.is_object() {
+            return Err("schema must be a JSON object".to_string());
+        }
+        Ok(Self { schema })
+    }
+
+    /// Read a schema file
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let schema = serde_json::from_str(&text)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Self::new(schema).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// The schema of a top-level key, if the overlay declares it
+    pub fn property(&self, key: &str) -> Option<&Json> {
+        if let Some(schema) = self.schema.get("properties").and_then(|p| p.get(key)) {
+            return Some(schema);
+        }
+        self.schema
+            .get("patternProperties")
+            .and_then(Json::as_object)?
+            .iter()
+            .find(|(pattern, _)| Regex::new(pattern).is_ok_and(|re| re.is_match(key)))
+            .map(|(_, schema)| schema)
+    }
+
+    /// The top-level keys the overlay requires
+    pub fn required(&self) -> impl Iterator<Item = &str> {
+        self.schema
+            .get("required")
+            .and_then(Json::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(Json::as_str)
+    }
+
+    /// Check `value` against `schema`, one of the overlay's subschemas
+    pub fn validate(&self, schema: &Json, value: &Value) -> Vec<Violation> {
+        let mut violations = Vec::new();
+        self.check(schema, value, &mut Vec::new(), &mut violations);
+        violations
+    }
+
+    fn check(
+        &self,
+        schema: &Json,
+        value: &Value,
+        path: &mut Vec<PathSegment>,
+        out: &mut Vec<Violation>,
+    ) {
+        let schema = match self.resolve(schema) {
+            Json::Bool(false) => return violate(out, path, "No value is allowed here".to_string()),
+            Json::Object(schema) => schema,
+            _ => return,
+        };
+        if is_expression(value) {
+            return;
+        }
+
+        if let Some(expected) = schema.get("type") {
+            let types: Vec<&str> = match expected {
+                Json::String(t) => vec![t.as_str()],
+                Json::Array(ts) => ts.iter().filter_map(Json::as_str).collect(),
+                _ => Vec::new(),
+            };
+            if !types.is_empty() && !types.iter().any(|t| has_type(value, t)) {
+                return violate(
+                    out,
+                    path,
+                    format!(
+                        "Expected {}, found {}",
+                        types.join(" or "),
+                        type_name(value)
+                    ),
+                );
+            }
+        }
+        let json = serde_json::to_value(value).ok();
+        if let (Some(options), Some(json)) = (schema.get("enum").and_then(Json::as_array), &json) {
+            if !options.contains(json) {
+                let options: Vec<String> = options.iter().map(Json::to_string).collect();
+                violate(out, path, format!("Expected one of {}", options.join(", ")));
+            }
+        }
+        if let (Some(expected), Some(json)) = (schema.get("const"), &json) {
+            if expected != json {
+                violate(out, path, format!("Expected {}", expected));
+            }
+        }
+        let limit = |key: &str| schema.get(key).and_then(Json::as_f64);
+
+        match value {
+            Value::String(s) => {
+                let len = s.chars().count() as f64;
+                if limit("minLength").is_some_and(|min| len < min) {
+                    violate(
+                        out,
+                        path,
+                        format!("Expected at least {} characters", schema["minLength"]),
+                    );
+                }
+                if limit("maxLength").is_some_and(|max| len > max) {
+                    violate(
+                        out,
+                        path,
+                        format!("Expected at most {} characters", schema["maxLength"]),
+                    );
+                }
+                if let Some(pattern) = schema.get("pattern").and_then(Json::as_str) {
+                    if Regex::new(pattern).is_ok_and(|re| !re.is_match(s)) {
+                        violate(
+                            out,
+                            path,
+                            format!("Expected a value matching '{}'", pattern),
+                        );
+                    }
+                }
+            }
+            Value::Number(n) => {
+                let n = n.as_f64().unwrap_or_default();
+                if limit("minimum").is_some_and(|min| n < min) {
+                    violate(
+                        out,
+                        path,
+                        format!("Expected at least {}", schema["minimum"]),
+                    );
+                }
+                if limit("maximum").is_some_and(|max| n > max) {
+                    violate(out, path, format!("Expected at most {}", schema["maximum"]));
+                }
+            }
+            Value::Sequence(items) => {
+                let len = items.len() as f64;
+                if limit("minItems").is_some_and(|min| len < min) {
+                    violate(
+                        out,
+                        path,
+                        format!("Expected at least {} items", schema["minItems"]),
+                    );
+                }
+                if limit("maxItems").is_some_and(|max| len > max) {
+                    violate(
+                        out,
+                        path,
+                        format!("Expected at most {} items", schema["maxItems"]),
+                    );
+                }
+                if let Some(item_schema) = schema.get("items") {
+                    for (i, item) in items.iter().enumerate() {
+                        path.push(PathSegment::Index(i));
+                        self.check(item_schema, item, path, out);
+                        path.pop();
+                    }
+                }
+            }
+            Value::Mapping(mapping) => {
+                for key in schema
+                    .get("required")
+                    .and_then(Json::as_array)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(Json::as_str)
+                {
+                    if !mapping.contains_key(key) {
+                        violate(out, path, format!("Missing required property '{}'", key));
+                    }
+                }
+                for (key, item) in mapping {
+                    let Some(key) = key.as_str() else {
+                        continue;
+                    };
+                    let declared = schema.get("properties").and_then(|p| p.get(key));
+                    let patterned: Vec<&Json> = schema
+                        .get("patternProperties")
+                        .and_then(Json::as_object)
+                        .into_iter()
+                        .flatten()
+                        .filter(|(pattern, _)| Regex::new(pattern).is_ok_and(|re| re.is_match(key)))
+                        .map(|(_, schema)| schema)
+                        .collect();
+                    path.push(PathSegment::Key(key.to_string()));
+                    match (declared, schema.get("additionalProperties")) {
+                        (Some(property), _) => self.check(property, item, path, out),
+                        (None, Some(Json::Bool(false))) if patterned.is_empty() => {
+                            violate(out, path, format!("Property '{}' is not allowed", key))
+                        }
+                        (None, Some(additional)) if patterned.is_empty() => {
+                            self.check(additional, item, path, out)
+                        }
+                        _ => {}
+                    }
+                    for pattern_schema in patterned {
+                        self.check(pattern_schema, item, path, out);
+                    }
+                    path.pop();
+                }
+            }
+            _ => {}
+        }
+
+        for branch in schema
+            .get("allOf")
+            .and_then(Json::as_array)
+            .into_iter()
+            .flatten()
+        {
+            self.check(branch, value, path, out);
+        }
+        if let Some(branches) = schema.get("anyOf").and_then(Json::as_array) {
+            let matches = |branch| {
+                let mut violations = Vec::new();
+                self.check(branch, value, &mut path.clone(), &mut violations);
+                violations.is_empty()
+            };
+            if !branches.is_empty() && !branches.iter().any(matches) {
+                violate(
+                    out,
+                    path,
+                    "Does not match any of the allowed schemas".to_string(),
+                );
+            }
+        }
+    }
+
+    /// Follow `$ref`s to other parts of the overlay, such as `#/$defs/owner`
+    fn resolve<'s>(&'s self, mut schema: &'s Json) -> &'s Json {
+        // Bounded, so a reference cycle can't hang validation
+        for _ in 0..32 {
+            let target = schema
+                .get("$ref")
+                .and_then(Json::as_str)
+                .and_then(|r| r.strip_prefix('#'))
+                .and_then(|pointer| self.schema.pointer(pointer));
+            match target {
+                Some(target) => schema = target,
+                None => break,
+            }
+        }
+        schema
+    }
+}
+
+fn violate(out: &mut Vec<Violation>, path: &[PathSegment], message: String) {
+    out.push(Violation {
+        path: path.to_vec(),
+        message,
+    });
+}
+
+/// Whether a value is, or contains, an expression placeholder
+fn is_expression(value: &Value) -> bool {
+    value.as_str().is_some_and(|s| s.contains("__EXPR_"))
+}
+
+fn has_type(value: &Value, name: &str) -> bool {
+    match name {
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => {
+            value.as_i64().is_some()
+                || value.as_u64().is_some()
+                || value.as_f64().is_some_and(|f| f.fract() == 0.0)
+        }
+        "boolean" => value.is_bool(),
+        "object" => value.is_mapping(),
+        "array" => value.is_sequence(),
+        "null" => value.is_null(),
+        _ => true,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Sequence(_) => "array",
+        Value::Mapping(_) => "object",
+        Value::Tagged(_) => "tagged value",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn overlay() -> SchemaOverlay {
+        SchemaOverlay::new(json!({
+            "properties": {
+                "metadata": { "$ref": "#/$defs/metadata" }
+            },
+            "patternProperties": { "^x-": true },
+            "required": ["metadata"],
+            "$defs": {
+                "metadata": {
+                    "type": "object",
+                    "required": ["owner"],
+                    "properties": {
+                        "owner": { "type": "string", "pattern": "^team-" },
+                        "tier": { "enum": ["gold", "silver"] },
+                        "tags": { "type": "array", "items": { "type": "string" } }
+                    },
+                    "additionalProperties": false
+                }
+            }
+        }))
+        .unwrap()
+    }
+
+    fn check(yaml: &str) -> Vec<Violation> {
+        let overlay = overlay();
+        let value: Value = serde_yaml::from_str(yaml).unwrap();
+        overlay.validate(overlay.property("metadata").unwrap(), &value)
+    }
+
+    #[test]
+    fn test_declared_keys() {
+        let overlay = overlay();
+        assert!(overlay.property("metadata").is_some());
+        assert!(overlay.property("x-team").is_some());
+        assert!(overlay.property("main").is_none());
+        assert_eq!(overlay.required().collect::<Vec<_>>(), vec!["metadata"]);
+        assert!(SchemaOverlay::new(json!([])).is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(check("owner: team-data\ntier: gold\ntags: [a, b]").is_empty());
+        // Expressions match anything
+        assert!(check("owner: __EXPR_000__\ntier: __EXPR_001__").is_empty());
+
+        let messages: Vec<String> = check("tier: bronze\ntags: [a, 1]\nextra: true")
+            .into_iter()
+            .map(|v| v.message)
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                "Missing required property 'owner'",
+                "Expected one of \"gold\", \"silver\"",
+                "Expected string, found number",
+                "Property 'extra' is not allowed",
+            ]
+        );
+
+        let violations = check("owner: data\ntags: [1]");
+        assert_eq!(violations[0].path, vec![PathSegment::Key("owner".into())]);
+        assert_eq!(
+            violations[1].path,
+            vec![PathSegment::Key("tags".into()), PathSegment::Index(0)]
+        );
+        assert_eq!(check("[]")[0].message, "Expected object, found array");
+    }
+}
@@ -0,0 +1,527 @@
+//! GCP Workflows standard library catalog
+//!
+//! Every standard library function with its qualified name, parameters,
+//! return type and a one-line description. The catalog drives completion
+//! inside `$${...}`, hover, the `args:` checks of call steps and the types
+//! expression checks infer.
+
+use super::http::{HTTP_BODY_ARGS, HTTP_GET_ARGS, HTTP_REQUEST_ARGS, HTTP_RESPONSE};
+use super::workflows::{
+    optional, required, CallArg, ExprType, ANY, BOOLEAN, INTEGER, LIST, MAPPING, NUMBER, STRING,
+};
+
+/// A standard library function, callable from a step's `call:` and,
+/// unless it blocks, from an expression
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StdlibFunction {
+    /// Qualified name, such as `text.split`, or a bare name for built-ins
+    pub name: &'static str,
+    pub params: &'static [CallArg],
+    /// Type of the result, `None` when it depends on the arguments
+    pub returns: Option<ExprType>,
+    pub description: &'static str,
+    /// Blocking calls, such as `http.get`, only run as a step's `call:`
+    pub blocking: bool,
+}
+
+impl StdlibFunction {
+    /// The module the function belongs to, `None` for built-ins like `len`
+    pub fn module(&self) -> Option<&'static str> {
+        self.name.rsplit_once('.').map(|(module, _)| module)
+    }
+
+    /// The name without its module
+    pub fn short_name(&self) -> &'static str {
+        self.name
+            .rsplit_once('.')
+            .map_or(self.name, |(_, name)| name)
+    }
+
+    /// The call signature, e.g. `text.split(source, separator)`
+    pub fn signature(&self) -> String {
+        let params: Vec<&str> = self.params.iter().map(|param| param.name).collect();
+        format!("{}({})", self.name, params.join(", "))
+    }
+
+    /// Whether the function can be called inside an expression
+    pub fn in_expressions(&self) -> bool {
+        !self.blocking
+    }
+}
+
+const fn function(
+    name: &'static str,
+    params: &'static [CallArg],
+    returns: Option<ExprType>,
+    description: &'static str,
+) -> StdlibFunction {
+    StdlibFunction {
+        name,
+        params,
+        returns,
+        description,
+        blocking: false,
+    }
+}
+
+const fn blocking(
+    name: &'static str,
+    params: &'static [CallArg],
+    returns: Option<ExprType>,
+    description: &'static str,
+) -> StdlibFunction {
+    StdlibFunction {
+        name,
+        params,
+        returns,
+        description,
+        blocking: true,
+    }
+}
+
+/// Modules of the standard library, in the order completion offers them
+pub const STDLIB_MODULES: &[&str] = &[
+    "sys",
+    "http",
+    "text",
+    "math",
+    "list",
+    "map",
+    "json",
+    "base64",
+    "time",
+    "uuid",
+    "events",
+    "retry",
+    "experimental.executions",
+];
+
+/// The standard library, built-ins first, then by module
+pub const STDLIB_FUNCTIONS: &[StdlibFunction] = &[
+    function(
+        "default",
+        &[required("value", ANY), required("default_value", ANY)],
+        None,
+        "`value`, or `default_value` when it is null or missing.",
+    ),
+    function(
+        "double",
+        &[required("value", ANY)],
+        Some(ExprType::Double),
+        "Convert a string or int to a double.",
+    ),
+    function(
+        "int",
+        &[required("value", ANY)],
+        Some(ExprType::Int),
+        "Convert a string or double to an int.",
+    ),
+    function(
+        "string",
+        &[required("value", ANY)],
+        Some(ExprType::String),
+        "Convert a number or boolean to a string.",
+    ),
+    function(
+        "keys",
+        &[required("map", MAPPING)],
+        Some(ExprType::List),
+        "List of the keys of a map.",
+    ),
+    function(
+        "len",
+        &[required("value", ANY)],
+        Some(ExprType::Int),
+        "Length of a string, list, map or bytes value.",
+    ),
+    function(
+        "get_type",
+        &[required("value", ANY)],
+        Some(ExprType::String),
+        "Name of the value's type, such as `string` or `map`.",
+    ),
+    function(
+        "sys.get_env",
+        &[required("name", STRING)],
+        Some(ExprType::String),
+        "Value of a built-in environment variable, such as `GOOGLE_CLOUD_PROJECT_ID`.",
+    ),
+    blocking(
+        "sys.log",
+        &[
+            optional("data", ANY),
+            optional("text", ANY),
+            optional("json", ANY),
+            optional("severity", STRING),
+            optional("timeout", NUMBER),
+        ],
+        None,
+        "Write an entry to Cloud Logging.",
+    ),
+    function(
+        "sys.now",
+        &[],
+        Some(ExprType::Double),
+        "Seconds since the Unix epoch, as a double.",
+    ),
+    blocking(
+        "sys.sleep",
+        &[required("seconds", NUMBER)],
+        None,
+        "Pause the execution for a number of seconds.",
+    ),
+    blocking(
+        "sys.sleep_until",
+        &[required("time", STRING)],
+        None,
+        "Pause the execution until an RFC 3339 timestamp.",
+    ),
+    blocking(
+        "http.delete",
+        HTTP_BODY_ARGS,
+        Some(ExprType::Map),
+        HTTP_RESPONSE,
+    ),
+    blocking(
+        "http.get",
+        HTTP_GET_ARGS,
+        Some(ExprType::Map),
+        HTTP_RESPONSE,
+    ),
+    blocking(
+        "http.patch",
+        HTTP_BODY_ARGS,
+        Some(ExprType::Map),
+        HTTP_RESPONSE,
+    ),
+    blocking(
+        "http.post",
+        HTTP_BODY_ARGS,
+        Some(ExprType::Map),
+        HTTP_RESPONSE,
+    ),
+    blocking(
+        "http.put",
+        HTTP_BODY_ARGS,
+        Some(ExprType::Map),
+        HTTP_RESPONSE,
+    ),
+    blocking(
+        "http.request",
+        HTTP_REQUEST_ARGS,
+        Some(ExprType::Map),
+        HTTP_RESPONSE,
+    ),
+    function(
+        "http.default_retry_predicate",
+        &[required("e", ANY)],
+        Some(ExprType::Boolean),
+        "Whether an error from an idempotent request is worth retrying.",
+    ),
+    function(
+        "http.default_retry_predicate_non_idempotent",
+        &[required("e", ANY)],
+        Some(ExprType::Boolean),
+        "Whether an error from a non-idempotent request is worth retrying.",
+    ),
+    function(
+        "text.decode",
+        &[required("data", ANY), optional("charset", STRING)],
+        Some(ExprType::String),
+        "Decode bytes into a string.",
+    ),
+    function(
+        "text.encode",
+        &[required("text", STRING), optional("charset", STRING)],
+        Some(ExprType::Bytes),
+        "Encode a string into bytes.",
+    ),
+    function(
+        "text.find_all",
+        &[required("source", STRING), required("substr", STRING)],
+        Some(ExprType::List),
+        "Every occurrence of `substr` in `source`, with its index.",
+    ),
+    function(
+        "text.find_all_regex",
+        &[required("source", STRING), required("regexp", STRING)],
+        Some(ExprType::List),
+        "Every match of `regexp` in `source`, with its index.",
+    ),
+    function(
+        "text.match_regex",
+        &[required("source", STRING), required("regexp", STRING)],
+        Some(ExprType::Boolean),
+        "Whether `regexp` matches somewhere in `source`.",
+    ),
+    function(
+        "text.replace_all",
+        &[
+            required("source", STRING),
+            required("substr", STRING),
+            required("repl", STRING),
+        ],
+        Some(ExprType::String),
+        "`source` with every `substr` replaced by `repl`.",
+    ),
+    function(
+        "text.replace_all_regex",
+        &[
+            required("source", STRING),
+            required("regexp", STRING),
+            required("repl", STRING),
+        ],
+        Some(ExprType::String),
+        "`source` with every match of `regexp` replaced by `repl`.",
+    ),
+    function(
+        "text.split",
+        &[required("source", STRING), required("separator", STRING)],
+        Some(ExprType::List),
+        "Split a string into a list at each `separator`.",
+    ),
+    function(
+        "text.substring",
+        &[
+            required("source", STRING),
+            required("start", INTEGER),
+            required("end", INTEGER),
+        ],
+        Some(ExprType::String),
+        "Characters of `source` from `start` up to `end`.",
+    ),
+    function(
+        "text.to_lower",
+        &[required("source", STRING)],
+        Some(ExprType::String),
+        "`source` in lower case.",
+    ),
+    function(
+        "text.to_upper",
+        &[required("source", STRING)],
+        Some(ExprType::String),
+        "`source` in upper case.",
+    ),
+    function(
+        "text.url_decode",
+        &[required("source", STRING)],
+        Some(ExprType::String),
+        "Decode a percent-encoded string.",
+    ),
+    function(
+        "text.url_encode",
+        &[required("source", STRING)],
+        Some(ExprType::String),
+        "Percent-encode a string, spaces as `%20`.",
+    ),
+    function(
+        "text.url_encode_plus",
+        &[required("source", STRING)],
+        Some(ExprType::String),
+        "Percent-encode a string, spaces as `+`.",
+    ),
+    function(
+        "math.abs",
+        &[required("x", NUMBER)],
+        None,
+        "Absolute value of a number.",
+    ),
+    function(
+        "math.floor",
+        &[required("x", NUMBER)],
+        Some(ExprType::Int),
+        "Largest int not greater than `x`.",
+    ),
+    function(
+        "math.max",
+        &[required("x", NUMBER), required("y", NUMBER)],
+        None,
+        "The larger of two numbers.",
+    ),
+    function(
+        "math.min",
+        &[required("x", NUMBER), required("y", NUMBER)],
+        None,
+        "The smaller of two numbers.",
+    ),
+    function(
+        "math.sqrt",
+        &[required("x", NUMBER)],
+        Some(ExprType::Double),
+        "Square root of a number, as a double.",
+    ),
+    function(
+        "list.concat",
+        &[required("objs", LIST), required("val", ANY)],
+        Some(ExprType::List),
+        "`objs` with `val` appended.",
+    ),
+    function(
+        "list.prepend",
+        &[required("objs", LIST), required("val", ANY)],
+        Some(ExprType::List),
+        "`objs` with `val` inserted at the front.",
+    ),
+    function(
+        "map.delete",
+        &[required("map", MAPPING), required("key", STRING)],
+        Some(ExprType::Map),
+        "A copy of `map` without `key`.",
+    ),
+    function(
+        "map.get",
+        &[required("map", MAPPING), required("keys", ANY)],
+        None,
+        "The value at `keys` (a key or a list of nested keys), or null.",
+    ),
+    function(
+        "map.merge",
+        &[required("first", MAPPING), required("second", MAPPING)],
+        Some(ExprType::Map),
+        "`first` with the keys of `second` added or replaced.",
+    ),
+    function(
+        "map.merge_nested",
+        &[required("first", MAPPING), required("second", MAPPING)],
+        Some(ExprType::Map),
+        "Like `map.merge`, merging nested maps too.",
+    ),
+    function(
+        "json.decode",
+        &[required("data", ANY)],
+        None,
+        "Parse JSON bytes or a JSON string into a value.",
+    ),
+    function(
+        "json.encode",
+        &[required("data", ANY), optional("indent", ANY)],
+        Some(ExprType::Bytes),
+        "Encode a value as JSON bytes.",
+    ),
+    function(
+        "json.encode_to_string",
+        &[required("data", ANY), optional("indent", ANY)],
+        Some(ExprType::String),
+        "Encode a value as a JSON string.",
+    ),
+    function(
+        "base64.decode",
+        &[required("data", STRING), optional("padding", BOOLEAN)],
+        Some(ExprType::Bytes),
+        "Decode a base64 string into bytes.",
+    ),
+    function(
+        "base64.encode",
+        &[required("data", ANY), optional("padding", BOOLEAN)],
+        Some(ExprType::String),
+        "Encode bytes as a base64 string.",
+    ),
+    function(
+        "time.format",
+        &[required("seconds", NUMBER), optional("timezone", STRING)],
+        Some(ExprType::String),
+        "Format seconds since the epoch as an RFC 3339 timestamp.",
+    ),
+    function(
+        "time.parse",
+        &[required("value", STRING)],
+        Some(ExprType::Double),
+        "Seconds since the epoch of an RFC 3339 timestamp.",
+    ),
+    function(
+        "uuid.generate",
+        &[],
+        Some(ExprType::String),
+        "A random version 4 UUID string.",
+    ),
+    blocking(
+        "events.create_callback_endpoint",
+        &[optional("http_callback_method", STRING)],
+        Some(ExprType::Map),
+        "Create an endpoint that resumes the execution when called; its `url` is in the result.",
+    ),
+    blocking(
+        "events.await_callback",
+        &[required("callback", MAPPING), optional("timeout", NUMBER)],
+        Some(ExprType::Map),
+        "Wait for a request to a callback endpoint, returning it as a map.",
+    ),
+    function(
+        "retry.always",
+        &[required("e", ANY)],
+        Some(ExprType::Boolean),
+        "Retry predicate that retries every error.",
+    ),
+    function(
+        "retry.never",
+        &[required("e", ANY)],
+        Some(ExprType::Boolean),
+        "Retry predicate that retries no error.",
+    ),
+    blocking(
+        "experimental.executions.run",
+        &[required("workflow_id", STRING), optional("argument", ANY)],
+        None,
+        "Run a workflow and wait for its result.",
+    ),
+    blocking(
+        "experimental.executions.map",
+        &[required("workflow_id", STRING), required("arguments", LIST)],
+        Some(ExprType::List),
+        "Run a workflow once per argument in parallel, returning every result.",
+    ),
+];
+
+/// Look up a standard library function by its qualified name
+pub fn stdlib_function(name: &str) -> Option<&'static StdlibFunction> {
+    STDLIB_FUNCTIONS.iter().find(|f| f.name == name)
+}
+
+/// Functions of `module`, or the built-ins when `module` is `None`
+pub fn module_functions(
+    module: Option<&str>,
+) -> impl Iterator<Item = &'static StdlibFunction> + '_ {
+    STDLIB_FUNCTIONS
+        .iter()
+        .filter(move |f| f.module() == module)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_catalog_is_consistent() {
+        for function in STDLIB_FUNCTIONS {
+            assert!(
+                function
+                    .module()
+                    .is_none_or(|m| STDLIB_MODULES.contains(&m)),
+                "{} is in no module",
+                function.name
+            );
+            assert_eq!(stdlib_function(function.name), Some(function));
+        }
+        for module in STDLIB_MODULES {
+            assert!(
+                module_functions(Some(module)).next().is_some(),
+                "{}",
+                module
+            );
+        }
+    }
+
+    #[test]
+    fn test_stdlib_function() {
+        let run = stdlib_function("experimental.executions.run").unwrap();
+        assert_eq!(run.module(), Some("experimental.executions"));
+        assert_eq!(run.short_name(), "run");
+        assert!(!run.in_expressions());
+
+        let substring = stdlib_function("text.substring").unwrap();
+        assert_eq!(substring.signature(), "text.substring(source, start, end)");
+        assert_eq!(substring.returns, Some(ExprType::String));
+        assert!(substring.in_expressions());
+        assert!(stdlib_function("text.nope").is_none());
+    }
+}
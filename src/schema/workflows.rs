@@ -1,127 +1,562 @@
 //! GCP Workflows keywords and structure definitions
 //!
 //! This module defines the schema rules and validation structures for
-//! Google Cloud Workflows syntax.
+//! Google Cloud Workflows syntax. Each kind of mapping is a [`Block`] listing
+//! the [`Field`]s it takes, and the validator, completion and hover all read
+//! keys, types and documentation from there.
 
-use std::collections::HashSet;
+use super::connectors::connector;
+use super::stdlib::stdlib_function;
 
-/// Reserved keywords in Google Cloud Workflows syntax
-pub const WORKFLOW_KEYWORDS: &[&str] = &[
-    // Step types
+/// A key of a Workflows mapping, with what its value holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Field {
+    pub name: &'static str,
+    /// Whether the mapping must have the key
+    pub required: bool,
+    /// Expected type of the value, `None` when it has no fixed shape
+    pub value_type: Option<ValueType>,
+    /// Short documentation, shown on hover and in completion
+    pub doc: &'static str,
+}
+
+const fn optional_field(
+    name: &'static str,
+    value_type: Option<ValueType>,
+    doc: &'static str,
+) -> Field {
+    Field {
+        name,
+        required: false,
+        value_type,
+        doc,
+    }
+}
+
+const fn required_field(
+    name: &'static str,
+    value_type: Option<ValueType>,
+    doc: &'static str,
+) -> Field {
+    Field {
+        name,
+        required: true,
+        value_type,
+        doc,
+    }
+}
+
+const MAIN: Field = required_field("main", MAPPING, "Entry point of the workflow.");
+const PARAMS: Field = optional_field("params", LIST, "Parameters accepted by the workflow.");
+const STEPS: Field = optional_field("steps", LIST, "The list of steps to run.");
+const LABELS: Field = optional_field(
+    "labels",
+    MAPPING,
+    "Key/value labels attached to the workflow definition.",
+);
+const ASSIGN: Field = optional_field(
     "assign",
+    LIST,
+    "Assign values to variables. A list of `name: value` mappings.",
+);
+const CALL: Field = optional_field(
     "call",
-    "switch",
-    "for",
-    "parallel",
-    "try",
-    "raise",
-    "return",
-    "next",
-    // Call step
+    STRING,
+    "Call a subworkflow, standard library function or connector.",
+);
+const ARGS: Field = optional_field(
     "args",
+    MAPPING,
+    "Arguments passed to the called function or subworkflow.",
+);
+const RESULT: Field = optional_field(
     "result",
-    // Switch step
+    STRING,
+    "Variable that receives the result of the call.",
+);
+const CALL_LOG_LEVEL: Field = optional_field(
+    "call_log_level",
+    STRING,
+    "Call logging for this step: `LOG_ALL_CALLS`, `LOG_ERRORS_ONLY` or `LOG_NONE`. Overrides the execution's call log level.",
+);
+const SWITCH: Field = optional_field(
+    "switch",
+    LIST,
+    "Conditional jumps. A list of `condition` entries evaluated in order.",
+);
+const CONDITION: Field = required_field(
     "condition",
-    // For loop
+    ANY,
+    "Expression that selects this `switch` entry when true.",
+);
+const FOR: Field = optional_field(
+    "for",
+    MAPPING,
+    "Iterate over a list (`in`) or a numeric `range`.",
+);
+const VALUE: Field = required_field(
     "value",
-    "index",
+    STRING,
+    "Loop variable holding the current element.",
+);
+const INDEX: Field = optional_field("index", STRING, "Loop variable holding the current index.");
+const RANGE: Field = optional_field(
     "range",
-    "in",
-    // Parallel step
+    ANY,
+    "Inclusive `[start, end]` numeric range to iterate over.",
+);
+const IN: Field = optional_field("in", ANY, "List to iterate over.");
+const PARALLEL: Field = optional_field(
+    "parallel",
+    MAPPING,
+    "Run `branches` or a `for` loop concurrently.",
+);
+const BRANCHES: Field = optional_field(
     "branches",
+    LIST,
+    "Branches that run concurrently, each with its own `steps`.",
+);
+const SHARED: Field = optional_field(
     "shared",
+    Some(ValueType::ListOfStrings),
+    "Variables from the enclosing scope that parallel branches may assign.",
+);
+const CONCURRENCY_LIMIT: Field = optional_field(
     "concurrency_limit",
+    INTEGER,
+    "Maximum number of branches or iterations running at once.",
+);
+const EXCEPTION_POLICY: Field = optional_field(
     "exception_policy",
-    // Try/except
+    ANY,
+    "How errors in one branch affect the others (`continueAll`).",
+);
+const TRY: Field = optional_field(
+    "try",
+    ANY,
+    "Steps whose errors are handled by `except` or `retry`.",
+);
+const EXCEPT: Field = optional_field(
     "except",
+    ANY,
+    "Error handler for a `try` block, with the error bound to `as`.",
+);
+const AS: Field = required_field("as", ANY, "Variable that receives the caught error.");
+const RETRY: Field = optional_field(
     "retry",
-    "as",
-    // Retry policy
+    ANY,
+    "Retry policy for a `try` block: a predicate and backoff.",
+);
+const PREDICATE: Field = optional_field(
     "predicate",
-    "max_retries",
+    ANY,
+    "Expression or function deciding whether an error is retried.",
+);
+const MAX_RETRIES: Field = optional_field("max_retries", INTEGER, "Maximum number of retries.");
+const BACKOFF: Field = optional_field(
     "backoff",
+    MAPPING,
+    "Delay between retries: `initial_delay`, `max_delay` and `multiplier`.",
+);
+const INITIAL_DELAY: Field = optional_field(
     "initial_delay",
+    NUMBER,
+    "Delay before the first retry, in seconds.",
+);
+const MAX_DELAY: Field = optional_field(
     "max_delay",
+    NUMBER,
+    "Upper bound on the delay between retries, in seconds.",
+);
+const MULTIPLIER: Field = optional_field(
     "multiplier",
-    // Workflow structure
-    "main",
-    "params",
-    "steps",
-];
+    NUMBER,
+    "Factor applied to the delay after each retry.",
+);
+const RAISE: Field = optional_field(
+    "raise",
+    ANY,
+    "Raise an error, either a string or a mapping.",
+);
+const RETURN: Field = optional_field(
+    "return",
+    ANY,
+    "Stop the workflow and return a value to the caller.",
+);
+const NEXT: Field = optional_field(
+    "next",
+    STRING,
+    "Jump to another step, or `end`, `break` or `continue`.",
+);
 
-/// Step action keywords - valid inside a step definition
-pub const STEP_ACTION_KEYWORDS: &[&str] = &[
-    "assign", "call", "switch", "for", "parallel", "try", "raise", "return", "next",
-];
+/// A kind of mapping in a workflow definition
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Block {
+    /// The document itself, holding `main` and the subworkflows
+    Document,
+    /// `main` or a subworkflow
+    Workflow,
+    /// The body of a step
+    Step,
+    /// A `for` loop
+    For,
+    /// A `parallel` step's settings
+    Parallel,
+    /// An entry of a `switch` list
+    SwitchCondition,
+    /// An `except` handler
+    Except,
+    /// A custom `retry` policy
+    Retry,
+    /// A retry policy's `backoff`
+    Backoff,
+}
 
-/// Keywords valid inside a `call` step
-pub const CALL_STEP_KEYWORDS: &[&str] = &["call", "args", "result"];
+impl Block {
+    pub const ALL: &'static [Block] = &[
+        Block::Document,
+        Block::Workflow,
+        Block::Step,
+        Block::For,
+        Block::Parallel,
+        Block::SwitchCondition,
+        Block::Except,
+        Block::Retry,
+        Block::Backoff,
+    ];
 
-/// Keywords valid inside a `switch` step
-pub const SWITCH_STEP_KEYWORDS: &[&str] = &["switch"];
+    /// The keys the mapping takes, in the order completion offers them
+    pub fn fields(self) -> &'static [Field] {
+        match self {
+            Block::Document => &[MAIN],
+            Block::Workflow => &[
+                PARAMS,
+                Field {
+                    required: true,
+                    ..STEPS
+                },
+                LABELS,
+            ],
+            Block::Step => &[
+                CALL,
+                ARGS,
+                RESULT,
+                CALL_LOG_LEVEL,
+                ASSIGN,
+                SWITCH,
+                FOR,
+                PARALLEL,
+                TRY,
+                EXCEPT,
+                RETRY,
+                RAISE,
+                RETURN,
+                NEXT,
+                STEPS,
+            ],
+            Block::For => &[
+                VALUE,
+                INDEX,
+                RANGE,
+                IN,
+                Field {
+                    required: true,
+                    ..STEPS
+                },
+            ],
+            Block::Parallel => &[BRANCHES, SHARED, CONCURRENCY_LIMIT, EXCEPTION_POLICY, FOR],
+            Block::SwitchCondition => &[CONDITION, NEXT, STEPS, RETURN, RAISE, ASSIGN],
+            Block::Except => &[
+                AS,
+                Field {
+                    required: true,
+                    ..STEPS
+                },
+            ],
+            Block::Retry => &[PREDICATE, MAX_RETRIES, BACKOFF],
+            Block::Backoff => &[INITIAL_DELAY, MAX_DELAY, MULTIPLIER],
+        }
+    }
 
-/// Keywords valid inside switch conditions
-pub const SWITCH_CONDITION_KEYWORDS: &[&str] = &["condition", "next", "return", "raise", "steps"];
+    /// The field named `name`, if the mapping takes it
+    pub fn field(self, name: &str) -> Option<&'static Field> {
+        self.fields().iter().find(|field| field.name == name)
+    }
 
-/// Keywords valid inside a `for` loop
-pub const FOR_STEP_KEYWORDS: &[&str] = &["for", "value", "index", "range", "in", "steps"];
+    /// The names of the keys the mapping takes
+    pub fn names(self) -> impl Iterator<Item = &'static str> {
+        self.fields().iter().map(|field| field.name)
+    }
 
-/// Keywords valid inside a `parallel` step
-pub const PARALLEL_STEP_KEYWORDS: &[&str] = &[
-    "parallel",
-    "branches",
-    "shared",
-    "concurrency_limit",
-    "exception_policy",
-    "steps",
-];
+    /// The mapping as messages name it, e.g. "a 'switch' entry"
+    pub fn describe(self) -> &'static str {
+        match self {
+            Block::Document => "the top level of the document",
+            Block::Workflow => "a workflow block",
+            Block::Step => "a step",
+            Block::For => "a 'for' loop",
+            Block::Parallel => "a 'parallel' step",
+            Block::SwitchCondition => "a 'switch' entry",
+            Block::Except => "an 'except' block",
+            Block::Retry => "a 'retry' policy",
+            Block::Backoff => "a retry 'backoff'",
+        }
+    }
 
-/// Keywords valid inside a `try` block
-pub const TRY_STEP_KEYWORDS: &[&str] = &["try", "except", "retry", "steps", "as"];
+    /// The first mapping that takes a key named `name`
+    pub fn of(name: &str) -> Option<Block> {
+        Block::ALL
+            .iter()
+            .copied()
+            .find(|block| block.field(name).is_some())
+    }
+}
 
-/// Keywords valid for retry policy
-pub const RETRY_KEYWORDS: &[&str] = &[
-    "predicate",
-    "max_retries",
-    "backoff",
-    "initial_delay",
-    "max_delay",
-    "multiplier",
-];
+/// What a step does, named by its action key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepKind {
+    Assign,
+    Call,
+    Switch,
+    For,
+    Parallel,
+    Try,
+    Raise,
+    Return,
+    Next,
+}
+
+impl StepKind {
+    pub const ALL: &'static [StepKind] = &[
+        StepKind::Assign,
+        StepKind::Call,
+        StepKind::Switch,
+        StepKind::For,
+        StepKind::Parallel,
+        StepKind::Try,
+        StepKind::Raise,
+        StepKind::Return,
+        StepKind::Next,
+    ];
+
+    /// The key that makes a step this kind
+    pub fn action(self) -> &'static Field {
+        match self {
+            StepKind::Assign => &ASSIGN,
+            StepKind::Call => &CALL,
+            StepKind::Switch => &SWITCH,
+            StepKind::For => &FOR,
+            StepKind::Parallel => &PARALLEL,
+            StepKind::Try => &TRY,
+            StepKind::Raise => &RAISE,
+            StepKind::Return => &RETURN,
+            StepKind::Next => &NEXT,
+        }
+    }
+
+    /// The kind a step's action key makes it, if `key` is an action
+    pub fn from_key(key: &str) -> Option<StepKind> {
+        StepKind::ALL
+            .iter()
+            .copied()
+            .find(|kind| kind.action().name == key)
+    }
+}
+
+/// Values accepted by a step's `call_log_level`
+pub const CALL_LOG_LEVELS: &[&str] = &["LOG_ALL_CALLS", "LOG_ERRORS_ONLY", "LOG_NONE"];
+
+/// An argument accepted by a standard library call
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CallArg {
+    pub name: &'static str,
+    /// Expected type, `None` when any value is accepted
+    pub value_type: Option<ValueType>,
+    pub required: bool,
+}
 
-/// Keywords valid inside a subworkflow definition
-pub const SUBWORKFLOW_KEYWORDS: &[&str] = &["params", "steps"];
-
-/// Standard library connectors that can be called
-#[allow(dead_code)]
-pub const STDLIB_CONNECTORS: &[&str] = &[
-    "http.get",
-    "http.post",
-    "http.request",
-    "sys.get_env",
-    "sys.now",
-    "sys.sleep",
-    "sys.log",
+// Argument types, as the catalogs spell them
+pub(super) const ANY: Option<ValueType> = None;
+pub(super) const STRING: Option<ValueType> = Some(ValueType::String);
+pub(super) const NUMBER: Option<ValueType> = Some(ValueType::Number);
+pub(super) const INTEGER: Option<ValueType> = Some(ValueType::Integer);
+pub(super) const BOOLEAN: Option<ValueType> = Some(ValueType::Boolean);
+pub(super) const LIST: Option<ValueType> = Some(ValueType::List);
+pub(super) const MAPPING: Option<ValueType> = Some(ValueType::Mapping);
+
+pub(super) const fn required(name: &'static str, value_type: Option<ValueType>) -> CallArg {
+    CallArg {
+        name,
+        value_type,
+        required: true,
+    }
+}
+
+pub(super) const fn optional(name: &'static str, value_type: Option<ValueType>) -> CallArg {
+    CallArg {
+        name,
+        value_type,
+        required: false,
+    }
+}
+
+/// Get the argument schema of a standard library or connector call, if it
+/// has one
+pub fn call_args(call: &str) -> Option<&'static [CallArg]> {
+    stdlib_function(call)
+        .map(|function| function.params)
+        .or_else(|| connector(call).map(|connector| connector.params))
+}
+
+/// Expected type of a field's value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueType {
+    String,
+    Integer,
+    /// Integer or floating point
+    Number,
+    Boolean,
+    List,
+    ListOfStrings,
+    Mapping,
+}
+
+impl ValueType {
+    /// Human-readable type name used in diagnostics
+    pub fn name(&self) -> &'static str {
+        match self {
+            ValueType::String => "string",
+            ValueType::Integer => "integer",
+            ValueType::Number => "number",
+            ValueType::Boolean => "boolean",
+            ValueType::List => "list",
+            ValueType::ListOfStrings => "list of strings",
+            ValueType::Mapping => "mapping",
+        }
+    }
+
+    /// Check whether a parsed YAML value has this type
+    pub fn matches(&self, value: &serde_yaml::Value) -> bool {
+        use serde_yaml::Value;
+        match self {
+            ValueType::String => value.is_string(),
+            ValueType::Integer => value.is_i64() || value.is_u64(),
+            ValueType::Number => value.is_number(),
+            ValueType::Boolean => value.is_bool(),
+            ValueType::List => value.is_sequence(),
+            ValueType::ListOfStrings => value
+                .as_sequence()
+                .is_some_and(|items| items.iter().all(Value::is_string)),
+            ValueType::Mapping => value.is_mapping(),
+        }
+    }
+}
+
+/// Type of a value inside a Workflows expression
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExprType {
+    String,
+    Int,
+    Double,
+    Boolean,
+    Bytes,
+    List,
+    Map,
+    Null,
+}
+
+impl ExprType {
+    /// The type's name as Workflows spells it in errors
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExprType::String => "string",
+            ExprType::Int => "int",
+            ExprType::Double => "double",
+            ExprType::Boolean => "boolean",
+            ExprType::Bytes => "bytes",
+            ExprType::List => "list",
+            ExprType::Map => "map",
+            ExprType::Null => "null",
+        }
+    }
+
+    /// Whether the type is `int` or `double`
+    pub fn is_numeric(&self) -> bool {
+        matches!(self, ExprType::Int | ExprType::Double)
+    }
+}
+
+/// A field of the error map an `except` block binds to its `as` variable
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ErrorField {
+    pub name: &'static str,
+    /// Type of the field, `None` when it depends on the error
+    pub value_type: Option<ExprType>,
+    pub doc: &'static str,
+}
+
+/// Fields of a caught error; which ones are set depends on the error's tags
+pub const ERROR_FIELDS: &[ErrorField] = &[
+    ErrorField {
+        name: "code",
+        value_type: Some(ExprType::Int),
+        doc: "HTTP status code of a failed request, or the code of a connector error.",
+    },
+    ErrorField {
+        name: "message",
+        value_type: Some(ExprType::String),
+        doc: "Human-readable description of the error.",
+    },
+    ErrorField {
+        name: "tags",
+        value_type: Some(ExprType::List),
+        doc: "Categories of the error, such as `HttpError`, `ConnectionError` or `TimeoutError`; test them with `in`.",
+    },
+    ErrorField {
+        name: "operation",
+        value_type: Some(ExprType::Map),
+        doc: "The long-running operation a connector call was waiting on when it failed.",
+    },
+    ErrorField {
+        name: "headers",
+        value_type: Some(ExprType::Map),
+        doc: "Response headers of a failed HTTP request.",
+    },
+    ErrorField {
+        name: "body",
+        value_type: None,
+        doc: "Response body of a failed HTTP request, parsed when it is JSON.",
+    },
 ];
 
-/// Check if a key is a known workflow keyword
-pub fn is_workflow_keyword(key: &str) -> bool {
-    WORKFLOW_KEYWORDS.contains(&key)
+/// Look up a field of a caught error
+pub fn error_field(name: &str) -> Option<&'static ErrorField> {
+    ERROR_FIELDS.iter().find(|field| field.name == name)
 }
 
-/// Check if a key is a valid step action
-pub fn is_step_action(key: &str) -> bool {
-    STEP_ACTION_KEYWORDS.contains(&key)
+/// Get the return type of a standard library function, if it is fixed
+pub fn return_type(function: &str) -> Option<ExprType> {
+    stdlib_function(function)?.returns
 }
 
-/// Get set of valid step action keywords
-pub fn step_action_set() -> HashSet<&'static str> {
-    STEP_ACTION_KEYWORDS.iter().copied().collect()
+/// Look up a key of any Workflows mapping
+pub fn field(name: &str) -> Option<&'static Field> {
+    Block::of(name)?.field(name)
 }
 
-/// Get set of all workflow keywords
-pub fn workflow_keyword_set() -> HashSet<&'static str> {
-    WORKFLOW_KEYWORDS.iter().copied().collect()
+/// Get the documentation of a keyword
+pub fn keyword_doc(key: &str) -> Option<&'static str> {
+    field(key).map(|field| field.doc)
+}
+
+/// Check if a key is a known workflow keyword
+pub fn is_workflow_keyword(key: &str) -> bool {
+    field(key).is_some()
+}
+
+/// Check if a key is a valid step action
+pub fn is_step_action(key: &str) -> bool {
+    StepKind::from_key(key).is_some()
 }
 
 #[cfg(test)]
@@ -149,18 +584,96 @@ mod tests {
     }
 
     #[test]
-    fn test_step_action_set() {
-        let set = step_action_set();
-        assert!(set.contains("assign"));
-        assert!(set.contains("call"));
-        assert!(!set.contains("main"));
+    fn test_step_kind() {
+        assert_eq!(StepKind::from_key("call"), Some(StepKind::Call));
+        assert_eq!(
+            StepKind::Parallel.action().value_type,
+            Some(ValueType::Mapping)
+        );
+        assert_eq!(StepKind::from_key("args"), None);
+        for kind in StepKind::ALL {
+            assert!(Block::Step.field(kind.action().name).is_some());
+        }
+    }
+
+    #[test]
+    fn test_value_type_matches() {
+        let int: serde_yaml::Value = serde_yaml::from_str("3").unwrap();
+        let strs: serde_yaml::Value = serde_yaml::from_str("[a, b]").unwrap();
+        let mixed: serde_yaml::Value = serde_yaml::from_str("[a, 1]").unwrap();
+
+        assert!(ValueType::Integer.matches(&int));
+        assert!(!ValueType::String.matches(&int));
+        assert!(ValueType::ListOfStrings.matches(&strs));
+        assert!(!ValueType::ListOfStrings.matches(&mixed));
+        assert!(ValueType::List.matches(&mixed));
+    }
+
+    #[test]
+    fn test_block_fields() {
+        let field_type = |block: Block, name| block.field(name).and_then(|f| f.value_type);
+        assert_eq!(
+            field_type(Block::Parallel, "concurrency_limit"),
+            Some(ValueType::Integer)
+        );
+        assert_eq!(field_type(Block::Step, "args"), Some(ValueType::Mapping));
+        assert_eq!(field_type(Block::Step, "return"), None);
+        assert!(Block::Step.field("concurrency_limit").is_none());
+        assert!(Block::Workflow.field("steps").unwrap().required);
+        assert!(!Block::Step.field("steps").unwrap().required);
+        assert!(Block::SwitchCondition.field("condition").unwrap().required);
+        assert_eq!(Block::of("branches"), Some(Block::Parallel));
+        assert_eq!(Block::of("condition"), Some(Block::SwitchCondition));
+        assert_eq!(Block::of("custom"), None);
+        assert_eq!(
+            Block::Backoff.names().collect::<Vec<_>>(),
+            vec!["initial_delay", "max_delay", "multiplier"]
+        );
+    }
+
+    #[test]
+    fn test_call_args() {
+        let sleep = call_args("sys.sleep").unwrap();
+        assert_eq!(sleep.len(), 1);
+        assert!(sleep[0].required);
+        assert_eq!(sleep[0].value_type, Some(ValueType::Number));
+
+        let get = call_args("http.get").unwrap();
+        assert!(get.iter().any(|a| a.name == "url" && a.required));
+        assert!(!get.iter().any(|a| a.name == "body"));
+        assert!(call_args("helper").is_none());
+
+        let insert = call_args("googleapis.bigquery.v2.jobs.insert").unwrap();
+        assert!(insert.iter().any(|a| a.name == "projectId" && a.required));
+        assert!(call_args("googleapis.example.v1.unknown").is_none());
+
+        let decode = call_args("base64.decode").unwrap();
+        assert!(decode.iter().any(|a| a.name == "padding" && !a.required));
+    }
+
+    #[test]
+    fn test_return_type() {
+        assert_eq!(return_type("text.split"), Some(ExprType::List));
+        assert_eq!(return_type("http.get"), Some(ExprType::Map));
+        assert_eq!(return_type("map.get"), None);
+        assert_eq!(return_type("helper"), None);
     }
 
     #[test]
-    fn test_workflow_keyword_set() {
-        let set = workflow_keyword_set();
-        assert!(set.contains("main"));
-        assert!(set.contains("steps"));
-        assert!(set.contains("assign"));
+    fn test_every_field_is_documented() {
+        for block in Block::ALL {
+            for field in block.fields() {
+                assert!(!field.doc.is_empty(), "{} has no docs", field.name);
+                // A key means the same wherever it is written
+                assert_eq!(
+                    super::field(field.name).map(|f| (f.value_type, f.doc)),
+                    Some((field.value_type, field.doc))
+                );
+            }
+        }
+        assert_eq!(keyword_doc("main"), Some("Entry point of the workflow."));
+        assert!(keyword_doc("unknown").is_none());
+        assert_eq!(error_field("code").unwrap().value_type, Some(ExprType::Int));
+        assert!(error_field("status").is_none());
     }
 }
@@ -0,0 +1,337 @@
+//! Client settings
+//!
+//! Settings arrive from the editor, either as `initializationOptions` or
+//! through `workspace/didChangeConfiguration`, and tune how documents are
+//! validated and which editor features are offered. Unlike the workspace configuration file they are per user.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::DiagnosticSeverity;
+
+use crate::diagnostics::{
+    QuotaLimits, SchemaExtensions, DEFAULT_MAX_EXPRESSION_LENGTH, DEFAULT_MAX_NESTING_DEPTH,
+    DEFAULT_MAX_STEPS,
+};
+use crate::schema::SchemaOverlay;
+use crate::terraform::{
+    find_module_dir, load_module_decls, template_calls, ModuleDecls, TemplateCall,
+};
+
+/// Configuration section the settings live under
+pub const SECTION: &str = "yaml-tftpl-lsp";
+
+/// Files larger than this are not analyzed or indexed unless configured
+pub const DEFAULT_MAX_FILE_SIZE: usize = 16 * 1024 * 1024;
+
+/// Documents larger than this only get YAML syntax checking unless
+/// configured
+pub const DEFAULT_DEEP_ANALYSIS_MAX_SIZE: usize = 1024 * 1024;
+
+/// Severity a rule is reported with, as configured by the user
+///
+/// Levels are ordered from most to least severe, with `Off` last.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleLevel {
+    Error,
+    Warning,
+    Information,
+    Hint,
+    /// Do not report the rule at all
+    Off,
+}
+
+impl RuleLevel {
+    /// The LSP severity for this level, `None` when the rule is off
+    pub fn severity(self) -> Option<DiagnosticSeverity> {
+        match self {
+            RuleLevel::Error => Some(DiagnosticSeverity::ERROR),
+            RuleLevel::Warning => Some(DiagnosticSeverity::WARNING),
+            RuleLevel::Information => Some(DiagnosticSeverity::INFORMATION),
+            RuleLevel::Hint => Some(DiagnosticSeverity::HINT),
+            RuleLevel::Off => None,
+        }
+    }
+}
+
+/// User settings that tune validation and features
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Settings {
+    /// Validate GCP Workflows structure; turn off for plain YAML templates
+    pub workflow_validation: bool,
+    /// Severity overrides keyed by diagnostic code (e.g. `"unknown-keyword"`)
+    pub rules: BTreeMap<String, RuleLevel>,
+    /// Offer range and on-type formatting
+    pub formatting: bool,
+    /// Normalize spacing inside expressions when range formatting; off by
+    /// default, the code actions doing it on request
+    pub format_expressions: bool,
+    /// Parts a `$${}` expression may concatenate into a URL or resource name
+    /// before `string-concatenation` reports it
+    pub max_concatenated_parts: usize,
+    /// Steps a workflow definition may have before `quota-exceeded` reports
+    /// it; 0 turns the check off
+    pub max_steps: usize,
+    /// Characters a `$${}` expression may have; 0 turns the check off
+    pub max_expression_length: usize,
+    /// Levels steps may be nested; 0 turns the check off
+    pub max_nesting_depth: usize,
+    /// Show inlay hints
+    pub inlay_hints: bool,
+    /// Show code lenses
+    pub code_lens: bool,
+    /// Documents validated at the same time; 0 uses one per CPU
+    pub max_concurrent_analyses: usize,
+    /// Size in bytes above which documents are not analyzed and workspace
+    /// files are not indexed
+    pub max_file_size: usize,
+    /// Size in bytes above which documents only get YAML syntax checking,
+    /// skipping workflow and expression analysis; 0 analyzes every document
+    /// fully
+    pub deep_analysis_max_size: usize,
+    /// Threads used to walk the workspace; 0 picks a number automatically
+    pub indexing_threads: usize,
+    /// Validate documents when they are saved rather than on every edit
+    pub validate_on_save: bool,
+    /// JSON Schema file extending workflow validation, absolute or relative
+    /// to the workspace root; overrides the workspace configuration's
+    pub schema: Option<PathBuf>,
+    /// Top-level keys that get no "Unknown workflow element" hint
+    pub additional_top_level_keys: Vec<String>,
+    /// Step keys that get no "Unknown step action" hint
+    pub additional_step_keys: Vec<String>,
+    /// The schema overlay documents are validated with, loaded by the server
+    /// from `schema` or the workspace configuration
+    #[serde(skip)]
+    pub schema_overlay: Option<Arc<SchemaOverlay>>,
+    /// Declarations of the Terraform module the document belongs to, loaded
+    /// by the server; `var.` references are checked against them when set
+    #[serde(skip)]
+    pub terraform_module: Option<Arc<ModuleDecls>>,
+    /// The `templatefile()` calls rendering the document, found by the
+    /// server; its variables are checked against what they pass
+    #[serde(skip)]
+    pub template_calls: Vec<TemplateCall>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            workflow_validation: true,
+            rules: BTreeMap::new(),
+            formatting: true,
+            format_expressions: false,
+            max_concatenated_parts: 4,
+            max_steps: DEFAULT_MAX_STEPS,
+            max_expression_length: DEFAULT_MAX_EXPRESSION_LENGTH,
+            max_nesting_depth: DEFAULT_MAX_NESTING_DEPTH,
+            inlay_hints: true,
+            code_lens: true,
+            max_concurrent_analyses: 0,
+            max_file_size: DEFAULT_MAX_FILE_SIZE,
+            deep_analysis_max_size: DEFAULT_DEEP_ANALYSIS_MAX_SIZE,
+            indexing_threads: 0,
+            validate_on_save: false,
+            schema: None,
+            additional_top_level_keys: Vec::new(),
+            additional_step_keys: Vec::new(),
+            schema_overlay: None,
+            terraform_module: None,
+            template_calls: Vec::new(),
+        }
+    }
+}
+
+impl Settings {
+    /// Read settings sent by the client
+    ///
+    /// Accepts the settings object itself or one nested under the
+    /// `yaml-tftpl-lsp` section, since clients differ in which they send.
+    /// Invalid settings fall back to the defaults.
+    pub fn from_value(value: serde_json::Value) -> Self {
+        Self::default().merge(value)
+    }
+
+    /// These settings with the ones sent by the client applied over them,
+    /// field by field
+    ///
+    /// Fields the client leaves out keep their value, so `null` or `{}`
+    /// change nothing, and so does an invalid field, which is logged by name.
+    /// Values loaded by the server are kept as they are.
+    pub fn merge(&self, value: serde_json::Value) -> Self {
+        let value = match value {
+            serde_json::Value::Object(mut map) if map.contains_key(SECTION) => {
+                map.remove(SECTION).unwrap_or_default()
+            }
+            other => other,
+        };
+        let serde_json::Value::Object(sent) = value else {
+            if !value.is_null() {
+                tracing::warn!("Ignoring settings that are not an object");
+            }
+            return self.clone();
+        };
+
+        let Ok(serde_json::Value::Object(mut merged)) = serde_json::to_value(self) else {
+            return self.clone();
+        };
+        for (field, value) in sent {
+            let previous = merged.insert(field.clone(), value);
+            if let Err(e) = serde_json::from_value::<Settings>(merged.clone().into()) {
+                tracing::warn!(field = %field, error = %e, "Ignoring invalid setting");
+                match previous {
+                    Some(previous) => merged.insert(field, previous),
+                    None => merged.remove(&field),
+                };
+            }
+        }
+        match serde_json::from_value::<Settings>(merged.into()) {
+            Ok(settings) => Settings {
+                schema_overlay: self.schema_overlay.clone(),
+                terraform_module: self.terraform_module.clone(),
+                template_calls: self.template_calls.clone(),
+                ..settings
+            },
+            Err(_) => self.clone(),
+        }
+    }
+
+    /// These settings with the Terraform context of a template read from disk:
+    /// its module's declarations and the `templatefile()` calls rendering it,
+    /// found in the directories up to `root`
+    ///
+    /// The server keeps both in its indexes; this is for one-shot runs such
+    /// as `doctor` and `check`.
+    pub fn for_template(&self, path: &Path, root: &Path) -> Settings {
+        Settings {
+            terraform_module: find_module_dir(path).map(|_| Arc::new(load_module_decls(path))),
+            template_calls: template_calls(path, Some(root)),
+            ..self.clone()
+        }
+    }
+
+    /// How many documents may be validated at the same time
+    pub fn analysis_concurrency(&self) -> usize {
+        match self.max_concurrent_analyses {
+            0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+            n => n,
+        }
+    }
+
+    /// The quota limits documents are checked against
+    pub fn quota_limits(&self) -> QuotaLimits {
+        QuotaLimits {
+            steps: self.max_steps,
+            expression_length: self.max_expression_length,
+            nesting_depth: self.max_nesting_depth,
+        }
+    }
+
+    /// What the user has added to the Workflows schema
+    pub fn schema_extensions(&self) -> SchemaExtensions<'_> {
+        SchemaExtensions {
+            overlay: self.schema_overlay.as_deref(),
+            top_level_keys: &self.additional_top_level_keys,
+            step_keys: &self.additional_step_keys,
+        }
+    }
+
+    /// Whether a document of `len` bytes gets more than YAML syntax checking
+    pub fn analyzes_deeply(&self, len: usize) -> bool {
+        self.deep_analysis_max_size == 0 || len <= self.deep_analysis_max_size
+    }
+
+    /// The configured level of a rule, if overridden
+    pub fn rule_level(&self, code: &str) -> Option<RuleLevel> {
+        self.rules.get(code).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_defaults() {
+        let settings = Settings::from_value(serde_json::Value::Null);
+        assert!(settings.workflow_validation);
+        assert!(settings.rules.is_empty());
+        assert!(settings.formatting && settings.inlay_hints && settings.code_lens);
+    }
+
+    #[test]
+    fn test_flat_and_sectioned_settings() {
+        let flat = Settings::from_value(json!({
+            "workflowValidation": false,
+            "rules": { "unknown-keyword": "off", "type-mismatch": "error" }
+        }));
+        assert!(!flat.workflow_validation);
+        assert_eq!(flat.rule_level("unknown-keyword"), Some(RuleLevel::Off));
+        assert_eq!(flat.rule_level("type-mismatch"), Some(RuleLevel::Error));
+        assert_eq!(flat.rule_level("yaml-syntax"), None);
+
+        let sectioned = Settings::from_value(json!({ SECTION: { "workflowValidation": false } }));
+        assert!(!sectioned.workflow_validation);
+
+        let features = Settings::from_value(json!({ "formatting": false, "inlayHints": false }));
+        assert!(!features.formatting && !features.inlay_hints && features.code_lens);
+        assert!(!features.validate_on_save);
+        assert!(Settings::from_value(json!({ "validateOnSave": true })).validate_on_save);
+        let schema = Settings::from_value(json!({ "schema": "schemas/org.json" }));
+        assert_eq!(schema.schema, Some(PathBuf::from("schemas/org.json")));
+        assert!(schema.schema_overlay.is_none());
+        let allowed = Settings::from_value(json!({
+            "additionalTopLevelKeys": ["metadata"],
+            "additionalStepKeys": ["owner"]
+        }));
+        let extensions = allowed.schema_extensions();
+        assert_eq!(extensions.top_level_keys, ["metadata"]);
+        assert_eq!(extensions.step_keys, ["owner"]);
+
+        let limits = Settings::from_value(json!({
+            "maxConcurrentAnalyses": 2,
+            "maxFileSize": 1024,
+            "deepAnalysisMaxSize": 4,
+            "indexingThreads": 1
+        }));
+        assert_eq!(limits.analysis_concurrency(), 2);
+        assert_eq!(limits.max_file_size, 1024);
+        assert_eq!(limits.indexing_threads, 1);
+        assert!(limits.analyzes_deeply(4) && !limits.analyzes_deeply(5));
+        let unlimited = Settings::from_value(json!({ "deepAnalysisMaxSize": 0 }));
+        assert!(unlimited.analyzes_deeply(usize::MAX));
+        assert!(Settings::default().analysis_concurrency() >= 1);
+    }
+
+    #[test]
+    fn test_invalid_settings_fall_back_to_defaults() {
+        let settings = Settings::from_value(json!({ "rules": { "yaml-syntax": "loud" } }));
+        assert_eq!(settings, Settings::default());
+    }
+
+    #[test]
+    fn test_merge_keeps_what_is_not_sent() {
+        let current = Settings::from_value(json!({
+            "workflowValidation": false,
+            "maxSteps": 10,
+            "rules": { "unknown-keyword": "off" }
+        }));
+        assert_eq!(current.merge(serde_json::Value::Null), current);
+        assert_eq!(current.merge(json!({})), current);
+        assert_eq!(current.merge(json!({ SECTION: {} })), current);
+
+        let merged = current.merge(json!({
+            "maxSteps": "many",
+            "formatting": false,
+            "rules": { "yaml-syntax": "loud" }
+        }));
+        assert!(!merged.workflow_validation && !merged.formatting);
+        assert_eq!(merged.max_steps, 10);
+        assert_eq!(merged.rule_level("unknown-keyword"), Some(RuleLevel::Off));
+    }
+}
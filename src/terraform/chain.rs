@@ -0,0 +1,274 @@
+//! Definition chains of `local.*` and `var.*` references
+//!
+//! A `${local.x}` in a template may be defined in terms of other locals and
+//! variables, which in turn get their values from tfvars files. Following the
+//! chain to its end shows where a rendered value ultimately comes from.
+
+use std::collections::{HashSet, VecDeque};
+use std::fs;
+use std::ops::Range as ByteRange;
+use std::path::{Path, PathBuf};
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Serialize;
+use tower_lsp::lsp_types::{Location, Range, Url};
+
+use super::declarations::{find_module_dir, tf_files};
+use super::tfvars::{find_tfvars_files, TfVars};
+use crate::document::LineOffsets;
+
+lazy_static! {
+    /// A `local.name` or `var.name` reference
+    static ref REFERENCE_RE: Regex = Regex::new(r"\b(?:local|var)\.[A-Za-z_][A-Za-z0-9_-]*").unwrap();
+    /// The start of a `locals { ... }` block
+    static ref LOCALS_RE: Regex = Regex::new(r"(?m)^[ \t]*locals[ \t]*\{").unwrap();
+}
+
+/// What a link of the chain is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LinkKind {
+    /// An attribute of a `locals` block
+    Local,
+    /// A `variable` block
+    Variable,
+    /// A value assigned to a variable in a tfvars file
+    Tfvars,
+}
+
+/// One definition in a chain
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChainLink {
+    /// The reference this defines, e.g. `local.name` or `var.region`
+    pub name: String,
+    pub kind: LinkKind,
+    /// Where the name is defined or assigned
+    pub location: Location,
+    /// The local's expression, the variable's default or the tfvars value
+    pub value: Option<String>,
+    /// References in `value` that the chain follows next
+    pub references: Vec<String>,
+}
+
+/// The reference in an expression at byte `offset` of the expression, or its
+/// first reference when the offset isn't on one
+pub fn reference_at(expression: &str, offset: usize) -> Option<String> {
+    let mut references = REFERENCE_RE.find_iter(expression);
+    let first = references.next()?;
+    std::iter::once(first)
+        .chain(references)
+        .find(|m| m.start() <= offset && offset <= m.end())
+        .unwrap_or(first)
+        .as_str()
+        .to_string()
+        .into()
+}
+
+/// Follow `reference` through the module of `template`, breadth first
+///
+/// Every definition appears once, even when several links refer to it.
+/// References with no definition in the module end their branch.
+pub fn definition_chain(template: &Path, reference: &str) -> Vec<ChainLink> {
+    let Some(module) = find_module_dir(template) else {
+        return Vec::new();
+    };
+    let sources: Vec<(PathBuf, String)> = tf_files(&module)
+        .into_iter()
+        .filter_map(|path| Some((path.clone(), fs::read_to_string(&path).ok()?)))
+        .collect();
+    let tfvars: Vec<(PathBuf, String)> = find_tfvars_files(template)
+        .into_iter()
+        .filter_map(|path| Some((path.clone(), fs::read_to_string(&path).ok()?)))
+        .collect();
+
+    let mut chain = Vec::new();
+    let mut seen = HashSet::new();
+    let mut queue = VecDeque::from([reference.to_string()]);
+    while let Some(name) = queue.pop_front() {
+        if !seen.insert(name.clone()) {
+            continue;
+        }
+        let links = if let Some(local) = name.strip_prefix("local.") {
+            find_local(&sources, local).into_iter().collect()
+        } else if let Some(variable) = name.strip_prefix("var.") {
+            let mut links: Vec<ChainLink> = find_variable(&sources, variable).into_iter().collect();
+            links.extend(find_assignments(&tfvars, variable));
+            links
+        } else {
+            Vec::new()
+        };
+        for mut link in links {
+            link.name = name.clone();
+            queue.extend(link.references.iter().cloned());
+            chain.push(link);
+        }
+    }
+    chain
+}
+
+fn find_local(sources: &[(PathBuf, String)], name: &str) -> Option<ChainLink> {
+    sources.iter().find_map(|(path, text)| {
+        let body = hcl::parse(text).ok()?;
+        let expr = body
+            .blocks()
+            .filter(|block| block.identifier() == "locals")
+            .flat_map(|block| block.body().attributes())
+            .find(|attr| attr.key() == name)?
+            .expr();
+        let value = hcl::format::to_string(expr).ok()?;
+        let span = locals_spans(text).into_iter().find_map(|block| {
+            attribute_span(&text[block.clone()], name).map(|s| shift(s, block.start))
+        })?;
+        link(LinkKind::Local, path, text, span, Some(value))
+    })
+}
+
+fn find_variable(sources: &[(PathBuf, String)], name: &str) -> Option<ChainLink> {
+    let pattern = Regex::new(&format!(r#"variable[ \t]+"({})""#, regex::escape(name))).ok()?;
+    sources.iter().find_map(|(path, text)| {
+        let span = pattern.captures(text)?.get(1)?.range();
+        let default = hcl::parse(text).ok().and_then(|body| {
+            let block = body.blocks().find(|block| {
+                block.identifier() == "variable"
+                    && block.labels().first().is_some_and(|l| l.as_str() == name)
+            })?;
+            let attr = block.body().attributes().find(|a| a.key() == "default")?;
+            hcl::format::to_string(attr.expr()).ok()
+        });
+        link(LinkKind::Variable, path, text, span, default)
+    })
+}
+
+/// Assignments of a variable in the tfvars files, in load order
+fn find_assignments(tfvars: &[(PathBuf, String)], name: &str) -> Vec<ChainLink> {
+    tfvars
+        .iter()
+        .filter_map(|(path, text)| {
+            let span = attribute_span(text, name)?;
+            let value = TfVars::parse(text).and_then(|vars| vars.get(name).map(str::to_string));
+            link(LinkKind::Tfvars, path, text, span, value)
+        })
+        .collect()
+}
+
+fn link(
+    kind: LinkKind,
+    path: &Path,
+    text: &str,
+    span: ByteRange<usize>,
+    value: Option<String>,
+) -> Option<ChainLink> {
+    let references = match (kind, &value) {
+        (LinkKind::Local, Some(value)) => REFERENCE_RE
+            .find_iter(value)
+            .map(|m| m.as_str().to_string())
+            .collect(),
+        _ => Vec::new(),
+    };
+    let lines = LineOffsets::new(text);
+    Some(ChainLink {
+        name: String::new(),
+        kind,
+        location: Location {
+            uri: Url::from_file_path(path).ok()?,
+            range: Range::new(
+                lines.offset_to_position(text, span.start),
+                lines.offset_to_position(text, span.end),
+            ),
+        },
+        value,
+        references,
+    })
+}
+
+/// Byte ranges of the bodies of `locals` blocks
+pub(super) fn locals_spans(text: &str) -> Vec<ByteRange<usize>> {
+    LOCALS_RE
+        .find_iter(text)
+        .map(|m| {
+            let mut depth = 1;
+            let mut in_string = false;
+            let mut escaped = false;
+            let mut end = text.len();
+            for (i, c) in text[m.end()..].char_indices() {
+                match c {
+                    _ if escaped => escaped = false,
+                    '\\' if in_string => escaped = true,
+                    '"' => in_string = !in_string,
+                    '{' if !in_string => depth += 1,
+                    '}' if !in_string => {
+                        depth -= 1;
+                        if depth == 0 {
+                            end = m.end() + i;
+                            break;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            m.end()..end
+        })
+        .collect()
+}
+
+/// Byte range of `name` in the first `name = ...` line of `text`
+pub(super) fn attribute_span(text: &str, name: &str) -> Option<ByteRange<usize>> {
+    let pattern = Regex::new(&format!(r"(?m)^[ \t]*({})[ \t]*=[^=]", regex::escape(name))).ok()?;
+    Some(pattern.captures(text)?.get(1)?.range())
+}
+
+pub(super) fn shift(span: ByteRange<usize>, by: usize) -> ByteRange<usize> {
+    span.start + by..span.end + by
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower_lsp::lsp_types::Position;
+
+    #[test]
+    fn test_reference_at() {
+        let expression = "${local.name}-${var.env}";
+        assert_eq!(reference_at(expression, 18).as_deref(), Some("var.env"));
+        assert_eq!(reference_at(expression, 0).as_deref(), Some("local.name"));
+        assert_eq!(reference_at("${upper(\"x\")}", 3), None);
+    }
+
+    #[test]
+    fn test_definition_chain() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("main.tf"),
+            "variable \"project\" {\n  type = string\n}\n\nvariable \"env\" {\n  default = \"dev\"\n}\n\nlocals {\n  prefix = \"${var.project}-${var.env}\"\n  name   = \"${local.prefix}-wf\"\n  labels = { env = var.env }\n}\n",
+        )
+        .unwrap();
+        fs::write(dir.path().join("terraform.tfvars"), "project = \"acme\"\n").unwrap();
+        let template = dir.path().join("w.yaml.tftpl");
+
+        let chain = definition_chain(&template, "local.name");
+        let summary: Vec<(&str, LinkKind, u32)> = chain
+            .iter()
+            .map(|l| (l.name.as_str(), l.kind, l.location.range.start.line))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("local.name", LinkKind::Local, 10),
+                ("local.prefix", LinkKind::Local, 9),
+                ("var.project", LinkKind::Variable, 0),
+                ("var.project", LinkKind::Tfvars, 0),
+                ("var.env", LinkKind::Variable, 4),
+            ]
+        );
+        assert_eq!(chain[1].references, vec!["var.project", "var.env"]);
+        assert_eq!(chain[3].value.as_deref(), Some("\"acme\""));
+        assert_eq!(chain[4].value.as_deref(), Some("\"dev\""));
+        assert_eq!(
+            chain[0].location.range,
+            Range::new(Position::new(10, 2), Position::new(10, 6))
+        );
+        assert!(definition_chain(&template, "local.missing").is_empty());
+    }
+}
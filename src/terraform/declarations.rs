@@ -0,0 +1,272 @@
+//! Declarations from the module's `.tf` files: variables, locals and module
+//! calls, the things a `${...}` expression can refer to
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// A `variable "name" { ... }` block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VariableDecl {
+    pub name: String,
+    /// The `type` constraint, rendered as HCL
+    pub type_expr: Option<String>,
+    /// The `default` value, rendered as HCL
+    pub default: Option<String>,
+    pub description: Option<String>,
+    /// File the declaration was read from
+    pub file: PathBuf,
+}
+
+/// Everything declared in a Terraform module
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ModuleDecls {
+    pub variables: Vec<VariableDecl>,
+    /// Names of `locals { ... }` attributes
+    pub locals: Vec<String>,
+    /// Names of `module "name" { ... }` blocks
+    pub modules: Vec<String>,
+}
+
+impl ModuleDecls {
+    /// Parse the declarations of a single `.tf` file
+    pub fn parse(text: &str, file: &Path) -> Option<Self> {
+        let body = hcl::parse(text).ok()?;
+        let blocks = |identifier: &'static str| {
+            body.blocks()
+                .filter(move |block| block.identifier() == identifier)
+        };
+        Some(Self {
+            variables: parse_variables(&body, file),
+            locals: blocks("locals")
+                .flat_map(|block| block.body().attributes())
+                .map(|attr| attr.key().to_string())
+                .collect(),
+            modules: blocks("module")
+                .filter_map(|block| block.labels().first())
+                .map(|label| label.as_str().to_string())
+                .collect(),
+        })
+    }
+
+    /// Add the declarations of another file
+    pub fn extend(&mut self, other: ModuleDecls) {
+        self.variables.extend(other.variables);
+        self.locals.extend(other.locals);
+        self.modules.extend(other.modules);
+    }
+}
+
+fn parse_variables(body: &hcl::Body, file: &Path) -> Vec<VariableDecl> {
+    body.blocks()
+        .filter(|block| block.identifier() == "variable")
+        .filter_map(|block| {
+            let name = block.labels().first()?.as_str().to_string();
+            let attr = |key: &str| {
+                block
+                    .body()
+                    .attributes()
+                    .find(|a| a.key() == key)
+                    .map(|a| a.expr())
+            };
+            Some(VariableDecl {
+                name,
+                type_expr: attr("type").and_then(|e| hcl::format::to_string(e).ok()),
+                default: attr("default").and_then(|e| hcl::format::to_string(e).ok()),
+                description: attr("description").map(|e| match e {
+                    hcl::Expression::String(s) => s.clone(),
+                    other => other.to_string(),
+                }),
+                file: file.to_path_buf(),
+            })
+        })
+        .collect()
+}
+
+/// The Terraform module a template belongs to: the nearest directory, starting
+/// at the template's own, that contains `.tf` files
+pub fn find_module_dir(template: &Path) -> Option<PathBuf> {
+    let mut dir = template.parent();
+    while let Some(current) = dir {
+        if !tf_files(current).is_empty() {
+            return Some(current.to_path_buf());
+        }
+        dir = current.parent();
+    }
+    None
+}
+
+/// Load every declaration in the module a template belongs to
+pub fn load_module_decls(template: &Path) -> ModuleDecls {
+    let mut decls = ModuleDecls::default();
+    let Some(module) = find_module_dir(template) else {
+        return decls;
+    };
+    for path in tf_files(&module) {
+        match fs::read_to_string(&path)
+            .ok()
+            .and_then(|t| ModuleDecls::parse(&t, &path))
+        {
+            Some(parsed) => decls.extend(parsed),
+            None => tracing::debug!(path = %path.display(), "Skipping unreadable .tf file"),
+        }
+    }
+    decls
+}
+
+/// The file a new `variable` block of a module goes in: its `variables.tf`,
+/// or else its first `.tf` file
+pub fn variables_file(module: &Path) -> Option<PathBuf> {
+    let files = tf_files(module);
+    files
+        .iter()
+        .find(|path| path.file_name().is_some_and(|name| name == "variables.tf"))
+        .or(files.first())
+        .cloned()
+}
+
+/// The `.tf` files of a module with their modification times
+type Fingerprint = Vec<(PathBuf, Option<SystemTime>)>;
+
+/// Module declarations cached by module directory
+///
+/// One index can be shared by every editor session of a server, since
+/// sessions only read it. A module is parsed again once one of its `.tf`
+/// files is added, removed or modified.
+#[derive(Debug, Default)]
+pub struct ModuleIndex {
+    modules: Mutex<HashMap<PathBuf, (Fingerprint, Arc<ModuleDecls>)>>,
+}
+
+impl ModuleIndex {
+    /// The declarations of the module a template belongs to
+    pub fn decls(&self, template: &Path) -> Arc<ModuleDecls> {
+        let Some(module) = find_module_dir(template) else {
+            return Arc::default();
+        };
+        let fingerprint: Fingerprint = tf_files(&module)
+            .into_iter()
+            .map(|path| {
+                let modified = fs::metadata(&path).and_then(|m| m.modified()).ok();
+                (path, modified)
+            })
+            .collect();
+
+        if let Some((cached, decls)) = self.modules.lock().unwrap().get(&module) {
+            if *cached == fingerprint {
+                return decls.clone();
+            }
+        }
+        // Read and parse without the lock, so lookups for other modules
+        // aren't held up; two callers may both load a changed module, and
+        // either result is kept
+        let decls = Arc::new(load_module_decls(template));
+        self.modules
+            .lock()
+            .unwrap()
+            .insert(module, (fingerprint, decls.clone()));
+        decls
+    }
+}
+
+/// The `.tf` files directly in a directory, sorted
+pub(super) fn tf_files(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_file() && p.extension().is_some_and(|ext| ext == "tf"))
+        .collect();
+    files.sort();
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TF: &str = r#"
+variable "region" {
+  type        = string
+  default     = "europe-west1"
+  description = "Region to deploy to"
+}
+
+variable "replicas" {
+  type = number
+}
+
+resource "google_workflows_workflow" "w" {
+  name = "w"
+}
+
+locals {
+  prefix = "wf"
+  labels = {}
+}
+
+module "network" {
+  source = "./network"
+}
+"#;
+
+    #[test]
+    fn test_parse_variables() {
+        let vars = ModuleDecls::parse(TF, Path::new("variables.tf"))
+            .unwrap()
+            .variables;
+        assert_eq!(vars.len(), 2);
+        assert_eq!(vars[0].name, "region");
+        assert_eq!(vars[0].type_expr.as_deref(), Some("string"));
+        assert_eq!(vars[0].default.as_deref(), Some("\"europe-west1\""));
+        assert_eq!(vars[0].description.as_deref(), Some("Region to deploy to"));
+        assert_eq!(vars[1].default, None);
+    }
+
+    #[test]
+    fn test_parse_locals_and_modules() {
+        let decls = ModuleDecls::parse(TF, Path::new("main.tf")).unwrap();
+        assert_eq!(decls.locals, vec!["prefix", "labels"]);
+        assert_eq!(decls.modules, vec!["network"]);
+    }
+
+    #[test]
+    fn test_module_index_reloads_changed_modules() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("variables.tf"), TF).unwrap();
+        let template = dir.path().join("w.yaml.tftpl");
+        let index = ModuleIndex::default();
+
+        let first = index.decls(&template);
+        assert_eq!(first.variables.len(), 2);
+        assert!(Arc::ptr_eq(&first, &index.decls(&template)));
+
+        fs::write(
+            dir.path().join("extra.tf"),
+            "variable \"zone\" {\n  type = string\n}\n",
+        )
+        .unwrap();
+        assert_eq!(index.decls(&template).variables.len(), 3);
+    }
+
+    #[test]
+    fn test_load_variables_from_module_root() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("templates")).unwrap();
+        fs::write(dir.path().join("variables.tf"), TF).unwrap();
+        let template = dir.path().join("templates/w.yaml.tftpl");
+
+        assert_eq!(find_module_dir(&template).as_deref(), Some(dir.path()));
+        assert_eq!(
+            variables_file(dir.path()),
+            Some(dir.path().join("variables.tf"))
+        );
+        let vars = load_module_decls(&template).variables;
+        assert_eq!(vars.len(), 2);
+        assert_eq!(vars[0].file, dir.path().join("variables.tf"));
+    }
+}
@@ -0,0 +1,573 @@
+//! Terraform built-in functions
+//!
+//! The signature table behind function completion inside `${...}`: each
+//! function's name, parameters and a one-line description. A parameter
+//! ending in `...` takes any number of arguments.
+
+/// A Terraform built-in function
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TerraformFunction {
+    pub name: &'static str,
+    pub params: &'static [&'static str],
+    pub description: &'static str,
+}
+
+impl TerraformFunction {
+    /// The call signature, e.g. `join(separator, list)`
+    pub fn signature(&self) -> String {
+        format!("{}({})", self.name, self.params.join(", "))
+    }
+
+    /// An LSP snippet calling the function with a tab stop per parameter
+    pub fn snippet(&self) -> String {
+        let params: Vec<String> = self
+            .params
+            .iter()
+            .enumerate()
+            .map(|(i, param)| format!("${{{}:{}}}", i + 1, param.trim_end_matches("...")))
+            .collect();
+        format!("{}({})", self.name, params.join(", "))
+    }
+}
+
+const fn function(
+    name: &'static str,
+    params: &'static [&'static str],
+    description: &'static str,
+) -> TerraformFunction {
+    TerraformFunction {
+        name,
+        params,
+        description,
+    }
+}
+
+/// Terraform's built-in functions, grouped as in its documentation
+pub const TERRAFORM_FUNCTIONS: &[TerraformFunction] = &[
+    // Numeric
+    function("abs", &["number"], "Absolute value of a number."),
+    function(
+        "ceil",
+        &["number"],
+        "Smallest whole number not less than `number`.",
+    ),
+    function(
+        "floor",
+        &["number"],
+        "Largest whole number not greater than `number`.",
+    ),
+    function(
+        "log",
+        &["number", "base"],
+        "Logarithm of `number` in `base`.",
+    ),
+    function("max", &["numbers..."], "The largest of the given numbers."),
+    function("min", &["numbers..."], "The smallest of the given numbers."),
+    function(
+        "parseint",
+        &["string", "base"],
+        "Parse a string as an integer in `base`.",
+    ),
+    function("pow", &["base", "exponent"], "`base` raised to `exponent`."),
+    function("signum", &["number"], "The sign of a number: -1, 0 or 1."),
+    // String
+    function(
+        "chomp",
+        &["string"],
+        "Remove newlines at the end of a string.",
+    ),
+    function(
+        "endswith",
+        &["string", "suffix"],
+        "Whether `string` ends with `suffix`.",
+    ),
+    function(
+        "format",
+        &["spec", "values..."],
+        "Format values according to a `printf`-style spec.",
+    ),
+    function(
+        "formatlist",
+        &["spec", "values..."],
+        "Format each element of lists according to a spec.",
+    ),
+    function(
+        "indent",
+        &["spaces", "string"],
+        "Indent every line but the first by `spaces` spaces.",
+    ),
+    function(
+        "join",
+        &["separator", "list"],
+        "Concatenate a list of strings with `separator` between them.",
+    ),
+    function("lower", &["string"], "Convert letters to lower case."),
+    function(
+        "regex",
+        &["pattern", "string"],
+        "The first match of a regular expression.",
+    ),
+    function(
+        "regexall",
+        &["pattern", "string"],
+        "Every match of a regular expression.",
+    ),
+    function(
+        "replace",
+        &["string", "substring", "replacement"],
+        "Replace every occurrence of `substring`; `/re/` is a regular expression.",
+    ),
+    function(
+        "split",
+        &["separator", "string"],
+        "Split a string into a list at each `separator`.",
+    ),
+    function(
+        "startswith",
+        &["string", "prefix"],
+        "Whether `string` starts with `prefix`.",
+    ),
+    function(
+        "strcontains",
+        &["string", "substr"],
+        "Whether `string` contains `substr`.",
+    ),
+    function("strrev", &["string"], "Reverse the characters of a string."),
+    function(
+        "substr",
+        &["string", "offset", "length"],
+        "Part of a string by character offset and length.",
+    ),
+    function(
+        "templatestring",
+        &["template", "vars"],
+        "Render a template held in a string with the given variables.",
+    ),
+    function(
+        "title",
+        &["string"],
+        "Capitalize the first letter of each word.",
+    ),
+    function(
+        "trim",
+        &["string", "characters"],
+        "Remove the given characters from both ends.",
+    ),
+    function(
+        "trimprefix",
+        &["string", "prefix"],
+        "Remove `prefix` from the start, if present.",
+    ),
+    function(
+        "trimsuffix",
+        &["string", "suffix"],
+        "Remove `suffix` from the end, if present.",
+    ),
+    function(
+        "trimspace",
+        &["string"],
+        "Remove whitespace from both ends.",
+    ),
+    function("upper", &["string"], "Convert letters to upper case."),
+    // Collection
+    function("alltrue", &["list"], "Whether every element is true."),
+    function("anytrue", &["list"], "Whether any element is true."),
+    function(
+        "chunklist",
+        &["list", "size"],
+        "Split a list into chunks of `size` elements.",
+    ),
+    function(
+        "coalesce",
+        &["values..."],
+        "The first argument that isn't null or empty.",
+    ),
+    function(
+        "coalescelist",
+        &["lists..."],
+        "The first list that isn't empty.",
+    ),
+    function(
+        "compact",
+        &["list"],
+        "Remove null and empty strings from a list.",
+    ),
+    function("concat", &["lists..."], "Join lists into one."),
+    function(
+        "contains",
+        &["list", "value"],
+        "Whether a list or set contains `value`.",
+    ),
+    function("distinct", &["list"], "Remove duplicate elements."),
+    function(
+        "element",
+        &["list", "index"],
+        "The element at `index`, wrapping around.",
+    ),
+    function(
+        "flatten",
+        &["list"],
+        "Replace nested lists with their elements.",
+    ),
+    function(
+        "index",
+        &["list", "value"],
+        "Index of the first element equal to `value`.",
+    ),
+    function("keys", &["map"], "The keys of a map, sorted."),
+    function(
+        "length",
+        &["value"],
+        "Number of elements, or characters of a string.",
+    ),
+    function(
+        "lookup",
+        &["map", "key", "default"],
+        "The value at `key`, or `default`.",
+    ),
+    function(
+        "merge",
+        &["maps..."],
+        "Merge maps or objects, later ones winning.",
+    ),
+    function(
+        "matchkeys",
+        &["values", "keys", "searchset"],
+        "The elements of `values` whose matching `keys` are in `searchset`.",
+    ),
+    function(
+        "one",
+        &["list"],
+        "The only element of a list, or null when empty.",
+    ),
+    function(
+        "range",
+        &["start", "limit", "step"],
+        "A list of numbers from `start` up to `limit`.",
+    ),
+    function(
+        "reverse",
+        &["list"],
+        "The elements of a list in reverse order.",
+    ),
+    function(
+        "setintersection",
+        &["sets..."],
+        "Elements present in every set.",
+    ),
+    function(
+        "setproduct",
+        &["sets..."],
+        "Every combination of one element from each set.",
+    ),
+    function(
+        "setsubtract",
+        &["a", "b"],
+        "Elements of `a` that aren't in `b`.",
+    ),
+    function("setunion", &["sets..."], "Elements present in any set."),
+    function(
+        "slice",
+        &["list", "start", "end"],
+        "Elements from `start` up to `end`.",
+    ),
+    function(
+        "sort",
+        &["list"],
+        "Sort a list of strings lexicographically.",
+    ),
+    function("sum", &["list"], "Sum of a list of numbers."),
+    function(
+        "transpose",
+        &["map"],
+        "Swap the keys and values of a map of lists.",
+    ),
+    function("values", &["map"], "The values of a map, ordered by key."),
+    function(
+        "zipmap",
+        &["keys", "values"],
+        "Build a map from a list of keys and a list of values.",
+    ),
+    // Encoding
+    function("base64decode", &["string"], "Decode a base64 string."),
+    function("base64encode", &["string"], "Encode a string as base64."),
+    function(
+        "base64gzip",
+        &["string"],
+        "Gzip a string and encode it as base64.",
+    ),
+    function(
+        "csvdecode",
+        &["string"],
+        "Parse CSV with a header row into a list of maps.",
+    ),
+    function(
+        "jsondecode",
+        &["string"],
+        "Parse a JSON string into a value.",
+    ),
+    function("jsonencode", &["value"], "Encode a value as JSON."),
+    function(
+        "textdecodebase64",
+        &["string", "encoding"],
+        "Decode base64 text in the given character encoding.",
+    ),
+    function(
+        "textencodebase64",
+        &["string", "encoding"],
+        "Encode text in a character encoding, then as base64.",
+    ),
+    function(
+        "urlencode",
+        &["string"],
+        "Percent-encode a string for use in a URL.",
+    ),
+    function(
+        "yamldecode",
+        &["string"],
+        "Parse a YAML string into a value.",
+    ),
+    function("yamlencode", &["value"], "Encode a value as YAML."),
+    // Filesystem
+    function("abspath", &["path"], "Absolute form of a path."),
+    function("basename", &["path"], "The last element of a path."),
+    function("dirname", &["path"], "A path without its last element."),
+    function("file", &["path"], "Contents of a file as a string."),
+    function(
+        "filebase64",
+        &["path"],
+        "Contents of a file, base64-encoded.",
+    ),
+    function("fileexists", &["path"], "Whether a file exists."),
+    function(
+        "fileset",
+        &["path", "pattern"],
+        "Files under `path` matching a glob pattern.",
+    ),
+    function(
+        "pathexpand",
+        &["path"],
+        "Expand a leading `~` to the home directory.",
+    ),
+    function(
+        "templatefile",
+        &["path", "vars"],
+        "Render a template file with the given variables.",
+    ),
+    // Date and time
+    function(
+        "formatdate",
+        &["spec", "timestamp"],
+        "Format an RFC 3339 timestamp.",
+    ),
+    function(
+        "plantimestamp",
+        &[],
+        "The time of the plan, as an RFC 3339 timestamp.",
+    ),
+    function(
+        "timeadd",
+        &["timestamp", "duration"],
+        "Add a duration such as `\"1h\"` to a timestamp.",
+    ),
+    function(
+        "timecmp",
+        &["timestamp_a", "timestamp_b"],
+        "Compare two timestamps: -1, 0 or 1.",
+    ),
+    function(
+        "timestamp",
+        &[],
+        "The current time, as an RFC 3339 timestamp.",
+    ),
+    // Hash and crypto
+    function(
+        "base64sha256",
+        &["string"],
+        "SHA-256 hash of a string, base64-encoded.",
+    ),
+    function(
+        "base64sha512",
+        &["string"],
+        "SHA-512 hash of a string, base64-encoded.",
+    ),
+    function("bcrypt", &["string", "cost"], "Bcrypt hash of a string."),
+    function(
+        "filebase64sha256",
+        &["path"],
+        "SHA-256 hash of a file's contents, base64-encoded.",
+    ),
+    function(
+        "filebase64sha512",
+        &["path"],
+        "SHA-512 hash of a file's contents, base64-encoded.",
+    ),
+    function("filemd5", &["path"], "MD5 hash of a file's contents."),
+    function("filesha1", &["path"], "SHA-1 hash of a file's contents."),
+    function(
+        "filesha256",
+        &["path"],
+        "SHA-256 hash of a file's contents.",
+    ),
+    function(
+        "filesha512",
+        &["path"],
+        "SHA-512 hash of a file's contents.",
+    ),
+    function("md5", &["string"], "MD5 hash of a string, hex-encoded."),
+    function(
+        "rsadecrypt",
+        &["ciphertext", "privatekey"],
+        "Decrypt a base64-encoded RSA ciphertext with a PEM private key.",
+    ),
+    function("sha1", &["string"], "SHA-1 hash of a string, hex-encoded."),
+    function(
+        "sha256",
+        &["string"],
+        "SHA-256 hash of a string, hex-encoded.",
+    ),
+    function(
+        "sha512",
+        &["string"],
+        "SHA-512 hash of a string, hex-encoded.",
+    ),
+    function("uuid", &[], "A random UUID, different on every run."),
+    function("uuidv5", &["namespace", "name"], "A name-based UUID."),
+    // IP network
+    function(
+        "cidrhost",
+        &["prefix", "hostnum"],
+        "The IP address of host `hostnum` in a CIDR prefix.",
+    ),
+    function(
+        "cidrnetmask",
+        &["prefix"],
+        "The netmask of an IPv4 CIDR prefix.",
+    ),
+    function(
+        "cidrsubnet",
+        &["prefix", "newbits", "netnum"],
+        "A subnet of a CIDR prefix.",
+    ),
+    function(
+        "cidrsubnets",
+        &["prefix", "newbits..."],
+        "Consecutive subnets of a CIDR prefix.",
+    ),
+    // Type conversion
+    function(
+        "can",
+        &["expression"],
+        "Whether an expression evaluates without error.",
+    ),
+    function(
+        "ephemeralasnull",
+        &["value"],
+        "Replace ephemeral values with null, keeping the rest.",
+    ),
+    function(
+        "issensitive",
+        &["value"],
+        "Whether a value is marked sensitive.",
+    ),
+    function(
+        "nonsensitive",
+        &["value"],
+        "Drop the sensitive marking of a value.",
+    ),
+    function("sensitive", &["value"], "Mark a value as sensitive."),
+    function("tobool", &["value"], "Convert a value to a bool."),
+    function("tolist", &["value"], "Convert a value to a list."),
+    function("tomap", &["value"], "Convert a value to a map."),
+    function("tonumber", &["value"], "Convert a value to a number."),
+    function("toset", &["value"], "Convert a value to a set."),
+    function("tostring", &["value"], "Convert a value to a string."),
+    function(
+        "try",
+        &["expressions..."],
+        "The first expression that evaluates without error.",
+    ),
+    function(
+        "type",
+        &["value"],
+        "The type of a value, in the console only.",
+    ),
+];
+
+/// Look up a built-in function by name
+pub fn terraform_function(name: &str) -> Option<&'static TerraformFunction> {
+    TERRAFORM_FUNCTIONS.iter().find(|f| f.name == name)
+}
+
+/// The built-in function a misspelled `name` most likely meant: one differing
+/// only in case, or else the closest within a couple of edits
+pub fn similar_function(name: &str) -> Option<&'static TerraformFunction> {
+    if let Some(function) = TERRAFORM_FUNCTIONS
+        .iter()
+        .find(|f| f.name.eq_ignore_ascii_case(name))
+    {
+        return Some(function);
+    }
+    let max_distance = name.len().max(3) / 3;
+    TERRAFORM_FUNCTIONS
+        .iter()
+        .map(|f| (edit_distance(&name.to_ascii_lowercase(), f.name), f))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, f)| f)
+}
+
+/// Levenshtein distance, counting a swap of adjacent characters as one edit
+fn edit_distance(a: &str, b: &str) -> usize {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in rows[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (rows[i - 1][j] + 1)
+                .min(rows[i][j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = distance;
+        }
+    }
+    rows[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snippet() {
+        let join = terraform_function("join").unwrap();
+        assert_eq!(join.signature(), "join(separator, list)");
+        assert_eq!(join.snippet(), "join(${1:separator}, ${2:list})");
+        assert_eq!(
+            terraform_function("format").unwrap().snippet(),
+            "format(${1:spec}, ${2:values})"
+        );
+        assert_eq!(
+            terraform_function("timestamp").unwrap().snippet(),
+            "timestamp()"
+        );
+    }
+
+    #[test]
+    fn test_similar_function() {
+        let similar = |name| similar_function(name).map(|f| f.name);
+        assert_eq!(similar("jsonEncode"), Some("jsonencode"));
+        assert_eq!(similar("lenght"), Some("length"));
+        assert_eq!(similar("formatt"), Some("format"));
+        assert_eq!(similar("frobnicate"), None);
+        assert_eq!(similar("xy"), None);
+    }
+}
@@ -0,0 +1,312 @@
+//! Workspace index of Terraform symbols
+//!
+//! Every `.tf` and `.tfvars` file below the workspace folders is parsed once
+//! into the symbols it declares: variables, locals, tfvars assignments and
+//! `templatefile()` calls. Files are re-parsed one at a time as the client
+//! reports them changed, so cross-file features never walk the workspace
+//! themselves.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::RwLock;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use tower_lsp::lsp_types::{Location, Range, Url};
+
+use super::chain::{attribute_span, locals_spans, shift};
+use super::declarations::ModuleDecls;
+use super::references::{file_template_calls, TemplateCall};
+use super::tfvars::TfVars;
+use crate::document::LineOffsets;
+use crate::settings::Settings;
+
+lazy_static! {
+    /// The label of a `variable "name"` block
+    static ref VARIABLE_RE: Regex = Regex::new(r#"(?m)^[ \t]*variable[ \t]+"([^"]+)""#).unwrap();
+}
+
+/// A name declared in a file, with where
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    /// Range of the name in its file
+    pub range: Range,
+}
+
+/// What one `.tf` or `.tfvars` file declares
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileSymbols {
+    pub variables: Vec<Symbol>,
+    pub locals: Vec<Symbol>,
+    /// Variables a `.tfvars` file assigns
+    pub assignments: Vec<Symbol>,
+    /// `templatefile()` calls, with the template each renders
+    pub template_calls: Vec<(PathBuf, TemplateCall)>,
+}
+
+impl FileSymbols {
+    /// Parse the symbols of a file, by its extension; `None` for files that
+    /// are neither `.tf` nor `.tfvars`
+    pub fn parse(text: &str, path: &Path) -> Option<Self> {
+        let lines = LineOffsets::new(text);
+        let symbol = |name: &str, span: std::ops::Range<usize>| Symbol {
+            name: name.to_string(),
+            range: Range::new(
+                lines.offset_to_position(text, span.start),
+                lines.offset_to_position(text, span.end),
+            ),
+        };
+
+        if is_tfvars(path) {
+            let assignments = TfVars::parse(text)
+                .map(|vars| {
+                    vars.iter()
+                        .filter_map(|(name, _)| Some(symbol(name, attribute_span(text, name)?)))
+                        .collect()
+                })
+                .unwrap_or_default();
+            return Some(Self {
+                assignments,
+                ..Self::default()
+            });
+        }
+        if !is_tf(path) {
+            return None;
+        }
+
+        let variables = VARIABLE_RE
+            .captures_iter(text)
+            .filter_map(|caps| caps.get(1))
+            .map(|name| symbol(name.as_str(), name.range()))
+            .collect();
+        let locals = ModuleDecls::parse(text, path)
+            .map(|decls| decls.locals)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|name| {
+                let span = locals_spans(text).into_iter().find_map(|block| {
+                    attribute_span(&text[block.clone()], name).map(|s| shift(s, block.start))
+                })?;
+                Some(symbol(name, span))
+            })
+            .collect();
+        Some(Self {
+            variables,
+            locals,
+            assignments: Vec::new(),
+            template_calls: file_template_calls(text, path),
+        })
+    }
+}
+
+/// Terraform symbols of every `.tf` and `.tfvars` file in the workspace
+///
+/// The index is empty until [`WorkspaceIndex::index_root`] has walked the
+/// workspace folders; [`WorkspaceIndex::is_ready`] tells whether it has.
+#[derive(Debug, Default)]
+pub struct WorkspaceIndex {
+    files: RwLock<BTreeMap<PathBuf, FileSymbols>>,
+    ready: AtomicBool,
+}
+
+impl WorkspaceIndex {
+    /// Parse every Terraform file below `root`, returning how many were
+    /// indexed
+    pub fn index_root(&self, root: &Path, settings: &Settings) -> usize {
+        let parsed: Vec<(PathBuf, FileSymbols)> = crate::workspace::walk_with(root, settings)
+            .into_iter()
+            .filter(|path| is_tf(path) || is_tfvars(path))
+            .filter_map(|path| {
+                let text = fs::read_to_string(&path).ok()?;
+                let symbols = FileSymbols::parse(&text, &path)?;
+                Some((path, symbols))
+            })
+            .collect();
+        let count = parsed.len();
+        self.files.write().unwrap().extend(parsed);
+        count
+    }
+
+    /// Mark the initial walk of the workspace folders as done
+    pub fn mark_ready(&self) {
+        self.ready.store(true, Ordering::Release);
+    }
+
+    /// Whether the workspace folders have been indexed
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::Acquire)
+    }
+
+    /// Re-parse a file the client reports created or changed, dropping it if
+    /// it is gone or not a Terraform file
+    pub fn update(&self, path: &Path) {
+        let symbols = fs::read_to_string(path)
+            .ok()
+            .and_then(|text| FileSymbols::parse(&text, path));
+        let mut files = self.files.write().unwrap();
+        match symbols {
+            Some(symbols) => {
+                files.insert(path.to_path_buf(), symbols);
+            }
+            None => {
+                files.remove(path);
+            }
+        }
+    }
+
+    /// Forget a deleted file, or every file below a deleted directory
+    pub fn remove(&self, path: &Path) {
+        self.files
+            .write()
+            .unwrap()
+            .retain(|file, _| !file.starts_with(path));
+    }
+
+    /// Number of files indexed
+    pub fn len(&self) -> usize {
+        self.files.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Every `templatefile()` call in the workspace rendering `template`
+    pub fn template_calls(&self, template: &Path) -> Vec<TemplateCall> {
+        self.files
+            .read()
+            .unwrap()
+            .values()
+            .flat_map(|symbols| &symbols.template_calls)
+            .filter(|(target, _)| target == template)
+            .map(|(_, call)| call.clone())
+            .collect()
+    }
+
+    /// Where the module in directory `module` declares variable `name`
+    pub fn variable(&self, module: &Path, name: &str) -> Option<Location> {
+        self.find(module, name, |symbols| &symbols.variables)
+    }
+
+    /// Where the module in directory `module` declares local `name`
+    pub fn local(&self, module: &Path, name: &str) -> Option<Location> {
+        self.find(module, name, |symbols| &symbols.locals)
+    }
+
+    fn find(
+        &self,
+        module: &Path,
+        name: &str,
+        kind: impl Fn(&FileSymbols) -> &Vec<Symbol>,
+    ) -> Option<Location> {
+        let files = self.files.read().unwrap();
+        files
+            .iter()
+            .filter(|(path, _)| path.parent() == Some(module))
+            .find_map(|(path, symbols)| {
+                let symbol = kind(symbols).iter().find(|s| s.name == name)?;
+                Some(Location {
+                    uri: Url::from_file_path(path).ok()?,
+                    range: symbol.range,
+                })
+            })
+    }
+}
+
+fn is_tf(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "tf")
+}
+
+fn is_tfvars(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "tfvars")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower_lsp::lsp_types::Position;
+
+    const MAIN_TF: &str = r#"variable "project" {
+  type = string
+}
+
+locals {
+  prefix = "wf"
+  name   = "${local.prefix}-${var.project}"
+}
+
+resource "google_workflows_workflow" "w" {
+  source_contents = templatefile("${path.module}/templates/w.yaml.tftpl", {
+    project = var.project
+  })
+}
+"#;
+
+    #[test]
+    fn test_file_symbols() {
+        let symbols = FileSymbols::parse(MAIN_TF, Path::new("/repo/main.tf")).unwrap();
+        assert_eq!(
+            symbols.variables,
+            vec![Symbol {
+                name: "project".to_string(),
+                range: Range::new(Position::new(0, 10), Position::new(0, 17)),
+            }]
+        );
+        let locals: Vec<(&str, u32)> = symbols
+            .locals
+            .iter()
+            .map(|s| (s.name.as_str(), s.range.start.line))
+            .collect();
+        assert_eq!(locals, vec![("prefix", 5), ("name", 6)]);
+        let (target, call) = &symbols.template_calls[0];
+        assert_eq!(target, Path::new("/repo/templates/w.yaml.tftpl"));
+        assert_eq!(call.line, 10);
+
+        let tfvars = FileSymbols::parse("project = \"acme\"\n", Path::new("a.tfvars")).unwrap();
+        assert_eq!(tfvars.assignments[0].name, "project");
+        assert!(FileSymbols::parse("", Path::new("README.md")).is_none());
+    }
+
+    #[test]
+    fn test_workspace_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("infra/templates")).unwrap();
+        fs::write(root.join("infra/main.tf"), MAIN_TF).unwrap();
+        fs::write(root.join("infra/terraform.tfvars"), "project = \"acme\"\n").unwrap();
+        fs::write(root.join("README.md"), "# docs\n").unwrap();
+
+        let index = WorkspaceIndex::default();
+        assert!(!index.is_ready());
+        assert_eq!(index.index_root(root, &Settings::default()), 2);
+        index.mark_ready();
+        assert!(index.is_ready());
+
+        let template = root.join("infra/templates/w.yaml.tftpl");
+        let calls = index.template_calls(&template);
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].vars, Some(vec!["project".to_string()]));
+
+        let module = root.join("infra");
+        let variable = index.variable(&module, "project").unwrap();
+        assert_eq!(
+            variable.uri,
+            Url::from_file_path(module.join("main.tf")).unwrap()
+        );
+        assert_eq!(index.local(&module, "name").unwrap().range.start.line, 6);
+        assert!(index.variable(root, "project").is_none());
+
+        // Changes are picked up file by file
+        fs::write(root.join("infra/main.tf"), "variable \"region\" {}\n").unwrap();
+        index.update(&root.join("infra/main.tf"));
+        assert!(index.variable(&module, "project").is_none());
+        assert!(index.variable(&module, "region").is_some());
+        assert!(index.template_calls(&template).is_empty());
+
+        index.remove(&module);
+        assert!(index.is_empty());
+    }
+}
@@ -0,0 +1,25 @@
+//! Terraform module awareness
+//!
+//! Templates are rendered by Terraform's `templatefile()`, so the surrounding
+//! module's `.tf` and `.tfvars` files tell us what `${...}` expressions will
+//! resolve to. This module reads those files, finds the `templatefile()`
+//! calls that refer to templates, and indexes the symbols of a whole
+//! workspace.
+
+mod chain;
+mod declarations;
+mod functions;
+mod index;
+mod references;
+mod render;
+mod tfvars;
+
+pub use chain::{definition_chain, reference_at, ChainLink, LinkKind};
+pub use declarations::{
+    find_module_dir, load_module_decls, variables_file, ModuleDecls, ModuleIndex, VariableDecl,
+};
+pub use functions::{similar_function, terraform_function, TerraformFunction, TERRAFORM_FUNCTIONS};
+pub use index::{FileSymbols, Symbol, WorkspaceIndex};
+pub use references::{template_calls, template_references, TemplateCall, TemplateReference};
+pub use render::{profile_variables, render, tfvars_values, Variables};
+pub use tfvars::{find_tfvars_files, load_tfvars, TfVars};
@@ -0,0 +1,333 @@
+//! `templatefile()` calls that point at templates, and the variables they
+//! pass
+
+use std::fs;
+use std::ops::Range;
+use std::path::{Component, Path, PathBuf};
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+lazy_static! {
+    /// A `templatefile(` call whose first argument is a string literal
+    static ref TEMPLATEFILE_RE: Regex = Regex::new(r#"templatefile\(\s*"([^"\n]*)""#).unwrap();
+    /// The key of an object entry: a name or a quoted string before `=` or `:`
+    static ref OBJECT_KEY_RE: Regex =
+        Regex::new(r#"^\s*(?:"([^"]*)"|([A-Za-z_][A-Za-z0-9_-]*))\s*(?:=[^=]|=$|:)"#).unwrap();
+}
+
+/// Prefix for paths relative to the module directory
+const PATH_MODULE: &str = "${path.module}/";
+
+/// The path argument of a `templatefile()` call
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TemplateReference {
+    /// Byte range of the literal's contents, without the quotes
+    pub span: Range<usize>,
+    /// Whether the literal starts with `${path.module}/`
+    pub module_relative: bool,
+    /// The template the call reads
+    pub target: PathBuf,
+    /// Keys of the variables object, `None` unless it is written as an
+    /// object literal
+    pub vars: Option<Vec<String>>,
+}
+
+impl TemplateReference {
+    /// The literal that makes this call read `target` instead, from a `.tf`
+    /// file in `dir`
+    pub fn literal_for(&self, dir: &Path, target: &Path) -> String {
+        let relative = relative_path(dir, target)
+            .to_string_lossy()
+            .replace('\\', "/");
+        if self.module_relative {
+            format!("{}{}", PATH_MODULE, relative)
+        } else {
+            relative
+        }
+    }
+}
+
+/// Every `templatefile()` call in a `.tf` file whose path can be resolved
+/// without evaluating Terraform: a plain relative path or one under
+/// `${path.module}`, both taken relative to the file's directory
+pub fn template_references(text: &str, tf_file: &Path) -> Vec<TemplateReference> {
+    let dir = tf_file.parent().unwrap_or(Path::new(""));
+    TEMPLATEFILE_RE
+        .captures_iter(text)
+        .filter_map(|caps| {
+            let literal = caps.get(1)?;
+            let (module_relative, rest) = match literal.as_str().strip_prefix(PATH_MODULE) {
+                Some(rest) => (true, rest),
+                None => (false, literal.as_str()),
+            };
+            if rest.contains("${") || rest.contains("%{") || Path::new(rest).is_absolute() {
+                return None;
+            }
+            let after = text[literal.end() + 1..].trim_start();
+            let vars = after
+                .strip_prefix(',')
+                .map(str::trim_start)
+                .and_then(|arg| arg.strip_prefix('{'))
+                .and_then(object_keys);
+            Some(TemplateReference {
+                span: literal.range(),
+                module_relative,
+                target: normalize(&dir.join(rest)),
+                vars,
+            })
+        })
+        .collect()
+}
+
+/// A `templatefile()` call that renders a given template
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TemplateCall {
+    /// The `.tf` file making the call
+    pub file: PathBuf,
+    /// Line of the call's path literal, 0-based
+    pub line: u32,
+    /// Keys of the variables object, `None` unless it is written as an
+    /// object literal
+    pub vars: Option<Vec<String>>,
+}
+
+impl TemplateCall {
+    /// Where the call is, as `main.tf:12`
+    pub fn describe(&self) -> String {
+        format!(
+            "{}:{}",
+            self.file.file_name().unwrap_or_default().to_string_lossy(),
+            self.line + 1
+        )
+    }
+}
+
+/// The `templatefile()` calls rendering `template` in the `.tf` files of its
+/// directory and every directory above it, up to and including `root`, or
+/// up to its module's directory without a root
+pub fn template_calls(template: &Path, root: Option<&Path>) -> Vec<TemplateCall> {
+    let last = match root {
+        Some(root) => root.to_path_buf(),
+        None => match super::find_module_dir(template) {
+            Some(module) => module,
+            None => return Vec::new(),
+        },
+    };
+    let mut calls = Vec::new();
+    let mut dir = template.parent();
+    while let Some(current) = dir {
+        for file in super::declarations::tf_files(current) {
+            let Ok(text) = fs::read_to_string(&file) else {
+                continue;
+            };
+            calls.extend(
+                file_template_calls(&text, &file)
+                    .into_iter()
+                    .filter(|(target, _)| target == template)
+                    .map(|(_, call)| call),
+            );
+        }
+        if current == last || !current.starts_with(&last) {
+            break;
+        }
+        dir = current.parent();
+    }
+    calls
+}
+
+/// Every `templatefile()` call of a `.tf` file, with the template it renders
+pub(super) fn file_template_calls(text: &str, file: &Path) -> Vec<(PathBuf, TemplateCall)> {
+    template_references(text, file)
+        .into_iter()
+        .map(|reference| {
+            let call = TemplateCall {
+                file: file.to_path_buf(),
+                line: text[..reference.span.start].matches('\n').count() as u32,
+                vars: reference.vars,
+            };
+            (reference.target, call)
+        })
+        .collect()
+}
+
+/// The keys of an object literal, given the text after its opening `{`
+fn object_keys(text: &str) -> Option<Vec<String>> {
+    let mut keys = Vec::new();
+    let mut depth = 0usize;
+    let mut entry = 0;
+    let mut chars = text.char_indices().peekable();
+    let mut push = |entry: &str| {
+        if let Some(caps) = OBJECT_KEY_RE.captures(entry) {
+            let key = caps.get(1).or(caps.get(2)).unwrap().as_str();
+            keys.push(key.to_string());
+        }
+    };
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => {
+                while let Some((_, c)) = chars.next() {
+                    match c {
+                        '\\' => {
+                            chars.next();
+                        }
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+            }
+            '#' => while chars.next_if(|&(_, c)| c != '\n').is_some() {},
+            '/' if chars.peek().is_some_and(|&(_, c)| c == '/') => {
+                while chars.next_if(|&(_, c)| c != '\n').is_some() {}
+            }
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' if depth > 0 => depth -= 1,
+            '}' => {
+                push(&text[entry..i]);
+                return Some(keys);
+            }
+            ',' | '\n' if depth == 0 => {
+                push(&text[entry..i]);
+                entry = i + 1;
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Resolve `.` and `..` components without touching the filesystem
+fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !out.pop() {
+                    out.push("..");
+                }
+            }
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+/// `target` relative to `dir`, both absolute or both relative
+fn relative_path(dir: &Path, target: &Path) -> PathBuf {
+    let dir: Vec<Component> = dir.components().collect();
+    let target: Vec<Component> = target.components().collect();
+    let common = dir.iter().zip(&target).take_while(|(a, b)| a == b).count();
+
+    let mut out = PathBuf::new();
+    for _ in common..dir.len() {
+        out.push("..");
+    }
+    for component in &target[common..] {
+        out.push(component);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_template_references() {
+        let tf = r#"
+resource "google_workflows_workflow" "a" {
+  source_contents = templatefile("${path.module}/workflows/a.yaml.tftpl", {})
+}
+resource "google_workflows_workflow" "b" {
+  source_contents = templatefile(
+    "../shared/b.yaml.tftpl",
+    { region = var.region }
+  )
+}
+locals {
+  c = templatefile("${var.dir}/c.yaml.tftpl", {})
+}
+"#;
+        let refs = template_references(tf, Path::new("/repo/infra/main.tf"));
+        assert_eq!(refs.len(), 2);
+        assert!(refs[0].module_relative);
+        assert_eq!(
+            refs[0].target,
+            PathBuf::from("/repo/infra/workflows/a.yaml.tftpl")
+        );
+        assert_eq!(
+            &tf[refs[0].span.clone()],
+            "${path.module}/workflows/a.yaml.tftpl"
+        );
+        assert!(!refs[1].module_relative);
+        assert_eq!(refs[1].target, PathBuf::from("/repo/shared/b.yaml.tftpl"));
+    }
+
+    #[test]
+    fn test_template_vars() {
+        let tf = r#"
+a = templatefile("a.yaml.tftpl", {
+  project = var.project # the project
+  "region" = "europe-west1",
+  labels   = { team = "data", tier = "1" }
+  steps    = [for s in local.steps : s.name]
+  url      = "https://${var.host}/{id}"
+})
+b = templatefile("b.yaml.tftpl", local.vars)
+c = templatefile("c.yaml.tftpl", { compare = 1 == 1, other = x })
+"#;
+        let refs = template_references(tf, Path::new("/repo/main.tf"));
+        assert_eq!(
+            refs[0].vars.as_deref(),
+            Some(&["project", "region", "labels", "steps", "url"].map(String::from)[..])
+        );
+        assert_eq!(refs[1].vars, None);
+        assert_eq!(
+            refs[2].vars.as_deref(),
+            Some(&["compare", "other"].map(String::from)[..])
+        );
+    }
+
+    #[test]
+    fn test_template_calls() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("infra/templates")).unwrap();
+        fs::write(
+            root.join("main.tf"),
+            "x = templatefile(\"${path.module}/infra/templates/w.yaml.tftpl\", {})\n",
+        )
+        .unwrap();
+        fs::write(
+            root.join("infra/workflows.tf"),
+            "\n\ny = templatefile(\"templates/w.yaml.tftpl\", { a = 1 })\nz = templatefile(\"templates/other.yaml.tftpl\", {})\n",
+        )
+        .unwrap();
+        let template = root.join("infra/templates/w.yaml.tftpl");
+
+        let calls = template_calls(&template, Some(root));
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].describe(), "workflows.tf:3");
+        assert_eq!(calls[0].vars, Some(vec!["a".to_string()]));
+        assert_eq!(calls[1].file, root.join("main.tf"));
+        assert_eq!(calls[1].vars, Some(Vec::new()));
+
+        // Without a root, only the module's own files are searched
+        assert_eq!(template_calls(&template, None).len(), 1);
+    }
+
+    #[test]
+    fn test_literal_for() {
+        let tf = r#"x = templatefile("${path.module}/a.yaml.tftpl", {})"#;
+        let refs = template_references(tf, Path::new("/repo/infra/main.tf"));
+        assert_eq!(
+            refs[0].literal_for(
+                Path::new("/repo/infra"),
+                Path::new("/repo/templates/b.yaml.tftpl")
+            ),
+            "${path.module}/../templates/b.yaml.tftpl"
+        );
+    }
+}
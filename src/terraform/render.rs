@@ -0,0 +1,132 @@
+//! Rendering templates the way `templatefile()` does
+//!
+//! Used to preview a template with a given set of variable values and to
+//! validate the result. Variables are available both bare (`${project}`, as
+//! `templatefile()` passes them) and under `var.` (`${var.project}`).
+//! `jsonencode()` is the only function supported.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use hcl::eval::{Context, Evaluate, FuncArgs, FuncDef, ParamType};
+use hcl::{Template, Value};
+
+use super::tfvars::TfVars;
+use crate::config::RenderProfile;
+
+/// Variable values by name
+pub type Variables = BTreeMap<String, Value>;
+
+/// Render a template with the given variables
+pub fn render(text: &str, vars: &Variables) -> Result<String, String> {
+    let template: Template = text.parse().map_err(|e: hcl::Error| e.to_string())?;
+
+    let mut ctx = Context::new();
+    let object: hcl::Map<String, Value> = vars.clone().into_iter().collect();
+    for (name, value) in vars {
+        ctx.declare_var(name.as_str(), value.clone());
+    }
+    ctx.declare_var("var", Value::Object(object));
+    ctx.declare_func(
+        "jsonencode",
+        FuncDef::builder().param(ParamType::Any).build(jsonencode),
+    );
+
+    template.evaluate(&ctx).map_err(|e| e.to_string())
+}
+
+fn jsonencode(args: FuncArgs) -> Result<Value, String> {
+    serde_json::to_string(&args[0])
+        .map(Value::String)
+        .map_err(|e| e.to_string())
+}
+
+/// Evaluate the values of a set of tfvars
+pub fn tfvars_values(tfvars: &TfVars) -> Variables {
+    let ctx = Context::new();
+    tfvars
+        .iter()
+        .filter_map(|(name, text)| {
+            let body = hcl::parse(&format!("value = {}", text)).ok()?;
+            let attr = body.attributes().next()?;
+            let value = attr.expr().evaluate(&ctx).ok()?;
+            Some((name.to_string(), value))
+        })
+        .collect()
+}
+
+/// The variables of a render profile: its tfvars files, relative to the
+/// workspace root, with its inline values applied over them
+pub fn profile_variables(root: &Path, profile: &RenderProfile) -> Result<Variables, String> {
+    let mut tfvars = TfVars::default();
+    for file in &profile.tfvars {
+        let path = root.join(file);
+        let text = fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let parsed = TfVars::parse(&text)
+            .ok_or_else(|| format!("{}: not a valid tfvars file", path.display()))?;
+        tfvars.extend(parsed);
+    }
+
+    let mut vars = tfvars_values(&tfvars);
+    for (name, value) in &profile.variables {
+        let value = hcl::to_value(value).map_err(|e| format!("{}: {}", name, e))?;
+        vars.insert(name.clone(), value);
+    }
+    Ok(vars)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, Value)]) -> Variables {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn test_render() {
+        let vars = vars(&[
+            ("project", Value::from("my-project")),
+            ("regions", Value::from(vec!["us-central1", "europe-west1"])),
+        ]);
+        let text =
+            "a: ${project}\nb: ${var.project}\nc: $${sys.now()}\nd: ${jsonencode(regions)}\n";
+        assert_eq!(
+            render(text, &vars).unwrap(),
+            "a: my-project\nb: my-project\nc: ${sys.now()}\nd: [\"us-central1\",\"europe-west1\"]\n"
+        );
+    }
+
+    #[test]
+    fn test_render_unknown_variable() {
+        assert!(render("a: ${missing}\n", &Variables::new()).is_err());
+    }
+
+    #[test]
+    fn test_profile_variables() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("prod.tfvars"),
+            "project = \"prod\"\nreplicas = 3\n",
+        )
+        .unwrap();
+        let profile: RenderProfile = serde_json::from_str(
+            r#"{ "name": "prod", "tfvars": ["prod.tfvars"], "variables": { "replicas": 5 } }"#,
+        )
+        .unwrap();
+
+        let vars = profile_variables(dir.path(), &profile).unwrap();
+        assert_eq!(vars["project"], Value::from("prod"));
+        assert_eq!(vars["replicas"], Value::from(5));
+
+        let missing = RenderProfile {
+            tfvars: vec!["missing.tfvars".into()],
+            ..profile
+        };
+        assert!(profile_variables(dir.path(), &missing).is_err());
+    }
+}
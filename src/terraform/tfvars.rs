@@ -0,0 +1,178 @@
+//! Variable values from `terraform.tfvars` and `*.auto.tfvars`
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Maximum length of a rendered value before it is truncated
+const MAX_VALUE_LEN: usize = 40;
+
+/// Variable values collected from tfvars files, rendered as HCL text
+#[derive(Debug, Default, Clone)]
+pub struct TfVars {
+    values: BTreeMap<String, String>,
+}
+
+impl TfVars {
+    /// Parse the contents of a single tfvars file
+    pub fn parse(text: &str) -> Option<Self> {
+        let body = hcl::parse(text).ok()?;
+        let values = body
+            .attributes()
+            .filter_map(|attr| {
+                let value = hcl::format::to_string(attr.expr()).ok()?;
+                Some((attr.key().to_string(), value))
+            })
+            .collect();
+        Some(Self { values })
+    }
+
+    /// Merge another set of values over this one (later files win)
+    pub fn extend(&mut self, other: TfVars) {
+        self.values.extend(other.values);
+    }
+
+    /// The rendered value of a variable
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(String::as_str)
+    }
+
+    /// The rendered value of a variable, shortened for inline display
+    pub fn display(&self, name: &str) -> Option<String> {
+        let value = self.get(name)?;
+        let single_line = value.split_whitespace().collect::<Vec<_>>().join(" ");
+        if single_line.chars().count() > MAX_VALUE_LEN {
+            let truncated: String = single_line.chars().take(MAX_VALUE_LEN - 1).collect();
+            Some(format!("{}…", truncated))
+        } else {
+            Some(single_line)
+        }
+    }
+
+    /// Every variable with its rendered value, by name
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.values.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+/// Find the tfvars files that apply to a template, in Terraform's load order
+///
+/// Starts in the template's own directory and walks up to the nearest
+/// directory containing `.tf` files (the Terraform module root), collecting
+/// `terraform.tfvars` followed by `*.auto.tfvars` in lexical order.
+pub fn find_tfvars_files(template: &Path) -> Vec<PathBuf> {
+    let mut dir = template.parent();
+
+    while let Some(current) = dir {
+        let Ok(entries) = fs::read_dir(current) else {
+            break;
+        };
+        let mut names: Vec<String> = entries
+            .filter_map(|e| e.ok())
+            .filter(|e| e.path().is_file())
+            .filter_map(|e| e.file_name().into_string().ok())
+            .collect();
+        names.sort();
+
+        let mut files = Vec::new();
+        if names.iter().any(|n| n == "terraform.tfvars") {
+            files.push(current.join("terraform.tfvars"));
+        }
+        files.extend(
+            names
+                .iter()
+                .filter(|n| n.ends_with(".auto.tfvars"))
+                .map(|n| current.join(n)),
+        );
+
+        if !files.is_empty() {
+            return files;
+        }
+        if names.iter().any(|n| n.ends_with(".tf")) {
+            break;
+        }
+        dir = current.parent();
+    }
+
+    Vec::new()
+}
+
+/// Load and merge all tfvars files that apply to a template
+pub fn load_tfvars(template: &Path) -> TfVars {
+    let mut vars = TfVars::default();
+    for path in find_tfvars_files(template) {
+        match fs::read_to_string(&path)
+            .ok()
+            .and_then(|t| TfVars::parse(&t))
+        {
+            Some(parsed) => vars.extend(parsed),
+            None => tracing::debug!(path = %path.display(), "Skipping unreadable tfvars file"),
+        }
+    }
+    vars
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_tfvars() {
+        let vars = TfVars::parse("project_id = \"my-project\"\nreplicas = 3\n").unwrap();
+        assert_eq!(vars.get("project_id"), Some("\"my-project\""));
+        assert_eq!(vars.get("replicas"), Some("3"));
+        assert_eq!(vars.get("missing"), None);
+    }
+
+    #[test]
+    fn test_display_truncates_long_values() {
+        let long = format!("name = \"{}\"", "x".repeat(100));
+        let vars = TfVars::parse(&long).unwrap();
+        let shown = vars.display("name").unwrap();
+        assert_eq!(shown.chars().count(), MAX_VALUE_LEN);
+        assert!(shown.ends_with('…'));
+    }
+
+    #[test]
+    fn test_invalid_tfvars() {
+        assert!(TfVars::parse("not = = valid").is_none());
+    }
+
+    #[test]
+    fn test_find_and_load_tfvars() {
+        let dir = tempfile::tempdir().unwrap();
+        let templates = dir.path().join("templates");
+        fs::create_dir(&templates).unwrap();
+        fs::write(dir.path().join("main.tf"), "").unwrap();
+        fs::write(
+            dir.path().join("terraform.tfvars"),
+            "region = \"us\"\nzone = \"a\"",
+        )
+        .unwrap();
+        fs::write(dir.path().join("b.auto.tfvars"), "region = \"eu\"").unwrap();
+
+        let template = templates.join("wf.yaml.tftpl");
+        let files = find_tfvars_files(&template);
+        assert_eq!(files.len(), 2);
+        assert!(files[0].ends_with("terraform.tfvars"));
+
+        let vars = load_tfvars(&template);
+        assert_eq!(vars.get("region"), Some("\"eu\""));
+        assert_eq!(vars.get("zone"), Some("\"a\""));
+    }
+
+    #[test]
+    fn test_stops_at_module_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let module = dir.path().join("module");
+        fs::create_dir(&module).unwrap();
+        fs::write(dir.path().join("terraform.tfvars"), "region = \"us\"").unwrap();
+        fs::write(module.join("main.tf"), "").unwrap();
+
+        assert!(find_tfvars_files(&module.join("wf.yaml.tftpl")).is_empty());
+    }
+}
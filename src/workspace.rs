@@ -0,0 +1,152 @@
+//! Workspace discovery
+//!
+//! Finds template and Terraform files below a workspace root, skipping
+//! directories that never contain user-authored templates as well as
+//! anything matched by `.gitignore` or `.yamltftplignore` files.
+
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use ignore::{WalkBuilder, WalkState};
+
+use crate::settings::Settings;
+
+/// File name suffixes the server handles
+pub const TEMPLATE_EXTENSIONS: &[&str] = &[".yaml.tftpl", ".yml.tftpl"];
+
+/// Ignore file for templates that should not be indexed or linted, such as
+/// generated or vendored ones. Uses `.gitignore` syntax.
+pub const IGNORE_FILE: &str = ".yamltftplignore";
+
+/// Directories never descended into
+const SKIPPED_DIRS: &[&str] = &[".git", ".terraform", "node_modules", "target"];
+
+/// Whether a path names a YAML template this server handles
+pub fn is_template(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .is_some_and(|n| TEMPLATE_EXTENSIONS.iter().any(|ext| n.ends_with(ext)))
+}
+
+/// Every file below `root`, sorted, excluding skipped directories and
+/// ignored paths, with the default limits
+///
+/// `.gitignore` files are honored even outside a git repository, and
+/// `.yamltftplignore` files are read in every directory like `.gitignore`.
+pub fn walk(root: &Path) -> Vec<PathBuf> {
+    walk_with(root, &Settings::default())
+}
+
+/// Like [`walk`], using the configured number of threads and skipping files
+/// over the configured size
+pub fn walk_with(root: &Path, settings: &Settings) -> Vec<PathBuf> {
+    let files = Mutex::new(Vec::new());
+    WalkBuilder::new(root)
+        .hidden(false)
+        .require_git(false)
+        .add_custom_ignore_filename(IGNORE_FILE)
+        .max_filesize(Some(settings.max_file_size as u64))
+        .threads(settings.indexing_threads)
+        .filter_entry(|entry| {
+            !entry.file_type().is_some_and(|t| t.is_dir())
+                || entry
+                    .file_name()
+                    .to_str()
+                    .is_none_or(|n| !SKIPPED_DIRS.contains(&n))
+        })
+        .build_parallel()
+        .run(|| {
+            let files = &files;
+            Box::new(move |entry| {
+                if let Ok(entry) = entry {
+                    if entry.file_type().is_some_and(|t| t.is_file()) {
+                        files.lock().unwrap().push(entry.into_path());
+                    }
+                }
+                WalkState::Continue
+            })
+        });
+
+    let mut files = files.into_inner().unwrap();
+    files.sort();
+    files
+}
+
+/// Every template below `root`
+pub fn find_templates(root: &Path) -> Vec<PathBuf> {
+    walk(root).into_iter().filter(|p| is_template(p)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_is_template() {
+        assert!(is_template(Path::new("a/workflow.yaml.tftpl")));
+        assert!(is_template(Path::new("workflow.yml.tftpl")));
+        assert!(!is_template(Path::new("workflow.json.tftpl")));
+        assert!(!is_template(Path::new("workflow.yaml")));
+    }
+
+    #[test]
+    fn test_find_templates_honors_ignore_files() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("generated")).unwrap();
+        fs::create_dir_all(dir.path().join("vendor")).unwrap();
+        fs::write(
+            dir.path().join(IGNORE_FILE),
+            "generated/\n*.gen.yaml.tftpl\n",
+        )
+        .unwrap();
+        fs::write(dir.path().join(".gitignore"), "vendor/\n").unwrap();
+        fs::write(dir.path().join("a.yaml.tftpl"), "").unwrap();
+        fs::write(dir.path().join("b.gen.yaml.tftpl"), "").unwrap();
+        fs::write(dir.path().join("generated/c.yaml.tftpl"), "").unwrap();
+        fs::write(dir.path().join("vendor/d.yaml.tftpl"), "").unwrap();
+
+        assert_eq!(
+            find_templates(dir.path()),
+            vec![dir.path().join("a.yaml.tftpl")]
+        );
+    }
+
+    #[test]
+    fn test_walk_with_skips_large_files() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("small.tf"), "a").unwrap();
+        fs::write(dir.path().join("large.tf"), "a".repeat(100)).unwrap();
+
+        let settings = Settings {
+            max_file_size: 10,
+            indexing_threads: 1,
+            ..Settings::default()
+        };
+        assert_eq!(
+            walk_with(dir.path(), &settings),
+            vec![dir.path().join("small.tf")]
+        );
+        assert_eq!(walk(dir.path()).len(), 2);
+    }
+
+    #[test]
+    fn test_find_templates_skips_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("sub")).unwrap();
+        fs::create_dir_all(dir.path().join(".terraform/modules")).unwrap();
+        fs::write(dir.path().join("a.yaml.tftpl"), "").unwrap();
+        fs::write(dir.path().join("sub/b.yml.tftpl"), "").unwrap();
+        fs::write(dir.path().join("sub/c.tf"), "").unwrap();
+        fs::write(dir.path().join(".terraform/modules/d.yaml.tftpl"), "").unwrap();
+
+        let found = find_templates(dir.path());
+        assert_eq!(
+            found,
+            vec![
+                dir.path().join("a.yaml.tftpl"),
+                dir.path().join("sub/b.yml.tftpl")
+            ]
+        );
+    }
+}
@@ -9,7 +9,7 @@ use tower_lsp::lsp_types::Diagnostic;
 /// Compute diagnostics running the full pipeline (YAML parsing + workflow validation).
 /// Matches what the LSP backend does.
 fn compute_diagnostics(text: &str) -> Vec<Diagnostic> {
-    use yaml_tftpl_lsp::diagnostics::{validate_workflow, DiagnosticCollector};
+    use yaml_tftpl_lsp::diagnostics::{validate_workflow, DiagnosticCollector, SchemaExtensions};
     use yaml_tftpl_lsp::parser::{parse_yaml, preprocess_expressions};
 
     let mut collector = DiagnosticCollector::new();
@@ -17,7 +17,12 @@ fn compute_diagnostics(text: &str) -> Vec<Diagnostic> {
     let result = parse_yaml(&preprocessed, &expression_map, &mut collector);
 
     if let Some(ref value) = result.value {
-        validate_workflow(value, &preprocessed, &mut collector);
+        validate_workflow(
+            value,
+            &preprocessed,
+            SchemaExtensions::default(),
+            &mut collector,
+        );
     }
 
     collector.into_diagnostics()
@@ -567,6 +572,32 @@ main:
     );
 }
 
+#[test]
+fn test_workflow_merge_keys_resolved_before_validation() {
+    let text = r#"
+main:
+  steps:
+    - first:
+        <<: &get_status
+          call: http.get
+          args:
+            url: https://api.example.com/status
+        result: ignored
+    - second:
+        <<: *get_status
+        result: status
+    - done:
+        return: $${status}
+"#;
+
+    let diagnostics = compute_diagnostics(text);
+    assert!(
+        diagnostics.is_empty(),
+        "Merge keys should be resolved before validation, got: {:?}",
+        diagnostics
+    );
+}
+
 #[test]
 fn test_non_mapping_document_produces_warning() {
     let text = r#"
@@ -604,3 +635,8 @@ fn test_workflow_fixture_with_full_validation() {
         errors_and_warnings
     );
 }
+
+#[test]
+fn test_fixture_corpus() {
+    yaml_tftpl_lsp::fixtures::assert_corpus(std::path::Path::new("tests/fixtures"));
+}